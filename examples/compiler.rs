@@ -6,7 +6,7 @@ use reqlang_expr::{cliutil::read_in_source, prelude::*};
 fn main() -> ExprResult<()> {
     let args = Args::parse();
 
-    let source = read_in_source(args.path);
+    let source = read_in_source(args.path)?;
 
     let ast: Expr = parse(&source).expect("should parse successfully");
 
@@ -8,7 +8,7 @@ use reqlang_expr::{
 fn main() -> ExprResult<()> {
     let args = Args::parse();
 
-    let source = read_in_source(args.path);
+    let source = read_in_source(args.path)?;
 
     let ast: Expr = parse(&source).expect("should parse successfully");
 
@@ -17,7 +17,7 @@ fn main() -> ExprResult<()> {
     let bytecode = compile(&mut (ast, 0..source.len()), &env)?;
 
     let disassemble = Disassembler::new(&bytecode, &env);
-    let disassembly = disassemble.disassemble();
+    let disassembly = disassemble.disassemble()?;
 
     eprintln!("{disassembly}");
 
@@ -7,7 +7,7 @@ use reqlang_expr::{
 fn main() -> ExprResult<()> {
     let args = Args::parse();
 
-    let source = read_in_source(args.path);
+    let source = read_in_source(args.path)?;
 
     let ast: Expr = parse(&source).expect("should parse successfully");
     let (var_keys, var_values) = unzip_key_values(args.vars);
@@ -27,7 +27,7 @@ fn main() -> ExprResult<()> {
         secrets: secret_values,
         client_context: client_context_values
             .iter()
-            .map(|string_value| Value::String(string_value.clone()))
+            .map(|string_value| Value::String(string_value.clone().into()))
             .collect(),
     };
 
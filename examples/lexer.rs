@@ -1,14 +1,16 @@
 use clap::Parser;
 use reqlang_expr::{cliutil::read_in_source, prelude::*};
 
-fn main() {
+fn main() -> ExprResult<()> {
     let args = Args::parse();
 
-    let source = read_in_source(args.path);
+    let source = read_in_source(args.path)?;
 
     let tokens = lex(&source);
 
     eprintln!("{tokens:#?}");
+
+    Ok(())
 }
 
 #[derive(Parser, Debug)]
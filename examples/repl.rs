@@ -1,4 +1,5 @@
 use clap::Parser;
+use codespan_reporting::diagnostic::Diagnostic;
 use codespan_reporting::files::SimpleFile;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
 use codespan_reporting::term::{self};
@@ -11,11 +12,18 @@ use reedline::{
 };
 use regex::Regex;
 use reqlang_expr::{
-    cliutil::{parse_key_val, unzip_key_values},
+    cliutil::{parse_key_val, read_in_source, unzip_key_values},
     disassembler::Disassembler,
     errors::diagnostics::get_diagnostics,
+    metrics::{Metrics, set_metrics},
     prelude::*,
 };
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 fn main() -> ExprResult<()> {
     let crate_version = env!("CARGO_PKG_VERSION");
@@ -24,8 +32,31 @@ fn main() -> ExprResult<()> {
 
     let args = Args::parse();
 
-    let mut prompt = DefaultPrompt::default();
-    prompt.left_prompt = DefaultPromptSegment::Basic("interpet    ".to_string());
+    let timings = Arc::new(Mutex::new(Timings::default()));
+
+    set_metrics(Box::new(TimingsRecorder(timings.clone()))).ok();
+
+    let mut repl = Repl {
+        session: ReplSession {
+            vars: args.vars,
+            prompts: args.prompts,
+            secrets: args.secrets,
+            client_context: args.client_context,
+            ..Default::default()
+        },
+        mode: ReplMode::default(),
+        vm: Vm::new(),
+        timing: args.timing,
+        timings,
+    };
+
+    if let Some(path) = args.script {
+        let script = read_in_source(Some(path))?;
+
+        repl.run_script(&script);
+
+        return Ok(());
+    }
 
     // Set up the required keybindings
     let mut keybindings = default_emacs_keybindings();
@@ -38,102 +69,18 @@ fn main() -> ExprResult<()> {
         ]),
     );
 
-    let mut commands = vec![
-        "/env".into(),
-        "/exit".into(),
-        "/mode".into(),
-        "/mode interpret".into(),
-        "/mode compile".into(),
-        "/mode disassemble".into(),
-        "/mode lex".into(),
-        "/mode parse".into(),
-        "/set var ".into(),
-        "/set prompt ".into(),
-        "/set secret ".into(),
-        "/version".into(),
-        "id".into(),
-        "noop".into(),
-        "is_empty".into(),
-        "not".into(),
-        "and".into(),
-        "or".into(),
-        "cond".into(),
-        "to_str".into(),
-        "concat".into(),
-        "contains".into(),
-        "trim".into(),
-        "trim_start".into(),
-        "trim_end".into(),
-        "lowercase".into(),
-        "uppercase".into(),
-        "eq".into(),
-        "type".into(),
-    ];
-
-    // Diagnostics
-    let writer = StandardStream::stderr(ColorChoice::Auto);
-    let config = term::Config::default();
-
-    let mut vm = Vm::new();
-
-    let (mut var_keys, mut var_values) = unzip_key_values(args.vars);
-    let (mut prompt_keys, mut prompt_values) = unzip_key_values(args.prompts);
-    let (mut secret_keys, mut secret_values) = unzip_key_values(args.secrets);
-    let (mut client_keys, mut client_values) = unzip_key_values(args.client_context);
-
-    {
-        commands.extend(
-            var_keys
-                .iter()
-                .map(|key| format!(":{key}"))
-                .collect::<Vec<String>>(),
-        );
-        commands.extend(
-            prompt_keys
-                .iter()
-                .map(|key| format!("?{key}"))
-                .collect::<Vec<String>>(),
-        );
-        commands.extend(
-            secret_keys
-                .iter()
-                .map(|key| format!("!{key}"))
-                .collect::<Vec<String>>(),
-        );
-        commands.extend(
-            client_keys
-                .iter()
-                .map(|key| format!("@{key}"))
-                .collect::<Vec<String>>(),
-        );
-    }
-
-    let mut repl_mode = ReplMode::default();
-    let mut last_value: Option<Value> = None;
-
     loop {
-        let mut env = CompileTimeEnv::new(
-            var_keys.clone(),
-            prompt_keys.clone(),
-            secret_keys.clone(),
-            client_keys.clone(),
-        );
-
-        let mut runtime_env: RuntimeEnv = RuntimeEnv {
-            vars: var_values.clone(),
-            prompts: prompt_values.clone(),
-            secrets: secret_values.clone(),
-            client_context: client_values
-                .iter()
-                .map(|string_value| Value::String(string_value.clone()))
-                .collect(),
-        };
-
-        if let Some(last_value) = &last_value {
-            let i = env.add_to_client_context(REPL_LAST_VALUE_PLACEHOLDER);
-            runtime_env.add_to_client_context(i, last_value.clone());
-            commands.extend(vec![format!("@{}", REPL_LAST_VALUE_PLACEHOLDER)]);
-        }
+        let (env, _) = repl.session.envs();
+
+        let commands: Vec<String> = REPL_COMMANDS
+            .iter()
+            .map(|command| command.to_string())
+            .chain(
+                env.identifiers()
+                    .into_iter()
+                    .map(|(identifier, _, _)| identifier),
+            )
+            .collect();
 
         // Use the interactive menu to select options from the completer
         let completion_menu = Box::new(ColumnarMenu::default().with_name("completion_menu"));
@@ -143,7 +90,7 @@ fn main() -> ExprResult<()> {
         let mut completions = DefaultCompleter::with_inclusions(&['/', ':', '?', '!', '@', '_']);
         completions.insert(commands.clone());
 
-        let mut example_highlighter = ExampleHighlighter::new(commands.clone());
+        let mut example_highlighter = ExampleHighlighter::new(commands);
         example_highlighter.change_colors(Color::Yellow, Color::White, Color::LightGray);
 
         let mut line_editor = Reedline::create()
@@ -152,188 +99,16 @@ fn main() -> ExprResult<()> {
             .with_edit_mode(edit_mode)
             .with_highlighter(Box::new(example_highlighter));
 
+        let prompt = DefaultPrompt {
+            left_prompt: DefaultPromptSegment::Basic(repl.mode.prompt().to_string()),
+            ..Default::default()
+        };
+
         match line_editor.read_line(&prompt) {
             Ok(Signal::Success(source)) => {
-                if source.trim().is_empty() {
-                    continue;
-                }
-
-                if EXIT_PATTERN.is_match(&source) {
+                if let Flow::Exit = repl.handle(source) {
                     break;
                 }
-
-                if MODE_GET_PATTERN.is_match(&source) {
-                    println!("MODE: {repl_mode:#?}");
-                    continue;
-                }
-
-                if VERSION_PATTERN.is_match(&source) {
-                    println!("{crate_version}");
-                    continue;
-                }
-
-                if MODE_SET_PATTERN.is_match(&source) {
-                    for (_, [new_mode]) in
-                        MODE_SET_PATTERN.captures_iter(&source).map(|c| c.extract())
-                    {
-                        match new_mode {
-                            "interpret" => {
-                                repl_mode = ReplMode::Interpret;
-                                prompt.left_prompt =
-                                    DefaultPromptSegment::Basic("interpet    ".to_string());
-                            }
-                            "compile" => {
-                                repl_mode = ReplMode::Compile;
-                                prompt.left_prompt =
-                                    DefaultPromptSegment::Basic("compile     ".to_string());
-                            }
-                            "disassemble" => {
-                                repl_mode = ReplMode::Disassemble;
-                                prompt.left_prompt =
-                                    DefaultPromptSegment::Basic("disassemble ".to_string());
-                            }
-                            "parse" => {
-                                repl_mode = ReplMode::Parse;
-                                prompt.left_prompt =
-                                    DefaultPromptSegment::Basic("parse       ".to_string());
-                            }
-                            "lex" => {
-                                repl_mode = ReplMode::Lex;
-                                prompt.left_prompt =
-                                    DefaultPromptSegment::Basic("lex         ".to_string());
-                            }
-                            _ => {
-                                println!(
-                                    "Invalid repl mode: '{new_mode}'. Please use 'interpret', 'compile', 'disassemble', 'parse', or 'lex'\n"
-                                );
-                            }
-                        }
-                    }
-
-                    continue;
-                }
-
-                if ENV_PATTERN.is_match(&source) {
-                    println!("{env:#?}");
-                    continue;
-                }
-
-                if SET_PATTERN.is_match(&source) {
-                    for (_, [set_type, key, value]) in
-                        SET_PATTERN.captures_iter(&source).map(|c| c.extract())
-                    {
-                        match set_type {
-                            "var" => {
-                                var_keys.push(key.to_string());
-                                var_values.push(value.to_string());
-                                commands.extend(vec![format!(":{}", key.to_string())]);
-                            }
-                            "prompt" => {
-                                prompt_keys.push(key.to_string());
-                                prompt_values.push(value.to_string());
-                                commands.extend(vec![format!("?{}", key.to_string())]);
-                            }
-                            "secret" => {
-                                secret_keys.push(key.to_string());
-                                secret_values.push(value.to_string());
-                                commands.extend(vec![format!("!{}", key.to_string())]);
-                            }
-                            "client" => {
-                                client_keys.push(key.to_string());
-                                client_values.push(value.to_string());
-                                commands.extend(vec![format!("@{}", key.to_string())]);
-                            }
-                            _ => {}
-                        }
-                    }
-
-                    continue;
-                }
-
-                if repl_mode == ReplMode::Lex {
-                    let tokens = lex(&source);
-                    println!("{tokens:#?}");
-                    continue;
-                }
-
-                let mut errs = vec![];
-
-                let ast = parse(&source);
-
-                match ast {
-                    Ok(ast) => {
-                        if repl_mode == ReplMode::Parse {
-                            println!("{ast:#?}");
-                            continue;
-                        }
-
-                        let bytecode = compile(&mut (ast, 0..source.len()), &env);
-
-                        match bytecode {
-                            Ok(bytecode) => {
-                                if repl_mode == ReplMode::Compile {
-                                    println!("{bytecode:#?}");
-                                    continue;
-                                }
-
-                                if repl_mode == ReplMode::Disassemble {
-                                    let disassemble = Disassembler::new(&bytecode, &env);
-                                    let disassembly = disassemble.disassemble();
-
-                                    println!("{disassembly}");
-                                    continue;
-                                }
-
-                                match vm.interpret(bytecode.into(), &env, &runtime_env) {
-                                    Ok(value) => {
-                                        println!("{value}");
-
-                                        last_value = Some(value);
-                                    }
-                                    Err(err) => {
-                                        let diagnostics = get_diagnostics(&err, &source);
-
-                                        let file = SimpleFile::new("expression", source);
-
-                                        for diagnostic in diagnostics {
-                                            term::emit(
-                                                &mut writer.lock(),
-                                                &config,
-                                                &file,
-                                                &diagnostic,
-                                            )
-                                            .expect("should emit diagnostics to term");
-                                        }
-                                    }
-                                }
-                            }
-                            Err(err) => {
-                                errs.extend(err);
-
-                                let diagnostics = get_diagnostics(&errs, &source);
-
-                                let file = SimpleFile::new("expression", source);
-
-                                for diagnostic in diagnostics {
-                                    term::emit(&mut writer.lock(), &config, &file, &diagnostic)
-                                        .expect("should emit diagnostics to term");
-                                }
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        errs.extend(err);
-
-                        let diagnostics = get_diagnostics(&errs, &source);
-
-                        let file = SimpleFile::new("expression", source);
-
-                        for diagnostic in diagnostics {
-                            term::emit(&mut writer.lock(), &config, &file, &diagnostic)
-                                .expect("should emit diagnostics to term");
-                        }
-                    }
-                }
             }
             Ok(Signal::CtrlD) | Ok(Signal::CtrlC) => {
                 println!("\nAborted!");
@@ -350,6 +125,27 @@ fn main() -> ExprResult<()> {
 
 static REPL_LAST_VALUE_PLACEHOLDER: &str = "_";
 
+/// Commands completed alongside the identifiers in the environment
+const REPL_COMMANDS: [&str; 17] = [
+    "/env",
+    "/exit",
+    "/explain ",
+    "/load ",
+    "/mode",
+    "/mode interpret",
+    "/mode compile",
+    "/mode disassemble",
+    "/mode lex",
+    "/mode parse",
+    "/save ",
+    "/set var ",
+    "/set prompt ",
+    "/set secret ",
+    "/set client ",
+    "/time ",
+    "/version",
+];
+
 /// # Set Command
 ///
 /// ```repl
@@ -375,6 +171,28 @@ static SET_PATTERN: Lazy<Regex> = Lazy::new(|| {
 /// - secrets
 static ENV_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"/env").expect(INVALID_REGEX_ERROR));
 
+/// # Save Command
+///
+/// ```repl
+/// /save session.json
+/// ```
+///
+/// Save the current vars, prompts, secrets, client context, and user builtin
+/// signatures to a JSON file
+static SAVE_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^/save (.+)$").expect(INVALID_REGEX_ERROR));
+
+/// # Load Command
+///
+/// ```repl
+/// /load session.json
+/// ```
+///
+/// Replace the current vars, prompts, secrets, and client context with ones
+/// saved by `/save`, warning about user builtins that aren't defined
+static LOAD_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^/load (.+)$").expect(INVALID_REGEX_ERROR));
+
 /// # Exit Command
 ///
 /// ```repl
@@ -394,6 +212,28 @@ static EXIT_PATTERN: Lazy<Regex> = Lazy::new(|| Regex::new(r"^/exit$").expect(IN
 static VERSION_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^/version$").expect(INVALID_REGEX_ERROR));
 
+/// # Time Command
+///
+/// ```repl
+/// /time (concat `a` `b`)
+/// ```
+///
+/// Evaluate an expression in the current mode then print how long each stage
+/// took
+static TIME_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^/time (.+)$").expect(INVALID_REGEX_ERROR));
+
+/// # Explain Command
+///
+/// ```repl
+/// /explain (concat `a` (uppercase :b))
+/// ```
+///
+/// Interpret an expression then print each call with its callee, the values
+/// of its args, and its result, redacting secrets
+static EXPLAIN_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^/explain (.+)$").expect(INVALID_REGEX_ERROR));
+
 /// # Set Mode Command
 ///
 /// ```repl
@@ -428,6 +268,182 @@ static MODE_GET_PATTERN: Lazy<Regex> =
 
 static INVALID_REGEX_ERROR: &str = "should be a valid regex pattern";
 
+/// State of the REPL between lines of input
+struct Repl {
+    session: ReplSession,
+    mode: ReplMode,
+    vm: Vm,
+    /// Print timings after evaluating every expression
+    timing: bool,
+    timings: Arc<Mutex<Timings>>,
+}
+
+/// If the REPL should keep reading input after a line
+enum Flow {
+    Continue,
+    Exit,
+}
+
+impl Repl {
+    /// Handle a line of input, running a command or evaluating an expression
+    fn handle(&mut self, source: String) -> Flow {
+        let (env, _) = self.session.envs();
+
+        if source.trim().is_empty() {
+            return Flow::Continue;
+        }
+
+        if EXIT_PATTERN.is_match(&source) {
+            return Flow::Exit;
+        }
+
+        if MODE_GET_PATTERN.is_match(&source) {
+            println!("MODE: {:#?}", self.mode);
+            return Flow::Continue;
+        }
+
+        if VERSION_PATTERN.is_match(&source) {
+            println!("{}", env!("CARGO_PKG_VERSION"));
+            return Flow::Continue;
+        }
+
+        if MODE_SET_PATTERN.is_match(&source) {
+            for (_, [new_mode]) in MODE_SET_PATTERN.captures_iter(&source).map(|c| c.extract()) {
+                match new_mode {
+                    "interpret" => self.mode = ReplMode::Interpret,
+                    "compile" => self.mode = ReplMode::Compile,
+                    "disassemble" => self.mode = ReplMode::Disassemble,
+                    "parse" => self.mode = ReplMode::Parse,
+                    "lex" => self.mode = ReplMode::Lex,
+                    _ => {
+                        println!(
+                            "Invalid repl mode: '{new_mode}'. Please use 'interpret', 'compile', 'disassemble', 'parse', or 'lex'\n"
+                        );
+                    }
+                }
+            }
+
+            return Flow::Continue;
+        }
+
+        if ENV_PATTERN.is_match(&source) {
+            ReplOutput::EnvSnapshot(env).print(&source);
+            return Flow::Continue;
+        }
+
+        if let Some(captures) = SAVE_PATTERN.captures(&source) {
+            let path = &captures[1];
+
+            self.session.user_builtins = (0..)
+                .map_while(|i| env.get_user_builtin(i))
+                .map(|builtin| format!("{builtin:?}"))
+                .collect();
+
+            match self.session.save(path) {
+                Ok(()) => println!("Saved session to {path}"),
+                Err(err) => println!("Unable to save session to {path}: {err}"),
+            }
+
+            return Flow::Continue;
+        }
+
+        if let Some(captures) = LOAD_PATTERN.captures(&source) {
+            let path = &captures[1];
+
+            let loaded = match ReplSession::load(path) {
+                Ok(loaded) => loaded,
+                Err(err) => {
+                    println!("Unable to load session from {path}: {err}");
+                    return Flow::Continue;
+                }
+            };
+
+            for signature in &loaded.user_builtins {
+                let defined = (0..)
+                    .map_while(|i| env.get_user_builtin(i))
+                    .any(|builtin| &format!("{builtin:?}") == signature);
+
+                if !defined {
+                    println!("Warning: user builtin {signature} is not defined");
+                }
+            }
+
+            self.session = ReplSession {
+                last_value: self.session.last_value.take(),
+                ..loaded
+            };
+
+            println!("Loaded session from {path}");
+
+            return Flow::Continue;
+        }
+
+        if SET_PATTERN.is_match(&source) {
+            for (_, [set_type, key, value]) in
+                SET_PATTERN.captures_iter(&source).map(|c| c.extract())
+            {
+                let entries = match set_type {
+                    "var" => &mut self.session.vars,
+                    "prompt" => &mut self.session.prompts,
+                    "secret" => &mut self.session.secrets,
+                    "client" => &mut self.session.client_context,
+                    _ => continue,
+                };
+
+                entries.push((key.to_string(), value.to_string()));
+            }
+
+            return Flow::Continue;
+        }
+
+        if let Some(captures) = EXPLAIN_PATTERN.captures(&source) {
+            let source = &captures[1];
+
+            self.session.explain(source, &mut self.vm).print(source);
+
+            return Flow::Continue;
+        }
+
+        let (source, timed) = match TIME_PATTERN
+            .captures(&source)
+            .map(|captures| captures[1].to_string())
+        {
+            Some(expr) => (expr, true),
+            None => (source, self.timing),
+        };
+
+        *self.timings.lock().unwrap() = Timings::default();
+
+        self.session
+            .evaluate(&self.mode, &source, &mut self.vm)
+            .print(&source);
+
+        if timed {
+            print!("{}", self.timings.lock().unwrap());
+        }
+
+        Flow::Continue
+    }
+
+    /// Handle each line of a script, echoing it before its output, until
+    /// the end of the script or `/exit`
+    ///
+    /// Blank lines and lines starting with `#` are skipped.
+    fn run_script(&mut self, script: &str) {
+        for line in script.lines() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            println!("{}> {line}", self.mode.prompt());
+
+            if let Flow::Exit = self.handle(line.to_string()) {
+                break;
+            }
+        }
+    }
+}
+
 /// Controls what the repl does with input
 #[derive(PartialEq, Debug, Default)]
 enum ReplMode {
@@ -439,6 +455,223 @@ enum ReplMode {
     Lex,
 }
 
+impl ReplMode {
+    /// Prompt shown before input in this mode
+    fn prompt(&self) -> &'static str {
+        match self {
+            ReplMode::Interpret => "interpet    ",
+            ReplMode::Compile => "compile     ",
+            ReplMode::Disassemble => "disassemble ",
+            ReplMode::Parse => "parse       ",
+            ReplMode::Lex => "lex         ",
+        }
+    }
+}
+
+/// How long each stage of evaluating the last expression took
+#[derive(Debug, Default)]
+struct Timings {
+    lex: Duration,
+    parse: Duration,
+    compile: Duration,
+    interpret: Duration,
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "lex       {:?}", self.lex)?;
+        writeln!(f, "parse     {:?}", self.parse)?;
+        writeln!(f, "compile   {:?}", self.compile)?;
+        writeln!(f, "interpret {:?}", self.interpret)
+    }
+}
+
+/// Records [`Timings`] from the durations reported to [`Metrics`]
+struct TimingsRecorder(Arc<Mutex<Timings>>);
+
+impl Metrics for TimingsRecorder {
+    fn lexed(&self, duration: Duration) {
+        self.0.lock().unwrap().lex += duration;
+    }
+
+    fn parsed(&self, _success: bool, duration: Duration) {
+        self.0.lock().unwrap().parse += duration;
+    }
+
+    fn compiled(&self, _success: bool, duration: Duration) {
+        self.0.lock().unwrap().compile += duration;
+    }
+
+    fn evaluated(&self, duration: Duration) {
+        self.0.lock().unwrap().interpret += duration;
+    }
+}
+
+/// What evaluating input in the REPL produced
+///
+/// Evaluation returns these instead of printing so the REPL can be driven
+/// without capturing stdout. [`ReplOutput::print`] is what the terminal shows.
+#[derive(Debug)]
+enum ReplOutput {
+    /// Result of interpreting an expression
+    Value(Value),
+    /// How interpreting an expression evaluated each call
+    Explanation(Explanation),
+    /// Bytecode compiled from an expression
+    ByteCode(ExprByteCode),
+    /// Human readable bytecode compiled from an expression
+    Disassembly(String),
+    /// Tokens lexed from an expression
+    Tokens(Vec<Result<(usize, Token, usize), ExprErrorS>>),
+    /// Expression parsed from the input
+    Ast(Expr),
+    /// Errors lexing, parsing, compiling, or interpreting an expression
+    Diagnostics(Vec<Diagnostic<()>>),
+    /// The current compile time environment
+    EnvSnapshot(CompileTimeEnv),
+}
+
+impl ReplOutput {
+    /// Print the output, rendering diagnostics against the input's source
+    fn print(self, source: &str) {
+        match self {
+            ReplOutput::Value(value) => match value.to_expr_source() {
+                Some(source) => println!("{source}"),
+                None => println!("{value}"),
+            },
+            ReplOutput::Explanation(explanation) => print!("{explanation}"),
+            ReplOutput::ByteCode(bytecode) => println!("{bytecode:#?}"),
+            ReplOutput::Disassembly(disassembly) => println!("{disassembly}"),
+            ReplOutput::Tokens(tokens) => println!("{tokens:#?}"),
+            ReplOutput::Ast(ast) => println!("{ast:#?}"),
+            ReplOutput::Diagnostics(diagnostics) => {
+                let writer = StandardStream::stderr(ColorChoice::Auto);
+                let config = term::Config::default();
+                let file = SimpleFile::new("expression", source);
+
+                for diagnostic in diagnostics {
+                    term::emit(&mut writer.lock(), &config, &file, &diagnostic)
+                        .expect("should emit diagnostics to term");
+                }
+            }
+            ReplOutput::EnvSnapshot(env) => println!("{env:#?}"),
+        }
+    }
+}
+
+/// Environment of the REPL, saved and loaded with `/save` and `/load`
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct ReplSession {
+    vars: Vec<(String, String)>,
+    prompts: Vec<(String, String)>,
+    secrets: Vec<(String, String)>,
+    client_context: Vec<(String, String)>,
+    /// Signatures of user builtins. Functions can't be saved so these are
+    /// only checked against the builtins defined when loading.
+    user_builtins: Vec<String>,
+    /// Value of the last interpreted expression, available as `@_`
+    #[serde(skip)]
+    last_value: Option<Value>,
+}
+
+impl ReplSession {
+    /// Compile time and runtime environments for evaluating an expression
+    fn envs(&self) -> (CompileTimeEnv, RuntimeEnv) {
+        let (var_keys, var_values) = unzip_key_values(self.vars.clone());
+        let (prompt_keys, prompt_values) = unzip_key_values(self.prompts.clone());
+        let (secret_keys, secret_values) = unzip_key_values(self.secrets.clone());
+        let (client_keys, client_values) = unzip_key_values(self.client_context.clone());
+
+        let mut env = CompileTimeEnv::new(var_keys, prompt_keys, secret_keys, client_keys);
+
+        let mut runtime_env = RuntimeEnv {
+            vars: var_values,
+            prompts: prompt_values,
+            secrets: secret_values,
+            client_context: client_values.into_iter().map(Value::from).collect(),
+        };
+
+        if let Some(last_value) = &self.last_value {
+            let i = env.add_to_client_context(REPL_LAST_VALUE_PLACEHOLDER);
+            runtime_env.add_to_client_context(i, last_value.clone());
+        }
+
+        (env, runtime_env)
+    }
+
+    /// Evaluate an expression as far as the [`ReplMode`] goes
+    ///
+    /// Interpreted values become the session's last value.
+    fn evaluate(&mut self, mode: &ReplMode, source: &str, vm: &mut Vm) -> ReplOutput {
+        if *mode == ReplMode::Lex {
+            return ReplOutput::Tokens(lex(source));
+        }
+
+        let (env, runtime_env) = self.envs();
+
+        let ast = match parse(source) {
+            Ok(ast) => ast,
+            Err(errs) => return ReplOutput::Diagnostics(get_diagnostics(&errs, source)),
+        };
+
+        if *mode == ReplMode::Parse {
+            return ReplOutput::Ast(ast);
+        }
+
+        let bytecode = match compile(&mut (ast, 0..source.len()), &env) {
+            Ok(bytecode) => bytecode,
+            Err(errs) => return ReplOutput::Diagnostics(get_diagnostics(&errs, source)),
+        };
+
+        match mode {
+            ReplMode::Compile => ReplOutput::ByteCode(bytecode),
+            ReplMode::Disassemble => ReplOutput::Disassembly(
+                Disassembler::new(&bytecode, &env)
+                    .disassemble()
+                    .expect("should disassemble compiled bytecode"),
+            ),
+            _ => match vm.interpret(bytecode.into(), &env, &runtime_env) {
+                Ok(value) => {
+                    self.last_value = Some(value.clone());
+
+                    ReplOutput::Value(value)
+                }
+                Err(errs) => ReplOutput::Diagnostics(get_diagnostics(&errs, source)),
+            },
+        }
+    }
+
+    /// Interpret an expression with [`Vm::explain`]
+    ///
+    /// The interpreted value becomes the session's last value.
+    fn explain(&mut self, source: &str, vm: &mut Vm) -> ReplOutput {
+        let (env, runtime_env) = self.envs();
+
+        let result = parse(source)
+            .and_then(|ast| compile(&mut (ast, 0..source.len()), &env))
+            .and_then(|bytecode| vm.explain(&bytecode, &env, &runtime_env));
+
+        match result {
+            Ok((value, explanation)) => {
+                self.last_value = Some(value);
+
+                ReplOutput::Explanation(explanation)
+            }
+            Err(errs) => ReplOutput::Diagnostics(get_diagnostics(&errs, source)),
+        }
+    }
+
+    fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about = "REPL to compile expressions")]
 struct Args {
@@ -457,4 +690,13 @@ struct Args {
     /// List of indexed client context names
     #[arg(long, value_delimiter = ' ', num_args = 1.., value_parser=parse_key_val::<String, String>)]
     client_context: Vec<(String, String)>,
+
+    /// Print how long each stage took after evaluating every expression
+    #[arg(long)]
+    timing: bool,
+
+    /// Run the commands and expressions in a file, one per line, instead of
+    /// reading input interactively
+    #[arg(long)]
+    script: Option<String>,
 }
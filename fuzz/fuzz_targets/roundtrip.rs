@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use reqlang_expr::{ast::Expr, fuzzing::roundtrip};
+
+fuzz_target!(|expr: Expr| {
+    roundtrip(&expr);
+});
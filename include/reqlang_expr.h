@@ -0,0 +1,50 @@
+/* C API for reqlang-expr, built with the `capi` feature. See src/capi.rs. */
+
+#ifndef REQLANG_EXPR_H
+#define REQLANG_EXPR_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An expression compiled by reqlang_expr_compile. Free with reqlang_expr_free. */
+typedef struct ReqlangExpr ReqlangExpr;
+
+/* A list of `len` null terminated UTF-8 strings. `items` may be NULL when `len` is 0. */
+typedef struct ReqlangExprStrings {
+    const char *const *items;
+    size_t len;
+} ReqlangExprStrings;
+
+/* Compile an expression against the names of the vars, prompts, secrets, and
+ * client context it can reference. Returns NULL on failure. */
+ReqlangExpr *reqlang_expr_compile(const char *source,
+                                  ReqlangExprStrings vars,
+                                  ReqlangExprStrings prompts,
+                                  ReqlangExprStrings secrets,
+                                  ReqlangExprStrings client_context);
+
+/* Evaluate a compiled expression with values in the same order as the names
+ * passed to reqlang_expr_compile. Returns the resulting value as a string, or
+ * NULL on failure. Free the result with reqlang_expr_string_free. */
+char *reqlang_expr_eval(const ReqlangExpr *expr,
+                        ReqlangExprStrings vars,
+                        ReqlangExprStrings prompts,
+                        ReqlangExprStrings secrets,
+                        ReqlangExprStrings client_context);
+
+/* The error message from the last call on this thread that failed, or NULL.
+ * Valid until the next call on this thread. */
+const char *reqlang_expr_last_error(void);
+
+void reqlang_expr_free(ReqlangExpr *expr);
+
+void reqlang_expr_string_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* REQLANG_EXPR_H */
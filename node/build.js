@@ -0,0 +1,14 @@
+// Copy the compiled library to reqlang_expr.node so Node can require it
+const fs = require("fs");
+const path = require("path");
+
+const profile = process.argv[2] || "release";
+const library = {
+  darwin: "libreqlang_expr_node.dylib",
+  win32: "reqlang_expr_node.dll",
+}[process.platform] || "libreqlang_expr_node.so";
+
+fs.copyFileSync(
+  path.join(__dirname, "target", profile, library),
+  path.join(__dirname, "reqlang_expr.node")
+);
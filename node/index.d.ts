@@ -0,0 +1,48 @@
+/** Names of the vars, prompts, secrets, and client context an expression can reference */
+export interface Names {
+  vars?: string[];
+  prompts?: string[];
+  secrets?: string[];
+  clientContext?: string[];
+}
+
+/** Values of the vars, prompts, secrets, and client context keyed by name */
+export interface Values {
+  vars?: Record<string, string>;
+  prompts?: Record<string, string>;
+  secrets?: Record<string, string>;
+  clientContext?: Record<string, string>;
+}
+
+/** A value produced by evaluating an expression */
+export interface ExprValue {
+  /** Name of the value's type e.g. `String` */
+  type: string;
+  /** Functions and types are returned in their source form */
+  value: string | number | boolean;
+}
+
+/** An error in an expression and where it occurred. Lines and characters are zero based. */
+export interface Diagnostic {
+  code: string;
+  message: string;
+  startLine: number;
+  startCharacter: number;
+  endLine: number;
+  endCharacter: number;
+}
+
+/** An expression compiled by `compile` */
+export class Expr {
+  /** Evaluate the expression. Every name the expression references must have a value. */
+  eval(values?: Values): ExprValue;
+}
+
+/** Compile an expression against the names it can reference. Throws on errors. */
+export function compile(source: string, names?: Names): Expr;
+
+/** Compile and evaluate an expression. Names are taken from the keys of the values passed in. */
+export function evaluate(source: string, values?: Values): ExprValue;
+
+/** Diagnostics for every lexing, parsing, and compilation error in an expression */
+export function diagnostics(source: string, names?: Names): Diagnostic[];
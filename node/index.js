@@ -0,0 +1 @@
+module.exports = require("./reqlang_expr.node");
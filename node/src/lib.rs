@@ -0,0 +1,242 @@
+//! Node.js bindings for reqlang-expr
+//!
+//! See [index.d.ts](../index.d.ts) for the JavaScript API.
+
+use std::collections::HashMap;
+
+use napi::{Error, Result, bindgen_prelude::Either3};
+use napi_derive::napi;
+use reqlang_expr::{
+    analysis::Requirements,
+    compiler::compile as compile_expr,
+    errors::{ExprErrorS, diagnostics::AsDiagnostic},
+    prelude::*,
+};
+
+/// Names of the vars, prompts, secrets, and client context an expression can
+/// reference
+#[napi(object)]
+#[derive(Default)]
+pub struct Names {
+    pub vars: Option<Vec<String>>,
+    pub prompts: Option<Vec<String>>,
+    pub secrets: Option<Vec<String>>,
+    pub client_context: Option<Vec<String>>,
+}
+
+impl Names {
+    fn env(self) -> CompileTimeEnv {
+        CompileTimeEnv::new(
+            self.vars.unwrap_or_default(),
+            self.prompts.unwrap_or_default(),
+            self.secrets.unwrap_or_default(),
+            self.client_context.unwrap_or_default(),
+        )
+    }
+}
+
+/// Values of the vars, prompts, secrets, and client context keyed by name
+#[napi(object)]
+#[derive(Default)]
+pub struct Values {
+    pub vars: Option<HashMap<String, String>>,
+    pub prompts: Option<HashMap<String, String>>,
+    pub secrets: Option<HashMap<String, String>>,
+    pub client_context: Option<HashMap<String, String>>,
+}
+
+impl Values {
+    /// Names taken from the keys of the values
+    fn names(&self) -> Names {
+        let keys = |values: &Option<HashMap<String, String>>| {
+            values
+                .as_ref()
+                .map(|values| values.keys().cloned().collect())
+        };
+
+        Names {
+            vars: keys(&self.vars),
+            prompts: keys(&self.prompts),
+            secrets: keys(&self.secrets),
+            client_context: keys(&self.client_context),
+        }
+    }
+}
+
+/// A value produced by evaluating an expression
+///
+/// Functions and types are returned in their source form.
+#[napi(object)]
+pub struct ExprValue {
+    #[napi(js_name = "type")]
+    pub ty: String,
+    pub value: Either3<String, f64, bool>,
+}
+
+impl From<Value> for ExprValue {
+    fn from(value: Value) -> Self {
+        let ty = value.get_type().to_string();
+
+        let value = match value {
+            Value::String(string) => Either3::A(string.to_string()),
+            Value::Number(number) => Either3::B(number),
+            Value::Bool(bool) => Either3::C(bool),
+            value => Either3::A(value.to_string()),
+        };
+
+        Self { ty, value }
+    }
+}
+
+/// An error in an expression and where it occurred
+///
+/// Lines and characters are zero based.
+#[napi(object)]
+pub struct Diagnostic {
+    pub code: String,
+    pub message: String,
+    pub start_line: u32,
+    pub start_character: u32,
+    pub end_line: u32,
+    pub end_character: u32,
+}
+
+/// An expression compiled by `compile`
+#[napi]
+pub struct Expr {
+    source: String,
+    env: CompileTimeEnv,
+    bytecode: ExprByteCode,
+}
+
+#[napi]
+impl Expr {
+    /// Evaluate the expression with values for the names it was compiled with
+    ///
+    /// Every name the expression references must have a value.
+    #[napi]
+    pub fn eval(&self, values: Option<Values>) -> Result<ExprValue> {
+        let values = values.unwrap_or_default();
+        let requirements = Requirements::of(&self.bytecode, &self.env);
+
+        let get = |kind: &str,
+                   names: &[String],
+                   required: &[String],
+                   values: &Option<HashMap<String, String>>| {
+            names
+                .iter()
+                .map(
+                    |name| match values.as_ref().and_then(|values| values.get(name)) {
+                        Some(value) => Ok(value.clone()),
+                        None if required.contains(name) => Err(Error::from_reason(format!(
+                            "missing value for {kind} `{name}`"
+                        ))),
+                        None => Ok(String::new()),
+                    },
+                )
+                .collect::<Result<Vec<String>>>()
+        };
+
+        let runtime_env = RuntimeEnv {
+            vars: get("var", self.env.vars(), &requirements.vars, &values.vars)?,
+            prompts: get(
+                "prompt",
+                self.env.prompts(),
+                &requirements.prompts,
+                &values.prompts,
+            )?,
+            secrets: get(
+                "secret",
+                self.env.secrets(),
+                &requirements.secrets,
+                &values.secrets,
+            )?,
+            client_context: get(
+                "client context",
+                self.env.client_context(),
+                &requirements.client_context,
+                &values.client_context,
+            )?
+            .into_iter()
+            .map(Value::from)
+            .collect(),
+        };
+
+        Vm::new()
+            .interpret(self.bytecode.clone().into(), &self.env, &runtime_env)
+            .map(ExprValue::from)
+            .map_err(|errs| error(&errs, &self.source))
+    }
+}
+
+/// Compile an expression against the names it can reference
+///
+/// Throws with the messages of every error in the expression.
+#[napi]
+pub fn compile(source: String, names: Option<Names>) -> Result<Expr> {
+    let env = names.unwrap_or_default().env();
+
+    let bytecode = compile_source(&source, &env).map_err(|errs| error(&errs, &source))?;
+
+    Ok(Expr {
+        source,
+        env,
+        bytecode,
+    })
+}
+
+/// Compile and evaluate an expression
+///
+/// Names are taken from the keys of the values passed in.
+#[napi]
+pub fn evaluate(source: String, values: Option<Values>) -> Result<ExprValue> {
+    let values = values.unwrap_or_default();
+
+    compile(source, Some(values.names()))?.eval(Some(values))
+}
+
+/// Diagnostics for every lexing, parsing, and compilation error in an
+/// expression
+#[napi]
+pub fn diagnostics(source: String, names: Option<Names>) -> Vec<Diagnostic> {
+    let env = names.unwrap_or_default().env();
+
+    match compile_source(&source, &env) {
+        Ok(_) => vec![],
+        Err(errs) => to_diagnostics(&errs, &source),
+    }
+}
+
+fn compile_source(source: &str, env: &CompileTimeEnv) -> ExprResult<ExprByteCode> {
+    let ast = parse(source)?;
+
+    compile_expr(&mut (ast, 0..source.len()), env)
+}
+
+fn to_diagnostics(errs: &[ExprErrorS], source: &str) -> Vec<Diagnostic> {
+    errs.iter()
+        .map(|(err, span)| {
+            let diagnostic = err.as_diagnostic(source, span);
+            let range = diagnostic.range;
+
+            Diagnostic {
+                code: diagnostic.code,
+                message: diagnostic.message,
+                start_line: range.start.line,
+                start_character: range.start.character,
+                end_line: range.end.line,
+                end_character: range.end.character,
+            }
+        })
+        .collect()
+}
+
+fn error(errs: &[ExprErrorS], source: &str) -> Error {
+    let message = to_diagnostics(errs, source)
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Error::from_reason(message)
+}
@@ -0,0 +1,45 @@
+const assert = require("assert");
+const { compile, evaluate, diagnostics } = require("./index");
+
+const expr = compile("(concat `Bearer ` !token)", { secrets: ["token"] });
+
+assert.deepStrictEqual(expr.eval({ secrets: { token: "abc123" } }), {
+  type: "String",
+  value: "Bearer abc123",
+});
+
+assert.throws(() => expr.eval(), /missing value for secret `token`/);
+
+assert.deepStrictEqual(evaluate("(eq :a `b`)", { vars: { a: "b" } }), {
+  type: "Bool",
+  value: true,
+});
+assert.deepStrictEqual(evaluate("(pow 2 3)"), { type: "Number", value: 8 });
+assert.deepStrictEqual(evaluate("@a", { clientContext: { a: "b" } }), {
+  type: "String",
+  value: "b",
+});
+
+assert.throws(() => compile("(concat :x)"), /undefined: :x/);
+
+assert.deepStrictEqual(diagnostics("(concat :x)"), [
+  {
+    code: "compiler",
+    message: "expects 2 arguments but received 1",
+    startLine: 0,
+    startCharacter: 0,
+    endLine: 0,
+    endCharacter: 11,
+  },
+  {
+    code: "compiler",
+    message: "undefined: :x",
+    startLine: 0,
+    startCharacter: 8,
+    endLine: 0,
+    endCharacter: 10,
+  },
+]);
+assert.deepStrictEqual(diagnostics(":x", { vars: ["x"] }), []);
+
+console.log("ok");
@@ -0,0 +1,605 @@
+//! Static analysis of expressions
+
+use std::fmt::Display;
+
+use crate::{
+    ast::{Expr, ExprIdentifier, ExprS, IdentifierKind},
+    builtins::BuiltinFn,
+    compiler::{CompileTimeEnv, ExprByteCode, compile, lookup},
+    errors::{
+        DependencyError, ExprResult,
+        diagnostics::{AsDiagnostic, ExprDiagnosisSeverity, ExprDiagnostic, get_range},
+    },
+    parser::parse,
+    span::{Span, Spanned},
+    types::Type,
+};
+
+/// The inputs an expression needs for evaluation to succeed
+///
+/// Names are stored without their sigil, in the order they're first
+/// referenced.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Requirements {
+    pub vars: Vec<String>,
+    /// Prompts that must be asked
+    pub prompts: Vec<String>,
+    /// Secrets that must be resolvable
+    pub secrets: Vec<String>,
+    /// Client context keys that must be provided
+    pub client_context: Vec<String>,
+}
+
+impl Requirements {
+    /// Requirements of already compiled bytecode
+    pub fn of(bytecode: &ExprByteCode, env: &CompileTimeEnv) -> Self {
+        let names = |lookup_type: u8, names: &[String]| -> Vec<String> {
+            bytecode
+                .referenced_indexes(lookup_type)
+                .into_iter()
+                .filter_map(|index| names.get(index as usize).cloned())
+                .collect()
+        };
+
+        Self {
+            vars: names(lookup::VAR, env.vars()),
+            prompts: names(lookup::PROMPT, env.prompts()),
+            secrets: names(lookup::SECRET, env.secrets()),
+            client_context: names(lookup::CLIENT_CTX, env.client_context()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+            && self.prompts.is_empty()
+            && self.secrets.is_empty()
+            && self.client_context.is_empty()
+    }
+}
+
+/// Report the inputs an expression requires without evaluating it
+///
+/// Clients can use this to gather prompts, secrets, and client context before
+/// running a request.
+///
+/// ```
+/// use reqlang_expr::{analysis::requirements, prelude::*};
+///
+/// let env = CompileTimeEnv::new(
+///     vec![],
+///     vec!["otp".to_string(), "unused".to_string()],
+///     vec!["token".to_string()],
+///     vec![],
+/// );
+///
+/// let requirements = requirements("(concat !token ?otp)", &env).unwrap();
+///
+/// assert_eq!(vec!["otp".to_string()], requirements.prompts);
+/// assert_eq!(vec!["token".to_string()], requirements.secrets);
+/// ```
+pub fn requirements(source: &str, env: &CompileTimeEnv) -> ExprResult<Requirements> {
+    let ast = parse(source)?;
+    let bytecode = compile(&mut (ast, 0..source.len()), env)?;
+
+    Ok(Requirements::of(&bytecode, env))
+}
+
+/// Which named expressions consume values produced by other named expressions
+///
+/// An expression named with a var (`:name`) or client context (`@name`)
+/// identifier produces that value. Expressions referencing it depend on the
+/// expression producing it. Other names (e.g. `Authorization`) produce
+/// nothing other expressions can reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyGraph {
+    names: Vec<String>,
+    /// Indexes of the expressions each expression depends on
+    dependencies: Vec<Vec<usize>>,
+}
+
+impl DependencyGraph {
+    /// Names of the expressions in the order they were given
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Names of the expressions an expression directly depends on
+    pub fn dependencies(&self, name: &str) -> Option<Vec<&str>> {
+        let index = self.names.iter().position(|n| n == name)?;
+
+        Some(
+            self.dependencies[index]
+                .iter()
+                .map(|&i| self.names[i].as_str())
+                .collect(),
+        )
+    }
+
+    /// An order to evaluate the expressions in so every expression is
+    /// evaluated after its dependencies
+    ///
+    /// Independent expressions keep the order they were given in.
+    pub fn evaluation_order(&self) -> Result<Vec<&str>, DependencyError> {
+        let mut evaluated = vec![false; self.names.len()];
+        let mut order = Vec::with_capacity(self.names.len());
+
+        while order.len() < self.names.len() {
+            let next = (0..self.names.len())
+                .find(|&i| !evaluated[i] && self.dependencies[i].iter().all(|&dep| evaluated[dep]));
+
+            let Some(next) = next else {
+                return Err(DependencyError::Cycle(self.find_cycle(&evaluated)));
+            };
+
+            evaluated[next] = true;
+            order.push(self.names[next].as_str());
+        }
+
+        Ok(order)
+    }
+
+    /// Walk dependencies from an unevaluated expression until one repeats
+    fn find_cycle(&self, evaluated: &[bool]) -> Vec<String> {
+        let mut path: Vec<usize> = vec![];
+        let mut current = evaluated
+            .iter()
+            .position(|evaluated| !evaluated)
+            .expect("should have an unevaluated expression");
+
+        while !path.contains(&current) {
+            path.push(current);
+
+            current = *self.dependencies[current]
+                .iter()
+                .find(|&&dep| !evaluated[dep])
+                .expect("unevaluated expression should have an unevaluated dependency");
+        }
+
+        let start = path.iter().position(|&i| i == current).unwrap();
+
+        path[start..]
+            .iter()
+            .chain([&current])
+            .map(|&i| self.names[i].clone())
+            .collect()
+    }
+}
+
+/// Build a [`DependencyGraph`] from named expressions
+///
+/// Every expression is compiled against `env`, which must declare the vars
+/// and client context the expressions produce.
+///
+/// ```
+/// use reqlang_expr::{analysis::dependency_graph, prelude::*};
+///
+/// let env = CompileTimeEnv::new(
+///     vec!["token".to_string()],
+///     vec![],
+///     vec!["key".to_string()],
+///     vec![],
+/// );
+///
+/// let graph = dependency_graph(
+///     &[
+///         ("Authorization", "(concat `Bearer ` :token)"),
+///         (":token", "(trim !key)"),
+///     ],
+///     &env,
+/// )
+/// .unwrap();
+///
+/// assert_eq!(
+///     vec![":token", "Authorization"],
+///     graph.evaluation_order().unwrap()
+/// );
+/// ```
+pub fn dependency_graph(
+    exprs: &[(&str, &str)],
+    env: &CompileTimeEnv,
+) -> Result<DependencyGraph, Vec<DependencyError>> {
+    let mut requirements = vec![];
+    let mut errs = vec![];
+
+    for (name, source) in exprs {
+        match self::requirements(source, env) {
+            Ok(r) => requirements.push(r),
+            Err(e) => errs.push(DependencyError::Expr {
+                name: name.to_string(),
+                errs: e,
+            }),
+        }
+    }
+
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+
+    let producer = |kind: IdentifierKind, lookup_name: &str| {
+        exprs.iter().position(|(name, _)| {
+            if !name.is_char_boundary(1) {
+                return false;
+            }
+
+            let identifier = ExprIdentifier::new(name);
+
+            *identifier.identifier_kind() == kind && identifier.lookup_name() == lookup_name
+        })
+    };
+
+    let dependencies = requirements
+        .iter()
+        .map(|r| {
+            let mut dependencies: Vec<usize> = vec![];
+
+            let produced = r
+                .vars
+                .iter()
+                .filter_map(|var| producer(IdentifierKind::Var, var))
+                .chain(
+                    r.client_context
+                        .iter()
+                        .filter_map(|key| producer(IdentifierKind::Client, key)),
+                );
+
+            for i in produced {
+                if !dependencies.contains(&i) {
+                    dependencies.push(i);
+                }
+            }
+
+            dependencies
+        })
+        .collect();
+
+    Ok(DependencyGraph {
+        names: exprs.iter().map(|(name, _)| name.to_string()).collect(),
+        dependencies,
+    })
+}
+
+/// An expression that compiles but may fail at runtime
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lint {
+    /// An argument that could be any value is passed to a builtin expecting
+    /// `expected`, which fails with a type mismatch at runtime if it isn't
+    ImplicitConversion {
+        callee: String,
+        arg: String,
+        expected: Type,
+    },
+}
+
+pub type LintS = Spanned<Lint>;
+
+impl Lint {
+    /// How to make the expression explicit
+    pub fn suggestion(&self) -> Option<&'static str> {
+        match self {
+            Lint::ImplicitConversion { expected, .. } => match expected {
+                Type::String => Some("convert it with `to_str`"),
+                Type::Bool => Some("compare it with `eq`"),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lint::ImplicitConversion {
+                callee,
+                arg,
+                expected,
+            } => write!(
+                f,
+                "{callee} expects {expected} for {arg} but may receive any value at runtime"
+            )?,
+        }
+
+        if let Some(suggestion) = self.suggestion() {
+            write!(f, ", {suggestion}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl AsDiagnostic for Lint {
+    fn as_diagnostic(&self, source: &str, span: &Span) -> ExprDiagnostic {
+        ExprDiagnostic {
+            code: "lint".to_string(),
+            range: get_range(source, span),
+            severity: Some(ExprDiagnosisSeverity::WARNING),
+            message: format!("{self}"),
+            notes: vec![],
+            fix: None,
+        }
+    }
+}
+
+/// Report expressions that compile but may fail at runtime
+///
+/// Arguments typed as any value (e.g. the result of `id`) can be passed to
+/// builtins expecting a specific type. The builtin converts the argument at
+/// runtime, returning a type mismatch error if the value isn't that type.
+///
+/// ```
+/// use reqlang_expr::{analysis::{Lint, lint}, prelude::*};
+///
+/// let env = CompileTimeEnv::new(vec![], vec![], vec![], vec!["flag".to_string()]);
+///
+/// let lints = lint("(not (id @flag))", &env).unwrap();
+///
+/// assert_eq!(
+///     vec![(
+///         Lint::ImplicitConversion {
+///             callee: "not".to_string(),
+///             arg: "value".to_string(),
+///             expected: Type::Bool,
+///         },
+///         5..15
+///     )],
+///     lints
+/// );
+/// ```
+pub fn lint(source: &str, env: &CompileTimeEnv) -> ExprResult<Vec<LintS>> {
+    let mut ast = (parse(source)?, 0..source.len());
+
+    // Compiling types the identifiers in the ast
+    compile(&mut ast, env)?;
+
+    let mut lints = vec![];
+
+    lint_expr(&ast, env, &mut lints);
+
+    Ok(lints)
+}
+
+fn lint_expr((expr, _): &ExprS, env: &CompileTimeEnv, lints: &mut Vec<LintS>) {
+    if let Expr::Let(binding) = expr {
+        lint_expr(&binding.value, env, lints);
+        lint_expr(&binding.body, env, lints);
+
+        return;
+    }
+
+    if let Expr::Spread(spread) = expr {
+        lint_expr(&spread.list, env, lints);
+
+        return;
+    }
+
+    let Expr::Call(call) = expr else {
+        return;
+    };
+
+    if let Some(builtin) = builtin(&call.callee.0, env) {
+        for (i, (arg, arg_span)) in call.args.iter().enumerate() {
+            let param = builtin
+                .args
+                .get(i)
+                .or(builtin.args.last().filter(|param| param.variadic));
+
+            let Some(param) = param else {
+                continue;
+            };
+
+            let implicit =
+                matches!(param.ty, Type::String | Type::Bool) && arg_type(arg, env) == Type::Value;
+
+            if implicit {
+                lints.push((
+                    Lint::ImplicitConversion {
+                        callee: builtin.name.to_string(),
+                        arg: param.name.to_string(),
+                        expected: param.ty.clone(),
+                    },
+                    arg_span.clone(),
+                ));
+            }
+        }
+    }
+
+    lint_expr(&call.callee, env, lints);
+
+    for arg in &call.args {
+        lint_expr(arg, env, lints);
+    }
+}
+
+/// The builtin an expression refers to
+fn builtin<'a>(expr: &Expr, env: &'a CompileTimeEnv) -> Option<&'a BuiltinFn<'a>> {
+    let Expr::Identifier(identifier) = expr else {
+        return None;
+    };
+
+    if *identifier.identifier_kind() != IdentifierKind::Builtin {
+        return None;
+    }
+
+    let name = identifier.lookup_name();
+
+    env.get_builtin_index(name)
+        .or_else(|| env.get_user_builtin_index(name))
+        .map(|(builtin, _)| builtin)
+}
+
+/// Type of an argument, including the return type of builtin calls
+fn arg_type(expr: &Expr, env: &CompileTimeEnv) -> Type {
+    match expr {
+        Expr::Call(call) => builtin(&call.callee.0, env)
+            .map(|builtin| builtin.return_type.clone())
+            .unwrap_or(Type::Unknown),
+        expr => expr.get_type(),
+    }
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use super::*;
+
+    use crate::errors::{CompileError, ExprError};
+
+    fn env() -> CompileTimeEnv {
+        CompileTimeEnv::new(
+            vec!["a".to_string(), "b".to_string()],
+            vec!["c".to_string(), "d".to_string()],
+            vec!["e".to_string(), "f".to_string()],
+            vec!["g".to_string(), "h".to_string()],
+        )
+    }
+
+    #[test]
+    fn no_requirements() {
+        assert!(requirements("(concat `a` `b`)", &env()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn requirements_in_reference_order_without_duplicates() {
+        assert_eq!(
+            Ok(Requirements {
+                vars: vec!["b".to_string(), "a".to_string()],
+                prompts: vec!["d".to_string()],
+                secrets: vec!["e".to_string()],
+                client_context: vec!["h".to_string()],
+            }),
+            requirements("(concat :b ?d :a !e @h :b !e)", &env())
+        );
+    }
+
+    #[test]
+    fn requirements_of_invalid_source() {
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::Undefined("?z".to_string())),
+                0..2
+            )]),
+            requirements("?z", &env())
+        );
+    }
+
+    #[test]
+    fn lints_any_value_passed_where_string_or_bool_expected() {
+        assert_eq!(
+            Ok(vec![(
+                Lint::ImplicitConversion {
+                    callee: "not".to_string(),
+                    arg: "value".to_string(),
+                    expected: Type::Bool,
+                },
+                5..12
+            )]),
+            lint("(not (id @g))", &env())
+        );
+        assert_eq!(
+            Ok(vec![(
+                Lint::ImplicitConversion {
+                    callee: "trim".to_string(),
+                    arg: "value".to_string(),
+                    expected: Type::String,
+                },
+                18..30
+            )]),
+            lint("(concat `a` (trim (id (id :a))))", &env())
+        );
+    }
+
+    #[test]
+    fn no_lints_for_typed_or_any_value_args() {
+        assert_eq!(Ok(vec![]), lint("(not (eq @g `x`))", &env()));
+        assert_eq!(Ok(vec![]), lint("(concat (id :a) (trim :b))", &env()));
+    }
+
+    #[test]
+    fn lints_are_warnings() {
+        let source = "(not (id @g))";
+        let (lint, span) = lint(source, &env()).unwrap().remove(0);
+
+        let diagnostic = lint.as_diagnostic(source, &span).to_diagnostic(&span);
+
+        assert_eq!(
+            codespan_reporting::diagnostic::Severity::Warning,
+            diagnostic.severity
+        );
+        assert_eq!(
+            "not expects Bool for value but may receive any value at runtime, compare it with `eq`",
+            diagnostic.message
+        );
+    }
+
+    fn graph_env() -> CompileTimeEnv {
+        CompileTimeEnv::new(
+            vec!["a".to_string(), "b".to_string()],
+            vec![],
+            vec![],
+            vec!["c".to_string()],
+        )
+    }
+
+    #[test]
+    fn dependency_graph_evaluation_order() {
+        let graph = dependency_graph(
+            &[
+                ("Header", "(concat :a @c)"),
+                ("@c", ":b"),
+                (":a", "(concat :b `!`)"),
+                (":b", "`b`"),
+            ],
+            &graph_env(),
+        )
+        .unwrap();
+
+        assert_eq!(Some(vec![":a", "@c"]), graph.dependencies("Header"));
+        assert_eq!(Some(vec![]), graph.dependencies(":b"));
+        assert_eq!(None, graph.dependencies(":z"));
+
+        assert_eq!(
+            Ok(vec![":b", "@c", ":a", "Header"]),
+            graph.evaluation_order()
+        );
+    }
+
+    #[test]
+    fn dependency_graph_cycle() {
+        let graph = dependency_graph(
+            &[("Header", ":a"), (":a", ":b"), (":b", "(concat :a `!`)")],
+            &graph_env(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Err(DependencyError::Cycle(vec![
+                ":a".to_string(),
+                ":b".to_string(),
+                ":a".to_string()
+            ])),
+            graph.evaluation_order()
+        );
+    }
+
+    #[test]
+    fn dependency_graph_self_reference() {
+        let graph = dependency_graph(&[(":a", ":a")], &graph_env()).unwrap();
+
+        assert_eq!(
+            "dependency cycle: :a -> :a",
+            graph.evaluation_order().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn dependency_graph_compile_errors() {
+        assert_eq!(
+            Err(vec![DependencyError::Expr {
+                name: ":b".to_string(),
+                errs: vec![(
+                    ExprError::CompileError(CompileError::Undefined(":z".to_string())),
+                    0..2
+                )]
+            }]),
+            dependency_graph(&[(":a", "`a`"), (":b", ":z")], &graph_env())
+        );
+    }
+}
@@ -0,0 +1,692 @@
+//! The assembler and associated types
+
+use crate::{
+    compiler::{CompileTimeEnv, ExprByteCode, get_version_bytes, lookup, opcode},
+    disassembler::Disassembler,
+    errors::{AssembleError, RoundtripError},
+    types::Type,
+    value::Value,
+};
+
+/// Assemble the text produced by [`crate::disassembler::Disassembler`] back
+/// in to [`ExprByteCode`]
+///
+/// Names referenced by `GET` and `CALL_BUILTIN` ops are checked against
+/// `env`. Types are rebuilt from their names so disassembling the assembled
+/// bytecode produces the same text, even if the types themselves differ
+/// (e.g. `Type<String>` disassembles as `String`).
+///
+/// ```
+/// use reqlang_expr::{assembler::assemble, disassembler::Disassembler, prelude::*};
+///
+/// let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+///
+/// let source = "(concat :a `b`)";
+/// let ast = parse(source).unwrap();
+/// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+///
+/// let disassembled = Disassembler::new(&bytecode, &env).disassemble().unwrap();
+///
+/// assert_eq!(Ok(bytecode), assemble(&disassembled, &env));
+/// ```
+pub fn assemble(source: &str, env: &CompileTimeEnv) -> Result<ExprByteCode, AssembleError> {
+    // Split on new lines only so carriage returns in string constants are kept
+    let mut lines = source
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| (i + 1, line));
+
+    let version = lines
+        .next()
+        .and_then(|(_, line)| line.strip_prefix("VERSION "))
+        .ok_or(AssembleError::MissingHeader)?;
+
+    if lines.next().map(|(_, line)| line) != Some("----") {
+        return Err(AssembleError::MissingHeader);
+    }
+
+    let version_bytes = get_version_bytes();
+
+    let expected_version: String = version_bytes.iter().map(|byte| byte.to_string()).collect();
+
+    if version != expected_version {
+        return Err(AssembleError::UnsupportedVersion(version.to_string()));
+    }
+
+    let mut assembler = Assembler {
+        env,
+        codes: version_bytes.to_vec(),
+        constants: vec![],
+        types: vec![],
+    };
+
+    while let Some((line_number, line)) = lines.next() {
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut line = line.to_string();
+
+        // String constants containing new lines span multiple lines
+        while is_unterminated_string_constant(&line) {
+            let Some((_, next_line)) = lines.next() else {
+                break;
+            };
+
+            line.push('\n');
+            line.push_str(next_line);
+        }
+
+        assembler
+            .assemble_line(&line)
+            .map_err(|message| AssembleError::InvalidLine {
+                line: line_number,
+                message,
+            })?;
+    }
+
+    let constants = assembler
+        .constants
+        .into_iter()
+        .enumerate()
+        .map(|(i, constant)| constant.ok_or(AssembleError::MissingConstant(i)))
+        .collect::<Result<Vec<Value>, AssembleError>>()?;
+
+    let types = assembler
+        .types
+        .into_iter()
+        .enumerate()
+        .map(|(i, ty)| ty.ok_or(AssembleError::MissingType(i)))
+        .collect::<Result<Vec<Type>, AssembleError>>()?;
+
+    Ok(ExprByteCode::new(assembler.codes, constants, types))
+}
+
+/// Check bytecode survives disassembling and assembling unchanged
+///
+/// Run it in CI against the bytecode of your own expressions to catch the
+/// [`Disassembler`] and [`assemble`] drifting apart when this crate's
+/// bytecode version changes. Ops are compared one at a time, in the order
+/// the disassembler walks them, so the first op that differs is reported.
+/// Types are compared by name since assembling them is lossy.
+///
+/// ```
+/// use reqlang_expr::{assembler::verify_roundtrip, prelude::*};
+///
+/// let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+///
+/// let source = "(cond (eq :a `b`) String 1.5)";
+/// let ast = parse(source).unwrap();
+/// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+///
+/// assert_eq!(Ok(()), verify_roundtrip(&bytecode, &env));
+/// ```
+pub fn verify_roundtrip(
+    bytecode: &ExprByteCode,
+    env: &CompileTimeEnv,
+) -> Result<(), RoundtripError> {
+    let disassembler = Disassembler::new(bytecode, env);
+
+    let disassembled = disassembler
+        .disassemble()
+        .map_err(RoundtripError::Disassemble)?;
+
+    let assembled = assemble(&disassembled, env)?;
+
+    let expected = bytecode.codes();
+    let actual = assembled.codes();
+
+    let mut op_idx = 0;
+
+    while op_idx < expected.len() {
+        let (op_size, _, _) = disassembler
+            .disassemble_op(op_idx)
+            .map_err(RoundtripError::Disassemble)?;
+
+        let expected_op = &expected[op_idx..op_idx + op_size];
+        let actual_op: Vec<u8> = actual.iter().skip(op_idx).take(op_size).copied().collect();
+
+        if actual_op != expected_op {
+            return Err(RoundtripError::OpMismatch {
+                op_idx,
+                expected: expected_op.to_vec(),
+                actual: actual_op,
+            });
+        }
+
+        op_idx += op_size;
+    }
+
+    if actual.len() > expected.len() {
+        return Err(RoundtripError::OpMismatch {
+            op_idx,
+            expected: vec![],
+            actual: actual[op_idx..].to_vec(),
+        });
+    }
+
+    let constants = bytecode.constants().len().max(assembled.constants().len());
+
+    for index in 0..constants {
+        let expected = bytecode.constants().get(index);
+        let actual = assembled.constants().get(index);
+
+        if expected != actual {
+            return Err(RoundtripError::ConstantMismatch {
+                index,
+                expected: expected.cloned(),
+                actual: actual.cloned(),
+            });
+        }
+    }
+
+    let types = bytecode.types().len().max(assembled.types().len());
+
+    for index in 0..types {
+        let expected = bytecode.types().get(index).map(Type::name);
+        let actual = assembled.types().get(index).map(Type::name);
+
+        if expected != actual {
+            return Err(RoundtripError::TypeMismatch {
+                index,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+struct Assembler<'env> {
+    env: &'env CompileTimeEnv,
+    codes: Vec<u8>,
+    constants: Vec<Option<Value>>,
+    types: Vec<Option<Type>>,
+}
+
+impl Assembler<'_> {
+    fn assemble_line(&mut self, line: &str) -> Result<(), String> {
+        let (op_idx, op) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("expected an op: {line}"))?;
+
+        let expected_op_idx = self.codes.len() - get_version_bytes().len();
+
+        if op_idx.parse::<usize>().ok() != Some(expected_op_idx) {
+            return Err(format!(
+                "expected op at {expected_op_idx:04} but found {op_idx}"
+            ));
+        }
+
+        let (name, operands) = op.split_once(' ').unwrap_or((op, ""));
+        let operands = operands.trim_start();
+
+        match name {
+            "TRUE" | "FALSE" if !operands.is_empty() => {
+                return Err(format!("unexpected operands for {name}: {operands}"));
+            }
+            "TRUE" => self.codes.push(opcode::TRUE),
+            "FALSE" => self.codes.push(opcode::FALSE),
+            "CALL" => {
+                let arg_count = parse_arg_count(operands)?;
+
+                self.codes.extend([opcode::CALL, arg_count]);
+            }
+            "CALL_SPREAD" => {
+                let arg_count = parse_arg_count(operands)?;
+
+                self.codes.extend([opcode::CALL_SPREAD, arg_count]);
+            }
+            "LIST" => {
+                let item_count = parse_item_count(operands)?;
+
+                self.codes.extend([opcode::LIST, item_count]);
+            }
+            "GET_LOCAL" | "SET_LOCAL" => {
+                let slot = operands
+                    .parse::<u8>()
+                    .map_err(|_| format!("invalid slot: {operands}"))?;
+
+                let op_code = if name == "GET_LOCAL" {
+                    opcode::GET_LOCAL
+                } else {
+                    opcode::SET_LOCAL
+                };
+
+                self.codes.extend([op_code, slot]);
+            }
+            "JUMP_IF_FALSE" | "JUMP" | "TRY" | "END_TRY" => {
+                let offset = parse_jump(operands, expected_op_idx)?;
+
+                let op_code = match name {
+                    "JUMP_IF_FALSE" => opcode::JUMP_IF_FALSE,
+                    "JUMP" => opcode::JUMP,
+                    "TRY" => opcode::TRY,
+                    _ => opcode::END_TRY,
+                };
+
+                self.codes.push(op_code);
+                self.codes.extend(offset.to_be_bytes());
+            }
+            "CONSTANT" => {
+                let (index, value) = parse_indexed(operands)?;
+                let value = value
+                    .strip_suffix('\'')
+                    .ok_or_else(|| format!("unterminated constant: {value}"))?;
+
+                define(&mut self.constants, index, parse_constant(value)?)?;
+
+                self.codes.extend([opcode::CONSTANT, index]);
+            }
+            "GET" => {
+                let (lookup_name, operands) = operands
+                    .split_once(' ')
+                    .ok_or_else(|| format!("expected a lookup type: {operands}"))?;
+
+                let (index, value) = parse_indexed(operands.trim_start())?;
+                let value = value
+                    .strip_suffix('\'')
+                    .ok_or_else(|| format!("unterminated name: {value}"))?;
+
+                let lookup_type = self.assemble_lookup(lookup_name, index, value)?;
+
+                self.codes.extend([opcode::GET, lookup_type, index]);
+            }
+            "CALL_BUILTIN" => {
+                let (index, value) = parse_indexed(operands)?;
+                let (value, arg_count) = value
+                    .split_once("' ")
+                    .ok_or_else(|| format!("expected an arg count: {value}"))?;
+
+                let arg_count = parse_arg_count(arg_count)?;
+
+                expect_name(
+                    "builtin",
+                    self.env
+                        .get_builtin(index as usize)
+                        .map(|builtin| builtin.name),
+                    index,
+                    value,
+                )?;
+
+                self.codes.extend([opcode::CALL_BUILTIN, index, arg_count]);
+            }
+            _ => return Err(format!("unknown op: {name}")),
+        }
+
+        Ok(())
+    }
+
+    /// Check the name of a lookup and return its lookup type
+    fn assemble_lookup(&mut self, lookup_name: &str, index: u8, name: &str) -> Result<u8, String> {
+        let idx = index as usize;
+
+        let (lookup_type, expected) = match lookup_name {
+            "BUILTIN" => (
+                lookup::BUILTIN,
+                self.env.get_builtin(idx).map(|builtin| builtin.name),
+            ),
+            "USER_BUILTIN" => (
+                lookup::USER_BUILTIN,
+                self.env.get_user_builtin(idx).map(|builtin| builtin.name),
+            ),
+            "VAR" => (lookup::VAR, self.env.get_var(idx).map(|s| s.as_str())),
+            "PROMPT" => (lookup::PROMPT, self.env.get_prompt(idx).map(|s| s.as_str())),
+            "SECRET" => (lookup::SECRET, self.env.get_secret(idx).map(|s| s.as_str())),
+            "CLIENT_CTX" => (
+                lookup::CLIENT_CTX,
+                self.env.get_client_context(idx).map(|s| s.as_str()),
+            ),
+            "TYPE" => {
+                define(&mut self.types, index, Type::from(name))?;
+
+                return Ok(lookup::TYPE);
+            }
+            _ => return Err(format!("unknown lookup type: {lookup_name}")),
+        };
+
+        expect_name(&lookup_name.to_lowercase(), expected, index, name)?;
+
+        Ok(lookup_type)
+    }
+}
+
+/// If a line is a `CONSTANT` op with a string value that continues on the
+/// next line
+fn is_unterminated_string_constant(line: &str) -> bool {
+    let Some((_, value)) = line.split_once(" == '") else {
+        return false;
+    };
+
+    let is_constant = line
+        .split_whitespace()
+        .nth(1)
+        .is_some_and(|name| name == "CONSTANT");
+
+    is_constant && value.starts_with('`') && !(value.len() >= 3 && value.ends_with("`'"))
+}
+
+/// Parse `$INDEX == '$VALUE...` returning the index and everything after the
+/// opening quote
+fn parse_indexed(operands: &str) -> Result<(u8, &str), String> {
+    let (index, value) = operands
+        .split_once(" == '")
+        .ok_or_else(|| format!("expected $INDEX == '$VALUE': {operands}"))?;
+
+    let index = index
+        .trim()
+        .parse::<u8>()
+        .map_err(|_| format!("invalid index: {}", index.trim()))?;
+
+    Ok((index, value))
+}
+
+/// Parse `($ARG_COUNT args)`
+fn parse_arg_count(operands: &str) -> Result<u8, String> {
+    operands
+        .strip_prefix('(')
+        .and_then(|operands| operands.strip_suffix(" args)"))
+        .and_then(|arg_count| arg_count.parse::<u8>().ok())
+        .ok_or_else(|| format!("expected ($ARG_COUNT args): {operands}"))
+}
+
+/// Parse `$OFFSET -> $TARGET`, checking the jump from `op_idx` lands on `$TARGET`
+fn parse_jump(operands: &str, op_idx: usize) -> Result<u16, String> {
+    let (offset, target) = operands
+        .split_once(" -> ")
+        .ok_or_else(|| format!("expected $OFFSET -> $TARGET: {operands}"))?;
+
+    let offset = offset
+        .trim()
+        .parse::<u16>()
+        .map_err(|_| format!("invalid offset: {}", offset.trim()))?;
+
+    let target = target
+        .parse::<usize>()
+        .map_err(|_| format!("invalid target: {target}"))?;
+
+    let expected = op_idx + 3 + offset as usize;
+
+    if target != expected {
+        return Err(format!(
+            "expected jump to {expected:04} but found {target:04}"
+        ));
+    }
+
+    Ok(offset)
+}
+
+fn parse_item_count(operands: &str) -> Result<u8, String> {
+    operands
+        .strip_prefix('(')
+        .and_then(|operands| operands.strip_suffix(" items)"))
+        .and_then(|item_count| item_count.parse::<u8>().ok())
+        .ok_or_else(|| format!("expected ($ITEM_COUNT items): {operands}"))
+}
+
+fn parse_constant(value: &str) -> Result<Value, String> {
+    if let Some(string) = value
+        .strip_prefix('`')
+        .and_then(|value| value.strip_suffix('`'))
+        .filter(|string| !string.contains('`'))
+    {
+        return Ok(Value::String(string.into()));
+    }
+
+    match value {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => value
+            .parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid constant: {value}")),
+    }
+}
+
+fn expect_name(kind: &str, expected: Option<&str>, index: u8, name: &str) -> Result<(), String> {
+    match expected {
+        Some(expected) if expected == name => Ok(()),
+        Some(expected) => Err(format!(
+            "expected {kind} '{expected}' at index {index} but found '{name}'"
+        )),
+        None => Err(format!("no {kind} at index {index}")),
+    }
+}
+
+/// Define the value at an index, growing the list as needed
+fn define<T: PartialEq + std::fmt::Debug>(
+    slots: &mut Vec<Option<T>>,
+    index: u8,
+    value: T,
+) -> Result<(), String> {
+    let index = index as usize;
+
+    if slots.len() <= index {
+        slots.resize_with(index + 1, || None);
+    }
+
+    match &slots[index] {
+        Some(existing) if existing != &value => Err(format!(
+            "conflicting values at index {index}: {existing:?} and {value:?}"
+        )),
+        _ => {
+            slots[index] = Some(value);
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod assembler_tests {
+    use super::*;
+
+    use crate::{
+        compiler::{CompileOptions, compile_with_options},
+        disassembler::Disassembler,
+        parser::parse,
+    };
+
+    fn env() -> CompileTimeEnv {
+        CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        )
+    }
+
+    fn disassemble(source: &str, fuse_calls: bool) -> (ExprByteCode, String) {
+        let env = env();
+        let ast = parse(source).unwrap();
+        let bytecode = compile_with_options(
+            &mut (ast, 0..source.len()),
+            &env,
+            &CompileOptions {
+                fuse_calls,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let disassembled = Disassembler::new(&bytecode, &env).disassemble().unwrap();
+
+        (bytecode, disassembled)
+    }
+
+    #[test]
+    fn round_trips() {
+        for source in [
+            "true",
+            "`a`",
+            "1.5",
+            "(noop)",
+            "(concat :a ?b !c `e` `e` 1)",
+            "(cond (eq :a `a`) (not false) true)",
+            "(eq (type @d) String)",
+            "(id Fn(String, ...Value) -> Bool)",
+            "(or (eq :a `a`) (and true (id @d)))",
+            "(try (parse_bool :a) (try (eq :a `a`) false))",
+            "(concat :a ...[?b `c`])",
+        ] {
+            for fuse_calls in [false, true] {
+                let (bytecode, disassembled) = disassemble(source, fuse_calls);
+
+                assert_eq!(
+                    Ok(bytecode),
+                    assemble(&disassembled, &env()),
+                    "{source}\n{disassembled}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_multi_line_string_constants() {
+        for source in ["`a\nb`", "`\n'`", "`'\n`", "`\n`", "`a\r\n\nb`"] {
+            let (bytecode, disassembled) = disassemble(source, false);
+
+            assert_eq!(Ok(bytecode), assemble(&disassembled, &env()));
+        }
+    }
+
+    #[test]
+    fn lossy_types_round_trip_disassembly() {
+        let env = env();
+        let (_, disassembled) = disassemble("(id Type<String>)", false);
+        let assembled = assemble(&disassembled, &env).unwrap();
+
+        assert_eq!(
+            disassembled,
+            Disassembler::new(&assembled, &env).disassemble().unwrap()
+        );
+    }
+
+    #[test]
+    fn verifies_roundtrip() {
+        for source in [
+            "(concat :a ?b !c `e` `e` 1)",
+            "(cond (eq :a `a`) (not false) true)",
+            "(id Type<String>)",
+            "`a\nb`",
+            "(let (x :a) (concat x x))",
+        ] {
+            let (bytecode, _) = disassemble(source, false);
+
+            assert_eq!(Ok(()), verify_roundtrip(&bytecode, &env()), "{source}");
+        }
+    }
+
+    fn bytecode(codes: &[u8], constants: Vec<Value>) -> ExprByteCode {
+        let mut bytes = get_version_bytes().to_vec();
+        bytes.extend(codes);
+
+        ExprByteCode::new(bytes, constants, vec![])
+    }
+
+    #[test]
+    fn roundtrip_unknown_op() {
+        assert_eq!(
+            Err(RoundtripError::Assemble(AssembleError::InvalidLine {
+                line: 4,
+                message: "unknown op: UNKNOWN".to_string()
+            })),
+            verify_roundtrip(&bytecode(&[opcode::TRUE, 0xff], vec![]), &env())
+        );
+    }
+
+    #[test]
+    fn roundtrip_constant_mismatch() {
+        // Constants no op references aren't disassembled
+        let bytecode = bytecode(
+            &[opcode::CONSTANT, 0],
+            vec![Value::Number(1.0), Value::Number(2.0)],
+        );
+
+        assert_eq!(
+            Err(RoundtripError::ConstantMismatch {
+                index: 1,
+                expected: Some(Value::Number(2.0)),
+                actual: None,
+            }),
+            verify_roundtrip(&bytecode, &env())
+        );
+    }
+
+    #[test]
+    fn missing_header() {
+        assert_eq!(Err(AssembleError::MissingHeader), assemble("", &env()));
+        assert_eq!(
+            Err(AssembleError::MissingHeader),
+            assemble("VERSION 0800\n0000 TRUE\n", &env())
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        assert_eq!(
+            Err(AssembleError::UnsupportedVersion("9999".to_string())),
+            assemble("VERSION 9999\n----\n0000 TRUE\n", &env())
+        );
+    }
+
+    fn assemble_ops(ops: &str) -> Result<ExprByteCode, AssembleError> {
+        let (_, header) = disassemble("true", false);
+        let header = header.lines().take(2).collect::<Vec<_>>().join("\n");
+
+        assemble(&format!("{header}\n{ops}"), &env())
+    }
+
+    #[test]
+    fn invalid_op_index() {
+        assert_eq!(
+            Err(AssembleError::InvalidLine {
+                line: 4,
+                message: "expected op at 0001 but found 0002".to_string()
+            }),
+            assemble_ops("0000 TRUE\n0002 FALSE\n")
+        );
+    }
+
+    #[test]
+    fn unknown_op() {
+        assert_eq!(
+            Err(AssembleError::InvalidLine {
+                line: 3,
+                message: "unknown op: HALT".to_string()
+            }),
+            assemble_ops("0000 HALT\n")
+        );
+    }
+
+    #[test]
+    fn mismatched_jump_target() {
+        assert_eq!(
+            Err(AssembleError::InvalidLine {
+                line: 4,
+                message: "expected jump to 0005 but found 0006".to_string()
+            }),
+            assemble_ops("0000 TRUE\n0001 JUMP_IF_FALSE       1 -> 0006\n")
+        );
+    }
+
+    #[test]
+    fn mismatched_name() {
+        assert_eq!(
+            Err(AssembleError::InvalidLine {
+                line: 3,
+                message: "expected var 'a' at index 0 but found 'z'".to_string()
+            }),
+            assemble_ops("0000 GET VAR             0 == 'z'\n")
+        );
+    }
+
+    #[test]
+    fn missing_constant() {
+        assert_eq!(
+            Err(AssembleError::MissingConstant(0)),
+            assemble_ops("0000 CONSTANT            1 == '`a`'\n")
+        );
+    }
+}
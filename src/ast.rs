@@ -1,12 +1,23 @@
 //! Abstract syntax tree types
 
-use crate::{prelude::CompileTimeEnv, span::Spanned, types::Type};
+use std::fmt::Display;
+
+use crate::{
+    lexer::{Token, escape_interpolations},
+    prelude::CompileTimeEnv,
+    span::{Span, Spanned},
+    types::Type,
+};
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
     Bool(Box<ExprBool>),
     Identifier(Box<ExprIdentifier>),
     Call(Box<ExprCall>),
+    List(Box<ExprList>),
+    Spread(Box<ExprSpread>),
+    Let(Box<ExprLet>),
     String(Box<ExprString>),
     Number(Box<ExprNumber>),
     Error,
@@ -44,6 +55,24 @@ impl Expr {
         Self::Bool(Box::new(ExprBool::new(value)))
     }
 
+    pub fn list(items: Vec<ExprS>) -> Self {
+        Self::List(Box::new(ExprList { items }))
+    }
+
+    pub fn spread(list: ExprS) -> Self {
+        Self::Spread(Box::new(ExprSpread { list }))
+    }
+
+    pub fn let_(name: Spanned<String>, value: ExprS, body: ExprS) -> Self {
+        Self::Let(Box::new(ExprLet { name, value, body }))
+    }
+
+    /// Type of the expression once [`add_type_to_expr`] has typed its identifiers
+    ///
+    /// Calls have the declared return type of their callee, or
+    /// [`Type::Unknown`] if the callee's type isn't known. Calls to
+    /// `compose`, `partial`, `map`, `filter`, and `reduce` have a type
+    /// computed from their args.
     pub fn get_type(&self) -> Type {
         match self {
             Expr::Bool(_) => Type::Bool,
@@ -52,7 +81,13 @@ impl Expr {
                 .as_ref()
                 .unwrap_or(&Type::Unknown)
                 .clone(),
-            Expr::Call(_) => Type::Unknown,
+            Expr::Call(call) => match call.callee.0.get_type() {
+                Type::Fn { returns, .. } => call.computed_type().unwrap_or(*returns),
+                _ => Type::Unknown,
+            },
+            Expr::List(list) => Type::list_of(list.items.iter().map(|(item, _)| item.get_type())),
+            Expr::Spread(_) => Type::Unknown,
+            Expr::Let(binding) => binding.body.0.get_type(),
             Expr::String(_) => Type::String,
             Expr::Number(_) => Type::Number,
             Expr::Error => Type::Unknown,
@@ -60,7 +95,52 @@ impl Expr {
     }
 }
 
+/// Print the expression as source code
+///
+/// Parsing the printed source produces an equivalent expression. Spans aren't
+/// preserved. [`Expr::Error`] has no source form and prints nothing.
+impl Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Bool(bool) => write!(f, "{}", bool.0),
+            Expr::Identifier(identifier) => write!(f, "{}", identifier.full_name()),
+            Expr::Call(call) => {
+                write!(f, "({}", call.callee.0)?;
+
+                for (arg, _) in &call.args {
+                    write!(f, " {arg}")?;
+                }
+
+                write!(f, ")")
+            }
+            Expr::List(list) => {
+                write!(f, "[")?;
+
+                for (i, (item, _)) in list.items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+
+                    write!(f, "{item}")?;
+                }
+
+                write!(f, "]")
+            }
+            Expr::Spread(spread) => write!(f, "...{}", spread.list.0),
+            Expr::Let(binding) => write!(
+                f,
+                "(let ({} {}) {})",
+                binding.name.0, binding.value.0, binding.body.0
+            ),
+            Expr::String(string) => write!(f, "`{}`", escape_interpolations(&string.0)),
+            Expr::Number(number) => write!(f, "{}", number.0),
+            Expr::Error => Ok(()),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprIdentifier(pub String, pub IdentifierKind, pub Option<Type>);
 
 impl ExprIdentifier {
@@ -72,23 +152,22 @@ impl ExprIdentifier {
         )
     }
 
+    /// Identifier for a type name like `String` or `Fn(String) -> Bool`
+    pub fn new_type(name: &str) -> Self {
+        Self(name.to_string(), IdentifierKind::Type, None)
+    }
+
+    /// Kind of an identifier from its sigil, or its case if it has none
+    ///
+    /// Follows the same rules as the lexer: names starting with an uppercase
+    /// letter are lexed as [`Token::Type`].
     pub fn get_identifier_kind(identifier: &str) -> IdentifierKind {
-        let identifier_prefix = &identifier[..1];
-
-        match identifier_prefix {
-            "?" => IdentifierKind::Prompt,
-            "!" => IdentifierKind::Secret,
-            ":" => IdentifierKind::Var,
-            "@" => IdentifierKind::Client,
-            _ => {
-                let prefix_char: char = identifier_prefix.chars().nth(0).unwrap();
-
-                if prefix_char.is_uppercase() {
-                    IdentifierKind::Type
-                } else {
-                    IdentifierKind::Builtin
-                }
-            }
+        let prefix_char: char = identifier.chars().next().unwrap();
+
+        match IdentifierKind::from_sigil(prefix_char) {
+            Some(kind) => kind,
+            None if prefix_char.is_uppercase() => IdentifierKind::Type,
+            None => IdentifierKind::Builtin,
         }
     }
 
@@ -114,13 +193,9 @@ impl ExprIdentifier {
     /// - @client => client
     ///
     pub fn lookup_name(&self) -> &str {
-        match self.identifier_kind() {
-            IdentifierKind::Builtin => &self.0,
-            IdentifierKind::Var => &self.0[1..],
-            IdentifierKind::Prompt => &self.0[1..],
-            IdentifierKind::Secret => &self.0[1..],
-            IdentifierKind::Client => &self.0[1..],
-            IdentifierKind::Type => &self.0,
+        match self.identifier_kind().sigil() {
+            Some(sigil) => &self.0[sigil.len_utf8()..],
+            None => &self.0,
         }
     }
 
@@ -133,7 +208,8 @@ impl ExprIdentifier {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IdentifierKind {
     Builtin,
     Var,
@@ -143,7 +219,47 @@ pub enum IdentifierKind {
     Type,
 }
 
+impl IdentifierKind {
+    /// Kind of identifier a token is, if it's an identifier
+    ///
+    /// Type names and `Fn` types are lexed as [`Token::Type`] and
+    /// [`Token::Fn`] instead of [`Token::Identifier`] but are identifiers of
+    /// kind [`IdentifierKind::Type`] once parsed.
+    pub fn from_token(token: &Token) -> Option<Self> {
+        match token {
+            Token::Identifier(identifier) => Some(ExprIdentifier::get_identifier_kind(identifier)),
+            Token::Type(_) | Token::Fn => Some(IdentifierKind::Type),
+            _ => None,
+        }
+    }
+
+    /// Kind of identifier prefixed with a sigil
+    pub fn from_sigil(sigil: char) -> Option<Self> {
+        match sigil {
+            ':' => Some(IdentifierKind::Var),
+            '?' => Some(IdentifierKind::Prompt),
+            '!' => Some(IdentifierKind::Secret),
+            '@' => Some(IdentifierKind::Client),
+            _ => None,
+        }
+    }
+
+    /// Sigil prefixing identifiers of this kind
+    ///
+    /// Builtins and types don't have one.
+    pub fn sigil(&self) -> Option<char> {
+        match self {
+            IdentifierKind::Var => Some(':'),
+            IdentifierKind::Prompt => Some('?'),
+            IdentifierKind::Secret => Some('!'),
+            IdentifierKind::Client => Some('@'),
+            IdentifierKind::Builtin | IdentifierKind::Type => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprString(pub String);
 
 impl ExprString {
@@ -153,6 +269,7 @@ impl ExprString {
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprNumber(pub f64);
 
 impl ExprNumber {
@@ -162,12 +279,93 @@ impl ExprNumber {
 }
 
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprCall {
     pub callee: Box<ExprS>,
     pub args: Vec<ExprS>,
 }
 
+impl ExprCall {
+    /// If the call is a `(def name expr)` definition
+    pub fn is_def(&self) -> bool {
+        matches!(&self.callee.0, Expr::Identifier(identifier) if identifier.full_name() == "def")
+    }
+
+    /// Type of a call to a builtin whose result depends on its args
+    ///
+    /// `compose` and `partial` return a function with a signature computed
+    /// from the signatures of their args. `map` returns a list of what its
+    /// function returns, `filter` the list it was passed, and `reduce` what
+    /// its function returns.
+    fn computed_type(&self) -> Option<Type> {
+        let Expr::Identifier(callee) = &self.callee.0 else {
+            return None;
+        };
+
+        let arg_type = |i: usize| self.args.get(i).map(|(arg, _)| arg.get_type());
+        let fn_returns = || match arg_type(0)? {
+            Type::Fn { returns, .. } => Some(*returns),
+            _ => None,
+        };
+
+        match callee.full_name() {
+            "compose" => Some(Type::composed(&arg_type(0)?, &arg_type(1)?)),
+            "partial" => Some(Type::partially_applied(&arg_type(0)?)),
+            "map" => Some(Type::List(fn_returns()?.into())),
+            "filter" => Some(arg_type(1)?).filter(|ty| matches!(ty, Type::List(_))),
+            "reduce" => fn_returns(),
+            _ => None,
+        }
+    }
+
+    /// If the call is a `(use `path`)` import of a module
+    pub fn is_use(&self) -> bool {
+        matches!(&self.callee.0, Expr::Identifier(identifier) if identifier.full_name() == "use")
+    }
+
+    /// If the call's last arg is a `...list` spread in to the args
+    pub fn is_spread(&self) -> bool {
+        matches!(self.args.last(), Some((Expr::Spread(_), _)))
+    }
+
+    /// If the call is a `let` that isn't a valid `(let (name value) body)`
+    ///
+    /// Valid ones are parsed as [`Expr::Let`] instead.
+    pub fn is_let(&self) -> bool {
+        matches!(&self.callee.0, Expr::Identifier(identifier) if identifier.full_name() == "let")
+    }
+}
+
+/// A `(let (name value) body)` expression
+///
+/// `value` is evaluated once and `name` refers to it anywhere in `body`.
+/// The name isn't in scope in `value`.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExprLet {
+    pub name: Spanned<String>,
+    pub value: ExprS,
+    pub body: ExprS,
+}
+
+/// A list literal e.g. `` [`a` `b` :c] ``
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExprList {
+    pub items: Vec<ExprS>,
+}
+
+/// A list spread in to the args of a call e.g. `(concat ...:a)`
+///
+/// Only valid as the last arg of a call.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExprSpread {
+    pub list: ExprS,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExprBool(pub bool);
 
 impl ExprBool {
@@ -178,6 +376,66 @@ impl ExprBool {
 
 pub type ExprS = Spanned<Expr>;
 
+/// Rewrite syntax sugar callees to the builtin they call
+///
+/// `(if cond then else)` is sugar for `(cond cond then else)` so the
+/// bytecode is identical. This shadows any user builtin named `if`.
+pub fn desugar_callee(callee: ExprS) -> ExprS {
+    match callee {
+        (Expr::Identifier(identifier), span) if identifier.full_name() == "if" => {
+            (Expr::identifier("cond"), span)
+        }
+        callee => callee,
+    }
+}
+
+/// A call expression, or an [`Expr::Let`] if it's a `(let (name value) body)`
+///
+/// Calls to `let` that don't bind a single bare name are left as calls and
+/// fail to compile.
+pub fn call_or_let(callee: ExprS, mut args: Vec<ExprS>) -> Expr {
+    let is_let =
+        matches!(&callee.0, Expr::Identifier(identifier) if identifier.full_name() == "let");
+
+    let is_binding = |(binding, _): &ExprS| {
+        matches!(binding, Expr::Call(call)
+            if call.args.len() == 1
+                && matches!(&call.callee.0, Expr::Identifier(name)
+                    if name.identifier_kind() == &IdentifierKind::Builtin))
+    };
+
+    if !is_let || args.len() != 2 || !is_binding(&args[0]) {
+        return Expr::call(desugar_callee(callee), args);
+    }
+
+    let body = args.pop().expect("should have a body");
+
+    let Some((Expr::Call(binding), _)) = args.pop() else {
+        unreachable!("should have a binding");
+    };
+
+    let ExprCall { callee, mut args } = *binding;
+
+    let (Expr::Identifier(name), name_span) = *callee else {
+        unreachable!("should bind a name");
+    };
+
+    let value = args.pop().expect("should have a value");
+
+    Expr::let_((name.full_name().to_string(), name_span), value, body)
+}
+
+/// Rewrite a string with interpolations to a call concatenating its parts
+///
+/// `` `Hello {:name}!` `` is sugar for `` (concat `Hello ` :name `!`) ``. A
+/// string that's only an interpolation is sugar for `to_str` since `concat`
+/// needs at least two args.
+pub fn desugar_template(parts: Vec<ExprS>, span: Span) -> Expr {
+    let callee = if parts.len() == 1 { "to_str" } else { "concat" };
+
+    Expr::call((Expr::identifier(callee), span), parts)
+}
+
 pub fn add_type_to_expr_parse(expr: &mut Expr) {
     match expr {
         Expr::Identifier(expr_identifier) => match expr_identifier.identifier_kind() {
@@ -203,6 +461,16 @@ pub fn add_type_to_expr_parse(expr: &mut Expr) {
                 add_type_to_expr_parse(&mut arg.0);
             }
         }
+        Expr::List(list) => {
+            for item in &mut list.items {
+                add_type_to_expr_parse(&mut item.0);
+            }
+        }
+        Expr::Spread(spread) => add_type_to_expr_parse(&mut spread.list.0),
+        Expr::Let(binding) => {
+            add_type_to_expr_parse(&mut binding.value.0);
+            add_type_to_expr_parse(&mut binding.body.0);
+        }
         _ => {}
     }
 }
@@ -212,19 +480,20 @@ pub fn add_type_to_expr(expr: &mut Expr, env: &CompileTimeEnv) {
         Expr::Identifier(expr_identifier) => match expr_identifier.identifier_kind() {
             IdentifierKind::Builtin => {
                 if let Some((_, index)) = env.get_builtin_index(expr_identifier.lookup_name()) {
-                    if let Some(v) = env.get_builtin(index as usize) {
+                    if let Some(v) = env.get_builtin(index) {
                         let v_type: Type = v.clone().into();
 
                         expr_identifier.2 = Some(v_type);
                     }
                 } else if let Some((_, index)) =
                     env.get_user_builtin_index(expr_identifier.lookup_name())
+                    && let Some(v) = env.get_user_builtin(index)
                 {
-                    if let Some(v) = env.get_builtin(index as usize) {
-                        let v_type: Type = v.clone().into();
+                    let v_type: Type = v.clone().into();
 
-                        expr_identifier.2 = Some(v_type);
-                    }
+                    expr_identifier.2 = Some(v_type);
+                } else if let Some(def) = env.get_def(expr_identifier.lookup_name()) {
+                    expr_identifier.2 = Some(def.signature());
                 }
             }
             IdentifierKind::Var => {
@@ -260,10 +529,360 @@ pub fn add_type_to_expr(expr: &mut Expr, env: &CompileTimeEnv) {
             }
         },
         Expr::Call(expr_call) => {
+            add_type_to_expr(&mut expr_call.callee.0, env);
+
             for arg in &mut expr_call.args {
                 add_type_to_expr(&mut arg.0, env);
             }
         }
+        Expr::List(list) => {
+            for item in &mut list.items {
+                add_type_to_expr(&mut item.0, env);
+            }
+        }
+        Expr::Spread(spread) => add_type_to_expr(&mut spread.list.0, env),
+        Expr::Let(binding) => {
+            add_type_to_expr(&mut binding.value.0, env);
+            add_type_to_expr(&mut binding.body.0, env);
+
+            let ty = binding.value.0.get_type();
+
+            add_type_to_local(&mut binding.body.0, &binding.name.0, &ty);
+        }
+        _ => {}
+    }
+}
+
+/// Type the untyped references to a `let` name in its body
+///
+/// Stops at nested `let`s binding the same name since they shadow it.
+fn add_type_to_local(expr: &mut Expr, name: &str, ty: &Type) {
+    match expr {
+        Expr::Identifier(identifier)
+            if identifier.1 == IdentifierKind::Builtin
+                && identifier.2.is_none()
+                && identifier.0 == name =>
+        {
+            identifier.2 = Some(ty.clone());
+        }
+        Expr::Call(call) => {
+            add_type_to_local(&mut call.callee.0, name, ty);
+
+            for arg in &mut call.args {
+                add_type_to_local(&mut arg.0, name, ty);
+            }
+        }
+        Expr::List(list) => {
+            for item in &mut list.items {
+                add_type_to_local(&mut item.0, name, ty);
+            }
+        }
+        Expr::Spread(spread) => add_type_to_local(&mut spread.list.0, name, ty),
+        Expr::Let(binding) => {
+            add_type_to_local(&mut binding.value.0, name, ty);
+
+            if binding.name.0 != name {
+                add_type_to_local(&mut binding.body.0, name, ty);
+            }
+        }
         _ => {}
     }
 }
+
+/// A structural change between two versions of an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum AstEdit {
+    /// The expression at `old` was replaced by the one at `new`
+    Replace { old: Span, new: Span },
+    /// The argument at `new` was inserted as argument `index` of the call at
+    /// `call` in the old expression
+    Insert { call: Span, index: usize, new: Span },
+    /// Argument `index` at `old` was removed from the call at `call` in the
+    /// old expression
+    Delete { call: Span, index: usize, old: Span },
+}
+
+/// Structural edits turning `old` into `new`
+///
+/// Spans in each edit point in to the old or new source. Expressions that
+/// only differ in whitespace have no edits. Arguments are matched with a
+/// longest common subsequence so inserting or removing one argument doesn't
+/// replace the ones after it.
+///
+/// ```
+/// use reqlang_expr::{ast::{AstEdit, diff}, prelude::*};
+///
+/// let old = "(concat :a `b`)";
+/// let new = "(concat :a `c` :d)";
+///
+/// let edits = diff(
+///     &(parse(old).unwrap(), 0..old.len()),
+///     &(parse(new).unwrap(), 0..new.len()),
+/// );
+///
+/// assert_eq!(
+///     vec![
+///         AstEdit::Replace { old: 11..14, new: 11..14 },
+///         AstEdit::Insert { call: 0..15, index: 2, new: 15..17 },
+///     ],
+///     edits
+/// );
+/// ```
+pub fn diff(old: &ExprS, new: &ExprS) -> Vec<AstEdit> {
+    let mut edits = vec![];
+
+    diff_expr(old, new, &mut edits);
+
+    edits
+}
+
+fn diff_expr((old, old_span): &ExprS, (new, new_span): &ExprS, edits: &mut Vec<AstEdit>) {
+    if same_expr(old, new) {
+        return;
+    }
+
+    match (old, new) {
+        (Expr::Call(old_call), Expr::Call(new_call)) => {
+            diff_expr(&old_call.callee, &new_call.callee, edits);
+            diff_args(old_span, &old_call.args, &new_call.args, edits);
+        }
+        _ => edits.push(AstEdit::Replace {
+            old: old_span.clone(),
+            new: new_span.clone(),
+        }),
+    }
+}
+
+/// Diff call arguments, pairing up unmatched arguments between matches
+fn diff_args(call: &Span, old: &[ExprS], new: &[ExprS], edits: &mut Vec<AstEdit>) {
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if same_expr(&old[i].0, &new[j].0) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let (mut deleted, mut inserted) = (vec![], vec![]);
+
+    loop {
+        let matched = i < old.len() && j < new.len() && same_expr(&old[i].0, &new[j].0);
+
+        if matched || (i == old.len() && j == new.len()) {
+            let paired = deleted.len().min(inserted.len());
+
+            for (&old_index, &new_index) in deleted.iter().zip(&inserted) {
+                diff_expr(&old[old_index], &new[new_index], edits);
+            }
+
+            for &index in &deleted[paired..] {
+                edits.push(AstEdit::Delete {
+                    call: call.clone(),
+                    index,
+                    old: old[index].1.clone(),
+                });
+            }
+
+            for &index in &inserted[paired..] {
+                edits.push(AstEdit::Insert {
+                    call: call.clone(),
+                    index,
+                    new: new[index].1.clone(),
+                });
+            }
+
+            deleted.clear();
+            inserted.clear();
+
+            if !matched {
+                break;
+            }
+
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            deleted.push(i);
+            i += 1;
+        } else {
+            inserted.push(j);
+            j += 1;
+        }
+    }
+}
+
+/// If expressions are the same ignoring spans and whitespace
+fn same_expr(old: &Expr, new: &Expr) -> bool {
+    !matches!(old, Expr::Error) && old.to_string() == new.to_string()
+}
+
+#[cfg(test)]
+mod ast_tests {
+    use super::*;
+
+    use crate::parser::parse;
+
+    fn diff_sources(old: &str, new: &str) -> Vec<AstEdit> {
+        diff(
+            &(parse(old).unwrap(), 0..old.len()),
+            &(parse(new).unwrap(), 0..new.len()),
+        )
+    }
+
+    #[test]
+    fn identifier_kinds_match_tokens() {
+        let source = "(:a ?b !c @d e String Fn() -> Bool)";
+
+        let token_kinds: Vec<_> = crate::lexer::lex(source)
+            .into_iter()
+            .filter_map(|token| IdentifierKind::from_token(&token.unwrap().1))
+            .collect();
+
+        let Expr::Call(call) = parse(source).unwrap() else {
+            panic!("should parse a call");
+        };
+
+        let expr_kinds: Vec<_> = std::iter::once(&*call.callee)
+            .chain(&call.args)
+            .map(|(expr, _)| match expr {
+                Expr::Identifier(identifier) => identifier.identifier_kind().clone(),
+                _ => panic!("should be an identifier"),
+            })
+            .collect();
+
+        assert_eq!(
+            vec![
+                IdentifierKind::Var,
+                IdentifierKind::Prompt,
+                IdentifierKind::Secret,
+                IdentifierKind::Client,
+                IdentifierKind::Builtin,
+                IdentifierKind::Type,
+                IdentifierKind::Type,
+            ],
+            expr_kinds
+        );
+
+        // `Fn() -> Bool` is lexed as `Fn` and `Bool` but parsed as one identifier
+        assert_eq!(expr_kinds, token_kinds[..expr_kinds.len()].to_vec());
+    }
+
+    #[test]
+    fn lookup_names_strip_sigils() {
+        let names: Vec<_> = [":a", "?b", "!c", "@d", "e", "String"]
+            .map(ExprIdentifier::new)
+            .iter()
+            .map(|identifier| identifier.lookup_name().to_string())
+            .collect();
+
+        assert_eq!(vec!["a", "b", "c", "d", "e", "String"], names);
+    }
+
+    #[test]
+    fn diff_same_ignoring_whitespace() {
+        assert_eq!(
+            Vec::<AstEdit>::new(),
+            diff_sources("(concat `a` :b)", "( concat\n  `a`   :b )")
+        );
+    }
+
+    #[test]
+    fn diff_replace_root() {
+        assert_eq!(
+            vec![AstEdit::Replace {
+                old: 0..4,
+                new: 0..6
+            }],
+            diff_sources("true", "(noop)")
+        );
+    }
+
+    #[test]
+    fn diff_delete_arg() {
+        assert_eq!(
+            vec![AstEdit::Delete {
+                call: 0..18,
+                index: 1,
+                old: 11..14
+            }],
+            diff_sources("(concat :a `b` :c)", "(concat :a :c)")
+        );
+    }
+
+    #[test]
+    fn diff_nested_change() {
+        assert_eq!(
+            vec![
+                AstEdit::Replace {
+                    old: 7..9,
+                    new: 7..10
+                },
+                AstEdit::Replace {
+                    old: 18..21,
+                    new: 19..22
+                }
+            ],
+            diff_sources("(cond (eq :a `b`) `c` `d`)", "(cond (neq :a `b`) `x` `d`)")
+        );
+    }
+
+    #[test]
+    fn display_round_trips_through_parse() {
+        for source in [
+            "true",
+            "`a b`",
+            "1.5",
+            ":a",
+            "Fn(String, ...Value) -> Bool",
+            "Type<String>",
+            "(noop)",
+            "((id concat) `a` (not false) 100 ?b)",
+            "(concat `a` ...[`b` :c])",
+        ] {
+            assert_eq!(source, parse(source).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn if_desugars_to_cond() {
+        assert_eq!(
+            "(cond true `a` (cond false `b` `c`))",
+            parse("(if true `a` (if false `b` `c`))")
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!("(id if)", parse("(id if)").unwrap().to_string());
+    }
+
+    #[test]
+    fn display_normalizes_whitespace() {
+        assert_eq!(
+            "(concat `a` :b)",
+            parse("( concat\n  `a`   :b )").unwrap().to_string()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let source = "(let (x [:a `b` 1.5]) (cond true (x) ?c))";
+        let mut ast = parse(source).unwrap();
+        add_type_to_expr_parse(&mut ast);
+
+        let json = serde_json::to_value(&ast).unwrap();
+
+        assert_eq!(
+            serde_json::json!({"Bool": true}),
+            json["Let"]["body"][0]["Call"]["args"][0][0]
+        );
+
+        let deserialized: Expr = serde_json::from_value(json).unwrap();
+
+        assert_eq!(ast, deserialized);
+    }
+}
@@ -0,0 +1,229 @@
+//! The `expr` CLI, enabled by the `cli` feature
+//!
+//! ```shell
+//! cargo run --features cli --bin expr -- compile spec/valid/greeting_name.expr \
+//!     --vars greeting --prompts name -o greeting_name.bin
+//! cargo run --features cli --bin expr -- inspect greeting_name.bin
+//! cargo run --features cli --bin expr -- run spec/valid/greeting_name.expr \
+//!     --vars greeting=Hello --prompts name=World --format json
+//! cargo run --features cli --bin expr -- builtins --json
+//! ```
+
+use std::{
+    error::Error,
+    io::{stdin, stdout},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use reqlang_expr::{
+    binary::NameTables,
+    catalog::BuiltinDescriptor,
+    cliutil::{parse_key_val, read_in_source},
+    errors::ExprErrorS,
+    inspect::inspect,
+    prelude::*,
+    run::run,
+    server::{Server, SessionEnv},
+};
+
+fn main() -> ExitCode {
+    match execute(Args::parse()) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            eprintln!("{err}");
+
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn execute(args: Args) -> Result<ExitCode, Box<dyn Error>> {
+    match args.command {
+        Command::Serve => Server::new().serve(stdin().lock(), stdout().lock())?,
+        Command::Compile {
+            path,
+            output,
+            vars,
+            prompts,
+            secrets,
+            client_context,
+            strip,
+        } => {
+            let source = read_in_source(path).map_err(|errs| errors_message(&errs))?;
+            let env = CompileTimeEnv::new(vars, prompts, secrets, client_context);
+
+            let bytecode = parse(&source)
+                .and_then(|ast| compile(&mut (ast, 0..source.len()), &env))
+                .map_err(|errs| errors_message(&errs))?;
+
+            let bytes = if strip {
+                bytecode.to_bytes()
+            } else {
+                bytecode.to_bytes_with_names(&NameTables::of(&env))
+            };
+
+            std::fs::write(output, bytes)?;
+        }
+        Command::Builtins { json } => {
+            let catalog = BuiltinFn::catalog();
+
+            if json {
+                let catalog: Vec<_> = catalog.iter().map(BuiltinDescriptor::to_json).collect();
+
+                println!("{:#}", serde_json::Value::from(catalog));
+            } else {
+                for builtin in catalog {
+                    println!("{}\n    {}", builtin.signature, builtin.summary);
+                }
+            }
+        }
+        Command::Inspect { path, json } => {
+            let inspection = inspect(&std::fs::read(path)?)?;
+
+            if json {
+                println!("{:#}", inspection.to_json());
+            } else {
+                print!("{inspection}");
+            }
+        }
+        Command::Run {
+            path,
+            vars,
+            prompts,
+            secrets,
+            client_context,
+            format,
+        } => {
+            let source = read_in_source(path).map_err(|errs| errors_message(&errs))?;
+            let env = SessionEnv {
+                vars,
+                prompts,
+                secrets,
+                client_context,
+                ..Default::default()
+            };
+
+            let result = run(&source, &env);
+
+            match format {
+                Format::Json => println!("{:#}", result.to_json()),
+                Format::Text => match &result.value {
+                    Some(value) => println!("{value}"),
+                    None => eprintln!("{}", errors_message(&result.errs)),
+                },
+            }
+
+            if !result.is_ok() {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn errors_message(errs: &[ExprErrorS]) -> String {
+    errs.iter()
+        .map(|(err, span)| format!("{err} at {}..{}", span.start, span.end))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Compile, evaluate, and inspect expressions")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Handle JSON-RPC requests (one per line) over stdin and stdout
+    ///
+    /// Methods: compile, eval, disassemble, complete
+    Serve,
+
+    /// Compile an expression to a serialized bytecode file
+    Compile {
+        /// Path to expression file, read from stdin if not provided
+        path: Option<String>,
+
+        /// Path to write the serialized bytecode to
+        #[arg(long, short)]
+        output: String,
+
+        /// List of indexed variable names
+        #[arg(long, value_delimiter = ' ', num_args = 1..)]
+        vars: Vec<String>,
+
+        /// List of indexed prompt names
+        #[arg(long, value_delimiter = ' ', num_args = 1..)]
+        prompts: Vec<String>,
+
+        /// List of indexed secret names
+        #[arg(long, value_delimiter = ' ', num_args = 1..)]
+        secrets: Vec<String>,
+
+        /// List of indexed client context names
+        #[arg(long, value_delimiter = ' ', num_args = 1..)]
+        client_context: Vec<String>,
+
+        /// Leave the var, prompt, secret, and client context names out of
+        /// the bytecode file
+        #[arg(long)]
+        strip: bool,
+    },
+
+    /// Print the header, constants, types, and a hexdump aligned disassembly
+    /// of a serialized bytecode file
+    Inspect {
+        /// Path to serialized bytecode
+        path: String,
+
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List the builtins with their signatures, summaries, and arg docs
+    Builtins {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Compile and evaluate an expression, exiting with a failure if it errors
+    Run {
+        /// Path to expression file, read from stdin if not provided
+        path: Option<String>,
+
+        /// List of indexed variable names and values (name=value)
+        #[arg(long, value_delimiter = ' ', num_args = 1.., value_parser = parse_key_val::<String, String>)]
+        vars: Vec<(String, String)>,
+
+        /// List of indexed prompt names and values (name=value)
+        #[arg(long, value_delimiter = ' ', num_args = 1.., value_parser = parse_key_val::<String, String>)]
+        prompts: Vec<(String, String)>,
+
+        /// List of indexed secret names and values (name=value)
+        #[arg(long, value_delimiter = ' ', num_args = 1.., value_parser = parse_key_val::<String, String>)]
+        secrets: Vec<(String, String)>,
+
+        /// List of indexed client context names and values (name=value)
+        #[arg(long, value_delimiter = ' ', num_args = 1.., value_parser = parse_key_val::<String, String>)]
+        client_context: Vec<(String, String)>,
+
+        /// Print just the value, or the value, type, provenance,
+        /// diagnostics, and stats as JSON
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+enum Format {
+    #[default]
+    Text,
+    Json,
+}
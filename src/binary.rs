@@ -0,0 +1,999 @@
+//! Binary format for saving and loading [`ExprByteCode`]
+//!
+//! All integers are little endian.
+//!
+//! | Section   | Layout                                                       |
+//! | --------- | ------------------------------------------------------------ |
+//! | Header    | [`MAGIC`], the 4 version bytes, then a `u32` max stack depth |
+//! | Constants | `u32` count then each constant: a tag byte and its value     |
+//! | Types     | `u32` count then each type: a tag byte and its params        |
+//! | Code      | `u32` length then the op codes                               |
+//! | Names     | Optional, see [`NameTables`]                                 |
+//!
+//! The max stack depth is [`ExprByteCode::max_stack_depth`]. Reading
+//! bytecode whose code needs a different depth is a
+//! [`BytecodeError::StackDepthMismatch`].
+//!
+//! Values are tagged with [`value_tag`]. Constants are strings (a `u32`
+//! length then UTF-8 bytes) or numbers (an `f64`). Types are tagged with
+//! [`type_tag`], with `Type<T>` and `List<T>` followed by `T` and `Fn`
+//! followed by a `u32` arg count, the args, a `0`/`1` byte for whether it has
+//! a variadic arg (and the arg), then its return type.
+//!
+//! Other values only appear in [`crate::snapshot::VmSnapshot`]s: bools are a
+//! `0`/`1` byte, functions their builtin's name (tagged by its
+//! [`FnNamespace`]), closures the values they were built from, type values
+//! their type, and lists a `u32` count then each item.
+
+use std::ops::Range;
+
+use crate::{
+    builtins::FnNamespace,
+    compiler::{CompileTimeEnv, ExprByteCode, get_version_bytes},
+    errors::BytecodeError,
+    types::Type,
+    value::{Closure, Value},
+};
+
+/// The first bytes of every serialized [`ExprByteCode`]
+pub const MAGIC: [u8; 4] = *b"RQXB";
+
+/// The first bytes of the optional [`NameTables`] section
+pub const NAMES_MAGIC: [u8; 4] = *b"RQXN";
+
+pub mod value_tag {
+    iota::iota! {
+        pub const
+        STRING: u8 = iota;,
+        NUMBER,
+        BOOL,
+        FN,
+        COMPOSE,
+        PARTIAL,
+        TYPE,
+        USER_FN,
+        LIST
+    }
+}
+
+pub mod type_tag {
+    iota::iota! {
+        pub const
+        VALUE: u8 = iota;,
+        STRING,
+        NUMBER,
+        BOOL,
+        UNKNOWN,
+        TYPE,
+        FN,
+        LIST
+    }
+}
+
+/// Byte ranges of each section, and each entry in them, in serialized
+/// bytecode
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub magic: Range<usize>,
+    pub version: Range<usize>,
+    pub max_stack_depth: Range<usize>,
+    pub constants_count: Range<usize>,
+    pub constants: Vec<Range<usize>>,
+    pub types_count: Range<usize>,
+    pub types: Vec<Range<usize>>,
+    pub code_length: Range<usize>,
+    pub code: Range<usize>,
+    /// Range of [`NAMES_MAGIC`] if the bytecode was serialized with names
+    pub names_magic: Option<Range<usize>>,
+    /// Ranges of the var, prompt, secret, and client context name lists, or
+    /// empty if the bytecode was serialized without names
+    pub names: Vec<Range<usize>>,
+}
+
+/// Names of the vars, prompts, secrets, and client context the bytecode was
+/// compiled with
+///
+/// Bytecode only references these by index. Serializing them with
+/// [`ExprByteCode::to_bytes_with_names`] lets tools like `expr inspect` show
+/// their names without the original [`CompileTimeEnv`]. They're written after
+/// the code section as [`NAMES_MAGIC`] then four lists, each a `u32` count
+/// then the names as strings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NameTables {
+    pub vars: Vec<String>,
+    pub prompts: Vec<String>,
+    pub secrets: Vec<String>,
+    pub client_context: Vec<String>,
+}
+
+impl NameTables {
+    /// Names from the environment bytecode was compiled with
+    pub fn of(env: &CompileTimeEnv) -> Self {
+        Self {
+            vars: env.vars().to_vec(),
+            prompts: env.prompts().to_vec(),
+            secrets: env.secrets().to_vec(),
+            client_context: env.client_context().to_vec(),
+        }
+    }
+
+    /// An environment with these names and the default builtins
+    pub fn env(&self) -> CompileTimeEnv {
+        CompileTimeEnv::new(
+            self.vars.clone(),
+            self.prompts.clone(),
+            self.secrets.clone(),
+            self.client_context.clone(),
+        )
+    }
+
+    fn lists(&self) -> [&Vec<String>; 4] {
+        [
+            &self.vars,
+            &self.prompts,
+            &self.secrets,
+            &self.client_context,
+        ]
+    }
+}
+
+impl ExprByteCode {
+    /// Serialize to the [binary format](self)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        write_header(&mut bytes, MAGIC);
+        write_len(&mut bytes, self.max_stack_depth());
+
+        write_len(&mut bytes, self.constants().len());
+
+        for constant in self.constants() {
+            match constant {
+                Value::String(_) | Value::Number(_) => write_value(&mut bytes, constant),
+                value => unreachable!("constants should be strings or numbers: {value}"),
+            }
+        }
+
+        write_len(&mut bytes, self.types().len());
+
+        for ty in self.types() {
+            write_type(&mut bytes, ty);
+        }
+
+        write_len(&mut bytes, self.codes().len());
+        bytes.extend(self.codes());
+
+        bytes
+    }
+
+    /// Serialize to the [binary format](self) including the names of the
+    /// vars, prompts, secrets, and client context it was compiled with
+    pub fn to_bytes_with_names(&self, names: &NameTables) -> Vec<u8> {
+        let mut bytes = self.to_bytes();
+
+        bytes.extend(NAMES_MAGIC);
+
+        for list in names.lists() {
+            write_len(&mut bytes, list.len());
+
+            for name in list {
+                write_string(&mut bytes, name);
+            }
+        }
+
+        bytes
+    }
+
+    /// Deserialize from the [binary format](self)
+    ///
+    /// Names serialized with the bytecode are ignored. The code is
+    /// [verified](ExprByteCode::verify) so loaded bytecode can't panic the VM.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BytecodeError> {
+        read(bytes).map(|(bytecode, _)| bytecode)
+    }
+
+    /// Check the op codes are well formed
+    ///
+    /// Every op must be known and have all of its operands. `CONSTANT` and
+    /// `GET TYPE` ops must reference the bytecode's constants and types,
+    /// `GET` ops a known lookup type, `GET_LOCAL` a slot set by an earlier
+    /// op, `SET_LOCAL` at most one slot past them, and jumps must land on an
+    /// op or the end of the code. Offsets in errors are in to the code.
+    ///
+    /// Indexes in to the [`CompileTimeEnv`] aren't known until the bytecode
+    /// is evaluated, the VM returns
+    /// [`RuntimeError::InvalidBytecode`](crate::errors::RuntimeError::InvalidBytecode)
+    /// for those.
+    pub fn verify(&self) -> Result<(), BytecodeError> {
+        verify_code(self, 0)
+    }
+}
+
+/// [`ExprByteCode::verify`] with offsets starting from `code_start`
+fn verify_code(bytecode: &ExprByteCode, code_start: usize) -> Result<(), BytecodeError> {
+    use crate::compiler::{lookup, opcode};
+
+    let codes = bytecode.codes();
+    let mut ops = vec![false; codes.len() + 1];
+    let mut jumps = vec![];
+    let mut slots = 0;
+    let mut ip = 0;
+
+    ops[codes.len()] = true;
+
+    while let Some(&op_code) = codes.get(ip) {
+        let offset = code_start + ip;
+
+        ops[ip] = true;
+
+        let count = opcode::operand_count(op_code)
+            .ok_or(BytecodeError::InvalidOpCode { offset, op_code })?;
+        let operands = codes
+            .get(ip + 1..ip + 1 + count)
+            .ok_or(BytecodeError::MissingOperands { offset })?;
+
+        ip += 1 + count;
+
+        match (op_code, operands) {
+            (opcode::CONSTANT, &[index]) if index as usize >= bytecode.constants().len() => {
+                return Err(BytecodeError::UndefinedConstant {
+                    offset,
+                    index: index.into(),
+                });
+            }
+            (opcode::GET, &[lookup::TYPE, index]) if index as usize >= bytecode.types().len() => {
+                return Err(BytecodeError::UndefinedType {
+                    offset,
+                    index: index.into(),
+                });
+            }
+            (opcode::GET, &[lookup, _]) if lookup > lookup::TYPE => {
+                return Err(BytecodeError::InvalidLookup { offset, lookup });
+            }
+            (opcode::GET_LOCAL, &[slot]) if slot as usize >= slots => {
+                return Err(BytecodeError::UndefinedLocal {
+                    offset,
+                    slot: slot.into(),
+                });
+            }
+            (opcode::SET_LOCAL, &[slot]) if slot as usize > slots => {
+                return Err(BytecodeError::UndefinedLocal {
+                    offset,
+                    slot: slot.into(),
+                });
+            }
+            (opcode::SET_LOCAL, &[slot]) => slots = slots.max(slot as usize + 1),
+            (opcode::JUMP_IF_FALSE | opcode::JUMP | opcode::TRY | opcode::END_TRY, _) => {
+                let target = ip + opcode::jump_offset(operands).expect("should have an offset");
+
+                jumps.push((offset, target));
+            }
+            _ => {}
+        }
+    }
+
+    for (offset, target) in jumps {
+        if !ops.get(target).copied().unwrap_or(false) {
+            return Err(BytecodeError::InvalidJump {
+                offset,
+                target: code_start + target,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Deserialize bytecode along with where each part of it was read from
+pub fn read(bytes: &[u8]) -> Result<(ExprByteCode, Layout), BytecodeError> {
+    read_with_names(bytes).map(|(bytecode, _, layout)| (bytecode, layout))
+}
+
+/// Deserialize bytecode, the names serialized with it if any, and where each
+/// part of it was read from
+pub fn read_with_names(
+    bytes: &[u8],
+) -> Result<(ExprByteCode, Option<NameTables>, Layout), BytecodeError> {
+    let mut reader = Reader::new(bytes);
+
+    let (magic, version) = reader.header(MAGIC)?;
+    let (max_stack_depth, declared_stack_depth) = reader.len()?;
+
+    let (constants_count, count) = reader.len()?;
+    let mut constants = vec![];
+    let mut constant_ranges = vec![];
+
+    for _ in 0..count {
+        let start = reader.offset;
+
+        let constant = match reader.u8()? {
+            value_tag::STRING => Value::String(reader.string()?.into()),
+            value_tag::NUMBER => Value::Number(reader.f64()?),
+            tag => {
+                return Err(BytecodeError::InvalidTag { offset: start, tag });
+            }
+        };
+
+        constants.push(constant);
+        constant_ranges.push(start..reader.offset);
+    }
+
+    let (types_count, count) = reader.len()?;
+    let mut types = vec![];
+    let mut type_ranges = vec![];
+
+    for _ in 0..count {
+        let start = reader.offset;
+
+        types.push(reader.ty()?);
+        type_ranges.push(start..reader.offset);
+    }
+
+    let (code_length, length) = reader.len()?;
+    let code_start = reader.offset;
+    let codes = reader.take(length)?;
+    let code = code_start..reader.offset;
+
+    let mut names = None;
+    let mut names_magic = None;
+    let mut name_ranges = vec![];
+
+    if reader.remaining().starts_with(&NAMES_MAGIC) {
+        let start = reader.offset;
+        reader.take(NAMES_MAGIC.len())?;
+        names_magic = Some(start..reader.offset);
+
+        let mut lists = vec![];
+
+        for _ in 0..4 {
+            let start = reader.offset;
+            let (_, count) = reader.len()?;
+
+            lists.push(
+                (0..count)
+                    .map(|_| reader.string())
+                    .collect::<Result<Vec<_>, _>>()?,
+            );
+            name_ranges.push(start..reader.offset);
+        }
+
+        let [vars, prompts, secrets, client_context] =
+            lists.try_into().expect("should read 4 name lists");
+
+        names = Some(NameTables {
+            vars,
+            prompts,
+            secrets,
+            client_context,
+        });
+    }
+
+    reader.finish()?;
+
+    let bytecode = ExprByteCode::new(
+        get_version_bytes().iter().chain(codes).copied().collect(),
+        constants,
+        types,
+    );
+
+    verify_code(&bytecode, code.start)?;
+
+    if bytecode.max_stack_depth() != declared_stack_depth {
+        return Err(BytecodeError::StackDepthMismatch {
+            declared: declared_stack_depth,
+            actual: bytecode.max_stack_depth(),
+        });
+    }
+
+    Ok((
+        bytecode,
+        names,
+        Layout {
+            magic,
+            version,
+            max_stack_depth,
+            constants_count,
+            constants: constant_ranges,
+            types_count,
+            types: type_ranges,
+            code_length,
+            code,
+            names_magic,
+            names: name_ranges,
+        },
+    ))
+}
+
+/// Write the magic bytes and current version
+pub(crate) fn write_header(bytes: &mut Vec<u8>, magic: [u8; 4]) {
+    bytes.extend(magic);
+    bytes.extend(get_version_bytes());
+}
+
+pub(crate) fn write_len(bytes: &mut Vec<u8>, len: usize) {
+    let len: u32 = len.try_into().expect("length should fit in a u32");
+
+    bytes.extend(len.to_le_bytes());
+}
+
+pub(crate) fn write_string(bytes: &mut Vec<u8>, string: &str) {
+    write_len(bytes, string.len());
+    bytes.extend(string.as_bytes());
+}
+
+pub(crate) fn write_value(bytes: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::String(string) => {
+            bytes.push(value_tag::STRING);
+            write_string(bytes, string);
+        }
+        Value::Number(number) => {
+            bytes.push(value_tag::NUMBER);
+            bytes.extend(number.to_le_bytes());
+        }
+        Value::Bool(bool) => {
+            bytes.push(value_tag::BOOL);
+            bytes.push(*bool as u8);
+        }
+        Value::Fn(builtin) => {
+            bytes.push(match builtin.namespace {
+                FnNamespace::Builtin => value_tag::FN,
+                FnNamespace::User => value_tag::USER_FN,
+            });
+            write_string(bytes, builtin.name);
+        }
+        Value::Closure(closure) => match closure.as_ref() {
+            Closure::Compose { f, g } => {
+                bytes.push(value_tag::COMPOSE);
+                write_value(bytes, f);
+                write_value(bytes, g);
+            }
+            Closure::Partial { f, arg } => {
+                bytes.push(value_tag::PARTIAL);
+                write_value(bytes, f);
+                write_value(bytes, arg);
+            }
+        },
+        Value::Type(ty) => {
+            bytes.push(value_tag::TYPE);
+            write_type(bytes, ty);
+        }
+        Value::List(items) => {
+            bytes.push(value_tag::LIST);
+            write_len(bytes, items.len());
+
+            for item in items {
+                write_value(bytes, item);
+            }
+        }
+    }
+}
+
+pub(crate) fn write_type(bytes: &mut Vec<u8>, ty: &Type) {
+    match ty {
+        Type::Value => bytes.push(type_tag::VALUE),
+        Type::String => bytes.push(type_tag::STRING),
+        Type::Number => bytes.push(type_tag::NUMBER),
+        Type::Bool => bytes.push(type_tag::BOOL),
+        Type::Unknown => bytes.push(type_tag::UNKNOWN),
+        Type::Type(ty) => {
+            bytes.push(type_tag::TYPE);
+            write_type(bytes, ty);
+        }
+        Type::List(item) => {
+            bytes.push(type_tag::LIST);
+            write_type(bytes, item);
+        }
+        Type::Fn {
+            args,
+            variadic_arg,
+            returns,
+        } => {
+            bytes.push(type_tag::FN);
+            write_len(bytes, args.len());
+
+            for arg in args {
+                write_type(bytes, arg);
+            }
+
+            match variadic_arg {
+                Some(variadic_arg) => {
+                    bytes.push(1);
+                    write_type(bytes, variadic_arg);
+                }
+                None => bytes.push(0),
+            }
+
+            write_type(bytes, returns);
+        }
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub(crate) offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    /// Read the magic bytes and version, returning where each was read from
+    pub(crate) fn header(
+        &mut self,
+        magic: [u8; 4],
+    ) -> Result<(Range<usize>, Range<usize>), BytecodeError> {
+        let magic_start = self.offset;
+
+        if self.take(magic.len())? != magic {
+            return Err(BytecodeError::InvalidMagic);
+        }
+
+        let magic = magic_start..self.offset;
+
+        let version_start = self.offset;
+        let version = self.take(4)?;
+
+        if version != get_version_bytes() {
+            return Err(BytecodeError::UnsupportedVersion(
+                version.iter().map(|byte| byte.to_string()).collect(),
+            ));
+        }
+
+        Ok((magic, version_start..self.offset))
+    }
+
+    /// Bytes left to read
+    pub(crate) fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.offset..]
+    }
+
+    /// Error if there are bytes left to read
+    pub(crate) fn finish(&self) -> Result<(), BytecodeError> {
+        if self.offset != self.bytes.len() {
+            return Err(BytecodeError::TrailingBytes {
+                offset: self.offset,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], BytecodeError> {
+        let bytes =
+            self.bytes
+                .get(self.offset..self.offset + n)
+                .ok_or(BytecodeError::UnexpectedEnd {
+                    offset: self.bytes.len(),
+                })?;
+
+        self.offset += n;
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read a `u32` length, returning where it was read from
+    pub(crate) fn len(&mut self) -> Result<(Range<usize>, usize), BytecodeError> {
+        let start = self.offset;
+        let len = u32::from_le_bytes(self.take(4)?.try_into().expect("should take 4 bytes"));
+
+        Ok((start..self.offset, len as usize))
+    }
+
+    pub(crate) fn f64(&mut self) -> Result<f64, BytecodeError> {
+        Ok(f64::from_le_bytes(
+            self.take(8)?.try_into().expect("should take 8 bytes"),
+        ))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, BytecodeError> {
+        let (range, len) = self.len()?;
+
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| BytecodeError::InvalidString {
+            offset: range.start,
+        })
+    }
+
+    /// Read a value, resolving functions by name from `env`
+    pub(crate) fn value(&mut self, env: &CompileTimeEnv) -> Result<Value, BytecodeError> {
+        let offset = self.offset;
+
+        let value = match self.u8()? {
+            value_tag::STRING => Value::String(self.string()?.into()),
+            value_tag::NUMBER => Value::Number(self.f64()?),
+            value_tag::BOOL => match self.u8()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                tag => {
+                    return Err(BytecodeError::InvalidTag {
+                        offset: offset + 1,
+                        tag,
+                    });
+                }
+            },
+            value_tag::FN => {
+                let name = self.string()?;
+
+                let builtin = env
+                    .get_builtin_index(&name)
+                    .and_then(|(_, index)| env.get_builtin(index))
+                    // User builtins were tagged FN before USER_FN was added
+                    .or_else(|| {
+                        env.get_user_builtin_index(&name)
+                            .and_then(|(_, index)| env.get_user_builtin(index))
+                    })
+                    .ok_or(BytecodeError::UndefinedFn { offset, name })?;
+
+                Value::Fn(builtin.clone().into())
+            }
+            value_tag::USER_FN => {
+                let name = self.string()?;
+
+                let builtin = env
+                    .get_user_builtin_index(&name)
+                    .and_then(|(_, index)| env.get_user_builtin(index))
+                    .ok_or(BytecodeError::UndefinedFn { offset, name })?;
+
+                Value::Fn(builtin.clone().into())
+            }
+            value_tag::COMPOSE => Value::Closure(
+                Closure::Compose {
+                    f: self.value(env)?,
+                    g: self.value(env)?,
+                }
+                .into(),
+            ),
+            value_tag::PARTIAL => Value::Closure(
+                Closure::Partial {
+                    f: self.value(env)?,
+                    arg: self.value(env)?,
+                }
+                .into(),
+            ),
+            value_tag::TYPE => Value::Type(self.ty()?.into()),
+            value_tag::LIST => {
+                let (_, count) = self.len()?;
+
+                Value::List(
+                    (0..count)
+                        .map(|_| self.value(env))
+                        .collect::<Result<Vec<_>, _>>()?,
+                )
+            }
+            tag => return Err(BytecodeError::InvalidTag { offset, tag }),
+        };
+
+        Ok(value)
+    }
+
+    fn ty(&mut self) -> Result<Type, BytecodeError> {
+        let offset = self.offset;
+
+        let ty = match self.u8()? {
+            type_tag::VALUE => Type::Value,
+            type_tag::STRING => Type::String,
+            type_tag::NUMBER => Type::Number,
+            type_tag::BOOL => Type::Bool,
+            type_tag::UNKNOWN => Type::Unknown,
+            type_tag::TYPE => Type::Type(self.ty()?.into()),
+            type_tag::LIST => Type::List(self.ty()?.into()),
+            type_tag::FN => {
+                let (_, count) = self.len()?;
+                let args = (0..count)
+                    .map(|_| self.ty())
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let variadic_offset = self.offset;
+                let variadic_arg = match self.u8()? {
+                    0 => None,
+                    1 => Some(self.ty()?.into()),
+                    tag => {
+                        return Err(BytecodeError::InvalidTag {
+                            offset: variadic_offset,
+                            tag,
+                        });
+                    }
+                };
+
+                Type::Fn {
+                    args,
+                    variadic_arg,
+                    returns: self.ty()?.into(),
+                }
+            }
+            tag => return Err(BytecodeError::InvalidTag { offset, tag }),
+        };
+
+        Ok(ty)
+    }
+}
+
+#[cfg(test)]
+mod binary_tests {
+    use super::*;
+
+    use crate::prelude::*;
+
+    fn compile_source(source: &str) -> ExprByteCode {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let ast = parse(source).unwrap();
+
+        compile(&mut (ast, 0..source.len()), &env).unwrap()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let bytecode =
+            compile_source("(cond (eq :a `b`) (id Fn(String, ...Value) -> Bool) (id Type<Bool>))");
+
+        assert_eq!(
+            Ok(bytecode.clone()),
+            ExprByteCode::from_bytes(&bytecode.to_bytes())
+        );
+    }
+
+    #[test]
+    fn layout() {
+        let bytecode = compile_source("(concat `b` `c`)");
+        let bytes = bytecode.to_bytes();
+
+        let (_, layout) = read(&bytes).unwrap();
+
+        assert_eq!(
+            Layout {
+                magic: 0..4,
+                version: 4..8,
+                max_stack_depth: 8..12,
+                constants_count: 12..16,
+                constants: vec![16..22, 22..28],
+                types_count: 28..32,
+                types: vec![],
+                code_length: 32..36,
+                code: 36..45,
+                names_magic: None,
+                names: vec![],
+            },
+            layout
+        );
+        assert_eq!(bytes.len(), 45);
+    }
+
+    #[test]
+    fn roundtrip_with_names() {
+        let bytecode = compile_source("(concat :a `b`)");
+        let names = NameTables {
+            vars: vec!["a".to_string()],
+            prompts: vec![],
+            secrets: vec!["token".to_string(), "key".to_string()],
+            client_context: vec![],
+        };
+
+        let bytes = bytecode.to_bytes_with_names(&names);
+        let (read_bytecode, read_names, layout) = read_with_names(&bytes).unwrap();
+
+        assert_eq!(bytecode, read_bytecode);
+        assert_eq!(Some(names), read_names);
+        assert_eq!(Some(40..44), layout.names_magic);
+        assert_eq!(vec![44..53, 53..57, 57..77, 77..81], layout.names);
+        assert_eq!(Ok(bytecode), ExprByteCode::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn stripped_names() {
+        let bytes = compile_source("`b`").to_bytes();
+
+        assert!(matches!(read_with_names(&bytes), Ok((_, None, _))));
+    }
+
+    #[test]
+    fn invalid_magic() {
+        assert_eq!(
+            Err(BytecodeError::InvalidMagic),
+            ExprByteCode::from_bytes(b"NOPE")
+        );
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut bytes = compile_source("true").to_bytes();
+        bytes[4..8].copy_from_slice(&[0, 0, 1, 0]);
+
+        assert_eq!(
+            Err(BytecodeError::UnsupportedVersion("0010".to_string())),
+            ExprByteCode::from_bytes(&bytes)
+        );
+    }
+
+    #[test]
+    fn unexpected_end() {
+        let bytes = compile_source("`b`").to_bytes();
+
+        assert_eq!(
+            Err(BytecodeError::UnexpectedEnd { offset: 15 }),
+            ExprByteCode::from_bytes(&bytes[..15])
+        );
+    }
+
+    #[test]
+    fn invalid_tag() {
+        let mut bytes = compile_source("`b`").to_bytes();
+        bytes[16] = 9;
+
+        assert_eq!(
+            Err(BytecodeError::InvalidTag { offset: 16, tag: 9 }),
+            ExprByteCode::from_bytes(&bytes)
+        );
+    }
+
+    #[test]
+    fn stack_depth_mismatch() {
+        let mut bytes = compile_source("`b`").to_bytes();
+        bytes[8..12].copy_from_slice(&7u32.to_le_bytes());
+
+        assert_eq!(
+            Err(BytecodeError::StackDepthMismatch {
+                declared: 7,
+                actual: 1
+            }),
+            ExprByteCode::from_bytes(&bytes)
+        );
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut bytes = compile_source("true").to_bytes();
+        bytes.push(0);
+
+        assert_eq!(
+            Err(BytecodeError::TrailingBytes {
+                offset: bytes.len() - 1
+            }),
+            ExprByteCode::from_bytes(&bytes)
+        );
+    }
+
+    #[test]
+    fn verify() {
+        use crate::compiler::{lookup, opcode::*};
+
+        let verify = |codes: &[u8]| {
+            let codes = get_version_bytes().iter().chain(codes).copied().collect();
+
+            ExprByteCode::new(codes, vec![Value::String("b".into())], vec![]).verify()
+        };
+
+        assert_eq!(Ok(()), verify(&[TRUE, SET_LOCAL, 0, GET_LOCAL, 0]));
+        assert_eq!(Ok(()), verify(&[TRUE, JUMP_IF_FALSE, 0, 2, CONSTANT, 0]));
+        assert_eq!(
+            Err(BytecodeError::InvalidOpCode {
+                offset: 1,
+                op_code: 200
+            }),
+            verify(&[TRUE, 200])
+        );
+        assert_eq!(
+            Err(BytecodeError::MissingOperands { offset: 0 }),
+            verify(&[CONSTANT])
+        );
+        assert_eq!(
+            Err(BytecodeError::UndefinedConstant {
+                offset: 0,
+                index: 1
+            }),
+            verify(&[CONSTANT, 1])
+        );
+        assert_eq!(
+            Err(BytecodeError::UndefinedType {
+                offset: 0,
+                index: 0
+            }),
+            verify(&[GET, lookup::TYPE, 0])
+        );
+        assert_eq!(
+            Err(BytecodeError::InvalidLookup {
+                offset: 0,
+                lookup: 9
+            }),
+            verify(&[GET, 9, 0])
+        );
+        assert_eq!(
+            Err(BytecodeError::UndefinedLocal { offset: 0, slot: 0 }),
+            verify(&[GET_LOCAL, 0])
+        );
+        assert_eq!(
+            Err(BytecodeError::UndefinedLocal { offset: 1, slot: 1 }),
+            verify(&[TRUE, SET_LOCAL, 1])
+        );
+        assert_eq!(
+            Err(BytecodeError::InvalidJump {
+                offset: 1,
+                target: 5
+            }),
+            verify(&[TRUE, JUMP_IF_FALSE, 0, 1, CONSTANT, 0])
+        );
+        assert_eq!(
+            Err(BytecodeError::InvalidJump {
+                offset: 0,
+                target: 12
+            }),
+            verify(&[JUMP, 0, 9])
+        );
+    }
+
+    #[test]
+    fn invalid_code() {
+        let bytes = compile_source("`b`").to_bytes();
+        let (_, layout) = read(&bytes).unwrap();
+
+        let mut invalid = bytes.clone();
+        invalid[layout.code.start + 1] = 1;
+
+        assert_eq!(
+            Err(BytecodeError::UndefinedConstant {
+                offset: layout.code.start,
+                index: 1
+            }),
+            ExprByteCode::from_bytes(&invalid)
+        );
+    }
+
+    /// Loaded bytecode evaluates to a value or an error, even if it was
+    /// corrupted
+    #[test]
+    fn mutated_bytes_never_panic() {
+        let env = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        );
+        let runtime_env = RuntimeEnv {
+            vars: vec!["a".to_string()],
+            prompts: vec!["b".to_string()],
+            secrets: vec!["c".to_string()],
+            client_context: vec![Value::String("d".into())],
+        };
+
+        // xorshift so the mutations are the same every run
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+
+        for source in [
+            "(concat :a ?b !c `x` (to_str 1) (type @d))",
+            "(cond (eq :a `b`) [1 2 Number] (try (trim @d) `f`))",
+            "(let (x :a) (let (y (concat x x)) (and true (or false (eq x y)))))",
+        ] {
+            let bytecode = compile(&mut (parse(source).unwrap(), 0..source.len()), &env).unwrap();
+            let bytes = bytecode.to_bytes();
+
+            for _ in 0..2000 {
+                let mut mutated = bytes.clone();
+
+                for _ in 0..=random() % 3 {
+                    let index = random() % mutated.len();
+                    mutated[index] = random() as u8;
+                }
+
+                let Ok(bytecode) = ExprByteCode::from_bytes(&mutated) else {
+                    continue;
+                };
+
+                let result = std::panic::catch_unwind(|| {
+                    let _ = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+                });
+
+                assert!(result.is_ok(), "{mutated:?}");
+            }
+        }
+    }
+}
@@ -1,13 +1,25 @@
 use core::fmt;
-use std::fmt::Display;
-
-use crate::{errors::ExprResult, types::Type, value::Value};
+use std::{
+    cell::Cell,
+    fmt::Display,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    errors::{ExprErrorS, ExprResult, RuntimeError},
+    types::Type,
+    value::{Closure, Value},
+};
 
 #[derive(Clone)]
 pub struct FnArg {
     pub name: &'static str,
     pub ty: Type,
     pub variadic: bool,
+    /// Can be left out of calls. Optional args come after required args and
+    /// aren't part of the builtin's [`Type::Fn`].
+    pub optional: bool,
 }
 
 impl FnArg {
@@ -16,6 +28,7 @@ impl FnArg {
             name,
             ty,
             variadic: false,
+            optional: false,
         }
     }
 
@@ -24,10 +37,91 @@ impl FnArg {
             name,
             ty,
             variadic: true,
+            optional: false,
+        }
+    }
+
+    pub fn new_optional(name: &'static str, ty: Type) -> Self {
+        Self {
+            name,
+            ty,
+            variadic: false,
+            optional: true,
         }
     }
 }
 
+thread_local! {
+    /// Name of the builtin the VM is calling and when it times out
+    static DEADLINE: Cell<Option<(&'static str, Instant)>> = const { Cell::new(None) };
+}
+
+/// Fail with [`RuntimeError::BuiltinTimeout`] if the builtin being called has
+/// run longer than [`VmConfig::builtin_timeout`](crate::vm::VmConfig::builtin_timeout)
+///
+/// Builtins can't be interrupted so long running ones should call this
+/// periodically and return its error. Does nothing outside of a call made by
+/// a [`Vm`](crate::vm::Vm) with a timeout.
+pub fn check_timeout() -> ExprResult<()> {
+    match DEADLINE.get() {
+        Some((name, deadline)) if Instant::now() >= deadline => Err(timeout_error(name)),
+        _ => Ok(()),
+    }
+}
+
+/// Make a call, failing with [`RuntimeError::BuiltinTimeout`] if it takes
+/// longer than `timeout`
+///
+/// The call's result is discarded if it returns after the timeout.
+pub(crate) fn call_with_timeout(
+    name: &'static str,
+    timeout: Option<Duration>,
+    call: impl FnOnce() -> ExprResult<Value>,
+) -> ExprResult<Value> {
+    let Some(timeout) = timeout else {
+        return call();
+    };
+
+    let start = Instant::now();
+    let deadline = Deadline::set(name, start + timeout);
+
+    let result = call();
+
+    drop(deadline);
+
+    if start.elapsed() > timeout {
+        return Err(timeout_error(name));
+    }
+
+    result
+}
+
+/// Sets [`DEADLINE`] for a call, restoring the previous deadline when
+/// dropped so it's restored even if the call panics
+struct Deadline(Option<(&'static str, Instant)>);
+
+impl Deadline {
+    fn set(name: &'static str, deadline: Instant) -> Self {
+        Self(DEADLINE.replace(Some((name, deadline))))
+    }
+}
+
+impl Drop for Deadline {
+    fn drop(&mut self) {
+        DEADLINE.set(self.0);
+    }
+}
+
+fn timeout_error(name: &str) -> Vec<ExprErrorS> {
+    vec![(
+        RuntimeError::BuiltinTimeout {
+            name: name.to_string(),
+        }
+        .into(),
+        0..0,
+    )]
+}
+
 #[derive(Clone)]
 /// Builtin function used in expressions
 pub struct BuiltinFn<'a> {
@@ -39,13 +133,43 @@ pub struct BuiltinFn<'a> {
     pub return_type: Type,
     /// Function used at runtime
     pub func: fn(Vec<Value>) -> ExprResult<Value>,
+    /// Where the function was defined
+    pub namespace: FnNamespace,
+    /// Returns the same result for the same args without side effects, so
+    /// [`VmConfig::memoize`](crate::vm::VmConfig::memoize) can cache it
+    pub pure: bool,
+    /// What kind of values the function works with, for grouping it in
+    /// docs and completions
+    pub category: BuiltinCategory,
+    /// Group hosts can disable the function with, if it's in one
+    pub capability: Option<BuiltinGroup>,
+}
+
+/// Where a [`BuiltinFn`] was defined
+///
+/// Part of a function's identity so a user builtin never equals a builtin of
+/// the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FnNamespace {
+    /// Defined by this crate, including optional [`BuiltinGroup`]s
+    Builtin,
+    /// Added with [`CompileTimeEnv::add_user_builtin`](crate::compiler::CompileTimeEnv::add_user_builtin)
+    User,
 }
 
 impl<'a> BuiltinFn<'a> {
+    /// Number of required args
     pub fn arity(&self) -> u8 {
-        let len = self.args.len() as u8;
+        self.args
+            .iter()
+            .filter(|arg| !arg.variadic && !arg.optional)
+            .count() as u8
+    }
 
-        if self.is_variadic() { len - 1 } else { len }
+    /// Number of args that can be passed, not counting variadic args
+    pub fn max_arity(&self) -> u8 {
+        self.args.iter().filter(|arg| !arg.variadic).count() as u8
     }
 
     pub fn is_variadic(&self) -> bool {
@@ -56,14 +180,45 @@ impl<'a> BuiltinFn<'a> {
         if self.is_variadic() {
             self.arity() <= arity
         } else {
-            self.arity() == arity
+            (self.arity()..=self.max_arity()).contains(&arity)
+        }
+    }
+
+    /// Fail with [`RuntimeError::WrongNumberOfArgs`] unless `args` can be
+    /// passed to the function
+    ///
+    /// Calls are checked when they're compiled but not calls to functions
+    /// that are values, e.g. `((id trim))`, or calls in bytecode that wasn't
+    /// compiled from source.
+    pub fn check_arity(&self, args: &[Value]) -> ExprResult<()> {
+        let matches = u8::try_from(args.len())
+            .map(|arity| self.arity_matches(arity))
+            .unwrap_or(self.is_variadic());
+
+        if matches {
+            return Ok(());
         }
+
+        let expected = match args.len() > self.max_arity() as usize {
+            true => self.max_arity(),
+            false => self.arity(),
+        };
+
+        Err(vec![(
+            RuntimeError::WrongNumberOfArgs {
+                name: self.name.to_string(),
+                expected: expected as usize,
+                actual: args.len(),
+            }
+            .into(),
+            0..0,
+        )])
     }
 
     /// The default set of builtin functions
     ///
     /// This also defines the lookup index for builtins during compilation
-    pub const DEFAULT_BUILTINS: [BuiltinFn<'a>; 17] = [
+    pub const DEFAULT_BUILTINS: [BuiltinFn<'a>; 49] = [
         BuiltinFn::ID,
         BuiltinFn::NOOP,
         BuiltinFn::IS_EMPTY,
@@ -81,6 +236,49 @@ impl<'a> BuiltinFn<'a> {
         BuiltinFn::TYPE,
         BuiltinFn::EQ,
         BuiltinFn::NOT,
+        BuiltinFn::EQ_IGNORE_CASE,
+        BuiltinFn::SNAKE_CASE,
+        BuiltinFn::CAMEL_CASE,
+        BuiltinFn::KEBAB_CASE,
+        BuiltinFn::TITLE_CASE,
+        BuiltinFn::TRUNCATE,
+        BuiltinFn::COUNT_MATCHES,
+        BuiltinFn::LINE,
+        BuiltinFn::HTML_ESCAPE,
+        BuiltinFn::HTML_UNESCAPE,
+        BuiltinFn::SEMVER_GTE,
+        BuiltinFn::CRC32,
+        BuiltinFn::ADLER32,
+        BuiltinFn::COMPOSE,
+        BuiltinFn::PARTIAL,
+        BuiltinFn::NEQ,
+        BuiltinFn::XOR,
+        BuiltinFn::IMPLIES,
+        BuiltinFn::PARSE_BOOL,
+        BuiltinFn::CMP,
+        BuiltinFn::SORT_KEY,
+        BuiltinFn::LEN,
+        BuiltinFn::GET,
+        BuiltinFn::MAP_JOIN,
+        BuiltinFn::MAP,
+        BuiltinFn::FILTER,
+        BuiltinFn::REDUCE,
+        BuiltinFn::SECURE_EQ,
+        BuiltinFn::ASSERT,
+        BuiltinFn::ASSERT_EQ,
+        BuiltinFn::TRY,
+        BuiltinFn::NEGATE,
+    ];
+
+    /// Math builtins registered as [`BuiltinGroup::Math`]
+    pub const MATH_BUILTINS: [BuiltinFn<'a>; 7] = [
+        BuiltinFn::ABS,
+        BuiltinFn::FLOOR,
+        BuiltinFn::CEIL,
+        BuiltinFn::ROUND,
+        BuiltinFn::POW,
+        BuiltinFn::SQRT,
+        BuiltinFn::CLAMP,
     ];
 
     // Builtin Definitions
@@ -94,9 +292,14 @@ impl<'a> BuiltinFn<'a> {
             name: "value",
             ty: Type::Value,
             variadic: false,
+            optional: false,
         }],
         return_type: Type::Value,
         func: Self::id,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
     };
 
     fn id(args: Vec<Value>) -> ExprResult<Value> {
@@ -113,10 +316,14 @@ impl<'a> BuiltinFn<'a> {
         args: &[],
         return_type: Type::String,
         func: Self::noop,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
     };
 
     fn noop(_: Vec<Value>) -> ExprResult<Value> {
-        Ok(Value::String(String::from("noop")))
+        Ok(Value::String("noop".into()))
     }
 
     /// Return [`Type::Bool`] if [`Value::String`] is empty
@@ -128,9 +335,14 @@ impl<'a> BuiltinFn<'a> {
             name: "value",
             ty: Type::String,
             variadic: false,
+            optional: false,
         }],
         return_type: Type::Bool,
         func: Self::is_empty,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn is_empty(args: Vec<Value>) -> ExprResult<Value> {
@@ -152,15 +364,21 @@ impl<'a> BuiltinFn<'a> {
                 name: "a",
                 ty: Type::Bool,
                 variadic: false,
+                optional: false,
             },
             FnArg {
                 name: "b",
                 ty: Type::Bool,
                 variadic: false,
+                optional: false,
             },
         ],
         return_type: Type::Bool,
         func: Self::and,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Bool,
+        capability: None,
     };
 
     fn and(args: Vec<Value>) -> ExprResult<Value> {
@@ -186,15 +404,21 @@ impl<'a> BuiltinFn<'a> {
                 name: "a",
                 ty: Type::Bool,
                 variadic: false,
+                optional: false,
             },
             FnArg {
                 name: "b",
                 ty: Type::Bool,
                 variadic: false,
+                optional: false,
             },
         ],
         return_type: Type::Bool,
         func: Self::or,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Bool,
+        capability: None,
     };
 
     fn or(args: Vec<Value>) -> ExprResult<Value> {
@@ -220,20 +444,27 @@ impl<'a> BuiltinFn<'a> {
                 name: "cond",
                 ty: Type::Bool,
                 variadic: false,
+                optional: false,
             },
             FnArg {
                 name: "then",
                 ty: Type::Value,
                 variadic: false,
+                optional: false,
             },
             FnArg {
                 name: "else",
                 ty: Type::Value,
                 variadic: false,
+                optional: false,
             },
         ],
-        return_type: Type::Bool,
+        return_type: Type::Value,
         func: Self::cond,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Bool,
+        capability: None,
     };
 
     fn cond(args: Vec<Value>) -> ExprResult<Value> {
@@ -262,9 +493,14 @@ impl<'a> BuiltinFn<'a> {
             name: "value",
             ty: Type::Value,
             variadic: false,
+            optional: false,
         }],
         return_type: Type::String,
         func: Self::to_str,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
     };
 
     fn to_str(args: Vec<Value>) -> ExprResult<Value> {
@@ -272,7 +508,7 @@ impl<'a> BuiltinFn<'a> {
 
         Ok(match value_arg {
             Value::String(_) => value_arg.clone(),
-            _ => Value::String(value_arg.to_string()),
+            _ => Value::String(value_arg.to_string().into()),
         })
     }
 
@@ -286,35 +522,40 @@ impl<'a> BuiltinFn<'a> {
                 name: "a",
                 ty: Type::Value,
                 variadic: false,
+                optional: false,
             },
             FnArg {
                 name: "b",
                 ty: Type::Value,
                 variadic: false,
+                optional: false,
             },
             FnArg {
                 name: "rest",
                 ty: Type::Value,
                 variadic: true,
+                optional: false,
             },
         ],
         return_type: Type::String,
         func: Self::concat,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn concat(args: Vec<Value>) -> ExprResult<Value> {
         let mut result = String::new();
 
         for arg in args {
-            let value = match arg {
-                Value::String(string) => string,
-                _ => arg.to_string(),
-            };
-
-            result.push_str(value.as_str());
+            match arg {
+                Value::String(string) => result.push_str(&string),
+                _ => result.push_str(&arg.to_string()),
+            }
         }
 
-        Ok(Value::String(result))
+        Ok(Value::String(result.into()))
     }
 
     /// Returns [`Value::Bool`] if `needle` [`Value::String`] is in `haystack` [`Value::String`]
@@ -327,15 +568,21 @@ impl<'a> BuiltinFn<'a> {
                 name: "needle",
                 ty: Type::String,
                 variadic: false,
+                optional: false,
             },
             FnArg {
                 name: "haystack",
                 ty: Type::String,
                 variadic: false,
+                optional: false,
             },
         ],
         return_type: Type::Bool,
         func: Self::contains,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn contains(args: Vec<Value>) -> ExprResult<Value> {
@@ -351,18 +598,34 @@ impl<'a> BuiltinFn<'a> {
         Ok(Value::Bool(haystack_arg.contains(needle_arg)))
     }
 
-    /// Returns [`Value::String`] with whitespace trimmed from both sides of [`Value::String`]
+    /// Returns [`Value::String`] with whitespace, or the characters in `chars`,
+    /// trimmed from both sides of [`Value::String`]
     ///
     /// `` (trim ` Hello `) ``
+    ///
+    /// `` (trim `--Hello--` `-`) ``
     pub const TRIM: BuiltinFn<'static> = BuiltinFn {
         name: "trim",
-        args: &[FnArg {
-            name: "value",
-            ty: Type::String,
-            variadic: false,
-        }],
+        args: &[
+            FnArg {
+                name: "value",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "chars",
+                ty: Type::String,
+                variadic: false,
+                optional: true,
+            },
+        ],
         return_type: Type::String,
         func: Self::trim,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn trim(args: Vec<Value>) -> ExprResult<Value> {
@@ -371,21 +634,42 @@ impl<'a> BuiltinFn<'a> {
             .expect("should have string expression passed")
             .get_string()?;
 
-        Ok(Value::String(string_arg.trim().to_string()))
+        let trimmed = match Self::trim_chars(&args)? {
+            Some(chars) => string_arg.trim_matches(|c| chars.contains(c)),
+            None => string_arg.trim(),
+        };
+
+        Ok(Value::String(trimmed.into()))
     }
 
-    /// Returns [`Value::String`] with whitespace trimmed from start of [`Value::String`]
+    /// Returns [`Value::String`] with whitespace, or the characters in `chars`,
+    /// trimmed from start of [`Value::String`]
     ///
     /// `` (trim_start ` Hello`) ``
+    ///
+    /// `` (trim_start `--Hello` `-`) ``
     pub const TRIM_START: BuiltinFn<'static> = BuiltinFn {
         name: "trim_start",
-        args: &[FnArg {
-            name: "value",
-            ty: Type::String,
-            variadic: false,
-        }],
+        args: &[
+            FnArg {
+                name: "value",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "chars",
+                ty: Type::String,
+                variadic: false,
+                optional: true,
+            },
+        ],
         return_type: Type::String,
         func: Self::trim_start,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn trim_start(args: Vec<Value>) -> ExprResult<Value> {
@@ -394,21 +678,42 @@ impl<'a> BuiltinFn<'a> {
             .expect("should have string expression passed")
             .get_string()?;
 
-        Ok(Value::String(string_arg.trim_start().to_string()))
+        let trimmed = match Self::trim_chars(&args)? {
+            Some(chars) => string_arg.trim_start_matches(|c| chars.contains(c)),
+            None => string_arg.trim_start(),
+        };
+
+        Ok(Value::String(trimmed.into()))
     }
 
-    /// Returns [`Value::String`] with whitespace trimmed from end of [`Value::String`]
+    /// Returns [`Value::String`] with whitespace, or the characters in `chars`,
+    /// trimmed from end of [`Value::String`]
     ///
     /// `` (trim_end `Hello `) ``
+    ///
+    /// `` (trim_end `Hello--` `-`) ``
     pub const TRIM_END: BuiltinFn<'static> = BuiltinFn {
         name: "trim_end",
-        args: &[FnArg {
-            name: "value",
-            ty: Type::String,
-            variadic: false,
-        }],
+        args: &[
+            FnArg {
+                name: "value",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "chars",
+                ty: Type::String,
+                variadic: false,
+                optional: true,
+            },
+        ],
         return_type: Type::String,
         func: Self::trim_end,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn trim_end(args: Vec<Value>) -> ExprResult<Value> {
@@ -417,7 +722,18 @@ impl<'a> BuiltinFn<'a> {
             .expect("should have string expression passed")
             .get_string()?;
 
-        Ok(Value::String(string_arg.trim_end().to_string()))
+        let trimmed = match Self::trim_chars(&args)? {
+            Some(chars) => string_arg.trim_end_matches(|c| chars.contains(c)),
+            None => string_arg.trim_end(),
+        };
+
+        Ok(Value::String(trimmed.into()))
+    }
+
+    /// Characters passed to `trim`, `trim_start`, or `trim_end` to trim
+    /// instead of whitespace
+    fn trim_chars(args: &[Value]) -> ExprResult<Option<&str>> {
+        args.get(1).map(Value::get_string).transpose()
     }
 
     /// Returns [`Value::String`] lowercased
@@ -430,10 +746,15 @@ impl<'a> BuiltinFn<'a> {
                 name: "value",
                 ty: Type::String,
                 variadic: false,
+                optional: false,
             }
         }],
         return_type: Type::String,
         func: Self::lowercase,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn lowercase(args: Vec<Value>) -> ExprResult<Value> {
@@ -442,7 +763,7 @@ impl<'a> BuiltinFn<'a> {
             .expect("should have string expression passed")
             .get_string()?;
 
-        Ok(Value::String(string_arg.to_lowercase().to_string()))
+        Ok(Value::String(string_arg.to_lowercase().into()))
     }
 
     /// Returns [`Value::String`] uppercased
@@ -454,9 +775,14 @@ impl<'a> BuiltinFn<'a> {
             name: "value",
             ty: Type::String,
             variadic: false,
+            optional: false,
         }],
         return_type: Type::String,
         func: Self::uppercase,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
     };
 
     fn uppercase(args: Vec<Value>) -> ExprResult<Value> {
@@ -465,7 +791,7 @@ impl<'a> BuiltinFn<'a> {
             .expect("should have string expression passed")
             .get_string()?;
 
-        Ok(Value::String(string_arg.to_uppercase().to_string()))
+        Ok(Value::String(string_arg.to_uppercase().into()))
     }
 
     /// Returns [`Value::Type`] of [`Value`]
@@ -477,9 +803,14 @@ impl<'a> BuiltinFn<'a> {
             name: "value",
             ty: Type::Value,
             variadic: false,
+            optional: false,
         }],
-        return_type: Type::String,
+        return_type: Type::Value,
         func: Self::get_type,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
     };
 
     fn get_type(args: Vec<Value>) -> ExprResult<Value> {
@@ -490,6 +821,12 @@ impl<'a> BuiltinFn<'a> {
 
     /// Returns [`Value::Bool`] if two [`Value`] are equal
     ///
+    /// Values of different types are never equal. Functions are equal when
+    /// they're the same function from the same [`FnNamespace`], so a user
+    /// builtin named `id` doesn't equal `id`. Closures are equal when they're
+    /// built from equal functions and arguments. Numbers are equal by value
+    /// except that `NaN` equals `NaN`.
+    ///
     /// (eq true true)
     pub const EQ: BuiltinFn<'static> = BuiltinFn {
         name: "eq",
@@ -499,6 +836,7 @@ impl<'a> BuiltinFn<'a> {
                     name: "a",
                     ty: Type::Value,
                     variadic: false,
+                    optional: false,
                 }
             },
             {
@@ -506,11 +844,16 @@ impl<'a> BuiltinFn<'a> {
                     name: "b",
                     ty: Type::Value,
                     variadic: false,
+                    optional: false,
                 }
             },
         ],
         return_type: Type::Bool,
         func: Self::eq,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
     };
 
     fn eq(args: Vec<Value>) -> ExprResult<Value> {
@@ -533,10 +876,15 @@ impl<'a> BuiltinFn<'a> {
                 name: "value",
                 ty,
                 variadic: false,
+                optional: false,
             }
         }],
         return_type: Type::Bool,
         func: Self::not,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Bool,
+        capability: None,
     };
 
     fn not(args: Vec<Value>) -> ExprResult<Value> {
@@ -546,98 +894,2254 @@ impl<'a> BuiltinFn<'a> {
 
         Ok(Value::Bool(!value))
     }
-}
-
-impl<'a> PartialEq for BuiltinFn<'a> {
-    fn eq(&self, other: &Self) -> bool {
-        self.name == other.name
-    }
-}
 
-impl<'a> Display for BuiltinFn<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let name = &self.name;
-        let args: Vec<String> = self
-            .args
-            .iter()
-            .map(|arg| {
-                let prefix: &str = if arg.variadic { "..." } else { "" };
+    /// Returns [`Value::Bool`] if two [`Value::String`] are equal, taking the
+    /// same time for any strings of the same length
+    ///
+    /// Use this instead of [`BuiltinFn::EQ`] to check secrets, e.g. webhook
+    /// signatures, so how long the comparison takes doesn't leak how much of
+    /// the secret matched. It isn't [`pure`](BuiltinFn::pure) so secrets
+    /// aren't memoized.
+    ///
+    /// `(secure_eq !provided_sig :computed_sig)`
+    pub const SECURE_EQ: BuiltinFn<'static> = BuiltinFn {
+        name: "secure_eq",
+        args: &[
+            FnArg {
+                name: "a",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "b",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Bool,
+        func: Self::secure_eq,
+        namespace: FnNamespace::Builtin,
+        pure: false,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
 
-                format!("{prefix}{}: {}", arg.name, arg.ty.name())
-            })
-            .collect();
+    fn secure_eq(args: Vec<Value>) -> ExprResult<Value> {
+        let first_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_string()?;
+        let second_arg = args
+            .get(1)
+            .expect("should have second expression passed")
+            .get_string()?;
 
-        let args: String = args.join(", ");
+        if first_arg.len() != second_arg.len() {
+            return Ok(Value::Bool(false));
+        }
 
-        let return_type: String = self.return_type.name().to_string();
+        let difference = first_arg
+            .bytes()
+            .zip(second_arg.bytes())
+            .fold(0u8, |difference, (a, b)| difference | (a ^ b));
 
-        write!(f, "{name}({args}) -> {return_type}")
+        Ok(Value::Bool(std::hint::black_box(difference) == 0))
     }
-}
-
-impl<'a> fmt::Debug for BuiltinFn<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let name = &self.name;
-        let args: Vec<String> = self
-            .args
-            .iter()
-            .map(|arg| {
-                let prefix: &str = if arg.variadic { "..." } else { "" };
 
-                format!("{prefix}{}: {}", arg.name, arg.ty.name())
-            })
-            .collect();
+    /// Returns `true` if the condition is `true`, otherwise fails with
+    /// [`RuntimeError::AssertionFailed`]
+    ///
+    /// `` (assert (contains `json` @content_type) `expected a JSON response`) ``
+    pub const ASSERT: BuiltinFn<'static> = BuiltinFn {
+        name: "assert",
+        args: &[
+            FnArg {
+                name: "condition",
+                ty: Type::Bool,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "message",
+                ty: Type::String,
+                variadic: false,
+                optional: true,
+            },
+        ],
+        return_type: Type::Bool,
+        func: Self::assert,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
+    };
 
-        let args: String = args.join(", ");
+    fn assert(args: Vec<Value>) -> ExprResult<Value> {
+        let condition = args
+            .first()
+            .expect("should have condition expression passed")
+            .get_bool()?;
 
-        let return_type: String = self.return_type.name().to_string();
+        if condition {
+            return Ok(Value::Bool(true));
+        }
 
-        write!(f, "{name}({args}) -> {return_type}")
+        Err(vec![(
+            RuntimeError::AssertionFailed {
+                message: Self::assertion_message(args.get(1), "condition is false")?,
+                left: None,
+                right: None,
+            }
+            .into(),
+            0..0,
+        )])
     }
-}
 
-#[derive(Debug, PartialEq)]
-pub enum FnArity {
-    N(u8),
-    Variadic { n: u8 },
-}
+    /// Returns the first [`Value`] if it's equal to the second, otherwise
+    /// fails with [`RuntimeError::AssertionFailed`] reporting both
+    ///
+    /// `` (assert_eq @status `200` `expected a successful response`) ``
+    pub const ASSERT_EQ: BuiltinFn<'static> = BuiltinFn {
+        name: "assert_eq",
+        args: &[
+            FnArg {
+                name: "left",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "right",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "message",
+                ty: Type::String,
+                variadic: false,
+                optional: true,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::assert_eq,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
+    };
 
-#[cfg(test)]
-mod value_tests {
-    use super::*;
+    fn assert_eq(args: Vec<Value>) -> ExprResult<Value> {
+        let left = args.first().expect("should have left expression passed");
+        let right = args.get(1).expect("should have right expression passed");
 
-    fn example_builtin(_args: Vec<Value>) -> ExprResult<Value> {
-        Ok(Value::String("".to_string()))
-    }
+        if left == right {
+            return Ok(left.clone());
+        }
 
-    #[test]
-    fn test_builtins_display_var_arity() {
-        let f = BuiltinFn {
-            name: "test_builtin",
-            args: &[FnArg::new_varadic("rest", Type::String)],
-            return_type: Type::String,
-            func: example_builtin,
-        };
-        assert_eq!("test_builtin(...rest: String) -> String", format!("{f}"))
+        Err(vec![(
+            RuntimeError::AssertionFailed {
+                message: Self::assertion_message(args.get(2), "values aren't equal")?,
+                left: Some(left.clone()),
+                right: Some(right.clone()),
+            }
+            .into(),
+            0..0,
+        )])
     }
 
-    #[test]
-    fn test_builtins_display_0_arity() {
-        assert_eq!(
-            "test_builtin() -> String",
-            format!(
-                "{}",
-                BuiltinFn {
-                    name: "test_builtin",
-                    args: &[],
-                    return_type: Type::String,
-                    func: example_builtin
-                }
-            )
-        )
-    }
+    /// Returns the first [`Value`], or the fallback if evaluating it fails
+    ///
+    /// Calls are compiled so the fallback is only evaluated if evaluating the
+    /// first value fails with a runtime error. When `try` is called as a
+    /// value its args are evaluated before the call, so it can't catch their
+    /// errors and returns the first.
+    ///
+    /// `` (try (assert_eq ?region `us`) `eu`) ``
+    pub const TRY: BuiltinFn<'static> = BuiltinFn {
+        name: "try",
+        args: &[
+            FnArg {
+                name: "expr",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "fallback",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::try_fallback,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
+    };
 
-    #[test]
+    fn try_fallback(args: Vec<Value>) -> ExprResult<Value> {
+        Ok(args
+            .first()
+            .cloned()
+            .expect("should have expr expression passed"))
+    }
+
+    /// The message an assertion fails with, `default` if one isn't passed
+    fn assertion_message(message: Option<&Value>, default: &str) -> ExprResult<String> {
+        match message {
+            Some(message) => Ok(message.get_string()?.to_string()),
+            None => Ok(default.to_string()),
+        }
+    }
+
+    /// Returns [`Value::Bool`] if two [`Value::String`] are equal ignoring case
+    ///
+    /// `` (eq_ignore_case `application/json` `Application/JSON`) ``
+    pub const EQ_IGNORE_CASE: BuiltinFn<'static> = BuiltinFn {
+        name: "eq_ignore_case",
+        args: &[
+            FnArg {
+                name: "a",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "b",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Bool,
+        func: Self::eq_ignore_case,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn eq_ignore_case(args: Vec<Value>) -> ExprResult<Value> {
+        let first_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_string()?;
+        let second_arg = args
+            .get(1)
+            .expect("should have second expression passed")
+            .get_string()?;
+
+        Ok(Value::Bool(
+            first_arg.to_lowercase() == second_arg.to_lowercase(),
+        ))
+    }
+
+    /// Returns [`Value::String`] converted to `snake_case`
+    ///
+    /// `` (snake_case `userId`) ``
+    pub const SNAKE_CASE: BuiltinFn<'static> = BuiltinFn {
+        name: "snake_case",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::snake_case,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn snake_case(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let words: Vec<String> = Self::words(string_arg)
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect();
+
+        Ok(Value::String(words.join("_").into()))
+    }
+
+    /// Returns [`Value::String`] converted to `camelCase`
+    ///
+    /// `` (camel_case `user_id`) ``
+    pub const CAMEL_CASE: BuiltinFn<'static> = BuiltinFn {
+        name: "camel_case",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::camel_case,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn camel_case(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let words: Vec<String> = Self::words(string_arg)
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                if i == 0 {
+                    word.to_lowercase()
+                } else {
+                    Self::capitalize(word)
+                }
+            })
+            .collect();
+
+        Ok(Value::String(words.concat().into()))
+    }
+
+    /// Returns [`Value::String`] converted to `kebab-case`
+    ///
+    /// `` (kebab_case `userId`) ``
+    pub const KEBAB_CASE: BuiltinFn<'static> = BuiltinFn {
+        name: "kebab_case",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::kebab_case,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn kebab_case(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let words: Vec<String> = Self::words(string_arg)
+            .iter()
+            .map(|word| word.to_lowercase())
+            .collect();
+
+        Ok(Value::String(words.join("-").into()))
+    }
+
+    /// Returns [`Value::String`] converted to `Title Case`
+    ///
+    /// `` (title_case `user_id`) ``
+    pub const TITLE_CASE: BuiltinFn<'static> = BuiltinFn {
+        name: "title_case",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::title_case,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn title_case(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let words: Vec<String> = Self::words(string_arg)
+            .iter()
+            .map(|word| Self::capitalize(word))
+            .collect();
+
+        Ok(Value::String(words.join(" ").into()))
+    }
+
+    /// Returns [`Value::String`] truncated to at most `length` characters
+    ///
+    /// Passing `true` for `ellipsis` ends truncated strings with `…`, which
+    /// counts towards `length`.
+    ///
+    /// `` (truncate `Hello World` 5) `` or `` (truncate `Hello World` 5 true) ``
+    pub const TRUNCATE: BuiltinFn<'static> = BuiltinFn {
+        name: "truncate",
+        args: &[
+            FnArg {
+                name: "value",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "length",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "ellipsis",
+                ty: Type::Bool,
+                variadic: false,
+                optional: true,
+            },
+        ],
+        return_type: Type::String,
+        func: Self::truncate,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn truncate(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+        let length = Self::get_count(
+            "length",
+            args.get(1).expect("should have length expression passed"),
+        )?;
+
+        let ellipsis = match args.get(2) {
+            Some(ellipsis) => ellipsis.get_bool()?,
+            None => false,
+        };
+
+        if string_arg.chars().count() <= length {
+            return Ok(Value::String(string_arg.into()));
+        }
+
+        let truncated: String = if ellipsis && length > 0 {
+            string_arg
+                .chars()
+                .take(length - 1)
+                .chain(std::iter::once('…'))
+                .collect()
+        } else {
+            string_arg.chars().take(length).collect()
+        };
+
+        Ok(Value::String(truncated.into()))
+    }
+
+    /// Returns [`Value::Number`] of non overlapping occurrences of `needle` [`Value::String`] in `haystack` [`Value::String`]
+    ///
+    /// An empty `needle` has no occurrences.
+    ///
+    /// `` (count_matches `o` `Hello World`) ``
+    pub const COUNT_MATCHES: BuiltinFn<'static> = BuiltinFn {
+        name: "count_matches",
+        args: &[
+            FnArg {
+                name: "needle",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "haystack",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Number,
+        func: Self::count_matches,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn count_matches(args: Vec<Value>) -> ExprResult<Value> {
+        let needle_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_string()?;
+        let haystack_arg = args
+            .get(1)
+            .expect("should have second expression passed")
+            .get_string()?;
+
+        if needle_arg.is_empty() {
+            return Ok(Value::Number(0.0));
+        }
+
+        Ok(Value::Number(
+            haystack_arg.matches(needle_arg).count() as f64
+        ))
+    }
+
+    /// Returns the zero indexed `n`th line of [`Value::String`]
+    ///
+    /// Lines are split on `\n` and `\r\n`. Line endings aren't included.
+    ///
+    /// `` (line :body 0) ``
+    pub const LINE: BuiltinFn<'static> = BuiltinFn {
+        name: "line",
+        args: &[
+            FnArg {
+                name: "value",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "n",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::String,
+        func: Self::line,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn line(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+        let n = Self::get_count("n", args.get(1).expect("should have n expression passed"))?;
+
+        match string_arg.lines().nth(n) {
+            Some(line) => Ok(Value::String(line.into())),
+            None => Err(vec![(
+                RuntimeError::InvalidArgument {
+                    name: "n".to_string(),
+                    message: format!(
+                        "line {n} is out of range for {} lines",
+                        string_arg.lines().count()
+                    ),
+                }
+                .into(),
+                0..0,
+            )]),
+        }
+    }
+
+    /// Returns [`Value::String`] with `&`, `<`, `>`, `"`, and `'` escaped as
+    /// HTML/XML entities
+    ///
+    /// `` (html_escape `<b>Tom & Jerry</b>`) ``
+    pub const HTML_ESCAPE: BuiltinFn<'static> = BuiltinFn {
+        name: "html_escape",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::html_escape,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn html_escape(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let mut escaped = String::with_capacity(string_arg.len());
+
+        for c in string_arg.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#39;"),
+                c => escaped.push(c),
+            }
+        }
+
+        Ok(Value::String(escaped.into()))
+    }
+
+    /// Returns [`Value::String`] with HTML/XML entities unescaped
+    ///
+    /// Supports `&amp;`, `&lt;`, `&gt;`, `&quot;`, `&apos;`, and numeric
+    /// entities (`&#39;`, `&#x27;`). Unrecognized entities are left as is.
+    ///
+    /// `` (html_unescape `Tom &amp; Jerry`) ``
+    pub const HTML_UNESCAPE: BuiltinFn<'static> = BuiltinFn {
+        name: "html_unescape",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::html_unescape,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn html_unescape(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let mut unescaped = String::with_capacity(string_arg.len());
+        let mut rest = string_arg;
+
+        while let Some(start) = rest.find('&') {
+            unescaped.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let entity = rest
+                .find(';')
+                .and_then(|end| Some((Self::html_entity(&rest[1..end])?, end)));
+
+            match entity {
+                Some((c, end)) => {
+                    unescaped.push(c);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    unescaped.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        unescaped.push_str(rest);
+
+        Ok(Value::String(unescaped.into()))
+    }
+
+    /// The character for an entity's name e.g. `amp` or `#x27`
+    fn html_entity(name: &str) -> Option<char> {
+        match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = name.strip_prefix('#')?;
+
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse::<u32>().ok()?,
+                };
+
+                char::from_u32(code)
+            }
+        }
+    }
+
+    /// Returns [`Value::Bool`] if version `a` [`Value::String`] is greater than
+    /// or equal to version `b` [`Value::String`]
+    ///
+    /// Versions are compared using [semver](https://semver.org) precedence. A
+    /// leading `v` is allowed, missing minor and patch versions are `0`, and
+    /// build metadata is ignored.
+    ///
+    /// `` (semver_gte @api_version `2.3.0`) ``
+    pub const SEMVER_GTE: BuiltinFn<'static> = BuiltinFn {
+        name: "semver_gte",
+        args: &[
+            FnArg {
+                name: "a",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "b",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Bool,
+        func: Self::semver_gte,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn semver_gte(args: Vec<Value>) -> ExprResult<Value> {
+        let first_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_string()?;
+        let second_arg = args
+            .get(1)
+            .expect("should have second expression passed")
+            .get_string()?;
+
+        let a = Self::semver("a", first_arg)?;
+        let b = Self::semver("b", second_arg)?;
+
+        Ok(Value::Bool(Self::semver_cmp(&a, &b).is_ge()))
+    }
+
+    /// Parse a version in to its numeric components and pre-release identifiers
+    fn semver<'v>(name: &str, version: &'v str) -> ExprResult<([u64; 3], Vec<&'v str>)> {
+        let invalid = || {
+            vec![(
+                RuntimeError::InvalidArgument {
+                    name: name.to_string(),
+                    message: format!("invalid semver version `{version}`"),
+                }
+                .into(),
+                0..0,
+            )]
+        };
+
+        let trimmed = version.trim();
+        let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+        let trimmed = trimmed.split_once('+').map_or(trimmed, |(v, _build)| v);
+
+        let (core, pre) = match trimmed.split_once('-') {
+            Some((core, pre)) => (core, pre.split('.').collect()),
+            None => (trimmed, vec![]),
+        };
+
+        if pre.iter().any(|identifier: &&str| identifier.is_empty()) {
+            return Err(invalid());
+        }
+
+        let mut numbers = [0; 3];
+        let parts: Vec<&str> = core.split('.').collect();
+
+        if parts.len() > 3 {
+            return Err(invalid());
+        }
+
+        for (i, part) in parts.iter().enumerate() {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+
+            numbers[i] = part.parse().map_err(|_| invalid())?;
+        }
+
+        Ok((numbers, pre))
+    }
+
+    /// Compare versions by semver precedence
+    fn semver_cmp(
+        (a_numbers, a_pre): &([u64; 3], Vec<&str>),
+        (b_numbers, b_pre): &([u64; 3], Vec<&str>),
+    ) -> std::cmp::Ordering {
+        use std::cmp::Ordering;
+
+        a_numbers.cmp(b_numbers).then_with(|| {
+            match (a_pre.is_empty(), b_pre.is_empty()) {
+                (true, true) => return Ordering::Equal,
+                // Pre-release versions have lower precedence than releases
+                (true, false) => return Ordering::Greater,
+                (false, true) => return Ordering::Less,
+                (false, false) => {}
+            }
+
+            for (a, b) in a_pre.iter().zip(b_pre.iter()) {
+                let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    // Numeric identifiers have lower precedence than alphanumeric
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => a.cmp(b),
+                };
+
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+
+            a_pre.len().cmp(&b_pre.len())
+        })
+    }
+
+    /// Returns the CRC-32 (IEEE) checksum of [`Value::String`] as 8 lowercase
+    /// hex digits
+    ///
+    /// `` (crc32 `Hello World`) ``
+    pub const CRC32: BuiltinFn<'static> = BuiltinFn {
+        name: "crc32",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::crc32,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Checksum,
+        capability: None,
+    };
+
+    fn crc32(args: Vec<Value>) -> ExprResult<Value> {
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let mut crc = !0u32;
+
+        for byte in string_arg.bytes() {
+            crc ^= byte as u32;
+
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+
+        Ok(Value::String(format!("{:08x}", !crc).into()))
+    }
+
+    /// Returns the Adler-32 checksum of [`Value::String`] as 8 lowercase hex
+    /// digits
+    ///
+    /// `` (adler32 `Hello World`) ``
+    pub const ADLER32: BuiltinFn<'static> = BuiltinFn {
+        name: "adler32",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::adler32,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Checksum,
+        capability: None,
+    };
+
+    fn adler32(args: Vec<Value>) -> ExprResult<Value> {
+        const MOD_ADLER: u32 = 65521;
+
+        let string_arg = args
+            .first()
+            .expect("should have string expression passed")
+            .get_string()?;
+
+        let (mut a, mut b) = (1u32, 0u32);
+
+        for byte in string_arg.bytes() {
+            a = (a + byte as u32) % MOD_ADLER;
+            b = (b + a) % MOD_ADLER;
+        }
+
+        Ok(Value::String(format!("{:08x}", (b << 16) | a).into()))
+    }
+
+    /// Returns [`Value::Bool`] if two [`Value`] are not equal
+    ///
+    /// `(neq :a :b)`
+    pub const NEQ: BuiltinFn<'static> = BuiltinFn {
+        name: "neq",
+        args: &[
+            FnArg {
+                name: "a",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "b",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Bool,
+        func: Self::neq,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Value,
+        capability: None,
+    };
+
+    fn neq(args: Vec<Value>) -> ExprResult<Value> {
+        let first_arg = args.first().expect("should have first expression passed");
+        let second_arg = args.get(1).expect("should have second expression passed");
+
+        Ok((first_arg != second_arg).into())
+    }
+
+    /// Return [`Type::Bool`] if exactly one of args [`Value::Bool`] is `true`
+    ///
+    /// `(xor true false)`
+    pub const XOR: BuiltinFn<'static> = BuiltinFn {
+        name: "xor",
+        args: &[
+            FnArg {
+                name: "a",
+                ty: Type::Bool,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "b",
+                ty: Type::Bool,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Bool,
+        func: Self::xor,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Bool,
+        capability: None,
+    };
+
+    fn xor(args: Vec<Value>) -> ExprResult<Value> {
+        let a_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_bool()?;
+        let b_arg = args
+            .get(1)
+            .expect("should have second expression passed")
+            .get_bool()?;
+
+        Ok(Value::Bool(a_arg != b_arg))
+    }
+
+    /// Return [`Type::Bool`] `false` only if `a` [`Value::Bool`] is `true` and
+    /// `b` [`Value::Bool`] is `false`
+    ///
+    /// `(implies true false)`
+    pub const IMPLIES: BuiltinFn<'static> = BuiltinFn {
+        name: "implies",
+        args: &[
+            FnArg {
+                name: "a",
+                ty: Type::Bool,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "b",
+                ty: Type::Bool,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Bool,
+        func: Self::implies,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Bool,
+        capability: None,
+    };
+
+    fn implies(args: Vec<Value>) -> ExprResult<Value> {
+        let a_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_bool()?;
+        let b_arg = args
+            .get(1)
+            .expect("should have second expression passed")
+            .get_bool()?;
+
+        Ok(Value::Bool(!a_arg || b_arg))
+    }
+
+    /// Parse a [`Value::String`] as a [`Value::Bool`]
+    ///
+    /// Accepts `true`/`false`, `yes`/`no`, and `1`/`0` ignoring case and
+    /// surrounding whitespace, e.g. to use a prompt with `not` or `and`.
+    ///
+    /// `(parse_bool ?confirm)`
+    pub const PARSE_BOOL: BuiltinFn<'static> = BuiltinFn {
+        name: "parse_bool",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Bool,
+        func: Self::parse_bool,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Bool,
+        capability: None,
+    };
+
+    fn parse_bool(args: Vec<Value>) -> ExprResult<Value> {
+        let value_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_string()?;
+
+        match value_arg.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok(Value::Bool(true)),
+            "false" | "no" | "0" => Ok(Value::Bool(false)),
+            _ => Err(vec![(
+                RuntimeError::InvalidArgument {
+                    name: "value".to_string(),
+                    message: format!(
+                        "expected true, false, yes, no, 1, or 0 but received `{value_arg}`"
+                    ),
+                }
+                .into(),
+                0..0,
+            )]),
+        }
+    }
+
+    /// Compare two [`Value::String`] returning a [`Value::Number`] of `-1`,
+    /// `0`, or `1`
+    ///
+    /// Strings are compared by Unicode code point so the order doesn't depend
+    /// on the locale.
+    ///
+    /// `(cmp :a :b)`
+    pub const CMP: BuiltinFn<'static> = BuiltinFn {
+        name: "cmp",
+        args: &[
+            FnArg {
+                name: "a",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "b",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Number,
+        func: Self::cmp,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn cmp(args: Vec<Value>) -> ExprResult<Value> {
+        let a_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_string()?;
+        let b_arg = args
+            .get(1)
+            .expect("should have second expression passed")
+            .get_string()?;
+
+        Ok(Value::Number(a_arg.cmp(b_arg) as i8 as f64))
+    }
+
+    /// Returns a [`Value::String`] key that orders strings ignoring case
+    ///
+    /// Comparing keys with `cmp` orders strings case insensitively, then
+    /// case sensitively to break ties, independent of the locale.
+    ///
+    /// `(cmp (sort_key :a) (sort_key :b))`
+    pub const SORT_KEY: BuiltinFn<'static> = BuiltinFn {
+        name: "sort_key",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: Self::sort_key,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::String,
+        capability: None,
+    };
+
+    fn sort_key(args: Vec<Value>) -> ExprResult<Value> {
+        let value_arg = args
+            .first()
+            .expect("should have first expression passed")
+            .get_string()?;
+
+        // NUL sorts before every other character so a prefix of a string
+        // still sorts before it
+        Ok(Value::String(
+            format!("{}\0{value_arg}", value_arg.to_lowercase()).into(),
+        ))
+    }
+
+    /// Returns a [`Value::Closure`] calling `g` then passing its result to `f`
+    ///
+    /// `f` must accept a single argument. The closure accepts the arguments
+    /// `g` accepts and returns what `f` returns.
+    ///
+    /// `` ((compose uppercase trim) ` a `) ``
+    pub const COMPOSE: BuiltinFn<'static> = BuiltinFn {
+        name: "compose",
+        args: &[
+            FnArg {
+                name: "f",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "g",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::compose,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Function,
+        capability: None,
+    };
+
+    fn compose(args: Vec<Value>) -> ExprResult<Value> {
+        let f = args.first().expect("should have f expression passed");
+        let g = args.get(1).expect("should have g expression passed");
+
+        let (f_args, f_variadic_arg) = Self::get_fn_args(f)?;
+        Self::get_fn_args(g)?;
+
+        let accepts_one_arg = match f_args.len() {
+            0 => f_variadic_arg.is_some(),
+            1 => true,
+            _ => false,
+        };
+
+        if !accepts_one_arg {
+            return Err(vec![(
+                RuntimeError::InvalidArgument {
+                    name: "f".to_string(),
+                    message: format!(
+                        "expected a function accepting one argument but received {}",
+                        f.get_type()
+                    ),
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(Value::Closure(
+            Closure::Compose {
+                f: f.clone(),
+                g: g.clone(),
+            }
+            .into(),
+        ))
+    }
+
+    /// Returns a [`Value::Closure`] calling `f` with `arg` as its first
+    /// argument followed by the arguments the closure is called with
+    ///
+    /// `` ((partial concat `Bearer `) !token) ``
+    pub const PARTIAL: BuiltinFn<'static> = BuiltinFn {
+        name: "partial",
+        args: &[
+            FnArg {
+                name: "f",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "arg",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::partial,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Function,
+        capability: None,
+    };
+
+    fn partial(args: Vec<Value>) -> ExprResult<Value> {
+        let f = args.first().expect("should have f expression passed");
+        let arg = args.get(1).expect("should have arg expression passed");
+
+        let (f_args, f_variadic_arg) = Self::get_fn_args(f)?;
+
+        let first_arg_type = f_args.first().or(f_variadic_arg.as_deref());
+
+        match first_arg_type {
+            None => {
+                return Err(vec![(
+                    RuntimeError::InvalidArgument {
+                        name: "f".to_string(),
+                        message: format!(
+                            "expected a function accepting arguments but received {}",
+                            f.get_type()
+                        ),
+                    }
+                    .into(),
+                    0..0,
+                )]);
+            }
+            Some(ty) if *ty != Type::Value && *ty != arg.get_type() => {
+                return Err(vec![(
+                    RuntimeError::TypeMismatch {
+                        expected: ty.clone(),
+                        actual: arg.get_type(),
+                    }
+                    .into(),
+                    0..0,
+                )]);
+            }
+            Some(_) => {}
+        }
+
+        Ok(Value::Closure(
+            Closure::Partial {
+                f: f.clone(),
+                arg: arg.clone(),
+            }
+            .into(),
+        ))
+    }
+
+    /// Returns the number of items in a [`Value::List`]
+    ///
+    /// `` (len [`a` `b`]) ``
+    pub const LEN: BuiltinFn<'static> = BuiltinFn {
+        name: "len",
+        args: &[FnArg {
+            name: "list",
+            ty: Type::Value,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Number,
+        func: Self::len,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::List,
+        capability: None,
+    };
+
+    fn len(args: Vec<Value>) -> ExprResult<Value> {
+        let list = args
+            .first()
+            .expect("should have list expression passed")
+            .get_list()?;
+
+        Ok(Value::Number(list.len() as f64))
+    }
+
+    /// Returns the item at a zero based index in a [`Value::List`]
+    ///
+    /// `` (get [`a` `b`] 1) ``
+    pub const GET: BuiltinFn<'static> = BuiltinFn {
+        name: "get",
+        args: &[
+            FnArg {
+                name: "list",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "index",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::get,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::List,
+        capability: None,
+    };
+
+    fn get(args: Vec<Value>) -> ExprResult<Value> {
+        let list = args
+            .first()
+            .expect("should have list expression passed")
+            .get_list()?;
+        let index = Self::get_count(
+            "index",
+            args.get(1).expect("should have index expression passed"),
+        )?;
+
+        list.get(index).cloned().ok_or_else(|| {
+            vec![(
+                RuntimeError::InvalidArgument {
+                    name: "index".to_string(),
+                    message: format!(
+                        "index {index} is out of range for a list of {} items",
+                        list.len()
+                    ),
+                }
+                .into(),
+                0..0,
+            )]
+        })
+    }
+
+    /// Returns [`Value::String`] joining the results of calling `f` on each
+    /// item of a [`Value::List`] with `separator`
+    ///
+    /// `` (map_join [`a` `b`] uppercase `, `) ``
+    pub const MAP_JOIN: BuiltinFn<'static> = BuiltinFn {
+        name: "map_join",
+        args: &[
+            FnArg {
+                name: "list",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "f",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "separator",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::String,
+        func: Self::map_join,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::List,
+        capability: None,
+    };
+
+    fn map_join(args: Vec<Value>) -> ExprResult<Value> {
+        let list = args
+            .first()
+            .expect("should have list expression passed")
+            .get_list()?;
+        let f = args.get(1).expect("should have f expression passed");
+        let separator = args
+            .get(2)
+            .expect("should have separator expression passed")
+            .get_string()?;
+
+        Self::get_fn_args(f)?;
+
+        let mut strings = Vec::with_capacity(list.len());
+
+        for item in list {
+            check_timeout()?;
+
+            strings.push(match f.call(vec![item.clone()])? {
+                Value::String(string) => string,
+                value => value.to_string().into(),
+            });
+        }
+
+        Ok(Value::String(strings.join(separator).into()))
+    }
+
+    /// Returns a [`Value::List`] of the results of calling `f` on each item of
+    /// a [`Value::List`]
+    ///
+    /// `` (map uppercase [`a` `b`]) ``
+    pub const MAP: BuiltinFn<'static> = BuiltinFn {
+        name: "map",
+        args: &[
+            FnArg {
+                name: "f",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "list",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::map,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::List,
+        capability: None,
+    };
+
+    fn map(args: Vec<Value>) -> ExprResult<Value> {
+        let f = args.first().expect("should have f expression passed");
+        let list = args
+            .get(1)
+            .expect("should have list expression passed")
+            .get_list()?;
+
+        Self::get_fn_args(f)?;
+
+        let mut items = Vec::with_capacity(list.len());
+
+        for item in list {
+            check_timeout()?;
+
+            items.push(f.call(vec![item.clone()])?);
+        }
+
+        Ok(Value::List(items))
+    }
+
+    /// Returns a [`Value::List`] of the items of a [`Value::List`] that `f`
+    /// returns `true` for
+    ///
+    /// `` (filter is_empty [`a` ``]) ``
+    pub const FILTER: BuiltinFn<'static> = BuiltinFn {
+        name: "filter",
+        args: &[
+            FnArg {
+                name: "f",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "list",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::filter,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::List,
+        capability: None,
+    };
+
+    fn filter(args: Vec<Value>) -> ExprResult<Value> {
+        let f = args.first().expect("should have f expression passed");
+        let list = args
+            .get(1)
+            .expect("should have list expression passed")
+            .get_list()?;
+
+        Self::get_fn_args(f)?;
+
+        let mut items = vec![];
+
+        for item in list {
+            check_timeout()?;
+
+            if f.call(vec![item.clone()])?.get_bool()? {
+                items.push(item.clone());
+            }
+        }
+
+        Ok(Value::List(items))
+    }
+
+    /// Returns the result of calling `f` with the result so far, starting with
+    /// `initial`, and each item of a [`Value::List`]
+    ///
+    /// `` (reduce concat `` [`a` `b`]) ``
+    pub const REDUCE: BuiltinFn<'static> = BuiltinFn {
+        name: "reduce",
+        args: &[
+            FnArg {
+                name: "f",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "initial",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "list",
+                ty: Type::Value,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Value,
+        func: Self::reduce,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::List,
+        capability: None,
+    };
+
+    fn reduce(args: Vec<Value>) -> ExprResult<Value> {
+        let f = args.first().expect("should have f expression passed");
+        let initial = args.get(1).expect("should have initial expression passed");
+        let list = args
+            .get(2)
+            .expect("should have list expression passed")
+            .get_list()?;
+
+        Self::get_fn_args(f)?;
+
+        let mut result = initial.clone();
+
+        for item in list {
+            check_timeout()?;
+
+            result = f.call(vec![result, item.clone()])?;
+        }
+
+        Ok(result)
+    }
+
+    /// Returns [`Value::Number`] with its sign flipped
+    ///
+    /// There are no negative number literals so this is how they're written.
+    /// It's registered by default, unlike the math group, so negative numbers
+    /// always read back.
+    ///
+    /// `(negate 1.5)`
+    pub const NEGATE: BuiltinFn<'static> = BuiltinFn {
+        name: "negate",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::Number,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Number,
+        func: Self::negate,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: None,
+    };
+
+    fn negate(args: Vec<Value>) -> ExprResult<Value> {
+        let number_arg = args
+            .first()
+            .expect("should have number expression passed")
+            .get_number()?;
+
+        Ok(Value::Number(-number_arg))
+    }
+
+    /// Returns the absolute value of [`Value::Number`]
+    ///
+    /// `(abs :offset)`
+    pub const ABS: BuiltinFn<'static> = BuiltinFn {
+        name: "abs",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::Number,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Number,
+        func: Self::abs,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: Some(BuiltinGroup::Math),
+    };
+
+    fn abs(args: Vec<Value>) -> ExprResult<Value> {
+        let number_arg = args
+            .first()
+            .expect("should have number expression passed")
+            .get_number()?;
+
+        Ok(Value::Number(number_arg.abs()))
+    }
+
+    /// Returns [`Value::Number`] rounded down to a whole number
+    ///
+    /// `(floor 2.7)`
+    pub const FLOOR: BuiltinFn<'static> = BuiltinFn {
+        name: "floor",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::Number,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Number,
+        func: Self::floor,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: Some(BuiltinGroup::Math),
+    };
+
+    fn floor(args: Vec<Value>) -> ExprResult<Value> {
+        let number_arg = args
+            .first()
+            .expect("should have number expression passed")
+            .get_number()?;
+
+        Ok(Value::Number(number_arg.floor()))
+    }
+
+    /// Returns [`Value::Number`] rounded up to a whole number
+    ///
+    /// `(ceil 2.2)`
+    pub const CEIL: BuiltinFn<'static> = BuiltinFn {
+        name: "ceil",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::Number,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Number,
+        func: Self::ceil,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: Some(BuiltinGroup::Math),
+    };
+
+    fn ceil(args: Vec<Value>) -> ExprResult<Value> {
+        let number_arg = args
+            .first()
+            .expect("should have number expression passed")
+            .get_number()?;
+
+        Ok(Value::Number(number_arg.ceil()))
+    }
+
+    /// Returns [`Value::Number`] rounded to the nearest whole number
+    ///
+    /// Halfway values round away from zero.
+    ///
+    /// `(round 2.5)`
+    pub const ROUND: BuiltinFn<'static> = BuiltinFn {
+        name: "round",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::Number,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Number,
+        func: Self::round,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: Some(BuiltinGroup::Math),
+    };
+
+    fn round(args: Vec<Value>) -> ExprResult<Value> {
+        let number_arg = args
+            .first()
+            .expect("should have number expression passed")
+            .get_number()?;
+
+        Ok(Value::Number(number_arg.round()))
+    }
+
+    /// Returns `base` [`Value::Number`] raised to the power of `exponent`
+    /// [`Value::Number`]
+    ///
+    /// `(pow 2 10)`
+    pub const POW: BuiltinFn<'static> = BuiltinFn {
+        name: "pow",
+        args: &[
+            FnArg {
+                name: "base",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "exponent",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Number,
+        func: Self::pow,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: Some(BuiltinGroup::Math),
+    };
+
+    fn pow(args: Vec<Value>) -> ExprResult<Value> {
+        let base = args
+            .first()
+            .expect("should have base expression passed")
+            .get_number()?;
+        let exponent = args
+            .get(1)
+            .expect("should have exponent expression passed")
+            .get_number()?;
+
+        let result = base.powf(exponent);
+
+        if !result.is_finite() {
+            return Err(vec![(
+                RuntimeError::InvalidArgument {
+                    name: "exponent".to_string(),
+                    message: format!("{base} to the power of {exponent} is not a finite number"),
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(Value::Number(result))
+    }
+
+    /// Returns the square root of a non negative [`Value::Number`]
+    ///
+    /// `(sqrt 16)`
+    pub const SQRT: BuiltinFn<'static> = BuiltinFn {
+        name: "sqrt",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::Number,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::Number,
+        func: Self::sqrt,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: Some(BuiltinGroup::Math),
+    };
+
+    fn sqrt(args: Vec<Value>) -> ExprResult<Value> {
+        let number_arg = args
+            .first()
+            .expect("should have number expression passed")
+            .get_number()?;
+
+        if number_arg < 0.0 {
+            return Err(vec![(
+                RuntimeError::InvalidArgument {
+                    name: "value".to_string(),
+                    message: format!("expected a non negative number but received {number_arg}"),
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(Value::Number(number_arg.sqrt()))
+    }
+
+    /// Returns `value` [`Value::Number`] limited to the range `min` to `max`
+    ///
+    /// `(clamp ?page_size 1 100)`
+    pub const CLAMP: BuiltinFn<'static> = BuiltinFn {
+        name: "clamp",
+        args: &[
+            FnArg {
+                name: "value",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "min",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+            FnArg {
+                name: "max",
+                ty: Type::Number,
+                variadic: false,
+                optional: false,
+            },
+        ],
+        return_type: Type::Number,
+        func: Self::clamp,
+        namespace: FnNamespace::Builtin,
+        pure: true,
+        category: BuiltinCategory::Math,
+        capability: Some(BuiltinGroup::Math),
+    };
+
+    fn clamp(args: Vec<Value>) -> ExprResult<Value> {
+        let value = args
+            .first()
+            .expect("should have value expression passed")
+            .get_number()?;
+        let min = args
+            .get(1)
+            .expect("should have min expression passed")
+            .get_number()?;
+        let max = args
+            .get(2)
+            .expect("should have max expression passed")
+            .get_number()?;
+
+        if min > max {
+            return Err(vec![(
+                RuntimeError::InvalidArgument {
+                    name: "min".to_string(),
+                    message: format!("min {min} is greater than max {max}"),
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(Value::Number(value.clamp(min, max)))
+    }
+
+    /// Get the argument types of a [`Value::Fn`] or [`Value::Closure`]
+    /// argument
+    fn get_fn_args(value: &Value) -> ExprResult<(Vec<Type>, Option<Box<Type>>)> {
+        match value.get_type() {
+            Type::Fn {
+                args, variadic_arg, ..
+            } => Ok((args, variadic_arg)),
+            actual => Err(vec![(
+                RuntimeError::TypeMismatch {
+                    expected: Type::Fn {
+                        args: vec![],
+                        variadic_arg: Some(Type::Value.into()),
+                        returns: Type::Value.into(),
+                    },
+                    actual,
+                }
+                .into(),
+                0..0,
+            )]),
+        }
+    }
+
+    /// Get a [`Value::Number`] argument that must be a non negative whole number
+    fn get_count(name: &str, value: &Value) -> ExprResult<usize> {
+        let number = value.get_number()?;
+
+        if number < 0.0 || number.fract() != 0.0 {
+            return Err(vec![(
+                RuntimeError::InvalidArgument {
+                    name: name.to_string(),
+                    message: format!("expected a non negative whole number but received {number}"),
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(number as usize)
+    }
+
+    /// Split a string in to words for case conversion
+    ///
+    /// Words are separated by non alphanumeric characters and case changes:
+    /// `XMLHttpRequest2_id` => `XML`, `Http`, `Request2`, `id`
+    fn words(value: &str) -> Vec<&str> {
+        let chars: Vec<(usize, char)> = value.char_indices().collect();
+        let mut words = vec![];
+        let mut start: Option<usize> = None;
+
+        for (i, &(index, c)) in chars.iter().enumerate() {
+            if !c.is_alphanumeric() {
+                if let Some(word_start) = start.take() {
+                    words.push(&value[word_start..index]);
+                }
+
+                continue;
+            }
+
+            let prev = i.checked_sub(1).map(|prev| chars[prev].1);
+            let next = chars.get(i + 1).map(|&(_, next)| next);
+
+            let is_boundary = c.is_uppercase()
+                && prev.is_some_and(|prev| {
+                    prev.is_lowercase()
+                        || prev.is_numeric()
+                        || (prev.is_uppercase() && next.is_some_and(char::is_lowercase))
+                });
+
+            match start {
+                Some(word_start) if is_boundary => {
+                    words.push(&value[word_start..index]);
+                    start = Some(index);
+                }
+                Some(_) => {}
+                None => start = Some(index),
+            }
+        }
+
+        if let Some(word_start) = start {
+            words.push(&value[word_start..]);
+        }
+
+        words
+    }
+
+    /// Uppercase the first character of a word and lowercase the rest
+    fn capitalize(word: &str) -> String {
+        let mut chars = word.chars();
+
+        match chars.next() {
+            Some(first) => first
+                .to_uppercase()
+                .chain(chars.flat_map(char::to_lowercase))
+                .collect(),
+            None => String::new(),
+        }
+    }
+}
+
+/// Functions are equal when they have the same name and namespace and run
+/// the same Rust function
+impl<'a> PartialEq for BuiltinFn<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.namespace == other.namespace
+            && std::ptr::fn_addr_eq(self.func, other.func)
+    }
+}
+
+impl<'a> Eq for BuiltinFn<'a> {}
+
+impl<'a> Hash for BuiltinFn<'a> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.namespace.hash(state);
+        (self.func as usize).hash(state);
+    }
+}
+
+impl<'a> Display for BuiltinFn<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = &self.name;
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| {
+                let prefix: &str = if arg.variadic { "..." } else { "" };
+                let suffix: &str = if arg.optional { "?" } else { "" };
+
+                format!("{prefix}{}{suffix}: {}", arg.name, arg.ty.name())
+            })
+            .collect();
+
+        let args: String = args.join(", ");
+
+        let return_type: String = self.return_type.name().to_string();
+
+        write!(f, "{name}({args}) -> {return_type}")
+    }
+}
+
+impl<'a> fmt::Debug for BuiltinFn<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = &self.name;
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| {
+                let prefix: &str = if arg.variadic { "..." } else { "" };
+                let suffix: &str = if arg.optional { "?" } else { "" };
+
+                format!("{prefix}{}{suffix}: {}", arg.name, arg.ty.name())
+            })
+            .collect();
+
+        let args: String = args.join(", ");
+
+        let return_type: String = self.return_type.name().to_string();
+
+        write!(f, "{name}({args}) -> {return_type}")
+    }
+}
+
+/// Optional group of builtins registered in the default
+/// [`crate::compiler::CompileTimeEnv`]
+///
+/// Hosts can disable a group with
+/// [`crate::compiler::CompileTimeEnv::disable_builtin_group`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinGroup {
+    /// `abs`, `floor`, `ceil`, `round`, `pow`, `sqrt`, and `clamp`
+    Math,
+    /// `now`, `add_duration`, `diff_seconds`, and `to_timezone`
+    #[cfg(feature = "time")]
+    Time,
+    /// `same_json` and `ctx_json`
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl BuiltinGroup {
+    /// Every group enabled by features
+    pub const ALL: &'static [BuiltinGroup] = &[
+        BuiltinGroup::Math,
+        #[cfg(feature = "time")]
+        BuiltinGroup::Time,
+        #[cfg(feature = "json")]
+        BuiltinGroup::Json,
+    ];
+
+    /// Builtins in the group
+    pub fn builtins(&self) -> &'static [BuiltinFn<'static>] {
+        match self {
+            BuiltinGroup::Math => &BuiltinFn::MATH_BUILTINS,
+            #[cfg(feature = "time")]
+            BuiltinGroup::Time => &crate::time::TIME_BUILTINS,
+            #[cfg(feature = "json")]
+            BuiltinGroup::Json => &crate::json::JSON_BUILTINS,
+        }
+    }
+}
+
+/// What kind of values a builtin works with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinCategory {
+    Value,
+    String,
+    Bool,
+    Math,
+    Checksum,
+    Function,
+    Time,
+    Json,
+    List,
+}
+
+impl BuiltinCategory {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinCategory::Value => "Value",
+            BuiltinCategory::String => "String",
+            BuiltinCategory::Bool => "Bool",
+            BuiltinCategory::Math => "Math",
+            BuiltinCategory::Checksum => "Checksum",
+            BuiltinCategory::Function => "Function",
+            BuiltinCategory::Time => "Time",
+            BuiltinCategory::Json => "Json",
+            BuiltinCategory::List => "List",
+        }
+    }
+}
+
+impl Display for BuiltinCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FnArity {
+    N(u8),
+    Variadic { n: u8 },
+}
+
+#[cfg(test)]
+mod value_tests {
+    use super::*;
+
+    #[test]
+    fn math_negative_numbers() {
+        assert_eq!(
+            Ok(Value::Number(2.5)),
+            BuiltinFn::abs(vec![Value::Number(-2.5)])
+        );
+        assert_eq!(
+            Ok(Value::Number(-3.0)),
+            BuiltinFn::round(vec![Value::Number(-2.5)])
+        );
+        assert_eq!(
+            Ok(Value::Number(-3.0)),
+            BuiltinFn::floor(vec![Value::Number(-2.5)])
+        );
+        assert!(BuiltinFn::sqrt(vec![Value::Number(-1.0)]).is_err());
+        assert!(BuiltinFn::pow(vec![Value::Number(-8.0), Value::Number(0.5)]).is_err());
+    }
+
+    #[test]
+    fn eq_compares_function_identity() {
+        let user_id = BuiltinFn {
+            name: "id",
+            namespace: FnNamespace::User,
+            pure: false,
+            ..BuiltinFn::ID
+        };
+        let other_id = BuiltinFn {
+            func: |_| Ok(Value::Bool(true)),
+            ..BuiltinFn::ID
+        };
+
+        let eq = |a: &BuiltinFn<'static>, b: &BuiltinFn<'static>| {
+            BuiltinFn::eq(vec![
+                Value::Fn(a.clone().into()),
+                Value::Fn(b.clone().into()),
+            ])
+        };
+
+        assert_eq!(Ok(Value::Bool(true)), eq(&BuiltinFn::ID, &BuiltinFn::ID));
+        assert_eq!(Ok(Value::Bool(false)), eq(&BuiltinFn::ID, &user_id));
+        assert_eq!(Ok(Value::Bool(false)), eq(&BuiltinFn::ID, &other_id));
+    }
+
+    #[test]
+    fn semver_precedence() {
+        let versions = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1",
+            "1.1",
+            "v2.0.0+build.5",
+            "10.0.0",
+        ];
+
+        for pair in versions.windows(2) {
+            let a = BuiltinFn::semver("a", pair[0]).unwrap();
+            let b = BuiltinFn::semver("b", pair[1]).unwrap();
+
+            assert!(BuiltinFn::semver_cmp(&a, &b).is_lt(), "{pair:?}");
+        }
+    }
+
+    #[test]
+    fn semver_invalid() {
+        for version in ["", "a.b.c", "1..0", "1.0.0.0", "1.0.0-", "1.0.0-a..b"] {
+            assert!(BuiltinFn::semver("a", version).is_err(), "{version}");
+        }
+    }
+
+    #[test]
+    fn words_split_on_separators_and_case_changes() {
+        assert_eq!(
+            vec!["XML", "Http", "Request2", "id"],
+            BuiltinFn::words("XMLHttpRequest2_id")
+        );
+        assert_eq!(vec!["user", "id"], BuiltinFn::words("  user--id "));
+        assert_eq!(vec!["Content", "Type"], BuiltinFn::words("Content-Type"));
+        assert!(BuiltinFn::words("_-_").is_empty());
+    }
+
+    fn example_builtin(_args: Vec<Value>) -> ExprResult<Value> {
+        Ok(Value::String("".into()))
+    }
+
+    #[test]
+    fn test_builtins_display_var_arity() {
+        let f = BuiltinFn {
+            name: "test_builtin",
+            args: &[FnArg::new_varadic("rest", Type::String)],
+            return_type: Type::String,
+            func: example_builtin,
+            namespace: FnNamespace::User,
+            pure: false,
+            category: BuiltinCategory::Value,
+            capability: None,
+        };
+        assert_eq!("test_builtin(...rest: String) -> String", format!("{f}"))
+    }
+
+    #[test]
+    fn test_builtins_display_optional_arity() {
+        let f = BuiltinFn {
+            name: "test_builtin",
+            args: &[
+                FnArg::new("value", Type::String),
+                FnArg::new_optional("chars", Type::String),
+            ],
+            return_type: Type::String,
+            func: example_builtin,
+            namespace: FnNamespace::User,
+            pure: false,
+            category: BuiltinCategory::Value,
+            capability: None,
+        };
+
+        assert_eq!(
+            "test_builtin(value: String, chars?: String) -> String",
+            format!("{f}")
+        );
+        assert_eq!((1, 2), (f.arity(), f.max_arity()));
+        assert!(!f.arity_matches(0));
+        assert!(f.arity_matches(1));
+        assert!(f.arity_matches(2));
+        assert!(!f.arity_matches(3));
+    }
+
+    #[test]
+    fn test_builtins_display_0_arity() {
+        assert_eq!(
+            "test_builtin() -> String",
+            format!(
+                "{}",
+                BuiltinFn {
+                    name: "test_builtin",
+                    args: &[],
+                    return_type: Type::String,
+                    func: example_builtin,
+                    namespace: FnNamespace::User,
+                    pure: false,
+                    category: BuiltinCategory::Value,
+                    capability: None,
+                }
+            )
+        )
+    }
+
+    #[test]
     fn test_builtins_debug_0_arity() {
         assert_eq!(
             "test_builtin() -> String",
@@ -647,7 +3151,11 @@ mod value_tests {
                     name: "test_builtin",
                     args: &[],
                     return_type: Type::String,
-                    func: example_builtin
+                    func: example_builtin,
+                    namespace: FnNamespace::User,
+                    pure: false,
+                    category: BuiltinCategory::Value,
+                    capability: None,
                 }
             )
         )
@@ -663,7 +3171,11 @@ mod value_tests {
                     name: "test_builtin",
                     args: &[FnArg::new("value", Type::String)],
                     return_type: Type::String,
-                    func: example_builtin
+                    func: example_builtin,
+                    namespace: FnNamespace::User,
+                    pure: false,
+                    category: BuiltinCategory::Value,
+                    capability: None,
                 }
             )
         )
@@ -679,7 +3191,11 @@ mod value_tests {
                     name: "test_builtin",
                     args: &[FnArg::new("value", Type::String)],
                     return_type: Type::String,
-                    func: example_builtin
+                    func: example_builtin,
+                    namespace: FnNamespace::User,
+                    pure: false,
+                    category: BuiltinCategory::Value,
+                    capability: None,
                 }
             )
         )
@@ -695,7 +3211,11 @@ mod value_tests {
                     name: "test_builtin",
                     args: &[FnArg::new("a", Type::String), FnArg::new("b", Type::String)],
                     return_type: Type::String,
-                    func: example_builtin
+                    func: example_builtin,
+                    namespace: FnNamespace::User,
+                    pure: false,
+                    category: BuiltinCategory::Value,
+                    capability: None,
                 }
             )
         )
@@ -711,9 +3231,25 @@ mod value_tests {
                     name: "test_builtin",
                     args: &[FnArg::new("a", Type::String), FnArg::new("b", Type::String)],
                     return_type: Type::String,
-                    func: example_builtin
+                    func: example_builtin,
+                    namespace: FnNamespace::User,
+                    pure: false,
+                    category: BuiltinCategory::Value,
+                    capability: None,
                 }
             )
         )
     }
+
+    #[test]
+    fn timeout_deadline_restored_after_panic() {
+        let panicked = std::panic::catch_unwind(|| {
+            call_with_timeout("panics", Some(Duration::ZERO), || {
+                panic!("builtin panicked")
+            })
+        });
+
+        assert!(panicked.is_err());
+        assert_eq!(Ok(()), check_timeout());
+    }
 }
@@ -0,0 +1,198 @@
+//! Caching compiled bytecode between evaluations
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::{
+    compiler::{CompileTimeEnv, ExprByteCode, compile},
+    errors::ExprResult,
+    metrics::metrics,
+    parser::parse,
+};
+
+/// Memoizes `source + env fingerprint → ExprByteCode`
+///
+/// Clients evaluating the same expressions over and over (e.g. once per HTTP
+/// call in a collection run) can use this to skip lexing, parsing, and
+/// compiling after the first evaluation.
+///
+/// Entries are keyed by the source and [`CompileTimeEnv::fingerprint`].
+/// Sources that fail to compile aren't cached.
+///
+/// ```
+/// use reqlang_expr::prelude::*;
+///
+/// let env = CompileTimeEnv::default();
+/// let mut cache = CompilationCache::new();
+///
+/// let bytecode = cache.get_or_compile("(noop)", &env).unwrap().clone();
+///
+/// let mut vm = Vm::new();
+/// let value = vm.interpret(bytecode.into(), &env, &RuntimeEnv::default());
+///
+/// assert_eq!(Ok(Value::String("noop".into())), value);
+/// assert_eq!(1, cache.len());
+/// ```
+#[derive(Debug, Default)]
+pub struct CompilationCache {
+    entries: HashMap<CacheKey, ExprByteCode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    /// The source itself so sources with the same hash can't share bytecode
+    source: String,
+    env: u64,
+}
+
+impl CacheKey {
+    fn new(source: &str, env: &CompileTimeEnv) -> Self {
+        Self {
+            source: source.to_string(),
+            env: env.fingerprint(),
+        }
+    }
+}
+
+/// A hash of expression source code
+///
+/// Used as the `source_hash` field of `tracing` spans.
+pub fn source_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+
+    hasher.finish()
+}
+
+impl CompilationCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the cached bytecode for source, compiling it on a cache miss
+    pub fn get_or_compile(
+        &mut self,
+        source: &str,
+        env: &CompileTimeEnv,
+    ) -> ExprResult<&ExprByteCode> {
+        let entry = match self.entries.entry(CacheKey::new(source, env)) {
+            Entry::Occupied(entry) => {
+                metrics().cache_hit();
+
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                metrics().cache_miss();
+
+                let ast = parse(source)?;
+                let bytecode = compile(&mut (ast, 0..source.len()), env)?;
+
+                entry.insert(bytecode)
+            }
+        };
+
+        Ok(entry)
+    }
+
+    /// Get the cached bytecode for source if it's been compiled
+    pub fn get(&self, source: &str, env: &CompileTimeEnv) -> Option<&ExprByteCode> {
+        self.entries.get(&CacheKey::new(source, env))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    use crate::errors::{CompileError, ExprError};
+
+    #[test]
+    fn miss_then_hit() {
+        let env = CompileTimeEnv::default();
+        let mut cache = CompilationCache::new();
+
+        assert_eq!(None, cache.get("(noop)", &env));
+
+        let compiled = cache.get_or_compile("(noop)", &env).unwrap().clone();
+
+        assert_eq!(Some(&compiled), cache.get("(noop)", &env));
+        assert_eq!(1, cache.len());
+
+        cache.get_or_compile("(noop)", &env).unwrap();
+
+        assert_eq!(1, cache.len());
+    }
+
+    #[test]
+    fn keyed_by_source() {
+        let env = CompileTimeEnv::default();
+        let mut cache = CompilationCache::new();
+
+        let noop = cache.get_or_compile("(noop)", &env).unwrap().clone();
+        let id = cache.get_or_compile("(id `a`)", &env).unwrap().clone();
+
+        assert_ne!(noop, id);
+        assert_eq!(Some(&noop), cache.get("(noop)", &env));
+        assert_eq!(Some(&id), cache.get("(id `a`)", &env));
+        assert_eq!(2, cache.len());
+    }
+
+    #[test]
+    fn keyed_by_env_fingerprint() {
+        let env_a = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let env_b = CompileTimeEnv::new(
+            vec!["b".to_string(), "a".to_string()],
+            vec![],
+            vec![],
+            vec![],
+        );
+
+        let mut cache = CompilationCache::new();
+
+        let a = cache.get_or_compile(":a", &env_a).unwrap().clone();
+        let b = cache.get_or_compile(":a", &env_b).unwrap().clone();
+
+        assert_ne!(a, b);
+        assert_eq!(2, cache.len());
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let env = CompileTimeEnv::default();
+        let mut cache = CompilationCache::new();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::Undefined(":a".to_string())),
+                0..2
+            )]),
+            cache.get_or_compile(":a", &env)
+        );
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn clear() {
+        let env = CompileTimeEnv::default();
+        let mut cache = CompilationCache::new();
+
+        cache.get_or_compile("(noop)", &env).unwrap();
+        cache.clear();
+
+        assert!(cache.is_empty());
+    }
+}
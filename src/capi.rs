@@ -0,0 +1,375 @@
+//! C API enabled by the `capi` feature
+//!
+//! Build the shared library with:
+//!
+//! ```shell
+//! cargo rustc --release --lib --features capi --crate-type cdylib
+//! ```
+//!
+//! Declarations are in [reqlang_expr.h](../include/reqlang_expr.h).
+//! Functions returning a null pointer on failure set an error message
+//! readable with [`reqlang_expr_last_error`].
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char},
+    panic::{AssertUnwindSafe, catch_unwind},
+    ptr, slice,
+};
+
+use crate::{
+    compiler::{CompileTimeEnv, ExprByteCode, compile},
+    errors::ExprErrorS,
+    parser::parse,
+    value::Value,
+    vm::{RuntimeEnv, Vm},
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An expression compiled by [`reqlang_expr_compile`]
+///
+/// Opaque to C. Free with [`reqlang_expr_free`].
+pub struct ReqlangExpr {
+    env: CompileTimeEnv,
+    bytecode: ExprByteCode,
+}
+
+/// A list of `len` null terminated UTF-8 strings
+///
+/// `items` may be null when `len` is `0`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ReqlangExprStrings {
+    pub items: *const *const c_char,
+    pub len: usize,
+}
+
+/// Compile an expression against the names of the vars, prompts, secrets,
+/// and client context it can reference
+///
+/// Returns null on failure.
+///
+/// # Safety
+///
+/// `source` must be a valid null terminated string and every
+/// [`ReqlangExprStrings`] must point to `len` valid null terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn reqlang_expr_compile(
+    source: *const c_char,
+    vars: ReqlangExprStrings,
+    prompts: ReqlangExprStrings,
+    secrets: ReqlangExprStrings,
+    client_context: ReqlangExprStrings,
+) -> *mut ReqlangExpr {
+    let result = guard(|| {
+        let source = unsafe { read_string(source, "source") }?;
+
+        let env = CompileTimeEnv::new(
+            unsafe { read_strings(vars, "vars") }?,
+            unsafe { read_strings(prompts, "prompts") }?,
+            unsafe { read_strings(secrets, "secrets") }?,
+            unsafe { read_strings(client_context, "client_context") }?,
+        );
+
+        let ast = parse(&source).map_err(|errs| errors_message(&errs))?;
+        let bytecode =
+            compile(&mut (ast, 0..source.len()), &env).map_err(|errs| errors_message(&errs))?;
+
+        Ok(ReqlangExpr { env, bytecode })
+    });
+
+    match result {
+        Some(expr) => Box::into_raw(Box::new(expr)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Evaluate a compiled expression with the values of its vars, prompts,
+/// secrets, and client context
+///
+/// Values are given in the same order as the names passed to
+/// [`reqlang_expr_compile`]. Client context values are strings.
+///
+/// Returns the resulting value as a string, or null on failure. String values
+/// are returned as is and other values in their source form e.g. `true`. Free
+/// the result with [`reqlang_expr_string_free`].
+///
+/// # Safety
+///
+/// `expr` must be a pointer returned by [`reqlang_expr_compile`] that hasn't
+/// been freed and every [`ReqlangExprStrings`] must point to `len` valid null
+/// terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn reqlang_expr_eval(
+    expr: *const ReqlangExpr,
+    vars: ReqlangExprStrings,
+    prompts: ReqlangExprStrings,
+    secrets: ReqlangExprStrings,
+    client_context: ReqlangExprStrings,
+) -> *mut c_char {
+    let result = guard(|| {
+        let expr = unsafe { expr.as_ref() }.ok_or_else(|| "expr is null".to_string())?;
+
+        let runtime_env = RuntimeEnv {
+            vars: unsafe { read_strings(vars, "vars") }?,
+            prompts: unsafe { read_strings(prompts, "prompts") }?,
+            secrets: unsafe { read_strings(secrets, "secrets") }?,
+            client_context: unsafe { read_strings(client_context, "client_context") }?
+                .into_iter()
+                .map(Value::from)
+                .collect(),
+        };
+
+        let value = Vm::new()
+            .interpret(expr.bytecode.clone().into(), &expr.env, &runtime_env)
+            .map_err(|errs| errors_message(&errs))?;
+
+        let value = match value {
+            Value::String(string) => string.to_string(),
+            value => value.to_string(),
+        };
+
+        CString::new(value).map_err(|_| "value contains a null byte".to_string())
+    });
+
+    match result {
+        Some(value) => value.into_raw(),
+        None => ptr::null_mut(),
+    }
+}
+
+/// The error message from the last call on this thread that failed
+///
+/// Returns null if the last call succeeded. The message is valid until the
+/// next call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn reqlang_expr_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Free an expression returned by [`reqlang_expr_compile`]
+///
+/// # Safety
+///
+/// `expr` must be null or a pointer returned by [`reqlang_expr_compile`]
+/// that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn reqlang_expr_free(expr: *mut ReqlangExpr) {
+    if !expr.is_null() {
+        drop(unsafe { Box::from_raw(expr) });
+    }
+}
+
+/// Free a string returned by [`reqlang_expr_eval`]
+///
+/// # Safety
+///
+/// `string` must be null or a pointer returned by [`reqlang_expr_eval`]
+/// that hasn't been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn reqlang_expr_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+/// Run a call, recording its error (or panic) as the last error
+fn guard<T>(f: impl FnOnce() -> Result<T, String>) -> Option<T> {
+    let result = catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err("reqlang_expr panicked".to_string()));
+
+    LAST_ERROR.with(|last_error| {
+        *last_error.borrow_mut() = result.as_ref().err().map(|message| {
+            CString::new(message.replace('\0', "")).expect("null bytes should be removed")
+        });
+    });
+
+    result.ok()
+}
+
+fn errors_message(errs: &[ExprErrorS]) -> String {
+    errs.iter()
+        .map(|(err, span)| format!("{err} at {}..{}", span.start, span.end))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+unsafe fn read_string(string: *const c_char, name: &str) -> Result<String, String> {
+    if string.is_null() {
+        return Err(format!("{name} is null"));
+    }
+
+    unsafe { CStr::from_ptr(string) }
+        .to_str()
+        .map(|string| string.to_string())
+        .map_err(|_| format!("{name} is not valid UTF-8"))
+}
+
+unsafe fn read_strings(strings: ReqlangExprStrings, name: &str) -> Result<Vec<String>, String> {
+    if strings.len == 0 {
+        return Ok(vec![]);
+    }
+
+    if strings.items.is_null() {
+        return Err(format!("{name} is null"));
+    }
+
+    unsafe { slice::from_raw_parts(strings.items, strings.len) }
+        .iter()
+        .enumerate()
+        .map(|(i, &string)| unsafe { read_string(string, &format!("{name}[{i}]")) })
+        .collect()
+}
+
+#[cfg(test)]
+mod capi_tests {
+    use super::*;
+
+    /// Owned strings and pointers to them
+    struct Strings {
+        _owned: Vec<CString>,
+        pointers: Vec<*const c_char>,
+    }
+
+    impl Strings {
+        fn new(strings: &[&str]) -> Self {
+            let owned: Vec<CString> = strings.iter().map(|s| CString::new(*s).unwrap()).collect();
+            let pointers = owned.iter().map(|s| s.as_ptr()).collect();
+
+            Self {
+                _owned: owned,
+                pointers,
+            }
+        }
+
+        fn get(&self) -> ReqlangExprStrings {
+            ReqlangExprStrings {
+                items: self.pointers.as_ptr(),
+                len: self.pointers.len(),
+            }
+        }
+    }
+
+    fn last_error() -> Option<String> {
+        let error = reqlang_expr_last_error();
+
+        (!error.is_null()).then(|| {
+            unsafe { CStr::from_ptr(error) }
+                .to_string_lossy()
+                .to_string()
+        })
+    }
+
+    #[test]
+    fn compile_and_eval() {
+        let source = CString::new("(concat :greeting ` ` !name)").unwrap();
+        let none = Strings::new(&[]);
+
+        unsafe {
+            let expr = reqlang_expr_compile(
+                source.as_ptr(),
+                Strings::new(&["greeting"]).get(),
+                none.get(),
+                Strings::new(&["name"]).get(),
+                none.get(),
+            );
+
+            assert!(!expr.is_null());
+            assert_eq!(None, last_error());
+
+            let value = reqlang_expr_eval(
+                expr,
+                Strings::new(&["Hello"]).get(),
+                none.get(),
+                Strings::new(&["World"]).get(),
+                none.get(),
+            );
+
+            assert_eq!("Hello World", CStr::from_ptr(value).to_str().unwrap());
+
+            reqlang_expr_string_free(value);
+            reqlang_expr_free(expr);
+        }
+    }
+
+    #[test]
+    fn eval_non_string_value() {
+        let source = CString::new("(eq @a `b`)").unwrap();
+        let none = Strings::new(&[]);
+
+        unsafe {
+            let expr = reqlang_expr_compile(
+                source.as_ptr(),
+                none.get(),
+                none.get(),
+                none.get(),
+                Strings::new(&["a"]).get(),
+            );
+
+            let value = reqlang_expr_eval(
+                expr,
+                none.get(),
+                none.get(),
+                none.get(),
+                Strings::new(&["b"]).get(),
+            );
+
+            assert_eq!("true", CStr::from_ptr(value).to_str().unwrap());
+
+            reqlang_expr_string_free(value);
+            reqlang_expr_free(expr);
+        }
+    }
+
+    #[test]
+    fn compile_error() {
+        let source = CString::new(":missing").unwrap();
+        let none = Strings::new(&[]);
+
+        let expr = unsafe {
+            reqlang_expr_compile(
+                source.as_ptr(),
+                none.get(),
+                none.get(),
+                none.get(),
+                none.get(),
+            )
+        };
+
+        assert!(expr.is_null());
+        assert_eq!(
+            Some(
+                "There was a compliation error with the expression: undefined: :missing at 0..8"
+                    .to_string()
+            ),
+            last_error()
+        );
+    }
+
+    #[test]
+    fn null_arguments() {
+        let none = Strings::new(&[]);
+
+        let expr = unsafe {
+            reqlang_expr_compile(ptr::null(), none.get(), none.get(), none.get(), none.get())
+        };
+
+        assert!(expr.is_null());
+        assert_eq!(Some("source is null".to_string()), last_error());
+
+        let value = unsafe {
+            reqlang_expr_eval(ptr::null(), none.get(), none.get(), none.get(), none.get())
+        };
+
+        assert!(value.is_null());
+        assert_eq!(Some("expr is null".to_string()), last_error());
+    }
+}
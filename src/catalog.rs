@@ -0,0 +1,530 @@
+//! Descriptions of the builtins this crate defines
+//!
+//! [`BuiltinFn::catalog`] lists every builtin with its signature, a summary,
+//! docs for each arg, a [`BuiltinCategory`], and the [`BuiltinGroup`] hosts
+//! can disable it with. Documentation sites, CLIs (`expr builtins --json`),
+//! and editors render the builtin reference from it instead of copying it.
+
+pub use crate::builtins::BuiltinCategory;
+
+use crate::{
+    builtins::{BuiltinFn, BuiltinGroup},
+    types::Type,
+};
+
+/// Description of a builtin
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltinDescriptor {
+    pub name: &'static str,
+    /// e.g. `trim(value: String, chars?: String) -> String`
+    pub signature: String,
+    /// One line description of what the builtin does
+    pub summary: &'static str,
+    pub args: Vec<ArgDescriptor>,
+    pub return_type: Type,
+    pub category: BuiltinCategory,
+    /// Group hosts can disable the builtin with, if it's in one
+    pub capability: Option<BuiltinGroup>,
+}
+
+/// Description of a builtin's arg
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgDescriptor {
+    pub name: &'static str,
+    pub ty: Type,
+    pub doc: &'static str,
+    pub variadic: bool,
+    pub optional: bool,
+}
+
+/// Docs for a builtin, matched to its [`BuiltinFn`] by name
+struct BuiltinDoc {
+    name: &'static str,
+    summary: &'static str,
+    /// Docs for each arg in order
+    args: &'static [&'static str],
+}
+
+const fn doc(
+    name: &'static str,
+    summary: &'static str,
+    args: &'static [&'static str],
+) -> BuiltinDoc {
+    BuiltinDoc {
+        name,
+        summary,
+        args,
+    }
+}
+
+const DOCS: &[BuiltinDoc] = &[
+    doc("id", "Returns the value passed to it", &["Value to return"]),
+    doc("noop", "Returns the string \"noop\"", &[]),
+    doc(
+        "is_empty",
+        "Checks if the given string is empty",
+        &["String to check"],
+    ),
+    doc(
+        "and",
+        "Logical AND operation between two booleans",
+        &["First operand", "Second operand"],
+    ),
+    doc(
+        "or",
+        "Logical OR operation between two booleans",
+        &["First operand", "Second operand"],
+    ),
+    doc(
+        "cond",
+        "Conditional expression",
+        &[
+            "Condition to check",
+            "Returned if `cond` is `true`",
+            "Returned if `cond` is `false`",
+        ],
+    ),
+    doc(
+        "to_str",
+        "Converts a value to its string representation",
+        &["Value to convert"],
+    ),
+    doc(
+        "concat",
+        "Concatenates a list of values in to a string",
+        &["First value", "Second value", "Values after the second"],
+    ),
+    doc(
+        "contains",
+        "Checks for a substring match",
+        &["Substring to look for", "String to search"],
+    ),
+    doc(
+        "trim",
+        "Trim whitespace, or `chars`, from a string",
+        &["String to trim", "Characters to trim instead of whitespace"],
+    ),
+    doc(
+        "trim_start",
+        "Trim whitespace, or `chars`, from the start",
+        &["String to trim", "Characters to trim instead of whitespace"],
+    ),
+    doc(
+        "trim_end",
+        "Trim whitespace, or `chars`, from the end",
+        &["String to trim", "Characters to trim instead of whitespace"],
+    ),
+    doc(
+        "lowercase",
+        "Return a lowercase version of a string",
+        &["String to lowercase"],
+    ),
+    doc(
+        "uppercase",
+        "Return a uppercase version of a string",
+        &["String to uppercase"],
+    ),
+    doc(
+        "type",
+        "Get the string representation of a value's type",
+        &["Value to get the type of"],
+    ),
+    doc(
+        "eq",
+        "Compare two values for equality",
+        &["First value", "Second value"],
+    ),
+    doc(
+        "not",
+        "Logical NOT operation on a boolean value",
+        &["Boolean to negate"],
+    ),
+    doc(
+        "secure_eq",
+        "Compare two strings for equality in constant time, for checking secrets",
+        &["First string", "Second string"],
+    ),
+    doc(
+        "assert",
+        "Return `true`, or fail with `message` if the condition is `false`",
+        &["Condition that should be `true`", "Message to fail with"],
+    ),
+    doc(
+        "assert_eq",
+        "Return `left`, or fail with `message` and both values if they aren't equal",
+        &[
+            "Value to return",
+            "Value it should equal",
+            "Message to fail with",
+        ],
+    ),
+    doc(
+        "try",
+        "Return `expr`, or `fallback` if evaluating `expr` fails",
+        &["Value to return", "Value to return if `expr` fails"],
+    ),
+    doc(
+        "eq_ignore_case",
+        "Compare two strings for equality ignoring case",
+        &["First string", "Second string"],
+    ),
+    doc(
+        "snake_case",
+        "Convert a string to snake_case",
+        &["String to convert"],
+    ),
+    doc(
+        "camel_case",
+        "Convert a string to camelCase",
+        &["String to convert"],
+    ),
+    doc(
+        "kebab_case",
+        "Convert a string to kebab-case",
+        &["String to convert"],
+    ),
+    doc(
+        "title_case",
+        "Convert a string to Title Case",
+        &["String to convert"],
+    ),
+    doc(
+        "truncate",
+        "Truncate a string to at most `length` characters, optionally ending with `…`",
+        &[
+            "String to truncate",
+            "Most characters to keep",
+            "End with `…` if truncated",
+        ],
+    ),
+    doc(
+        "count_matches",
+        "Count non overlapping occurrences of a substring",
+        &["Substring to count", "String to search"],
+    ),
+    doc(
+        "line",
+        "Get the zero indexed `n`th line of a string",
+        &["String to get the line from", "Zero indexed line number"],
+    ),
+    doc(
+        "html_escape",
+        "Escape a string for HTML/XML",
+        &["String to escape"],
+    ),
+    doc(
+        "html_unescape",
+        "Unescape HTML/XML entities in a string",
+        &["String to unescape"],
+    ),
+    doc(
+        "semver_gte",
+        "Check if version `a` is greater than or equal to version `b`",
+        &["Version to compare", "Version to compare against"],
+    ),
+    doc(
+        "crc32",
+        "CRC-32 checksum of a string as hex",
+        &["String to checksum"],
+    ),
+    doc(
+        "adler32",
+        "Adler-32 checksum of a string as hex",
+        &["String to checksum"],
+    ),
+    doc(
+        "compose",
+        "Function calling `g` then `f` with its result",
+        &["Function called last", "Function called first"],
+    ),
+    doc(
+        "partial",
+        "Function calling `f` with `arg` as its first argument",
+        &["Function to call", "First argument to call `f` with"],
+    ),
+    doc(
+        "neq",
+        "Compare two values for inequality",
+        &["First value", "Second value"],
+    ),
+    doc(
+        "xor",
+        "Logical XOR operation between two booleans",
+        &["First operand", "Second operand"],
+    ),
+    doc(
+        "implies",
+        "Logical implication, `false` only if `a` is `true` and `b` is `false`",
+        &["Antecedent", "Consequent"],
+    ),
+    doc(
+        "parse_bool",
+        "Parse `true`/`false`, `yes`/`no`, or `1`/`0` ignoring case as a boolean",
+        &["String to parse"],
+    ),
+    doc(
+        "cmp",
+        "Compare two strings by code point, returning `-1`, `0`, or `1`",
+        &["First string", "Second string"],
+    ),
+    doc(
+        "sort_key",
+        "Key that orders strings ignoring case when compared with `cmp`",
+        &["String to get the key of"],
+    ),
+    doc(
+        "len",
+        "Number of items in a list",
+        &["List to count the items of"],
+    ),
+    doc(
+        "get",
+        "Item at a zero based index in a list",
+        &["List to get the item from", "Index of the item"],
+    ),
+    doc(
+        "map_join",
+        "Call `f` on each item of a list and join the results with `separator`",
+        &[
+            "List to map",
+            "Function called with each item",
+            "String placed between results",
+        ],
+    ),
+    doc(
+        "map",
+        "List of the results of calling `f` on each item of a list",
+        &["Function called with each item", "List to map"],
+    ),
+    doc(
+        "filter",
+        "List of the items of a list that `f` returns `true` for",
+        &["Function called with each item", "List to filter"],
+    ),
+    doc(
+        "reduce",
+        "Call `f` with the result so far, starting with `initial`, and each item of a list",
+        &[
+            "Function called with the result so far and each item",
+            "Result to start with",
+            "List to reduce",
+        ],
+    ),
+    doc(
+        "negate",
+        "Number with its sign flipped, how negative numbers are written",
+        &["Number to negate"],
+    ),
+    doc(
+        "abs",
+        "Absolute value of a number",
+        &["Number to get the absolute value of"],
+    ),
+    doc(
+        "floor",
+        "Round a number down to a whole number",
+        &["Number to round"],
+    ),
+    doc(
+        "ceil",
+        "Round a number up to a whole number",
+        &["Number to round"],
+    ),
+    doc(
+        "round",
+        "Round a number to the nearest whole number, halves away from zero",
+        &["Number to round"],
+    ),
+    doc(
+        "pow",
+        "Raise `base` to the power of `exponent`",
+        &["Number to raise", "Power to raise `base` to"],
+    ),
+    doc(
+        "sqrt",
+        "Square root of a non negative number",
+        &["Number to get the square root of"],
+    ),
+    doc(
+        "clamp",
+        "Limit a number to the range `min` to `max`",
+        &[
+            "Number to limit",
+            "Lowest number returned",
+            "Highest number returned",
+        ],
+    ),
+    doc("now", "The current UTC time as a timestamp", &[]),
+    doc(
+        "add_duration",
+        "Offset a timestamp by a duration, prefix with `-` to subtract",
+        &[
+            "Timestamp to offset",
+            "Duration to add, e.g. `15m` or `-1h`",
+        ],
+    ),
+    doc(
+        "diff_seconds",
+        "Seconds from timestamp `b` to timestamp `a`",
+        &["Later timestamp", "Earlier timestamp"],
+    ),
+    doc(
+        "to_timezone",
+        "Convert a timestamp to an IANA timezone e.g. `America/Chicago`",
+        &["Timestamp to convert", "IANA timezone name"],
+    ),
+    doc(
+        "same_json",
+        "Compare two JSON strings structurally, ignoring key order and whitespace",
+        &["First JSON string", "Second JSON string"],
+    ),
+    doc(
+        "ctx_json",
+        "Get the value at a `.` separated path (e.g. `headers.location` or `items.0.id`) in a JSON string such as a client context value",
+        &["JSON string to read", "`.` separated path to the value"],
+    ),
+];
+
+impl BuiltinFn<'static> {
+    /// Describe every builtin this crate defines
+    ///
+    /// Includes the builtins in every [`BuiltinGroup`] enabled by features,
+    /// in the order [`CompileTimeEnv`](crate::compiler::CompileTimeEnv)
+    /// registers them.
+    ///
+    /// ```
+    /// use reqlang_expr::{catalog::BuiltinCategory, prelude::*};
+    ///
+    /// let catalog = BuiltinFn::catalog();
+    /// let trim = catalog.iter().find(|builtin| builtin.name == "trim").unwrap();
+    ///
+    /// assert_eq!("trim(value: String, chars?: String) -> String", trim.signature);
+    /// assert_eq!(BuiltinCategory::String, trim.category);
+    /// assert!(trim.args[1].optional);
+    /// ```
+    pub fn catalog() -> Vec<BuiltinDescriptor> {
+        let grouped = BuiltinGroup::ALL.iter().flat_map(BuiltinGroup::builtins);
+
+        BuiltinFn::DEFAULT_BUILTINS
+            .iter()
+            .chain(grouped)
+            .map(BuiltinFn::describe)
+            .collect()
+    }
+
+    /// Describe the builtin with its docs
+    ///
+    /// # Panics
+    ///
+    /// If the builtin isn't defined by this crate.
+    pub fn describe(&self) -> BuiltinDescriptor {
+        let doc = DOCS
+            .iter()
+            .find(|doc| doc.name == self.name)
+            .expect("should have docs for every builtin");
+
+        let args = self
+            .args
+            .iter()
+            .zip(doc.args)
+            .map(|(arg, doc)| ArgDescriptor {
+                name: arg.name,
+                ty: arg.ty.clone(),
+                doc,
+                variadic: arg.variadic,
+                optional: arg.optional,
+            })
+            .collect();
+
+        BuiltinDescriptor {
+            name: self.name,
+            signature: self.to_string(),
+            summary: doc.summary,
+            args,
+            return_type: self.return_type.clone(),
+            category: self.category,
+            capability: self.capability,
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl BuiltinDescriptor {
+    /// `{ name, signature, summary, args, returns, category, capability }`
+    ///
+    /// `capability` is `null` for builtins that can't be disabled.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "signature": self.signature,
+            "summary": self.summary,
+            "args": self.args.iter().map(|arg| serde_json::json!({
+                "name": arg.name,
+                "type": arg.ty.name(),
+                "doc": arg.doc,
+                "variadic": arg.variadic,
+                "optional": arg.optional,
+            })).collect::<Vec<_>>(),
+            "returns": self.return_type.name(),
+            "category": self.category.name(),
+            "capability": self.capability.map(|group| format!("{group:?}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod catalog_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn every_builtin_has_docs_for_each_arg() {
+        for builtin in BuiltinFn::catalog() {
+            let doc = DOCS.iter().find(|doc| doc.name == builtin.name).unwrap();
+
+            assert_eq!(doc.args.len(), builtin.args.len(), "{}", builtin.name);
+        }
+    }
+
+    #[test]
+    fn capabilities_are_builtin_groups() {
+        let capability = |name: &str| {
+            BuiltinFn::catalog()
+                .into_iter()
+                .find(|builtin| builtin.name == name)
+                .unwrap()
+                .capability
+        };
+
+        assert_eq!(None, capability("concat"));
+        assert_eq!(Some(BuiltinGroup::Math), capability("clamp"));
+    }
+
+    /// The README's builtin tables are written from the catalog
+    #[test]
+    fn readme_matches_catalog() {
+        let readme = std::fs::read_to_string("README.md").unwrap();
+
+        let rows: Vec<(&str, &str)> = readme
+            .lines()
+            .filter_map(|line| {
+                let cells: Vec<&str> = line.split('|').map(str::trim).collect();
+
+                match cells.as_slice() {
+                    ["", signature, summary, ""] => Some((signature.trim_matches('`'), *summary)),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        for builtin in BuiltinFn::catalog() {
+            assert!(
+                rows.contains(&(builtin.signature.as_str(), builtin.summary)),
+                "README should have | `{}` | {} |",
+                builtin.signature,
+                builtin.summary
+            );
+        }
+    }
+}
@@ -2,10 +2,15 @@
 
 use std::{
     error::Error,
-    fs::read_to_string,
+    fs::read,
     io::{Read, stdin},
 };
 
+use crate::errors::{ExprResult, LexicalError};
+
+/// UTF-8 byte order mark some editors add to the start of files
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
 /// Unzip a vector of key-value pairs into separate vectors for keys and values.
 ///
 /// ```
@@ -95,6 +100,10 @@ where
 
 /// Read source code from a file at the provided path or from standard input if no path is provided.
 ///
+/// A leading UTF-8 byte order mark is removed. Errors reading the source are
+/// a [`LexicalError::Io`] and source that isn't UTF-8 is a
+/// [`LexicalError::InvalidUtf8`] at the first invalid byte.
+///
 /// # Usage
 ///
 /// ## From a file:
@@ -104,7 +113,7 @@ where
 ///
 /// let source_code = read_in_source(Some("./spec/valid/call_id.expr".to_string()));
 ///
-/// assert_eq!("(id (noop))", source_code);
+/// assert_eq!(Ok("(id (noop))".to_string()), source_code);
 /// ```
 ///
 /// ## From stdin:
@@ -116,35 +125,79 @@ where
 ///
 /// let source_code = read_in_source(None);
 ///
-/// assert_eq!("(id (noop))".to_string(), source_code);
+/// assert_eq!(Ok("(id (noop))".to_string()), source_code);
 /// ```
-pub fn read_in_source(path: Option<String>) -> String {
-    match path {
-        Some(path) => {
-            //
-            read_to_string(path).expect("should be able to open file at path")
-        }
+pub fn read_in_source(path: Option<String>) -> ExprResult<String> {
+    let bytes = match path {
+        Some(path) => read(&path)
+            .map_err(|err| vec![(LexicalError::Io(format!("{path}: {err}")).into(), 0..0)])?,
         None => {
-            let mut source = String::new();
+            let mut bytes = vec![];
 
-            stdin().read_to_string(&mut source).unwrap();
+            stdin()
+                .read_to_end(&mut bytes)
+                .map_err(|err| vec![(LexicalError::Io(err.to_string()).into(), 0..0)])?;
 
-            source
+            bytes
         }
+    };
+
+    decode_source(bytes)
+}
+
+/// Decode source as UTF-8 without a leading byte order mark
+fn decode_source(mut bytes: Vec<u8>) -> ExprResult<String> {
+    if bytes.starts_with(UTF8_BOM) {
+        bytes.drain(..UTF8_BOM.len());
     }
+
+    String::from_utf8(bytes).map_err(|err| {
+        let at = err.utf8_error().valid_up_to();
+
+        vec![(LexicalError::InvalidUtf8.into(), at..at)]
+    })
 }
 
 #[cfg(test)]
 mod cliutil_tests {
     use clap::Parser;
 
-    use crate::cliutil::{parse_key_val, read_in_source};
+    use crate::{
+        cliutil::{decode_source, parse_key_val, read_in_source},
+        errors::LexicalError,
+    };
 
     #[test]
     fn read_in_source_from_file() {
         let result = read_in_source(Some("./spec/valid/call_id.expr".to_string()));
 
-        assert_eq!("(id (noop))", result);
+        assert_eq!(Ok("(id (noop))".to_string()), result);
+    }
+
+    #[test]
+    fn read_in_source_from_missing_file() {
+        let result = read_in_source(Some("./spec/valid/missing.expr".to_string()));
+
+        let (err, span) = &result.unwrap_err()[0];
+
+        assert!(err.to_string().contains("./spec/valid/missing.expr"));
+        assert_eq!(&(0..0), span);
+    }
+
+    #[test]
+    fn decode_source_strips_bom() {
+        assert_eq!(
+            Ok("(noop)".to_string()),
+            decode_source(b"\xEF\xBB\xBF(noop)".to_vec())
+        );
+    }
+
+    #[test]
+    fn decode_source_invalid_utf8() {
+        assert_eq!(
+            Err(vec![(LexicalError::InvalidUtf8.into(), 4..4)]),
+            decode_source(b"`a` \xff `b`".to_vec())
+        );
     }
 
     #[test]
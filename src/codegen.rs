@@ -0,0 +1,706 @@
+//! Compile bytecode in to native Rust closures
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    compiler::{
+        CompileTimeEnv, ExprByteCode,
+        lookup::{BUILTIN, CLIENT_CTX, PROMPT, SECRET, TYPE, USER_BUILTIN, VAR},
+        opcode,
+    },
+    disassembler::{operands, undefined},
+    errors::{DisassembleError, ExprResult, RuntimeError},
+    types::Type,
+    value::Value,
+    vm::{invalid_bytecode, is_fatal, set_slot},
+};
+
+/// An expression compiled to a closure over a [`RuntimeEnv`](crate::vm::RuntimeEnv)
+pub type CompiledExpr = Box<dyn Fn(&crate::vm::RuntimeEnv) -> ExprResult<Value>>;
+
+/// Convert [`ExprByteCode`] in to a [`CompiledExpr`] by composing closures
+///
+/// Errors with a [`DisassembleError`] if an op is missing operands or
+/// references something not in the bytecode or environment, and with
+/// [`RuntimeError::InvalidBytecode`] for unknown op codes or jumps that aren't
+/// compiled branches.
+///
+/// Op dispatch, constant lookups, and builtin resolution happen once here
+/// instead of on every evaluation. This trades a slower compile for faster
+/// repeated evaluation compared to [`Vm::interpret`](crate::vm::Vm::interpret).
+///
+/// A `SET_LOCAL` doesn't have a closure of its own. It's run by the closure of
+/// the op after it, the first op of the `let`'s body, so the value is bound
+/// before anything in the body reads it. Jumps don't have closures either.
+/// The closures of a branch's condition, `then` ops, and `else` ops are
+/// combined in to one when the branch ends, as are the closures of a `try`'s
+/// guarded and fallback ops.
+///
+/// ```
+/// use reqlang_expr::{codegen::codegen, prelude::*};
+///
+/// let source = "(concat `Hello, ` :name)";
+/// let env = CompileTimeEnv::new(vec!["name".to_string()], vec![], vec![], vec![]);
+///
+/// let ast = parse(source).unwrap();
+/// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+///
+/// let compiled = codegen(&bytecode, &env).unwrap();
+///
+/// let runtime_env = RuntimeEnv {
+///     vars: vec!["World".to_string()],
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(
+///     Ok(Value::String("Hello, World".into())),
+///     compiled(&runtime_env)
+/// );
+/// ```
+pub fn codegen(bytecode: &ExprByteCode, env: &CompileTimeEnv) -> ExprResult<CompiledExpr> {
+    let codes = bytecode.codes();
+    let mut stack: Vec<CompiledExpr> = vec![];
+    let locals: Rc<RefCell<Vec<Value>>> = Rc::default();
+    let mut pending_local: Option<(usize, CompiledExpr)> = None;
+    // Where each branch or `try` ends and its closures compiled so far
+    let mut branches: Vec<(usize, Branch)> = vec![];
+    let mut ip = 0;
+
+    loop {
+        while let Some((end, _)) = branches.last()
+            && *end == ip
+        {
+            let (_, branch) = branches.pop().expect("should have a branch ending");
+            let otherwise = pop(&mut stack);
+
+            stack.push(match branch {
+                Branch::Cond { condition, then } => {
+                    let then = then.ok_or_else(|| {
+                        invalid_bytecode(format!("branch ending at {ip} without a JUMP"))
+                    })?;
+
+                    Box::new(move |runtime_env| {
+                        if condition(runtime_env)?.get_bool()? {
+                            then(runtime_env)
+                        } else {
+                            otherwise(runtime_env)
+                        }
+                    })
+                }
+                Branch::Try { guarded } => {
+                    let guarded = guarded.ok_or_else(|| {
+                        invalid_bytecode(format!("try ending at {ip} without an END_TRY"))
+                    })?;
+
+                    Box::new(move |runtime_env| match guarded(runtime_env) {
+                        Err(errs) if !is_fatal(&errs) => otherwise(runtime_env),
+                        result => result,
+                    })
+                }
+            });
+        }
+
+        let Some(&op_code) = codes.get(ip) else {
+            break;
+        };
+
+        let (Some(name), Some(operand_count)) =
+            (opcode::name(op_code), opcode::operand_count(op_code))
+        else {
+            return Err(invalid_bytecode(format!("invalid op code {op_code}")));
+        };
+
+        let operands = operands(codes, name, ip, operand_count)?;
+        let op_idx = ip;
+        ip += 1 + operand_count;
+
+        match op_code {
+            opcode::CALL | opcode::CALL_SPREAD => {
+                let spread = op_code == opcode::CALL_SPREAD;
+                let arg_count = operands[0] as usize;
+
+                let args = pop_n(&mut stack, arg_count);
+                let callee = pop(&mut stack);
+
+                stack.push(Box::new(move |runtime_env| {
+                    let callee = callee(runtime_env)?;
+
+                    let mut args = args
+                        .iter()
+                        .map(|arg| arg(runtime_env))
+                        .collect::<ExprResult<Vec<Value>>>()?;
+
+                    if spread && let Some(list) = args.pop() {
+                        args.extend(list.get_list()?.iter().cloned());
+                    }
+
+                    callee.call(args)
+                }));
+            }
+            opcode::CALL_BUILTIN => {
+                let builtin_idx = operands[0] as usize;
+                let arg_count = operands[1] as usize;
+
+                let builtin = env
+                    .get_builtin(builtin_idx)
+                    .ok_or_else(|| undefined(name, op_idx, "builtin", builtin_idx))?
+                    .clone();
+
+                let args = pop_n(&mut stack, arg_count);
+
+                stack.push(Box::new(move |runtime_env| {
+                    let args = args
+                        .iter()
+                        .map(|arg| arg(runtime_env))
+                        .collect::<ExprResult<Vec<Value>>>()?;
+
+                    builtin.check_arity(&args)?;
+
+                    (builtin.func)(args)
+                }));
+            }
+            opcode::GET => {
+                let get_lookup = operands[0];
+                let get_idx = operands[1] as usize;
+
+                stack.push(codegen_get(bytecode, env, op_idx, get_lookup, get_idx)?);
+            }
+            opcode::CONSTANT => {
+                let get_idx = operands[0] as usize;
+
+                let value = bytecode
+                    .constants()
+                    .get(get_idx)
+                    .ok_or_else(|| undefined(name, op_idx, "constant", get_idx))?
+                    .clone();
+
+                stack.push(Box::new(move |_| Ok(value.clone())));
+            }
+            opcode::LIST => {
+                let item_count = operands[0] as usize;
+
+                let items = pop_n(&mut stack, item_count);
+
+                stack.push(Box::new(move |runtime_env| {
+                    let items = items
+                        .iter()
+                        .map(|item| item(runtime_env))
+                        .collect::<ExprResult<Vec<Value>>>()?;
+
+                    Ok(Value::List(items))
+                }));
+            }
+            opcode::TRUE => {
+                stack.push(Box::new(|_| Ok(Value::Bool(true))));
+            }
+            opcode::FALSE => {
+                stack.push(Box::new(|_| Ok(Value::Bool(false))));
+            }
+            opcode::GET_LOCAL => {
+                let slot = operands[0] as usize;
+
+                let locals = locals.clone();
+
+                stack.push(Box::new(move |_| {
+                    let locals = locals.borrow();
+
+                    Ok(locals
+                        .get(slot)
+                        .ok_or_else(|| invalid_bytecode(format!("undefined local {slot}")))?
+                        .clone())
+                }));
+            }
+            opcode::SET_LOCAL => {
+                let slot = operands[0] as usize;
+
+                pending_local = Some((slot, pop(&mut stack)));
+
+                continue;
+            }
+            opcode::JUMP_IF_FALSE => {
+                let end = opcode::branch_end(codes, op_idx).ok_or_else(|| {
+                    invalid_bytecode(format!("JUMP_IF_FALSE at {op_idx} isn't a branch"))
+                })?;
+
+                branches.push((
+                    end,
+                    Branch::Cond {
+                        condition: pop(&mut stack),
+                        then: None,
+                    },
+                ));
+
+                continue;
+            }
+            opcode::JUMP => {
+                let Some((_, Branch::Cond { then, .. })) = branches.last_mut() else {
+                    return Err(invalid_bytecode(format!(
+                        "JUMP at {op_idx} outside of a branch"
+                    )));
+                };
+
+                *then = Some(pop(&mut stack));
+
+                continue;
+            }
+            opcode::TRY => {
+                let end = opcode::try_end(codes, op_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("TRY at {op_idx} isn't a try")))?;
+
+                branches.push((end, Branch::Try { guarded: None }));
+
+                continue;
+            }
+            opcode::END_TRY => {
+                let Some((_, Branch::Try { guarded })) = branches.last_mut() else {
+                    return Err(invalid_bytecode(format!(
+                        "END_TRY at {op_idx} outside of a try"
+                    )));
+                };
+
+                *guarded = Some(pop(&mut stack));
+
+                continue;
+            }
+            _ => unreachable!("every named op code should be compiled"),
+        }
+
+        if let Some((slot, value)) = pending_local.take() {
+            let next = pop(&mut stack);
+            let locals = locals.clone();
+
+            stack.push(Box::new(move |runtime_env| {
+                let value = value(runtime_env)?;
+
+                set_slot(&mut locals.borrow_mut(), slot, value)?;
+
+                next(runtime_env)
+            }));
+        }
+    }
+
+    if let Some((end, _)) = branches.last() {
+        return Err(invalid_bytecode(format!(
+            "branch ending at {end} past the end of the bytecode"
+        )));
+    }
+
+    Ok(pop(&mut stack))
+}
+
+/// A branch or `try` whose closures are being compiled
+enum Branch {
+    Cond {
+        condition: CompiledExpr,
+        then: Option<CompiledExpr>,
+    },
+    Try {
+        guarded: Option<CompiledExpr>,
+    },
+}
+
+fn codegen_get(
+    bytecode: &ExprByteCode,
+    env: &CompileTimeEnv,
+    op_idx: usize,
+    get_lookup: u8,
+    get_idx: usize,
+) -> ExprResult<CompiledExpr> {
+    let undefined = |lookup: &str| undefined("GET", op_idx, lookup, get_idx);
+
+    Ok(match get_lookup {
+        BUILTIN => {
+            let value = Value::Fn(
+                env.get_builtin(get_idx)
+                    .ok_or_else(|| undefined("builtin"))?
+                    .clone()
+                    .into(),
+            );
+
+            Box::new(move |_| Ok(value.clone()))
+        }
+        USER_BUILTIN => {
+            let value = Value::Fn(
+                env.get_user_builtin(get_idx)
+                    .ok_or_else(|| undefined("user_builtin"))?
+                    .clone()
+                    .into(),
+            );
+
+            Box::new(move |_| Ok(value.clone()))
+        }
+        VAR => {
+            env.get_var(get_idx).ok_or_else(|| undefined("var"))?;
+
+            Box::new(move |runtime_env| {
+                let value = runtime_env
+                    .vars
+                    .get(get_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("undefined variable {get_idx}")))?;
+
+                Ok(Value::String(value.clone().into()))
+            })
+        }
+        PROMPT => {
+            env.get_prompt(get_idx).ok_or_else(|| undefined("prompt"))?;
+
+            Box::new(move |runtime_env| {
+                let value = runtime_env
+                    .prompts
+                    .get(get_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("undefined prompt {get_idx}")))?;
+
+                Ok(Value::String(value.clone().into()))
+            })
+        }
+        SECRET => {
+            env.get_secret(get_idx).ok_or_else(|| undefined("secret"))?;
+
+            Box::new(move |runtime_env| {
+                let value = runtime_env
+                    .secrets
+                    .get(get_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("undefined secret {get_idx}")))?;
+
+                Ok(Value::String(value.clone().into()))
+            })
+        }
+        CLIENT_CTX => {
+            env.get_client_context(get_idx)
+                .ok_or_else(|| undefined("client_ctx"))?;
+
+            Box::new(move |runtime_env| {
+                let value = runtime_env.client_context.get(get_idx).ok_or_else(|| {
+                    invalid_bytecode(format!("undefined client context {get_idx}"))
+                })?;
+
+                Ok(value.clone())
+            })
+        }
+        TYPE => {
+            let ty = bytecode
+                .types()
+                .get(get_idx)
+                .ok_or_else(|| undefined("type"))?;
+
+            let value = if ty.is_type() {
+                Value::Type(ty.clone().into())
+            } else {
+                Value::Type(Type::Type(ty.clone().into()).into())
+            };
+
+            Box::new(move |_| Ok(value.clone()))
+        }
+        lookup_type => {
+            return Err(vec![(
+                DisassembleError::InvalidLookupType {
+                    op_idx,
+                    lookup_type,
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+    })
+}
+
+/// Pop a closure off the simulated stack
+///
+/// Bytecode that underflows the stack compiles to a closure returning
+/// [`RuntimeError::EmptyStack`], matching the VM.
+fn pop(stack: &mut Vec<CompiledExpr>) -> CompiledExpr {
+    stack
+        .pop()
+        .unwrap_or_else(|| Box::new(|_| Err(vec![(RuntimeError::EmptyStack.into(), 0..0)])))
+}
+
+fn pop_n(stack: &mut Vec<CompiledExpr>, n: usize) -> Vec<CompiledExpr> {
+    let mut values: Vec<CompiledExpr> = (0..n).map(|_| pop(stack)).collect();
+
+    values.reverse();
+
+    values
+}
+
+#[cfg(test)]
+mod codegen_tests {
+    use super::*;
+
+    use crate::{
+        compiler::{CompileOptions, compile_with_options, get_version_bytes},
+        errors::ExprError,
+        parser::parse,
+        vm::{RuntimeEnv, Vm},
+    };
+
+    fn env() -> (CompileTimeEnv, RuntimeEnv) {
+        let env = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        );
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["a_value".to_string()],
+            prompts: vec!["b_value".to_string()],
+            secrets: vec!["c_value".to_string()],
+            client_context: vec![Value::Bool(true)],
+        };
+
+        (env, runtime_env)
+    }
+
+    #[test]
+    fn matches_vm() {
+        let (env, runtime_env) = env();
+
+        let sources = [
+            "true",
+            "false",
+            "`foo`",
+            ":a",
+            "?b",
+            "!c",
+            "@d",
+            "noop",
+            "String",
+            "(noop)",
+            "(id :a)",
+            "(concat :a ?b !c)",
+            "(cond (eq :a `a_value`) `yes` `no`)",
+            "(eq (type :a) String)",
+            "(and (not false) (contains `a` :a))",
+            "(uppercase (trim ` foo `))",
+            "(let (x (concat :a ?b)) (concat x (let (y x) (concat y x))))",
+            "(let (x (let (y !c) (uppercase y))) (concat x x))",
+            "(let (f concat) (f :a :a))",
+            "(or (eq :a `x`) (and (id @d) (not false)))",
+            "(cond (eq :a `x`) (parse_bool `maybe`) (let (x :a) (cond true x `no`)))",
+            "(concat (cond (id @d) `a` `b`) (let (x (or false (id @d))) (cond x `c` `d`)))",
+            "(and (id @d) (id :a))",
+            "(try (parse_bool `maybe`) `fallback`)",
+            "(try (try (parse_bool `x`) (parse_bool `y`)) (concat :a `!`))",
+            "(cond (try (parse_bool :a) false) `a` (try `b` `c`))",
+        ];
+
+        for fuse_calls in [false, true] {
+            let options = CompileOptions {
+                fuse_calls,
+                ..Default::default()
+            };
+
+            for source in sources {
+                let ast = parse(source).unwrap();
+                let bytecode =
+                    compile_with_options(&mut (ast, 0..source.len()), &env, &options).unwrap();
+
+                let expected = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+                let actual = codegen(&bytecode, &env).unwrap()(&runtime_env);
+
+                assert_eq!(expected, actual, "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn reusable_across_runtime_envs() {
+        let (env, runtime_env) = env();
+
+        let source = "(concat :a ?b)";
+        let ast = parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let compiled = codegen(&bytecode, &env).unwrap();
+
+        assert_eq!(
+            Ok(Value::String("a_valueb_value".into())),
+            compiled(&runtime_env)
+        );
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["x".to_string()],
+            prompts: vec!["y".to_string()],
+            ..runtime_env
+        };
+
+        assert_eq!(Ok(Value::String("xy".into())), compiled(&runtime_env));
+    }
+
+    #[test]
+    fn empty_stack() {
+        let mut codes = get_version_bytes().to_vec();
+
+        codes.push(opcode::GET);
+        codes.push(BUILTIN);
+        codes.push(0);
+
+        codes.push(opcode::CALL);
+        codes.push(1);
+
+        let bytecode = ExprByteCode::new(codes, vec![], vec![]);
+        let env = CompileTimeEnv::default();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::EmptyStack),
+                0..0
+            )]),
+            codegen(&bytecode, &env).unwrap()(&RuntimeEnv::default())
+        );
+    }
+
+    fn codegen_err(codes: &[u8], constants: Vec<Value>) -> ExprError {
+        let codes = get_version_bytes().iter().chain(codes).copied().collect();
+        let bytecode = ExprByteCode::new(codes, constants, vec![]);
+
+        match codegen(&bytecode, &CompileTimeEnv::default()) {
+            Err(mut errs) => errs.remove(0).0,
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        assert_eq!(
+            ExprError::RuntimeError(RuntimeError::InvalidBytecode {
+                message: "invalid op code 99".to_string()
+            }),
+            codegen_err(&[99], vec![])
+        );
+    }
+
+    #[test]
+    fn missing_operands() {
+        for (codes, op, expected) in [
+            (&[opcode::CALL][..], "CALL", 1),
+            (&[opcode::CALL_BUILTIN, 0], "CALL_BUILTIN", 2),
+            (&[opcode::GET, VAR], "GET", 2),
+            (&[opcode::CONSTANT], "CONSTANT", 1),
+            (&[opcode::LIST], "LIST", 1),
+            (&[opcode::JUMP_IF_FALSE, 0], "JUMP_IF_FALSE", 2),
+            (&[opcode::TRY], "TRY", 2),
+        ] {
+            assert_eq!(
+                ExprError::DisassembleError(DisassembleError::MissingOperands {
+                    op: op.to_string(),
+                    op_idx: 0,
+                    expected,
+                }),
+                codegen_err(codes, vec![]),
+                "{op}"
+            );
+        }
+    }
+
+    #[test]
+    fn undefined_references() {
+        for (codes, op, lookup, index) in [
+            (
+                &[opcode::CALL_BUILTIN, 255, 0][..],
+                "CALL_BUILTIN",
+                "builtin",
+                255,
+            ),
+            (&[opcode::GET, BUILTIN, 255], "GET", "builtin", 255),
+            (&[opcode::GET, VAR, 0], "GET", "var", 0),
+            (&[opcode::GET, TYPE, 0], "GET", "type", 0),
+            (&[opcode::CONSTANT, 1], "CONSTANT", "constant", 1),
+        ] {
+            assert_eq!(
+                ExprError::DisassembleError(DisassembleError::Undefined {
+                    op: op.to_string(),
+                    op_idx: 0,
+                    lookup: lookup.to_string(),
+                    index,
+                }),
+                codegen_err(codes, vec![Value::Bool(true)]),
+                "{op}"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_lookup_type() {
+        assert_eq!(
+            ExprError::DisassembleError(DisassembleError::InvalidLookupType {
+                op_idx: 0,
+                lookup_type: 99,
+            }),
+            codegen_err(&[opcode::GET, 99, 0], vec![])
+        );
+    }
+
+    #[test]
+    fn local_set_out_of_order() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.extend([opcode::TRUE, opcode::SET_LOCAL, 1, opcode::GET_LOCAL, 1]);
+
+        let bytecode = ExprByteCode::new(codes, vec![], vec![]);
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::InvalidBytecode {
+                    message: "local 1 set before local 0".to_string()
+                }),
+                0..0
+            )]),
+            codegen(&bytecode, &CompileTimeEnv::default()).unwrap()(&RuntimeEnv::default())
+        );
+    }
+
+    #[test]
+    fn jumps_outside_branches() {
+        for (codes, message) in [
+            (&[opcode::JUMP, 0, 0][..], "JUMP at 0 outside of a branch"),
+            (&[opcode::END_TRY, 0, 0], "END_TRY at 0 outside of a try"),
+            (
+                &[opcode::TRUE, opcode::JUMP_IF_FALSE, 0, 0],
+                "JUMP_IF_FALSE at 1 isn't a branch",
+            ),
+            (&[opcode::TRY, 0, 0], "TRY at 0 isn't a try"),
+        ] {
+            assert_eq!(
+                ExprError::RuntimeError(RuntimeError::InvalidBytecode {
+                    message: message.to_string()
+                }),
+                codegen_err(codes, vec![]),
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_number_of_builtin_args() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.extend([opcode::CALL_BUILTIN, 0, 0]);
+
+        let bytecode = ExprByteCode::new(codes, vec![], vec![]);
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        let expected = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+
+        assert!(expected.is_err());
+        assert_eq!(expected, codegen(&bytecode, &env).unwrap()(&runtime_env));
+    }
+
+    #[test]
+    fn try_doesnt_catch_invalid_bytecode() {
+        let (env, _) = env();
+
+        let source = "(try :a `fallback`)";
+        let ast = parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::InvalidBytecode {
+                    message: "undefined variable 0".to_string()
+                }),
+                0..0
+            )]),
+            codegen(&bytecode, &env).unwrap()(&RuntimeEnv::default())
+        );
+    }
+}
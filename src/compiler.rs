@@ -1,13 +1,18 @@
 //! The compiler and associated types
 
+use std::{collections::BTreeMap, time::Instant};
+
 use crate::{
-    ast::{Expr, ExprS, IdentifierKind, add_type_to_expr},
-    builtins::BuiltinFn,
+    ast::{Expr, ExprCall, ExprIdentifier, ExprS, IdentifierKind, add_type_to_expr},
+    builtins::{BuiltinFn, BuiltinGroup, FnNamespace},
     errors::{
         CompileError::{self, WrongNumberOfArgs},
         ExprError, ExprErrorS, ExprResult,
     },
+    metrics::metrics,
+    module::{ModuleLoader, ModuleResolver, NoModuleResolver},
     prelude::lookup::TYPE,
+    span::Span,
     types::Type,
     value::Value,
 };
@@ -19,7 +24,146 @@ pub mod opcode {
         GET,
         CONSTANT,
         TRUE,
-        FALSE
+        FALSE,
+        CALL_BUILTIN,
+        LIST,
+        GET_LOCAL,
+        SET_LOCAL,
+        JUMP_IF_FALSE,
+        JUMP,
+        TRY,
+        END_TRY,
+        CALL_SPREAD
+    }
+
+    /// Number of values an op pops off and pushes on to the stack
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct StackEffect {
+        pub pops: usize,
+        pub pushes: usize,
+    }
+
+    /// Number of operand bytes following an op code, `None` if it's unknown
+    pub fn operand_count(op_code: u8) -> Option<usize> {
+        match op_code {
+            TRUE | FALSE => Some(0),
+            CALL | CONSTANT | LIST | GET_LOCAL | SET_LOCAL | CALL_SPREAD => Some(1),
+            GET | CALL_BUILTIN | JUMP_IF_FALSE | JUMP | TRY | END_TRY => Some(2),
+            _ => None,
+        }
+    }
+
+    /// Name of an op code as it's disassembled, `None` if it's unknown
+    pub fn name(op_code: u8) -> Option<&'static str> {
+        match op_code {
+            CALL => Some("CALL"),
+            GET => Some("GET"),
+            CONSTANT => Some("CONSTANT"),
+            TRUE => Some("TRUE"),
+            FALSE => Some("FALSE"),
+            CALL_BUILTIN => Some("CALL_BUILTIN"),
+            LIST => Some("LIST"),
+            GET_LOCAL => Some("GET_LOCAL"),
+            SET_LOCAL => Some("SET_LOCAL"),
+            JUMP_IF_FALSE => Some("JUMP_IF_FALSE"),
+            JUMP => Some("JUMP"),
+            TRY => Some("TRY"),
+            END_TRY => Some("END_TRY"),
+            CALL_SPREAD => Some("CALL_SPREAD"),
+            _ => None,
+        }
+    }
+
+    /// Stack effect of an op code followed by its operands
+    ///
+    /// `CALL` and `CALL_SPREAD` pop their args and the callee, `CALL_BUILTIN`
+    /// only its args, and `LIST` its items. `SET_LOCAL` pops the value it binds and
+    /// `JUMP_IF_FALSE` its condition without pushing anything. `TRY` and
+    /// `END_TRY` only change the VM's error handlers. Returns `None` if the op
+    /// code is unknown or its operands are missing.
+    pub fn stack_effect(op_code: u8, operands: &[u8]) -> Option<StackEffect> {
+        let (pops, pushes) = match op_code {
+            GET | CONSTANT | TRUE | FALSE => (0, 1),
+            CALL | CALL_SPREAD => (*operands.first()? as usize + 1, 1),
+            CALL_BUILTIN => (*operands.get(1)? as usize, 1),
+            LIST => (*operands.first()? as usize, 1),
+            GET_LOCAL => {
+                operands.first()?;
+
+                (0, 1)
+            }
+            SET_LOCAL => {
+                operands.first()?;
+
+                (1, 0)
+            }
+            JUMP_IF_FALSE => {
+                jump_offset(operands)?;
+
+                (1, 0)
+            }
+            JUMP | TRY | END_TRY => {
+                jump_offset(operands)?;
+
+                (0, 0)
+            }
+            _ => return None,
+        };
+
+        Some(StackEffect { pops, pushes })
+    }
+
+    /// Bytes a `JUMP`, `JUMP_IF_FALSE`, `TRY`, or `END_TRY` skips past the end
+    /// of its operands
+    ///
+    /// Offsets are 2 bytes, big endian. Jumps only go forward. A `TRY` only
+    /// jumps when an op after it fails.
+    pub fn jump_offset(operands: &[u8]) -> Option<usize> {
+        match operands {
+            [high, low, ..] => Some(u16::from_be_bytes([*high, *low]) as usize),
+            _ => None,
+        }
+    }
+
+    /// Offset the branch started by the `JUMP_IF_FALSE` at `ip` ends at
+    ///
+    /// Branches are compiled as a `JUMP_IF_FALSE` over the `then` ops and a
+    /// `JUMP` over the `else` ops, so they end at the target of the `JUMP`
+    /// right before the `else` ops. `None` if the ops aren't shaped like that.
+    pub fn branch_end(codes: &[u8], ip: usize) -> Option<usize> {
+        if codes.get(ip) != Some(&JUMP_IF_FALSE) {
+            return None;
+        }
+
+        let else_ip = ip + 3 + jump_offset(codes.get(ip + 1..)?)?;
+        let jump_ip = else_ip.checked_sub(3).filter(|jump_ip| *jump_ip > ip)?;
+
+        if codes.get(jump_ip) != Some(&JUMP) {
+            return None;
+        }
+
+        Some(else_ip + jump_offset(codes.get(jump_ip + 1..)?)?)
+    }
+
+    /// Offset the `try` started by the `TRY` at `ip` ends at
+    ///
+    /// A `try` is compiled as a `TRY` to the fallback ops, the ops it guards,
+    /// and an `END_TRY` over the fallback ops, so it ends at the target of
+    /// the `END_TRY` right before the fallback ops. `None` if the ops aren't
+    /// shaped like that.
+    pub fn try_end(codes: &[u8], ip: usize) -> Option<usize> {
+        if codes.get(ip) != Some(&TRY) {
+            return None;
+        }
+
+        let fallback_ip = ip + 3 + jump_offset(codes.get(ip + 1..)?)?;
+        let end_ip = fallback_ip.checked_sub(3).filter(|end_ip| *end_ip > ip)?;
+
+        if codes.get(end_ip) != Some(&END_TRY) {
+            return None;
+        }
+
+        Some(fallback_ip + jump_offset(codes.get(end_ip + 1..)?)?)
     }
 }
 
@@ -41,30 +185,93 @@ pub mod lookup {
     }
 }
 
-/// Try to get a string from a list
-fn get(list: &[String], identifier: &str) -> Option<u8> {
-    list.iter().position(|x| x == identifier).map(|i| i as u8)
+/// Try to get the index of a string in a list
+fn get(list: &[String], identifier: &str, options: &LookupOptions) -> Option<usize> {
+    list.iter().position(|x| options.matches(x, identifier))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CompileTimeEnv {
     builtins: Vec<BuiltinFn<'static>>,
     user_builtins: Vec<BuiltinFn<'static>>,
+    defs: Vec<Def>,
     vars: Vec<String>,
     prompts: Vec<String>,
     secrets: Vec<String>,
     client_context: Vec<String>,
+    lookup_options: LookupOptions,
+}
+
+/// How identifiers are matched to the names of vars, prompts, secrets, and
+/// client context
+///
+/// Names sourced from user edited files (e.g. front matter) often differ from
+/// the identifiers referencing them only by surrounding whitespace or case.
+/// Names match exactly by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LookupOptions {
+    /// Ignore whitespace surrounding names in the env
+    pub trim: bool,
+    /// Ignore case e.g. `:Token` matches a var named `token`
+    pub case_insensitive: bool,
+}
+
+impl LookupOptions {
+    /// If a name in the env matches an identifier's lookup name
+    pub fn matches(&self, name: &str, identifier: &str) -> bool {
+        let name = if self.trim { name.trim() } else { name };
+
+        if self.case_insensitive {
+            name.to_lowercase() == identifier.to_lowercase()
+        } else {
+            name == identifier
+        }
+    }
+}
+
+/// A named expression added with [`CompileTimeEnv::define`]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Def {
+    name: String,
+    /// Source form of the defined expression
+    source: String,
+    bytecode: ExprByteCode,
+    returns: Type,
+}
+
+impl Def {
+    /// Type of the definition as a function taking no args
+    pub(crate) fn signature(&self) -> Type {
+        Type::Fn {
+            args: vec![],
+            variadic_arg: None,
+            returns: self.returns.clone().into(),
+        }
+    }
 }
 
 impl Default for CompileTimeEnv {
     fn default() -> Self {
+        let mut builtins = BuiltinFn::DEFAULT_BUILTINS.to_vec();
+
+        builtins.extend_from_slice(BuiltinGroup::Math.builtins());
+
+        #[cfg(feature = "time")]
+        builtins.extend_from_slice(BuiltinGroup::Time.builtins());
+
+        #[cfg(feature = "json")]
+        builtins.extend_from_slice(BuiltinGroup::Json.builtins());
+
         Self {
-            builtins: BuiltinFn::DEFAULT_BUILTINS.to_vec(),
+            builtins,
             user_builtins: vec![],
+            defs: vec![],
             vars: vec![],
             prompts: vec![],
             secrets: vec![],
             client_context: vec![],
+            lookup_options: LookupOptions::default(),
         }
     }
 }
@@ -85,18 +292,16 @@ impl CompileTimeEnv {
         }
     }
 
-    pub fn get_builtin_index(&self, name: &str) -> Option<(&BuiltinFn, u8)> {
+    pub fn get_builtin_index(&self, name: &str) -> Option<(&BuiltinFn<'_>, usize)> {
         let index = self.builtins.iter().position(|x| x.name == name);
 
-        
-        index.map(|i| (self.builtins.get(i).unwrap(), i as u8))
+        index.map(|i| (self.builtins.get(i).unwrap(), i))
     }
 
-    pub fn get_user_builtin_index(&self, name: &str) -> Option<(&BuiltinFn, u8)> {
+    pub fn get_user_builtin_index(&self, name: &str) -> Option<(&BuiltinFn<'_>, usize)> {
         let index = self.user_builtins.iter().position(|x| x.name == name);
 
-        
-        index.map(|i| (self.user_builtins.get(i).unwrap(), i as u8))
+        index.map(|i| (self.user_builtins.get(i).unwrap(), i))
     }
 
     pub fn add_user_builtins(&mut self, builtins: Vec<BuiltinFn<'static>>) {
@@ -105,8 +310,158 @@ impl CompileTimeEnv {
         }
     }
 
+    /// Add a user builtin, placing it in [`FnNamespace::User`]
     pub fn add_user_builtin(&mut self, builtin: BuiltinFn<'static>) {
-        self.user_builtins.push(builtin);
+        self.user_builtins.push(BuiltinFn {
+            namespace: FnNamespace::User,
+            ..builtin
+        });
+    }
+
+    /// Remove a group of builtins so expressions can't call them
+    ///
+    /// Builtin lookup indexes change so expressions must be compiled after
+    /// the group is disabled.
+    pub fn disable_builtin_group(&mut self, group: BuiltinGroup) {
+        let disabled = group.builtins();
+
+        self.builtins
+            .retain(|builtin| !disabled.iter().any(|d| d.name == builtin.name));
+    }
+
+    /// Restrict the environment to builtins that are safe for evaluating
+    /// untrusted expressions
+    ///
+    /// Only the pure string, bool, and number builtins (the defaults and
+    /// [`BuiltinGroup::Math`]) are kept. Builtins that read the clock or parse
+    /// JSON, and all user builtins, are removed. Pair with
+    /// [`VmConfig::sandboxed`](crate::vm::VmConfig::sandboxed).
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]).sandboxed();
+    ///
+    /// assert!(env.get_builtin_index("concat").is_some());
+    /// assert_eq!(Some(0), env.get_var_index("a"));
+    /// ```
+    pub fn sandboxed(mut self) -> Self {
+        #[cfg(feature = "time")]
+        self.disable_builtin_group(BuiltinGroup::Time);
+
+        #[cfg(feature = "json")]
+        self.disable_builtin_group(BuiltinGroup::Json);
+
+        self.user_builtins.clear();
+
+        self
+    }
+
+    /// Match identifiers to names in the env using [`LookupOptions`]
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let env = CompileTimeEnv::new(vec![" Token ".to_string()], vec![], vec![], vec![]);
+    /// assert_eq!(None, env.get_var_index("token"));
+    ///
+    /// let env = env.with_lookup_options(LookupOptions {
+    ///     trim: true,
+    ///     case_insensitive: true,
+    /// });
+    /// assert_eq!(Some(0), env.get_var_index("token"));
+    /// ```
+    pub fn with_lookup_options(mut self, lookup_options: LookupOptions) -> Self {
+        self.lookup_options = lookup_options;
+
+        self
+    }
+
+    pub fn lookup_options(&self) -> &LookupOptions {
+        &self.lookup_options
+    }
+
+    /// Define a name from a `(def name expr)` expression for later
+    /// expressions to call with `(name)`
+    ///
+    /// The expression is compiled once and its bytecode returned. Calls
+    /// splice a copy of its op codes in to the caller so bytecode doesn't
+    /// depend on the definitions it was compiled with. Names can't shadow
+    /// builtins, user builtins, or earlier definitions. Definitions aren't
+    /// serialized with the environment.
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let vars = vec!["first".to_string(), "last".to_string()];
+    /// let mut env = CompileTimeEnv::new(vars, vec![], vec![], vec![]);
+    ///
+    /// let def = "(def full_name (concat :first ` ` :last))";
+    /// env.define(&mut (parse(def).unwrap(), 0..def.len())).unwrap();
+    ///
+    /// let source = "(uppercase (full_name))";
+    /// let bytecode = compile(&mut (parse(source).unwrap(), 0..source.len()), &env).unwrap();
+    ///
+    /// let runtime_env = RuntimeEnv {
+    ///     vars: vec!["Ada".to_string(), "Lovelace".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     Ok(Value::String("ADA LOVELACE".into())),
+    ///     Vm::new().interpret(bytecode.into(), &env, &runtime_env)
+    /// );
+    /// ```
+    pub fn define(&mut self, (expr, span): &mut ExprS) -> ExprResult<ExprByteCode> {
+        let invalid_def = |span: &Span| vec![(CompileError::InvalidDef.into(), span.clone())];
+
+        let Expr::Call(call) = expr else {
+            return Err(invalid_def(span));
+        };
+
+        if !call.is_def() {
+            return Err(invalid_def(span));
+        }
+
+        let [(Expr::Identifier(name), name_span), body] = call.args.as_mut_slice() else {
+            return Err(invalid_def(span));
+        };
+
+        if name.identifier_kind() != &IdentifierKind::Builtin {
+            return Err(invalid_def(name_span));
+        }
+
+        let name = name.lookup_name().to_string();
+
+        if self.is_defined(&name) {
+            return Err(vec![(
+                CompileError::AlreadyDefined(name).into(),
+                name_span.clone(),
+            )]);
+        }
+
+        let bytecode = compile(body, self)?;
+
+        self.defs.push(Def {
+            name,
+            source: body.0.to_string(),
+            bytecode: bytecode.clone(),
+            returns: body.0.get_type(),
+        });
+
+        Ok(bytecode)
+    }
+
+    /// If a name is reserved or names a builtin, user builtin, or definition
+    fn is_defined(&self, name: &str) -> bool {
+        ["def", "if", "let", "use"].contains(&name)
+            || self.get_builtin_index(name).is_some()
+            || self.get_user_builtin_index(name).is_some()
+            || self.get_def(name).is_some()
+    }
+
+    pub(crate) fn get_def(&self, name: &str) -> Option<&Def> {
+        self.defs.iter().find(|def| def.name == name)
     }
 
     pub fn get_builtin(&self, index: usize) -> Option<&BuiltinFn<'static>> {
@@ -117,17 +472,30 @@ impl CompileTimeEnv {
         self.user_builtins.get(index)
     }
 
+    pub fn vars(&self) -> &[String] {
+        &self.vars
+    }
+
+    pub fn prompts(&self) -> &[String] {
+        &self.prompts
+    }
+
+    pub fn secrets(&self) -> &[String] {
+        &self.secrets
+    }
+
+    pub fn client_context(&self) -> &[String] {
+        &self.client_context
+    }
+
     pub fn get_var(&self, index: usize) -> Option<&String> {
         self.vars.get(index)
     }
 
     pub fn get_var_index(&self, name: &str) -> Option<usize> {
-        
-
-        self
-            .vars
+        self.vars
             .iter()
-            .position(|context_name| context_name == name)
+            .position(|context_name| self.lookup_options.matches(context_name, name))
     }
 
     pub fn get_prompt(&self, index: usize) -> Option<&String> {
@@ -135,12 +503,9 @@ impl CompileTimeEnv {
     }
 
     pub fn get_prompt_index(&self, name: &str) -> Option<usize> {
-        
-
-        self
-            .prompts
+        self.prompts
             .iter()
-            .position(|context_name| context_name == name)
+            .position(|context_name| self.lookup_options.matches(context_name, name))
     }
 
     pub fn get_secret(&self, index: usize) -> Option<&String> {
@@ -148,12 +513,9 @@ impl CompileTimeEnv {
     }
 
     pub fn get_secret_index(&self, name: &str) -> Option<usize> {
-        
-
-        self
-            .secrets
+        self.secrets
             .iter()
-            .position(|context_name| context_name == name)
+            .position(|context_name| self.lookup_options.matches(context_name, name))
     }
 
     pub fn get_client_context(&self, index: usize) -> Option<&String> {
@@ -161,7 +523,11 @@ impl CompileTimeEnv {
     }
 
     pub fn add_to_client_context(&mut self, key: &str) -> usize {
-        match self.client_context.iter().position(|x| x == key) {
+        match self
+            .client_context
+            .iter()
+            .position(|x| self.lookup_options.matches(x, key))
+        {
             Some(i) => i,
             None => {
                 self.client_context.push(key.to_string());
@@ -171,24 +537,430 @@ impl CompileTimeEnv {
         }
     }
 
-    pub fn get_client_context_index(&self, name: &str) -> Option<(&String, u8)> {
+    pub fn get_client_context_index(&self, name: &str) -> Option<(&String, usize)> {
         let index = self
             .client_context
             .iter()
-            .position(|context_name| context_name == name);
+            .position(|context_name| self.lookup_options.matches(context_name, name));
+
+        index.map(|i| (self.client_context.get(i).unwrap(), i))
+    }
+
+    /// Kind of sigiled identifier a bare name is in the env, if any
+    ///
+    /// Vars are checked first, followed by prompts, secrets, and client
+    /// context. Used to suggest the sigil a bare name is missing.
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let env = CompileTimeEnv::new(vec![], vec![], vec!["token".to_string()], vec![]);
+    ///
+    /// assert_eq!(Some(IdentifierKind::Secret), env.get_sigiled_kind("token"));
+    /// assert_eq!(None, env.get_sigiled_kind("concat"));
+    /// ```
+    pub fn get_sigiled_kind(&self, name: &str) -> Option<IdentifierKind> {
+        if self.get_var_index(name).is_some() {
+            Some(IdentifierKind::Var)
+        } else if self.get_prompt_index(name).is_some() {
+            Some(IdentifierKind::Prompt)
+        } else if self.get_secret_index(name).is_some() {
+            Some(IdentifierKind::Secret)
+        } else if self.get_client_context_index(name).is_some() {
+            Some(IdentifierKind::Client)
+        } else {
+            None
+        }
+    }
+
+    /// Every identifier expressions can reference, with their kind and type
+    ///
+    /// Identifiers include their sigil (e.g. `:name` for vars). Builtins are
+    /// listed first, followed by user builtins, definitions, vars, prompts,
+    /// secrets, and client context. Useful for driving completions in editors
+    /// and REPLs.
+    ///
+    /// ```
+    /// use reqlang_expr::{ast::IdentifierKind, prelude::*};
+    ///
+    /// let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+    ///
+    /// let identifiers = env.identifiers();
+    ///
+    /// assert_eq!(
+    ///     Some(&(":a".to_string(), IdentifierKind::Var, Type::String)),
+    ///     identifiers.last()
+    /// );
+    /// ```
+    pub fn identifiers(&self) -> Vec<(String, IdentifierKind, Type)> {
+        let builtins = self
+            .builtins
+            .iter()
+            .chain(&self.user_builtins)
+            .map(|builtin| {
+                (
+                    builtin.name.to_string(),
+                    IdentifierKind::Builtin,
+                    builtin.clone().into(),
+                )
+            });
+
+        let defs = self
+            .defs
+            .iter()
+            .map(|def| (def.name.clone(), IdentifierKind::Builtin, def.signature()));
+
+        let names = [
+            (&self.vars, IdentifierKind::Var, Type::String),
+            (&self.prompts, IdentifierKind::Prompt, Type::String),
+            (&self.secrets, IdentifierKind::Secret, Type::String),
+            (&self.client_context, IdentifierKind::Client, Type::Value),
+        ]
+        .into_iter()
+        .flat_map(|(names, kind, ty)| {
+            let sigil = kind.sigil().expect("should have a sigil");
+
+            names
+                .iter()
+                .map(move |name| (format!("{sigil}{name}"), kind.clone(), ty.clone()))
+        });
+
+        builtins.chain(defs).chain(names).collect()
+    }
+
+    /// A hash of everything in the environment that affects compilation
+    ///
+    /// Two environments with the same fingerprint compile the same source to
+    /// the same bytecode. The hash is stable across processes, platforms, and
+    /// versions of Rust so it can be saved alongside bytecode.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+
+        let builtins = |builtins: &[BuiltinFn<'static>]| {
+            builtins
+                .iter()
+                .map(|builtin| builtin.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        for names in [
+            &builtins(&self.builtins),
+            &builtins(&self.user_builtins),
+            &self.vars,
+            &self.prompts,
+            &self.secrets,
+            &self.client_context,
+        ] {
+            hasher.write(&(names.len() as u64).to_le_bytes());
+
+            for name in names {
+                hasher.write(&(name.len() as u64).to_le_bytes());
+                hasher.write(name.as_bytes());
+            }
+        }
+
+        // Only hashed if there are any so fingerprints saved before
+        // definitions existed still match
+        for def in &self.defs {
+            for part in [&def.name, &def.source] {
+                hasher.write(&(part.len() as u64).to_le_bytes());
+                hasher.write(part.as_bytes());
+            }
+        }
+
+        // Likewise only hashed if they aren't the default
+        if self.lookup_options != LookupOptions::default() {
+            hasher.write(&[
+                self.lookup_options.trim as u8,
+                self.lookup_options.case_insensitive as u8,
+            ]);
+        }
+
+        hasher.finish()
+    }
+}
+
+/// 64 bit FNV-1a hash
+///
+/// Unlike [`std::hash::DefaultHasher`], its output is specified and won't
+/// change between versions of Rust.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// [`CompileTimeEnv`] as it's serialized
+///
+/// Builtins are serialized as their name and signature since function
+/// pointers can't be.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "CompileTimeEnv")]
+struct SerializedEnv {
+    builtins: Vec<SerializedBuiltin>,
+    user_builtins: Vec<SerializedBuiltin>,
+    vars: Vec<String>,
+    prompts: Vec<String>,
+    secrets: Vec<String>,
+    client_context: Vec<String>,
+    #[serde(default)]
+    lookup_options: LookupOptions,
+}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedBuiltin {
+    name: String,
+    signature: String,
+}
+
+#[cfg(feature = "serde")]
+impl From<&BuiltinFn<'static>> for SerializedBuiltin {
+    fn from(builtin: &BuiltinFn<'static>) -> Self {
+        Self {
+            name: builtin.name.to_string(),
+            signature: builtin.to_string(),
+        }
+    }
+}
+
+/// Names and builtin signatures
+///
+/// Builtins are resolved by name from the builtins this crate provides when
+/// deserializing. Deserialize with a [`CompileTimeEnvSeed`] to resolve the
+/// host's user builtins too. Deserializing fails if a builtin's signature
+/// has changed.
+#[cfg(feature = "serde")]
+impl serde::Serialize for CompileTimeEnv {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedEnv {
+            builtins: self.builtins.iter().map(Into::into).collect(),
+            user_builtins: self.user_builtins.iter().map(Into::into).collect(),
+            vars: self.vars.clone(),
+            prompts: self.prompts.clone(),
+            secrets: self.secrets.clone(),
+            client_context: self.client_context.clone(),
+            lookup_options: self.lookup_options,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Fails on user builtins that aren't builtins this crate provides, see
+/// [`CompileTimeEnvSeed`]
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CompileTimeEnv {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::DeserializeSeed;
+
+        CompileTimeEnvSeed { user_builtins: &[] }.deserialize(deserializer)
+    }
+}
+
+/// Deserializes a [`CompileTimeEnv`], resolving its user builtins against
+/// the host's
+///
+/// Function pointers can't be serialized so the host passes the user
+/// builtins it would add with [`CompileTimeEnv::add_user_builtin`]. Each
+/// serialized user builtin is resolved by name from `user_builtins`, then
+/// from the builtins this crate provides.
+///
+/// ```
+/// use reqlang_expr::prelude::*;
+/// use serde::de::DeserializeSeed;
+///
+/// const GREET: BuiltinFn<'static> = BuiltinFn {
+///     name: "greet",
+///     ..BuiltinFn::NOOP
+/// };
+///
+/// let mut env = CompileTimeEnv::default();
+/// env.add_user_builtin(GREET);
+///
+/// let json = serde_json::to_string(&env).unwrap();
+///
+/// let seed = CompileTimeEnvSeed { user_builtins: &[GREET] };
+/// let deserialized = seed
+///     .deserialize(&mut serde_json::Deserializer::from_str(&json))
+///     .unwrap();
+///
+/// assert_eq!(env.fingerprint(), deserialized.fingerprint());
+/// ```
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy)]
+pub struct CompileTimeEnvSeed<'a> {
+    pub user_builtins: &'a [BuiltinFn<'static>],
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::DeserializeSeed<'de> for CompileTimeEnvSeed<'_> {
+    type Value = CompileTimeEnv;
+
+    fn deserialize<D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        use serde::de::Error;
+
+        let env = <SerializedEnv as serde::Deserialize>::deserialize(deserializer)?;
+        let library = CompileTimeEnv::default();
+
+        let resolve = |builtins: Vec<SerializedBuiltin>,
+                       host: &[BuiltinFn<'static>],
+                       namespace: FnNamespace| {
+            builtins
+                .into_iter()
+                .map(|serialized| {
+                    let builtin = host
+                        .iter()
+                        .chain(&library.builtins)
+                        .find(|builtin| builtin.name == serialized.name)
+                        .ok_or_else(|| {
+                            D::Error::custom(format!("unknown builtin: {}", serialized.name))
+                        })?;
+
+                    if builtin.to_string() != serialized.signature {
+                        return Err(D::Error::custom(format!(
+                            "builtin has signature {builtin} but {} was expected",
+                            serialized.signature
+                        )));
+                    }
+
+                    Ok(BuiltinFn {
+                        namespace,
+                        ..builtin.clone()
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(CompileTimeEnv {
+            builtins: resolve(env.builtins, &[], FnNamespace::Builtin)?,
+            user_builtins: resolve(env.user_builtins, self.user_builtins, FnNamespace::User)?,
+            defs: vec![],
+            vars: env.vars,
+            prompts: env.prompts,
+            secrets: env.secrets,
+            client_context: env.client_context,
+            lookup_options: env.lookup_options,
+        })
+    }
+}
+
+/// The builtin this crate provides named `name`, placed in `namespace`
+///
+/// Used to resolve functions that were serialized by name.
+#[cfg(feature = "serde")]
+pub(crate) fn library_builtin(name: &str, namespace: FnNamespace) -> Option<BuiltinFn<'static>> {
+    CompileTimeEnv::default()
+        .builtins
+        .into_iter()
+        .find(|builtin| builtin.name == name)
+        .map(|builtin| BuiltinFn {
+            namespace,
+            ..builtin
+        })
+}
+
+/// [`ExprByteCode`] as it's serialized
+///
+/// The max stack depth is computed from the codes when deserializing.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "ExprByteCode")]
+struct SerializedByteCode {
+    version: [u8; 4],
+    codes: Vec<u8>,
+    constants: Vec<Value>,
+    types: Vec<Type>,
+    #[serde(default)]
+    call_spans: Vec<(usize, Span)>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ExprByteCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedByteCode {
+            version: self.version,
+            codes: self.codes.clone(),
+            constants: self.constants.clone(),
+            types: self.types.clone(),
+            call_spans: self.call_spans.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Fails on bytecode compiled by a different version or that doesn't
+/// [verify](ExprByteCode::verify), like [`ExprByteCode::from_bytes`]
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ExprByteCode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        use crate::errors::BytecodeError;
 
-        
-        index.map(|i| (self.client_context.get(i).unwrap(), i as u8))
+        let bytecode = SerializedByteCode::deserialize(deserializer)?;
+
+        if bytecode.version != get_version_bytes() {
+            let version = bytecode.version.iter().map(|byte| byte.to_string());
+
+            return Err(D::Error::custom(BytecodeError::UnsupportedVersion(
+                version.collect(),
+            )));
+        }
+
+        let bytecode = Self {
+            version: bytecode.version,
+            max_stack_depth: max_stack_depth(&bytecode.codes),
+            codes: bytecode.codes,
+            constants: bytecode.constants,
+            types: bytecode.types,
+            call_spans: bytecode.call_spans,
+        };
+
+        bytecode.verify().map_err(D::Error::custom)?;
+
+        Ok(bytecode)
     }
 }
 
 /// The compiled bytecode for an expression
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct ExprByteCode {
     version: [u8; 4],
     codes: Vec<u8>,
     constants: Vec<Value>,
     types: Vec<Type>,
+    max_stack_depth: usize,
+    /// Source spans of the `CALL`, `CALL_BUILTIN`, and `CALL_SPREAD` ops, by
+    /// the op's offset
+    call_spans: Vec<(usize, Span)>,
+}
+
+/// Call spans are debug info so bytecode that only differs in them, like
+/// bytecode read back from the [binary format](crate::binary), is equal
+impl PartialEq for ExprByteCode {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.codes == other.codes
+            && self.constants == other.constants
+            && self.types == other.types
+    }
 }
 
 impl ExprByteCode {
@@ -202,15 +974,30 @@ impl ExprByteCode {
         );
 
         let codes = codes[4..].to_vec();
+        let max_stack_depth = max_stack_depth(&codes);
 
         Self {
             version: version_bytes,
             codes,
             constants,
             types,
+            max_stack_depth,
+            call_spans: vec![],
         }
     }
 
+    /// Set the source spans of the `CALL`, `CALL_BUILTIN`, and `CALL_SPREAD`
+    /// ops, each paired with the offset of its op in [`ExprByteCode::codes`]
+    ///
+    /// Errors returned by a call are reported at its span, see
+    /// [`ExprByteCode::call_span`].
+    pub fn with_call_spans(mut self, mut call_spans: Vec<(usize, Span)>) -> Self {
+        call_spans.sort_by_key(|(ip, _)| *ip);
+
+        self.call_spans = call_spans;
+        self
+    }
+
     pub fn version(&self) -> &[u8; 4] {
         &self.version
     }
@@ -226,6 +1013,142 @@ impl ExprByteCode {
     pub fn types(&self) -> &[Type] {
         &self.types
     }
+
+    /// Most values on the stack at once while evaluating, see [`max_stack_depth`]
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    /// Source spans of the `CALL`, `CALL_BUILTIN`, and `CALL_SPREAD` ops, in
+    /// op order
+    pub fn call_spans(&self) -> &[(usize, Span)] {
+        &self.call_spans
+    }
+
+    /// Source span of the call whose op is at offset `ip`
+    ///
+    /// `None` if there isn't a call at `ip` or the bytecode has no spans,
+    /// e.g. it was [assembled](crate::assembler) or read from the
+    /// [binary format](crate::binary).
+    pub fn call_span(&self, ip: usize) -> Option<&Span> {
+        self.call_spans
+            .binary_search_by_key(&ip, |(call_ip, _)| *call_ip)
+            .ok()
+            .map(|index| &self.call_spans[index].1)
+    }
+
+    /// Report unspanned errors returned by the call at offset `ip` at the
+    /// call's span
+    pub(crate) fn span_call_errors(&self, ip: usize, errs: Vec<ExprErrorS>) -> Vec<ExprErrorS> {
+        let Some(call_span) = self.call_span(ip) else {
+            return errs;
+        };
+
+        errs.into_iter()
+            .map(|(err, span)| match span {
+                Span { start: 0, end: 0 } => (err, call_span.clone()),
+                span => (err, span),
+            })
+            .collect()
+    }
+
+    /// If the bytecode contains a `GET` op for the lookup type e.g. [`lookup::SECRET`]
+    pub fn references_lookup(&self, lookup_type: u8) -> bool {
+        !self.referenced_indexes(lookup_type).is_empty()
+    }
+
+    /// Indexes referenced by `GET` ops for the lookup type, in order and without duplicates
+    pub fn referenced_indexes(&self, lookup_type: u8) -> Vec<u8> {
+        let mut indexes = vec![];
+        let mut ip = 0;
+
+        while let Some(op_code) = self.codes.get(ip) {
+            match *op_code {
+                opcode::GET => {
+                    if self.codes.get(ip + 1) == Some(&lookup_type)
+                        && let Some(index) = self.codes.get(ip + 2)
+                        && !indexes.contains(index)
+                    {
+                        indexes.push(*index);
+                    }
+
+                    ip += 3;
+                }
+                opcode::CALL
+                | opcode::CONSTANT
+                | opcode::LIST
+                | opcode::GET_LOCAL
+                | opcode::SET_LOCAL
+                | opcode::CALL_SPREAD => ip += 2,
+                opcode::CALL_BUILTIN
+                | opcode::JUMP_IF_FALSE
+                | opcode::JUMP
+                | opcode::TRY
+                | opcode::END_TRY => ip += 3,
+                _ => ip += 1,
+            }
+        }
+
+        indexes
+    }
+}
+
+/// Most values on the stack at once while evaluating op codes
+///
+/// Adds up each op's [`opcode::stack_effect`]. Ops popping more values than
+/// are on the stack leave it empty and an unknown op code or missing operands
+/// end the count, so invalid code still has a depth. The VM reports those
+/// errors when it runs.
+///
+/// Ops after a `JUMP` or `END_TRY` are only reached by jumping to them, so
+/// they start from the depth at the jump instead of the depth after the ops
+/// jumped over. The fallback ops of a `try` start from the depth at its `TRY`.
+pub fn max_stack_depth(codes: &[u8]) -> usize {
+    let mut ip = 0;
+    let mut depth = 0usize;
+    let mut max_depth = 0;
+    let mut reachable = true;
+    let mut jump_targets: BTreeMap<usize, usize> = BTreeMap::new();
+
+    while let Some(&op_code) = codes.get(ip) {
+        if let Some(target_depth) = jump_targets.remove(&ip) {
+            depth = if reachable {
+                depth.max(target_depth)
+            } else {
+                target_depth
+            };
+
+            reachable = true;
+        }
+
+        let Some(count) = opcode::operand_count(op_code) else {
+            break;
+        };
+
+        let Some(operands) = codes.get(ip + 1..ip + 1 + count) else {
+            break;
+        };
+
+        let Some(effect) = opcode::stack_effect(op_code, operands) else {
+            break;
+        };
+
+        depth = depth.saturating_sub(effect.pops) + effect.pushes;
+        max_depth = max_depth.max(depth);
+
+        ip += 1 + count;
+
+        if let opcode::JUMP_IF_FALSE | opcode::JUMP | opcode::TRY | opcode::END_TRY = op_code
+            && let Some(offset) = opcode::jump_offset(operands)
+        {
+            let target_depth = jump_targets.entry(ip + offset).or_default();
+            *target_depth = depth.max(*target_depth);
+
+            reachable = !matches!(op_code, opcode::JUMP | opcode::END_TRY);
+        }
+    }
+
+    max_depth
 }
 
 pub fn get_version_bytes() -> [u8; 4] {
@@ -237,240 +1160,675 @@ pub fn get_version_bytes() -> [u8; 4] {
     ]
 }
 
+/// Options controlling how expressions are compiled
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompileOptions {
+    /// Fuse calls to builtins in to a single `CALL_BUILTIN` op
+    ///
+    /// `GET BUILTIN $INDEX` ... `CALL $ARG_COUNT` becomes
+    /// ... `CALL_BUILTIN $INDEX $ARG_COUNT`, skipping the push and pop of the
+    /// builtin's [`Value::Fn`] at runtime.
+    pub fuse_calls: bool,
+    /// Maximum nesting depth of call expressions before
+    /// [`CompileError::TooDeeplyNested`], unlimited if `None`
+    ///
+    /// A call that isn't an argument of another call has a depth of 1.
+    pub max_depth: Option<usize>,
+}
+
 /// Compile an [`ast::Expr`] into [`ExprByteCode`]
 pub fn compile(expr: &mut ExprS, env: &CompileTimeEnv) -> ExprResult<ExprByteCode> {
+    compile_with_options(expr, env, &CompileOptions::default())
+}
+
+/// Compile an [`crate::ast::Expr`] into [`ExprByteCode`] using [`CompileOptions`]
+///
+/// With the `tracing` feature the `source_hash` field of the `compile` span
+/// hashes the expression's source form.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "compile",
+        level = "debug",
+        skip_all,
+        fields(source_hash = crate::cache::source_hash(&expr.0.to_string()))
+    )
+)]
+pub fn compile_with_options(
+    expr: &mut ExprS,
+    env: &CompileTimeEnv,
+    options: &CompileOptions,
+) -> ExprResult<ExprByteCode> {
+    let start = Instant::now();
+
     let mut constants: Vec<Value> = vec![];
     let mut types: Vec<Type> = vec![];
     let mut codes = vec![];
 
     codes.extend(get_version_bytes());
 
-    codes.extend(compile_expr(expr, env, &mut constants, &mut types)?);
+    if let Some(max_depth) = options.max_depth
+        && let Some(span) = too_deeply_nested(expr, max_depth, 0)
+    {
+        return Err(vec![(
+            CompileError::TooDeeplyNested { max: max_depth }.into(),
+            span,
+        )]);
+    }
 
-    Ok(ExprByteCode::new(codes, constants, types))
-}
+    let mut calls = vec![];
+
+    let compiled = compile_expr(
+        expr,
+        env,
+        options,
+        &mut constants,
+        &mut types,
+        &mut vec![],
+        &mut calls,
+    )
+    .map_err(first_pool_overflows);
+
+    #[cfg(feature = "tracing")]
+    if let Err(errs) = &compiled {
+        tracing::debug!(errors = errs.len(), "compilation failed");
+    }
 
-fn compile_expr(
-    (expr, span): &mut ExprS,
-    env: &CompileTimeEnv,
-    constants: &mut Vec<Value>,
-    types: &mut Vec<Type>,
-) -> ExprResult<Vec<u8>> {
-    use opcode::*;
+    metrics().compiled(compiled.is_ok(), start.elapsed());
 
-    let mut codes = vec![];
-    let mut errs: Vec<ExprErrorS> = vec![];
+    codes.extend(compiled?);
 
-    add_type_to_expr(expr, env);
+    let bytecode = ExprByteCode::new(codes, constants, types);
+    let call_ips = call_ips(bytecode.codes());
 
-    match expr {
-        Expr::String(string) => {
-            if let Some(index) = constants.iter().position(|x| {
-                if let Value::String(string_constant) = x {
-                    string_constant == &string.0
-                } else {
-                    false
-                }
-            }) {
-                codes.push(CONSTANT);
-                codes.push(index as u8);
-            } else {
-                constants.push(Value::String(string.0.clone()));
-                let index = constants.len() - 1;
-                codes.push(CONSTANT);
-                codes.push(index as u8);
+    debug_assert_eq!(call_ips.len(), calls.len(), "every call should have a span");
+
+    Ok(bytecode.with_call_spans(call_ips.into_iter().zip(calls).collect()))
+}
+
+/// Drop all but the first [`CompileError::TooManyConstants`] and
+/// [`CompileError::TooManyTypes`]
+///
+/// Every constant or type past the limit fails to compile but only the first
+/// one to overflow is reported.
+fn first_pool_overflows(errs: Vec<ExprErrorS>) -> Vec<ExprErrorS> {
+    let mut constants_overflowed = false;
+    let mut types_overflowed = false;
+
+    errs.into_iter()
+        .filter(|(err, _)| match err {
+            ExprError::CompileError(CompileError::TooManyConstants { .. }) => {
+                !std::mem::replace(&mut constants_overflowed, true)
+            }
+            ExprError::CompileError(CompileError::TooManyTypes { .. }) => {
+                !std::mem::replace(&mut types_overflowed, true)
             }
+            _ => true,
+        })
+        .collect()
+}
+
+/// Offsets of the `CALL`, `CALL_BUILTIN`, and `CALL_SPREAD` ops, in order
+fn call_ips(codes: &[u8]) -> Vec<usize> {
+    let mut ips = vec![];
+    let mut ip = 0;
+
+    while let Some(&op_code) = codes.get(ip) {
+        if let opcode::CALL | opcode::CALL_BUILTIN | opcode::CALL_SPREAD = op_code {
+            ips.push(ip);
         }
-        Expr::Number(number) => {
-            if let Some(index) = constants.iter().position(|x| {
-                if let Value::Number(value) = x {
-                    value == &number.0
-                } else {
-                    false
-                }
-            }) {
-                codes.push(CONSTANT);
-                codes.push(index as u8);
-            } else {
-                constants.push(Value::Number(number.0));
-                let index = constants.len() - 1;
-                codes.push(CONSTANT);
-                codes.push(index as u8);
+
+        let Some(count) = opcode::operand_count(op_code) else {
+            break;
+        };
+
+        ip += 1 + count;
+    }
+
+    ips
+}
+
+/// Compile each [`crate::ast::Expr`] from [`parse_many`](crate::parser::parse_many)
+/// into its own [`ExprByteCode`]
+///
+/// Expressions are compiled independently so one failing doesn't stop the
+/// rest from compiling. Results are returned in the same order as `exprs`.
+///
+/// `(def name expr)` expressions are [defined](CompileTimeEnv::define) for
+/// the expressions after them to call. Their result is the defined
+/// expression's bytecode. `env` isn't changed.
+///
+/// ```
+/// use reqlang_expr::prelude::*;
+///
+/// let source = "(def greeting `hello`) (concat (greeting) ` world`)";
+/// let env = CompileTimeEnv::default();
+///
+/// let mut exprs = parse_many(source).unwrap();
+/// let bytecodes = compile_many(&mut exprs, &env);
+///
+/// let results = Vm::new().eval_many(bytecodes, &env, &RuntimeEnv::default());
+///
+/// assert_eq!(Ok(Value::String("hello world".into())), results[1]);
+/// ```
+///
+/// `(use `path`)` expressions fail with [`CompileError::UnresolvedModule`],
+/// use [`compile_many_with_resolver`] to import modules.
+pub fn compile_many(exprs: &mut [ExprS], env: &CompileTimeEnv) -> Vec<ExprResult<ExprByteCode>> {
+    compile_many_with_resolver(exprs, env, &NoModuleResolver)
+}
+
+/// [`compile_many`], importing the definitions of modules with
+/// `(use `path`)`
+///
+/// Modules are loaded by a [`ModuleResolver`] and can only contain
+/// definitions and `use`s of other modules. Their definitions are defined
+/// for the expressions after the `use` to call, like they were defined in
+/// the program. A `use`'s result is the list of names the module defined.
+/// Using a module more than once only defines its definitions once.
+///
+/// ```
+/// use std::io;
+///
+/// use reqlang_expr::prelude::*;
+///
+/// struct Helpers;
+///
+/// impl ModuleResolver for Helpers {
+///     fn resolve(&self, path: &str) -> io::Result<String> {
+///         match path {
+///             "./helpers.expr" => Ok("(def bearer (concat `Bearer ` !token))".to_string()),
+///             _ => Err(io::ErrorKind::NotFound.into()),
+///         }
+///     }
+/// }
+///
+/// let source = "(use `./helpers.expr`) (bearer)";
+/// let env = CompileTimeEnv::new(vec![], vec![], vec!["token".to_string()], vec![]);
+///
+/// let mut exprs = parse_many(source).unwrap();
+/// let bytecodes = compile_many_with_resolver(&mut exprs, &env, &Helpers);
+///
+/// let runtime_env = RuntimeEnv {
+///     secrets: vec!["abc".to_string()],
+///     ..Default::default()
+/// };
+///
+/// let results = Vm::new().eval_many(bytecodes, &env, &runtime_env);
+///
+/// assert_eq!(Ok(Value::String("Bearer abc".into())), results[1]);
+/// ```
+pub fn compile_many_with_resolver(
+    exprs: &mut [ExprS],
+    env: &CompileTimeEnv,
+    resolver: &dyn ModuleResolver,
+) -> Vec<ExprResult<ExprByteCode>> {
+    let mut env = env.clone();
+    let mut modules = ModuleLoader::new(resolver);
+
+    exprs
+        .iter_mut()
+        .map(|expr| match &expr.0 {
+            Expr::Call(call) if call.is_def() => env.define(expr),
+            Expr::Call(call) if call.is_use() => modules.use_module(&mut env, expr),
+            _ => compile(expr, &env),
+        })
+        .collect()
+}
+
+/// Definition a call is calling, if its callee names one
+fn called_def<'a>(call: &ExprCall, env: &'a CompileTimeEnv) -> Option<&'a Def> {
+    match &call.callee.0 {
+        Expr::Identifier(identifier)
+            if identifier.identifier_kind() == &IdentifierKind::Builtin =>
+        {
+            env.get_def(identifier.lookup_name())
+        }
+        _ => None,
+    }
+}
+
+/// Copy a definition's op codes, moving the constants and types they
+/// reference in to the caller's pools
+///
+/// The definition's local slots are moved past the `live_locals` slots
+/// bound by the caller's `let`s so they don't overwrite them.
+fn splice_def(
+    def: &Def,
+    constants: &mut Vec<Value>,
+    types: &mut Vec<Type>,
+    calls: &mut Vec<Span>,
+    live_locals: usize,
+    span: &Span,
+) -> ExprResult<Vec<u8>> {
+    use opcode::*;
+
+    let def_codes = def.bytecode.codes();
+    let mut codes = Vec::with_capacity(def_codes.len());
+    let mut ip = 0;
+
+    while let Some(&op_code) = def_codes.get(ip) {
+        let count = operand_count(op_code).expect("definitions should compile to known ops");
+        let mut operands = def_codes[ip + 1..ip + 1 + count].to_vec();
+
+        match (op_code, operands.as_mut_slice()) {
+            (CONSTANT, [index]) => {
+                let constant = &def.bytecode.constants()[*index as usize];
+
+                let index = constants
+                    .iter()
+                    .position(|existing| existing == constant)
+                    .unwrap_or_else(|| {
+                        constants.push(constant.clone());
+                        constants.len() - 1
+                    });
+
+                operands[0] = constant_operand(index, span)?;
+            }
+            (GET, [TYPE, index]) => {
+                let ty = &def.bytecode.types()[*index as usize];
+
+                let index = types
+                    .iter()
+                    .position(|existing| existing == ty)
+                    .unwrap_or_else(|| {
+                        types.push(ty.clone());
+                        types.len() - 1
+                    });
+
+                operands[1] = type_operand(index, span)?;
             }
+            (GET_LOCAL | SET_LOCAL, [slot]) => {
+                operands[0] = slot_operand(*slot as usize + live_locals, span)?;
+            }
+            (CALL | CALL_BUILTIN | CALL_SPREAD, _) => calls.push(span.clone()),
+            _ => {}
         }
-        Expr::Identifier(identifier) => {
-            let identifier_lookup_name = identifier.lookup_name();
-            let identifier_name = identifier.full_name().to_string();
 
-            let identifier_undefined_err = (
-                CompileError::Undefined(identifier_name.clone()).into(),
-                span.clone(),
-            );
+        codes.push(op_code);
+        codes.extend(operands);
 
-            let result = match identifier.identifier_kind() {
-                IdentifierKind::Var => get(&env.vars, identifier_lookup_name).map(|index| {
-                    codes.push(GET);
-                    codes.push(lookup::VAR);
-                    codes.push(index);
-                }),
-                IdentifierKind::Prompt => get(&env.prompts, identifier_lookup_name).map(|index| {
-                    codes.push(GET);
-                    codes.push(lookup::PROMPT);
-                    codes.push(index);
-                }),
-                IdentifierKind::Secret => get(&env.secrets, identifier_lookup_name).map(|index| {
-                    codes.push(GET);
-                    codes.push(lookup::SECRET);
-                    codes.push(index);
-                }),
-                IdentifierKind::Client => {
-                    get(&env.client_context, identifier_lookup_name).map(|index| {
-                        codes.push(GET);
-                        codes.push(lookup::CLIENT_CTX);
-                        codes.push(index);
-                    })
-                }
-                IdentifierKind::Builtin => {
-                    if let Some((_, index)) = env.get_builtin_index(identifier_lookup_name) {
-                        codes.push(GET);
-                        codes.push(lookup::BUILTIN);
-                        codes.push(index);
-
-                        Some(())
-                    } else if let Some((_, index)) =
-                        env.get_user_builtin_index(identifier_lookup_name)
-                    {
-                        codes.push(GET);
-                        codes.push(lookup::USER_BUILTIN);
-                        codes.push(index);
+        ip += 1 + count;
+    }
 
-                        Some(())
-                    } else {
-                        None
-                    }
-                }
-                IdentifierKind::Type => {
-                    let ty = Type::from(&identifier_name);
-                    if let Some(index) = types.iter().position(|x| x == &ty) {
-                        codes.push(GET);
-                        codes.push(TYPE);
-                        codes.push(index as u8);
-                    } else {
-                        types.push(ty);
-                        let index = types.len() - 1;
-                        codes.push(GET);
-                        codes.push(TYPE);
-                        codes.push(index as u8);
-                    }
+    Ok(codes)
+}
 
-                    Some(())
-                }
-            };
+/// Span of the first call nested deeper than `max_depth`
+///
+/// Lists and `let`s don't add to the depth of the calls in them.
+fn too_deeply_nested((expr, span): &ExprS, max_depth: usize, depth: usize) -> Option<Span> {
+    let call = match expr {
+        Expr::Call(call) => call,
+        Expr::List(list) => {
+            return list
+                .items
+                .iter()
+                .find_map(|item| too_deeply_nested(item, max_depth, depth));
+        }
+        Expr::Let(binding) => {
+            return too_deeply_nested(&binding.value, max_depth, depth)
+                .or_else(|| too_deeply_nested(&binding.body, max_depth, depth));
+        }
+        Expr::Spread(spread) => return too_deeply_nested(&spread.list, max_depth, depth),
+        _ => return None,
+    };
+
+    if depth + 1 > max_depth {
+        return Some(span.clone());
+    }
 
-            if result.is_none() {
-                errs.push(identifier_undefined_err);
+    std::iter::once(call.callee.as_ref())
+        .chain(&call.args)
+        .find_map(|arg| too_deeply_nested(arg, max_depth, depth + 1))
+}
+
+/// Operand for the constant at `index` in the constant pool
+fn constant_operand(index: usize, span: &Span) -> ExprResult<u8> {
+    u8::try_from(index).map_err(|_| {
+        vec![(
+            CompileError::TooManyConstants {
+                max: u8::MAX as usize + 1,
+            }
+            .into(),
+            span.clone(),
+        )]
+    })
+}
+
+/// Operand for the type at `index` in the type pool
+fn type_operand(index: usize, span: &Span) -> ExprResult<u8> {
+    u8::try_from(index).map_err(|_| {
+        vec![(
+            CompileError::TooManyTypes {
+                max: u8::MAX as usize + 1,
             }
+            .into(),
+            span.clone(),
+        )]
+    })
+}
+
+/// Error for a call to `builtin` passing the wrong number of args
+///
+/// Calls with too many args report the most args the builtin takes and span
+/// the extra args. Calls with too few span the closing paren. Calls with more
+/// args than fit in an operand are reported as TooManyArgs instead.
+fn wrong_number_of_args(builtin: &BuiltinFn, args: &[ExprS], span: &Span) -> Option<ExprErrorS> {
+    let call_arity = args.len();
+
+    let arity_matches = u8::try_from(call_arity)
+        .ok()
+        .is_none_or(|arity| builtin.arity_matches(arity));
+
+    if arity_matches {
+        return None;
+    }
+
+    let max_arity = builtin.max_arity() as usize;
+
+    let (expected, span) = match args.get(max_arity..) {
+        Some([(_, first), .., (_, last)]) => (max_arity, first.start..last.end),
+        Some([(_, extra)]) => (max_arity, extra.clone()),
+        _ => (
+            builtin.arity() as usize,
+            span.end.saturating_sub(1)..span.end,
+        ),
+    };
+
+    Some((
+        WrongNumberOfArgs {
+            expected,
+            actual: call_arity,
+            signature: builtin.to_string(),
         }
-        Expr::Call(expr_call) => {
-            let callee_bytecode = compile_expr(&mut expr_call.callee, env, constants, types)?;
+        .into(),
+        span,
+    ))
+}
 
-            if let Some(_op) = callee_bytecode.first()
-                && let Some(lookup) = callee_bytecode.get(1)
-                && let Some(index) = callee_bytecode.get(2)
-            {
-                match *lookup {
-                    lookup::BUILTIN => {
-                        let builtin = env.get_builtin((*index).into()).unwrap();
+/// Bare name that's in the env as a sigiled identifier of this kind
+fn missing_sigil(name: &str, kind: &IdentifierKind) -> Option<CompileError> {
+    let lookup = match kind {
+        IdentifierKind::Var => "vars",
+        IdentifierKind::Prompt => "prompts",
+        IdentifierKind::Secret => "secrets",
+        IdentifierKind::Client => "client context values",
+        IdentifierKind::Builtin | IdentifierKind::Type => return None,
+    };
+
+    Some(CompileError::MissingSigil {
+        name: name.to_string(),
+        lookup: lookup.to_string(),
+        sigil: kind.sigil()?,
+    })
+}
+
+/// Compile a call to `cond`, `and`, `or`, or `try` to jumps so only the args
+/// its result depends on are evaluated
+///
+/// `(and a b)` is compiled as `(cond a b false)` and `(or a b)` as
+/// `(cond a true b)`. A `b` that isn't typed `Bool` is compiled as
+/// `(cond b true false)` so it's still checked at runtime. Returns `None` for
+/// other builtins, or if an arg's ops are too long to jump over.
+fn compile_branch(
+    builtin: &BuiltinFn,
+    args: &[ExprS],
+    args_bytecode: &[Vec<u8>],
+) -> Option<Vec<u8>> {
+    use opcode::*;
 
-                        let call_arity: usize = expr_call.args.len();
+    let to_bool = |(arg, _): &ExprS, arg_bytecode: &Vec<u8>| match arg.get_type() {
+        Type::Bool => Some(arg_bytecode.clone()),
+        _ => branch(arg_bytecode.clone(), vec![TRUE], vec![FALSE]),
+    };
 
-                        if !builtin.arity_matches(call_arity.try_into().unwrap()) {
-                            errs.push((
-                                ExprError::CompileError(WrongNumberOfArgs {
-                                    expected: builtin.arity() as usize,
-                                    actual: call_arity,
-                                }),
-                                span.clone(),
-                            ));
-                        }
+    match (builtin.name, args, args_bytecode) {
+        (name, _, [condition, then, otherwise]) if name == BuiltinFn::COND.name => {
+            branch(condition.clone(), then.clone(), otherwise.clone())
+        }
+        (name, [_, b], [a_bytecode, b_bytecode]) if name == BuiltinFn::AND.name => {
+            branch(a_bytecode.clone(), to_bool(b, b_bytecode)?, vec![FALSE])
+        }
+        (name, [_, b], [a_bytecode, b_bytecode]) if name == BuiltinFn::OR.name => {
+            branch(a_bytecode.clone(), vec![TRUE], to_bool(b, b_bytecode)?)
+        }
+        (name, _, [guarded, fallback]) if name == BuiltinFn::TRY.name => {
+            try_fallback(guarded.clone(), fallback.clone())
+        }
+        _ => None,
+    }
+}
 
-                        let args: Vec<_> = expr_call.args.iter().take(call_arity).collect();
-
-                        for (i, fnarg) in builtin.args.iter().enumerate() {
-                            if let Some((a, a_span)) = args.get(i) {
-                                let a_type = a.get_type();
-
-                                let types_match = fnarg.ty == a_type
-                                    || fnarg.ty == Type::Value
-                                    || a_type == Type::Unknown;
-
-                                if !types_match {
-                                    errs.push((
-                                        CompileError::TypeMismatch {
-                                            expected: fnarg.ty.clone(),
-                                            actual: a_type.clone(),
-                                        }
-                                        .into(),
-                                        a_span.clone(),
-                                    ));
-                                }
-                            }
-                        }
-                    }
-                    lookup::USER_BUILTIN => {
-                        let builtin = env.get_user_builtin((*index).into()).unwrap();
+/// `condition`, a `JUMP_IF_FALSE` over `then`, `then`, a `JUMP` over
+/// `otherwise`, and `otherwise`
+///
+/// Returns `None` if `then` or `otherwise` is too long to jump over.
+fn branch(condition: Vec<u8>, then: Vec<u8>, otherwise: Vec<u8>) -> Option<Vec<u8>> {
+    use opcode::*;
 
-                        let call_arity: usize = expr_call.args.len();
+    // The JUMP_IF_FALSE skips the JUMP at the end of `then` too
+    let else_offset = u16::try_from(then.len() + 3).ok()?;
+    let end_offset = u16::try_from(otherwise.len()).ok()?;
 
-                        if !builtin.arity_matches(call_arity.try_into().unwrap()) {
-                            errs.push((
-                                ExprError::CompileError(WrongNumberOfArgs {
-                                    expected: builtin.arity() as usize,
-                                    actual: call_arity,
-                                }),
-                                span.clone(),
-                            ));
-                        }
-                    }
-                    lookup::CLIENT_CTX => {
-                        // No validation needs to be ran at this point
-                        // This won't happen until runtime when the client
-                        // a value.
-                    }
-                    _ => {
-                        errs.push((
-                            CompileError::InvalidLookupType(*lookup).into(),
-                            span.clone(),
-                        ));
-                    }
-                }
+    let mut codes = condition;
+
+    codes.push(JUMP_IF_FALSE);
+    codes.extend(else_offset.to_be_bytes());
+    codes.extend(then);
+    codes.push(JUMP);
+    codes.extend(end_offset.to_be_bytes());
+    codes.extend(otherwise);
+
+    Some(codes)
+}
+
+/// A `TRY` to `fallback`, `guarded`, an `END_TRY` over `fallback`, and
+/// `fallback`
+///
+/// Returns `None` if `guarded` or `fallback` is too long to jump over.
+fn try_fallback(guarded: Vec<u8>, fallback: Vec<u8>) -> Option<Vec<u8>> {
+    use opcode::*;
+
+    // The TRY skips the END_TRY at the end of `guarded` too
+    let fallback_offset = u16::try_from(guarded.len() + 3).ok()?;
+    let end_offset = u16::try_from(fallback.len()).ok()?;
+
+    let mut codes = vec![TRY];
+
+    codes.extend(fallback_offset.to_be_bytes());
+    codes.extend(guarded);
+    codes.push(END_TRY);
+    codes.extend(end_offset.to_be_bytes());
+    codes.extend(fallback);
+
+    Some(codes)
+}
+
+/// Operand for the index of a name in the compile time env
+///
+/// Names past the first 256 of their kind can't be encoded in a `GET`.
+fn name_operand(lookup: &str, index: usize, span: &Span) -> ExprResult<u8> {
+    u8::try_from(index).map_err(|_| {
+        vec![(
+            CompileError::TooManyNames {
+                lookup: lookup.to_string(),
+                max: u8::MAX as usize + 1,
             }
+            .into(),
+            span.clone(),
+        )]
+    })
+}
 
-            codes.extend(callee_bytecode);
+/// Operand for the slot of the next local, nested in `locals`
+fn local_operand(locals: &[String], span: &Span) -> ExprResult<u8> {
+    slot_operand(locals.len(), span)
+}
 
-            for arg in expr_call.args.iter_mut() {
-                match compile_expr(arg, env, constants, types) {
-                    Ok(arg_bytecode) => {
-                        codes.extend(arg_bytecode);
-                    }
-                    Err(err) => {
-                        errs.extend(err);
-                    }
-                }
+/// Operand for a local's slot
+fn slot_operand(slot: usize, span: &Span) -> ExprResult<u8> {
+    u8::try_from(slot).map_err(|_| {
+        vec![(
+            CompileError::TooManyLocals {
+                max: u8::MAX as usize + 1,
             }
+            .into(),
+            span.clone(),
+        )]
+    })
+}
 
-            codes.push(opcode::CALL);
-            codes.push(expr_call.args.len() as u8);
-        }
-        Expr::Bool(value) => match value.0 {
-            true => {
-                codes.push(opcode::TRUE);
+/// Splice in the op codes of the definition a call is calling
+///
+/// Definitions don't take arguments so a call passing any is an error.
+fn compile_def_call(
+    def: &Def,
+    expr_call: &ExprCall,
+    constants: &mut Vec<Value>,
+    types: &mut Vec<Type>,
+    calls: &mut Vec<Span>,
+    live_locals: usize,
+    span: &Span,
+) -> ExprResult<Vec<u8>> {
+    match (expr_call.args.first(), expr_call.args.last()) {
+        (Some((_, first)), Some((_, last))) => Err(vec![(
+            WrongNumberOfArgs {
+                expected: 0,
+                actual: expr_call.args.len(),
+                signature: format!("{}() -> {}", def.name, def.returns.name()),
             }
-            false => {
-                codes.push(opcode::FALSE);
+            .into(),
+            first.start..last.end,
+        )]),
+        _ => splice_def(def, constants, types, calls, live_locals, span),
+    }
+}
+
+/// A `CONSTANT` op for `value`, adding it to `constants` if it isn't already
+fn compile_constant(value: Value, constants: &mut Vec<Value>, span: &Span) -> ExprResult<Vec<u8>> {
+    let index = constants
+        .iter()
+        .position(|existing| existing == &value)
+        .unwrap_or_else(|| {
+            constants.push(value);
+            constants.len() - 1
+        });
+
+    Ok(vec![opcode::CONSTANT, constant_operand(index, span)?])
+}
+
+/// Compile a [`Expr::Identifier`] to a `GET` or `GET_LOCAL`
+///
+/// Kept out of [`compile_expr`] so its locals don't add to the stack frame of
+/// every level of nesting.
+fn compile_identifier(
+    identifier: &ExprIdentifier,
+    span: &Span,
+    env: &CompileTimeEnv,
+    types: &mut Vec<Type>,
+    locals: &[String],
+) -> ExprResult<Vec<u8>> {
+    use opcode::*;
+
+    let mut codes = vec![];
+    let mut errs: Vec<ExprErrorS> = vec![];
+
+    let identifier_lookup_name = identifier.lookup_name();
+    let identifier_name = identifier.full_name().to_string();
+
+    let identifier_undefined_err = (
+        CompileError::Undefined(identifier_name.clone()).into(),
+        span.clone(),
+    );
+    let index_of = |names: &[String], name: &str| {
+        get(names, identifier_lookup_name, &env.lookup_options)
+            .map(|index| name_operand(name, index, span))
+            .transpose()
+    };
+
+    let result = match identifier.identifier_kind() {
+        IdentifierKind::Var => index_of(&env.vars, "var")?.map(|index| {
+            codes.push(GET);
+            codes.push(lookup::VAR);
+            codes.push(index);
+        }),
+        IdentifierKind::Prompt => index_of(&env.prompts, "prompt")?.map(|index| {
+            codes.push(GET);
+            codes.push(lookup::PROMPT);
+            codes.push(index);
+        }),
+        IdentifierKind::Secret => index_of(&env.secrets, "secret")?.map(|index| {
+            codes.push(GET);
+            codes.push(lookup::SECRET);
+            codes.push(index);
+        }),
+        IdentifierKind::Client => index_of(&env.client_context, "client context")?.map(|index| {
+            codes.push(GET);
+            codes.push(lookup::CLIENT_CTX);
+            codes.push(index);
+        }),
+        IdentifierKind::Builtin => {
+            if let Some(slot) = locals
+                .iter()
+                .rposition(|local| local == identifier_lookup_name)
+            {
+                codes.push(GET_LOCAL);
+                codes.push(slot_operand(slot, span)?);
+
+                Some(())
+            } else if let Some((_, index)) = env.get_builtin_index(identifier_lookup_name) {
+                codes.push(GET);
+                codes.push(lookup::BUILTIN);
+                codes.push(name_operand("builtin", index, span)?);
+
+                Some(())
+            } else if let Some((_, index)) = env.get_user_builtin_index(identifier_lookup_name) {
+                codes.push(GET);
+                codes.push(lookup::USER_BUILTIN);
+                codes.push(name_operand("user builtin", index, span)?);
+
+                Some(())
+            } else if env.get_def(identifier_lookup_name).is_some() {
+                errs.push((
+                    CompileError::DefNotCalled(identifier_name.clone()).into(),
+                    span.clone(),
+                ));
+
+                Some(())
+            } else {
+                None
             }
-        },
-        Expr::Error => panic!("tried to compile despite parser errors"),
+        }
+        IdentifierKind::Type if !Type::from(&identifier_name).is_known() => None,
+        IdentifierKind::Type => {
+            let ty = Type::from(&identifier_name);
+            if let Some(index) = types.iter().position(|x| x == &ty) {
+                codes.push(GET);
+                codes.push(TYPE);
+                codes.push(type_operand(index, span)?);
+            } else {
+                types.push(ty);
+                let index = types.len() - 1;
+                codes.push(GET);
+                codes.push(TYPE);
+                codes.push(type_operand(index, span)?);
+            }
+
+            Some(())
+        }
+    };
+
+    if result.is_none() {
+        let missing_sigil = matches!(identifier.identifier_kind(), IdentifierKind::Builtin)
+            .then(|| env.get_sigiled_kind(identifier_lookup_name))
+            .flatten()
+            .and_then(|kind| missing_sigil(&identifier_name, &kind));
+
+        match missing_sigil {
+            Some(err) => errs.push((err.into(), span.clone())),
+            None => errs.push(identifier_undefined_err),
+        }
     }
 
     if !errs.is_empty() {
@@ -480,41 +1838,1627 @@ fn compile_expr(
     Ok(codes)
 }
 
-#[cfg(test)]
-mod compiler_tests {
-    use super::*;
+/// Check the args of a call to a builtin against its signature
+///
+/// Only callees that are a single GET can be checked at compile time.
+fn check_call(
+    callee_bytecode: &[u8],
+    expr_call: &ExprCall,
+    env: &CompileTimeEnv,
+    span: &Span,
+) -> Vec<ExprErrorS> {
+    use opcode::*;
 
-    #[test]
-    pub fn current_version_bytes() {
-        let version_bytes = get_version_bytes();
+    let mut errs: Vec<ExprErrorS> = vec![];
 
-        assert_eq!(version_bytes, [0, 8, 0, 0]);
+    if let Some((Expr::Spread(spread), _)) = expr_call.args.last() {
+        let (list, list_span) = &spread.list;
+        let expected = Type::List(Type::Value.into());
+        let list_type = list.get_type();
+
+        if !expected.accepts(&list_type) {
+            errs.push((
+                CompileError::TypeMismatch {
+                    expected,
+                    actual: list_type,
+                }
+                .into(),
+                list_span.clone(),
+            ));
+        }
     }
 
-    #[test]
-    pub fn valid_bytecode_version_bytes() {
-        let mut codes = get_version_bytes().to_vec();
-        codes.push(opcode::TRUE);
+    if let [GET, lookup, index] = callee_bytecode {
+        match *lookup {
+            lookup::BUILTIN => {
+                let builtin = env.get_builtin((*index).into()).unwrap();
 
-        ExprByteCode::new(codes.to_vec(), vec![], vec![]);
+                if !expr_call.is_spread() {
+                    errs.extend(wrong_number_of_args(builtin, &expr_call.args, span));
+                }
+
+                errs.extend(check_list_fn(builtin, &expr_call.args));
+
+                for (i, fnarg) in builtin.args.iter().enumerate() {
+                    if let Some((a, a_span)) = expr_call.args.get(i) {
+                        let a_type = a.get_type();
+
+                        if !fnarg.ty.accepts(&a_type) {
+                            errs.push((
+                                CompileError::TypeMismatch {
+                                    expected: fnarg.ty.clone(),
+                                    actual: a_type.clone(),
+                                }
+                                .into(),
+                                a_span.clone(),
+                            ));
+                        }
+                    }
+                }
+            }
+            lookup::USER_BUILTIN => {
+                let builtin = env.get_user_builtin((*index).into()).unwrap();
+
+                if !expr_call.is_spread() {
+                    errs.extend(wrong_number_of_args(builtin, &expr_call.args, span));
+                }
+            }
+            lookup::CLIENT_CTX => {
+                // No validation needs to be ran at this point
+                // This won't happen until runtime when the client
+                // a value.
+            }
+            _ => {
+                errs.push((
+                    CompileError::InvalidLookupType(*lookup).into(),
+                    span.clone(),
+                ));
+            }
+        }
+    } else if let Type::Fn {
+        args, variadic_arg, ..
+    } = expr_call.callee.0.get_type()
+    {
+        // Function values with a known signature, e.g. `(compose f g)`
+        for (i, (a, a_span)) in expr_call.args.iter().enumerate() {
+            let Some(expected) = args.get(i).or(variadic_arg.as_deref()) else {
+                break;
+            };
+
+            let a_type = a.get_type();
+
+            if !expected.accepts(&a_type) {
+                errs.push((
+                    CompileError::TypeMismatch {
+                        expected: expected.clone(),
+                        actual: a_type,
+                    }
+                    .into(),
+                    a_span.clone(),
+                ));
+            }
+        }
     }
 
-    #[test]
-    #[should_panic(expected = "Version bytes do not match")]
-    pub fn invalid_bytecode_version_bytes() {
-        let mut codes: Vec<u8> = [0, 0, 0, 0].to_vec();
-        codes.push(opcode::TRUE);
+    errs
+}
 
-        ExprByteCode::new(codes.to_vec(), vec![], vec![]);
+/// Check the function passed to `map`, `filter`, or `reduce` can be called
+/// with the items of the list it's passed
+///
+/// The function's signature is checked against the signature the builtin
+/// calls it with, e.g. `Fn(String) -> Bool` for `filter` with a list of
+/// strings. Functions and lists without a known type are checked at runtime.
+fn check_list_fn(builtin: &BuiltinFn, args: &[ExprS]) -> Vec<ExprErrorS> {
+    let arg_type = |i: usize| args.get(i).map(|(arg, _)| arg.get_type());
+
+    let (list_index, mut expected_args, expected_returns) = match builtin.name {
+        "map" => (1, vec![], Type::Value),
+        "filter" => (1, vec![], Type::Bool),
+        "reduce" => (2, vec![arg_type(1).unwrap_or(Type::Value)], Type::Value),
+        _ => return vec![],
+    };
+
+    let (Some((_, f_span)), Some(f_type @ Type::Fn { .. })) = (args.first(), arg_type(0)) else {
+        return vec![];
+    };
+
+    let Some(Type::List(item)) = arg_type(list_index) else {
+        return vec![];
+    };
+
+    expected_args.push(*item);
+
+    let expected = Type::Fn {
+        args: expected_args,
+        variadic_arg: None,
+        returns: expected_returns.into(),
+    };
+
+    if f_type.can_be_called_as(&expected) {
+        vec![]
+    } else {
+        vec![(
+            CompileError::TypeMismatch {
+                expected,
+                actual: f_type,
+            }
+            .into(),
+            f_span.clone(),
+        )]
     }
+}
 
-    #[test]
-    pub fn get_version_bytes_from_bytecode() {
-        let mut codes = get_version_bytes().to_vec();
-        codes.push(opcode::TRUE);
+/// Compile an expression, resolving bare names to the `let` bindings in
+/// scope before the env
+///
+/// `locals` are the names bound by the enclosing `let`s, outermost first, so
+/// each name's index is its slot.
+///
+/// The span of each `CALL` and `CALL_BUILTIN` op is pushed to `calls` in the
+/// same order as the ops. Calls to definitions use the span of the call to
+/// the definition.
+fn compile_expr(
+    (expr, span): &mut ExprS,
+    env: &CompileTimeEnv,
+    options: &CompileOptions,
+    constants: &mut Vec<Value>,
+    types: &mut Vec<Type>,
+    locals: &mut Vec<String>,
+    calls: &mut Vec<Span>,
+) -> ExprResult<Vec<u8>> {
+    use opcode::*;
 
-        let bytecode = ExprByteCode::new(codes.to_vec(), vec![], vec![]);
+    let mut codes = vec![];
+    let mut errs: Vec<ExprErrorS> = vec![];
 
-        assert_eq!(bytecode.version(), &get_version_bytes());
+    add_type_to_expr(expr, env);
+
+    match expr {
+        Expr::String(string) => {
+            codes.extend(compile_constant(
+                Value::String(string.0.clone().into()),
+                constants,
+                span,
+            )?);
+        }
+        Expr::Number(number) => {
+            codes.extend(compile_constant(Value::Number(number.0), constants, span)?);
+        }
+        Expr::Identifier(identifier) => {
+            match compile_identifier(identifier, span, env, types, locals) {
+                Ok(identifier_bytecode) => codes.extend(identifier_bytecode),
+                Err(err) => errs.extend(err),
+            }
+        }
+        Expr::Call(expr_call) if expr_call.is_def() => {
+            errs.push((CompileError::InvalidDef.into(), span.clone()));
+        }
+        Expr::Call(expr_call) if expr_call.is_use() => {
+            errs.push((CompileError::InvalidUse.into(), span.clone()));
+        }
+        Expr::Call(expr_call) if expr_call.is_let() => {
+            errs.push((CompileError::InvalidLet.into(), span.clone()));
+        }
+        Expr::Call(expr_call) if called_def(expr_call, env).is_some() => {
+            let def = called_def(expr_call, env).expect("should call a definition");
+
+            codes.extend(compile_def_call(
+                def,
+                expr_call,
+                constants,
+                types,
+                calls,
+                locals.len(),
+                span,
+            )?);
+        }
+        Expr::Call(expr_call) => {
+            let callee_bytecode = compile_expr(
+                &mut expr_call.callee,
+                env,
+                options,
+                constants,
+                types,
+                locals,
+                calls,
+            )?;
+
+            errs.extend(check_call(&callee_bytecode, expr_call, env, span));
+
+            let spread = expr_call.is_spread();
+            let last_arg = expr_call.args.len().saturating_sub(1);
+            let mut args_bytecode = vec![];
+
+            for (i, arg) in expr_call.args.iter_mut().enumerate() {
+                // The list of the last arg's `...list` is pushed like any
+                // other arg
+                let arg = match arg {
+                    (Expr::Spread(spread_arg), _) if i == last_arg => &mut spread_arg.list,
+                    arg => arg,
+                };
+
+                match compile_expr(arg, env, options, constants, types, locals, calls) {
+                    Ok(arg_bytecode) => {
+                        args_bytecode.push(arg_bytecode);
+                    }
+                    Err(err) => {
+                        errs.extend(err);
+                    }
+                }
+            }
+
+            let branch_bytecode = match callee_bytecode.as_slice() {
+                [GET, lookup::BUILTIN, index] if errs.is_empty() && !spread => env
+                    .get_builtin((*index).into())
+                    .and_then(|builtin| compile_branch(builtin, &expr_call.args, &args_bytecode)),
+                _ => None,
+            };
+
+            if let Some(branch_bytecode) = branch_bytecode {
+                codes.extend(branch_bytecode);
+
+                return Ok(codes);
+            }
+
+            let fused_builtin_index = match callee_bytecode.as_slice() {
+                [GET, lookup::BUILTIN, index] if options.fuse_calls && !spread => Some(*index),
+                _ => None,
+            };
+
+            if fused_builtin_index.is_none() {
+                codes.extend(callee_bytecode);
+            }
+
+            codes.extend(args_bytecode.concat());
+
+            if let Some(index) = fused_builtin_index {
+                codes.push(opcode::CALL_BUILTIN);
+                codes.push(index);
+            } else if spread {
+                codes.push(opcode::CALL_SPREAD);
+            } else {
+                codes.push(opcode::CALL);
+            }
+
+            calls.push(span.clone());
+
+            match u8::try_from(expr_call.args.len()) {
+                Ok(arg_count) => codes.push(arg_count),
+                Err(_) => errs.push((
+                    CompileError::TooManyArgs {
+                        max: u8::MAX.into(),
+                        actual: expr_call.args.len(),
+                    }
+                    .into(),
+                    span.clone(),
+                )),
+            }
+        }
+        Expr::List(list) => {
+            for item in list.items.iter_mut() {
+                match compile_expr(item, env, options, constants, types, locals, calls) {
+                    Ok(item_bytecode) => codes.extend(item_bytecode),
+                    Err(err) => errs.extend(err),
+                }
+            }
+
+            codes.push(opcode::LIST);
+
+            match u8::try_from(list.items.len()) {
+                Ok(item_count) => codes.push(item_count),
+                Err(_) => errs.push((
+                    CompileError::TooManyItems {
+                        max: u8::MAX.into(),
+                        actual: list.items.len(),
+                    }
+                    .into(),
+                    span.clone(),
+                )),
+            }
+        }
+        Expr::Spread(_) => {
+            errs.push((CompileError::InvalidSpread.into(), span.clone()));
+        }
+        Expr::Let(binding) => {
+            let (name, name_span) = &binding.name;
+
+            if env.is_defined(name) {
+                errs.push((
+                    CompileError::AlreadyDefined(name.clone()).into(),
+                    name_span.clone(),
+                ));
+            }
+
+            match compile_expr(
+                &mut binding.value,
+                env,
+                options,
+                constants,
+                types,
+                locals,
+                calls,
+            ) {
+                Ok(value_bytecode) => codes.extend(value_bytecode),
+                Err(err) => errs.extend(err),
+            }
+
+            codes.push(SET_LOCAL);
+            codes.push(local_operand(locals, span)?);
+
+            locals.push(name.clone());
+
+            let body = compile_expr(
+                &mut binding.body,
+                env,
+                options,
+                constants,
+                types,
+                locals,
+                calls,
+            );
+
+            locals.pop();
+
+            match body {
+                Ok(body_bytecode) => codes.extend(body_bytecode),
+                Err(err) => errs.extend(err),
+            }
+        }
+        Expr::Bool(value) => match value.0 {
+            true => {
+                codes.push(opcode::TRUE);
+            }
+            false => {
+                codes.push(opcode::FALSE);
+            }
+        },
+        Expr::Error => panic!("tried to compile despite parser errors"),
+    }
+
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+
+    Ok(codes)
+}
+
+#[cfg(test)]
+mod compiler_tests {
+    use super::*;
+
+    use crate::errors::ExprError;
+
+    #[test]
+    pub fn current_version_bytes() {
+        let version_bytes = get_version_bytes();
+
+        assert_eq!(version_bytes, [0, 8, 0, 0]);
+    }
+
+    #[test]
+    pub fn valid_bytecode_version_bytes() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::TRUE);
+
+        ExprByteCode::new(codes.to_vec(), vec![], vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Version bytes do not match")]
+    pub fn invalid_bytecode_version_bytes() {
+        let mut codes: Vec<u8> = [0, 0, 0, 0].to_vec();
+        codes.push(opcode::TRUE);
+
+        ExprByteCode::new(codes.to_vec(), vec![], vec![]);
+    }
+
+    #[test]
+    pub fn get_version_bytes_from_bytecode() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::TRUE);
+
+        let bytecode = ExprByteCode::new(codes.to_vec(), vec![], vec![]);
+
+        assert_eq!(bytecode.version(), &get_version_bytes());
+    }
+
+    #[test]
+    pub fn stack_effects() {
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 3, pushes: 1 }),
+            opcode::stack_effect(opcode::CALL, &[2])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 2, pushes: 1 }),
+            opcode::stack_effect(opcode::CALL_BUILTIN, &[7, 2])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 3, pushes: 1 }),
+            opcode::stack_effect(opcode::CALL_SPREAD, &[2])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 0, pushes: 1 }),
+            opcode::stack_effect(opcode::GET, &[lookup::VAR, 0])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 3, pushes: 1 }),
+            opcode::stack_effect(opcode::LIST, &[3])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 0, pushes: 1 }),
+            opcode::stack_effect(opcode::GET_LOCAL, &[0])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 1, pushes: 0 }),
+            opcode::stack_effect(opcode::SET_LOCAL, &[0])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 1, pushes: 0 }),
+            opcode::stack_effect(opcode::JUMP_IF_FALSE, &[0, 4])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 0, pushes: 0 }),
+            opcode::stack_effect(opcode::JUMP, &[1, 0])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 0, pushes: 0 }),
+            opcode::stack_effect(opcode::TRY, &[0, 4])
+        );
+        assert_eq!(
+            Some(opcode::StackEffect { pops: 0, pushes: 0 }),
+            opcode::stack_effect(opcode::END_TRY, &[0, 1])
+        );
+        assert_eq!(None, opcode::stack_effect(opcode::JUMP, &[1]));
+        assert_eq!(None, opcode::stack_effect(opcode::CALL, &[]));
+        assert_eq!(None, opcode::stack_effect(99, &[]));
+    }
+
+    #[test]
+    pub fn max_stack_depth_of_compiled_source() {
+        let env = CompileTimeEnv::default();
+        let source = "(concat `a` (concat `b` `c`) `d`)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert_eq!(5, bytecode.max_stack_depth());
+    }
+
+    #[test]
+    pub fn max_stack_depth_of_branches() {
+        let env = CompileTimeEnv::default();
+
+        for (source, expected) in [
+            ("(cond true (cond false `a` `b`) (cond true `c` `d`))", 1),
+            ("(concat `a` (cond true `b` `c`))", 3),
+            ("(cond true `a` (concat `b` `c`))", 3),
+            ("(concat `a` (try `b` (concat `c` `d`)))", 5),
+        ] {
+            let ast = crate::parser::parse(source).unwrap();
+            let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+            assert_eq!(expected, bytecode.max_stack_depth(), "{source}");
+        }
+    }
+
+    #[test]
+    pub fn max_stack_depth_of_invalid_codes() {
+        assert_eq!(0, max_stack_depth(&[]));
+        assert_eq!(1, max_stack_depth(&[opcode::TRUE, opcode::CALL]));
+        assert_eq!(2, max_stack_depth(&[opcode::TRUE, opcode::TRUE, 99]));
+        assert_eq!(
+            2,
+            max_stack_depth(&[opcode::CALL, 3, opcode::TRUE, opcode::CALL, 0])
+        );
+    }
+
+    #[test]
+    pub fn fuse_calls_to_builtins() {
+        let env = CompileTimeEnv::default();
+        let source = "(concat `a` (noop))";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile_with_options(
+            &mut (ast, 0..source.len()),
+            &env,
+            &CompileOptions {
+                fuse_calls: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let (concat_index, noop_index) = (
+            env.get_builtin_index("concat").unwrap().1 as u8,
+            env.get_builtin_index("noop").unwrap().1 as u8,
+        );
+
+        assert_eq!(
+            &[
+                opcode::CONSTANT,
+                0,
+                opcode::CALL_BUILTIN,
+                noop_index,
+                0,
+                opcode::CALL_BUILTIN,
+                concat_index,
+                2
+            ],
+            bytecode.codes()
+        );
+    }
+
+    fn define(env: &mut CompileTimeEnv, source: &str) -> ExprResult<ExprByteCode> {
+        let ast = crate::parser::parse(source).unwrap();
+
+        env.define(&mut (ast, 0..source.len()))
+    }
+
+    #[test]
+    pub fn calls_to_definitions_splice_their_codes() {
+        let mut env = CompileTimeEnv::default();
+        let greeting = define(&mut env, "(def greeting (concat `hello` Type<String>))").unwrap();
+
+        let source = "(concat `world` (greeting))";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let concat_index = env.get_builtin_index("concat").unwrap().1 as u8;
+
+        assert_eq!(
+            &[
+                opcode::GET,
+                lookup::BUILTIN,
+                concat_index,
+                opcode::CONSTANT,
+                0,
+                opcode::GET,
+                lookup::BUILTIN,
+                concat_index,
+                opcode::CONSTANT,
+                1,
+                opcode::GET,
+                lookup::TYPE,
+                0,
+                opcode::CALL,
+                2,
+                opcode::CALL,
+                2
+            ],
+            bytecode.codes()
+        );
+        assert_eq!(
+            &[Value::String("world".into()), Value::String("hello".into())],
+            bytecode.constants()
+        );
+        assert_eq!(greeting.types(), bytecode.types());
+        assert_eq!(&[(13, 16..26), (15, 0..27)], bytecode.call_spans());
+    }
+
+    #[test]
+    pub fn calls_to_definitions_are_typed_by_the_definition() {
+        let mut env = CompileTimeEnv::default();
+        define(&mut env, "(def yes (not false))").unwrap();
+
+        let source = "(uppercase (yes))";
+        let ast = crate::parser::parse(source).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::TypeMismatch {
+                    expected: Type::String,
+                    actual: Type::Bool,
+                }),
+                11..16
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+    }
+
+    #[test]
+    pub fn definitions_take_no_args() {
+        let mut env = CompileTimeEnv::default();
+        define(&mut env, "(def greeting `hello`)").unwrap();
+
+        let source = "(greeting `a` `b`)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::WrongNumberOfArgs {
+                    expected: 0,
+                    actual: 2,
+                    signature: "greeting() -> String".to_string(),
+                }),
+                10..17
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+    }
+
+    #[test]
+    pub fn definitions_must_be_called() {
+        let mut env = CompileTimeEnv::default();
+        define(&mut env, "(def greeting `hello`)").unwrap();
+
+        let source = "(id greeting)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::DefNotCalled("greeting".to_string())),
+                4..12
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+    }
+
+    #[test]
+    pub fn definitions_cannot_shadow() {
+        let mut env = CompileTimeEnv::default();
+        define(&mut env, "(def greeting `hello`)").unwrap();
+
+        for (source, name) in [
+            ("(def greeting `hi`)", "greeting"),
+            ("(def concat `hi`)", "concat"),
+            ("(def if `hi`)", "if"),
+            ("(def let `hi`)", "let"),
+        ] {
+            assert_eq!(
+                Err(vec![(
+                    ExprError::CompileError(CompileError::AlreadyDefined(name.to_string())),
+                    5..5 + name.len()
+                )]),
+                define(&mut env, source)
+            );
+        }
+    }
+
+    #[test]
+    pub fn invalid_definitions() {
+        let mut env = CompileTimeEnv::default();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::InvalidDef),
+                0..14
+            )]),
+            define(&mut env, "(def greeting)")
+        );
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::InvalidDef),
+                5..14
+            )]),
+            define(&mut env, "(def :greeting `hello`)")
+        );
+
+        let source = "(id (def greeting `hello`))";
+        let ast = crate::parser::parse(source).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::InvalidDef),
+                4..26
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+    }
+
+    #[test]
+    pub fn branches_compile_to_jumps() {
+        let env = CompileTimeEnv::default();
+        let source = "(or true (id false))";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+        let id_index = env.get_builtin_index("id").unwrap().1 as u8;
+
+        // `(id false)` isn't typed `Bool` so it's checked with a `cond`
+        assert_eq!(
+            &[
+                opcode::TRUE,
+                opcode::JUMP_IF_FALSE,
+                0,
+                4,
+                opcode::TRUE,
+                opcode::JUMP,
+                0,
+                14,
+                opcode::GET,
+                lookup::BUILTIN,
+                id_index,
+                opcode::FALSE,
+                opcode::CALL,
+                1,
+                opcode::JUMP_IF_FALSE,
+                0,
+                4,
+                opcode::TRUE,
+                opcode::JUMP,
+                0,
+                1,
+                opcode::FALSE
+            ],
+            bytecode.codes()
+        );
+
+        assert_eq!(Some(22), opcode::branch_end(bytecode.codes(), 1));
+        assert_eq!(Some(22), opcode::branch_end(bytecode.codes(), 14));
+        assert_eq!(None, opcode::branch_end(bytecode.codes(), 0));
+    }
+
+    #[test]
+    pub fn tries_compile_to_handlers() {
+        let env = CompileTimeEnv::default();
+        let source = "(try true false)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert_eq!(
+            &[
+                opcode::TRY,
+                0,
+                4,
+                opcode::TRUE,
+                opcode::END_TRY,
+                0,
+                1,
+                opcode::FALSE
+            ],
+            bytecode.codes()
+        );
+
+        assert_eq!(Some(8), opcode::try_end(bytecode.codes(), 0));
+        assert_eq!(None, opcode::try_end(bytecode.codes(), 3));
+    }
+
+    #[test]
+    pub fn calls_are_spanned() {
+        let env = CompileTimeEnv::default();
+        let source = "(concat (trim ` a `) (cond true (id `b`) `c`))";
+
+        for (fuse_calls, expected) in [
+            (false, [(8, 8..20), (19, 32..40), (26, 0..46)]),
+            (true, [(2, 8..20), (11, 32..40), (19, 0..46)]),
+        ] {
+            let options = CompileOptions {
+                fuse_calls,
+                ..Default::default()
+            };
+
+            let ast = crate::parser::parse(source).unwrap();
+            let bytecode =
+                compile_with_options(&mut (ast, 0..source.len()), &env, &options).unwrap();
+
+            assert_eq!(&expected, bytecode.call_spans(), "{fuse_calls}");
+            assert_eq!(Some(&(0..46)), bytecode.call_span(expected[2].0));
+            assert_eq!(None, bytecode.call_span(0));
+        }
+    }
+
+    #[test]
+    pub fn branches_called_as_values_are_eager() {
+        let env = CompileTimeEnv::default();
+        let source = "((id and) true false)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert!(!bytecode.codes().contains(&opcode::JUMP));
+        assert_eq!(Some(&opcode::CALL), bytecode.codes().iter().rev().nth(1));
+    }
+
+    #[test]
+    pub fn let_bindings_use_local_slots() {
+        let env = CompileTimeEnv::default();
+        let source = "(concat (let (x `a`) (let (y x) (concat x y))) (let (z `b`) z))";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+        let concat_index = env.get_builtin_index("concat").unwrap().1 as u8;
+
+        assert_eq!(
+            &[
+                opcode::GET,
+                lookup::BUILTIN,
+                concat_index,
+                opcode::CONSTANT,
+                0,
+                opcode::SET_LOCAL,
+                0,
+                opcode::GET_LOCAL,
+                0,
+                opcode::SET_LOCAL,
+                1,
+                opcode::GET,
+                lookup::BUILTIN,
+                concat_index,
+                opcode::GET_LOCAL,
+                0,
+                opcode::GET_LOCAL,
+                1,
+                opcode::CALL,
+                2,
+                opcode::CONSTANT,
+                1,
+                opcode::SET_LOCAL,
+                0,
+                opcode::GET_LOCAL,
+                0,
+                opcode::CALL,
+                2
+            ],
+            bytecode.codes()
+        );
+        assert_eq!(4, bytecode.max_stack_depth());
+    }
+
+    #[test]
+    pub fn let_bindings_cannot_shadow() {
+        let mut env = CompileTimeEnv::default();
+        define(&mut env, "(def greeting `hello`)").unwrap();
+
+        for (source, name) in [
+            ("(let (greeting `hi`) greeting)", "greeting"),
+            ("(let (concat `hi`) concat)", "concat"),
+            ("(let (let `hi`) `a`)", "let"),
+        ] {
+            let ast = crate::parser::parse(source).unwrap();
+
+            assert_eq!(
+                Err(vec![(
+                    ExprError::CompileError(CompileError::AlreadyDefined(name.to_string())),
+                    6..6 + name.len()
+                )]),
+                compile(&mut (ast, 0..source.len()), &env),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    pub fn bare_names_in_the_env_suggest_their_sigil() {
+        let env = CompileTimeEnv::new(
+            vec!["token".to_string()],
+            vec!["name".to_string()],
+            vec!["password".to_string()],
+            vec!["status".to_string()],
+        );
+
+        let cases = [
+            ("token", "vars", ':'),
+            ("name", "prompts", '?'),
+            ("password", "secrets", '!'),
+            ("status", "client context values", '@'),
+        ];
+
+        for (source, lookup, sigil) in cases {
+            let ast = crate::parser::parse(source).unwrap();
+
+            assert_eq!(
+                Err(vec![(
+                    CompileError::MissingSigil {
+                        name: source.to_string(),
+                        lookup: lookup.to_string(),
+                        sigil,
+                    }
+                    .into(),
+                    0..source.len()
+                )]),
+                compile(&mut (ast, 0..source.len()), &env)
+            );
+        }
+
+        let source = "(concat tokens :name)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        assert_eq!(
+            Err(vec![
+                (CompileError::Undefined("tokens".to_string()).into(), 8..14),
+                (CompileError::Undefined(":name".to_string()).into(), 15..20),
+            ]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+    }
+
+    #[test]
+    pub fn compile_many_defines_for_later_expressions() {
+        let env = CompileTimeEnv::default();
+        let source = "(greeting) (def greeting `hello`) (greeting)";
+        let mut exprs = crate::parser::parse_many(source).unwrap();
+
+        let bytecodes = compile_many(&mut exprs, &env);
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::Undefined("greeting".to_string())),
+                1..9
+            )]),
+            bytecodes[0]
+        );
+        assert_eq!(bytecodes[1], bytecodes[2]);
+        assert!(env.get_def("greeting").is_none());
+    }
+
+    #[test]
+    pub fn spliced_definitions_keep_caller_locals() {
+        let env = CompileTimeEnv::default();
+        let source = "(def d (let (y `inner`) y)) \
+            (let (x `outer`) (concat (d) x)) \
+            (let (x `outer`) (concat x (d) x))";
+        let mut exprs = crate::parser::parse_many(source).unwrap();
+
+        let bytecodes = compile_many(&mut exprs, &env);
+        let results = crate::vm::Vm::new().eval_many(bytecodes, &env, &Default::default());
+
+        assert_eq!(Ok(Value::String("innerouter".into())), results[1]);
+        assert_eq!(Ok(Value::String("outerinnerouter".into())), results[2]);
+    }
+
+    #[test]
+    pub fn fuse_calls_skips_non_builtin_callees() {
+        let env = CompileTimeEnv::new(vec![], vec![], vec![], vec!["f".to_string()]);
+        let source = "(@f `a`)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile_with_options(
+            &mut (ast, 0..source.len()),
+            &env,
+            &CompileOptions {
+                fuse_calls: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            &[
+                opcode::GET,
+                lookup::CLIENT_CTX,
+                0,
+                opcode::CONSTANT,
+                0,
+                opcode::CALL,
+                1
+            ],
+            bytecode.codes()
+        );
+    }
+
+    #[test]
+    pub fn fuse_calls_skips_spread_calls() {
+        let env = CompileTimeEnv::default();
+        let source = "(concat ...[`a`])";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile_with_options(
+            &mut (ast, 0..source.len()),
+            &env,
+            &CompileOptions {
+                fuse_calls: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            &[
+                opcode::GET,
+                lookup::BUILTIN,
+                7,
+                opcode::CONSTANT,
+                0,
+                opcode::LIST,
+                1,
+                opcode::CALL_SPREAD,
+                1
+            ],
+            bytecode.codes()
+        );
+    }
+
+    #[test]
+    pub fn disable_builtin_group() {
+        let mut env = CompileTimeEnv::default();
+        let source = "(abs 1)";
+
+        let ast = crate::parser::parse(source).unwrap();
+        assert!(compile(&mut (ast, 0..source.len()), &env).is_ok());
+
+        env.disable_builtin_group(BuiltinGroup::Math);
+
+        let ast = crate::parser::parse(source).unwrap();
+        assert_eq!(
+            Err(vec![(
+                CompileError::Undefined("abs".to_string()).into(),
+                1..4
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+
+        assert!(env.get_builtin_index("concat").is_some());
+    }
+
+    #[test]
+    pub fn max_depth() {
+        let source = "(concat (concat (concat `a` `b`) `c`) `d`)";
+        let env = CompileTimeEnv::default();
+
+        let compile_with_max_depth = |max_depth| {
+            let ast = crate::parser::parse(source).unwrap();
+
+            compile_with_options(
+                &mut (ast, 0..source.len()),
+                &env,
+                &CompileOptions {
+                    max_depth,
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(compile_with_max_depth(None).is_ok());
+        assert!(compile_with_max_depth(Some(3)).is_ok());
+        assert_eq!(
+            Err(vec![(
+                CompileError::TooDeeplyNested { max: 2 }.into(),
+                16..32
+            )]),
+            compile_with_max_depth(Some(2))
+        );
+    }
+
+    #[test]
+    pub fn too_many_args() {
+        let source = format!("(concat {})", vec!["`a`"; 256].join(" "));
+        let ast = crate::parser::parse(&source).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                CompileError::TooManyArgs {
+                    max: 255,
+                    actual: 256
+                }
+                .into(),
+                0..source.len()
+            )]),
+            compile(&mut (ast, 0..source.len()), &CompileTimeEnv::default())
+        );
+    }
+
+    #[test]
+    pub fn too_many_items() {
+        let source = format!("[{}]", vec!["true"; 256].join(" "));
+        let ast = crate::parser::parse(&source).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                CompileError::TooManyItems {
+                    max: 255,
+                    actual: 256
+                }
+                .into(),
+                0..source.len()
+            )]),
+            compile(&mut (ast, 0..source.len()), &CompileTimeEnv::default())
+        );
+    }
+
+    #[test]
+    pub fn too_many_constants() {
+        let strings = |range: std::ops::Range<usize>| {
+            range
+                .map(|i| format!("`{i}`"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let compile_source = |source: String| {
+            let ast = crate::parser::parse(&source).unwrap();
+
+            compile(&mut (ast, 0..source.len()), &CompileTimeEnv::default())
+        };
+
+        assert!(compile_source(format!("(concat (concat {}) `255`)", strings(0..255))).is_ok());
+
+        let source = format!("(concat (concat {}) `255` `256`)", strings(0..255));
+        let overflow = source.find("`256`").unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                CompileError::TooManyConstants { max: 256 }.into(),
+                overflow..overflow + 5
+            )]),
+            compile_source(source.clone())
+        );
+
+        // Only the first constant to overflow is reported
+        let source = format!(
+            "(concat (concat {}) `255` `256` `257` `258`)",
+            strings(0..255)
+        );
+
+        assert_eq!(
+            Err(vec![(
+                CompileError::TooManyConstants { max: 256 }.into(),
+                overflow..overflow + 5
+            )]),
+            compile_source(source)
+        );
+    }
+
+    #[test]
+    pub fn too_many_names() {
+        let vars = (0..257).map(|i| format!("v{i}")).collect::<Vec<_>>();
+        let env = CompileTimeEnv::new(vars, vec![], vec![], vec![]);
+
+        let compile_source = |source: &str| {
+            let ast = crate::parser::parse(source).unwrap();
+
+            compile(&mut (ast, 0..source.len()), &env)
+        };
+
+        assert_eq!(
+            Ok(vec![opcode::GET, lookup::VAR, 255]),
+            compile_source(":v255").map(|bytecode| bytecode.codes().to_vec())
+        );
+
+        assert_eq!(
+            Err(vec![(
+                CompileError::TooManyNames {
+                    lookup: "var".to_string(),
+                    max: 256
+                }
+                .into(),
+                0..5
+            )]),
+            compile_source(":v256")
+        );
+    }
+
+    #[test]
+    pub fn sandboxed() {
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(BuiltinFn::ID);
+
+        let env = env.sandboxed();
+
+        let mut names = BuiltinFn::DEFAULT_BUILTINS.to_vec();
+        names.extend_from_slice(&BuiltinFn::MATH_BUILTINS);
+
+        assert_eq!(
+            names.iter().map(|builtin| builtin.name).collect::<Vec<_>>(),
+            (0..)
+                .map_while(|i| env.get_builtin(i))
+                .map(|builtin| builtin.name)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(None, env.get_user_builtin(0).map(|builtin| builtin.name));
+    }
+
+    #[test]
+    pub fn identifiers() {
+        let mut env = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        )
+        .sandboxed();
+        env.add_user_builtin(BuiltinFn::ID);
+
+        let identifiers = env.identifiers();
+
+        assert_eq!(
+            Some(&(
+                "concat".to_string(),
+                IdentifierKind::Builtin,
+                BuiltinFn::CONCAT.into()
+            )),
+            identifiers.iter().find(|(name, _, _)| name == "concat")
+        );
+        assert_eq!(
+            vec![
+                (
+                    "id".to_string(),
+                    IdentifierKind::Builtin,
+                    BuiltinFn::ID.into()
+                ),
+                (":a".to_string(), IdentifierKind::Var, Type::String),
+                ("?b".to_string(), IdentifierKind::Prompt, Type::String),
+                ("!c".to_string(), IdentifierKind::Secret, Type::String),
+                ("@d".to_string(), IdentifierKind::Client, Type::Value),
+            ],
+            identifiers[identifiers.len() - 5..]
+        );
+    }
+
+    #[test]
+    pub fn fingerprint_is_stable() {
+        let env = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        )
+        .sandboxed();
+
+        let same = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        )
+        .sandboxed();
+
+        assert_eq!(env.fingerprint(), same.fingerprint());
+
+        let other = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec![],
+            vec!["b".to_string(), "c".to_string()],
+            vec!["d".to_string()],
+        )
+        .sandboxed();
+
+        assert_ne!(env.fingerprint(), other.fingerprint());
+
+        let mut defined = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        )
+        .sandboxed();
+        define(&mut defined, "(def e `e`)").unwrap();
+
+        assert_ne!(env.fingerprint(), defined.fingerprint());
+    }
+
+    #[test]
+    pub fn lookup_options_normalize_names() {
+        let names = || vec![" Token ".to_string()];
+        let env = CompileTimeEnv::new(names(), names(), names(), names());
+
+        for source in [":token", "?token", "!token", "@token"] {
+            let ast = crate::parser::parse(source).unwrap();
+
+            assert!(compile(&mut (ast, 0..source.len()), &env).is_err());
+        }
+
+        let env = env.with_lookup_options(LookupOptions {
+            trim: true,
+            case_insensitive: true,
+        });
+
+        for source in [":token", "?tOKEN", "!toKen", "@token"] {
+            let ast = crate::parser::parse(source).unwrap();
+
+            let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+            assert_eq!(0, bytecode.codes()[2]);
+        }
+
+        let trimmed = CompileTimeEnv::new(names(), vec![], vec![], vec![]).with_lookup_options(
+            LookupOptions {
+                trim: true,
+                case_insensitive: false,
+            },
+        );
+
+        assert_eq!(None, trimmed.get_var_index("token"));
+        assert_eq!(Some(0), trimmed.get_var_index("Token"));
+    }
+
+    #[test]
+    pub fn lookup_options_change_the_fingerprint() {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+
+        assert_eq!(
+            env.fingerprint(),
+            env.clone()
+                .with_lookup_options(LookupOptions::default())
+                .fingerprint()
+        );
+        assert_ne!(
+            env.fingerprint(),
+            env.clone()
+                .with_lookup_options(LookupOptions {
+                    trim: true,
+                    case_insensitive: false,
+                })
+                .fingerprint()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serde_roundtrip() {
+        let mut env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        env.disable_builtin_group(BuiltinGroup::Math);
+        env.add_user_builtin(BuiltinFn::ID);
+
+        let json = serde_json::to_value(&env).unwrap();
+
+        assert_eq!(
+            serde_json::json!({
+                "name": "id",
+                "signature": "id(value: Value) -> Value",
+            }),
+            json["user_builtins"][0]
+        );
+
+        let deserialized: CompileTimeEnv = serde_json::from_value(json).unwrap();
+
+        assert_eq!(env.fingerprint(), deserialized.fingerprint());
+        assert_eq!(None, deserialized.get_builtin_index("abs").map(|(_, i)| i));
+
+        let env = env.with_lookup_options(LookupOptions {
+            trim: true,
+            case_insensitive: true,
+        });
+
+        let mut json = serde_json::to_value(&env).unwrap();
+        let deserialized: CompileTimeEnv = serde_json::from_value(json.clone()).unwrap();
+
+        assert_eq!(env.lookup_options(), deserialized.lookup_options());
+
+        // Environments serialized before lookup options existed match exactly
+        json.as_object_mut().unwrap().remove("lookup_options");
+        let deserialized: CompileTimeEnv = serde_json::from_value(json).unwrap();
+
+        assert_eq!(&LookupOptions::default(), deserialized.lookup_options());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serde_unknown_or_changed_builtin() {
+        let mut json = serde_json::to_value(CompileTimeEnv::default()).unwrap();
+        json["user_builtins"] = serde_json::json!([{
+            "name": "missing",
+            "signature": "missing() -> String",
+        }]);
+
+        assert_eq!(
+            "unknown builtin: missing",
+            serde_json::from_value::<CompileTimeEnv>(json.clone())
+                .unwrap_err()
+                .to_string()
+        );
+
+        json["user_builtins"] = serde_json::json!([{
+            "name": "id",
+            "signature": "id(value: String) -> String",
+        }]);
+
+        assert_eq!(
+            "builtin has signature id(value: Value) -> Value but id(value: String) -> String was expected",
+            serde_json::from_value::<CompileTimeEnv>(json)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serde_host_user_builtins() {
+        use serde::de::DeserializeSeed;
+
+        const GREET: BuiltinFn<'static> = BuiltinFn {
+            name: "greet",
+            ..BuiltinFn::NOOP
+        };
+
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(GREET);
+
+        let json = serde_json::to_value(&env).unwrap();
+
+        assert_eq!(
+            "unknown builtin: greet",
+            serde_json::from_value::<CompileTimeEnv>(json.clone())
+                .unwrap_err()
+                .to_string()
+        );
+
+        let deserialized = CompileTimeEnvSeed {
+            user_builtins: &[GREET],
+        }
+        .deserialize(json.clone())
+        .unwrap();
+
+        assert_eq!(env.fingerprint(), deserialized.fingerprint());
+        assert_eq!(
+            Some(FnNamespace::User),
+            deserialized
+                .get_user_builtin_index("greet")
+                .map(|(builtin, _)| builtin.namespace)
+        );
+
+        const CHANGED: BuiltinFn<'static> = BuiltinFn {
+            name: "greet",
+            return_type: Type::Number,
+            ..BuiltinFn::NOOP
+        };
+
+        assert_eq!(
+            "builtin has signature greet() -> Number but greet() -> String was expected",
+            CompileTimeEnvSeed {
+                user_builtins: &[CHANGED],
+            }
+            .deserialize(json)
+            .unwrap_err()
+            .to_string()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serde_bytecode_roundtrip() {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let source = "(cond (eq (type :a) String) `yes` 1.5)";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let mut json = serde_json::to_value(&bytecode).unwrap();
+
+        assert_eq!(
+            serde_json::json!([
+                {"type": "String", "value": "yes"},
+                {"type": "Number", "value": 1.5},
+            ]),
+            json["constants"]
+        );
+
+        let deserialized: ExprByteCode = serde_json::from_value(json.clone()).unwrap();
+
+        assert_eq!(bytecode, deserialized);
+        assert_eq!(bytecode.call_spans(), deserialized.call_spans());
+
+        json["version"] = serde_json::json!([0, 0, 1, 0]);
+
+        assert_eq!(
+            "unsupported bytecode version 0010",
+            serde_json::from_value::<ExprByteCode>(json)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+
+    #[test]
+    pub fn call_with_call_callee() {
+        let env = CompileTimeEnv::default();
+        let source = "((true false))";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert_eq!(
+            &[
+                opcode::TRUE,
+                opcode::FALSE,
+                opcode::CALL,
+                1,
+                opcode::CALL,
+                0
+            ],
+            bytecode.codes()
+        );
+    }
+
+    #[test]
+    fn nested_calls_checked_with_callee_return_type() {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+
+        let source = "(not (is_empty :a))";
+        let ast = crate::parser::parse(source).unwrap();
+        assert!(compile(&mut (ast, 0..source.len()), &env).is_ok());
+
+        let source = "(not (noop))";
+        let ast = crate::parser::parse(source).unwrap();
+        assert_eq!(
+            Err(vec![(
+                CompileError::TypeMismatch {
+                    expected: Type::Bool,
+                    actual: Type::String,
+                }
+                .into(),
+                5..11
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+
+        let source = "(not (cond true true false))";
+        let ast = crate::parser::parse(source).unwrap();
+        assert!(compile(&mut (ast, 0..source.len()), &env).is_ok());
+
+        let source = "(not (type true))";
+        let ast = crate::parser::parse(source).unwrap();
+        assert!(compile(&mut (ast, 0..source.len()), &env).is_ok());
+    }
+
+    #[test]
+    fn calls_checked_with_composed_and_partial_signatures() {
+        let env = CompileTimeEnv::default();
+
+        let source = "((compose uppercase trim) ` a `)";
+        let ast = crate::parser::parse(source).unwrap();
+        assert!(compile(&mut (ast, 0..source.len()), &env).is_ok());
+
+        let source = "((compose uppercase trim) true)";
+        let ast = crate::parser::parse(source).unwrap();
+        assert_eq!(
+            Err(vec![(
+                CompileError::TypeMismatch {
+                    expected: Type::String,
+                    actual: Type::Bool,
+                }
+                .into(),
+                26..30
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+
+        let source = "((partial eq true) false)";
+        let ast = crate::parser::parse(source).unwrap();
+        assert!(compile(&mut (ast, 0..source.len()), &env).is_ok());
+
+        let source = "(not (partial eq true))";
+        let ast = crate::parser::parse(source).unwrap();
+        assert_eq!(
+            Err(vec![(
+                CompileError::TypeMismatch {
+                    expected: Type::Bool,
+                    actual: Type::Fn {
+                        args: vec![Type::Value],
+                        variadic_arg: None,
+                        returns: Type::Bool.into(),
+                    },
+                }
+                .into(),
+                5..22
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+    }
+
+    #[test]
+    fn nested_calls_checked_with_user_builtin_return_type() {
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(BuiltinFn {
+            name: "count",
+            return_type: Type::Number,
+            ..BuiltinFn::ID
+        });
+
+        let source = "(and (count true) true)";
+        let ast = crate::parser::parse(source).unwrap();
+        assert_eq!(
+            Err(vec![(
+                CompileError::TypeMismatch {
+                    expected: Type::Bool,
+                    actual: Type::Number,
+                }
+                .into(),
+                5..17
+            )]),
+            compile(&mut (ast, 0..source.len()), &env)
+        );
+    }
+
+    #[test]
+    fn references_lookup() {
+        let env = CompileTimeEnv::new(vec![], vec![], vec!["token".to_string()], vec![]);
+        let source = "(concat `Bearer ` !token)";
+        let ast = crate::parser::parse(source).unwrap();
+
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert!(bytecode.references_lookup(lookup::SECRET));
+        assert!(bytecode.references_lookup(lookup::BUILTIN));
+        assert!(!bytecode.references_lookup(lookup::VAR));
     }
 }
@@ -0,0 +1,271 @@
+//! Coverage of bytecode executed by a [`Vm`](crate::vm::Vm)
+//!
+//! Enable [`VmConfig::coverage`](crate::vm::VmConfig::coverage) and read
+//! [`Vm::coverage`](crate::vm::Vm::coverage) after evaluating an expression to
+//! see which ops ran and which way each `cond` went. `cond`, `and`, and `or`
+//! are compiled to jumps so the ops of a branch that's never taken are never
+//! executed. Each is recorded as a `cond` keyed by its `JUMP_IF_FALSE`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use crate::compiler::{ExprByteCode, opcode};
+
+/// Ops executed and `cond` branches taken while evaluating bytecode
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    ops: Vec<usize>,
+    executed: BTreeSet<usize>,
+    branches: BTreeMap<usize, BranchCoverage>,
+}
+
+/// Which results of a `cond` call were returned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCoverage {
+    pub then_taken: bool,
+    pub else_taken: bool,
+}
+
+impl BranchCoverage {
+    pub fn is_covered(&self) -> bool {
+        self.then_taken && self.else_taken
+    }
+}
+
+impl Coverage {
+    /// Coverage of bytecode with nothing executed yet
+    pub fn new(bytecode: &ExprByteCode) -> Self {
+        let codes = bytecode.codes();
+
+        let mut ops = vec![];
+        let mut ip = 0;
+
+        while let Some(op_code) = codes.get(ip) {
+            ops.push(ip);
+
+            ip += match *op_code {
+                opcode::GET
+                | opcode::CALL_BUILTIN
+                | opcode::JUMP_IF_FALSE
+                | opcode::JUMP
+                | opcode::TRY
+                | opcode::END_TRY => 3,
+                opcode::CALL
+                | opcode::CONSTANT
+                | opcode::LIST
+                | opcode::GET_LOCAL
+                | opcode::SET_LOCAL
+                | opcode::CALL_SPREAD => 2,
+                _ => 1,
+            };
+        }
+
+        Self {
+            ops,
+            ..Default::default()
+        }
+    }
+
+    /// Offsets of every op in the bytecode
+    pub fn ops(&self) -> &[usize] {
+        &self.ops
+    }
+
+    /// Offsets of ops that were executed
+    pub fn executed(&self) -> &BTreeSet<usize> {
+        &self.executed
+    }
+
+    /// Offsets of ops that were never executed
+    pub fn unexecuted(&self) -> Vec<usize> {
+        self.ops
+            .iter()
+            .filter(|ip| !self.executed.contains(ip))
+            .copied()
+            .collect()
+    }
+
+    /// Branches taken by each `cond`, keyed by the offset of its
+    /// `JUMP_IF_FALSE`, or of the call op if it's called as a value
+    pub fn branches(&self) -> &BTreeMap<usize, BranchCoverage> {
+        &self.branches
+    }
+
+    /// Fraction of ops and `cond` branches covered, from `0.0` to `1.0`
+    pub fn ratio(&self) -> f64 {
+        let total = self.ops.len() + self.branches.len() * 2;
+
+        if total == 0 {
+            return 1.0;
+        }
+
+        (self.executed.len() + self.branches_taken()) as f64 / total as f64
+    }
+
+    /// Number of `cond` branches taken across every `cond` call
+    pub fn branches_taken(&self) -> usize {
+        self.branches
+            .values()
+            .map(|branch| branch.then_taken as usize + branch.else_taken as usize)
+            .sum()
+    }
+
+    /// Combine coverage from another evaluation of the same bytecode
+    pub fn merge(&mut self, other: &Coverage) {
+        self.executed.extend(&other.executed);
+
+        for (ip, other_branch) in &other.branches {
+            let branch = self.branches.entry(*ip).or_default();
+
+            branch.then_taken |= other_branch.then_taken;
+            branch.else_taken |= other_branch.else_taken;
+        }
+    }
+
+    pub(crate) fn record_op(&mut self, ip: usize) {
+        self.executed.insert(ip);
+    }
+
+    pub(crate) fn record_cond(&mut self, ip: usize, condition: bool) {
+        let branch = self.branches.entry(ip).or_default();
+
+        if condition {
+            branch.then_taken = true;
+        } else {
+            branch.else_taken = true;
+        }
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "OPS {}/{}", self.executed.len(), self.ops.len())?;
+        writeln!(
+            f,
+            "BRANCHES {}/{}",
+            self.branches_taken(),
+            self.branches.len() * 2
+        )?;
+        writeln!(f, "COVERAGE {:.1}%", self.ratio() * 100.0)?;
+
+        for ip in self.unexecuted() {
+            writeln!(f, "{ip:04} NOT EXECUTED")?;
+        }
+
+        for (ip, branch) in &self.branches {
+            let taken = |taken: bool| if taken { "taken" } else { "NOT TAKEN" };
+
+            writeln!(
+                f,
+                "{ip:04} COND then {} else {}",
+                taken(branch.then_taken),
+                taken(branch.else_taken)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    use crate::prelude::*;
+
+    fn covered(source: &str, a: Option<&str>) -> Coverage {
+        covered_with_config(
+            source,
+            a,
+            VmConfig {
+                coverage: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn covered_with_config(source: &str, a: Option<&str>, config: VmConfig) -> Coverage {
+        let vars = a.iter().map(|_| "a".to_string()).collect();
+        let env = CompileTimeEnv::new(vars, vec![], vec![], vec![]);
+        let bytecode = compile(&mut (parse(source).unwrap(), 0..source.len()), &env).unwrap();
+
+        let runtime_env = RuntimeEnv {
+            vars: a.into_iter().map(String::from).collect(),
+            ..Default::default()
+        };
+
+        let mut vm = Vm::with_config(config);
+
+        let _ = vm.interpret(bytecode.into(), &env, &runtime_env);
+
+        vm.coverage().unwrap().clone()
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let env = CompileTimeEnv::default();
+        let bytecode = compile(&mut (parse("true").unwrap(), 0..4), &env).unwrap();
+
+        let mut vm = Vm::new();
+        vm.interpret(bytecode.into(), &env, &RuntimeEnv::default())
+            .unwrap();
+
+        assert_eq!(None, vm.coverage());
+    }
+
+    #[test]
+    fn cond_branches_merge() {
+        let source = "(cond (eq :a `1`) `one` `other`)";
+
+        let mut coverage = covered(source, Some("1"));
+
+        assert_eq!(
+            vec![BranchCoverage {
+                then_taken: true,
+                else_taken: false,
+            }],
+            coverage.branches().values().copied().collect::<Vec<_>>()
+        );
+        assert_eq!(0.8, coverage.ratio());
+
+        coverage.merge(&covered(source, Some("2")));
+
+        assert!(coverage.branches().values().all(BranchCoverage::is_covered));
+        assert_eq!(1.0, coverage.ratio());
+    }
+
+    #[test]
+    fn report() {
+        let coverage = covered("(cond true `a` `b`)", None);
+
+        assert_eq!(
+            "OPS 4/5
+BRANCHES 1/2
+COVERAGE 71.4%
+0009 NOT EXECUTED
+0001 COND then taken else NOT TAKEN
+",
+            coverage.to_string()
+        );
+    }
+
+    #[test]
+    fn ops_not_executed_when_the_stack_would_overflow() {
+        let coverage = covered_with_config(
+            "(concat `a` `b`)",
+            None,
+            VmConfig {
+                max_stack_depth: 1,
+                coverage: true,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(vec![0, 3, 5, 7], coverage.unexecuted());
+        assert!(coverage.ratio() < 1.0);
+    }
+}
@@ -2,6 +2,7 @@
 
 use crate::{
     compiler::{CompileTimeEnv, ExprByteCode, opcode},
+    errors::{DisassembleError, ExprErrorS, ExprResult},
     prelude::lookup,
 };
 
@@ -16,7 +17,10 @@ impl<'bytecode, 'env> Disassembler<'bytecode, 'env> {
     }
 
     /// Visualize the byte code as text
-    pub fn disassemble(&self) -> String {
+    ///
+    /// Errors if an op is missing operands or references something not in
+    /// the bytecode or environment. Unknown op codes are shown as `UNKNOWN`.
+    pub fn disassemble(&self) -> ExprResult<String> {
         let mut out = String::new();
 
         let mut op_idx = 0;
@@ -33,7 +37,7 @@ impl<'bytecode, 'env> Disassembler<'bytecode, 'env> {
 
         while op_idx < self.bytecode.codes().len() {
             let (op_byte_size, disassembled_byte_idx, disassembled_op) =
-                self.disassemble_op(op_idx);
+                self.disassemble_op(op_idx)?;
 
             let op_string = &format!("{disassembled_byte_idx} {disassembled_op}");
             out.push_str(op_string);
@@ -41,139 +45,333 @@ impl<'bytecode, 'env> Disassembler<'bytecode, 'env> {
             op_idx += op_byte_size;
         }
 
-        out
+        Ok(out)
     }
 
-    pub fn disassemble_op(&self, op_idx: usize) -> (usize, String, String) {
+    /// Disassemble the op at `op_idx` returning its size in bytes, its
+    /// formatted index, and its text
+    pub fn disassemble_op(&self, op_idx: usize) -> ExprResult<(usize, String, String)> {
         let op_idx_str = format!("{op_idx:04}");
 
         let (op_idx_inc, op_str): (usize, String) = match self.bytecode.codes()[op_idx] {
-            opcode::GET => self.disassemble_op_get(op_idx),
-            opcode::CALL => self.disassemble_op_call("CALL", op_idx),
-            opcode::CONSTANT => self.disassemble_op_constant("CONSTANT", op_idx),
-            opcode::TRUE => self.disassemble_op_true("TRUE", op_idx),
-            opcode::FALSE => self.disassemble_op_false("FALSE", op_idx),
-            _ => (1, "".to_string()),
+            opcode::GET => self.disassemble_op_get(op_idx)?,
+            opcode::CALL => self.disassemble_op_call("CALL", op_idx)?,
+            opcode::CONSTANT => self.disassemble_op_constant("CONSTANT", op_idx)?,
+            opcode::TRUE => (1, "TRUE\n".to_string()),
+            opcode::FALSE => (1, "FALSE\n".to_string()),
+            opcode::CALL_BUILTIN => self.disassemble_op_call_builtin("CALL_BUILTIN", op_idx)?,
+            opcode::LIST => self.disassemble_op_list("LIST", op_idx)?,
+            opcode::GET_LOCAL => self.disassemble_op_local("GET_LOCAL", op_idx)?,
+            opcode::SET_LOCAL => self.disassemble_op_local("SET_LOCAL", op_idx)?,
+            opcode::JUMP_IF_FALSE => self.disassemble_op_jump("JUMP_IF_FALSE", op_idx)?,
+            opcode::JUMP => self.disassemble_op_jump("JUMP", op_idx)?,
+            opcode::TRY => self.disassemble_op_jump("TRY", op_idx)?,
+            opcode::END_TRY => self.disassemble_op_jump("END_TRY", op_idx)?,
+            opcode::CALL_SPREAD => self.disassemble_op_call("CALL_SPREAD", op_idx)?,
+            op => (1, format!("{:16} {op:#04x}\n", "UNKNOWN")),
         };
 
-        (op_idx_inc, op_idx_str, op_str)
+        Ok((op_idx_inc, op_idx_str, op_str))
     }
 
-    // TODO
-    // fn disassemble_op_u8(&self, name: &str, op_idx: usize, expected: u8) -> (usize, String) {
-    //     let constant_op = self.bytecode.codes()[op_idx];
-    //     assert_eq!(constant_op, expected);
+    /// The `count` operand bytes following the op at `op_idx`
+    fn operands(&self, name: &str, op_idx: usize, count: usize) -> ExprResult<&[u8]> {
+        operands(self.bytecode.codes(), name, op_idx, count)
+    }
+
+    /// Constants are shown as their [`Value`](crate::value::Value) would be
+    /// printed so every kind of value is supported
+    fn disassemble_op_constant(&self, name: &str, op_idx: usize) -> ExprResult<(usize, String)> {
+        let constant_idx = self.operands(name, op_idx, 1)?[0] as usize;
+
+        let value = self
+            .bytecode
+            .constants()
+            .get(constant_idx)
+            .ok_or_else(|| undefined(name, op_idx, "constant", constant_idx))?;
 
-    //     let string = format!("{name}\n");
+        let string = format!("{name:16} {constant_idx:>4} == '{value}'\n");
 
-    //     (1, string)
-    // }
+        Ok((2, string))
+    }
 
-    fn disassemble_op_true(&self, name: &str, op_idx: usize) -> (usize, String) {
-        let constant_op = self.bytecode.codes()[op_idx];
-        assert_eq!(constant_op, opcode::TRUE);
+    fn disassemble_op_get(&self, op_idx: usize) -> ExprResult<(usize, String)> {
+        let name = "GET";
 
-        let string = format!("{name}\n");
+        let operands = self.operands(name, op_idx, 2)?;
+        let lookup_type = operands[0];
+        let constant_idx = operands[1] as usize;
 
-        (1, string)
+        let lookup_type_string = match lookup_type {
+            lookup::BUILTIN => "BUILTIN",
+            lookup::USER_BUILTIN => "USER_BUILTIN",
+            lookup::VAR => "VAR",
+            lookup::PROMPT => "PROMPT",
+            lookup::SECRET => "SECRET",
+            lookup::CLIENT_CTX => "CLIENT_CTX",
+            lookup::TYPE => "TYPE",
+            _ => {
+                return Err(vec![(
+                    DisassembleError::InvalidLookupType {
+                        op_idx,
+                        lookup_type,
+                    }
+                    .into(),
+                    0..0,
+                )]);
+            }
+        };
+
+        let value = match lookup_type {
+            lookup::BUILTIN => self
+                .env
+                .get_builtin(constant_idx)
+                .map(|builtin| builtin.name.to_string()),
+            lookup::USER_BUILTIN => self
+                .env
+                .get_user_builtin(constant_idx)
+                .map(|builtin| builtin.name.to_string()),
+            lookup::VAR => self.env.get_var(constant_idx).cloned(),
+            lookup::PROMPT => self.env.get_prompt(constant_idx).cloned(),
+            lookup::SECRET => self.env.get_secret(constant_idx).cloned(),
+            lookup::CLIENT_CTX => self.env.get_client_context(constant_idx).cloned(),
+            _ => self.bytecode.types().get(constant_idx).map(|ty| ty.name()),
+        }
+        .ok_or_else(|| {
+            undefined(
+                name,
+                op_idx,
+                &lookup_type_string.to_lowercase(),
+                constant_idx,
+            )
+        })?;
+
+        let string = format!("{name} {lookup_type_string:12} {constant_idx:>4} == '{value}'\n");
+
+        Ok((3, string))
     }
 
-    fn disassemble_op_false(&self, name: &str, op_idx: usize) -> (usize, String) {
-        let constant_op = self.bytecode.codes()[op_idx];
-        assert_eq!(constant_op, opcode::FALSE);
+    fn disassemble_op_call(&self, name: &str, op_idx: usize) -> ExprResult<(usize, String)> {
+        let arg_count = self.operands(name, op_idx, 1)?[0];
 
-        let string = format!("{name}\n");
+        let string = format!("{name:16} ({arg_count} args)\n",);
 
-        (1, string)
+        Ok((2, string))
     }
 
-    fn disassemble_op_constant(&self, name: &str, op_idx: usize) -> (usize, String) {
-        let constant_op = self.bytecode.codes()[op_idx];
-        assert_eq!(constant_op, opcode::CONSTANT);
+    fn disassemble_op_list(&self, name: &str, op_idx: usize) -> ExprResult<(usize, String)> {
+        let item_count = self.operands(name, op_idx, 1)?[0];
 
-        let constant_idx = self.bytecode.codes()[op_idx + 1] as usize;
+        let string = format!("{name:16} ({item_count} items)\n");
 
-        let value = self
-            .bytecode
-            .constants()
-            .get(constant_idx)
-            .expect("should have value in constants at index");
+        Ok((2, string))
+    }
 
-        let string = format!("{name:16} {constant_idx:>4} == '{value}'\n");
+    fn disassemble_op_local(&self, name: &str, op_idx: usize) -> ExprResult<(usize, String)> {
+        let slot = self.operands(name, op_idx, 1)?[0];
 
-        (2, string)
+        let string = format!("{name:16} {slot:>4}\n");
+
+        Ok((2, string))
     }
 
-    fn disassemble_op_get(&self, op_idx: usize) -> (usize, String) {
-        let call_op = self.bytecode.codes()[op_idx];
-        assert_eq!(call_op, opcode::GET);
+    /// Jumps are shown with the offset of the op they jump to
+    fn disassemble_op_jump(&self, name: &str, op_idx: usize) -> ExprResult<(usize, String)> {
+        let operands = self.operands(name, op_idx, 2)?;
+        let offset = u16::from_be_bytes([operands[0], operands[1]]) as usize;
+        let target = op_idx + 3 + offset;
 
-        let lookup_type = self.bytecode.codes()[op_idx + 1];
-        let constant_idx = self.bytecode.codes()[op_idx + 2] as usize;
+        let string = format!("{name:16} {offset:>4} -> {target:04}\n");
 
-        let value = match lookup_type {
-            lookup::BUILTIN => {
-                let value = self.env.get_builtin(constant_idx).unwrap();
-                value.name
-            }
-            lookup::USER_BUILTIN => {
-                let value = self.env.get_user_builtin(constant_idx).unwrap();
-                value.name
-            }
-            lookup::VAR => {
-                let value = self.env.get_var(constant_idx).unwrap();
+        Ok((3, string))
+    }
 
-                value.as_str()
-            }
-            lookup::PROMPT => {
-                
+    fn disassemble_op_call_builtin(
+        &self,
+        name: &str,
+        op_idx: usize,
+    ) -> ExprResult<(usize, String)> {
+        let operands = self.operands(name, op_idx, 2)?;
+        let builtin_idx = operands[0] as usize;
+        let arg_count = operands[1];
 
-                self.env.get_prompt(constant_idx).unwrap()
-            }
-            lookup::SECRET => {
-                
+        let value = self
+            .env
+            .get_builtin(builtin_idx)
+            .ok_or_else(|| undefined(name, op_idx, "builtin", builtin_idx))?
+            .name;
 
-                self.env.get_secret(constant_idx).unwrap()
-            }
-            lookup::CLIENT_CTX => {
-                
+        let string = format!("{name:16} {builtin_idx:>4} == '{value}' ({arg_count} args)\n");
 
-                self.env.get_client_context(constant_idx).unwrap()
-            }
-            lookup::TYPE => {
-                let value = self.bytecode.types().get(constant_idx).unwrap();
+        Ok((3, string))
+    }
+}
 
-                &value.name()
+/// The `count` operand bytes following the op at `op_idx` in `codes`
+pub(crate) fn operands<'codes>(
+    codes: &'codes [u8],
+    name: &str,
+    op_idx: usize,
+    count: usize,
+) -> ExprResult<&'codes [u8]> {
+    codes.get(op_idx + 1..op_idx + 1 + count).ok_or_else(|| {
+        vec![(
+            DisassembleError::MissingOperands {
+                op: name.to_string(),
+                op_idx,
+                expected: count,
             }
-            _ => panic!("invalid get lookup code: {lookup_type}"),
-        };
+            .into(),
+            0..0,
+        )]
+    })
+}
 
-        let lookup_type_string = match lookup_type {
-            lookup::BUILTIN => "BUILTIN",
-            lookup::USER_BUILTIN => "USER_BUILTIN",
-            lookup::VAR => "VAR",
-            lookup::PROMPT => "PROMPT",
-            lookup::SECRET => "SECRET",
-            lookup::CLIENT_CTX => "CLIENT_CTX",
-            lookup::TYPE => "TYPE",
-            _ => panic!("invalid get lookup code: {lookup_type}"),
-        };
+pub(crate) fn undefined(op: &str, op_idx: usize, lookup: &str, index: usize) -> Vec<ExprErrorS> {
+    vec![(
+        DisassembleError::Undefined {
+            op: op.to_string(),
+            op_idx,
+            lookup: lookup.to_string(),
+            index,
+        }
+        .into(),
+        0..0,
+    )]
+}
 
-        let name = "GET";
+#[cfg(test)]
+mod disassembler_tests {
+    use super::*;
 
-        let string = format!("{name} {lookup_type_string:12} {constant_idx:>4} == '{value}'\n");
+    use crate::{compiler::get_version_bytes, errors::ExprError, prelude::*};
 
-        (3, string)
+    fn bytecode(codes: &[u8], constants: Vec<Value>) -> ExprByteCode {
+        ExprByteCode::new(
+            get_version_bytes().iter().chain(codes).copied().collect(),
+            constants,
+            vec![],
+        )
     }
 
-    fn disassemble_op_call(&self, name: &str, op_idx: usize) -> (usize, String) {
-        let call_op = self.bytecode.codes()[op_idx];
-        assert_eq!(call_op, opcode::CALL);
+    fn disassemble_err(bytecode: &ExprByteCode) -> DisassembleError {
+        let env = CompileTimeEnv::default();
 
-        let arg_count = self.bytecode.codes()[op_idx + 1];
+        match Disassembler::new(bytecode, &env).disassemble() {
+            Err(errs) => match &errs[0].0 {
+                ExprError::DisassembleError(err) => err.clone(),
+                err => panic!("expected a disassemble error: {err}"),
+            },
+            Ok(disassembly) => panic!("expected an error: {disassembly}"),
+        }
+    }
 
-        let string = format!("{name:16} ({arg_count} args)\n",);
+    #[test]
+    fn missing_operands() {
+        assert_eq!(
+            DisassembleError::MissingOperands {
+                op: "GET".to_string(),
+                op_idx: 1,
+                expected: 2,
+            },
+            disassemble_err(&bytecode(&[opcode::TRUE, opcode::GET, lookup::VAR], vec![]))
+        );
+    }
+
+    #[test]
+    fn undefined_in_env() {
+        assert_eq!(
+            DisassembleError::Undefined {
+                op: "GET".to_string(),
+                op_idx: 0,
+                lookup: "var".to_string(),
+                index: 0,
+            },
+            disassemble_err(&bytecode(&[opcode::GET, lookup::VAR, 0], vec![]))
+        );
+    }
+
+    #[test]
+    fn undefined_constant() {
+        assert_eq!(
+            DisassembleError::Undefined {
+                op: "CONSTANT".to_string(),
+                op_idx: 0,
+                lookup: "constant".to_string(),
+                index: 2,
+            },
+            disassemble_err(&bytecode(&[opcode::CONSTANT, 2], vec![]))
+        );
+    }
+
+    #[test]
+    fn non_string_constants() {
+        let env = CompileTimeEnv::default();
+        let bytecode = bytecode(
+            &[
+                opcode::CONSTANT,
+                0,
+                opcode::CONSTANT,
+                1,
+                opcode::CONSTANT,
+                2,
+                opcode::CONSTANT,
+                3,
+                opcode::CONSTANT,
+                4,
+            ],
+            vec![
+                Value::Number(1.5),
+                Value::Bool(false),
+                Value::Type(Type::String.into()),
+                Value::Fn(BuiltinFn::NOOP.into()),
+                Value::Closure(
+                    Closure::Partial {
+                        f: Value::Fn(BuiltinFn::NOOP.into()),
+                        arg: Value::Number(2.0),
+                    }
+                    .into(),
+                ),
+            ],
+        );
+
+        let disassembly = Disassembler::new(&bytecode, &env).disassemble().unwrap();
+
+        assert!(
+            disassembly.ends_with(
+                "----
+0000 CONSTANT            0 == '1.5'
+0002 CONSTANT            1 == 'false'
+0004 CONSTANT            2 == 'Type<String>'
+0006 CONSTANT            3 == 'noop() -> String'
+0008 CONSTANT            4 == '(partial noop() -> String 2)'
+"
+            ),
+            "{disassembly}"
+        );
+    }
+
+    #[test]
+    fn invalid_lookup_type() {
+        assert_eq!(
+            DisassembleError::InvalidLookupType {
+                op_idx: 0,
+                lookup_type: 42,
+            },
+            disassemble_err(&bytecode(&[opcode::GET, 42, 0], vec![]))
+        );
+    }
+
+    #[test]
+    fn unknown_op() {
+        let env = CompileTimeEnv::default();
+        let bytecode = bytecode(&[0xff, opcode::TRUE], vec![]);
+
+        let disassembly = Disassembler::new(&bytecode, &env).disassemble().unwrap();
 
-        (2, string)
+        assert!(
+            disassembly.ends_with("----\n0000 UNKNOWN          0xff\n0001 TRUE\n"),
+            "{disassembly}"
+        );
     }
 }
@@ -0,0 +1,409 @@
+//! Evaluate every expression in a document
+//!
+//! [`evaluate_all`] compiles and evaluates a list of named expressions (e.g.
+//! the templated fields of a request) against one shared environment and
+//! collects each one's value, diagnostics, timing, and [`Provenance`] in a
+//! [`DocumentReport`]. With the `json` feature enabled
+#![cfg_attr(
+    feature = "json",
+    doc = "[`DocumentReport::to_json`] serializes it for clients rendering the"
+)]
+#![cfg_attr(
+    not(feature = "json"),
+    doc = "`DocumentReport::to_json` serializes it for clients rendering the"
+)]
+//! resolved document in one view.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    compiler::{CompileTimeEnv, compile},
+    errors::{ExprErrorS, ExprResult},
+    parser::parse,
+    provenance::{Provenance, TracedValue},
+    value::Value,
+    vm::{RuntimeEnv, Vm},
+};
+
+/// Reports for every expression in a document, in the order they were given
+#[derive(Debug, Default, PartialEq)]
+pub struct DocumentReport {
+    pub exprs: Vec<ExprReport>,
+}
+
+/// The outcome of compiling and evaluating one expression in a document
+#[derive(Debug, PartialEq)]
+pub struct ExprReport {
+    pub name: String,
+    pub source: String,
+    /// The resulting value, if evaluation succeeded
+    pub value: Option<Value>,
+    /// The inputs that contributed to the value, if evaluation succeeded
+    pub provenance: Option<Provenance>,
+    /// Errors with spans in to the expression's source
+    pub errs: Vec<ExprErrorS>,
+    /// Time spent compiling and evaluating
+    pub duration: Duration,
+}
+
+/// Compile and evaluate a list of name → expression source
+///
+/// Every expression is compiled against the same [`CompileTimeEnv`] and
+/// evaluated against the same [`RuntimeEnv`], reusing one VM. An expression
+/// failing doesn't stop the rest from being evaluated.
+///
+/// ```
+/// use reqlang_expr::{document::evaluate_all, prelude::*};
+///
+/// let env = CompileTimeEnv::new(vec!["id".to_string()], vec![], vec![], vec![]);
+///
+/// let runtime_env = RuntimeEnv {
+///     vars: vec!["123".to_string()],
+///     ..Default::default()
+/// };
+///
+/// let report = evaluate_all(
+///     [("url", "(concat `/users/` :id)"), ("body", ":missing")],
+///     &env,
+///     &runtime_env,
+/// );
+///
+/// assert!(!report.is_ok());
+///
+/// let url = report.get("url").unwrap();
+/// assert_eq!(Some(Value::String("/users/123".into())), url.value);
+///
+/// let body = report.get("body").unwrap();
+/// assert_eq!(None, body.value);
+/// assert_eq!(1, body.errs.len());
+/// ```
+pub fn evaluate_all<N, S>(
+    exprs: impl IntoIterator<Item = (N, S)>,
+    env: &CompileTimeEnv,
+    runtime_env: &RuntimeEnv,
+) -> DocumentReport
+where
+    N: AsRef<str>,
+    S: AsRef<str>,
+{
+    let mut vm = Vm::new();
+
+    let exprs = exprs
+        .into_iter()
+        .map(|(name, source)| {
+            let source = source.as_ref();
+
+            let start = Instant::now();
+            let traced = evaluate(source, env, runtime_env, &mut vm);
+            let duration = start.elapsed();
+
+            let (value, provenance, errs) = match traced {
+                Ok(traced) => {
+                    let provenance = traced.provenance().clone();
+
+                    (Some(traced.into_value()), Some(provenance), vec![])
+                }
+                Err(errs) => (None, None, errs),
+            };
+
+            ExprReport {
+                name: name.as_ref().to_string(),
+                source: source.to_string(),
+                value,
+                provenance,
+                errs,
+                duration,
+            }
+        })
+        .collect();
+
+    DocumentReport { exprs }
+}
+
+fn evaluate(
+    source: &str,
+    env: &CompileTimeEnv,
+    runtime_env: &RuntimeEnv,
+    vm: &mut Vm,
+) -> ExprResult<TracedValue> {
+    let ast = parse(source)?;
+    let bytecode = compile(&mut (ast, 0..source.len()), env)?;
+
+    vm.interpret_traced(bytecode.into(), env, runtime_env)
+}
+
+impl DocumentReport {
+    /// If every expression evaluated without errors
+    pub fn is_ok(&self) -> bool {
+        self.exprs.iter().all(ExprReport::is_ok)
+    }
+
+    /// Report for the first expression with the name
+    pub fn get(&self, name: &str) -> Option<&ExprReport> {
+        self.exprs.iter().find(|expr| expr.name == name)
+    }
+
+    /// Time spent compiling and evaluating every expression
+    pub fn duration(&self) -> Duration {
+        self.exprs.iter().map(|expr| expr.duration).sum()
+    }
+
+    /// `{ ok, duration_us, exprs: [{ name, value, type, secret, provenance, diagnostics, duration_us }] }`
+    ///
+    /// `value`, `type`, and `provenance` are `null` if evaluation failed.
+    /// Values a secret contributed to are `secret` and their `value` is
+    /// [redacted](crate::explain::REDACTED).
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "ok": self.is_ok(),
+            "duration_us": self.duration().as_micros() as u64,
+            "exprs": self.exprs.iter().map(ExprReport::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl ExprReport {
+    pub fn is_ok(&self) -> bool {
+        self.errs.is_empty()
+    }
+
+    /// If a secret contributed to the value
+    pub fn is_secret(&self) -> bool {
+        self.provenance.as_ref().is_some_and(Provenance::is_secret)
+    }
+
+    #[cfg(feature = "json")]
+    fn to_json(&self) -> serde_json::Value {
+        use serde_json::{Value as Json, json};
+
+        use crate::errors::diagnostics::{AsDiagnostic, ExprDiagnosticRange};
+
+        fn range_to_json(range: &ExprDiagnosticRange) -> Json {
+            json!({
+                "start": { "line": range.start.line, "character": range.start.character },
+                "end": { "line": range.end.line, "character": range.end.character },
+            })
+        }
+
+        let value = self.value.as_ref().map(|value| match value {
+            _ if self.is_secret() => json!(crate::explain::REDACTED),
+            Value::String(string) => json!(string),
+            Value::Number(number) => json!(number),
+            Value::Bool(bool) => json!(bool),
+            value => json!(value.to_string()),
+        });
+
+        let diagnostics: Vec<Json> = self
+            .errs
+            .iter()
+            .map(|(err, span)| {
+                let diagnostic = err.as_diagnostic(&self.source, span);
+
+                let mut json = json!({
+                    "code": diagnostic.code,
+                    "message": diagnostic.message,
+                    "range": range_to_json(&diagnostic.range),
+                });
+
+                if let Some(fix) = diagnostic.fix {
+                    json["fix"] = json!({
+                        "range": range_to_json(&fix.range),
+                        "replacement": fix.replacement,
+                    });
+                }
+
+                json
+            })
+            .collect();
+
+        json!({
+            "name": self.name,
+            "value": value,
+            "type": self.value.as_ref().map(|value| value.get_type().to_string()),
+            "secret": self.is_secret(),
+            "provenance": self.provenance.as_ref().map(|provenance| json!({
+                "vars": provenance.vars,
+                "prompts": provenance.prompts,
+                "secrets": provenance.secrets,
+                "client_context": provenance.client_context,
+            })),
+            "diagnostics": diagnostics,
+            "duration_us": self.duration.as_micros() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod document_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    use crate::errors::{CompileError, ExprError, RuntimeError};
+
+    fn env() -> (CompileTimeEnv, RuntimeEnv) {
+        let env = CompileTimeEnv::new(
+            vec!["user_id".to_string()],
+            vec![],
+            vec!["token".to_string()],
+            vec![],
+        );
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["123".to_string()],
+            secrets: vec!["abc".to_string()],
+            ..Default::default()
+        };
+
+        (env, runtime_env)
+    }
+
+    #[test]
+    fn evaluates_every_expression_in_order() {
+        let (env, runtime_env) = env();
+
+        let report = evaluate_all(
+            [
+                ("auth", "(concat `Bearer ` !token)"),
+                ("missing", ":missing"),
+                ("id", ":user_id"),
+                ("bool", "(parse_bool `maybe`)"),
+            ],
+            &env,
+            &runtime_env,
+        );
+
+        assert!(!report.is_ok());
+        assert_eq!(
+            vec!["auth", "missing", "id", "bool"],
+            report
+                .exprs
+                .iter()
+                .map(|expr| expr.name.as_str())
+                .collect::<Vec<_>>()
+        );
+
+        let auth = report.get("auth").unwrap();
+        assert_eq!(Some(Value::String("Bearer abc".into())), auth.value);
+        assert!(auth.is_secret());
+
+        let missing = report.get("missing").unwrap();
+        assert_eq!(
+            vec![(
+                ExprError::CompileError(CompileError::Undefined(":missing".to_string())),
+                0..8
+            )],
+            missing.errs
+        );
+        assert_eq!(None, missing.provenance);
+
+        let id = report.get("id").unwrap();
+        assert!(id.is_ok());
+        assert!(!id.is_secret());
+        assert_eq!(
+            Some(["user_id".to_string()].into()),
+            id.provenance
+                .as_ref()
+                .map(|provenance| provenance.vars.clone())
+        );
+
+        let bool = report.get("bool").unwrap();
+        assert!(matches!(
+            bool.errs.as_slice(),
+            [(
+                ExprError::RuntimeError(RuntimeError::InvalidArgument { .. }),
+                _
+            )]
+        ));
+    }
+
+    #[test]
+    fn empty_documents_are_ok() {
+        let (env, runtime_env) = env();
+
+        let report = evaluate_all(Vec::<(&str, &str)>::new(), &env, &runtime_env);
+
+        assert!(report.is_ok());
+        assert_eq!(DocumentReport::default(), report);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn document_json() {
+        use serde_json::json;
+
+        let (env, runtime_env) = env();
+
+        let report = evaluate_all(
+            [
+                ("auth", "(concat `Bearer ` !token)"),
+                ("id", "user_id"),
+                ("url", "(concat `/users/` :user_id)"),
+            ],
+            &env,
+            &runtime_env,
+        );
+
+        let mut json = report.to_json();
+        json["duration_us"] = json!(0);
+        for expr in json["exprs"].as_array_mut().unwrap() {
+            expr["duration_us"] = json!(0);
+        }
+
+        assert_eq!(
+            json!({
+                "ok": false,
+                "duration_us": 0,
+                "exprs": [
+                    {
+                        "name": "auth",
+                        "value": "<redacted>",
+                        "type": "String",
+                        "secret": true,
+                        "provenance": {
+                            "vars": [],
+                            "prompts": [],
+                            "secrets": ["token"],
+                            "client_context": [],
+                        },
+                        "diagnostics": [],
+                        "duration_us": 0,
+                    },
+                    {
+                        "name": "id",
+                        "value": null,
+                        "type": null,
+                        "secret": false,
+                        "provenance": null,
+                        "diagnostics": [{
+                            "code": "compiler",
+                            "message": "undefined: user_id, did you mean `:user_id`? vars use the `:` sigil",
+                            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 7 } },
+                            "fix": {
+                                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 7 } },
+                                "replacement": ":user_id",
+                            },
+                        }],
+                        "duration_us": 0,
+                    },
+                    {
+                        "name": "url",
+                        "value": "/users/123",
+                        "type": "String",
+                        "secret": false,
+                        "provenance": {
+                            "vars": ["user_id"],
+                            "prompts": [],
+                            "secrets": [],
+                            "client_context": [],
+                        },
+                        "diagnostics": [],
+                        "duration_us": 0,
+                    },
+                ],
+            }),
+            json
+        );
+    }
+}
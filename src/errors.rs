@@ -6,10 +6,11 @@ use lalrpop_util::ParseError;
 use thiserror::Error;
 
 use crate::{
-    errors::diagnostics::{ExprDiagnosisSeverity, ExprDiagnostic, get_range},
+    errors::diagnostics::{ExprDiagnosisSeverity, ExprDiagnostic, ExprFix, get_range},
     lexer::Token,
     span::{Span, Spanned},
     types::Type,
+    value::Value,
 };
 
 pub type ExprResult<T> = std::result::Result<T, Vec<ExprErrorS>>;
@@ -24,6 +25,8 @@ pub enum ExprError {
     CompileError(#[from] CompileError),
     #[error("There was a runtime error with the expression: {0}")]
     RuntimeError(#[from] RuntimeError),
+    #[error("There was an error disassembling the bytecode: {0}")]
+    DisassembleError(#[from] DisassembleError),
 }
 
 impl diagnostics::AsDiagnostic for ExprError {
@@ -33,6 +36,7 @@ impl diagnostics::AsDiagnostic for ExprError {
             ExprError::CompileError(e) => e.as_diagnostic(source, span),
             ExprError::SyntaxError(e) => e.as_diagnostic(source, span),
             ExprError::RuntimeError(e) => e.as_diagnostic(source, span),
+            ExprError::DisassembleError(e) => e.as_diagnostic(source, span),
         }
     }
 }
@@ -44,6 +48,14 @@ pub enum LexicalError {
     InvalidToken,
     #[error("Invalid number $0")]
     InvalidNumber(ParseFloatError),
+    #[error("Token exceeds max lookahead of {max} bytes")]
+    TokenTooLong { max: usize },
+    #[error("Invalid UTF-8")]
+    InvalidUtf8,
+    #[error("Unable to read source: {0}")]
+    Io(String),
+    #[error("Unterminated interpolation, expected `}}`")]
+    UnterminatedInterpolation,
 }
 
 impl diagnostics::AsDiagnostic for LexicalError {
@@ -54,6 +66,8 @@ impl diagnostics::AsDiagnostic for LexicalError {
             range: get_range(source, span),
             severity: Some(ExprDiagnosisSeverity::ERROR),
             message: format!("{self}"),
+            notes: vec![],
+            fix: None,
         }
     }
 }
@@ -75,6 +89,10 @@ pub enum SyntaxError {
     },
     #[error("unterminated string")]
     UnterminatedString,
+    #[error("unterminated template placeholder")]
+    UnterminatedPlaceholder,
+    #[error("nesting exceeds max depth of {max}")]
+    TooDeep { max: usize },
 }
 
 impl SyntaxError {
@@ -124,24 +142,32 @@ impl diagnostics::AsDiagnostic for SyntaxError {
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             SyntaxError::InvalidToken => ExprDiagnostic {
                 code: error_code,
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             SyntaxError::UnexpectedInput { token: _ } => ExprDiagnostic {
                 code: error_code,
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             SyntaxError::UnrecognizedEOF { expected: _ } => ExprDiagnostic {
                 code: error_code,
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             SyntaxError::UnrecognizedToken {
                 token: _,
@@ -151,12 +177,32 @@ impl diagnostics::AsDiagnostic for SyntaxError {
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             SyntaxError::UnterminatedString => ExprDiagnostic {
                 code: error_code,
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            SyntaxError::UnterminatedPlaceholder => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            SyntaxError::TooDeep { max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
         }
     }
@@ -167,13 +213,61 @@ pub enum CompileError {
     #[error("undefined: {0}")]
     Undefined(String),
     #[error("expects {expected} arguments but received {actual}")]
-    WrongNumberOfArgs { expected: usize, actual: usize },
+    WrongNumberOfArgs {
+        expected: usize,
+        actual: usize,
+        /// Signature of the function being called
+        signature: String,
+    },
     #[error("call expression without a callee")]
     NoCallee,
     #[error("expected type {expected} but received {actual}")]
     TypeMismatch { expected: Type, actual: Type },
     #[error("invalid lookup type: {0}")]
     InvalidLookupType(u8),
+    #[error("calls are nested deeper than the max depth of {max}")]
+    TooDeeplyNested { max: usize },
+    #[error("runtime env has {actual} {lookup} value(s) but the compile time env has {expected}")]
+    RuntimeEnvMismatch {
+        lookup: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("calls can have at most {max} arguments but received {actual}")]
+    TooManyArgs { max: usize, actual: usize },
+    #[error("lists can have at most {max} items but received {actual}")]
+    TooManyItems { max: usize, actual: usize },
+    #[error("expressions can have at most {max} constants")]
+    TooManyConstants { max: usize },
+    #[error("expressions can have at most {max} types")]
+    TooManyTypes { max: usize },
+    #[error("expected (def name expr) at the top level of a program")]
+    InvalidDef,
+    #[error("already defined: {0}")]
+    AlreadyDefined(String),
+    #[error("{0} is a definition and must be called like ({0})")]
+    DefNotCalled(String),
+    #[error("expected (use `path`) at the top level of a program")]
+    InvalidUse,
+    #[error("unable to resolve module {path}: {message}")]
+    UnresolvedModule { path: String, message: String },
+    #[error("invalid module {path}: {message}")]
+    InvalidModule { path: String, message: String },
+    #[error("expected (let (name value) body)")]
+    InvalidLet,
+    #[error("expected ...list as the last argument of a call")]
+    InvalidSpread,
+    #[error("expressions can have at most {max} nested let bindings")]
+    TooManyLocals { max: usize },
+    #[error("only the first {max} {lookup}s in the compile time env can be referenced")]
+    TooManyNames { lookup: String, max: usize },
+    #[error("undefined: {name}, did you mean `{sigil}{name}`? {lookup} use the `{sigil}` sigil")]
+    MissingSigil {
+        name: String,
+        /// Kind of name it matched e.g. `vars`
+        lookup: String,
+        sigil: char,
+    },
 }
 
 impl diagnostics::AsDiagnostic for CompileError {
@@ -185,21 +279,28 @@ impl diagnostics::AsDiagnostic for CompileError {
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             CompileError::WrongNumberOfArgs {
                 expected: _,
                 actual: _,
+                signature,
             } => ExprDiagnostic {
                 code: error_code,
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![format!("expected signature: {signature}")],
+                fix: None,
             },
             CompileError::NoCallee => ExprDiagnostic {
                 code: error_code,
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             CompileError::TypeMismatch {
                 expected: _,
@@ -209,12 +310,169 @@ impl diagnostics::AsDiagnostic for CompileError {
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             CompileError::InvalidLookupType(_) => ExprDiagnostic {
                 code: error_code,
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::TooDeeplyNested { max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::RuntimeEnvMismatch {
+                lookup: _,
+                expected: _,
+                actual: _,
+            } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::TooManyArgs { max: _, actual: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::TooManyItems { max: _, actual: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::TooManyConstants { max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::TooManyTypes { max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::InvalidDef => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::AlreadyDefined(_) => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::DefNotCalled(_) => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::InvalidUse => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::InvalidSpread => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::UnresolvedModule {
+                path: _,
+                message: _,
+            } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::InvalidModule {
+                path: _,
+                message: _,
+            } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::InvalidLet => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::TooManyLocals { max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::TooManyNames { lookup: _, max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            CompileError::MissingSigil {
+                name,
+                lookup: _,
+                sigil,
+            } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: Some(ExprFix {
+                    range: get_range(source, span),
+                    replacement: format!("{sigil}{name}"),
+                }),
             },
         }
     }
@@ -226,6 +484,67 @@ pub enum RuntimeError {
     EmptyStack,
     #[error("expected type {expected} but received {actual}")]
     TypeMismatch { expected: Type, actual: Type },
+    #[error("stack overflow: exceeded max stack depth of {max}")]
+    StackOverflow { max: usize },
+    #[error("bytecode exceeds max constants of {max}")]
+    TooManyConstants { max: usize },
+    #[error("unable to get secret !{name}: {message}")]
+    SecretUnavailable { name: String, message: String },
+    #[error("invalid argument {name}: {message}")]
+    InvalidArgument { name: String, message: String },
+    #[error("invalid snapshot: {message}")]
+    InvalidSnapshot { message: String },
+    /// Bytecode referencing something that doesn't exist when it's evaluated,
+    /// e.g. a var index past the end of the env
+    #[error("invalid bytecode: {message}")]
+    InvalidBytecode { message: String },
+    #[error("{name} expects {expected} args but received {actual}")]
+    WrongNumberOfArgs {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("output of {size} bytes exceeds max output size of {max} bytes")]
+    OutputTooLarge { size: usize, max: usize },
+    #[error("builtin {name} exceeded its timeout")]
+    BuiltinTimeout { name: String },
+    /// An `assert` or `assert_eq` failed
+    ///
+    /// `left` and `right` are the values an `assert_eq` compared.
+    #[error("assertion failed: {message}{}", assertion_values(.left, .right))]
+    AssertionFailed {
+        message: String,
+        left: Option<Value>,
+        right: Option<Value>,
+    },
+}
+
+/// The values an assertion compared, if any, e.g. `` (left: `a`, right: `b`) ``
+fn assertion_values(left: &Option<Value>, right: &Option<Value>) -> String {
+    match (left, right) {
+        (Some(left), Some(right)) => format!(" (left: {left}, right: {right})"),
+        _ => String::new(),
+    }
+}
+
+impl RuntimeError {
+    /// Name of the variant in `snake_case`, e.g. for labelling metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RuntimeError::EmptyStack => "empty_stack",
+            RuntimeError::TypeMismatch { .. } => "type_mismatch",
+            RuntimeError::StackOverflow { .. } => "stack_overflow",
+            RuntimeError::TooManyConstants { .. } => "too_many_constants",
+            RuntimeError::SecretUnavailable { .. } => "secret_unavailable",
+            RuntimeError::InvalidArgument { .. } => "invalid_argument",
+            RuntimeError::InvalidSnapshot { .. } => "invalid_snapshot",
+            RuntimeError::InvalidBytecode { .. } => "invalid_bytecode",
+            RuntimeError::WrongNumberOfArgs { .. } => "wrong_number_of_args",
+            RuntimeError::OutputTooLarge { .. } => "output_too_large",
+            RuntimeError::BuiltinTimeout { .. } => "builtin_timeout",
+            RuntimeError::AssertionFailed { .. } => "assertion_failed",
+        }
+    }
 }
 
 impl diagnostics::AsDiagnostic for RuntimeError {
@@ -237,6 +556,8 @@ impl diagnostics::AsDiagnostic for RuntimeError {
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
             RuntimeError::TypeMismatch {
                 expected: _,
@@ -246,11 +567,240 @@ impl diagnostics::AsDiagnostic for RuntimeError {
                 range: get_range(source, span),
                 severity: Some(ExprDiagnosisSeverity::ERROR),
                 message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::StackOverflow { max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::TooManyConstants { max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::SecretUnavailable {
+                name: _,
+                message: _,
+            } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::InvalidArgument {
+                name: _,
+                message: _,
+            } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::InvalidSnapshot { message: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::InvalidBytecode { message: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::WrongNumberOfArgs { .. } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::OutputTooLarge { size: _, max: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::BuiltinTimeout { name: _ } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
+            },
+            RuntimeError::AssertionFailed {
+                message: _,
+                left: _,
+                right: _,
+            } => ExprDiagnostic {
+                code: error_code,
+                range: get_range(source, span),
+                severity: Some(ExprDiagnosisSeverity::ERROR),
+                message: format!("{self}"),
+                notes: vec![],
+                fix: None,
             },
         }
     }
 }
 
+/// Errors disassembling truncated or foreign bytecode
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum DisassembleError {
+    #[error("{op} at {op_idx:04} expects {expected} operand bytes but the bytecode ends")]
+    MissingOperands {
+        op: String,
+        op_idx: usize,
+        expected: usize,
+    },
+    #[error("{op} at {op_idx:04} references undefined {lookup} at index {index}")]
+    Undefined {
+        op: String,
+        op_idx: usize,
+        lookup: String,
+        index: usize,
+    },
+    #[error("GET at {op_idx:04} has invalid lookup type: {lookup_type}")]
+    InvalidLookupType { op_idx: usize, lookup_type: u8 },
+}
+
+impl diagnostics::AsDiagnostic for DisassembleError {
+    fn as_diagnostic(&self, source: &str, span: &Span) -> ExprDiagnostic {
+        ExprDiagnostic {
+            code: "disassembler".to_string(),
+            range: get_range(source, span),
+            severity: Some(ExprDiagnosisSeverity::ERROR),
+            message: format!("{self}"),
+            notes: vec![],
+            fix: None,
+        }
+    }
+}
+
+/// Errors loading or resolving values from a provider
+#[derive(Debug, Error)]
+pub enum ProviderError {
+    #[error("unable to read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("invalid line {line}: expected KEY=value")]
+    InvalidLine { line: usize },
+    #[error("no value provided for {0}")]
+    Missing(String),
+    #[error("{0}")]
+    Unavailable(String),
+}
+
+/// Errors building or ordering a dependency graph of expressions
+#[derive(Debug, Error, PartialEq)]
+pub enum DependencyError {
+    #[error("unable to compile {name}")]
+    Expr { name: String, errs: Vec<ExprErrorS> },
+    #[error("dependency cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// Errors assembling disassembled bytecode text
+#[derive(Debug, Error, PartialEq)]
+pub enum AssembleError {
+    #[error("expected a VERSION header followed by ----")]
+    MissingHeader,
+    #[error("unsupported bytecode version {0}")]
+    UnsupportedVersion(String),
+    #[error("line {line}: {message}")]
+    InvalidLine { line: usize, message: String },
+    #[error("no constant defined at index {0}")]
+    MissingConstant(usize),
+    #[error("no type defined at index {0}")]
+    MissingType(usize),
+}
+
+/// Differences found by [`verify_roundtrip`](crate::assembler::verify_roundtrip)
+/// between bytecode and the bytecode assembled from its disassembly
+#[derive(Debug, Error, PartialEq)]
+pub enum RoundtripError {
+    #[error("unable to disassemble bytecode")]
+    Disassemble(Vec<ExprErrorS>),
+    #[error("unable to assemble disassembled bytecode: {0}")]
+    Assemble(#[from] AssembleError),
+    #[error("op at {op_idx:04} assembled as {actual:?} but was {expected:?}")]
+    OpMismatch {
+        op_idx: usize,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    #[error("constant at index {index} assembled as {actual:?} but was {expected:?}")]
+    ConstantMismatch {
+        index: usize,
+        expected: Option<Value>,
+        actual: Option<Value>,
+    },
+    #[error("type at index {index} assembled as {actual:?} but was {expected:?}")]
+    TypeMismatch {
+        index: usize,
+        expected: Option<String>,
+        actual: Option<String>,
+    },
+}
+
+/// Errors reading bytecode or VM snapshots from the
+/// [binary format](crate::binary)
+#[derive(Debug, Error, PartialEq)]
+pub enum BytecodeError {
+    #[error("invalid magic bytes")]
+    InvalidMagic,
+    #[error("unsupported bytecode version {0}")]
+    UnsupportedVersion(String),
+    #[error("unexpected end of bytecode at byte {offset}")]
+    UnexpectedEnd { offset: usize },
+    #[error("invalid tag {tag} at byte {offset}")]
+    InvalidTag { offset: usize, tag: u8 },
+    #[error("invalid UTF-8 string at byte {offset}")]
+    InvalidString { offset: usize },
+    #[error("unexpected trailing bytes at byte {offset}")]
+    TrailingBytes { offset: usize },
+    #[error("undefined function {name} at byte {offset}")]
+    UndefinedFn { offset: usize, name: String },
+    #[error("declared max stack depth {declared} but the code needs {actual}")]
+    StackDepthMismatch { declared: usize, actual: usize },
+    #[error("invalid op code {op_code} at byte {offset}")]
+    InvalidOpCode { offset: usize, op_code: u8 },
+    #[error("missing operands of the op at byte {offset}")]
+    MissingOperands { offset: usize },
+    #[error("invalid lookup type {lookup} at byte {offset}")]
+    InvalidLookup { offset: usize, lookup: u8 },
+    #[error("undefined constant {index} at byte {offset}")]
+    UndefinedConstant { offset: usize, index: usize },
+    #[error("undefined type {index} at byte {offset}")]
+    UndefinedType { offset: usize, index: usize },
+    #[error("local {slot} used before it's set at byte {offset}")]
+    UndefinedLocal { offset: usize, slot: usize },
+    #[error("jump at byte {offset} to byte {target} doesn't land on an op")]
+    InvalidJump { offset: usize, target: usize },
+}
+
 pub type ExprErrorS = Spanned<ExprError>;
 
 pub mod diagnostics {
@@ -282,16 +832,31 @@ pub mod diagnostics {
         pub severity: Option<ExprDiagnosisSeverity>,
 
         pub message: String,
+
+        pub notes: Vec<String>,
+
+        pub fix: Option<ExprFix>,
+    }
+
+    /// A machine-applicable fix replacing the source in a range
+    #[derive(Debug, Eq, PartialEq, Clone, Default)]
+    pub struct ExprFix {
+        pub range: ExprDiagnosticRange,
+
+        pub replacement: String,
     }
 
     impl ExprDiagnostic {
         pub fn to_diagnostic(&self, span: &Span) -> codespan_reporting::diagnostic::Diagnostic<()> {
             codespan_reporting::diagnostic::Diagnostic {
-                severity: ExprDiagnosisSeverity::ERROR.to_severity(),
+                severity: self
+                    .severity
+                    .unwrap_or(ExprDiagnosisSeverity::ERROR)
+                    .to_severity(),
                 code: Some(self.code.clone()),
                 message: self.message.clone(),
                 labels: vec![Label::primary((), span.clone())],
-                notes: vec![],
+                notes: self.notes.clone(),
             }
         }
     }
@@ -364,8 +929,12 @@ pub mod diagnostics {
     ///
     /// Line and column are zero based
     pub fn index_to_position(source: &str, index: usize) -> (usize, usize) {
-        let lookup = LineColLookup::new(source);
+        lookup_position(&LineColLookup::new(source), index)
+    }
 
+    /// [`index_to_position`] using a lookup shared between indexes so the
+    /// source is only scanned once
+    pub(crate) fn lookup_position(lookup: &LineColLookup, index: usize) -> (usize, usize) {
         let (line, char) = lookup.get(index);
 
         (line - 1, char - 1)
@@ -478,8 +1047,11 @@ pub mod diagnostics {
     #[cfg(test)]
     mod error_to_diagnostics_tests {
         use crate::{
-            errors::{CompileError, ExprError, LexicalError, RuntimeError, SyntaxError},
+            errors::{
+                CompileError, DisassembleError, ExprError, LexicalError, RuntimeError, SyntaxError,
+            },
             types::Type,
+            value::Value,
         };
 
         use super::*;
@@ -510,16 +1082,64 @@ pub mod diagnostics {
         }
 
         #[test]
-        fn it_converts_compileerror_undefined_to_diagnostic() {
+        fn it_converts_lexerror_token_too_long_to_diagnostic() {
             let source = dummy_source();
             let range = dummy_range();
-            let error = ExprError::CompileError(CompileError::Undefined("var".to_string()));
+            let error = ExprError::LexError(LexicalError::TokenTooLong { max: 8 });
             let diagnostics = get_diagnostics(&[(error, range.clone())], source);
 
             assert_eq!(diagnostics.len(), 1);
             let diagnostic = &diagnostics[0];
-            assert_eq!(diagnostic.code, Some("compiler".to_string()));
-            assert_eq!(diagnostic.message, "undefined: var".to_string());
+            assert_eq!(diagnostic.code, Some("lexical".to_string()));
+            assert_eq!(diagnostic.message, "Token exceeds max lookahead of 8 bytes");
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_lexerror_invalid_utf8_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::LexError(LexicalError::InvalidUtf8);
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("lexical".to_string()));
+            assert_eq!(diagnostic.message, "Invalid UTF-8");
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_lexerror_io_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::LexError(LexicalError::Io("broken pipe".to_string()));
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("lexical".to_string()));
+            assert_eq!(diagnostic.message, "Unable to read source: broken pipe");
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_undefined_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::Undefined("var".to_string()));
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(diagnostic.message, "undefined: var".to_string());
             assert_eq!(diagnostic.severity, Severity::Error);
             assert_eq!(diagnostic.labels.len(), 1);
             assert_eq!(diagnostic.labels[0], Label::primary((), range));
@@ -532,6 +1152,7 @@ pub mod diagnostics {
             let error = ExprError::CompileError(CompileError::WrongNumberOfArgs {
                 expected: 2,
                 actual: 3,
+                signature: "eq(a: Value, b: Value) -> Bool".to_string(),
             });
             let diagnostics = get_diagnostics(&[(error, range.clone())], source);
 
@@ -545,6 +1166,10 @@ pub mod diagnostics {
             assert_eq!(diagnostic.severity, Severity::Error);
             assert_eq!(diagnostic.labels.len(), 1);
             assert_eq!(diagnostic.labels[0], Label::primary((), range));
+            assert_eq!(
+                diagnostic.notes,
+                vec!["expected signature: eq(a: Value, b: Value) -> Bool".to_string()]
+            );
         }
 
         #[test]
@@ -604,6 +1229,356 @@ pub mod diagnostics {
             assert_eq!(diagnostic.labels[0], Label::primary((), range));
         }
 
+        #[test]
+        fn it_converts_compileerror_too_deeply_nested_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::TooDeeplyNested { max: 8 });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "calls are nested deeper than the max depth of 8".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_runtime_env_mismatch_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::RuntimeEnvMismatch {
+                lookup: "var".to_string(),
+                expected: 2,
+                actual: 1,
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "runtime env has 1 var value(s) but the compile time env has 2".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_too_many_args_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::TooManyArgs {
+                max: 255,
+                actual: 256,
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "calls can have at most 255 arguments but received 256".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_too_many_items_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::TooManyItems {
+                max: 255,
+                actual: 256,
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "lists can have at most 255 items but received 256".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_too_many_constants_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::TooManyConstants { max: 256 });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "expressions can have at most 256 constants".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_too_many_types_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::TooManyTypes { max: 256 });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "expressions can have at most 256 types".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_invalid_def_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::InvalidDef);
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "expected (def name expr) at the top level of a program".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_invalid_use_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::InvalidUse);
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "expected (use `path`) at the top level of a program".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_invalid_spread_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::InvalidSpread);
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "expected ...list as the last argument of a call".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_unresolved_module_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::UnresolvedModule {
+                path: "./helpers.expr".to_string(),
+                message: "not found".to_string(),
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "unable to resolve module ./helpers.expr: not found".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_invalid_module_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::InvalidModule {
+                path: "./helpers.expr".to_string(),
+                message: "already defined: concat".to_string(),
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "invalid module ./helpers.expr: already defined: concat".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_already_defined_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::AlreadyDefined("concat".to_string()));
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(diagnostic.message, "already defined: concat".to_string());
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_def_not_called_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::DefNotCalled("greeting".to_string()));
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "greeting is a definition and must be called like (greeting)".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_invalid_let_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::InvalidLet);
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "expected (let (name value) body)".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_too_many_locals_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::TooManyLocals { max: 256 });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "expressions can have at most 256 nested let bindings".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_too_many_names_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::CompileError(CompileError::TooManyNames {
+                lookup: "var".to_string(),
+                max: 256,
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "only the first 256 vars in the compile time env can be referenced".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_compileerror_missing_sigil_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = CompileError::MissingSigil {
+                name: "token".to_string(),
+                lookup: "vars".to_string(),
+                sigil: ':',
+            };
+            let diagnostics = get_diagnostics(&[(error.clone().into(), range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("compiler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "undefined: token, did you mean `:token`? vars use the `:` sigil".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range.clone()));
+            assert_eq!(
+                Some(ExprFix {
+                    range: get_range(source, &range),
+                    replacement: ":token".to_string(),
+                }),
+                error.as_diagnostic(source, &range).fix
+            );
+        }
+
         #[test]
         fn it_converts_runtimeerror_undefined_to_diagnostic() {
             let source = dummy_source();
@@ -645,6 +1620,202 @@ pub mod diagnostics {
             assert_eq!(diagnostic.labels[0], Label::primary((), range));
         }
 
+        #[test]
+        fn it_converts_runtimeerror_stack_overflow_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::StackOverflow { max: 8 });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "stack overflow: exceeded max stack depth of 8".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_runtimeerror_invalid_argument_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::InvalidArgument {
+                name: "length".to_string(),
+                message: "expected a whole number".to_string(),
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "invalid argument length: expected a whole number".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_runtimeerror_invalid_snapshot_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::InvalidSnapshot {
+                message: "expected a GET of name at 0003 in the bytecode".to_string(),
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "invalid snapshot: expected a GET of name at 0003 in the bytecode".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_runtimeerror_invalid_bytecode_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::InvalidBytecode {
+                message: "undefined variable 3".to_string(),
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "invalid bytecode: undefined variable 3".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_runtimeerror_wrong_number_of_args_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::WrongNumberOfArgs {
+                name: "trim".to_string(),
+                expected: 1,
+                actual: 0,
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "trim expects 1 args but received 0".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_runtimeerror_output_too_large_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::OutputTooLarge {
+                size: 2048,
+                max: 1024,
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "output of 2048 bytes exceeds max output size of 1024 bytes".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_runtimeerror_builtin_timeout_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::BuiltinTimeout {
+                name: "slow".to_string(),
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "builtin slow exceeded its timeout".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_runtimeerror_assertion_failed_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::RuntimeError(RuntimeError::AssertionFailed {
+                message: "expected a successful response".to_string(),
+                left: Some(Value::String("404".into())),
+                right: Some(Value::String("200".into())),
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("runtime".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "assertion failed: expected a successful response (left: `404`, right: `200`)"
+                    .to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_disassembleerror_missing_operands_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::DisassembleError(DisassembleError::MissingOperands {
+                op: "CALL".to_string(),
+                op_idx: 3,
+                expected: 1,
+            });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("disassembler".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "CALL at 0003 expects 1 operand bytes but the bytecode ends".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
         #[test]
         fn it_converts_syntaxerror_unrecognized_eof_to_diagnostic() {
             let source = dummy_source();
@@ -741,6 +1912,44 @@ pub mod diagnostics {
             assert_eq!(diagnostic.labels[0], Label::primary((), range));
         }
 
+        #[test]
+        fn it_converts_syntaxerror_unterminated_placeholder_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::SyntaxError(SyntaxError::UnterminatedPlaceholder);
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("syntax".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "unterminated template placeholder".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
+        #[test]
+        fn it_converts_syntaxerror_too_deep_to_diagnostic() {
+            let source = dummy_source();
+            let range = dummy_range();
+            let error = ExprError::SyntaxError(SyntaxError::TooDeep { max: 1024 });
+            let diagnostics = get_diagnostics(&[(error, range.clone())], source);
+
+            assert_eq!(diagnostics.len(), 1);
+            let diagnostic = &diagnostics[0];
+            assert_eq!(diagnostic.code, Some("syntax".to_string()));
+            assert_eq!(
+                diagnostic.message,
+                "nesting exceeds max depth of 1024".to_string()
+            );
+            assert_eq!(diagnostic.severity, Severity::Error);
+            assert_eq!(diagnostic.labels.len(), 1);
+            assert_eq!(diagnostic.labels[0], Label::primary((), range));
+        }
+
         #[test]
         fn it_converts_syntaxerror_extra_token_to_diagnostic() {
             let source = dummy_source();
@@ -0,0 +1,411 @@
+//! Explaining how a [`Vm`](crate::vm::Vm) evaluated an expression
+//!
+//! [`Vm::explain`](crate::vm::Vm::explain) returns an [`Explanation`] tree
+//! alongside the value: each call's callee, the value of each of its args, and
+//! its result. `cond`, `and`, and `or` only evaluate the branch they take so
+//! they're explained by their condition and that branch. A `try` is explained
+//! by its guarded expression, or its fallback if the guarded one failed.
+//! Values a secret
+//! contributed to are redacted so explanations can be shown in a REPL or
+//! editor without leaking them.
+
+use std::fmt::Display;
+
+use crate::value::Value;
+
+/// Shown in place of a redacted value
+pub const REDACTED: &str = "<redacted>";
+
+/// How a value was evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub enum Explanation {
+    /// A literal, or a lookup of a builtin, var, prompt, secret, client
+    /// context value, or type
+    Value {
+        /// Source form e.g. `:name`, `` `text` ``, or `concat`
+        source: String,
+        /// `None` if it's a secret
+        value: Option<Value>,
+    },
+    /// A call and the explanations of its callee and args
+    Call {
+        callee: Box<Explanation>,
+        args: Vec<Explanation>,
+        /// `None` if a secret contributed to the callee or any arg
+        value: Option<Value>,
+    },
+    /// A list literal and the explanations of its items
+    List {
+        items: Vec<Explanation>,
+        /// `None` if a secret contributed to any item
+        value: Option<Value>,
+    },
+    /// A `cond` and the explanations of its condition and the branch it took
+    ///
+    /// `and` and `or` are compiled to a `cond` so they're explained as one.
+    Branch {
+        condition: Box<Explanation>,
+        /// If the condition was `true` and the `then` branch was taken
+        then: bool,
+        taken: Box<Explanation>,
+        /// `None` if a secret contributed to the condition or the branch taken
+        value: Option<Value>,
+    },
+    /// A `try` and the explanation of the expression whose value it returned
+    Try {
+        /// If the guarded expression failed and the fallback was evaluated
+        caught: bool,
+        evaluated: Box<Explanation>,
+        /// `None` if a secret contributed to the expression evaluated
+        value: Option<Value>,
+    },
+}
+
+impl Explanation {
+    /// Explain a literal or lookup, redacting it if it's a secret
+    pub(crate) fn new_value(source: String, value: &Value, secret: bool) -> Self {
+        Explanation::Value {
+            source,
+            value: (!secret).then(|| value.clone()),
+        }
+    }
+
+    /// Explain a call, redacting its result if its callee or any arg was redacted
+    pub(crate) fn new_call(callee: Explanation, args: Vec<Explanation>, value: &Value) -> Self {
+        let redacted = callee.is_redacted() || args.iter().any(Explanation::is_redacted);
+
+        Explanation::Call {
+            callee: callee.into(),
+            args,
+            value: (!redacted).then(|| value.clone()),
+        }
+    }
+
+    /// Explain a list, redacting it if any item was redacted
+    pub(crate) fn new_list(items: Vec<Explanation>, value: &Value) -> Self {
+        let redacted = items.iter().any(Explanation::is_redacted);
+
+        Explanation::List {
+            items,
+            value: (!redacted).then(|| value.clone()),
+        }
+    }
+
+    /// Explain a `cond` by its condition and the branch it took, redacting
+    /// it if either was redacted
+    pub(crate) fn new_branch(condition: Explanation, then: bool, taken: Explanation) -> Self {
+        let redacted = condition.is_redacted() || taken.is_redacted();
+        let value = taken.value().filter(|_| !redacted).cloned();
+
+        Explanation::Branch {
+            condition: condition.into(),
+            then,
+            taken: taken.into(),
+            value,
+        }
+    }
+
+    /// Explain a `try` by the guarded expression or the fallback, redacting
+    /// it if that was redacted
+    pub(crate) fn new_try(caught: bool, evaluated: Explanation) -> Self {
+        let value = evaluated.value().cloned();
+
+        Explanation::Try {
+            caught,
+            evaluated: evaluated.into(),
+            value,
+        }
+    }
+
+    /// What it evaluated to, `None` if it was redacted
+    pub fn value(&self) -> Option<&Value> {
+        match self {
+            Explanation::Value { value, .. }
+            | Explanation::Call { value, .. }
+            | Explanation::List { value, .. }
+            | Explanation::Branch { value, .. }
+            | Explanation::Try { value, .. } => value.as_ref(),
+        }
+    }
+
+    /// If a secret contributed to the value
+    pub fn is_redacted(&self) -> bool {
+        self.value().is_none()
+    }
+
+    /// Source form of what was evaluated
+    ///
+    /// Calls are rebuilt from the source of their callee and args so
+    /// definitions show as the expression they were defined as. The branch a
+    /// `cond` didn't take and the guarded expression or fallback a `try`
+    /// didn't return are shown as `…`.
+    pub fn source(&self) -> String {
+        match self {
+            Explanation::Value { source, .. } => source.clone(),
+            Explanation::Call { callee, args, .. } => {
+                let mut source = format!("({}", callee.source());
+
+                for arg in args {
+                    source.push(' ');
+                    source.push_str(&arg.source());
+                }
+
+                source.push(')');
+
+                source
+            }
+            Explanation::List { items, .. } => {
+                let items: Vec<String> = items.iter().map(Explanation::source).collect();
+
+                format!("[{}]", items.join(" "))
+            }
+            Explanation::Branch {
+                condition,
+                then,
+                taken,
+                ..
+            } => {
+                let (then, otherwise) = match then {
+                    true => (taken.source(), "…".to_string()),
+                    false => ("…".to_string(), taken.source()),
+                };
+
+                format!("(cond {} {then} {otherwise})", condition.source())
+            }
+            Explanation::Try {
+                caught, evaluated, ..
+            } => match caught {
+                false => format!("(try {} …)", evaluated.source()),
+                true => format!("(try … {})", evaluated.source()),
+            },
+        }
+    }
+
+    fn write_tree(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let source = self.source();
+        let value = match self.value() {
+            Some(value) => value.to_expr_source().unwrap_or_else(|| value.to_string()),
+            None => REDACTED.to_string(),
+        };
+
+        write!(f, "{:indent$}{source}", "", indent = depth * 2)?;
+
+        // Literals and builtins evaluate to themselves
+        if value != source {
+            write!(f, " => {value}")?;
+        }
+
+        writeln!(f)?;
+
+        match self {
+            Explanation::Value { .. } => {}
+            Explanation::Call { callee, args, .. } => {
+                callee.write_tree(f, depth + 1)?;
+
+                for arg in args {
+                    arg.write_tree(f, depth + 1)?;
+                }
+            }
+            Explanation::List { items, .. } => {
+                for item in items {
+                    item.write_tree(f, depth + 1)?;
+                }
+            }
+            Explanation::Branch {
+                condition, taken, ..
+            } => {
+                condition.write_tree(f, depth + 1)?;
+                taken.write_tree(f, depth + 1)?;
+            }
+            Explanation::Try { evaluated, .. } => evaluated.write_tree(f, depth + 1)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// An indented tree of each call with its callee and args below it, each
+/// list with its items below it, each `cond` with its condition and the
+/// branch it took below it, and each `try` with the expression it returned
+/// below it
+///
+/// ```text
+/// (concat `Bearer ` !token) => <redacted>
+///   concat
+///   `Bearer `
+///   !token => <redacted>
+/// ```
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.write_tree(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod explain_tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::prelude::*;
+
+    fn explain(source: &str) -> ExprResult<(Value, Explanation)> {
+        let env = CompileTimeEnv::new(
+            vec!["name".to_string()],
+            vec![],
+            vec!["token".to_string()],
+            vec![],
+        );
+        let runtime_env = RuntimeEnv {
+            vars: vec!["world".to_string()],
+            secrets: vec!["abc123".to_string()],
+            ..Default::default()
+        };
+
+        let bytecode = compile(&mut (parse(source).unwrap(), 0..source.len()), &env).unwrap();
+
+        Vm::new().explain(&bytecode, &env, &runtime_env)
+    }
+
+    #[test]
+    fn explains_nested_calls() {
+        let (value, explanation) = explain("(concat `hello ` (uppercase :name))").unwrap();
+
+        assert_eq!(Value::String("hello WORLD".into()), value);
+        assert_eq!(
+            "(concat `hello ` (uppercase :name)) => `hello WORLD`
+  concat
+  `hello `
+  (uppercase :name) => `WORLD`
+    uppercase
+    :name => `world`
+",
+            explanation.to_string()
+        );
+    }
+
+    #[test]
+    fn redacts_secrets_and_values_they_contribute_to() {
+        let (value, explanation) = explain("(eq (concat `Bearer ` !token) :name)").unwrap();
+
+        assert_eq!(Value::Bool(false), value);
+        assert!(explanation.is_redacted());
+        assert_eq!(
+            "(eq (concat `Bearer ` !token) :name) => <redacted>
+  eq
+  (concat `Bearer ` !token) => <redacted>
+    concat
+    `Bearer `
+    !token => <redacted>
+  :name => `world`
+",
+            explanation.to_string()
+        );
+    }
+
+    #[test]
+    fn explains_lists() {
+        let (_, explanation) = explain("[:name (uppercase :name) !token]").unwrap();
+
+        assert_eq!(
+            "[:name (uppercase :name) !token] => <redacted>
+  :name => `world`
+  (uppercase :name) => `WORLD`
+    uppercase
+    :name => `world`
+  !token => <redacted>
+",
+            explanation.to_string()
+        );
+    }
+
+    #[test]
+    fn explains_let_bindings_where_they_are_used() {
+        let (_, explanation) =
+            explain("(let (auth (concat `Bearer ` !token)) [auth :name])").unwrap();
+
+        assert_eq!(
+            "[(concat `Bearer ` !token) :name] => <redacted>
+  (concat `Bearer ` !token) => <redacted>
+    concat
+    `Bearer `
+    !token => <redacted>
+  :name => `world`
+",
+            explanation.to_string()
+        );
+    }
+
+    #[test]
+    fn explains_branches_taken() {
+        let (value, explanation) =
+            explain("(cond (eq :name `world`) (uppercase :name) !token)").unwrap();
+
+        assert_eq!(Value::String("WORLD".into()), value);
+        assert_eq!(
+            "(cond (eq :name `world`) (uppercase :name) …) => `WORLD`
+  (eq :name `world`) => true
+    eq
+    :name => `world`
+    `world`
+  (uppercase :name) => `WORLD`
+    uppercase
+    :name => `world`
+",
+            explanation.to_string()
+        );
+
+        let (_, explanation) =
+            explain("(concat (and false true) (or (eq !token `x`) false))").unwrap();
+
+        assert_eq!(
+            "(concat (cond false … false) (cond (eq !token `x`) … false)) => <redacted>
+  concat
+  (cond false … false) => false
+    false
+    false
+  (cond (eq !token `x`) … false) => <redacted>
+    (eq !token `x`) => <redacted>
+      eq
+      !token => <redacted>
+      `x`
+    false
+",
+            explanation.to_string()
+        );
+    }
+
+    #[test]
+    fn explains_tries() {
+        let (value, explanation) =
+            explain("(concat (try (assert false) `fallback`) (try :name `unused`))").unwrap();
+
+        assert_eq!(Value::String("fallbackworld".into()), value);
+        assert_eq!(
+            "(concat (try … `fallback`) (try :name …)) => `fallbackworld`
+  concat
+  (try … `fallback`) => `fallback`
+    `fallback`
+  (try :name …) => `world`
+    :name => `world`
+",
+            explanation.to_string()
+        );
+    }
+
+    #[test]
+    fn explains_values() {
+        let (_, explanation) = explain("true").unwrap();
+
+        assert_eq!(
+            Explanation::Value {
+                source: "true".to_string(),
+                value: Some(Value::Bool(true)),
+            },
+            explanation
+        );
+    }
+
+    #[test]
+    fn errors_are_returned() {
+        assert!(explain("(parse_bool `maybe`)").is_err());
+    }
+}
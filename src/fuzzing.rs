@@ -0,0 +1,204 @@
+//! Generators and round trip checks for fuzzing
+
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::{
+    assembler::verify_roundtrip,
+    ast::Expr,
+    builtins::BuiltinFn,
+    compiler::{CompileOptions, CompileTimeEnv, compile_with_options},
+    parser::parse,
+};
+
+/// How deeply calls are nested in generated expressions
+///
+/// Keeps the number of constants in generated expressions below what a
+/// single byte can index.
+const MAX_DEPTH: usize = 3;
+
+const MAX_ARGS: usize = 3;
+
+/// Names defined for every kind of identifier in [`env`]
+const NAMES: [&str; 2] = ["a", "b"];
+
+const TYPES: [&str; 8] = [
+    "String",
+    "Number",
+    "Bool",
+    "Value",
+    "Type<String>",
+    "Fn() -> Value",
+    "Fn(String, Bool) -> String",
+    "Fn(String, ...Value) -> Bool",
+];
+
+/// The environment generated expressions are compiled against
+pub fn env() -> CompileTimeEnv {
+    let names = || NAMES.iter().map(|name| name.to_string()).collect();
+
+    CompileTimeEnv::new(names(), names(), names(), names())
+}
+
+impl<'a> Arbitrary<'a> for Expr {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        arbitrary_expr(u, MAX_DEPTH)
+    }
+}
+
+fn arbitrary_expr(u: &mut Unstructured<'_>, depth: usize) -> arbitrary::Result<Expr> {
+    let kinds = if depth == 0 { 4 } else { 5 };
+
+    Ok(match u.choose_index(kinds)? {
+        0 => Expr::bool(u.arbitrary()?),
+        1 => {
+            let string: String = u.arbitrary()?;
+
+            Expr::string(&string.replace('`', ""))
+        }
+        2 => Expr::number(u.arbitrary::<u32>()? as f64 / 100.0),
+        3 => Expr::identifier(&arbitrary_identifier(u)?),
+        _ => {
+            let callee = arbitrary_expr(u, depth - 1)?;
+            let arg_count = u.int_in_range(0..=MAX_ARGS)?;
+
+            let args = (0..arg_count)
+                .map(|_| arbitrary_expr(u, depth - 1).map(|arg| (arg, 0..0)))
+                .collect::<arbitrary::Result<Vec<_>>>()?;
+
+            Expr::call((callee, 0..0), args)
+        }
+    })
+}
+
+/// An identifier, usually one defined in [`env`]
+fn arbitrary_identifier(u: &mut Unstructured<'_>) -> arbitrary::Result<String> {
+    let sigil = *u.choose(&["", ":", "?", "!", "@"])?;
+
+    if sigil.is_empty() {
+        if u.ratio(1, 4)? {
+            return Ok(u.choose(&TYPES)?.to_string());
+        }
+
+        if u.ratio(3, 4)? {
+            return Ok(u.choose(&BuiltinFn::DEFAULT_BUILTINS)?.name.to_string());
+        }
+    } else if u.ratio(3, 4)? {
+        return Ok(format!("{sigil}{}", u.choose(&NAMES)?));
+    }
+
+    // Undefined names start with a letter so they never lex as `true` or `false`
+    let mut name = format!("{sigil}a");
+
+    for _ in 0..u.int_in_range(0..=8)? {
+        name.push(*u.choose(&['a', 'z', 'A', 'Z', '0', '9', '_'])?);
+    }
+
+    Ok(name)
+}
+
+/// Source code for an expression that lexes and parses without errors
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidSource(pub String);
+
+impl<'a> Arbitrary<'a> for ValidSource {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self(Expr::arbitrary(u)?.to_string()))
+    }
+}
+
+/// Check an expression round trips through parse → print → parse and
+/// compile → disassemble → assemble
+///
+/// Panics if either round trip fails. Expressions that fail to compile
+/// against [`env`] only check the parse round trip.
+pub fn roundtrip(expr: &Expr) {
+    let env = env();
+    let source = expr.to_string();
+
+    let reparsed = parse(&source)
+        .unwrap_or_else(|errs| panic!("unable to parse printed expression {source}: {errs:?}"));
+
+    assert_eq!(source, reparsed.to_string(), "printed expressions differ");
+
+    for fuse_calls in [false, true] {
+        let ast = parse(&source).expect("printed expression should parse");
+
+        let Ok(bytecode) = compile_with_options(
+            &mut (ast, 0..source.len()),
+            &env,
+            &CompileOptions {
+                fuse_calls,
+                ..Default::default()
+            },
+        ) else {
+            continue;
+        };
+
+        verify_roundtrip(&bytecode, &env)
+            .unwrap_or_else(|err| panic!("{source} didn't round trip: {err}"));
+    }
+}
+
+#[cfg(test)]
+mod fuzzing_tests {
+    use super::*;
+
+    /// Deterministic pseudo random bytes
+    fn bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generated_exprs_round_trip() {
+        for seed in 0..500 {
+            let bytes = bytes(seed, 256);
+            let expr = Expr::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+
+            roundtrip(&expr);
+        }
+    }
+
+    #[test]
+    fn generated_sources_are_valid() {
+        for seed in 0..500 {
+            let bytes = bytes(seed, 256);
+            let ValidSource(source) =
+                ValidSource::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+
+            assert!(parse(&source).is_ok(), "{source}");
+        }
+    }
+
+    #[test]
+    fn generates_compilable_exprs() {
+        let env = env();
+
+        let compiled = (0..500)
+            .filter(|&seed| {
+                let bytes = bytes(seed, 256);
+                let ValidSource(source) =
+                    ValidSource::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+                let ast = parse(&source).unwrap();
+
+                compile_with_options(
+                    &mut (ast, 0..source.len()),
+                    &env,
+                    &CompileOptions::default(),
+                )
+                .is_ok()
+            })
+            .count();
+
+        assert!(compiled > 50, "only {compiled} expressions compiled");
+    }
+}
@@ -0,0 +1,192 @@
+//! Evaluate request headers from expressions
+
+use crate::{
+    compiler::{CompileTimeEnv, compile, lookup},
+    errors::{ExprErrorS, ExprResult},
+    parser::parse,
+    template::stringify,
+    vm::{RuntimeEnv, Vm},
+};
+
+/// A header value produced by [`eval_headers`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderValue {
+    pub name: String,
+    pub value: String,
+    /// If the expression referenced a secret
+    ///
+    /// Clients should redact these values when logging or displaying requests.
+    pub secret: bool,
+}
+
+/// Errors evaluating a header's expression
+#[derive(Debug, PartialEq)]
+pub struct HeaderError {
+    pub name: String,
+    /// Errors with spans in to the header's expression source
+    pub errs: Vec<ExprErrorS>,
+}
+
+/// Evaluate a map of header name → expression source
+///
+/// Every header is compiled against the same [`CompileTimeEnv`] and evaluated
+/// against the same [`RuntimeEnv`]. Headers are returned in the order they
+/// were given. If any header fails, the errors for every failed header are
+/// returned.
+///
+/// ```
+/// use reqlang_expr::{headers::eval_headers, prelude::*};
+///
+/// let env = CompileTimeEnv::new(vec![], vec![], vec!["token".to_string()], vec![]);
+///
+/// let runtime_env = RuntimeEnv {
+///     secrets: vec!["abc123".to_string()],
+///     ..Default::default()
+/// };
+///
+/// let headers = eval_headers(
+///     [
+///         ("Accept", "`application/json`"),
+///         ("Authorization", "(concat `Bearer ` !token)"),
+///     ],
+///     &env,
+///     &runtime_env,
+/// )
+/// .unwrap();
+///
+/// assert_eq!("application/json", headers[0].value);
+/// assert!(!headers[0].secret);
+///
+/// assert_eq!("Bearer abc123", headers[1].value);
+/// assert!(headers[1].secret);
+/// ```
+pub fn eval_headers<K, V>(
+    headers: impl IntoIterator<Item = (K, V)>,
+    env: &CompileTimeEnv,
+    runtime_env: &RuntimeEnv,
+) -> Result<Vec<HeaderValue>, Vec<HeaderError>>
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut values = vec![];
+    let mut errors = vec![];
+    let mut vm = Vm::new();
+
+    for (name, source) in headers {
+        let name = name.as_ref().to_string();
+
+        match eval_header(source.as_ref(), env, runtime_env, &mut vm) {
+            Ok((value, secret)) => values.push(HeaderValue {
+                name,
+                value,
+                secret,
+            }),
+            Err(errs) => errors.push(HeaderError { name, errs }),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(values)
+}
+
+fn eval_header(
+    source: &str,
+    env: &CompileTimeEnv,
+    runtime_env: &RuntimeEnv,
+    vm: &mut Vm,
+) -> ExprResult<(String, bool)> {
+    let ast = parse(source)?;
+    let bytecode = compile(&mut (ast, 0..source.len()), env)?;
+
+    let secret = bytecode.references_lookup(lookup::SECRET);
+
+    let value = vm.interpret(bytecode.into(), env, runtime_env)?;
+
+    Ok((stringify(value), secret))
+}
+
+#[cfg(test)]
+mod headers_tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    use crate::errors::{CompileError, ExprError};
+
+    fn env() -> (CompileTimeEnv, RuntimeEnv) {
+        let env = CompileTimeEnv::new(
+            vec!["id".to_string()],
+            vec![],
+            vec!["token".to_string()],
+            vec![],
+        );
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["123".to_string()],
+            secrets: vec!["abc".to_string()],
+            ..Default::default()
+        };
+
+        (env, runtime_env)
+    }
+
+    #[test]
+    fn evaluates_headers_in_order() {
+        let (env, runtime_env) = env();
+
+        let mut headers = BTreeMap::new();
+        headers.insert("X-Id", ":id");
+        headers.insert("Authorization", "!token");
+        headers.insert("X-Flag", "(not false)");
+
+        assert_eq!(
+            Ok(vec![
+                HeaderValue {
+                    name: "Authorization".to_string(),
+                    value: "abc".to_string(),
+                    secret: true,
+                },
+                HeaderValue {
+                    name: "X-Flag".to_string(),
+                    value: "true".to_string(),
+                    secret: false,
+                },
+                HeaderValue {
+                    name: "X-Id".to_string(),
+                    value: "123".to_string(),
+                    secret: false,
+                },
+            ]),
+            eval_headers(&headers, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn returns_errors_for_every_failed_header() {
+        let (env, runtime_env) = env();
+
+        assert_eq!(
+            Err(vec![
+                HeaderError {
+                    name: "A".to_string(),
+                    errs: vec![(
+                        ExprError::CompileError(CompileError::Undefined(":a".to_string())),
+                        0..2
+                    )],
+                },
+                HeaderError {
+                    name: "C".to_string(),
+                    errs: vec![(
+                        ExprError::CompileError(CompileError::Undefined("!c".to_string())),
+                        0..2
+                    )],
+                },
+            ]),
+            eval_headers([("A", ":a"), ("B", ":id"), ("C", "!c")], &env, &runtime_env)
+        );
+    }
+}
@@ -0,0 +1,475 @@
+//! Inspection of serialized bytecode behind `expr inspect`, enabled by the
+//! `cli` feature
+//!
+//! Every part of a [binary](crate::binary) bytecode file is listed next to
+//! the bytes it was read from. Lookups of vars, prompts, secrets, and client
+//! context show their names if the file includes
+//! [name tables](crate::binary::NameTables), otherwise only their index.
+
+use std::{fmt::Display, ops::Range};
+
+use serde_json::{Value as Json, json};
+
+use crate::{
+    binary::{self, Layout, NameTables},
+    compiler::{CompileTimeEnv, ExprByteCode, lookup, opcode},
+    errors::BytecodeError,
+};
+
+/// Bytes shown per line of a hexdump
+const BYTES_PER_LINE: usize = 8;
+
+/// Serialized bytecode and where each part of it was read from
+#[derive(Debug)]
+pub struct Inspection {
+    bytes: Vec<u8>,
+    bytecode: ExprByteCode,
+    names: Option<NameTables>,
+    layout: Layout,
+    ops: Vec<InspectedOp>,
+}
+
+/// A disassembled op and where it was read from
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectedOp {
+    /// Byte range of the op in the serialized bytecode
+    pub range: Range<usize>,
+    /// Index of the op in the code section
+    pub index: usize,
+    pub text: String,
+}
+
+/// Read serialized bytecode for inspection
+pub fn inspect(bytes: &[u8]) -> Result<Inspection, BytecodeError> {
+    let (bytecode, names, layout) = binary::read_with_names(bytes)?;
+
+    let env = names.as_ref().map(NameTables::env).unwrap_or_default();
+    let codes = bytecode.codes();
+
+    let mut ops = vec![];
+    let mut index = 0;
+
+    while index < codes.len() {
+        let (size, text) = disassemble_op(&bytecode, &env, index);
+        let size = size.min(codes.len() - index);
+        let start = layout.code.start + index;
+
+        ops.push(InspectedOp {
+            range: start..start + size,
+            index,
+            text,
+        });
+
+        index += size;
+    }
+
+    Ok(Inspection {
+        bytes: bytes.to_vec(),
+        bytecode,
+        names,
+        layout,
+        ops,
+    })
+}
+
+impl Inspection {
+    pub fn bytecode(&self) -> &ExprByteCode {
+        &self.bytecode
+    }
+
+    /// Names serialized with the bytecode
+    pub fn names(&self) -> Option<&NameTables> {
+        self.names.as_ref()
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn ops(&self) -> &[InspectedOp] {
+        &self.ops
+    }
+
+    pub fn to_json(&self) -> Json {
+        let range = |range: &Range<usize>| json!({ "start": range.start, "end": range.end });
+        let hex = |range: &Range<usize>| json!(hex(&self.bytes[range.clone()]));
+
+        json!({
+            "magic": String::from_utf8_lossy(&self.bytes[self.layout.magic.clone()]),
+            "version": self.version(),
+            "max_stack_depth": self.bytecode.max_stack_depth(),
+            "size": self.bytes.len(),
+            "constants": self.bytecode.constants().iter().zip(&self.layout.constants).enumerate().map(|(index, (constant, r))| json!({
+                "index": index,
+                "type": constant.get_type().to_string(),
+                "value": constant.to_string(),
+                "range": range(r),
+                "bytes": hex(r),
+            })).collect::<Vec<_>>(),
+            "types": self.bytecode.types().iter().zip(&self.layout.types).enumerate().map(|(index, (ty, r))| json!({
+                "index": index,
+                "type": ty.name(),
+                "range": range(r),
+                "bytes": hex(r),
+            })).collect::<Vec<_>>(),
+            "code": {
+                "range": range(&self.layout.code),
+                "ops": self.ops.iter().map(|op| json!({
+                    "index": op.index,
+                    "op": op.text,
+                    "range": range(&op.range),
+                    "bytes": hex(&op.range),
+                })).collect::<Vec<_>>(),
+            },
+            "names": self.names.as_ref().map(|names| json!({
+                "vars": names.vars,
+                "prompts": names.prompts,
+                "secrets": names.secrets,
+                "client_context": names.client_context,
+            })),
+        })
+    }
+
+    fn version(&self) -> String {
+        self.bytecode
+            .version()
+            .iter()
+            .map(|byte| byte.to_string())
+            .collect()
+    }
+
+    /// Write hexdump lines of a byte range, labelling the first line
+    fn write_lines(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        range: &Range<usize>,
+        label: &str,
+    ) -> std::fmt::Result {
+        let bytes = &self.bytes[range.clone()];
+
+        if bytes.is_empty() {
+            return writeln!(
+                f,
+                "{:08x}  {:width$}  {label}",
+                range.start,
+                "",
+                width = hex_width()
+            );
+        }
+
+        for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+            let offset = range.start + i * BYTES_PER_LINE;
+            let label = if i == 0 { label } else { "" };
+
+            let line = format!(
+                "{offset:08x}  {:width$}  {label}",
+                hex(chunk),
+                width = hex_width()
+            );
+
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Inspection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let layout = &self.layout;
+        let constants = self.bytecode.constants();
+        let types = self.bytecode.types();
+
+        writeln!(f, "HEADER ({} bytes)", self.bytes.len())?;
+        self.write_lines(f, &layout.magic, "MAGIC")?;
+        self.write_lines(f, &layout.version, &format!("VERSION {}", self.version()))?;
+        self.write_lines(
+            f,
+            &layout.max_stack_depth,
+            &format!("MAX STACK DEPTH {}", self.bytecode.max_stack_depth()),
+        )?;
+
+        writeln!(f, "CONSTANTS ({})", constants.len())?;
+        self.write_lines(f, &layout.constants_count, "COUNT")?;
+
+        for (index, (constant, range)) in constants.iter().zip(&layout.constants).enumerate() {
+            self.write_lines(f, range, &format!("{index:>4} == '{constant}'"))?;
+        }
+
+        writeln!(f, "TYPES ({})", types.len())?;
+        self.write_lines(f, &layout.types_count, "COUNT")?;
+
+        for (index, (ty, range)) in types.iter().zip(&layout.types).enumerate() {
+            self.write_lines(f, range, &format!("{index:>4} == '{}'", ty.name()))?;
+        }
+
+        writeln!(f, "CODE ({} bytes)", layout.code.len())?;
+        self.write_lines(f, &layout.code_length, "LENGTH")?;
+
+        for op in &self.ops {
+            self.write_lines(f, &op.range, &format!("{:04} {}", op.index, op.text))?;
+        }
+
+        if let (Some(names), Some(magic)) = (&self.names, &layout.names_magic) {
+            writeln!(f, "NAMES")?;
+            self.write_lines(f, magic, "MAGIC")?;
+
+            let lists = [
+                ("VARS", &names.vars),
+                ("PROMPTS", &names.prompts),
+                ("SECRETS", &names.secrets),
+                ("CLIENT_CTX", &names.client_context),
+            ];
+
+            for ((label, list), range) in lists.iter().zip(&layout.names) {
+                let list: Vec<String> = list.iter().map(|name| format!("'{name}'")).collect();
+
+                self.write_lines(
+                    f,
+                    range,
+                    &format!("{label} ({}) {}", list.len(), list.join(", ")),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn hex_width() -> usize {
+    BYTES_PER_LINE * 3 - 1
+}
+
+fn named(name: Option<&String>) -> Option<String> {
+    name.map(|name| format!(" == '{name}'"))
+}
+
+/// Disassemble the op at `index`, leaving out anything not in `env`
+fn disassemble_op(bytecode: &ExprByteCode, env: &CompileTimeEnv, index: usize) -> (usize, String) {
+    let codes = bytecode.codes();
+    let operand = |i: usize| codes.get(index + i).copied();
+
+    match codes[index] {
+        opcode::TRUE => (1, "TRUE".to_string()),
+        opcode::FALSE => (1, "FALSE".to_string()),
+        opcode::CALL => match operand(1) {
+            Some(arg_count) => (2, format!("{:16} ({arg_count} args)", "CALL")),
+            None => (2, "CALL             <missing operand>".to_string()),
+        },
+        opcode::CALL_SPREAD => match operand(1) {
+            Some(arg_count) => (2, format!("{:16} ({arg_count} args)", "CALL_SPREAD")),
+            None => (2, "CALL_SPREAD      <missing operand>".to_string()),
+        },
+        opcode::LIST => match operand(1) {
+            Some(item_count) => (2, format!("{:16} ({item_count} items)", "LIST")),
+            None => (2, "LIST             <missing operand>".to_string()),
+        },
+        opcode::GET_LOCAL | opcode::SET_LOCAL => {
+            let name = if codes[index] == opcode::GET_LOCAL {
+                "GET_LOCAL"
+            } else {
+                "SET_LOCAL"
+            };
+
+            match operand(1) {
+                Some(slot) => (2, format!("{name:16} {slot:>4}")),
+                None => (2, format!("{name:16} <missing operand>")),
+            }
+        }
+        opcode::JUMP_IF_FALSE | opcode::JUMP | opcode::TRY | opcode::END_TRY => {
+            let name = match codes[index] {
+                opcode::JUMP_IF_FALSE => "JUMP_IF_FALSE",
+                opcode::JUMP => "JUMP",
+                opcode::TRY => "TRY",
+                _ => "END_TRY",
+            };
+
+            match (operand(1), operand(2)) {
+                (Some(high), Some(low)) => {
+                    let offset = u16::from_be_bytes([high, low]) as usize;
+
+                    (
+                        3,
+                        format!("{name:16} {offset:>4} -> {:04}", index + 3 + offset),
+                    )
+                }
+                _ => (3, format!("{name:16} <missing operands>")),
+            }
+        }
+        opcode::CONSTANT => match operand(1) {
+            Some(i) => match bytecode.constants().get(i as usize) {
+                Some(value) => (2, format!("{:16} {i:>4} == '{value}'", "CONSTANT")),
+                None => (2, format!("{:16} {i:>4} <missing constant>", "CONSTANT")),
+            },
+            None => (2, "CONSTANT         <missing operand>".to_string()),
+        },
+        opcode::GET => match (operand(1), operand(2)) {
+            (Some(lookup_type), Some(i)) => {
+                let (name, value) = match lookup_type {
+                    lookup::BUILTIN => (
+                        "BUILTIN",
+                        env.get_builtin(i as usize)
+                            .map(|builtin| format!(" == '{}'", builtin.name)),
+                    ),
+                    lookup::USER_BUILTIN => ("USER_BUILTIN", None),
+                    lookup::VAR => ("VAR", named(env.get_var(i as usize))),
+                    lookup::PROMPT => ("PROMPT", named(env.get_prompt(i as usize))),
+                    lookup::SECRET => ("SECRET", named(env.get_secret(i as usize))),
+                    lookup::CLIENT_CTX => ("CLIENT_CTX", named(env.get_client_context(i as usize))),
+                    lookup::TYPE => (
+                        "TYPE",
+                        bytecode
+                            .types()
+                            .get(i as usize)
+                            .map(|ty| format!(" == '{}'", ty.name())),
+                    ),
+                    _ => return (3, format!("GET <invalid lookup {lookup_type}> {i:>4}")),
+                };
+
+                (
+                    3,
+                    format!("GET {name:12} {i:>4}{}", value.unwrap_or_default()),
+                )
+            }
+            _ => (3, "GET <missing operands>".to_string()),
+        },
+        opcode::CALL_BUILTIN => match (operand(1), operand(2)) {
+            (Some(i), Some(arg_count)) => {
+                let name = env
+                    .get_builtin(i as usize)
+                    .map(|builtin| format!(" == '{}'", builtin.name))
+                    .unwrap_or_default();
+
+                (
+                    3,
+                    format!("{:16} {i:>4}{name} ({arg_count} args)", "CALL_BUILTIN"),
+                )
+            }
+            _ => (3, "CALL_BUILTIN     <missing operands>".to_string()),
+        },
+        op => (1, format!("<unknown op {op}>")),
+    }
+}
+
+#[cfg(test)]
+mod inspect_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::prelude::*;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let ast = parse(source).unwrap();
+
+        compile(&mut (ast, 0..source.len()), &env)
+            .unwrap()
+            .to_bytes()
+    }
+
+    #[test]
+    fn inspect_text() {
+        let inspection = inspect(&bytes("(concat :a `hello world`)")).unwrap();
+
+        let version = get_version_bytes();
+        let version_hex = hex(&version);
+        let version_str: String = version.iter().map(|byte| byte.to_string()).collect();
+
+        assert_eq!(
+            format!(
+                "HEADER (50 bytes)
+00000000  52 51 58 42              MAGIC
+00000004  {version_hex}              VERSION {version_str}
+00000008  03 00 00 00              MAX STACK DEPTH 3
+CONSTANTS (1)
+0000000c  01 00 00 00              COUNT
+00000010  00 0b 00 00 00 68 65 6c     0 == '`hello world`'
+00000018  6c 6f 20 77 6f 72 6c 64
+TYPES (0)
+00000020  00 00 00 00              COUNT
+CODE (10 bytes)
+00000024  0a 00 00 00              LENGTH
+00000028  01 00 07                 0000 GET BUILTIN         7 == 'concat'
+0000002b  01 01 00                 0003 GET VAR             0
+0000002e  02 00                    0006 CONSTANT            0 == '`hello world`'
+00000030  00 02                    0008 CALL             (2 args)
+"
+            ),
+            inspection.to_string()
+        );
+    }
+
+    #[test]
+    fn inspect_names() {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let source = "(concat :a `b`)";
+        let ast = parse(source).unwrap();
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let inspection = inspect(&bytecode.to_bytes_with_names(&NameTables::of(&env))).unwrap();
+
+        assert_eq!("GET VAR             0 == 'a'", inspection.ops()[1].text);
+        assert!(inspection.to_string().ends_with(
+            "NAMES
+00000028  52 51 58 4e              MAGIC
+0000002c  01 00 00 00 01 00 00 00  VARS (1) 'a'
+00000034  61
+00000035  00 00 00 00              PROMPTS (0)
+00000039  00 00 00 00              SECRETS (0)
+0000003d  00 00 00 00              CLIENT_CTX (0)
+"
+        ));
+        assert_eq!(
+            json!({
+                "vars": ["a"],
+                "prompts": [],
+                "secrets": [],
+                "client_context": [],
+            }),
+            inspection.to_json()["names"]
+        );
+    }
+
+    #[test]
+    fn inspect_json() {
+        let inspection = inspect(&bytes("(id Type<String>)")).unwrap();
+        let json = inspection.to_json();
+
+        assert_eq!(json!("RQXB"), json["magic"]);
+        assert_eq!(json!(2), json["max_stack_depth"]);
+        assert_eq!(
+            json!({
+                "index": 0,
+                "type": "String",
+                "range": { "start": 20, "end": 22 },
+                "bytes": "05 01",
+            }),
+            json["types"][0]
+        );
+        assert_eq!(
+            json!({
+                "index": 3,
+                "op": "GET TYPE            0 == 'String'",
+                "range": { "start": 29, "end": 32 },
+                "bytes": "01 06 00",
+            }),
+            json["code"]["ops"][1]
+        );
+    }
+
+    #[test]
+    fn inspect_invalid() {
+        assert_eq!(
+            BytecodeError::InvalidMagic,
+            inspect(b"not bytecode").unwrap_err()
+        );
+    }
+}
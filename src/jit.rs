@@ -0,0 +1,827 @@
+//! Compile bytecode in to native code with cranelift
+//!
+//! Values are Rust types so the generated code doesn't manipulate them
+//! directly. Instead each op is lowered to a call in to a small runtime helper
+//! with its operands baked in as immediates, removing the VM's fetch/decode
+//! loop. Jumps become branches between the blocks of the ops they target.
+//! Bytecode with ops the JIT doesn't support (or hosts cranelift doesn't
+//! support) falls back to the [`Vm`].
+
+use std::{
+    any::Any,
+    panic::{AssertUnwindSafe, catch_unwind, resume_unwind},
+};
+
+use cranelift::{
+    jit::{JITBuilder, JITModule},
+    module::{Linkage, Module, default_libcall_names},
+    prelude::*,
+};
+
+use crate::{
+    compiler::{
+        CompileTimeEnv, ExprByteCode,
+        lookup::{BUILTIN, CLIENT_CTX, PROMPT, SECRET, TYPE, USER_BUILTIN, VAR},
+        opcode,
+    },
+    errors::{ExprErrorS, ExprResult, RuntimeError},
+    types::Type,
+    value::Value,
+    vm::{RuntimeEnv, Vm, VmConfig, invalid_bytecode, is_fatal, validate_runtime_env},
+};
+
+/// Returned from a helper when the generated code should keep executing
+const STATUS_CONTINUE: i8 = 0;
+/// Returned from a helper when the generated code should stop executing
+const STATUS_ABORT: i8 = 1;
+/// Returned from [`jit_jump_if_false`] when the generated code should jump
+const STATUS_JUMP: i8 = 2;
+
+/// Helpers are passed the context, the op's operands, and the op's offset
+type JitHelper = extern "C" fn(*mut JitContext, i64, i64, i64) -> i8;
+type JitFn = extern "C" fn(*mut JitContext) -> i8;
+
+const HELPERS: [(&str, JitHelper); 6] = [
+    ("reqlang_expr_jit_call", jit_call),
+    ("reqlang_expr_jit_call_builtin", jit_call_builtin),
+    ("reqlang_expr_jit_get", jit_get),
+    ("reqlang_expr_jit_constant", jit_constant),
+    ("reqlang_expr_jit_bool", jit_bool),
+    ("reqlang_expr_jit_jump_if_false", jit_jump_if_false),
+];
+
+/// Bytecode compiled with [`jit`]
+pub struct JitExpr {
+    bytecode: ExprByteCode,
+    native: Option<(JITModule, JitFn)>,
+}
+
+impl JitExpr {
+    /// If the bytecode was compiled to native code or falls back to the [`Vm`]
+    pub fn is_native(&self) -> bool {
+        self.native.is_some()
+    }
+
+    /// Evaluate the expression
+    ///
+    /// Results are identical to [`Vm::interpret`] with [`VmConfig::default`]:
+    /// the runtime env is checked with [`validate_runtime_env`] first and
+    /// malformed bytecode is a [`RuntimeError::InvalidBytecode`].
+    pub fn eval(&self, env: &CompileTimeEnv, runtime_env: &RuntimeEnv) -> ExprResult<Value> {
+        let Some((_, func)) = &self.native else {
+            return Vm::new().interpret(self.bytecode.clone().into(), env, runtime_env);
+        };
+
+        validate_runtime_env(env, runtime_env)?;
+
+        let config = VmConfig::default();
+
+        if self.bytecode.constants().len() > config.max_constants {
+            return Err(vec![(
+                RuntimeError::TooManyConstants {
+                    max: config.max_constants,
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        let mut context = JitContext {
+            bytecode: &self.bytecode,
+            env,
+            runtime_env,
+            stack: Vec::with_capacity(config.initial_stack_capacity),
+            max_stack_depth: config.max_stack_depth,
+            errs: vec![],
+            panic: None,
+        };
+
+        func(&mut context);
+
+        if let Some(panic) = context.panic {
+            resume_unwind(panic);
+        }
+
+        if !context.errs.is_empty() {
+            return Err(context.errs);
+        }
+
+        context.pop()
+    }
+}
+
+impl Drop for JitExpr {
+    fn drop(&mut self) {
+        if let Some((module, _)) = self.native.take() {
+            // SAFETY: the only function pointer in to the module is dropped with it
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+impl std::fmt::Debug for JitExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JitExpr")
+            .field("bytecode", &self.bytecode)
+            .field("native", &self.is_native())
+            .finish()
+    }
+}
+
+/// Compile [`ExprByteCode`] to native code
+///
+/// ```
+/// use reqlang_expr::{jit::jit, prelude::*};
+///
+/// let source = "(concat `Hello, ` :name)";
+/// let env = CompileTimeEnv::new(vec!["name".to_string()], vec![], vec![], vec![]);
+///
+/// let ast = parse(source).unwrap();
+/// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+///
+/// let expr = jit(&bytecode);
+///
+/// let runtime_env = RuntimeEnv {
+///     vars: vec!["World".to_string()],
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(
+///     Ok(Value::String("Hello, World".into())),
+///     expr.eval(&env, &runtime_env)
+/// );
+/// ```
+pub fn jit(bytecode: &ExprByteCode) -> JitExpr {
+    JitExpr {
+        bytecode: bytecode.clone(),
+        native: lower(bytecode),
+    }
+}
+
+/// An op with its operands decoded
+///
+/// Jump targets are indexes in to the decoded ops.
+enum JitOp {
+    Call { arg_count: u8 },
+    CallBuiltin { builtin_idx: u8, arg_count: u8 },
+    Get { lookup: u8, idx: u8 },
+    Constant { idx: u8 },
+    Bool(bool),
+    JumpIfFalse { target: usize },
+    Jump { target: usize },
+}
+
+impl JitOp {
+    /// The helper the op calls, `None` if it doesn't call one
+    fn helper_args(&self) -> Option<(usize, i64, i64)> {
+        match self {
+            JitOp::Call { arg_count } => Some((0, *arg_count as i64, 0)),
+            JitOp::CallBuiltin {
+                builtin_idx,
+                arg_count,
+            } => Some((1, *builtin_idx as i64, *arg_count as i64)),
+            JitOp::Get { lookup, idx } => Some((2, *lookup as i64, *idx as i64)),
+            JitOp::Constant { idx } => Some((3, *idx as i64, 0)),
+            JitOp::Bool(value) => Some((4, *value as i64, 0)),
+            JitOp::JumpIfFalse { .. } => Some((5, 0, 0)),
+            JitOp::Jump { .. } => None,
+        }
+    }
+}
+
+/// Decode bytecode in to ops and their offsets, returning `None` if any op
+/// isn't supported
+fn decode(codes: &[u8]) -> Option<(Vec<JitOp>, Vec<usize>)> {
+    let mut ops = vec![];
+    let mut op_ips = vec![];
+    let mut ip = 0;
+
+    while let Some(op_code) = codes.get(ip) {
+        let (op, size) = match *op_code {
+            opcode::CALL => (
+                JitOp::Call {
+                    arg_count: *codes.get(ip + 1)?,
+                },
+                2,
+            ),
+            opcode::CALL_BUILTIN => (
+                JitOp::CallBuiltin {
+                    builtin_idx: *codes.get(ip + 1)?,
+                    arg_count: *codes.get(ip + 2)?,
+                },
+                3,
+            ),
+            opcode::GET => (
+                JitOp::Get {
+                    lookup: *codes.get(ip + 1)?,
+                    idx: *codes.get(ip + 2)?,
+                },
+                3,
+            ),
+            opcode::CONSTANT => (
+                JitOp::Constant {
+                    idx: *codes.get(ip + 1)?,
+                },
+                2,
+            ),
+            opcode::TRUE => (JitOp::Bool(true), 1),
+            opcode::FALSE => (JitOp::Bool(false), 1),
+            opcode::JUMP_IF_FALSE => (
+                JitOp::JumpIfFalse {
+                    target: ip + 3 + opcode::jump_offset(codes.get(ip + 1..)?)?,
+                },
+                3,
+            ),
+            opcode::JUMP => (
+                JitOp::Jump {
+                    target: ip + 3 + opcode::jump_offset(codes.get(ip + 1..)?)?,
+                },
+                3,
+            ),
+            _ => return None,
+        };
+
+        ops.push(op);
+        op_ips.push(ip);
+        ip += size;
+    }
+
+    // Targets are decoded as offsets until the offset of every op is known
+    for op in &mut ops {
+        if let JitOp::JumpIfFalse { target } | JitOp::Jump { target } = op {
+            *target = match op_ips.binary_search(target) {
+                Ok(idx) => idx,
+                Err(idx) if *target == ip => idx,
+                Err(_) => return None,
+            };
+        }
+    }
+
+    Some((ops, op_ips))
+}
+
+fn lower(bytecode: &ExprByteCode) -> Option<(JITModule, JitFn)> {
+    let (ops, op_ips) = decode(bytecode.codes())?;
+
+    let mut flag_builder = settings::builder();
+    flag_builder.set("use_colocated_libcalls", "false").ok()?;
+    flag_builder.set("is_pic", "false").ok()?;
+
+    let isa = cranelift::native::builder()
+        .ok()?
+        .finish(settings::Flags::new(flag_builder))
+        .ok()?;
+
+    let mut jit_builder = JITBuilder::with_isa(isa, default_libcall_names());
+
+    for (name, helper) in HELPERS {
+        jit_builder.symbol(name, helper as *const u8);
+    }
+
+    let mut module = JITModule::new(jit_builder);
+    let pointer_type = module.target_config().pointer_type();
+
+    let mut helper_signature = module.make_signature();
+    helper_signature.params.push(AbiParam::new(pointer_type));
+    helper_signature.params.push(AbiParam::new(types::I64));
+    helper_signature.params.push(AbiParam::new(types::I64));
+    helper_signature.params.push(AbiParam::new(types::I64));
+    helper_signature.returns.push(AbiParam::new(types::I8));
+
+    let mut helper_ids = vec![];
+
+    for (name, _) in HELPERS {
+        helper_ids.push(
+            module
+                .declare_function(name, Linkage::Import, &helper_signature)
+                .ok()?,
+        );
+    }
+
+    let mut ctx = module.make_context();
+    ctx.func.signature.params.push(AbiParam::new(pointer_type));
+    ctx.func.signature.returns.push(AbiParam::new(types::I8));
+
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut builder = FunctionBuilder::new(&mut ctx.func, &mut builder_context);
+
+    let helper_refs: Vec<_> = helper_ids
+        .iter()
+        .map(|id| module.declare_func_in_func(*id, builder.func))
+        .collect();
+
+    let entry_block = builder.create_block();
+    let exit_block = builder.create_block();
+    // One block per op, plus one for the end of the bytecode
+    let op_blocks: Vec<_> = (0..=ops.len()).map(|_| builder.create_block()).collect();
+    builder.append_block_params_for_function_params(entry_block);
+    builder.append_block_param(exit_block, types::I8);
+
+    builder.switch_to_block(entry_block);
+    builder.seal_block(entry_block);
+
+    let context = builder.block_params(entry_block)[0];
+    builder.ins().jump(op_blocks[0], &[]);
+
+    for (op_idx, op) in ops.iter().enumerate() {
+        builder.switch_to_block(op_blocks[op_idx]);
+
+        let next_block = op_blocks[op_idx + 1];
+
+        let Some((helper, a, b)) = op.helper_args() else {
+            if let JitOp::Jump { target } = op {
+                builder.ins().jump(op_blocks[*target], &[]);
+            }
+
+            continue;
+        };
+
+        let a = builder.ins().iconst(types::I64, a);
+        let b = builder.ins().iconst(types::I64, b);
+        let ip = builder.ins().iconst(types::I64, op_ips[op_idx] as i64);
+        let call = builder
+            .ins()
+            .call(helper_refs[helper], &[context, a, b, ip]);
+        let status = builder.inst_results(call)[0];
+
+        if let JitOp::JumpIfFalse { target } = op {
+            let aborted = builder
+                .ins()
+                .icmp_imm(IntCC::Equal, status, STATUS_ABORT as i64);
+            let jump_block = builder.create_block();
+
+            builder
+                .ins()
+                .brif(aborted, exit_block, &[status], jump_block, &[]);
+
+            builder.switch_to_block(jump_block);
+            builder
+                .ins()
+                .brif(status, op_blocks[*target], &[], next_block, &[]);
+        } else {
+            builder
+                .ins()
+                .brif(status, exit_block, &[status], next_block, &[]);
+        }
+    }
+
+    builder.switch_to_block(op_blocks[ops.len()]);
+
+    let status = builder.ins().iconst(types::I8, STATUS_CONTINUE as i64);
+    builder.ins().jump(exit_block, &[status]);
+
+    builder.switch_to_block(exit_block);
+    // Every block's predecessors are known once all the ops are lowered
+    builder.seal_all_blocks();
+
+    let status = builder.block_params(exit_block)[0];
+    builder.ins().return_(&[status]);
+
+    builder.finalize();
+
+    let func_id = module
+        .declare_anonymous_function(&ctx.func.signature)
+        .ok()?;
+    module.define_function(func_id, &mut ctx).ok()?;
+    module.clear_context(&mut ctx);
+    module.finalize_definitions().ok()?;
+
+    let code = module.get_finalized_function(func_id);
+
+    // SAFETY: the function was defined with the `JitFn` signature
+    let func = unsafe { std::mem::transmute::<*const u8, JitFn>(code) };
+
+    Some((module, func))
+}
+
+/// State shared between the generated code and the runtime helpers
+struct JitContext<'a> {
+    bytecode: &'a ExprByteCode,
+    env: &'a CompileTimeEnv,
+    runtime_env: &'a RuntimeEnv,
+    stack: Vec<Value>,
+    max_stack_depth: usize,
+    errs: Vec<ExprErrorS>,
+    /// Panics can't unwind through the generated code so they're stashed
+    /// here and resumed once it returns
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl JitContext<'_> {
+    fn push(&mut self, value: Value) -> ExprResult<()> {
+        if self.stack.len() >= self.max_stack_depth {
+            return Err(vec![(
+                RuntimeError::StackOverflow {
+                    max: self.max_stack_depth,
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> ExprResult<Value> {
+        if let Some(value) = self.stack.pop() {
+            return Ok(value);
+        };
+
+        Err(vec![(RuntimeError::EmptyStack.into(), 0..0)])
+    }
+
+    fn pop_args(&mut self, arg_count: usize) -> ExprResult<Vec<Value>> {
+        let mut args: Vec<Value> = vec![];
+
+        for _ in 0..arg_count {
+            args.push(self.pop()?);
+        }
+
+        args.reverse();
+
+        Ok(args)
+    }
+}
+
+/// Run an op against the context, recording errors the same way the VM does
+fn run_helper(context: *mut JitContext, op: impl FnOnce(&mut JitContext) -> ExprResult<()>) -> i8 {
+    // SAFETY: the generated code only ever passes along the context given to it
+    let context = unsafe { &mut *context };
+
+    match catch_unwind(AssertUnwindSafe(|| op(context))) {
+        Ok(Ok(())) => STATUS_CONTINUE,
+        Ok(Err(errs)) => {
+            let fatal = is_fatal(&errs);
+
+            context.errs.extend(errs);
+
+            if fatal { STATUS_ABORT } else { STATUS_CONTINUE }
+        }
+        Err(panic) => {
+            context.panic = Some(panic);
+
+            STATUS_ABORT
+        }
+    }
+}
+
+extern "C" fn jit_call(context: *mut JitContext, arg_count: i64, _: i64, ip: i64) -> i8 {
+    run_helper(context, |context| {
+        let args = context.pop_args(arg_count as usize)?;
+
+        let value = context.pop()?;
+
+        let result = value
+            .call(args)
+            .map_err(|errs| context.bytecode.span_call_errors(ip as usize, errs));
+
+        context.push(result?)
+    })
+}
+
+extern "C" fn jit_call_builtin(
+    context: *mut JitContext,
+    builtin_idx: i64,
+    arg_count: i64,
+    ip: i64,
+) -> i8 {
+    run_helper(context, |context| {
+        let builtin_idx = builtin_idx as usize;
+
+        let args = context.pop_args(arg_count as usize)?;
+
+        let builtin = context
+            .env
+            .get_builtin(builtin_idx)
+            .ok_or_else(|| invalid_bytecode(format!("undefined builtin {builtin_idx}")))?;
+
+        let result = builtin
+            .check_arity(&args)
+            .and_then(|_| (builtin.func)(args))
+            .map_err(|errs| context.bytecode.span_call_errors(ip as usize, errs));
+
+        context.push(result?)
+    })
+}
+
+extern "C" fn jit_get(context: *mut JitContext, get_lookup: i64, get_idx: i64, _: i64) -> i8 {
+    run_helper(context, |context| {
+        let get_lookup = get_lookup as u8;
+        let get_idx = get_idx as usize;
+
+        let (env, runtime_env) = (context.env, context.runtime_env);
+
+        let value = match get_lookup {
+            BUILTIN => {
+                let value = env
+                    .get_builtin(get_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("undefined builtin {get_idx}")))?;
+                Value::Fn(value.clone().into())
+            }
+            USER_BUILTIN => {
+                let value = env
+                    .get_user_builtin(get_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("undefined user builtin {get_idx}")))?;
+                Value::Fn(value.clone().into())
+            }
+            VAR => {
+                let value = env
+                    .get_var(get_idx)
+                    .and_then(|_| runtime_env.vars.get(get_idx))
+                    .ok_or_else(|| invalid_bytecode(format!("undefined variable {get_idx}")))?;
+                Value::String(value.clone().into())
+            }
+            PROMPT => {
+                let value = env
+                    .get_prompt(get_idx)
+                    .and_then(|_| runtime_env.prompts.get(get_idx))
+                    .ok_or_else(|| invalid_bytecode(format!("undefined prompt {get_idx}")))?;
+                Value::String(value.clone().into())
+            }
+            SECRET => {
+                let value = env
+                    .get_secret(get_idx)
+                    .and_then(|_| runtime_env.secrets.get(get_idx))
+                    .ok_or_else(|| invalid_bytecode(format!("undefined secret {get_idx}")))?;
+                Value::String(value.clone().into())
+            }
+            CLIENT_CTX => env
+                .get_client_context(get_idx)
+                .and_then(|_| runtime_env.client_context.get(get_idx))
+                .ok_or_else(|| invalid_bytecode(format!("undefined client context {get_idx}")))?
+                .clone(),
+            TYPE => {
+                let ty = context
+                    .bytecode
+                    .types()
+                    .get(get_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("undefined type {get_idx}")))?;
+
+                if ty.is_type() {
+                    Value::Type(ty.clone().into())
+                } else {
+                    Value::Type(Type::Type(ty.clone().into()).into())
+                }
+            }
+            _ => {
+                return Err(invalid_bytecode(format!(
+                    "invalid lookup type {get_lookup}"
+                )));
+            }
+        };
+
+        context.push(value)
+    })
+}
+
+extern "C" fn jit_constant(context: *mut JitContext, get_idx: i64, _: i64, _: i64) -> i8 {
+    run_helper(context, |context| {
+        let get_idx = get_idx as usize;
+
+        let value = context
+            .bytecode
+            .constants()
+            .get(get_idx)
+            .ok_or_else(|| invalid_bytecode(format!("undefined constant {get_idx}")))?
+            .clone();
+
+        context.push(value)
+    })
+}
+
+extern "C" fn jit_bool(context: *mut JitContext, value: i64, _: i64, _: i64) -> i8 {
+    run_helper(context, |context| context.push(Value::Bool(value != 0)))
+}
+
+extern "C" fn jit_jump_if_false(context: *mut JitContext, _: i64, _: i64, _: i64) -> i8 {
+    let mut jump = false;
+
+    let status = run_helper(context, |context| {
+        jump = !context.pop()?.get_bool()?;
+
+        Ok(())
+    });
+
+    if status == STATUS_CONTINUE && jump {
+        STATUS_JUMP
+    } else {
+        status
+    }
+}
+
+#[cfg(test)]
+mod jit_tests {
+    use super::*;
+
+    use crate::{
+        builtins::{BuiltinFn, FnNamespace},
+        compiler::{CompileOptions, compile_with_options, get_version_bytes},
+        errors::ExprError,
+        parser::parse,
+    };
+
+    fn env() -> (CompileTimeEnv, RuntimeEnv) {
+        let env = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec!["d".to_string()],
+        );
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["a_value".to_string()],
+            prompts: vec!["b_value".to_string()],
+            secrets: vec!["c_value".to_string()],
+            client_context: vec![Value::Bool(true)],
+        };
+
+        (env, runtime_env)
+    }
+
+    #[test]
+    fn matches_vm() {
+        let (env, runtime_env) = env();
+
+        let sources = [
+            "true",
+            "false",
+            "`foo`",
+            ":a",
+            "?b",
+            "!c",
+            "@d",
+            "noop",
+            "String",
+            "(noop)",
+            "(id :a)",
+            "(concat :a ?b !c)",
+            "(cond (eq :a `a_value`) `yes` `no`)",
+            "(eq (type :a) String)",
+            "(and (not false) (contains `a` :a))",
+            "(uppercase (trim ` foo `))",
+            "(cond (not true) (assert false) `no`)",
+            "(or (eq :a `b`) (id false))",
+            "(and true (id :a))",
+            "(id (assert false))",
+            "(assert_eq :a `b`)",
+        ];
+
+        for fuse_calls in [false, true] {
+            let options = CompileOptions {
+                fuse_calls,
+                ..Default::default()
+            };
+
+            for source in sources {
+                let ast = parse(source).unwrap();
+                let bytecode =
+                    compile_with_options(&mut (ast, 0..source.len()), &env, &options).unwrap();
+
+                let expected = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+
+                let expr = jit(&bytecode);
+
+                assert!(expr.is_native(), "{source}");
+                assert_eq!(expected, expr.eval(&env, &runtime_env), "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn matches_vm_on_empty_stack() {
+        let mut codes = get_version_bytes().to_vec();
+
+        codes.push(opcode::GET);
+        codes.push(BUILTIN);
+        codes.push(0);
+
+        codes.push(opcode::CALL);
+        codes.push(1);
+
+        let bytecode = ExprByteCode::new(codes, vec![], vec![]);
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env),
+            jit(&bytecode).eval(&env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn matches_vm_on_stack_overflow() {
+        let mut codes = get_version_bytes().to_vec();
+
+        codes.extend(std::iter::repeat_n(
+            opcode::TRUE,
+            VmConfig::default().max_stack_depth + 1,
+        ));
+
+        let bytecode = ExprByteCode::new(codes, vec![], vec![]);
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        let expected = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+
+        assert!(expected.is_err());
+        assert_eq!(expected, jit(&bytecode).eval(&env, &runtime_env));
+    }
+
+    #[test]
+    fn falls_back_to_vm_for_unsupported_ops() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(99);
+
+        let bytecode = ExprByteCode::new(codes, vec![], vec![]);
+
+        assert!(!jit(&bytecode).is_native());
+    }
+
+    #[test]
+    fn matches_vm_on_invalid_bytecode() {
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        for codes in [
+            &[opcode::GET, VAR, 99][..],
+            &[opcode::GET, BUILTIN, 255],
+            &[opcode::GET, 99, 0],
+            &[opcode::CONSTANT, 0],
+            &[opcode::CALL_BUILTIN, 255, 0],
+            &[opcode::GET, VAR, 99, opcode::TRUE],
+        ] {
+            let bytecode = ExprByteCode::new(
+                get_version_bytes().iter().chain(codes).copied().collect(),
+                vec![],
+                vec![],
+            );
+
+            let expected = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+            let expr = jit(&bytecode);
+
+            assert!(expr.is_native(), "{codes:?}");
+            assert!(
+                matches!(
+                    expected.as_ref().map_err(Vec::as_slice),
+                    Err([(
+                        ExprError::RuntimeError(RuntimeError::InvalidBytecode { .. }),
+                        _
+                    )])
+                ),
+                "{codes:?}"
+            );
+            assert_eq!(expected, expr.eval(&env, &runtime_env), "{codes:?}");
+        }
+    }
+
+    #[test]
+    fn matches_vm_on_runtime_env_mismatch() {
+        let (env, _) = env();
+
+        let source = ":a";
+        let ast = parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let runtime_env = RuntimeEnv::default();
+
+        let expected = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+
+        assert!(expected.is_err());
+        assert_eq!(expected, jit(&bytecode).eval(&env, &runtime_env));
+    }
+
+    #[test]
+    fn matches_vm_on_wrong_number_of_builtin_args() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.extend([opcode::CALL_BUILTIN, 0, 0]);
+
+        let bytecode = ExprByteCode::new(codes, vec![], vec![]);
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        let expected = Vm::new().interpret(bytecode.clone().into(), &env, &runtime_env);
+
+        assert!(expected.is_err());
+        assert_eq!(expected, jit(&bytecode).eval(&env, &runtime_env));
+    }
+
+    #[test]
+    #[should_panic(expected = "user builtin panicked")]
+    fn resumes_panics() {
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(BuiltinFn {
+            name: "boom",
+            func: |_| panic!("user builtin panicked"),
+            namespace: FnNamespace::User,
+            ..BuiltinFn::NOOP
+        });
+
+        let source = "(boom)";
+        let ast = parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let _ = jit(&bytecode).eval(&env, &RuntimeEnv::default());
+    }
+}
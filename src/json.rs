@@ -0,0 +1,284 @@
+//! JSON builtins enabled by the `json` feature
+
+use serde_json::Value as Json;
+
+use crate::{
+    builtins::{BuiltinCategory, BuiltinFn, BuiltinGroup, FnArg, FnNamespace},
+    errors::{ExprErrorS, ExprResult, RuntimeError},
+    types::Type,
+    value::Value,
+};
+
+/// Builtins registered as [`crate::builtins::BuiltinGroup::Json`] when the
+/// `json` feature is enabled
+pub const JSON_BUILTINS: [BuiltinFn<'static>; 2] = [SAME_JSON, CTX_JSON];
+
+/// Returns [`Value::Bool`] if two JSON [`Value::String`] are structurally
+/// equal
+///
+/// Object key order and whitespace are ignored. Numbers are compared by
+/// value so `1` and `1.0` are equal. Array order is significant.
+///
+/// `` (same_json `{"a": 1, "b": [true]}` `{"b":[true],"a":1}`) ``
+pub const SAME_JSON: BuiltinFn<'static> = BuiltinFn {
+    name: "same_json",
+    args: &[
+        FnArg {
+            name: "a",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+        FnArg {
+            name: "b",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+    ],
+    return_type: Type::Bool,
+    func: same_json,
+    namespace: FnNamespace::Builtin,
+    pure: true,
+    category: BuiltinCategory::Json,
+    capability: Some(BuiltinGroup::Json),
+};
+
+fn same_json(args: Vec<Value>) -> ExprResult<Value> {
+    let a = parse_json(
+        "a",
+        args.first()
+            .expect("should have first expression passed")
+            .get_string()?,
+    )?;
+    let b = parse_json(
+        "b",
+        args.get(1)
+            .expect("should have second expression passed")
+            .get_string()?,
+    )?;
+
+    Ok(Value::Bool(json_eq(&a, &b)))
+}
+
+/// Returns the value at a `.` separated path in a JSON [`Value::String`]
+///
+/// Clients can put a whole structured value (e.g. an HTTP response) in one
+/// client context slot and query it from expressions. Path segments are
+/// object keys or array indexes and an empty path is the whole document.
+/// Strings, numbers, and bools are returned as [`Value::String`],
+/// [`Value::Number`], and [`Value::Bool`]. Objects, arrays, and `null` are
+/// returned as JSON strings so they can be queried again.
+///
+/// `` (ctx_json @response `headers.location`) ``
+///
+/// `` (ctx_json @response `items.0.id`) ``
+pub const CTX_JSON: BuiltinFn<'static> = BuiltinFn {
+    name: "ctx_json",
+    args: &[
+        FnArg {
+            name: "value",
+            ty: Type::Value,
+            variadic: false,
+            optional: false,
+        },
+        FnArg {
+            name: "path",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+    ],
+    return_type: Type::Value,
+    func: ctx_json,
+    namespace: FnNamespace::Builtin,
+    pure: true,
+    category: BuiltinCategory::Json,
+    capability: Some(BuiltinGroup::Json),
+};
+
+fn ctx_json(args: Vec<Value>) -> ExprResult<Value> {
+    let json = parse_json(
+        "value",
+        args.first()
+            .expect("should have first expression passed")
+            .get_string()?,
+    )?;
+    let path = args
+        .get(1)
+        .expect("should have second expression passed")
+        .get_string()?;
+
+    let mut current = &json;
+
+    for segment in path.split('.').filter(|segment| !segment.is_empty()) {
+        let next = match current {
+            Json::Object(object) => object.get(segment),
+            Json::Array(array) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| array.get(index)),
+            _ => None,
+        };
+
+        current = next.ok_or_else(|| {
+            vec![(
+                RuntimeError::InvalidArgument {
+                    name: "path".to_string(),
+                    message: format!("no value at {path}"),
+                }
+                .into(),
+                0..0,
+            )]
+        })?;
+    }
+
+    Ok(match current {
+        Json::String(string) => Value::String(string.clone().into()),
+        Json::Number(number) => Value::Number(number.as_f64().unwrap_or(f64::NAN)),
+        Json::Bool(bool) => Value::Bool(*bool),
+        json => Value::String(json.to_string().into()),
+    })
+}
+
+/// Structural equality between JSON values
+fn json_eq(a: &Json, b: &Json) -> bool {
+    match (a, b) {
+        (Json::Number(a), Json::Number(b)) => a.as_f64() == b.as_f64(),
+        (Json::Array(a), Json::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_eq(a, b))
+        }
+        (Json::Object(a), Json::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| json_eq(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn parse_json(name: &str, json: &str) -> Result<Json, Vec<ExprErrorS>> {
+    serde_json::from_str(json).map_err(|err| {
+        vec![(
+            RuntimeError::InvalidArgument {
+                name: name.to_string(),
+                message: format!("invalid JSON: {err}"),
+            }
+            .into(),
+            0..0,
+        )]
+    })
+}
+
+#[cfg(test)]
+mod json_tests {
+    use super::*;
+
+    fn same(a: &str, b: &str) -> ExprResult<Value> {
+        same_json(vec![Value::String(a.into()), Value::String(b.into())])
+    }
+
+    #[test]
+    fn same_json_ignores_key_order_and_whitespace() {
+        assert_eq!(
+            Ok(Value::Bool(true)),
+            same(
+                r#"{"a": 1, "b": {"c": [true, null], "d": "e"}}"#,
+                r#"{"b":{"d":"e","c":[true,null]},"a":1}"#
+            )
+        );
+    }
+
+    #[test]
+    fn same_json_compares_numbers_by_value() {
+        assert_eq!(Ok(Value::Bool(true)), same("[1, 2.50]", "[1.0, 2.5]"));
+    }
+
+    #[test]
+    fn same_json_array_order_is_significant() {
+        assert_eq!(Ok(Value::Bool(false)), same("[1, 2]", "[2, 1]"));
+    }
+
+    #[test]
+    fn same_json_different_keys() {
+        assert_eq!(Ok(Value::Bool(false)), same(r#"{"a": 1}"#, r#"{"b": 1}"#));
+        assert_eq!(
+            Ok(Value::Bool(false)),
+            same(r#"{"a": 1}"#, r#"{"a": 1, "b": 2}"#)
+        );
+    }
+
+    fn query(json: &str, path: &str) -> ExprResult<Value> {
+        ctx_json(vec![Value::String(json.into()), Value::String(path.into())])
+    }
+
+    const RESPONSE: &str = r#"{
+        "status": 302,
+        "ok": false,
+        "headers": { "location": "/login" },
+        "items": [{ "id": "a" }, { "id": "b" }],
+        "body": null
+    }"#;
+
+    #[test]
+    fn ctx_json_scalars() {
+        assert_eq!(
+            Ok(Value::String("/login".into())),
+            query(RESPONSE, "headers.location")
+        );
+        assert_eq!(Ok(Value::Number(302.0)), query(RESPONSE, "status"));
+        assert_eq!(Ok(Value::Bool(false)), query(RESPONSE, "ok"));
+        assert_eq!(Ok(Value::String("b".into())), query(RESPONSE, "items.1.id"));
+    }
+
+    #[test]
+    fn ctx_json_structured_values_are_json() {
+        assert_eq!(
+            Ok(Value::String(r#"{"id":"a"}"#.into())),
+            query(RESPONSE, "items.0")
+        );
+        assert_eq!(Ok(Value::String("null".into())), query(RESPONSE, "body"));
+        assert_eq!(Ok(Value::Number(1.0)), query("1", ""));
+    }
+
+    #[test]
+    fn ctx_json_from_client_context() {
+        use crate::prelude::*;
+
+        let source = "(ctx_json @response `headers.location`)";
+        let env = CompileTimeEnv::new(vec![], vec![], vec![], vec!["response".to_string()]);
+
+        let ast = parse(source).unwrap();
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let runtime_env = RuntimeEnv {
+            client_context: vec![Value::String(RESPONSE.to_string().into())],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Ok(Value::String("/login".into())),
+            Vm::new().interpret(bytecode.into(), &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn ctx_json_missing_path() {
+        for path in ["headers.missing", "items.2", "items.x", "status.code"] {
+            assert!(matches!(
+                &query(RESPONSE, path).unwrap_err()[0].0,
+                crate::errors::ExprError::RuntimeError(RuntimeError::InvalidArgument { name, message })
+                    if name == "path" && message == &format!("no value at {path}")
+            ));
+        }
+    }
+
+    #[test]
+    fn same_json_invalid() {
+        assert!(matches!(
+            &same("{}", "{").unwrap_err()[0].0,
+            crate::errors::ExprError::RuntimeError(RuntimeError::InvalidArgument { name, message })
+                if name == "b" && message.starts_with("invalid JSON")
+        ));
+    }
+}
@@ -1,18 +1,28 @@
 //! The lexer and associated types
 
+use line_col::LineColLookup;
 use logos::Logos;
-use std::ops::Range;
+use std::{collections::VecDeque, io::Read, ops::Range, time::Instant};
 
 use crate::{
-    errors::{ExprErrorS, LexicalError},
-    span::Spanned,
+    errors::{ExprErrorS, LexicalError, diagnostics::lookup_position},
+    metrics::metrics,
+    span::{Span, Spanned},
 };
 
 /// Parse source code in to a list of [`Token`].
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(source_hash = crate::cache::source_hash(source)))
+)]
 pub fn lex(source: &str) -> Vec<Result<(usize, Token, usize), ExprErrorS>> {
+    let start = Instant::now();
+
     let lexer: Lexer<'_> = Lexer::new(source);
     let tokens: Vec<Result<(usize, Token, usize), ExprErrorS>> = lexer.collect::<Vec<_>>();
 
+    metrics().lexed(start.elapsed());
+
     tokens
 }
 
@@ -20,14 +30,15 @@ pub fn lex(source: &str) -> Vec<Result<(usize, Token, usize), ExprErrorS>> {
 #[derive(Debug)]
 pub struct Lexer<'a> {
     inner: logos::Lexer<'a, Token>,
-    pending: Option<(usize, Token, usize)>,
+    /// Tokens from an interpolated string not returned yet
+    pending: VecDeque<Result<(usize, Token, usize), ExprErrorS>>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(source: &'a str) -> Self {
         Self {
             inner: Token::lexer(source),
-            pending: None,
+            pending: VecDeque::new(),
         }
     }
 }
@@ -36,20 +47,409 @@ impl Iterator for Lexer<'_> {
     type Item = Result<(usize, Token, usize), ExprErrorS>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(token) = self.pending.take() {
-            return Some(Ok(token));
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
         }
 
         let token = self.inner.next()?;
 
-        {
-            let Range { start, end } = self.inner.span();
+        let Range { start, end } = self.inner.span();
 
-            Some(
+        match token {
+            Ok(Token::String(string)) => {
+                self.pending.extend(interpolate(start, string, end));
+                self.pending.pop_front()
+            }
+            token => Some(
                 token
                     .map(|token| (start, token, end))
                     .map_err(|(err, err_span)| (err.into(), err_span)),
-            )
+            ),
+        }
+    }
+}
+
+/// Split a string with `{...}` interpolations in to tokens
+///
+/// `` `Hello {:name}!` `` becomes [`Token::TemplateStart`], `Hello ` as a
+/// [`Token::String`], [`Token::LBrace`], the tokens of `:name`,
+/// [`Token::RBrace`], `!` as a [`Token::String`], then
+/// [`Token::TemplateEnd`]. Spans are offsets in to the whole source so
+/// diagnostics point inside the string. Strings without interpolations are a
+/// single [`Token::String`].
+///
+/// A `{` only starts an interpolation if it's followed by `(`, `[`, or a
+/// sigil (`:`, `?`, `!`, `@`), so strings like JSON are left as is.
+/// Interpolations end at the first `}` and can't contain strings since a
+/// backtick ends the string. A backslash before the `{` escapes it, so
+/// `` `\{:name}` `` is the text `{:name}`.
+fn interpolate(
+    start: usize,
+    string: String,
+    end: usize,
+) -> Vec<Result<(usize, Token, usize), ExprErrorS>> {
+    if find_interpolation(&string).is_none() {
+        return vec![Ok((
+            start,
+            Token::String(unescape_interpolations(&string)),
+            end,
+        ))];
+    }
+
+    // Offset of the string's contents, after the opening backtick
+    let offset = start + 1;
+
+    let mut tokens = vec![Ok((start, Token::TemplateStart, offset))];
+    let mut text_start = 0;
+
+    while let Some(open) = find_interpolation(&string[text_start..]).map(|i| i + text_start) {
+        if open > text_start {
+            tokens.push(Ok((
+                offset + text_start,
+                Token::String(unescape_interpolations(&string[text_start..open])),
+                offset + open,
+            )));
+        }
+
+        let Some(close) = string[open..].find('}').map(|i| i + open) else {
+            tokens.push(Err((
+                LexicalError::UnterminatedInterpolation.into(),
+                offset + open..end - 1,
+            )));
+
+            return tokens;
+        };
+
+        let inner_offset = offset + open + 1;
+
+        tokens.push(Ok((offset + open, Token::LBrace, inner_offset)));
+        tokens.extend(Lexer::new(&string[open + 1..close]).map(|token| {
+            token
+                .map(|(start, token, end)| (inner_offset + start, token, inner_offset + end))
+                .map_err(|(err, span)| (err, inner_offset + span.start..inner_offset + span.end))
+        }));
+        tokens.push(Ok((offset + close, Token::RBrace, offset + close + 1)));
+
+        text_start = close + 1;
+    }
+
+    if text_start < string.len() {
+        tokens.push(Ok((
+            offset + text_start,
+            Token::String(unescape_interpolations(&string[text_start..])),
+            end - 1,
+        )));
+    }
+
+    tokens.push(Ok((end - 1, Token::TemplateEnd, end)));
+
+    tokens
+}
+
+/// Index of the first `{` starting an interpolation
+fn find_interpolation(text: &str) -> Option<usize> {
+    interpolation_braces(text).find(|&i| !text[..i].ends_with('\\'))
+}
+
+/// Indexes of every `{` that would start an interpolation if not escaped
+fn interpolation_braces(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.match_indices('{').map(|(i, _)| i).filter(|&i| {
+        matches!(
+            text[i + 1..].chars().next(),
+            Some('(' | '[' | ':' | '?' | '!' | '@')
+        )
+    })
+}
+
+/// Drop the backslash escaping a `{` that would start an interpolation
+fn unescape_interpolations(text: &str) -> String {
+    let mut unescaped = text.to_string();
+
+    for i in interpolation_braces(text)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        if text[..i].ends_with('\\') {
+            unescaped.remove(i - 1);
+        }
+    }
+
+    unescaped
+}
+
+/// Escape every `{` in text that would start an interpolation
+///
+/// The inverse of how the lexer reads string literals, used to print strings
+/// back as source.
+pub(crate) fn escape_interpolations(text: &str) -> String {
+    let mut escaped = text.to_string();
+
+    for i in interpolation_braces(text)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        escaped.insert(i, '\\');
+    }
+
+    escaped
+}
+
+/// A [`Token`] with its zero based (line, column) positions
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionedToken {
+    pub token: Token,
+    /// Byte offsets of the token
+    pub span: Span,
+    /// Position of the first character
+    pub start: (usize, usize),
+    /// Position after the last character
+    pub end: (usize, usize),
+}
+
+/// Converts source in to [`PositionedToken`] for editor integrations
+///
+/// Positions match
+/// [`index_to_position`](crate::errors::diagnostics::index_to_position)
+/// but the source is only scanned for lines once. Errors are the same as
+/// [`Lexer`].
+///
+/// ```
+/// use reqlang_expr::lexer::{PositionedLexer, Token};
+///
+/// let tokens: Vec<_> = PositionedLexer::new("(noop\n  )").collect();
+///
+/// let rparan = tokens[2].as_ref().unwrap();
+///
+/// assert_eq!(Token::RParan, rparan.token);
+/// assert_eq!(8..9, rparan.span);
+/// assert_eq!((1, 2), rparan.start);
+/// assert_eq!((1, 3), rparan.end);
+/// ```
+pub struct PositionedLexer<'a> {
+    lexer: Lexer<'a>,
+    lookup: LineColLookup<'a>,
+}
+
+impl<'a> PositionedLexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            lexer: Lexer::new(source),
+            lookup: LineColLookup::new(source),
+        }
+    }
+}
+
+impl Iterator for PositionedLexer<'_> {
+    type Item = Result<PositionedToken, ExprErrorS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, token, end) = match self.lexer.next()? {
+            Ok(token) => token,
+            Err(err) => return Some(Err(err)),
+        };
+
+        Some(Ok(PositionedToken {
+            token,
+            span: start..end,
+            start: lookup_position(&self.lookup, start),
+            end: lookup_position(&self.lookup, end),
+        }))
+    }
+}
+
+/// Bytes read from the reader at a time by [`ReaderLexer`]
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Default maximum bytes [`ReaderLexer`] buffers while looking for the end of
+/// a token
+pub const DEFAULT_MAX_LOOKAHEAD: usize = 64 * 1024;
+
+/// Lexes source read incrementally from a [`Read`] in to [`Token`]
+///
+/// Only the text that hasn't been lexed yet is buffered so large documents
+/// piped through stdin don't have to be read in to memory first. Offsets are
+/// relative to the start of the whole input, matching [`lex`].
+///
+/// ```
+/// use reqlang_expr::lexer::{ReaderLexer, Token, lex};
+///
+/// let source = "(concat `a` :b)";
+///
+/// let tokens: Vec<_> = ReaderLexer::new(source.as_bytes()).collect();
+///
+/// assert_eq!(lex(source), tokens);
+/// ```
+#[derive(Debug)]
+pub struct ReaderLexer<R> {
+    reader: R,
+    /// Text read but not lexed yet, always starting at a token boundary
+    buffer: String,
+    /// Bytes read that don't form a complete UTF-8 character yet
+    partial: Vec<u8>,
+    /// Offset of the start of the buffer in the whole input
+    offset: usize,
+    max_lookahead: usize,
+    tokens: VecDeque<Result<(usize, Token, usize), ExprErrorS>>,
+    eof: bool,
+}
+
+impl<R: Read> ReaderLexer<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_max_lookahead(reader, DEFAULT_MAX_LOOKAHEAD)
+    }
+
+    /// Lex from a reader, buffering at most `max_lookahead` bytes while
+    /// looking for the end of a token
+    ///
+    /// Tokens that don't end within the lookahead are a
+    /// [`LexicalError::TokenTooLong`] and end lexing.
+    pub fn with_max_lookahead(reader: R, max_lookahead: usize) -> Self {
+        Self {
+            reader,
+            buffer: String::new(),
+            partial: vec![],
+            offset: 0,
+            max_lookahead,
+            tokens: VecDeque::new(),
+            eof: false,
+        }
+    }
+
+    /// Read the next chunk in to the buffer
+    fn fill(&mut self) {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+
+        let read = loop {
+            match self.reader.read(&mut chunk) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return self.fail(LexicalError::Io(err.to_string())),
+            }
+        };
+
+        if read == 0 {
+            self.eof = true;
+
+            if !self.partial.is_empty() {
+                self.fail(LexicalError::InvalidUtf8);
+            }
+
+            return;
+        }
+
+        self.partial.extend_from_slice(&chunk[..read]);
+
+        let (valid_len, invalid) = match std::str::from_utf8(&self.partial) {
+            Ok(valid) => (valid.len(), false),
+            Err(err) => (err.valid_up_to(), err.error_len().is_some()),
+        };
+
+        let valid = std::str::from_utf8(&self.partial[..valid_len])
+            .expect("should be valid UTF-8 up to the error");
+
+        self.buffer.push_str(valid);
+        self.partial.drain(..valid_len);
+
+        if invalid {
+            self.fail(LexicalError::InvalidUtf8);
+        }
+    }
+
+    /// Lex the buffer, queueing tokens that more input can't change
+    ///
+    /// A token ending at the end of the buffer could continue in to the next
+    /// chunk so it's left in the buffer until the end of input. So is an error
+    /// that the next chunk could turn in to a token, like a string missing its
+    /// closing backtick, along with a token right before it (`1.` could become
+    /// `1.5`).
+    fn lex_buffer(&mut self) {
+        let mut lexed = vec![];
+        let mut consumed = self.buffer.len();
+        let mut lexer = Token::lexer(&self.buffer);
+
+        while let Some(token) = lexer.next() {
+            let span = lexer.span();
+
+            if !self.eof && token.is_err() && is_incomplete(&self.buffer[span.start..]) {
+                consumed = span.start;
+                break;
+            }
+
+            lexed.push((span, token));
+        }
+
+        if !self.eof
+            && let Some((span, _)) = lexed.last()
+            && span.end == consumed
+        {
+            consumed = span.start;
+            lexed.pop();
+        }
+
+        let offset = self.offset;
+
+        self.tokens
+            .extend(lexed.into_iter().flat_map(|(span, token)| match token {
+                Ok(Token::String(string)) => {
+                    interpolate(offset + span.start, string, offset + span.end)
+                }
+                token => vec![
+                    token
+                        .map(|token| (offset + span.start, token, offset + span.end))
+                        .map_err(|(err, err_span)| (err.into(), err_span)),
+                ],
+            }));
+
+        self.buffer.drain(..consumed);
+        self.offset += consumed;
+    }
+
+    /// Lex what's been read then end with an error where reading stopped
+    fn fail(&mut self, err: LexicalError) {
+        self.eof = true;
+        self.lex_buffer();
+
+        let at = self.offset;
+
+        self.tokens.push_back(Err((err.into(), at..at)));
+    }
+}
+
+/// If more input could turn the start of an error in to a token
+fn is_incomplete(rest: &str) -> bool {
+    match rest.strip_prefix('`') {
+        Some(string) => !string.contains('`'),
+        None => "->".starts_with(rest) || "...".starts_with(rest),
+    }
+}
+
+impl<R: Read> Iterator for ReaderLexer<R> {
+    type Item = Result<(usize, Token, usize), ExprErrorS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.tokens.pop_front() {
+                return Some(token);
+            }
+
+            if self.eof {
+                return None;
+            }
+
+            if self.buffer.len() > self.max_lookahead {
+                let at = self.offset;
+                let max = self.max_lookahead;
+
+                self.eof = true;
+                self.buffer.clear();
+
+                return Some(Err((LexicalError::TokenTooLong { max }.into(), at..at)));
+            }
+
+            self.fill();
+            self.lex_buffer();
         }
     }
 }
@@ -64,6 +464,24 @@ pub enum Token {
     #[token(")")]
     RParan,
 
+    #[token("[")]
+    LBracket,
+
+    #[token("]")]
+    RBracket,
+
+    /// Opening backtick of a string with interpolations
+    TemplateStart,
+
+    /// Closing backtick of a string with interpolations
+    TemplateEnd,
+
+    /// Start of an interpolation in a string
+    LBrace,
+
+    /// End of an interpolation in a string
+    RBrace,
+
     #[token(",")]
     Comma,
 
@@ -122,3 +540,186 @@ impl Token {
         Token::Identifier(identifier.to_string())
     }
 }
+
+#[cfg(test)]
+mod lexer_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// Returns at most a number of bytes per read
+    struct Chunked<'a>(&'a [u8], usize);
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let read = self.0.take(self.1 as u64).read(buf)?;
+            self.0 = &self.0[read..];
+
+            Ok(read)
+        }
+    }
+
+    /// Returns its data then fails every read after
+    struct Failing<'a>(&'a [u8]);
+
+    impl Read for Failing<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(std::io::Error::other("broken pipe"));
+            }
+
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn positioned_tokens_match_index_to_position() {
+        use crate::errors::diagnostics::index_to_position;
+
+        let source = "(concat\n  `é`\n  :b)";
+
+        let tokens: Vec<_> = PositionedLexer::new(source)
+            .map(|token| token.unwrap())
+            .collect();
+
+        assert_eq!(
+            lex(source)
+                .into_iter()
+                .map(|token| token.unwrap())
+                .map(|(start, token, end)| (token, start..end))
+                .collect::<Vec<_>>(),
+            tokens
+                .iter()
+                .map(|token| (token.token.clone(), token.span.clone()))
+                .collect::<Vec<_>>()
+        );
+
+        for token in tokens {
+            assert_eq!(index_to_position(source, token.span.start), token.start);
+            assert_eq!(index_to_position(source, token.span.end), token.end);
+        }
+    }
+
+    #[test]
+    fn positioned_errors() {
+        let source = "(noop\n  $)";
+
+        let tokens: Vec<_> = PositionedLexer::new(source).collect();
+
+        assert_eq!(
+            lex(source)[2].as_ref().unwrap_err(),
+            tokens[2].as_ref().unwrap_err()
+        );
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        assert_eq!(
+            vec![
+                Ok((0, Token::TemplateStart, 1)),
+                Ok((1, Token::String("a ".to_string()), 3)),
+                Ok((3, Token::LBrace, 4)),
+                Ok((4, Token::identifier(":b"), 6)),
+                Ok((6, Token::RBrace, 7)),
+                Ok((7, Token::TemplateEnd, 8)),
+                Ok((9, Token::String("{\"c\": 1}".to_string()), 19)),
+            ],
+            lex("`a {:b}` `{\"c\": 1}`")
+        );
+
+        assert_eq!(
+            vec![
+                Ok((0, Token::TemplateStart, 1)),
+                Ok((1, Token::LBrace, 2)),
+                Err((LexicalError::InvalidToken.into(), 2..2)),
+                Err((LexicalError::InvalidToken.into(), 2..2)),
+                Ok((4, Token::RBrace, 5)),
+                Ok((5, Token::TemplateEnd, 6)),
+                Ok((7, Token::TemplateStart, 8)),
+                Err((LexicalError::UnterminatedInterpolation.into(), 8..11)),
+            ],
+            lex("`{:$}` `{:a`")
+        );
+    }
+
+    #[test]
+    fn escaped_interpolations() {
+        assert_eq!(
+            vec![
+                Ok((0, Token::String("{:a} \\{(b)}".to_string()), 15)),
+                Ok((16, Token::String("\\{x}".to_string()), 22)),
+                Ok((23, Token::TemplateStart, 24)),
+                Ok((24, Token::String("{[c]} ".to_string()), 31)),
+                Ok((31, Token::LBrace, 32)),
+                Ok((32, Token::identifier(":d"), 34)),
+                Ok((34, Token::RBrace, 35)),
+                Ok((35, Token::TemplateEnd, 36)),
+            ],
+            lex("`\\{:a} \\\\{(b)}` `\\{x}` `\\{[c]} {:d}`")
+        );
+    }
+
+    #[test]
+    fn streamed_interpolated_strings() {
+        let source = "(concat `{:a} {(id :b)}` `c`) ".repeat(1000);
+
+        let tokens: Vec<_> = ReaderLexer::new(Chunked(source.as_bytes(), 7)).collect();
+
+        assert_eq!(lex(&source), tokens);
+    }
+
+    #[test]
+    fn streamed_across_chunks() {
+        let source = "(concat `é` :b) ".repeat(2000);
+
+        let tokens: Vec<_> = ReaderLexer::new(source.as_bytes()).collect();
+
+        assert!(source.len() > READ_CHUNK_SIZE * 2);
+        assert_eq!(lex(&source), tokens);
+    }
+
+    #[test]
+    fn streamed_token_too_long() {
+        let source = format!("(id `{}`)", "a".repeat(32));
+        let reader = Chunked(source.as_bytes(), 4);
+
+        let tokens: Vec<_> = ReaderLexer::with_max_lookahead(reader, 16).collect();
+
+        assert_eq!(
+            vec![
+                Ok((0, Token::LParan, 1)),
+                Ok((1, Token::identifier("id"), 3)),
+                Err((LexicalError::TokenTooLong { max: 16 }.into(), 4..4)),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn streamed_invalid_utf8() {
+        let tokens: Vec<_> = ReaderLexer::new(&b"`a` \xff `b`"[..]).collect();
+
+        assert_eq!(
+            vec![
+                Ok((0, Token::String("a".to_string()), 3)),
+                Err((LexicalError::InvalidUtf8.into(), 4..4)),
+            ],
+            tokens
+        );
+    }
+
+    #[test]
+    fn streamed_io_error() {
+        let tokens: Vec<_> = ReaderLexer::new(Failing(b"(noop)")).collect();
+
+        assert_eq!(
+            vec![
+                Ok((0, Token::LParan, 1)),
+                Ok((1, Token::identifier("noop"), 5)),
+                Ok((5, Token::RParan, 6)),
+                Err((LexicalError::Io("broken pipe".to_string()).into(), 6..6)),
+            ],
+            tokens
+        );
+    }
+}
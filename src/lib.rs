@@ -1,10 +1,15 @@
 pub mod prelude {
     pub use crate::ast::*;
     pub use crate::builtins::*;
+    pub use crate::cache::*;
     pub use crate::compiler::*;
     pub use crate::errors::*;
+    pub use crate::explain::*;
     pub use crate::lexer::*;
+    pub use crate::module::*;
     pub use crate::parser::*;
+    pub use crate::provenance::*;
+    pub use crate::snapshot::*;
     pub use crate::span::*;
     pub use crate::types::*;
     pub use crate::value::*;
@@ -30,6 +35,67 @@ pub mod value;
 
 pub mod builtins;
 
+pub mod catalog;
+
 pub mod types;
 
 pub mod span;
+
+pub mod cache;
+
+pub mod codegen;
+
+#[cfg(feature = "jit")]
+pub mod jit;
+
+pub mod template;
+
+pub mod headers;
+
+pub mod document;
+
+pub mod providers;
+
+pub mod provenance;
+
+pub mod module;
+
+pub mod analysis;
+
+pub mod assembler;
+
+pub mod binary;
+
+pub mod snapshot;
+
+pub mod metrics;
+
+pub mod coverage;
+
+pub mod explain;
+
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+
+pub mod spec;
+
+#[cfg(feature = "time")]
+pub mod time;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
+#[cfg(feature = "python")]
+pub mod python;
+
+#[cfg(feature = "cli")]
+pub mod server;
+
+#[cfg(feature = "cli")]
+pub mod inspect;
+
+#[cfg(feature = "cli")]
+pub mod run;
@@ -0,0 +1,70 @@
+//! Hooks for exporting metrics about compilation and evaluation
+//!
+//! Implement [`Metrics`] and install it once at startup with [`set_metrics`].
+//! [`lex`](crate::lexer::lex), [`parse`](crate::parser::parse),
+//! [`compile`](crate::compiler::compile),
+//! [`CompilationCache`](crate::cache::CompilationCache), and
+//! [`Vm`](crate::vm::Vm) report to it from then on, so hosts can export
+//! counters and histograms (e.g. to Prometheus) without wrapping every call
+//! site.
+
+use std::{sync::OnceLock, time::Duration};
+
+use crate::errors::RuntimeError;
+
+/// Receiver of compilation and evaluation metrics
+///
+/// Every method defaults to doing nothing. Methods are called on the thread
+/// compiling or evaluating so they should be cheap (e.g. incrementing an
+/// atomic counter).
+pub trait Metrics: Send + Sync {
+    /// Source was lexed in to tokens
+    fn lexed(&self, _duration: Duration) {}
+
+    /// Tokens were parsed in to expressions, successfully or not
+    ///
+    /// Lexing the source isn't included in the duration.
+    fn parsed(&self, _success: bool, _duration: Duration) {}
+
+    /// An expression was compiled, successfully or not
+    fn compiled(&self, _success: bool, _duration: Duration) {}
+
+    /// [`CompilationCache`](crate::cache::CompilationCache) had bytecode
+    /// for a source
+    fn cache_hit(&self) {}
+
+    /// [`CompilationCache`](crate::cache::CompilationCache) had to compile
+    /// a source
+    fn cache_miss(&self) {}
+
+    /// The VM returned a runtime error. Use [`RuntimeError::kind`] to label it.
+    fn runtime_error(&self, _error: &RuntimeError) {}
+
+    /// The VM finished evaluating bytecode, successfully or not
+    ///
+    /// Evaluations that suspend aren't reported until they're resumed and
+    /// finish. Only the time spent after resuming is included.
+    fn evaluated(&self, _duration: Duration) {}
+}
+
+/// [`Metrics`] used until [`set_metrics`] is called
+struct NoopMetrics;
+
+impl Metrics for NoopMetrics {}
+
+static METRICS: OnceLock<Box<dyn Metrics>> = OnceLock::new();
+
+/// Install the [`Metrics`] reported to for the rest of the process
+///
+/// Can only be called once. Returns the metrics back if they were already set.
+pub fn set_metrics(metrics: Box<dyn Metrics>) -> Result<(), Box<dyn Metrics>> {
+    METRICS.set(metrics)
+}
+
+/// The installed [`Metrics`], or one that does nothing
+pub fn metrics() -> &'static dyn Metrics {
+    match METRICS.get() {
+        Some(metrics) => metrics.as_ref(),
+        None => &NoopMetrics,
+    }
+}
@@ -0,0 +1,348 @@
+//! Importing definitions from other files with `(use `path`)`
+
+use std::{fs::read_to_string, io, path::PathBuf};
+
+use crate::{
+    ast::{Expr, ExprS},
+    compiler::{CompileTimeEnv, ExprByteCode, compile},
+    errors::{CompileError, ExprErrorS, ExprResult, diagnostics::AsDiagnostic},
+    parser::parse_many,
+};
+
+/// Loads the source of modules imported with `(use `path`)`
+///
+/// A module is a program of `(def name expr)` definitions and `use`s of other
+/// modules. The path is passed as it's written in the `use`.
+pub trait ModuleResolver {
+    fn resolve(&self, path: &str) -> io::Result<String>;
+}
+
+/// Resolves module paths relative to a directory
+///
+/// `(use `./helpers.expr`)` reads `helpers.expr` in the directory.
+#[derive(Debug, Clone)]
+pub struct FsModuleResolver {
+    root: PathBuf,
+}
+
+impl FsModuleResolver {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ModuleResolver for FsModuleResolver {
+    fn resolve(&self, path: &str) -> io::Result<String> {
+        read_to_string(self.root.join(path))
+    }
+}
+
+/// Used by [`compile_many`](crate::compiler::compile_many), which can't
+/// resolve any modules
+pub(crate) struct NoModuleResolver;
+
+impl ModuleResolver for NoModuleResolver {
+    fn resolve(&self, _path: &str) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "modules can only be used with a ModuleResolver",
+        ))
+    }
+}
+
+/// Defines the definitions of modules for a program's `use`s
+pub(crate) struct ModuleLoader<'a> {
+    resolver: &'a dyn ModuleResolver,
+    /// Modules being loaded, to catch modules that use each other
+    loading: Vec<String>,
+    /// Modules already loaded and the names they defined
+    loaded: Vec<(String, Vec<String>)>,
+}
+
+impl<'a> ModuleLoader<'a> {
+    pub(crate) fn new(resolver: &'a dyn ModuleResolver) -> Self {
+        Self {
+            resolver,
+            loading: vec![],
+            loaded: vec![],
+        }
+    }
+
+    /// [Define](CompileTimeEnv::define) everything the module a
+    /// `(use `path`)` expression imports defines
+    ///
+    /// Returns bytecode for the list of names the module defined. Using a
+    /// module again doesn't define anything. Errors in a module are reported
+    /// at the `use` as a [`CompileError::InvalidModule`].
+    pub(crate) fn use_module(
+        &mut self,
+        env: &mut CompileTimeEnv,
+        (expr, span): &ExprS,
+    ) -> ExprResult<ExprByteCode> {
+        let names = self
+            .load(env, expr)
+            .map_err(|err| vec![(err.into(), span.clone())])?;
+
+        let names = names
+            .into_iter()
+            .map(|name| (Expr::string(&name), span.clone()))
+            .collect();
+
+        compile(&mut (Expr::list(names), span.clone()), env)
+    }
+
+    fn load(&mut self, env: &mut CompileTimeEnv, expr: &Expr) -> Result<Vec<String>, CompileError> {
+        let Expr::Call(call) = expr else {
+            return Err(CompileError::InvalidUse);
+        };
+
+        let (true, [(Expr::String(path), _)]) = (call.is_use(), call.args.as_slice()) else {
+            return Err(CompileError::InvalidUse);
+        };
+
+        let path = path.0.clone();
+
+        if let Some((_, names)) = self.loaded.iter().find(|(loaded, _)| *loaded == path) {
+            return Ok(names.clone());
+        }
+
+        if self.loading.contains(&path) {
+            return Err(CompileError::InvalidModule {
+                path,
+                message: "it's used by a module it uses".to_string(),
+            });
+        }
+
+        let source =
+            self.resolver
+                .resolve(&path)
+                .map_err(|err| CompileError::UnresolvedModule {
+                    path: path.clone(),
+                    message: err.to_string(),
+                })?;
+
+        self.loading.push(path.clone());
+
+        let names = self.load_source(env, &path, &source);
+
+        self.loading.pop();
+
+        let names = names?;
+
+        self.loaded.push((path, names.clone()));
+
+        Ok(names)
+    }
+
+    /// Define the definitions in a module's source
+    fn load_source(
+        &mut self,
+        env: &mut CompileTimeEnv,
+        path: &str,
+        source: &str,
+    ) -> Result<Vec<String>, CompileError> {
+        let invalid_module = |errs: Vec<ExprErrorS>| CompileError::InvalidModule {
+            path: path.to_string(),
+            message: errs
+                .iter()
+                .map(|(err, span)| err.as_diagnostic(source, span).message)
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+
+        let mut exprs = parse_many(source).map_err(invalid_module)?;
+        let mut names = vec![];
+
+        for expr in &mut exprs {
+            match &expr.0 {
+                Expr::Call(call) if call.is_def() => {
+                    let name = match call.args.first() {
+                        Some((Expr::Identifier(name), _)) => Some(name.lookup_name().to_string()),
+                        _ => None,
+                    };
+
+                    env.define(expr).map_err(invalid_module)?;
+                    names.extend(name);
+                }
+                Expr::Call(call) if call.is_use() => {
+                    self.load(env, &expr.0)?;
+                }
+                _ => {
+                    return Err(CompileError::InvalidModule {
+                        path: path.to_string(),
+                        message: "modules can only contain (def name expr) and (use `path`)"
+                            .to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod module_tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    use crate::{
+        compiler::{compile_many, compile_many_with_resolver},
+        errors::ExprError,
+        value::Value,
+        vm::{RuntimeEnv, Vm},
+    };
+
+    struct MapModuleResolver(HashMap<&'static str, &'static str>);
+
+    impl ModuleResolver for MapModuleResolver {
+        fn resolve(&self, path: &str) -> io::Result<String> {
+            self.0
+                .get(path)
+                .map(|source| source.to_string())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+        }
+    }
+
+    fn resolver() -> MapModuleResolver {
+        MapModuleResolver(HashMap::from([
+            (
+                "./greetings.expr",
+                "(def hello `hello`) (def world `world`)",
+            ),
+            (
+                "./phrases.expr",
+                "(use `./greetings.expr`) (def hello_world (concat (hello) ` ` (world)))",
+            ),
+            ("./a.expr", "(use `./b.expr`)"),
+            ("./b.expr", "(use `./a.expr`)"),
+            ("./call.expr", "(def hi `hi`) (hi)"),
+            ("./redefine.expr", "(def concat `concat`)"),
+        ]))
+    }
+
+    fn eval(source: &str) -> Vec<ExprResult<Value>> {
+        let env = CompileTimeEnv::default();
+        let mut exprs = parse_many(source).unwrap();
+        let bytecodes = compile_many_with_resolver(&mut exprs, &env, &resolver());
+
+        Vm::new().eval_many(bytecodes, &env, &RuntimeEnv::default())
+    }
+
+    fn compile_err(message: CompileError, span: std::ops::Range<usize>) -> ExprResult<Value> {
+        Err(vec![(ExprError::CompileError(message), span)])
+    }
+
+    #[test]
+    fn use_defines_module_definitions() {
+        assert_eq!(
+            vec![
+                Ok(Value::List(vec![
+                    Value::String("hello".into()),
+                    Value::String("world".into())
+                ])),
+                Ok(Value::String("world hello".into())),
+            ],
+            eval("(use `./greetings.expr`) (concat (world) ` ` (hello))")
+        );
+    }
+
+    #[test]
+    fn modules_use_modules() {
+        assert_eq!(
+            Ok(Value::String("hello world".into())),
+            eval("(use `./phrases.expr`) (hello_world)")[1]
+        );
+        assert_eq!(
+            Ok(Value::String("hello".into())),
+            eval("(use `./phrases.expr`) (use `./greetings.expr`) (hello)")[2]
+        );
+    }
+
+    #[test]
+    fn invalid_modules() {
+        assert_eq!(
+            compile_err(
+                CompileError::UnresolvedModule {
+                    path: "./missing.expr".to_string(),
+                    message: "not found".to_string()
+                },
+                0..22
+            ),
+            eval("(use `./missing.expr`)")[0]
+        );
+        assert_eq!(
+            compile_err(
+                CompileError::InvalidModule {
+                    path: "./a.expr".to_string(),
+                    message: "it's used by a module it uses".to_string()
+                },
+                0..16
+            ),
+            eval("(use `./a.expr`)")[0]
+        );
+        assert_eq!(
+            compile_err(
+                CompileError::InvalidModule {
+                    path: "./call.expr".to_string(),
+                    message: "modules can only contain (def name expr) and (use `path`)"
+                        .to_string()
+                },
+                0..19
+            ),
+            eval("(use `./call.expr`)")[0]
+        );
+        assert_eq!(
+            compile_err(
+                CompileError::InvalidModule {
+                    path: "./redefine.expr".to_string(),
+                    message: "already defined: concat".to_string()
+                },
+                0..23
+            ),
+            eval("(use `./redefine.expr`)")[0]
+        );
+    }
+
+    #[test]
+    fn invalid_use() {
+        assert_eq!(
+            compile_err(CompileError::InvalidUse, 0..11),
+            eval("(use hello)")[0]
+        );
+        assert_eq!(
+            compile_err(CompileError::InvalidUse, 4..28),
+            eval("(id (use `./greetings.expr`))")[0]
+        );
+    }
+
+    #[test]
+    fn compile_many_has_no_resolver() {
+        let source = "(use `./greetings.expr`)";
+        let mut exprs = parse_many(source).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::CompileError(CompileError::UnresolvedModule {
+                    path: "./greetings.expr".to_string(),
+                    message: "modules can only be used with a ModuleResolver".to_string()
+                }),
+                0..24
+            )]),
+            compile_many(&mut exprs, &CompileTimeEnv::default())[0]
+        );
+    }
+
+    #[test]
+    fn fs_module_resolver() {
+        let root = std::env::temp_dir().join("reqlang_expr_fs_module_resolver");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("helpers.expr"), "(def hi `hi`)").unwrap();
+
+        let resolver = FsModuleResolver::new(&root);
+
+        assert_eq!("(def hi `hi`)", resolver.resolve("./helpers.expr").unwrap());
+        assert!(resolver.resolve("./missing.expr").is_err());
+    }
+}
@@ -1,20 +1,43 @@
 //! Parsing source code in to expressions
 
+use std::time::Instant;
+
 use lalrpop_util::lalrpop_mod;
 
 use crate::{
     ast::{self, add_type_to_expr_parse},
-    errors::{ExprResult, SyntaxError},
-    lexer::lex,
-    parser::grammar::ExprParser,
+    errors::{ExprErrorS, ExprResult, SyntaxError},
+    lexer::{Token, lex},
+    metrics::metrics,
+    parser::grammar::{ExprParser, ExprsParser},
 };
 
-lalrpop_mod!(grammar);
+lalrpop_mod!(
+    #[allow(clippy::ptr_arg)]
+    grammar
+);
+
+/// Maximum depth of nested parentheses and angle brackets before
+/// [`SyntaxError::TooDeep`]
+///
+/// Checked on the tokens before parsing so pathological input is rejected
+/// instead of overflowing the stack while building or walking the AST.
+/// Expressions at this depth compile and evaluate on a 2MB thread stack, the
+/// default for spawned threads, in debug builds.
+pub const MAX_PARSE_DEPTH: usize = 256;
 
 /// Parse source code in to an [`ast::Expr`].
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(source_hash = crate::cache::source_hash(source)))
+)]
 pub fn parse(source: &str) -> ExprResult<ast::Expr> {
     let tokens = lex(source);
 
+    check_depth(&tokens)?;
+
+    let start = Instant::now();
+
     let mut errs = vec![];
 
     let expr_parser = ExprParser::new();
@@ -33,5 +56,153 @@ pub fn parse(source: &str) -> ExprResult<ast::Expr> {
 
     errs.extend(parser_errors);
 
+    metrics().parsed(errs.is_empty(), start.elapsed());
+
+    #[cfg(feature = "tracing")]
+    if !errs.is_empty() {
+        tracing::debug!(errors = errs.len(), "parsing failed");
+    }
+
     if errs.is_empty() { Ok(expr) } else { Err(errs) }
 }
+
+/// Parse source code containing any number of expressions in to a list of
+/// [`ast::ExprS`], each spanned by where it is in the source
+///
+/// ```
+/// use reqlang_expr::prelude::*;
+///
+/// let exprs = parse_many("(noop) `a`").unwrap();
+///
+/// assert_eq!(2, exprs.len());
+/// assert_eq!(7..10, exprs[1].1);
+/// ```
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "debug", skip_all, fields(source_hash = crate::cache::source_hash(source)))
+)]
+pub fn parse_many(source: &str) -> ExprResult<Vec<ast::ExprS>> {
+    let tokens = lex(source);
+
+    check_depth(&tokens)?;
+
+    let start = Instant::now();
+
+    let mut errs = vec![];
+
+    let exprs_parser = ExprsParser::new();
+
+    let mut parser_errors = Vec::new();
+
+    let mut exprs = match exprs_parser.parse(source, &mut parser_errors, tokens) {
+        Ok(exprs) => exprs,
+        Err(err) => {
+            errs.push(SyntaxError::from_parser_error(err, source));
+            vec![]
+        }
+    };
+
+    for (expr, _) in exprs.iter_mut() {
+        add_type_to_expr_parse(expr);
+    }
+
+    errs.extend(parser_errors);
+
+    metrics().parsed(errs.is_empty(), start.elapsed());
+
+    #[cfg(feature = "tracing")]
+    if !errs.is_empty() {
+        tracing::debug!(errors = errs.len(), "parsing failed");
+    }
+
+    if errs.is_empty() {
+        Ok(exprs)
+    } else {
+        Err(errs)
+    }
+}
+
+/// Fail with [`SyntaxError::TooDeep`] at the first token nested deeper than
+/// [`MAX_PARSE_DEPTH`]
+fn check_depth(tokens: &[Result<(usize, Token, usize), ExprErrorS>]) -> ExprResult<()> {
+    let mut depth = 0usize;
+
+    for (start, token, end) in tokens.iter().flatten() {
+        match token {
+            Token::LParan | Token::LBracket | Token::LAngle => depth += 1,
+            Token::RParan | Token::RBracket | Token::RAngle => depth = depth.saturating_sub(1),
+            _ => continue,
+        }
+
+        if depth > MAX_PARSE_DEPTH {
+            return Err(vec![(
+                SyntaxError::TooDeep {
+                    max: MAX_PARSE_DEPTH,
+                }
+                .into(),
+                *start..*end,
+            )]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    use crate::errors::ExprError;
+
+    fn nested(depth: usize) -> String {
+        format!("{}`a`{}", "(id ".repeat(depth), ")".repeat(depth))
+    }
+
+    #[test]
+    fn too_deep() {
+        let source = nested(100_000);
+        let offset = "(id ".len() * MAX_PARSE_DEPTH;
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::SyntaxError(SyntaxError::TooDeep {
+                    max: MAX_PARSE_DEPTH
+                }),
+                offset..offset + 1
+            )]),
+            parse(&source)
+        );
+        assert!(parse_many(&source).is_err());
+    }
+
+    #[test]
+    fn max_depth() {
+        assert!(parse(&nested(MAX_PARSE_DEPTH)).is_ok());
+    }
+
+    #[test]
+    fn max_depth_evaluates_on_small_stack() {
+        use crate::prelude::*;
+
+        let value = std::thread::Builder::new()
+            .stack_size(2 * 1024 * 1024)
+            .spawn(|| {
+                let source = nested(MAX_PARSE_DEPTH);
+                let env = CompileTimeEnv::default();
+
+                let mut expr = (parse(&source).unwrap(), 0..source.len());
+                let bytecode = compile(&mut expr, &env).unwrap();
+
+                Vm::new()
+                    .interpret(bytecode.into(), &env, &RuntimeEnv::default())
+                    .unwrap()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+
+        assert_eq!(Value::String("a".into()), value);
+    }
+}
@@ -0,0 +1,287 @@
+//! Tracking which inputs contributed to a value
+
+use std::{collections::BTreeSet, ops::Deref};
+
+use crate::{
+    compiler::{
+        CompileTimeEnv,
+        lookup::{CLIENT_CTX, PROMPT, SECRET, VAR},
+    },
+    value::Value,
+};
+
+/// The vars, prompts, secrets, and client context that contributed to a value
+///
+/// Traced by [`Vm::interpret_traced`](crate::vm::Vm::interpret_traced) as the
+/// bytecode is evaluated. Each `GET` taints the value it pushes and each
+/// call's result carries the taint of its callee and arguments. Reading a
+/// `let` binding carries the taint of its value. A `cond`, `and`, or `or`
+/// carries the taint of its condition and the branch taken. A `try` carries
+/// the taint of its guarded ops if they succeed, otherwise the taint of its
+/// fallback ops and of the values the guarded ops had evaluated when they
+/// failed.
+///
+/// Names are stored without their sigil.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    pub vars: BTreeSet<String>,
+    pub prompts: BTreeSet<String>,
+    pub secrets: BTreeSet<String>,
+    pub client_context: BTreeSet<String>,
+}
+
+impl Provenance {
+    /// The provenance of the var, prompt, secret, or client context at
+    /// `index`, empty for other lookup types
+    pub(crate) fn of_input(env: &CompileTimeEnv, lookup: u8, index: usize) -> Self {
+        let mut provenance = Provenance::default();
+
+        match lookup {
+            VAR => provenance.vars.extend(env.get_var(index).cloned()),
+            PROMPT => provenance.prompts.extend(env.get_prompt(index).cloned()),
+            SECRET => provenance.secrets.extend(env.get_secret(index).cloned()),
+            CLIENT_CTX => provenance
+                .client_context
+                .extend(env.get_client_context(index).cloned()),
+            _ => {}
+        }
+
+        provenance
+    }
+
+    /// If a secret contributed to the value
+    ///
+    /// Values with secret provenance should be redacted from logs and
+    /// shouldn't be sent over unencrypted connections.
+    pub fn is_secret(&self) -> bool {
+        !self.secrets.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+            && self.prompts.is_empty()
+            && self.secrets.is_empty()
+            && self.client_context.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Provenance) {
+        self.vars.extend(other.vars);
+        self.prompts.extend(other.prompts);
+        self.secrets.extend(other.secrets);
+        self.client_context.extend(other.client_context);
+    }
+}
+
+impl FromIterator<Provenance> for Provenance {
+    fn from_iter<I: IntoIterator<Item = Provenance>>(iter: I) -> Self {
+        let mut provenance = Provenance::default();
+
+        for other in iter {
+            provenance.extend(other);
+        }
+
+        provenance
+    }
+}
+
+/// A [`Value`] and the [`Provenance`] of the inputs that contributed to it
+///
+/// Derefs to the value so it can be used as one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TracedValue {
+    value: Value,
+    provenance: Provenance,
+}
+
+impl TracedValue {
+    pub fn new(value: Value, provenance: Provenance) -> Self {
+        Self { value, provenance }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+}
+
+impl Deref for TracedValue {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod provenance_tests {
+    use super::*;
+
+    use crate::{
+        compiler::compile,
+        parser::parse,
+        vm::{RuntimeEnv, Vm},
+    };
+
+    fn env() -> (CompileTimeEnv, RuntimeEnv) {
+        let env = CompileTimeEnv::new(
+            vec!["v".to_string()],
+            vec!["p".to_string()],
+            vec!["s".to_string()],
+            vec!["c".to_string()],
+        );
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["V".to_string()],
+            prompts: vec!["P".to_string()],
+            secrets: vec!["S".to_string()],
+            client_context: vec![Value::Bool(true)],
+        };
+
+        (env, runtime_env)
+    }
+
+    fn traced(source: &str) -> TracedValue {
+        let (env, runtime_env) = env();
+        let ast = parse(source).unwrap();
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        Vm::new()
+            .interpret_traced(bytecode.into(), &env, &runtime_env)
+            .unwrap()
+    }
+
+    fn provenance(source: &str) -> Provenance {
+        traced(source).provenance().clone()
+    }
+
+    fn set(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn literals_have_no_provenance() {
+        assert!(provenance("`a`").is_empty());
+        assert!(provenance("(concat `a` `b`)").is_empty());
+        assert!(provenance("true").is_empty());
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(set(&["v"]), provenance(":v").vars);
+        assert_eq!(set(&["p"]), provenance("?p").prompts);
+        assert_eq!(set(&["s"]), provenance("!s").secrets);
+        assert_eq!(set(&["c"]), provenance("@c").client_context);
+    }
+
+    #[test]
+    fn carried_through_calls() {
+        let provenance = provenance("(concat :v (trim !s) `x`)");
+
+        assert_eq!(set(&["v"]), provenance.vars);
+        assert_eq!(set(&["s"]), provenance.secrets);
+        assert!(provenance.prompts.is_empty());
+        assert!(provenance.is_secret());
+    }
+
+    #[test]
+    fn carried_through_let() {
+        let provenance = provenance("(let (x !s) (let (y :v) (concat x `x`)))");
+
+        assert_eq!(set(&["s"]), provenance.secrets);
+        assert!(provenance.vars.is_empty());
+    }
+
+    #[test]
+    fn carried_through_branch_taken() {
+        let then = provenance("(cond (eq :v `V`) ?p !s)");
+
+        assert_eq!(set(&["v"]), then.vars);
+        assert_eq!(set(&["p"]), then.prompts);
+        assert!(!then.is_secret());
+
+        let otherwise = provenance("(cond (eq :v `x`) ?p !s)");
+
+        assert_eq!(set(&["v"]), otherwise.vars);
+        assert!(otherwise.prompts.is_empty());
+        assert_eq!(set(&["s"]), otherwise.secrets);
+    }
+
+    #[test]
+    fn carried_through_condition() {
+        let provenance = provenance("(cond (eq !s `S`) `yes` `no`)");
+
+        assert_eq!(set(&["s"]), provenance.secrets);
+    }
+
+    #[test]
+    fn short_circuits() {
+        assert_eq!(
+            set(&["c"]),
+            provenance("(or (id @c) (eq !s `S`))").client_context
+        );
+        assert!(!provenance("(or (id @c) (eq !s `S`))").is_secret());
+        assert!(!provenance("(and (not (id @c)) (eq !s `S`))").is_secret());
+        assert!(provenance("(and (id @c) (eq !s `S`))").is_secret());
+    }
+
+    #[test]
+    fn carried_through_branch_taken_in_cond_called_as_value() {
+        let provenance = provenance("(let (f cond) (f (id @c) :v !s))");
+
+        assert_eq!(set(&["c"]), provenance.client_context);
+        assert_eq!(set(&["v"]), provenance.vars);
+        assert!(!provenance.is_secret());
+    }
+
+    #[test]
+    fn carried_through_guarded_ops_that_succeed() {
+        let provenance = provenance("(concat (try (concat :v ?p) !s) `x`)");
+
+        assert_eq!(set(&["v"]), provenance.vars);
+        assert_eq!(set(&["p"]), provenance.prompts);
+        assert!(!provenance.is_secret());
+    }
+
+    #[test]
+    fn carried_through_guarded_ops_that_fail_and_fallback() {
+        let provenance = provenance("(try (concat :v (parse_bool !s)) ?p)");
+
+        assert_eq!(set(&["v"]), provenance.vars);
+        assert_eq!(set(&["p"]), provenance.prompts);
+        assert_eq!(set(&["s"]), provenance.secrets);
+    }
+
+    #[test]
+    fn traced_value_derefs_to_value() {
+        let traced = traced("(concat ?p :v)");
+
+        assert_eq!(&Value::String("PV".into()), traced.value());
+        assert_eq!(Ok("PV"), traced.get_string());
+        assert_eq!(set(&["p"]), traced.provenance().prompts);
+        assert_eq!(set(&["v"]), traced.provenance().vars);
+        assert!(!traced.provenance().is_secret());
+    }
+
+    #[test]
+    fn errors() {
+        let (env, runtime_env) = env();
+        let source = "(parse_bool !s)";
+        let ast = parse(source).unwrap();
+        let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let mut vm = Vm::new();
+
+        assert_eq!(
+            vm.interpret(bytecode.clone().into(), &env, &runtime_env)
+                .map(|value| TracedValue::new(value, Provenance::default())),
+            vm.interpret_traced(bytecode.into(), &env, &runtime_env)
+        );
+    }
+}
@@ -0,0 +1,629 @@
+//! Providers of runtime values for variables and secrets
+
+use std::{collections::HashMap, future::Future, path::Path};
+
+use crate::{compiler::CompileTimeEnv, errors::ProviderError, vm::RuntimeEnv};
+
+/// Which value wins when a provider and an explicitly set value both exist
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precedence {
+    /// Values explicitly set in the [`RuntimeEnv`] win
+    #[default]
+    Explicit,
+    /// Values from the provider win
+    Provider,
+}
+
+/// Loads `KEY=value` (.env) files and maps them on to vars and secrets by name
+///
+/// ```
+/// use reqlang_expr::{prelude::*, providers::DotenvProvider};
+///
+/// let provider = DotenvProvider::parse("BASE_URL=https://example.com\nTOKEN=abc123").unwrap();
+///
+/// let env = CompileTimeEnv::new(
+///     vec!["BASE_URL".to_string()],
+///     vec![],
+///     vec!["TOKEN".to_string()],
+///     vec![],
+/// );
+///
+/// let runtime_env = provider.runtime_env(&env, &RuntimeEnv::default()).unwrap();
+///
+/// assert_eq!(vec!["https://example.com".to_string()], runtime_env.vars);
+/// assert_eq!(vec!["abc123".to_string()], runtime_env.secrets);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DotenvProvider {
+    entries: Vec<(String, String)>,
+    precedence: Precedence,
+}
+
+impl DotenvProvider {
+    /// Parse the contents of a .env file
+    ///
+    /// Blank lines and lines starting with `#` are skipped. Keys may be
+    /// prefixed with `export`. Values may be wrapped in single or double
+    /// quotes; double quoted values support `\n`, `\"`, and `\\` escapes.
+    pub fn parse(source: &str) -> Result<Self, ProviderError> {
+        let mut entries = vec![];
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line = line.strip_prefix("export ").unwrap_or(line);
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ProviderError::InvalidLine { line: i + 1 });
+            };
+
+            let key = key.trim();
+
+            if key.is_empty() {
+                return Err(ProviderError::InvalidLine { line: i + 1 });
+            }
+
+            entries.push((key.to_string(), parse_value(value.trim())));
+        }
+
+        Ok(Self {
+            entries,
+            precedence: Precedence::default(),
+        })
+    }
+
+    /// Read and parse a .env file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProviderError> {
+        let path = path.as_ref();
+
+        let source = std::fs::read_to_string(path).map_err(|source| ProviderError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+        Self::parse(&source)
+    }
+
+    pub fn with_precedence(mut self, precedence: Precedence) -> Self {
+        self.precedence = precedence;
+        self
+    }
+
+    pub fn precedence(&self) -> Precedence {
+        self.precedence
+    }
+
+    /// Get the value for a key, with later entries overriding earlier ones
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Build a [`RuntimeEnv`] for the vars and secrets in a [`CompileTimeEnv`]
+    ///
+    /// Values set in `explicit` are combined with values from the file using
+    /// the provider's [`Precedence`]. Prompts and client context are copied
+    /// from `explicit` as is. Every var or secret without a value is returned
+    /// as [`ProviderError::Missing`].
+    pub fn runtime_env(
+        &self,
+        env: &CompileTimeEnv,
+        explicit: &RuntimeEnv,
+    ) -> Result<RuntimeEnv, Vec<ProviderError>> {
+        let mut errs = vec![];
+
+        let vars = self.resolve(env.vars(), &explicit.vars, ":", &mut errs);
+        let secrets = self.resolve(env.secrets(), &explicit.secrets, "!", &mut errs);
+
+        if !errs.is_empty() {
+            return Err(errs);
+        }
+
+        Ok(RuntimeEnv {
+            vars,
+            prompts: explicit.prompts.clone(),
+            secrets,
+            client_context: explicit.client_context.clone(),
+        })
+    }
+
+    fn resolve(
+        &self,
+        names: &[String],
+        explicit: &[String],
+        sigil: &str,
+        errs: &mut Vec<ProviderError>,
+    ) -> Vec<String> {
+        names
+            .iter()
+            .enumerate()
+            .filter_map(|(i, name)| {
+                let explicit = explicit.get(i).map(String::as_str);
+                let provided = self.get(name);
+
+                let value = match self.precedence {
+                    Precedence::Explicit => explicit.or(provided),
+                    Precedence::Provider => provided.or(explicit),
+                };
+
+                if value.is_none() {
+                    errs.push(ProviderError::Missing(format!("{sigil}{name}")));
+                }
+
+                value.map(str::to_string)
+            })
+            .collect()
+    }
+}
+
+/// Provides secret values by name when the VM executes `GET SECRET`
+///
+/// Used with [`Vm::interpret_with_secret_provider`](crate::vm::Vm::interpret_with_secret_provider)
+/// so secrets don't need to be materialized in to [`RuntimeEnv::secrets`] up front.
+pub trait SecretProvider {
+    fn get_secret(&self, name: &str) -> Result<String, ProviderError>;
+}
+
+/// Async variant of [`SecretProvider`]
+///
+/// Used with [`Vm::interpret_with_async_secret_provider`](crate::vm::Vm::interpret_with_async_secret_provider)
+/// which fetches only the secrets an expression references before
+/// interpreting it.
+pub trait AsyncSecretProvider {
+    fn get_secret(&self, name: &str) -> impl Future<Output = Result<String, ProviderError>>;
+}
+
+/// Reads secrets from environment variables
+///
+/// The secret `!token` with a prefix of `REQLANG_SECRET_` is read from the
+/// environment variable `REQLANG_SECRET_token`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EnvSecretProvider {
+    prefix: String,
+}
+
+impl EnvSecretProvider {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+}
+
+impl SecretProvider for EnvSecretProvider {
+    fn get_secret(&self, name: &str) -> Result<String, ProviderError> {
+        let key = format!("{}{name}", self.prefix);
+
+        std::env::var(&key).map_err(|_| ProviderError::Missing(key))
+    }
+}
+
+impl AsyncSecretProvider for EnvSecretProvider {
+    async fn get_secret(&self, name: &str) -> Result<String, ProviderError> {
+        SecretProvider::get_secret(self, name)
+    }
+}
+
+/// Secrets fetched ahead of time from an [`AsyncSecretProvider`]
+#[derive(Debug, Default)]
+pub(crate) struct FetchedSecrets {
+    secrets: HashMap<String, Result<String, String>>,
+}
+
+impl FetchedSecrets {
+    pub(crate) fn insert(&mut self, name: String, value: Result<String, ProviderError>) {
+        self.secrets
+            .insert(name, value.map_err(|err| err.to_string()));
+    }
+}
+
+impl SecretProvider for FetchedSecrets {
+    fn get_secret(&self, name: &str) -> Result<String, ProviderError> {
+        match self.secrets.get(name) {
+            Some(Ok(value)) => Ok(value.clone()),
+            Some(Err(message)) => Err(ProviderError::Unavailable(message.clone())),
+            None => Err(ProviderError::Missing(format!("!{name}"))),
+        }
+    }
+}
+
+fn parse_value(value: &str) -> String {
+    if let Some(inner) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        let mut unescaped = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some('n')) => {
+                    unescaped.push('\n');
+                    chars.next();
+                }
+                ('\\', Some(escaped @ ('"' | '\\'))) => {
+                    unescaped.push(escaped);
+                    chars.next();
+                }
+                _ => unescaped.push(c),
+            }
+        }
+
+        return unescaped;
+    }
+
+    if let Some(inner) = value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+    {
+        return inner.to_string();
+    }
+
+    match value.find(" #") {
+        Some(i) => value[..i].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod providers_tests {
+    use super::*;
+
+    #[test]
+    fn parse_dotenv() {
+        let provider = DotenvProvider::parse(
+            r#"
+# comment
+A=1
+export B = two
+C="line\nbreak \"quoted\""
+D='single # not a comment'
+E=value # comment
+F=
+A=override
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(Some("override"), provider.get("A"));
+        assert_eq!(Some("two"), provider.get("B"));
+        assert_eq!(Some("line\nbreak \"quoted\""), provider.get("C"));
+        assert_eq!(Some("single # not a comment"), provider.get("D"));
+        assert_eq!(Some("value"), provider.get("E"));
+        assert_eq!(Some(""), provider.get("F"));
+        assert_eq!(None, provider.get("G"));
+    }
+
+    #[test]
+    fn parse_invalid_line() {
+        assert!(matches!(
+            DotenvProvider::parse("A=1\nB\n"),
+            Err(ProviderError::InvalidLine { line: 2 })
+        ));
+        assert!(matches!(
+            DotenvProvider::parse("=1"),
+            Err(ProviderError::InvalidLine { line: 1 })
+        ));
+    }
+
+    #[test]
+    fn load_missing_file() {
+        assert!(matches!(
+            DotenvProvider::load("does/not/exist.env"),
+            Err(ProviderError::Io { .. })
+        ));
+    }
+
+    fn env() -> CompileTimeEnv {
+        CompileTimeEnv::new(
+            vec!["a".to_string(), "b".to_string()],
+            vec!["p".to_string()],
+            vec!["s".to_string()],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn explicit_values_take_precedence() {
+        let provider = DotenvProvider::parse("a=file_a\nb=file_b\ns=file_s").unwrap();
+
+        let explicit = RuntimeEnv {
+            vars: vec!["explicit_a".to_string()],
+            prompts: vec!["prompt".to_string()],
+            ..Default::default()
+        };
+
+        let runtime_env = provider.runtime_env(&env(), &explicit).unwrap();
+
+        assert_eq!(
+            vec!["explicit_a".to_string(), "file_b".to_string()],
+            runtime_env.vars
+        );
+        assert_eq!(vec!["prompt".to_string()], runtime_env.prompts);
+        assert_eq!(vec!["file_s".to_string()], runtime_env.secrets);
+    }
+
+    #[test]
+    fn provider_values_take_precedence() {
+        let provider = DotenvProvider::parse("a=file_a\nb=file_b\ns=file_s")
+            .unwrap()
+            .with_precedence(Precedence::Provider);
+
+        let explicit = RuntimeEnv {
+            vars: vec!["explicit_a".to_string()],
+            ..Default::default()
+        };
+
+        let runtime_env = provider.runtime_env(&env(), &explicit).unwrap();
+
+        assert_eq!(
+            vec!["file_a".to_string(), "file_b".to_string()],
+            runtime_env.vars
+        );
+    }
+
+    #[test]
+    fn missing_values() {
+        let provider = DotenvProvider::parse("a=file_a").unwrap();
+
+        let errs = provider
+            .runtime_env(&env(), &RuntimeEnv::default())
+            .unwrap_err();
+
+        assert_eq!(
+            vec!["no value provided for :b", "no value provided for !s"],
+            errs.iter().map(|e| e.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    struct MapSecretProvider(HashMap<&'static str, &'static str>);
+
+    impl SecretProvider for MapSecretProvider {
+        fn get_secret(&self, name: &str) -> Result<String, ProviderError> {
+            self.0
+                .get(name)
+                .map(|value| value.to_string())
+                .ok_or_else(|| ProviderError::Missing(format!("!{name}")))
+        }
+    }
+
+    impl AsyncSecretProvider for MapSecretProvider {
+        async fn get_secret(&self, name: &str) -> Result<String, ProviderError> {
+            SecretProvider::get_secret(self, name)
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    fn compile_source(source: &str, env: &CompileTimeEnv) -> Box<crate::compiler::ExprByteCode> {
+        let ast = crate::parser::parse(source).unwrap();
+
+        crate::compiler::compile(&mut (ast, 0..source.len()), env)
+            .unwrap()
+            .into()
+    }
+
+    fn secrets_env() -> CompileTimeEnv {
+        CompileTimeEnv::new(
+            vec![],
+            vec![],
+            vec!["a".to_string(), "b".to_string()],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn vm_consults_secret_provider() {
+        let env = secrets_env();
+        let provider = MapSecretProvider(HashMap::from([("b", "provided_b")]));
+
+        let runtime_env = RuntimeEnv {
+            secrets: vec!["explicit_a".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Ok(crate::value::Value::String("explicit_a provided_b".into())),
+            crate::vm::Vm::new().interpret_with_secret_provider(
+                compile_source("(concat !a ` ` !b)", &env),
+                &env,
+                &runtime_env,
+                &provider
+            )
+        );
+    }
+
+    #[test]
+    fn vm_gets_each_secret_from_provider_once() {
+        struct CountingSecretProvider(std::cell::Cell<usize>);
+
+        impl SecretProvider for CountingSecretProvider {
+            fn get_secret(&self, name: &str) -> Result<String, ProviderError> {
+                self.0.set(self.0.get() + 1);
+
+                Ok(name.to_uppercase())
+            }
+        }
+
+        let env = secrets_env();
+        let provider = CountingSecretProvider(std::cell::Cell::new(0));
+        let mut vm = crate::vm::Vm::new();
+
+        for _ in 0..2 {
+            assert_eq!(
+                Ok(crate::value::Value::String("AAB".into())),
+                vm.interpret_with_secret_provider(
+                    compile_source("(concat !a !a !b)", &env),
+                    &env,
+                    &RuntimeEnv::default(),
+                    &provider
+                )
+            );
+        }
+
+        // Once per secret per evaluation
+        assert_eq!(4, provider.0.get());
+    }
+
+    #[test]
+    fn vm_only_gets_secrets_in_the_branch_taken() {
+        let env = secrets_env();
+        let provider = MapSecretProvider(HashMap::from([("a", "provided_a")]));
+
+        assert_eq!(
+            Ok(crate::value::Value::String("provided_a".into())),
+            crate::vm::Vm::new().interpret_with_secret_provider(
+                compile_source("(cond true !a !b)", &env),
+                &env,
+                &RuntimeEnv::default(),
+                &provider
+            )
+        );
+    }
+
+    #[test]
+    fn vm_reports_unavailable_secrets() {
+        use crate::errors::{ExprError, RuntimeError};
+
+        let env = secrets_env();
+        let provider = MapSecretProvider(HashMap::new());
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::SecretUnavailable {
+                    name: "a".to_string(),
+                    message: "no value provided for !a".to_string()
+                }),
+                0..0
+            )]),
+            crate::vm::Vm::new().interpret_with_secret_provider(
+                compile_source("!a", &env),
+                &env,
+                &RuntimeEnv::default(),
+                &provider
+            )
+        );
+    }
+
+    #[test]
+    fn vm_validates_runtime_env_with_secret_provider() {
+        use crate::errors::{CompileError, ExprError};
+
+        let env = CompileTimeEnv::new(vec!["v".to_string()], vec![], vec!["a".to_string()], vec![]);
+        let provider = MapSecretProvider(HashMap::from([("a", "provided_a")]));
+
+        let mismatch = Err(vec![(
+            ExprError::CompileError(CompileError::RuntimeEnvMismatch {
+                lookup: "var".to_string(),
+                expected: 1,
+                actual: 0,
+            }),
+            0..0,
+        )]);
+
+        assert_eq!(
+            mismatch,
+            crate::vm::Vm::new().interpret_with_secret_provider(
+                compile_source("(concat :v !a)", &env),
+                &env,
+                &RuntimeEnv::default(),
+                &provider
+            )
+        );
+        assert_eq!(
+            mismatch,
+            block_on(crate::vm::Vm::new().interpret_with_async_secret_provider(
+                compile_source("(concat :v !a)", &env),
+                &env,
+                &RuntimeEnv::default(),
+                &provider
+            ))
+        );
+    }
+
+    #[test]
+    fn vm_consults_async_secret_provider() {
+        let env = secrets_env();
+        let provider = MapSecretProvider(HashMap::from([("a", "provided_a")]));
+
+        let mut vm = crate::vm::Vm::new();
+
+        assert_eq!(
+            Ok(crate::value::Value::String("provided_a".into())),
+            block_on(vm.interpret_with_async_secret_provider(
+                compile_source("!a", &env),
+                &env,
+                &RuntimeEnv::default(),
+                &provider
+            ))
+        );
+    }
+
+    #[test]
+    fn vm_reports_undefined_secret_with_async_secret_provider() {
+        use crate::errors::{ExprError, RuntimeError};
+
+        let provider = MapSecretProvider(HashMap::new());
+
+        let mut codes = crate::compiler::get_version_bytes().to_vec();
+        codes.extend([
+            crate::compiler::opcode::GET,
+            crate::compiler::lookup::SECRET,
+            9,
+        ]);
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::InvalidBytecode {
+                    message: "undefined secret 9".to_string()
+                }),
+                0..0
+            )]),
+            block_on(crate::vm::Vm::new().interpret_with_async_secret_provider(
+                crate::compiler::ExprByteCode::new(codes, vec![], vec![]).into(),
+                &CompileTimeEnv::default(),
+                &RuntimeEnv::default(),
+                &provider
+            ))
+        );
+    }
+
+    #[test]
+    fn env_secret_provider() {
+        let provider = EnvSecretProvider::new("REQLANG_EXPR_TEST_SECRET_");
+
+        assert!(matches!(
+            SecretProvider::get_secret(&provider, "does_not_exist"),
+            Err(ProviderError::Missing(key)) if key == "REQLANG_EXPR_TEST_SECRET_does_not_exist"
+        ));
+
+        let path = std::env::var("PATH").unwrap();
+        let provider = EnvSecretProvider::new("PA");
+
+        assert_eq!(path, SecretProvider::get_secret(&provider, "TH").unwrap());
+        assert_eq!(
+            path,
+            block_on(AsyncSecretProvider::get_secret(&provider, "TH")).unwrap()
+        );
+    }
+}
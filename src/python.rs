@@ -0,0 +1,338 @@
+//! Python bindings enabled by the `python` feature
+//!
+//! Build an importable `reqlang_expr` extension module with:
+//!
+//! ```shell
+//! cargo rustc --release --lib --features python,pyo3/extension-module --crate-type cdylib
+//! cp target/release/libreqlang_expr.so reqlang_expr.so
+//! ```
+//!
+//! ```python
+//! import reqlang_expr
+//!
+//! expr = reqlang_expr.compile("(concat `Bearer ` !token)", secrets=["token"])
+//!
+//! assert expr.eval(secrets={"token": "abc123"}).value == "Bearer abc123"
+//! ```
+
+use std::collections::HashMap;
+
+use pyo3::{IntoPyObjectExt, create_exception, exceptions::PyException, prelude::*};
+
+use crate::{
+    analysis::Requirements,
+    compiler::{CompileTimeEnv, ExprByteCode, compile as compile_expr},
+    errors::{ExprErrorS, diagnostics::AsDiagnostic},
+    parser::parse,
+    value::Value,
+    vm::{RuntimeEnv, Vm},
+};
+
+create_exception!(
+    reqlang_expr,
+    ExprError,
+    PyException,
+    "Errors lexing, parsing, compiling, or evaluating an expression. The `diagnostics` attribute lists a `Diagnostic` for each error."
+);
+
+/// An error in an expression and where it occurred
+#[pyclass(module = "reqlang_expr", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: String,
+    pub message: String,
+    /// Zero based `(line, character)` the error starts at
+    pub start: (u32, u32),
+    /// Zero based `(line, character)` the error ends before
+    pub end: (u32, u32),
+}
+
+#[pymethods]
+impl Diagnostic {
+    fn __repr__(&self) -> String {
+        format!(
+            "Diagnostic({:?}, {:?}, {:?}, {:?})",
+            self.code, self.message, self.start, self.end
+        )
+    }
+}
+
+/// A value produced by evaluating an expression
+#[pyclass(name = "Value", module = "reqlang_expr", frozen, eq)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PyValue(pub Value);
+
+#[pymethods]
+impl PyValue {
+    /// Name of the value's type e.g. `String`
+    #[getter(r#type)]
+    fn ty(&self) -> String {
+        self.0.get_type().to_string()
+    }
+
+    /// The value as a `str`, `float`, or `bool`
+    ///
+    /// Functions and types are returned in their source form.
+    #[getter]
+    fn value(&self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        match &self.0 {
+            Value::String(string) => string.into_py_any(py),
+            Value::Number(number) => number.into_py_any(py),
+            Value::Bool(bool) => bool.into_py_any(py),
+            value => value.to_string().into_py_any(py),
+        }
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Value({})", self.0)
+    }
+}
+
+/// An expression compiled by `compile`
+#[pyclass(module = "reqlang_expr", frozen)]
+pub struct Expr {
+    source: String,
+    env: CompileTimeEnv,
+    bytecode: ExprByteCode,
+}
+
+#[pymethods]
+impl Expr {
+    /// Evaluate the expression with values for the names it was compiled with
+    ///
+    /// Every name the expression references must have a value.
+    #[pyo3(signature = (vars = HashMap::new(), prompts = HashMap::new(), secrets = HashMap::new(), client_context = HashMap::new()))]
+    fn eval(
+        &self,
+        vars: HashMap<String, String>,
+        prompts: HashMap<String, String>,
+        secrets: HashMap<String, String>,
+        client_context: HashMap<String, String>,
+    ) -> PyResult<PyValue> {
+        let requirements = Requirements::of(&self.bytecode, &self.env);
+
+        let values = |kind: &str,
+                      names: &[String],
+                      required: &[String],
+                      values: &HashMap<String, String>| {
+            names
+                .iter()
+                .map(|name| match values.get(name) {
+                    Some(value) => Ok(value.clone()),
+                    None if required.contains(name) => Err(ExprError::new_err(format!(
+                        "missing value for {kind} `{name}`"
+                    ))),
+                    None => Ok(String::new()),
+                })
+                .collect::<PyResult<Vec<String>>>()
+        };
+
+        let runtime_env = RuntimeEnv {
+            vars: values("var", self.env.vars(), &requirements.vars, &vars)?,
+            prompts: values(
+                "prompt",
+                self.env.prompts(),
+                &requirements.prompts,
+                &prompts,
+            )?,
+            secrets: values(
+                "secret",
+                self.env.secrets(),
+                &requirements.secrets,
+                &secrets,
+            )?,
+            client_context: values(
+                "client context",
+                self.env.client_context(),
+                &requirements.client_context,
+                &client_context,
+            )?
+            .into_iter()
+            .map(Value::from)
+            .collect(),
+        };
+
+        Vm::new()
+            .interpret(self.bytecode.clone().into(), &self.env, &runtime_env)
+            .map(PyValue)
+            .map_err(|errs| expr_error(&errs, &self.source))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Expr({:?})", self.source)
+    }
+}
+
+/// Compile an expression against the names of the vars, prompts, secrets,
+/// and client context it can reference
+#[pyfunction]
+#[pyo3(signature = (source, vars = vec![], prompts = vec![], secrets = vec![], client_context = vec![]))]
+pub fn compile(
+    source: &str,
+    vars: Vec<String>,
+    prompts: Vec<String>,
+    secrets: Vec<String>,
+    client_context: Vec<String>,
+) -> PyResult<Expr> {
+    let env = CompileTimeEnv::new(vars, prompts, secrets, client_context);
+
+    let bytecode = parse(source)
+        .and_then(|ast| compile_expr(&mut (ast, 0..source.len()), &env))
+        .map_err(|errs| expr_error(&errs, source))?;
+
+    Ok(Expr {
+        source: source.to_string(),
+        env,
+        bytecode,
+    })
+}
+
+/// Compile and evaluate an expression
+///
+/// Names are taken from the keys of the values passed in.
+#[pyfunction]
+#[pyo3(signature = (source, vars = HashMap::new(), prompts = HashMap::new(), secrets = HashMap::new(), client_context = HashMap::new()))]
+pub fn eval(
+    source: &str,
+    vars: HashMap<String, String>,
+    prompts: HashMap<String, String>,
+    secrets: HashMap<String, String>,
+    client_context: HashMap<String, String>,
+) -> PyResult<PyValue> {
+    let names = |values: &HashMap<String, String>| values.keys().cloned().collect();
+
+    compile(
+        source,
+        names(&vars),
+        names(&prompts),
+        names(&secrets),
+        names(&client_context),
+    )?
+    .eval(vars, prompts, secrets, client_context)
+}
+
+fn expr_error(errs: &[ExprErrorS], source: &str) -> PyErr {
+    let diagnostics: Vec<Diagnostic> = errs
+        .iter()
+        .map(|(err, span)| {
+            let diagnostic = err.as_diagnostic(source, span);
+            let range = diagnostic.range;
+
+            Diagnostic {
+                code: diagnostic.code,
+                message: diagnostic.message,
+                start: (range.start.line, range.start.character),
+                end: (range.end.line, range.end.character),
+            }
+        })
+        .collect();
+
+    let message = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.clone())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Python::attach(|py| {
+        let err = ExprError::new_err(message);
+
+        match err.value(py).setattr("diagnostics", diagnostics) {
+            Ok(()) => err,
+            Err(setattr_err) => setattr_err,
+        }
+    })
+}
+
+/// The `reqlang_expr` Python module
+#[pymodule]
+#[pyo3(name = "reqlang_expr")]
+pub fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(eval, m)?)?;
+    m.add_class::<Expr>()?;
+    m.add_class::<PyValue>()?;
+    m.add_class::<Diagnostic>()?;
+    m.add("ExprError", m.py().get_type::<ExprError>())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod python_tests {
+    use super::*;
+
+    use pyo3::types::PyDict;
+
+    fn run(code: &str) -> PyResult<()> {
+        Python::initialize();
+
+        Python::attach(|py| {
+            let module = PyModule::new(py, "reqlang_expr")?;
+            python_module(&module)?;
+
+            let globals = PyDict::new(py);
+            globals.set_item("reqlang_expr", module)?;
+
+            py.run(&std::ffi::CString::new(code)?, Some(&globals), None)
+        })
+    }
+
+    #[test]
+    fn compile_and_eval() {
+        run(r#"
+expr = reqlang_expr.compile("(concat `Bearer ` !token)", secrets=["token"])
+value = expr.eval(secrets={"token": "abc123"})
+
+assert value.value == "Bearer abc123", value
+assert value.type == "String", value.type
+"#)
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_values() {
+        run(r#"
+assert reqlang_expr.eval("(eq :a `b`)", vars={"a": "b"}).value is True
+assert reqlang_expr.eval("(pow 2 3)").value == 8.0
+assert reqlang_expr.eval("trim").type == "Fn(String) -> String"
+assert reqlang_expr.eval("true") == reqlang_expr.eval("(not false)")
+"#)
+        .unwrap();
+    }
+
+    #[test]
+    fn errors_have_diagnostics() {
+        run(r#"
+try:
+    reqlang_expr.compile("(concat :x)")
+    assert False, "should raise"
+except reqlang_expr.ExprError as err:
+    [arity, undefined] = err.diagnostics
+
+    assert arity.message == "expects 2 arguments but received 1", arity
+    assert undefined.message == "undefined: :x", undefined
+    assert (undefined.start, undefined.end) == ((0, 8), (0, 10)), undefined
+"#)
+        .unwrap();
+    }
+
+    #[test]
+    fn missing_values() {
+        run(r#"
+expr = reqlang_expr.compile("!token", secrets=["token", "unused"])
+
+try:
+    expr.eval()
+    assert False, "should raise"
+except reqlang_expr.ExprError as err:
+    assert str(err) == "missing value for secret `token`", err
+
+assert expr.eval(secrets={"token": "a"}).value == "a"
+"#)
+        .unwrap();
+    }
+}
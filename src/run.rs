@@ -0,0 +1,222 @@
+//! Evaluation behind `expr run`, enabled by the `cli` feature
+//!
+//! [`run`] compiles and evaluates an expression, collecting everything a CI
+//! pipeline validating expressions needs in one [`RunResult`]: the value and
+//! its type, its [`Provenance`], diagnostics for any errors, and
+//! [`RunStats`].
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use serde_json::{Value as Json, json};
+
+use crate::{
+    compiler::{ExprByteCode, compile, opcode},
+    errors::ExprErrorS,
+    parser::parse,
+    provenance::Provenance,
+    server::{SessionEnv, diagnostics, value_to_json},
+    value::Value,
+    vm::{Vm, VmConfig},
+};
+
+/// The outcome of compiling and evaluating an expression
+#[derive(Debug, PartialEq)]
+pub struct RunResult {
+    source: String,
+    /// The resulting value, if evaluation succeeded
+    pub value: Option<Value>,
+    /// The inputs that contributed to the value, if evaluation succeeded
+    pub provenance: Option<Provenance>,
+    /// Errors lexing, parsing, compiling, or evaluating
+    pub errs: Vec<ExprErrorS>,
+    /// Stats about evaluation, if the expression compiled
+    pub stats: Option<RunStats>,
+}
+
+/// Stats about evaluating an expression
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunStats {
+    /// Time spent evaluating, not including compilation
+    pub duration: Duration,
+    /// Number of times each op was executed, keyed by op name
+    pub opcodes: BTreeMap<&'static str, usize>,
+}
+
+impl RunStats {
+    /// Total number of ops executed
+    pub fn ops(&self) -> usize {
+        self.opcodes.values().sum()
+    }
+}
+
+/// Compile and evaluate an expression
+pub fn run(source: &str, env: &SessionEnv) -> RunResult {
+    let mut result = RunResult {
+        source: source.to_string(),
+        value: None,
+        provenance: None,
+        errs: vec![],
+        stats: None,
+    };
+
+    let compile_time_env = env.compile_time_env();
+
+    let bytecode = match parse(source)
+        .and_then(|ast| compile(&mut (ast, 0..source.len()), &compile_time_env))
+    {
+        Ok(bytecode) => bytecode,
+        Err(errs) => {
+            result.errs = errs;
+            return result;
+        }
+    };
+
+    let mut vm = Vm::with_config(VmConfig {
+        coverage: true,
+        ..Default::default()
+    });
+
+    let start = Instant::now();
+    let traced = vm.interpret_traced(
+        bytecode.clone().into(),
+        &compile_time_env,
+        &env.runtime_env(),
+    );
+    let duration = start.elapsed();
+
+    result.stats = Some(RunStats {
+        duration,
+        opcodes: executed_opcodes(&bytecode, &vm),
+    });
+
+    match traced {
+        Ok(traced) => {
+            result.provenance = Some(traced.provenance().clone());
+            result.value = Some(traced.into_value());
+        }
+        Err(errs) => result.errs = errs,
+    }
+
+    result
+}
+
+impl RunResult {
+    pub fn is_ok(&self) -> bool {
+        self.errs.is_empty()
+    }
+
+    /// `{ value, type, provenance, diagnostics, stats }`
+    ///
+    /// `value`, `type`, and `provenance` are `null` if evaluation failed.
+    /// `stats` is `null` if the expression didn't compile.
+    pub fn to_json(&self) -> Json {
+        let (value, ty) = match self.value.clone().map(value_to_json) {
+            Some(typed) => (typed["value"].clone(), typed["type"].clone()),
+            None => (Json::Null, Json::Null),
+        };
+
+        json!({
+            "value": value,
+            "type": ty,
+            "provenance": self.provenance.as_ref().map(|provenance| json!({
+                "vars": provenance.vars,
+                "prompts": provenance.prompts,
+                "secrets": provenance.secrets,
+                "client_context": provenance.client_context,
+            })),
+            "diagnostics": diagnostics(&self.errs, &self.source),
+            "stats": self.stats.as_ref().map(|stats| json!({
+                "duration_us": stats.duration.as_micros() as u64,
+                "ops": stats.ops(),
+                "opcodes": stats.opcodes,
+            })),
+        })
+    }
+}
+
+/// Count the ops the VM executed by name
+///
+/// Jumps only go forward so each op is executed at most once.
+fn executed_opcodes(bytecode: &ExprByteCode, vm: &Vm) -> BTreeMap<&'static str, usize> {
+    let mut opcodes = BTreeMap::new();
+
+    if let Some(coverage) = vm.coverage() {
+        for ip in coverage.executed() {
+            let name = opcode::name(bytecode.codes()[*ip]).unwrap_or("UNKNOWN");
+
+            *opcodes.entry(name).or_default() += 1;
+        }
+    }
+
+    opcodes
+}
+
+#[cfg(test)]
+mod run_tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn env() -> SessionEnv {
+        SessionEnv {
+            vars: vec![("greeting".to_string(), "Hello".to_string())],
+            secrets: vec![("name".to_string(), "World".to_string())],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn run_json() {
+        let result = run("(concat :greeting `, ` !name)", &env());
+
+        assert!(result.is_ok());
+
+        let mut json = result.to_json();
+        assert!(json["stats"]["duration_us"].is_u64());
+        json["stats"]["duration_us"] = json!(0);
+
+        assert_eq!(
+            json!({
+                "value": "Hello, World",
+                "type": "String",
+                "provenance": {
+                    "vars": ["greeting"],
+                    "prompts": [],
+                    "secrets": ["name"],
+                    "client_context": [],
+                },
+                "diagnostics": [],
+                "stats": {
+                    "duration_us": 0,
+                    "ops": 5,
+                    "opcodes": { "CALL": 1, "CONSTANT": 1, "GET": 3 },
+                },
+            }),
+            json
+        );
+    }
+
+    #[test]
+    fn run_compile_error() {
+        let json = run("(trim :missing)", &env()).to_json();
+
+        assert_eq!(Json::Null, json["value"]);
+        assert_eq!(Json::Null, json["stats"]);
+        assert_eq!(
+            json!("undefined: :missing"),
+            json["diagnostics"][0]["message"]
+        );
+    }
+
+    #[test]
+    fn run_runtime_error() {
+        let result = run("(not (id `a`))", &env());
+
+        assert!(!result.is_ok());
+        assert_eq!(None, result.value);
+        assert_eq!(Some(5), result.stats.map(|stats| stats.ops()));
+    }
+}
@@ -0,0 +1,822 @@
+//! JSON-RPC server behind `expr serve`, enabled by the `cli` feature
+//!
+//! Requests and responses are [JSON-RPC 2.0](https://www.jsonrpc.org/specification)
+//! objects, one per line. Every method takes an optional `session` name
+//! (default `"default"`). A session keeps the env (names and values of vars,
+//! prompts, secrets, and client context) it was last given so later requests
+//! can leave it out.
+//!
+//! | Method        | Params           | Result                              |
+//! | ------------- | ---------------- | ----------------------------------- |
+//! | `compile`     | `source`, `env?` | `{ "diagnostics": [...] }`          |
+//! | `eval`        | `source`, `env?` | `{ "type": "String", "value": .. }` |
+//! | `disassemble` | `source`, `env?` | `{ "disassembly": "..." }`          |
+//! | `complete`    | `prefix`, `env?` | `["concat", ":greeting", ...]`      |
+//!
+//! `env` is an object of `vars`, `prompts`, `secrets`, and `client_context`,
+//! each an object of names to string values, and `disabled_builtin_groups`,
+//! a list of [`BuiltinGroup`] names (e.g. `["Math"]`) the host has disabled.
+//!
+//! `complete` returns [`Completion`] objects instead of names when `details`
+//! is `true`, with the category and capability of builtins so editors can
+//! group them and grey out those in disabled groups.
+//!
+//! ```json
+//! {"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"source": "(concat :a `!`)", "env": {"vars": {"a": "hi"}}}}
+//! {"jsonrpc": "2.0", "id": 1, "result": {"type": "String", "value": "hi!"}}
+//! ```
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{Map, Value as Json, json};
+
+use crate::{
+    ast::IdentifierKind,
+    builtins::{BuiltinCategory, BuiltinGroup},
+    compiler::{CompileTimeEnv, ExprByteCode, compile},
+    disassembler::Disassembler,
+    errors::{
+        ExprErrorS,
+        diagnostics::{AsDiagnostic, ExprDiagnosticRange},
+    },
+    parser::parse,
+    value::Value,
+    vm::{RuntimeEnv, Vm},
+};
+
+/// Error codes defined by JSON-RPC 2.0
+pub mod error_code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// The expression failed to lex, parse, compile, or evaluate. The error's
+    /// `data` lists its diagnostics.
+    pub const EXPR_ERROR: i64 = -32000;
+}
+
+/// Names and values of the vars, prompts, secrets, and client context in a
+/// session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SessionEnv {
+    pub vars: Vec<(String, String)>,
+    pub prompts: Vec<(String, String)>,
+    pub secrets: Vec<(String, String)>,
+    pub client_context: Vec<(String, String)>,
+    /// Builtin groups the host has disabled
+    pub disabled_builtin_groups: Vec<BuiltinGroup>,
+}
+
+impl SessionEnv {
+    pub fn compile_time_env(&self) -> CompileTimeEnv {
+        let names = |values: &[(String, String)]| values.iter().map(|(k, _)| k.clone()).collect();
+
+        let mut env = CompileTimeEnv::new(
+            names(&self.vars),
+            names(&self.prompts),
+            names(&self.secrets),
+            names(&self.client_context),
+        );
+
+        for group in &self.disabled_builtin_groups {
+            env.disable_builtin_group(*group);
+        }
+
+        env
+    }
+
+    pub fn runtime_env(&self) -> RuntimeEnv {
+        let values = |values: &[(String, String)]| values.iter().map(|(_, v)| v.clone()).collect();
+
+        RuntimeEnv {
+            vars: values(&self.vars),
+            prompts: values(&self.prompts),
+            secrets: values(&self.secrets),
+            client_context: self
+                .client_context
+                .iter()
+                .map(|(_, v)| Value::String(v.clone().into()))
+                .collect(),
+        }
+    }
+
+    fn from_json(env: &Json) -> Result<Self, String> {
+        let env = env
+            .as_object()
+            .ok_or_else(|| "env should be an object".to_string())?;
+
+        let values = |key: &str| -> Result<Vec<(String, String)>, String> {
+            match env.get(key) {
+                None | Some(Json::Null) => Ok(vec![]),
+                Some(Json::Object(values)) => values
+                    .iter()
+                    .map(|(name, value)| match value {
+                        Json::String(value) => Ok((name.clone(), value.clone())),
+                        _ => Err(format!("env.{key}.{name} should be a string")),
+                    })
+                    .collect(),
+                Some(_) => Err(format!("env.{key} should be an object")),
+            }
+        };
+
+        let disabled_builtin_groups = match env.get("disabled_builtin_groups") {
+            None | Some(Json::Null) => vec![],
+            Some(Json::Array(groups)) => groups
+                .iter()
+                .map(|group| {
+                    BuiltinGroup::ALL
+                        .iter()
+                        .find(|g| group.as_str() == Some(&format!("{g:?}")))
+                        .copied()
+                        .ok_or_else(|| format!("unknown builtin group: {group}"))
+                })
+                .collect::<Result<_, _>>()?,
+            Some(_) => return Err("env.disabled_builtin_groups should be an array".to_string()),
+        };
+
+        Ok(Self {
+            vars: values("vars")?,
+            prompts: values("prompts")?,
+            secrets: values("secrets")?,
+            client_context: values("client_context")?,
+            disabled_builtin_groups,
+        })
+    }
+}
+
+/// Handles JSON-RPC requests, keeping an env per session
+#[derive(Debug, Default)]
+pub struct Server {
+    sessions: HashMap<String, SessionEnv>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The env of a session, if it has been used
+    pub fn session(&self, name: &str) -> Option<&SessionEnv> {
+        self.sessions.get(name)
+    }
+
+    /// Handle requests read line by line from `input`, writing a response
+    /// line to `output` for each one that isn't a notification
+    pub fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(response) = self.handle(&line) {
+                writeln!(output, "{response}")?;
+                output.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handle a single request, returning the response
+    ///
+    /// Notifications (requests without an `id`) get no response.
+    pub fn handle(&mut self, request: &str) -> Option<String> {
+        let request: Json = match serde_json::from_str(request) {
+            Ok(request) => request,
+            Err(err) => {
+                return Some(
+                    error_response(Json::Null, error_code::PARSE_ERROR, err.to_string(), None)
+                        .to_string(),
+                );
+            }
+        };
+
+        let id = request.get("id").cloned();
+
+        let method = match request.get("method").and_then(Json::as_str) {
+            Some(method) if request.get("jsonrpc") == Some(&json!("2.0")) => method,
+            _ => {
+                return Some(
+                    error_response(
+                        id.unwrap_or(Json::Null),
+                        error_code::INVALID_REQUEST,
+                        "request should be a JSON-RPC 2.0 object with a method".to_string(),
+                        None,
+                    )
+                    .to_string(),
+                );
+            }
+        };
+
+        let empty = Map::new();
+        let params = match request.get("params") {
+            None | Some(Json::Null) => Ok(&empty),
+            Some(Json::Object(params)) => Ok(params),
+            Some(_) => Err(RpcError::params("params should be an object")),
+        };
+
+        let result = params.and_then(|params| self.call(method, params));
+
+        let id = id?;
+
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(RpcError {
+                code,
+                message,
+                data,
+            }) => error_response(id, code, message, data),
+        };
+
+        Some(response.to_string())
+    }
+
+    fn call(&mut self, method: &str, params: &Map<String, Json>) -> Result<Json, RpcError> {
+        match method {
+            "compile" | "eval" | "disassemble" | "complete" => {}
+            _ => {
+                return Err(RpcError {
+                    code: error_code::METHOD_NOT_FOUND,
+                    message: format!("unknown method: {method}"),
+                    data: None,
+                });
+            }
+        }
+
+        let session = match params.get("session") {
+            None => "default",
+            Some(Json::String(session)) => session.as_str(),
+            Some(_) => return Err(RpcError::params("session should be a string")),
+        };
+
+        let env = self.sessions.entry(session.to_string()).or_default();
+
+        if let Some(new_env) = params.get("env") {
+            *env = SessionEnv::from_json(new_env).map_err(RpcError::params)?;
+        }
+
+        let env = env.clone();
+
+        match method {
+            "compile" => {
+                let source = string_param(params, "source")?;
+
+                let diagnostics = match compile_source(source, &env.compile_time_env()) {
+                    Ok(_) => vec![],
+                    Err(errs) => diagnostics(&errs, source),
+                };
+
+                Ok(json!({ "diagnostics": diagnostics }))
+            }
+            "eval" => {
+                let source = string_param(params, "source")?;
+                let compile_time_env = env.compile_time_env();
+
+                let value = compile_source(source, &compile_time_env)
+                    .and_then(|bytecode| {
+                        Vm::new().interpret(bytecode.into(), &compile_time_env, &env.runtime_env())
+                    })
+                    .map_err(|errs| RpcError::expr(&errs, source))?;
+
+                Ok(value_to_json(value))
+            }
+            "disassemble" => {
+                let source = string_param(params, "source")?;
+                let compile_time_env = env.compile_time_env();
+
+                let bytecode = compile_source(source, &compile_time_env)
+                    .map_err(|errs| RpcError::expr(&errs, source))?;
+
+                let disassembly = Disassembler::new(&bytecode, &compile_time_env)
+                    .disassemble()
+                    .map_err(|errs| RpcError::expr(&errs, source))?;
+
+                Ok(json!({ "disassembly": disassembly }))
+            }
+            _ => {
+                let prefix = string_param(params, "prefix")?;
+
+                match params.get("details") {
+                    None | Some(Json::Bool(false)) => Ok(json!(completions(&env, prefix))),
+                    Some(Json::Bool(true)) => Ok(json!(
+                        detailed_completions(&env, prefix)
+                            .iter()
+                            .map(Completion::to_json)
+                            .collect::<Vec<_>>()
+                    )),
+                    Some(_) => Err(RpcError::params("details should be a bool")),
+                }
+            }
+        }
+    }
+}
+
+/// Builtin names and sigiled env names starting with `prefix`
+pub fn completions(env: &SessionEnv, prefix: &str) -> Vec<String> {
+    env.compile_time_env()
+        .identifiers()
+        .into_iter()
+        .map(|(identifier, _, _)| identifier)
+        .filter(|completion| completion.starts_with(prefix))
+        .collect()
+}
+
+/// A completion with what editors need to label, group, and grey it out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: IdentifierKind,
+    /// Signature of a builtin or type of an env value
+    pub detail: String,
+    /// Category of a builtin this crate defines
+    pub category: Option<BuiltinCategory>,
+    /// Group a builtin this crate defines can be disabled with
+    pub capability: Option<BuiltinGroup>,
+    /// `false` for builtins in groups the host has disabled
+    pub enabled: bool,
+}
+
+impl Completion {
+    /// `{ label, kind, detail, category, capability, enabled }`
+    pub fn to_json(&self) -> Json {
+        let kind = match self.kind {
+            IdentifierKind::Builtin => "builtin",
+            IdentifierKind::Var => "var",
+            IdentifierKind::Prompt => "prompt",
+            IdentifierKind::Secret => "secret",
+            IdentifierKind::Client => "client_context",
+            IdentifierKind::Type => "type",
+        };
+
+        json!({
+            "label": self.label,
+            "kind": kind,
+            "detail": self.detail,
+            "category": self.category.map(|category| category.name()),
+            "capability": self.capability.map(|group| format!("{group:?}")),
+            "enabled": self.enabled,
+        })
+    }
+}
+
+/// [`completions`] with details, followed by the builtins in disabled groups
+pub fn detailed_completions(env: &SessionEnv, prefix: &str) -> Vec<Completion> {
+    let compile_time_env = env.compile_time_env();
+
+    let enabled = compile_time_env
+        .identifiers()
+        .into_iter()
+        .map(|(label, kind, ty)| {
+            let builtin = compile_time_env
+                .get_builtin_index(&label)
+                .or_else(|| compile_time_env.get_user_builtin_index(&label))
+                .filter(|_| kind == IdentifierKind::Builtin)
+                .map(|(builtin, _)| builtin);
+
+            Completion {
+                detail: builtin.map_or_else(|| ty.to_string(), ToString::to_string),
+                category: builtin.map(|builtin| builtin.category),
+                capability: builtin.and_then(|builtin| builtin.capability),
+                label,
+                kind,
+                enabled: true,
+            }
+        });
+
+    let disabled = env
+        .disabled_builtin_groups
+        .iter()
+        .flat_map(BuiltinGroup::builtins)
+        .map(|builtin| Completion {
+            label: builtin.name.to_string(),
+            kind: IdentifierKind::Builtin,
+            detail: builtin.to_string(),
+            category: Some(builtin.category),
+            capability: builtin.capability,
+            enabled: false,
+        });
+
+    enabled
+        .chain(disabled)
+        .filter(|completion| completion.label.starts_with(prefix))
+        .collect()
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Json>,
+}
+
+impl RpcError {
+    fn params(message: impl Into<String>) -> Self {
+        Self {
+            code: error_code::INVALID_PARAMS,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn expr(errs: &[ExprErrorS], source: &str) -> Self {
+        let diagnostics = diagnostics(errs, source);
+
+        let message = diagnostics
+            .iter()
+            .filter_map(|diagnostic| diagnostic["message"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Self {
+            code: error_code::EXPR_ERROR,
+            message,
+            data: Some(json!({ "diagnostics": diagnostics })),
+        }
+    }
+}
+
+fn error_response(id: Json, code: i64, message: String, data: Option<Json>) -> Json {
+    let mut error = json!({ "code": code, "message": message });
+
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+
+    json!({ "jsonrpc": "2.0", "id": id, "error": error })
+}
+
+fn string_param<'a>(params: &'a Map<String, Json>, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Json::as_str)
+        .ok_or_else(|| RpcError::params(format!("{name} should be a string")))
+}
+
+fn compile_source(source: &str, env: &CompileTimeEnv) -> Result<ExprByteCode, Vec<ExprErrorS>> {
+    let ast = parse(source)?;
+
+    compile(&mut (ast, 0..source.len()), env)
+}
+
+pub(crate) fn diagnostics(errs: &[ExprErrorS], source: &str) -> Vec<Json> {
+    errs.iter()
+        .map(|(err, span)| {
+            let diagnostic = err.as_diagnostic(source, span);
+
+            let mut json = json!({
+                "code": diagnostic.code,
+                "message": diagnostic.message,
+                "range": range_to_json(&diagnostic.range),
+            });
+
+            if let Some(fix) = diagnostic.fix {
+                json["fix"] = json!({
+                    "range": range_to_json(&fix.range),
+                    "replacement": fix.replacement,
+                });
+            }
+
+            json
+        })
+        .collect()
+}
+
+fn range_to_json(range: &ExprDiagnosticRange) -> Json {
+    json!({
+        "start": { "line": range.start.line, "character": range.start.character },
+        "end": { "line": range.end.line, "character": range.end.character },
+    })
+}
+
+/// Strings, numbers, and bools as JSON values. Other values in their source
+/// form.
+pub(crate) fn value_to_json(value: Value) -> Json {
+    let ty = value.get_type().to_string();
+
+    let value = match value {
+        Value::String(string) => json!(string),
+        Value::Number(number) => json!(number),
+        Value::Bool(bool) => json!(bool),
+        value => json!(value.to_string()),
+    };
+
+    json!({ "type": ty, "value": value })
+}
+
+#[cfg(test)]
+mod server_tests {
+    use super::*;
+    use crate::builtins::BuiltinFn;
+
+    fn request(server: &mut Server, request: Json) -> Json {
+        serde_json::from_str(&server.handle(&request.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn eval_keeps_session_env() {
+        let mut server = Server::new();
+
+        let response = request(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "eval",
+                "params": {
+                    "source": "(concat :greeting ` ` !name)",
+                    "env": { "vars": { "greeting": "Hello" }, "secrets": { "name": "World" } }
+                }
+            }),
+        );
+
+        assert_eq!(
+            json!({ "jsonrpc": "2.0", "id": 1, "result": { "type": "String", "value": "Hello World" } }),
+            response
+        );
+
+        let response = request(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "eval", "params": { "source": "(eq !name `World`)" } }),
+        );
+
+        assert_eq!(json!({ "type": "Bool", "value": true }), response["result"]);
+    }
+
+    #[test]
+    fn sessions_are_separate() {
+        let mut server = Server::new();
+
+        request(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "compile",
+                "params": { "session": "a", "source": ":x", "env": { "vars": { "x": "1" } } }
+            }),
+        );
+
+        let response = request(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "compile", "params": { "session": "b", "source": ":x" } }),
+        );
+
+        assert_eq!(
+            json!({
+                "diagnostics": [{
+                    "code": "compiler",
+                    "message": "undefined: :x",
+                    "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 2 } }
+                }]
+            }),
+            response["result"]
+        );
+
+        assert_eq!(
+            Some(&SessionEnv {
+                vars: vec![("x".to_string(), "1".to_string())],
+                ..Default::default()
+            }),
+            server.session("a")
+        );
+    }
+
+    #[test]
+    fn compile_diagnostics_have_fixes() {
+        let mut server = Server::new();
+
+        let response = request(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "compile", "params": { "source": "x", "env": { "vars": { "x": "1" } } } }),
+        );
+
+        assert_eq!(
+            json!({
+                "diagnostics": [{
+                    "code": "compiler",
+                    "message": "undefined: x, did you mean `:x`? vars use the `:` sigil",
+                    "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 1 } },
+                    "fix": {
+                        "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 1 } },
+                        "replacement": ":x"
+                    }
+                }]
+            }),
+            response["result"]
+        );
+    }
+
+    #[test]
+    fn eval_error_has_diagnostics() {
+        let mut server = Server::new();
+
+        let response = request(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "eval", "params": { "source": "(concat `a`)" } }),
+        );
+
+        assert_eq!(json!(error_code::EXPR_ERROR), response["error"]["code"]);
+        assert_eq!(
+            json!("expects 2 arguments but received 1"),
+            response["error"]["message"]
+        );
+        assert_eq!(
+            json!("expects 2 arguments but received 1"),
+            response["error"]["data"]["diagnostics"][0]["message"]
+        );
+    }
+
+    #[test]
+    fn disassemble() {
+        let mut server = Server::new();
+
+        let response = request(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "disassemble", "params": { "source": "true" } }),
+        );
+
+        assert!(
+            response["result"]["disassembly"]
+                .as_str()
+                .unwrap()
+                .ends_with("0000 TRUE\n")
+        );
+    }
+
+    #[test]
+    fn complete() {
+        let mut server = Server::new();
+
+        let response = request(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "complete",
+                "params": { "prefix": "con", "env": { "vars": { "conn": "" } } }
+            }),
+        );
+
+        assert_eq!(json!(["cond", "concat", "contains"]), response["result"]);
+
+        let response = request(
+            &mut server,
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "complete", "params": { "prefix": ":" } }),
+        );
+
+        assert_eq!(json!([":conn"]), response["result"]);
+    }
+
+    #[test]
+    fn complete_with_details() {
+        let mut server = Server::new();
+
+        let response = request(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "complete",
+                "params": {
+                    "prefix": "f",
+                    "details": true,
+                    "env": { "disabled_builtin_groups": ["Math"] }
+                }
+            }),
+        );
+
+        let floor = response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|completion| completion["label"] == "floor")
+            .unwrap();
+
+        assert_eq!(
+            &json!({
+                "label": "floor",
+                "kind": "builtin",
+                "detail": BuiltinFn::FLOOR.to_string(),
+                "category": "Math",
+                "capability": "Math",
+                "enabled": false
+            }),
+            floor
+        );
+
+        let response = request(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 2,
+                "method": "complete",
+                "params": { "prefix": ":", "details": true, "env": { "vars": { "conn": "" } } }
+            }),
+        );
+
+        assert_eq!(
+            json!([{
+                "label": ":conn",
+                "kind": "var",
+                "detail": "String",
+                "category": null,
+                "capability": null,
+                "enabled": true
+            }]),
+            response["result"]
+        );
+    }
+
+    #[test]
+    fn unknown_builtin_group() {
+        let mut server = Server::new();
+
+        let response = request(
+            &mut server,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "complete",
+                "params": { "prefix": "", "env": { "disabled_builtin_groups": ["Nope"] } }
+            }),
+        );
+
+        assert_eq!(json!(-32602), response["error"]["code"]);
+    }
+
+    #[test]
+    fn notifications_have_no_response() {
+        let mut server = Server::new();
+
+        assert_eq!(
+            None,
+            server.handle(r#"{"jsonrpc": "2.0", "method": "eval", "params": {"source": "true"}}"#)
+        );
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let mut server = Server::new();
+
+        let code = |server: &mut Server, request: &str| {
+            let response: Json = serde_json::from_str(&server.handle(request).unwrap()).unwrap();
+
+            response["error"]["code"].as_i64().unwrap()
+        };
+
+        assert_eq!(error_code::PARSE_ERROR, code(&mut server, "{"));
+        assert_eq!(
+            error_code::INVALID_REQUEST,
+            code(&mut server, r#"{"id": 1, "method": "eval"}"#)
+        );
+        assert_eq!(
+            error_code::METHOD_NOT_FOUND,
+            code(
+                &mut server,
+                r#"{"jsonrpc": "2.0", "id": 1, "method": "run"}"#
+            )
+        );
+        assert_eq!(
+            error_code::INVALID_PARAMS,
+            code(
+                &mut server,
+                r#"{"jsonrpc": "2.0", "id": 1, "method": "eval"}"#
+            )
+        );
+        assert_eq!(
+            error_code::INVALID_PARAMS,
+            code(
+                &mut server,
+                r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"source": "", "env": {"vars": {"a": 1}}}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn serve_lines() {
+        let mut server = Server::new();
+        let input = concat!(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "eval", "params": {"source": "true"}}"#,
+            "\n\n",
+            r#"{"jsonrpc": "2.0", "id": 2, "method": "eval", "params": {"source": "false"}}"#,
+            "\n"
+        );
+        let mut output = vec![];
+
+        server.serve(input.as_bytes(), &mut output).unwrap();
+
+        let responses: Vec<Json> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(2, responses.len());
+        assert_eq!(json!(true), responses[0]["result"]["value"]);
+        assert_eq!(json!(false), responses[1]["result"]["value"]);
+    }
+}
@@ -0,0 +1,299 @@
+//! Snapshots of a [`Vm`](crate::vm::Vm) suspended waiting on a prompt or
+//! secret
+//!
+//! [`Vm::interpret_suspendable`](crate::vm::Vm::interpret_suspendable)
+//! suspends instead of reading a prompt or secret the runtime env doesn't have
+//! a value for. The [`VmSnapshot`] it returns can be saved with
+//! [`VmSnapshot::to_bytes`], loaded later (even in another process) with
+//! [`VmSnapshot::from_bytes`], and resumed with
+//! [`Vm::resume`](crate::vm::Vm::resume) once the value is available.
+//!
+//! Snapshots use the [binary format](crate::binary): [`SNAPSHOT_MAGIC`], the
+//! version, the `u32` instruction pointer, the pending value's lookup type
+//! byte, `u32` index, and name, then a `u32` count of stack values followed by
+//! each value, a `u32` count of `let` bindings followed by each value, and a
+//! `u32` count of `try` handlers followed by each one's `u32` fallback offset
+//! and `u32` stack length.
+
+use crate::{
+    binary::{Reader, write_header, write_len, write_string, write_value},
+    compiler::{
+        CompileTimeEnv,
+        lookup::{PROMPT, SECRET},
+    },
+    errors::BytecodeError,
+    value::Value,
+    vm::ErrorHandler,
+};
+
+/// The first bytes of every serialized [`VmSnapshot`]
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"RQXS";
+
+/// A prompt or secret a suspended VM is waiting on
+#[derive(Debug, Clone, PartialEq)]
+pub enum PendingValue {
+    Prompt { index: usize, name: String },
+    Secret { index: usize, name: String },
+}
+
+impl PendingValue {
+    /// Index of the prompt or secret in the runtime env
+    pub fn index(&self) -> usize {
+        match self {
+            PendingValue::Prompt { index, .. } | PendingValue::Secret { index, .. } => *index,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            PendingValue::Prompt { name, .. } | PendingValue::Secret { name, .. } => name,
+        }
+    }
+
+    /// The [`crate::compiler::lookup`] type of the pending value
+    pub fn lookup(&self) -> u8 {
+        match self {
+            PendingValue::Prompt { .. } => PROMPT,
+            PendingValue::Secret { .. } => SECRET,
+        }
+    }
+}
+
+/// State of a VM suspended before reading a [`PendingValue`]
+///
+/// Calls in progress are captured by the stack: the function being called
+/// and the arguments evaluated so far. `let`s in progress are captured by
+/// the locals and `try`s in progress by their error handlers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmSnapshot {
+    pub(crate) ip: usize,
+    pub(crate) stack: Vec<Value>,
+    pub(crate) locals: Vec<Value>,
+    pub(crate) handlers: Vec<ErrorHandler>,
+    pub(crate) pending: PendingValue,
+}
+
+impl VmSnapshot {
+    /// Index of the `GET` op reading the pending value
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn stack(&self) -> &[Value] {
+        &self.stack
+    }
+
+    /// Values bound by the `let`s the VM was in, indexed by slot
+    pub fn locals(&self) -> &[Value] {
+        &self.locals
+    }
+
+    pub fn pending(&self) -> &PendingValue {
+        &self.pending
+    }
+
+    /// Serialize to the [binary format](self)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+
+        write_header(&mut bytes, SNAPSHOT_MAGIC);
+        write_len(&mut bytes, self.ip);
+
+        bytes.push(self.pending.lookup());
+        write_len(&mut bytes, self.pending.index());
+        write_string(&mut bytes, self.pending.name());
+
+        write_len(&mut bytes, self.stack.len());
+
+        for value in &self.stack {
+            write_value(&mut bytes, value);
+        }
+
+        write_len(&mut bytes, self.locals.len());
+
+        for value in &self.locals {
+            write_value(&mut bytes, value);
+        }
+
+        write_len(&mut bytes, self.handlers.len());
+
+        for handler in &self.handlers {
+            write_len(&mut bytes, handler.fallback_ip);
+            write_len(&mut bytes, handler.stack_len);
+        }
+
+        bytes
+    }
+
+    /// Deserialize from the [binary format](self)
+    ///
+    /// Functions on the stack are looked up by name in `env`.
+    pub fn from_bytes(bytes: &[u8], env: &CompileTimeEnv) -> Result<Self, BytecodeError> {
+        let mut reader = Reader::new(bytes);
+
+        reader.header(SNAPSHOT_MAGIC)?;
+
+        let (_, ip) = reader.len()?;
+
+        let lookup_offset = reader.offset;
+        let lookup = reader.u8()?;
+        let (_, index) = reader.len()?;
+        let name = reader.string()?;
+
+        let pending = match lookup {
+            PROMPT => PendingValue::Prompt { index, name },
+            SECRET => PendingValue::Secret { index, name },
+            tag => {
+                return Err(BytecodeError::InvalidTag {
+                    offset: lookup_offset,
+                    tag,
+                });
+            }
+        };
+
+        let (_, count) = reader.len()?;
+
+        let stack = (0..count)
+            .map(|_| reader.value(env))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (_, count) = reader.len()?;
+
+        let locals = (0..count)
+            .map(|_| reader.value(env))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (_, count) = reader.len()?;
+
+        let handlers = (0..count)
+            .map(|_| {
+                let (_, fallback_ip) = reader.len()?;
+                let (_, stack_len) = reader.len()?;
+
+                Ok(ErrorHandler {
+                    fallback_ip,
+                    stack_len,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        reader.finish()?;
+
+        Ok(Self {
+            ip,
+            stack,
+            locals,
+            handlers,
+            pending,
+        })
+    }
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use super::*;
+
+    use crate::{
+        builtins::{BuiltinCategory, BuiltinFn, FnNamespace},
+        types::Type,
+        value::Closure,
+    };
+
+    fn snapshot() -> VmSnapshot {
+        VmSnapshot {
+            ip: 9,
+            stack: vec![
+                Value::Fn(BuiltinFn::CONCAT.into()),
+                Value::String("a".into()),
+                Value::Number(1.5),
+                Value::Bool(true),
+                Value::Type(Type::Type(Type::String.into()).into()),
+                Value::Closure(
+                    Closure::Partial {
+                        f: Value::Fn(BuiltinFn::CONCAT.into()),
+                        arg: Value::String("b".into()),
+                    }
+                    .into(),
+                ),
+            ],
+            locals: vec![Value::String("c".into())],
+            handlers: vec![ErrorHandler {
+                fallback_ip: 14,
+                stack_len: 2,
+            }],
+            pending: PendingValue::Secret {
+                index: 1,
+                name: "token".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let snapshot = snapshot();
+
+        assert_eq!(
+            Ok(snapshot.clone()),
+            VmSnapshot::from_bytes(&snapshot.to_bytes(), &CompileTimeEnv::default())
+        );
+    }
+
+    #[test]
+    fn roundtrip_user_fn_with_builtin_name() {
+        let user_id = BuiltinFn {
+            func: |_| Ok(Value::Bool(true)),
+            ..BuiltinFn::ID
+        };
+
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(user_id.clone());
+
+        let mut snapshot = snapshot();
+        snapshot.stack = vec![
+            Value::Fn(BuiltinFn::ID.into()),
+            Value::Fn(env.get_user_builtin(0).unwrap().clone().into()),
+        ];
+
+        assert_eq!(
+            Ok(snapshot.clone()),
+            VmSnapshot::from_bytes(&snapshot.to_bytes(), &env)
+        );
+    }
+
+    #[test]
+    fn undefined_fn() {
+        let mut snapshot = snapshot();
+        snapshot.stack = vec![Value::Fn(
+            BuiltinFn {
+                name: "missing",
+                args: &[],
+                return_type: Type::Value,
+                func: |_| Ok(Value::Bool(true)),
+                namespace: FnNamespace::User,
+                pure: false,
+                category: BuiltinCategory::Value,
+                capability: None,
+            }
+            .into(),
+        )];
+
+        assert!(matches!(
+            VmSnapshot::from_bytes(&snapshot.to_bytes(), &CompileTimeEnv::default()),
+            Err(BytecodeError::UndefinedFn { name, .. }) if name == "missing"
+        ));
+    }
+
+    #[test]
+    fn not_a_snapshot() {
+        let bytecode = crate::compiler::ExprByteCode::new(
+            crate::compiler::get_version_bytes().to_vec(),
+            vec![],
+            vec![],
+        );
+
+        assert_eq!(
+            Err(BytecodeError::InvalidMagic),
+            VmSnapshot::from_bytes(&bytecode.to_bytes(), &CompileTimeEnv::default())
+        );
+    }
+}
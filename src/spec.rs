@@ -0,0 +1,526 @@
+//! Golden file specification harness
+//!
+//! A spec is an `.expr` source file with golden files next to it holding the
+//! expected output of each stage:
+//!
+//! - `.expr.tokens`: The tokens from the lexer
+//! - `.expr.disassembled`: The disassembled bytecode or errors
+//! - `.expr.interpreted`: The interpreted value, as expression source (see
+//!   [`Value::to_expr_source`]), or errors
+//!
+//! A spec can contain any number of expressions. Each is compiled and
+//! interpreted independently and its output is on its own line(s) of the
+//! golden file, in the same order as the spec.
+//!
+//! Golden files are optional. The first line of a `.expr.disassembled` or
+//! `.expr.interpreted` file can declare the environment the spec is compiled
+//! and interpreted with:
+//!
+//! ```text
+//! //--vars a=1 b=2 --prompts c=3 --secrets d=4 --client-context e=5
+//! ```
+
+use std::{
+    fmt::Display,
+    fs::read_to_string,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    builtins::BuiltinFn,
+    cliutil::{parse_key_val, unzip_key_values},
+    compiler::{CompileTimeEnv, ExprByteCode, compile_many},
+    disassembler::Disassembler,
+    errors::ExprResult,
+    lexer::lex,
+    parser::parse_many,
+    value::Value,
+    vm::{RuntimeEnv, Vm},
+};
+
+/// The stage output a golden file holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecOutput {
+    Tokens,
+    Disassembled,
+    Interpreted,
+}
+
+impl SpecOutput {
+    pub const ALL: [SpecOutput; 3] = [
+        SpecOutput::Tokens,
+        SpecOutput::Disassembled,
+        SpecOutput::Interpreted,
+    ];
+
+    /// Extension of the golden file, replacing the spec's `expr` extension
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SpecOutput::Tokens => "expr.tokens",
+            SpecOutput::Disassembled => "expr.disassembled",
+            SpecOutput::Interpreted => "expr.interpreted",
+        }
+    }
+}
+
+/// A golden file that didn't match the actual output
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecFailure {
+    /// Path to the golden file
+    pub path: PathBuf,
+    pub output: SpecOutput,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Display for SpecFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} does not match\n--- expected\n{}\n--- actual\n{}",
+            self.path.display(),
+            self.expected,
+            self.actual
+        )
+    }
+}
+
+/// Runs specs against the default builtins and any user builtins
+#[derive(Default)]
+pub struct SpecRunner {
+    user_builtins: Vec<BuiltinFn<'static>>,
+}
+
+impl SpecRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make user builtins available to every spec
+    pub fn with_user_builtins(mut self, builtins: Vec<BuiltinFn<'static>>) -> Self {
+        self.user_builtins.extend(builtins);
+        self
+    }
+
+    /// Check every `.expr` spec in a directory and its sub directories
+    pub fn check_dir(&self, path: impl AsRef<Path>) -> io::Result<Vec<SpecFailure>> {
+        let mut failures = vec![];
+
+        for spec_path in spec_paths(path.as_ref())? {
+            failures.extend(self.check_file(spec_path)?);
+        }
+
+        Ok(failures)
+    }
+
+    /// Check every golden file for an `.expr` spec
+    pub fn check_file(&self, path: impl AsRef<Path>) -> io::Result<Vec<SpecFailure>> {
+        let mut failures = vec![];
+
+        for output in SpecOutput::ALL {
+            failures.extend(self.check(path.as_ref(), output)?);
+        }
+
+        Ok(failures)
+    }
+
+    /// Check one golden file for an `.expr` spec, if it exists
+    pub fn check(&self, path: &Path, output: SpecOutput) -> io::Result<Option<SpecFailure>> {
+        let expected_path = path.with_extension(output.extension());
+
+        if !expected_path.exists() {
+            return Ok(None);
+        }
+
+        let source = read_to_string(path)?;
+        let expected = read_to_string(&expected_path)?;
+
+        let (args, expected) = split_args(&expected);
+
+        let args = SpecArgs::parse(args).map_err(|message| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {message}", expected_path.display()),
+            )
+        })?;
+
+        let actual = match output {
+            SpecOutput::Tokens => format!("{:#?}", lex(&source)),
+            SpecOutput::Disassembled => self.disassemble(&source, args),
+            SpecOutput::Interpreted => self.interpret(&source, args),
+        };
+
+        if actual == expected {
+            return Ok(None);
+        }
+
+        Ok(Some(SpecFailure {
+            path: expected_path,
+            output,
+            expected: expected.to_string(),
+            actual,
+        }))
+    }
+
+    /// Panic if any spec in a directory fails
+    pub fn assert_dir(&self, path: impl AsRef<Path>) {
+        let failures = self
+            .check_dir(path)
+            .expect("should be able to read spec files");
+
+        assert_no_failures(&failures);
+    }
+
+    /// Panic if any golden file for an `.expr` spec doesn't match
+    pub fn assert_file(&self, path: impl AsRef<Path>) {
+        let failures = self
+            .check_file(path)
+            .expect("should be able to read spec files");
+
+        assert_no_failures(&failures);
+    }
+
+    fn env(&self, args: &SpecArgs) -> CompileTimeEnv {
+        let key = |key_values: &[(String, String)]| {
+            key_values.iter().map(|(key, _)| key.clone()).collect()
+        };
+
+        let mut env = CompileTimeEnv::new(
+            key(&args.vars),
+            key(&args.prompts),
+            key(&args.secrets),
+            key(&args.client_context),
+        );
+
+        env.add_user_builtins(self.user_builtins.clone());
+
+        env
+    }
+
+    /// Compile each expression in a spec
+    fn compile(
+        &self,
+        source: &str,
+        env: &CompileTimeEnv,
+    ) -> ExprResult<Vec<ExprResult<ExprByteCode>>> {
+        let mut exprs = parse_many(source)?;
+
+        Ok(compile_many(&mut exprs, env))
+    }
+
+    fn disassemble(&self, source: &str, args: SpecArgs) -> String {
+        let env = self.env(&args);
+
+        let bytecodes = match self.compile(source, &env) {
+            Ok(bytecodes) => bytecodes,
+            Err(err) => return format!("{err:#?}"),
+        };
+
+        let outputs: Vec<String> = bytecodes
+            .into_iter()
+            .map(|bytecode| {
+                match bytecode.and_then(|bytecode| Disassembler::new(&bytecode, &env).disassemble())
+                {
+                    Ok(disassembly) => disassembly,
+                    Err(err) => format!("{err:#?}"),
+                }
+            })
+            .collect();
+
+        outputs.join("\n")
+    }
+
+    fn interpret(&self, source: &str, args: SpecArgs) -> String {
+        let env = self.env(&args);
+
+        let (_, var_values) = unzip_key_values(args.vars);
+        let (_, prompt_values) = unzip_key_values(args.prompts);
+        let (_, secret_values) = unzip_key_values(args.secrets);
+        let (_, client_context_values) = unzip_key_values(args.client_context);
+
+        let runtime_env = RuntimeEnv {
+            vars: var_values,
+            prompts: prompt_values,
+            secrets: secret_values,
+            client_context: client_context_values.into_iter().map(Value::from).collect(),
+        };
+
+        let bytecodes = match self.compile(source, &env) {
+            Ok(bytecodes) => bytecodes,
+            Err(err) => return format!("{err:#?}"),
+        };
+
+        let outputs: Vec<String> = Vm::new()
+            .eval_many(bytecodes, &env, &runtime_env)
+            .into_iter()
+            .map(|value| match value {
+                Ok(value) => value.to_expr_source().unwrap_or_else(|| value.to_string()),
+                Err(err) => format!("{err:#?}"),
+            })
+            .collect();
+
+        outputs.join("\n")
+    }
+}
+
+/// Panic if any spec in a directory fails
+///
+/// Downstream crates can run their own spec directories from a test:
+///
+/// ```
+/// reqlang_expr::spec::run_spec_dir("spec");
+/// ```
+///
+/// Use [`SpecRunner::with_user_builtins`] for specs that call user builtins.
+pub fn run_spec_dir(path: impl AsRef<Path>) {
+    SpecRunner::new().assert_dir(path);
+}
+
+/// Panic if any golden file for an `.expr` spec doesn't match
+pub fn run_spec_file(path: impl AsRef<Path>) {
+    SpecRunner::new().assert_file(path);
+}
+
+fn assert_no_failures(failures: &[SpecFailure]) {
+    if !failures.is_empty() {
+        let failures: Vec<String> = failures.iter().map(|failure| failure.to_string()).collect();
+
+        panic!(
+            "{} spec(s) failed\n\n{}",
+            failures.len(),
+            failures.join("\n\n")
+        );
+    }
+}
+
+/// Paths to `.expr` files in a directory and its sub directories, sorted
+fn spec_paths(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            paths.extend(spec_paths(&path)?);
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "expr")
+        {
+            paths.push(path);
+        }
+    }
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Split a golden file in to its `//` args line and expected output
+fn split_args(golden: &str) -> (&str, &str) {
+    match golden.lines().next() {
+        Some(line) if line.starts_with("//") => {
+            let (args, expected) = golden.split_at((line.len() + 1).min(golden.len()));
+
+            (args[2..].trim_end(), expected)
+        }
+        _ => ("", golden),
+    }
+}
+
+/// Environment keys and values declared by a golden file
+#[derive(Debug, Default, PartialEq)]
+struct SpecArgs {
+    vars: Vec<(String, String)>,
+    prompts: Vec<(String, String)>,
+    secrets: Vec<(String, String)>,
+    client_context: Vec<(String, String)>,
+}
+
+impl SpecArgs {
+    fn parse(args: &str) -> Result<Self, String> {
+        let mut spec_args = Self::default();
+        let mut flag = None;
+
+        for arg in args.split_whitespace() {
+            if let Some(name) = arg.strip_prefix("--") {
+                flag = Some(name);
+
+                continue;
+            }
+
+            let key_values = match flag {
+                Some("vars") => &mut spec_args.vars,
+                Some("prompts") => &mut spec_args.prompts,
+                Some("secrets") => &mut spec_args.secrets,
+                Some("client-context") => &mut spec_args.client_context,
+                Some(name) => return Err(format!("unknown argument: --{name}")),
+                None => return Err(format!("expected an argument before {arg}")),
+            };
+
+            key_values.push(parse_key_val::<String, String>(arg).map_err(|e| e.to_string())?);
+        }
+
+        Ok(spec_args)
+    }
+}
+
+#[cfg(test)]
+mod spec_tests {
+    use std::fs::{create_dir_all, write};
+
+    use super::*;
+
+    use crate::{
+        builtins::{BuiltinCategory, FnArg, FnNamespace},
+        errors::ExprResult,
+        types::Type,
+    };
+
+    /// Write spec files to a fresh temporary directory
+    fn spec_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("reqlang-expr-spec-{name}"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        for (file, contents) in files {
+            let path = dir.join(file);
+
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, contents).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(
+            Ok(SpecArgs {
+                vars: vec![
+                    ("a".to_string(), "1".to_string()),
+                    ("b".to_string(), "2".to_string())
+                ],
+                client_context: vec![("c".to_string(), "3".to_string())],
+                ..Default::default()
+            }),
+            SpecArgs::parse("--vars a=1 b=2 --client-context c=3")
+        );
+
+        assert!(SpecArgs::parse("a=1").is_err());
+        assert!(SpecArgs::parse("--unknown a=1").is_err());
+        assert!(SpecArgs::parse("--vars a").is_err());
+    }
+
+    #[test]
+    fn split_args_line() {
+        assert_eq!(("--vars a=1", "`1`"), split_args("//--vars a=1\n`1`"));
+        assert_eq!(("", "`1`"), split_args("`1`"));
+    }
+
+    #[test]
+    fn passing_specs() {
+        let dir = spec_dir(
+            "passing",
+            &[
+                ("a.expr", "(concat :a `b`)"),
+                ("a.expr.interpreted", "//--vars a=a\n`ab`"),
+                ("nested/b.expr", "true"),
+                ("nested/b.expr.interpreted", "true"),
+                ("nested/c.expr", "true"),
+            ],
+        );
+
+        assert_eq!(
+            Vec::<SpecFailure>::new(),
+            SpecRunner::new().check_dir(&dir).unwrap()
+        );
+
+        run_spec_dir(&dir);
+    }
+
+    #[test]
+    fn many_expressions() {
+        let dir = spec_dir(
+            "many",
+            &[
+                ("a.expr", "(not true)\n(missing)\ntrue\n"),
+                ("a.expr.interpreted", "false\nmissing\ntrue"),
+            ],
+        );
+
+        let failures = SpecRunner::new().check_dir(&dir).unwrap();
+
+        assert_eq!(
+            "false\n[\n    (\n        CompileError(\n            Undefined(\n                \"missing\",\n            ),\n        ),\n        12..19,\n    ),\n]\ntrue",
+            failures[0].actual
+        );
+    }
+
+    #[test]
+    fn failing_specs() {
+        let dir = spec_dir(
+            "failing",
+            &[
+                ("a.expr", "(not true)"),
+                ("a.expr.interpreted", "true"),
+                ("b.expr", "true"),
+                ("b.expr.interpreted", "true"),
+            ],
+        );
+
+        assert_eq!(
+            vec![SpecFailure {
+                path: dir.join("a.expr.interpreted"),
+                output: SpecOutput::Interpreted,
+                expected: "true".to_string(),
+                actual: "false".to_string(),
+            }],
+            SpecRunner::new().check_dir(&dir).unwrap()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "1 spec(s) failed")]
+    fn run_spec_dir_panics_on_failure() {
+        let dir = spec_dir(
+            "panics",
+            &[("a.expr", "(not true)"), ("a.expr.interpreted", "true")],
+        );
+
+        run_spec_dir(dir);
+    }
+
+    const SHOUT: BuiltinFn<'static> = BuiltinFn {
+        name: "shout",
+        args: &[FnArg {
+            name: "value",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        }],
+        return_type: Type::String,
+        func: shout,
+        namespace: FnNamespace::User,
+        pure: false,
+        category: BuiltinCategory::Value,
+        capability: None,
+    };
+
+    fn shout(args: Vec<Value>) -> ExprResult<Value> {
+        Ok(Value::String(args[0].get_string()?.to_uppercase().into()))
+    }
+
+    #[test]
+    fn user_builtins() {
+        let dir = spec_dir(
+            "user-builtins",
+            &[("a.expr", "(shout `a`)"), ("a.expr.interpreted", "`A`")],
+        );
+
+        let runner = SpecRunner::new().with_user_builtins(vec![SHOUT]);
+
+        assert_eq!(Vec::<SpecFailure>::new(), runner.check_dir(&dir).unwrap());
+        assert_eq!(1, SpecRunner::new().check_dir(&dir).unwrap().len());
+    }
+}
@@ -0,0 +1,274 @@
+//! Render templates with embedded expressions
+
+use crate::{
+    compiler::{CompileTimeEnv, compile},
+    errors::{ExprErrorS, ExprResult, SyntaxError},
+    parser::parse,
+    span::Span,
+    value::Value,
+    vm::{RuntimeEnv, Vm},
+};
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+/// A piece of a template: literal text or an embedded expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment<'a> {
+    Text(&'a str),
+    /// Expression source and its span in the template, excluding delimiters
+    /// and surrounding whitespace
+    Expr(&'a str, Span),
+}
+
+/// Split a template in to literal text and `{{...}}` expression segments
+pub fn segments(template: &str) -> ExprResult<Vec<Segment<'_>>> {
+    let mut segments = vec![];
+    let mut rest = 0;
+
+    while let Some(open) = template[rest..].find(OPEN).map(|i| rest + i) {
+        let expr_start = open + OPEN.len();
+
+        let Some(expr_end) = template[expr_start..].find(CLOSE).map(|i| expr_start + i) else {
+            return Err(vec![(
+                SyntaxError::UnterminatedPlaceholder.into(),
+                open..template.len(),
+            )]);
+        };
+
+        if open > rest {
+            segments.push(Segment::Text(&template[rest..open]));
+        }
+
+        let expr = &template[expr_start..expr_end];
+        let trimmed = expr.trim();
+        let trimmed_start = expr_start + (expr.len() - expr.trim_start().len());
+
+        segments.push(Segment::Expr(
+            trimmed,
+            trimmed_start..trimmed_start + trimmed.len(),
+        ));
+
+        rest = expr_end + CLOSE.len();
+    }
+
+    if rest < template.len() {
+        segments.push(Segment::Text(&template[rest..]));
+    }
+
+    Ok(segments)
+}
+
+/// Render a template, evaluating each `{{...}}` placeholder as an expression
+///
+/// String values are spliced in as is. Other values are spliced in using
+/// their display form. Errors from every placeholder are returned with spans
+/// mapped back in to the template.
+///
+/// ```
+/// use reqlang_expr::{prelude::*, template::render};
+///
+/// let env = CompileTimeEnv::new(
+///     vec!["user".to_string()],
+///     vec![],
+///     vec!["token".to_string()],
+///     vec![],
+/// );
+///
+/// let runtime_env = RuntimeEnv {
+///     vars: vec!["alice".to_string()],
+///     secrets: vec!["abc123".to_string()],
+///     ..Default::default()
+/// };
+///
+/// assert_eq!(
+///     Ok("Bearer abc123 for alice".to_string()),
+///     render("Bearer {{!token}} for {{:user}}", &env, &runtime_env)
+/// );
+/// ```
+pub fn render(
+    template: &str,
+    env: &CompileTimeEnv,
+    runtime_env: &RuntimeEnv,
+) -> ExprResult<String> {
+    let mut output = String::with_capacity(template.len());
+    let mut errs: Vec<ExprErrorS> = vec![];
+    let mut vm = Vm::new();
+
+    for segment in segments(template)? {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Expr(source, span) => match eval(source, env, runtime_env, &mut vm) {
+                Ok(value) => output.push_str(&stringify(value)),
+                Err(e) => errs.extend(
+                    e.into_iter()
+                        .map(|(err, err_span)| (err, map_span(&span, &err_span))),
+                ),
+            },
+        }
+    }
+
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+
+    Ok(output)
+}
+
+/// Convert a value to the string spliced in to a template
+pub(crate) fn stringify(value: Value) -> String {
+    match value {
+        Value::String(string) => string.to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn eval(
+    source: &str,
+    env: &CompileTimeEnv,
+    runtime_env: &RuntimeEnv,
+    vm: &mut Vm,
+) -> ExprResult<Value> {
+    let ast = parse(source)?;
+    let bytecode = compile(&mut (ast, 0..source.len()), env)?;
+
+    vm.interpret(bytecode.into(), env, runtime_env)
+}
+
+/// Map a span in an embedded expression to a span in the template
+///
+/// Runtime errors that don't carry span information, i.e. ones not returned
+/// by a call, are mapped to the whole expression.
+fn map_span(expr_span: &Span, err_span: &Span) -> Span {
+    if err_span.is_empty() && err_span.start == 0 {
+        return expr_span.clone();
+    }
+
+    (expr_span.start + err_span.start)..(expr_span.start + err_span.end)
+}
+
+#[cfg(test)]
+mod template_tests {
+    use super::*;
+
+    use crate::errors::{CompileError, ExprError};
+
+    fn env() -> (CompileTimeEnv, RuntimeEnv) {
+        let env = CompileTimeEnv::new(
+            vec!["user".to_string()],
+            vec![],
+            vec!["token".to_string()],
+            vec![],
+        );
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["alice".to_string()],
+            secrets: vec!["abc123".to_string()],
+            ..Default::default()
+        };
+
+        (env, runtime_env)
+    }
+
+    #[test]
+    fn segments_text_and_exprs() {
+        assert_eq!(
+            Ok(vec![
+                Segment::Text("a "),
+                Segment::Expr(":b", 4..6),
+                Segment::Text(" c"),
+            ]),
+            segments("a {{:b}} c")
+        );
+    }
+
+    #[test]
+    fn segments_adjacent_exprs() {
+        assert_eq!(
+            Ok(vec![Segment::Expr(":a", 2..4), Segment::Expr(":b", 8..10)]),
+            segments("{{:a}}{{:b}}")
+        );
+    }
+
+    #[test]
+    fn segments_trim_exprs() {
+        assert_eq!(Ok(vec![Segment::Expr(":a", 3..5)]), segments("{{ :a }}"));
+    }
+
+    #[test]
+    fn render_without_placeholders() {
+        let (env, runtime_env) = env();
+
+        assert_eq!(
+            Ok("no placeholders".to_string()),
+            render("no placeholders", &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn render_placeholders() {
+        let (env, runtime_env) = env();
+
+        assert_eq!(
+            Ok("Bearer abc123 for alice".to_string()),
+            render("Bearer {{!token}} for {{:user}}", &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn render_non_string_values() {
+        let (env, runtime_env) = env();
+
+        assert_eq!(
+            Ok("is alice: true".to_string()),
+            render("is alice: {{(eq :user `alice`)}}", &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn render_unterminated_placeholder() {
+        let (env, runtime_env) = env();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::SyntaxError(SyntaxError::UnterminatedPlaceholder),
+                6..13
+            )]),
+            render("Hello {{:user", &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn render_maps_error_spans_in_to_template() {
+        let (env, runtime_env) = env();
+
+        assert_eq!(
+            Err(vec![
+                (
+                    ExprError::CompileError(CompileError::Undefined(":a".to_string())),
+                    9..11
+                ),
+                (
+                    ExprError::CompileError(CompileError::Undefined("!b".to_string())),
+                    21..23
+                )
+            ]),
+            render("Hello, {{:a}} and {{ !b }}", &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn render_maps_call_error_spans_in_to_template() {
+        let (env, runtime_env) = env();
+
+        let errs = render("id: {{(id (assert false))}}", &env, &runtime_env).unwrap_err();
+
+        assert_eq!(10..24, errs[0].1);
+    }
+
+    #[test]
+    fn map_runtime_error_span_to_expr() {
+        assert_eq!(8..10, map_span(&(8..10), &(0..0)));
+        assert_eq!(9..10, map_span(&(8..10), &(1..2)));
+    }
+}
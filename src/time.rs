@@ -0,0 +1,346 @@
+//! Time builtins enabled by the `time` feature
+//!
+//! Timestamps are [RFC 3339](https://www.rfc-editor.org/rfc/rfc3339) strings
+//! e.g. `2025-01-01T00:00:00Z`. Durations are
+//! [humantime](https://docs.rs/humantime) strings e.g. `15m` or `1h 30m`.
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, TimeDelta, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    builtins::{BuiltinCategory, BuiltinFn, BuiltinGroup, FnArg, FnNamespace},
+    errors::{ExprErrorS, ExprResult, RuntimeError},
+    types::Type,
+    value::Value,
+};
+
+/// Builtins registered as [`crate::builtins::BuiltinGroup::Time`] when the
+/// `time` feature is enabled
+///
+/// These are appended after [`BuiltinFn::DEFAULT_BUILTINS`] so default
+/// builtin lookup indexes don't change.
+pub const TIME_BUILTINS: [BuiltinFn<'static>; 4] = [NOW, ADD_DURATION, DIFF_SECONDS, TO_TIMEZONE];
+
+/// Returns the current UTC time as a timestamp [`Value::String`]
+///
+/// `(now)`
+pub const NOW: BuiltinFn<'static> = BuiltinFn {
+    name: "now",
+    args: &[],
+    return_type: Type::String,
+    func: now,
+    namespace: FnNamespace::Builtin,
+    pure: false,
+    category: BuiltinCategory::Time,
+    capability: Some(BuiltinGroup::Time),
+};
+
+fn now(_: Vec<Value>) -> ExprResult<Value> {
+    Ok(Value::String(
+        format_timestamp(Utc::now().fixed_offset()).into(),
+    ))
+}
+
+/// Returns `timestamp` [`Value::String`] offset by `duration` [`Value::String`]
+///
+/// Prefix the duration with `-` to subtract it. The timestamp's UTC offset is
+/// kept.
+///
+/// `` (add_duration (now) `15m`) ``
+pub const ADD_DURATION: BuiltinFn<'static> = BuiltinFn {
+    name: "add_duration",
+    args: &[
+        FnArg {
+            name: "timestamp",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+        FnArg {
+            name: "duration",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+    ],
+    return_type: Type::String,
+    func: add_duration,
+    namespace: FnNamespace::Builtin,
+    pure: true,
+    category: BuiltinCategory::Time,
+    capability: Some(BuiltinGroup::Time),
+};
+
+fn add_duration(args: Vec<Value>) -> ExprResult<Value> {
+    let timestamp = parse_timestamp(
+        "timestamp",
+        args.first()
+            .expect("should have timestamp expression passed")
+            .get_string()?,
+    )?;
+    let duration = parse_duration(
+        args.get(1)
+            .expect("should have duration expression passed")
+            .get_string()?,
+    )?;
+
+    let offset = timestamp
+        .checked_add_signed(duration)
+        .ok_or_else(|| invalid_argument("duration", "timestamp out of range".to_string()))?;
+
+    Ok(Value::String(format_timestamp(offset).into()))
+}
+
+/// Returns [`Value::Number`] of seconds from timestamp `b` to timestamp `a`
+///
+/// The result is negative if `a` is before `b`.
+///
+/// `` (diff_seconds @expires_at (now)) ``
+pub const DIFF_SECONDS: BuiltinFn<'static> = BuiltinFn {
+    name: "diff_seconds",
+    args: &[
+        FnArg {
+            name: "a",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+        FnArg {
+            name: "b",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+    ],
+    return_type: Type::Number,
+    func: diff_seconds,
+    namespace: FnNamespace::Builtin,
+    pure: true,
+    category: BuiltinCategory::Time,
+    capability: Some(BuiltinGroup::Time),
+};
+
+fn diff_seconds(args: Vec<Value>) -> ExprResult<Value> {
+    let a = parse_timestamp(
+        "a",
+        args.first()
+            .expect("should have first expression passed")
+            .get_string()?,
+    )?;
+    let b = parse_timestamp(
+        "b",
+        args.get(1)
+            .expect("should have second expression passed")
+            .get_string()?,
+    )?;
+
+    let diff = a.signed_duration_since(b);
+
+    Ok(Value::Number(diff.num_milliseconds() as f64 / 1000.0))
+}
+
+/// Returns `timestamp` [`Value::String`] converted to the UTC offset of the
+/// IANA `timezone` [`Value::String`] at that instant
+///
+/// `` (to_timezone (now) `America/Chicago`) ``
+pub const TO_TIMEZONE: BuiltinFn<'static> = BuiltinFn {
+    name: "to_timezone",
+    args: &[
+        FnArg {
+            name: "timestamp",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+        FnArg {
+            name: "timezone",
+            ty: Type::String,
+            variadic: false,
+            optional: false,
+        },
+    ],
+    return_type: Type::String,
+    func: to_timezone,
+    namespace: FnNamespace::Builtin,
+    pure: true,
+    category: BuiltinCategory::Time,
+    capability: Some(BuiltinGroup::Time),
+};
+
+fn to_timezone(args: Vec<Value>) -> ExprResult<Value> {
+    let timestamp = parse_timestamp(
+        "timestamp",
+        args.first()
+            .expect("should have timestamp expression passed")
+            .get_string()?,
+    )?;
+    let timezone = args
+        .get(1)
+        .expect("should have timezone expression passed")
+        .get_string()?;
+
+    let tz: Tz = timezone
+        .parse()
+        .map_err(|_| invalid_argument("timezone", format!("unknown timezone `{timezone}`")))?;
+
+    Ok(Value::String(
+        format_timestamp(timestamp.with_timezone(&tz).fixed_offset()).into(),
+    ))
+}
+
+pub(crate) fn parse_timestamp(
+    name: &str,
+    timestamp: &str,
+) -> Result<DateTime<FixedOffset>, Vec<ExprErrorS>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map_err(|err| invalid_argument(name, format!("invalid timestamp `{timestamp}`: {err}")))
+}
+
+pub(crate) fn format_timestamp(timestamp: DateTime<FixedOffset>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+fn parse_duration(duration: &str) -> Result<TimeDelta, Vec<ExprErrorS>> {
+    let trimmed = duration.trim();
+
+    let (negative, unsigned) = match trimmed.strip_prefix('-') {
+        Some(unsigned) => (true, unsigned.trim_start()),
+        None => (false, trimmed),
+    };
+
+    let delta = humantime::parse_duration(unsigned)
+        .ok()
+        .and_then(|duration| TimeDelta::from_std(duration).ok())
+        .ok_or_else(|| invalid_argument("duration", format!("invalid duration `{duration}`")))?;
+
+    Ok(if negative { -delta } else { delta })
+}
+
+pub(crate) fn invalid_argument(name: &str, message: String) -> Vec<ExprErrorS> {
+    vec![(
+        RuntimeError::InvalidArgument {
+            name: name.to_string(),
+            message,
+        }
+        .into(),
+        0..0,
+    )]
+}
+
+#[cfg(test)]
+mod time_tests {
+    use super::*;
+
+    use crate::{
+        compiler::{CompileTimeEnv, compile},
+        parser::parse,
+        vm::{RuntimeEnv, Vm},
+    };
+
+    fn interpret(source: &str) -> ExprResult<Value> {
+        let env = CompileTimeEnv::default();
+        let ast = parse(source)?;
+        let bytecode = compile(&mut (ast, 0..source.len()), &env)?;
+
+        Vm::new().interpret(bytecode.into(), &env, &RuntimeEnv::default())
+    }
+
+    fn string(value: &str) -> ExprResult<Value> {
+        Ok(Value::String(value.into()))
+    }
+
+    #[test]
+    fn now_is_a_timestamp() {
+        let Ok(Value::String(now)) = interpret("(now)") else {
+            panic!("should return a string");
+        };
+
+        assert!(parse_timestamp("now", &now).is_ok());
+    }
+
+    #[test]
+    fn add_duration() {
+        assert_eq!(
+            string("2025-01-01T00:15:00Z"),
+            interpret("(add_duration `2025-01-01T00:00:00Z` `15m`)")
+        );
+        assert_eq!(
+            string("2025-01-02T01:30:00-06:00"),
+            interpret("(add_duration `2025-01-01T00:00:00-06:00` `1day 1h 30m`)")
+        );
+    }
+
+    #[test]
+    fn subtract_duration() {
+        assert_eq!(
+            string("2024-12-31T23:59:30Z"),
+            interpret("(add_duration `2025-01-01T00:00:00Z` `-30s`)")
+        );
+    }
+
+    #[test]
+    fn add_duration_to_now() {
+        assert!(matches!(
+            interpret("(add_duration (now) `15m`)"),
+            Ok(Value::String(_))
+        ));
+    }
+
+    #[test]
+    fn diff_seconds() {
+        assert_eq!(
+            Ok(Value::Number(90.5)),
+            interpret("(diff_seconds `2025-01-01T00:01:30.5Z` `2025-01-01T00:00:00Z`)")
+        );
+        assert_eq!(
+            Ok(Value::Number(-3600.0)),
+            interpret("(diff_seconds `2025-01-01T00:00:00Z` `2025-01-01T00:00:00-01:00`)")
+        );
+    }
+
+    #[test]
+    fn to_timezone() {
+        assert_eq!(
+            string("2024-12-31T18:00:00-06:00"),
+            interpret("(to_timezone `2025-01-01T00:00:00Z` `America/Chicago`)")
+        );
+        assert_eq!(
+            string("2025-07-01T07:30:00-05:00"),
+            interpret("(to_timezone `2025-07-01T12:30:00Z` `America/Chicago`)")
+        );
+        assert_eq!(
+            string("2025-01-01T00:00:00Z"),
+            interpret("(to_timezone `2025-01-01T09:00:00+09:00` `UTC`)")
+        );
+    }
+
+    #[test]
+    fn unknown_timezone() {
+        let source = "(to_timezone `2025-01-01T00:00:00Z` `Mars/Olympus_Mons`)";
+        let (err, _) = invalid_argument(
+            "timezone",
+            "unknown timezone `Mars/Olympus_Mons`".to_string(),
+        )
+        .remove(0);
+
+        assert_eq!(Err(vec![(err, 0..source.len())]), interpret(source));
+    }
+
+    #[test]
+    fn invalid_timestamp() {
+        assert!(matches!(
+            interpret("(add_duration `yesterday` `1h`)").unwrap_err()[0].0,
+            crate::errors::ExprError::RuntimeError(RuntimeError::InvalidArgument { ref name, .. })
+                if name == "timestamp"
+        ));
+    }
+
+    #[test]
+    fn invalid_duration() {
+        let source = "(add_duration `2025-01-01T00:00:00Z` `soon`)";
+        let (err, _) =
+            invalid_argument("duration", "invalid duration `soon`".to_string()).remove(0);
+
+        assert_eq!(Err(vec![(err, 0..source.len())]), interpret(source));
+    }
+}
@@ -4,7 +4,8 @@ use regex::Regex;
 
 use crate::{prelude::BuiltinFn, value::Value};
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Type {
     Value,
     String,
@@ -16,6 +17,8 @@ pub enum Type {
     },
     Bool,
     Type(Box<Type>),
+    /// List of values of the item type
+    List(Box<Type>),
     Unknown,
 }
 
@@ -23,6 +26,7 @@ impl Type {
     pub fn from(name: &str) -> Self {
         match name {
             "String" => Type::String,
+            "Number" => Type::Number,
             "Bool" => Type::Bool,
             "Value" => Type::Value,
             _ => {
@@ -30,6 +34,10 @@ impl Type {
                     return Type::Type(Type::from(&captures[1]).into());
                 }
 
+                if let Some(captures) = Regex::new(r"^List<(.+)>$").unwrap().captures(name) {
+                    return Type::List(Type::from(&captures[1]).into());
+                }
+
                 if let Some(captures) = Regex::new(
                     r"^Fn\((?P<args>(?:\w+(?:,\s*)?)*)\s*(?:\.\.\.(?P<varg>\w+))?\)\s*->\s*(?P<return>\w+)$",
                 ).unwrap().captures(name) {
@@ -92,13 +100,135 @@ impl Type {
             }
             Type::Bool => "Bool".to_string(),
             Type::Type(ty) => ty.name().to_string(),
+            Type::List(item) => format!("List<{}>", item.name()),
             Type::Unknown => "Unknown".to_string(),
         }
     }
 
+    /// Type of a list with items of these types
+    ///
+    /// Items all of the same type make a list of that type, otherwise it's a
+    /// list of [`Type::Value`].
+    pub fn list_of(items: impl IntoIterator<Item = Type>) -> Self {
+        let mut items = items.into_iter();
+
+        let item = match items.next() {
+            Some(first) if items.all(|item| item == first) => first,
+            _ => Type::Value,
+        };
+
+        Type::List(item.into())
+    }
+
+    /// Signature of `(compose f g)` from the signatures of `f` and `g`
+    ///
+    /// It takes the args `g` takes and returns what `f` returns.
+    /// [`Type::Unknown`] if either isn't a [`Type::Fn`].
+    pub fn composed(f: &Type, g: &Type) -> Self {
+        let (
+            Type::Fn { returns, .. },
+            Type::Fn {
+                args, variadic_arg, ..
+            },
+        ) = (f, g)
+        else {
+            return Type::Unknown;
+        };
+
+        Type::Fn {
+            args: args.clone(),
+            variadic_arg: variadic_arg.clone(),
+            returns: returns.clone(),
+        }
+    }
+
+    /// Signature of `(partial f arg)` from the signature of `f`
+    ///
+    /// It takes the args `f` takes after its first. [`Type::Unknown`] if `f`
+    /// isn't a [`Type::Fn`].
+    pub fn partially_applied(f: &Type) -> Self {
+        let Type::Fn {
+            args,
+            variadic_arg,
+            returns,
+        } = f
+        else {
+            return Type::Unknown;
+        };
+
+        Type::Fn {
+            args: args.iter().skip(1).cloned().collect(),
+            variadic_arg: variadic_arg.clone(),
+            returns: returns.clone(),
+        }
+    }
+
+    /// If a value of the `actual` type can be passed where this type is
+    /// expected
+    ///
+    /// [`Type::Value`] and [`Type::Unknown`] match any type, including the
+    /// items of lists.
+    pub fn accepts(&self, actual: &Type) -> bool {
+        match (self, actual) {
+            (Type::Value, _) | (_, Type::Value | Type::Unknown) => true,
+            (Type::List(expected), Type::List(actual)) => expected.accepts(actual),
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    /// If a function of this type can be called with the args of the
+    /// `expected` [`Type::Fn`] and returns what it expects
+    ///
+    /// `false` if either isn't a [`Type::Fn`].
+    pub fn can_be_called_as(&self, expected: &Type) -> bool {
+        let (
+            Type::Fn {
+                args,
+                variadic_arg,
+                returns,
+            },
+            Type::Fn {
+                args: expected_args,
+                returns: expected_returns,
+                ..
+            },
+        ) = (self, expected)
+        else {
+            return false;
+        };
+
+        args.len() <= expected_args.len()
+            && expected_args.iter().enumerate().all(|(i, expected_arg)| {
+                args.get(i)
+                    .or(variadic_arg.as_deref())
+                    .is_some_and(|arg| arg.accepts(expected_arg))
+            })
+            && expected_returns.accepts(returns)
+    }
+
     pub fn is_type(&self) -> bool {
         matches!(self, Type::Type(_))
     }
+
+    /// If the type and every type in it are known
+    ///
+    /// Unrecognized type names are parsed as [`Type::Unknown`].
+    pub fn is_known(&self) -> bool {
+        match self {
+            Type::Fn {
+                args,
+                variadic_arg,
+                returns,
+            } => {
+                args.iter().all(Type::is_known)
+                    && variadic_arg.as_deref().is_none_or(Type::is_known)
+                    && returns.is_known()
+            }
+            Type::Type(ty) | Type::List(ty) => ty.is_known(),
+            Type::Unknown => false,
+            _ => true,
+        }
+    }
 }
 
 impl Display for Type {
@@ -119,8 +249,12 @@ impl From<Value> for Type {
             Value::String(_) => Type::String,
             Value::Number(_) => Type::Number,
             Value::Fn(builtin_fn) => {
-                let mut args: Vec<Type> =
-                    builtin_fn.args.iter().map(|arg| arg.ty.clone()).collect();
+                let mut args: Vec<Type> = builtin_fn
+                    .args
+                    .iter()
+                    .filter(|arg| !arg.optional)
+                    .map(|arg| arg.ty.clone())
+                    .collect();
 
                 let variadic_arg = builtin_fn
                     .args
@@ -138,8 +272,10 @@ impl From<Value> for Type {
                     returns: Box::new(builtin_fn.return_type.clone()),
                 }
             }
+            Value::Closure(closure) => closure.get_type(),
             Value::Bool(_) => Type::Bool,
             Value::Type(ty) => *ty.clone(),
+            Value::List(items) => Type::list_of(items.iter().map(Value::get_type)),
         }
     }
 }
@@ -149,7 +285,7 @@ impl From<BuiltinFn<'static>> for Type {
         let args: Vec<Type> = value
             .args
             .iter()
-            .filter(|x| !x.variadic)
+            .filter(|x| !x.variadic && !x.optional)
             .map(|x| x.ty.clone())
             .collect();
         let varg = value
@@ -189,14 +325,14 @@ mod from_tests {
 
     #[test]
     fn test_from_string_value() {
-        let string_value = Value::String("test".to_string());
+        let string_value = Value::String("test".into());
         let ty: Type = string_value.into();
         assert_eq!(Type::String, ty);
     }
 
     #[test]
     fn test_get_type_string_value() {
-        let string_value = Value::String("test".to_string());
+        let string_value = Value::String("test".into());
         let ty: Type = string_value.get_type();
         assert_eq!(Type::String, ty);
     }
@@ -248,6 +384,64 @@ mod from_tests {
     }
 }
 
+#[cfg(test)]
+mod fn_signature_tests {
+    use super::*;
+
+    fn fn_type(args: Vec<Type>, returns: Type) -> Type {
+        Type::Fn {
+            args,
+            variadic_arg: None,
+            returns: returns.into(),
+        }
+    }
+
+    #[test]
+    fn test_composed() {
+        assert_eq!(
+            fn_type(vec![Type::Value], Type::Bool),
+            Type::composed(
+                &fn_type(vec![Type::String], Type::Bool),
+                &fn_type(vec![Type::Value], Type::String)
+            )
+        );
+    }
+
+    #[test]
+    fn test_composed_not_fn() {
+        assert_eq!(
+            Type::Unknown,
+            Type::composed(&Type::Value, &fn_type(vec![], Type::String))
+        );
+    }
+
+    #[test]
+    fn test_partially_applied() {
+        assert_eq!(
+            fn_type(vec![Type::String], Type::Bool),
+            Type::partially_applied(&fn_type(vec![Type::Value, Type::String], Type::Bool))
+        );
+    }
+
+    #[test]
+    fn test_can_be_called_as() {
+        let expected = fn_type(vec![Type::String], Type::Bool);
+
+        assert!(fn_type(vec![Type::String], Type::Bool).can_be_called_as(&expected));
+        assert!(fn_type(vec![Type::Value], Type::Value).can_be_called_as(&expected));
+        assert!(!fn_type(vec![Type::Number], Type::Bool).can_be_called_as(&expected));
+        assert!(!fn_type(vec![Type::String], Type::String).can_be_called_as(&expected));
+        assert!(!fn_type(vec![], Type::Bool).can_be_called_as(&expected));
+        assert!(!fn_type(vec![Type::String, Type::String], Type::Bool).can_be_called_as(&expected));
+        assert!(!Type::String.can_be_called_as(&expected));
+    }
+
+    #[test]
+    fn test_partially_applied_not_fn() {
+        assert_eq!(Type::Unknown, Type::partially_applied(&Type::String));
+    }
+}
+
 #[cfg(test)]
 mod name_and_display_tests {
     use super::*;
@@ -360,6 +554,22 @@ mod from_string_tests {
         assert_eq!(Type::Value, ty);
     }
 
+    #[test]
+    fn from_string_to_number() {
+        let ty = Type::from("Number");
+
+        assert_eq!(Type::Number, ty);
+    }
+
+    #[test]
+    fn is_known() {
+        assert!(Type::from("Fn(String, ...Number) -> Bool").is_known());
+        assert!(!Type::from("Foo").is_known());
+        assert!(!Type::from("Type<Foo>").is_known());
+        assert!(!Type::from("Fn(Foo) -> String").is_known());
+        assert!(!Type::from("Fn(...Foo) -> String").is_known());
+    }
+
     #[test]
     fn from_string_to_unknown() {
         let ty = Type::from("Unknown");
@@ -1,20 +1,39 @@
 //! The core value type used in the virtual machine.
 
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
 
 use crate::{
     builtins::BuiltinFn,
     errors::{ExprResult, RuntimeError},
+    lexer::escape_interpolations,
     types::Type,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+/// Serialized with the `serde` feature as `{"type": ..., "value": ...}`
+/// tagged by the variant name, with functions as their name and
+/// [`FnNamespace`](crate::builtins::FnNamespace)
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "type", content = "value")
+)]
 pub enum Value {
-    String(String),
+    /// Shared so copies of large strings, e.g. vars read many times, are
+    /// cheap
+    String(Arc<str>),
     Number(f64),
+    #[cfg_attr(feature = "serde", serde(with = "serde_fn"))]
     Fn(Box<BuiltinFn<'static>>),
+    /// Function built at runtime from other function values
+    Closure(Box<Closure>),
     Bool(bool),
     Type(Box<Type>),
+    List(Vec<Value>),
 }
 
 impl Value {
@@ -24,7 +43,7 @@ impl Value {
 
     pub fn get_string(&self) -> ExprResult<&str> {
         match self {
-            Value::String(s) => Ok(s.as_str()),
+            Value::String(s) => Ok(s),
             _ => Err(vec![(
                 RuntimeError::TypeMismatch {
                     expected: Type::String,
@@ -36,7 +55,7 @@ impl Value {
         }
     }
 
-    pub fn get_func(&self) -> ExprResult<Box<BuiltinFn>> {
+    pub fn get_func(&self) -> ExprResult<Box<BuiltinFn<'_>>> {
         match self {
             Value::Fn(f) => Ok(f.clone()),
             _ => Err(vec![(
@@ -54,6 +73,48 @@ impl Value {
         }
     }
 
+    /// Call a [`Value::Fn`] or [`Value::Closure`] with arguments
+    pub fn call(&self, args: Vec<Value>) -> ExprResult<Value> {
+        match self {
+            Value::Closure(closure) => closure.call(args),
+            _ => {
+                let builtin = self.get_func()?;
+
+                builtin.check_arity(&args)?;
+
+                (builtin.func)(args)
+            }
+        }
+    }
+
+    pub fn get_number(&self) -> ExprResult<f64> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            _ => Err(vec![(
+                RuntimeError::TypeMismatch {
+                    expected: Type::Number,
+                    actual: self.get_type(),
+                }
+                .into(),
+                0..0,
+            )]),
+        }
+    }
+
+    pub fn get_list(&self) -> ExprResult<&[Value]> {
+        match self {
+            Value::List(items) => Ok(items.as_slice()),
+            _ => Err(vec![(
+                RuntimeError::TypeMismatch {
+                    expected: Type::List(Type::Value.into()),
+                    actual: self.get_type(),
+                }
+                .into(),
+                0..0,
+            )]),
+        }
+    }
+
     pub fn get_bool(&self) -> ExprResult<bool> {
         match self {
             Value::Bool(s) => Ok(*s),
@@ -69,32 +130,261 @@ impl Value {
     }
 }
 
+impl Value {
+    /// Expression source that evaluates back to this value
+    ///
+    /// [`Display`] is meant for people and doesn't round-trip: backticks in
+    /// strings aren't escaped and functions show their signature. Strings
+    /// containing backticks or `&` are written with `html_unescape` since
+    /// string literals can't escape them, and a `{` that would start an
+    /// interpolation is escaped as `\{`. Functions are written by name so they
+    /// only round-trip in an environment with the same builtins.
+    ///
+    /// Negative numbers are written with `negate` since there are no negative
+    /// literals, and `-0.0` is written as `0`, which it equals. Returns `None`
+    /// for `NaN` and infinite numbers, or functions and lists containing one,
+    /// since there's no source for them.
+    pub fn to_expr_source(&self) -> Option<String> {
+        match self {
+            Value::String(string) if string.contains(['`', '&']) => Some(format!(
+                "(html_unescape `{}`)",
+                escape_interpolations(&string.replace('&', "&amp;").replace('`', "&#96;"))
+            )),
+            Value::String(string) => Some(format!("`{}`", escape_interpolations(string))),
+            Value::Number(value) if !value.is_finite() => None,
+            Value::Number(value) if *value < 0.0 => Some(format!("(negate {})", -value)),
+            Value::Number(value) => Some(format!("{}", value.abs())),
+            Value::Fn(builtin) => Some(builtin.name.to_string()),
+            Value::Closure(closure) => closure.to_expr_source(),
+            Value::Bool(value) => Some(value.to_string()),
+            Value::Type(ty) => Some(ty.name()),
+            Value::List(items) => Some(format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(Value::to_expr_source)
+                    .collect::<Option<Vec<_>>>()?
+                    .join(" ")
+            )),
+        }
+    }
+}
+
+/// Numbers are compared by value except that every `NaN` is equal, so a
+/// value always equals itself and can be used as a key
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::Fn(a), Value::Fn(b)) => a == b,
+            (Value::Closure(a), Value::Closure(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Type(a), Value::Type(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+
+        match self {
+            Value::String(string) => string.hash(state),
+            // -0.0 equals 0.0 and every NaN is equal so they have to hash the same
+            Value::Number(value) if *value == 0.0 => 0.0f64.to_bits().hash(state),
+            Value::Number(value) if value.is_nan() => f64::NAN.to_bits().hash(state),
+            Value::Number(value) => value.to_bits().hash(state),
+            Value::Fn(builtin) => builtin.hash(state),
+            Value::Closure(closure) => closure.hash(state),
+            Value::Bool(value) => value.hash(state),
+            Value::Type(ty) => ty.hash(state),
+            Value::List(items) => items.hash(state),
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Value::String(string) => write!(f, "`{string}`"),
             Value::Number(value) => write!(f, "{value}"),
             Value::Fn(builtin) => write!(f, "{builtin:?}"),
+            Value::Closure(closure) => write!(f, "{closure}"),
             Value::Bool(value) => write!(f, "{value}"),
             Value::Type(ty) => write!(f, "Type<{ty}>"),
+            Value::List(items) => {
+                write!(f, "[")?;
+
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+
+                    write!(f, "{item}")?;
+                }
+
+                write!(f, "]")
+            }
         }
     }
 }
 
+/// Function value returned by `compose` and `partial`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Closure {
+    /// Call `g` then call `f` with its result
+    Compose { f: Value, g: Value },
+    /// Call `f` with `arg` followed by the remaining arguments
+    Partial { f: Value, arg: Value },
+}
+
+impl Closure {
+    pub fn call(&self, args: Vec<Value>) -> ExprResult<Value> {
+        match self {
+            Closure::Compose { f, g } => f.call(vec![g.call(args)?]),
+            Closure::Partial { f, arg } => {
+                let mut partial_args = Vec::with_capacity(args.len() + 1);
+
+                partial_args.push(arg.clone());
+                partial_args.extend(args);
+
+                f.call(partial_args)
+            }
+        }
+    }
+
+    /// See [`Value::to_expr_source`]
+    pub fn to_expr_source(&self) -> Option<String> {
+        match self {
+            Closure::Compose { f, g } => Some(format!(
+                "(compose {} {})",
+                f.to_expr_source()?,
+                g.to_expr_source()?
+            )),
+            Closure::Partial { f, arg } => Some(format!(
+                "(partial {} {})",
+                f.to_expr_source()?,
+                arg.to_expr_source()?
+            )),
+        }
+    }
+
+    /// The [`Type::Fn`] signature of the closure
+    ///
+    /// Computed from the signatures of the functions it was built from.
+    pub fn get_type(&self) -> Type {
+        match self {
+            Closure::Compose { f, g } => Type::composed(&f.get_type(), &g.get_type()),
+            Closure::Partial { f, .. } => Type::partially_applied(&f.get_type()),
+        }
+    }
+}
+
+impl Display for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Closure::Compose { f: outer, g } => write!(f, "(compose {outer} {g})"),
+            Closure::Partial { f: func, arg } => write!(f, "(partial {func} {arg})"),
+        }
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value.into())
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.into())
+    }
+}
+
 impl From<bool> for Value {
     fn from(value: bool) -> Self {
         Self::Bool(value)
     }
 }
 
+/// Overwrites strings, including those in lists, with zeros
+///
+/// Strings still shared with another value, and values captured by a
+/// [`Closure`], are left as is.
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for Value {
+    fn zeroize(&mut self) {
+        match self {
+            Value::String(string) => {
+                if let Some(string) = Arc::get_mut(string) {
+                    string.zeroize();
+                }
+            }
+            Value::List(items) => items.zeroize(),
+            _ => {}
+        }
+    }
+}
+
+/// Functions are serialized as their name and namespace since function
+/// pointers can't be
+///
+/// They're resolved by name from the builtins this crate provides when
+/// deserializing, so user builtins that aren't one of them fail to
+/// deserialize.
+#[cfg(feature = "serde")]
+mod serde_fn {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+    use crate::{
+        builtins::{BuiltinFn, FnNamespace},
+        compiler::library_builtin,
+    };
+
+    #[derive(Serialize, Deserialize)]
+    struct SerializedFn {
+        name: String,
+        namespace: FnNamespace,
+    }
+
+    pub fn serialize<S: Serializer>(
+        builtin: &BuiltinFn<'static>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        SerializedFn {
+            name: builtin.name.to_string(),
+            namespace: builtin.namespace,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<BuiltinFn<'static>>, D::Error> {
+        let serialized = SerializedFn::deserialize(deserializer)?;
+
+        library_builtin(&serialized.name, serialized.namespace)
+            .map(Into::into)
+            .ok_or_else(|| D::Error::custom(format!("unknown builtin: {}", serialized.name)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::builtins::{BuiltinCategory, FnNamespace};
+
     use pretty_assertions::assert_eq;
 
     fn example_builtin(_args: Vec<Value>) -> ExprResult<Value> {
-        Ok(Value::String("".to_string()))
+        Ok(Value::String("".into()))
     }
 
     #[test]
@@ -108,7 +398,7 @@ mod tests {
                 .into(),
                 0..0
             )]),
-            Value::String("string".to_string()).get_bool()
+            Value::String("string".into()).get_bool()
         );
     }
 
@@ -134,13 +424,13 @@ mod tests {
 
     #[test]
     fn get_string_on_string() {
-        let value = Value::String("test".to_string());
+        let value = Value::String("test".into());
         assert_eq!(Ok("test"), value.get_string());
     }
 
     #[test]
     fn get_func_on_string() {
-        let value = Value::String("not a function".to_string());
+        let value = Value::String("not a function".into());
         assert_eq!(
             Err(vec![(
                 RuntimeError::TypeMismatch {
@@ -165,6 +455,10 @@ mod tests {
             args: &[],
             return_type: Type::Unknown,
             func: example_builtin,
+            namespace: FnNamespace::User,
+            pure: false,
+            category: BuiltinCategory::Value,
+            capability: None,
         }
         .into();
 
@@ -173,6 +467,26 @@ mod tests {
         assert_eq!(Ok(expected_fn), value.get_func());
     }
 
+    #[test]
+    fn call_closure() {
+        let closure = Value::Closure(
+            Closure::Partial {
+                f: Value::Fn(BuiltinFn::CONCAT.into()),
+                arg: Value::String("a".into()),
+            }
+            .into(),
+        );
+
+        assert_eq!(
+            Ok(Value::String("abc".into())),
+            closure.call(vec![Value::String("b".into()), Value::String("c".into())])
+        );
+        assert_eq!(
+            "(partial concat(a: Value, b: Value, ...rest: Value) -> String `a`)",
+            closure.to_string()
+        );
+    }
+
     #[test]
     fn get_func_on_bool() {
         let value = Value::Bool(true);
@@ -192,4 +506,225 @@ mod tests {
             value.get_func()
         );
     }
+
+    fn round_trip(source: &str) -> (Value, Option<Value>) {
+        use crate::prelude::*;
+
+        let env = CompileTimeEnv::default();
+        let eval = |source: &str| {
+            let bytecode = compile(&mut (parse(source).unwrap(), 0..source.len()), &env).unwrap();
+
+            Vm::new()
+                .interpret(bytecode.into(), &env, &RuntimeEnv::default())
+                .unwrap()
+        };
+
+        let value = eval(source);
+        let round_tripped = value.to_expr_source().map(|source| eval(&source));
+
+        (value, round_tripped)
+    }
+
+    #[test]
+    fn to_expr_source_round_trips() {
+        for source in [
+            "`a b`",
+            "(html_unescape `&#96;a&#96; &amp;amp;`)",
+            "(html_unescape `&#96;`)",
+            "``",
+            "1.5",
+            "(pow 10 21)",
+            "(floor 0.5)",
+            "true",
+            "concat",
+            "(compose trim (partial concat (html_unescape `&#96;`)))",
+            "(type concat)",
+            "Bool",
+        ] {
+            let (value, round_tripped) = round_trip(source);
+
+            assert_eq!(Some(value), round_tripped, "{source}");
+        }
+    }
+
+    #[test]
+    fn to_expr_source_escapes_backticks() {
+        assert_eq!(
+            Some("(html_unescape `a&#96;b&amp;`)".to_string()),
+            Value::String("a`b&".into()).to_expr_source()
+        );
+    }
+
+    #[test]
+    fn to_expr_source_escapes_interpolations() {
+        for opener in ["(", "[", ":", "?", "!", "@"] {
+            for string in [
+                format!("{{{opener}a}}"),
+                format!("x{{{opener}noop}}y"),
+                format!("\\{{{opener}a}} {{{{{opener}"),
+                format!("`\\\\{{{opener}}}&"),
+            ] {
+                let value = Value::String(string.clone().into());
+                let source = value.to_expr_source().unwrap();
+
+                let (evaluated, round_tripped) = round_trip(&source);
+
+                assert_eq!(value, evaluated, "{source}");
+                assert_eq!(Some(value), round_tripped, "{source}");
+            }
+        }
+    }
+
+    #[test]
+    fn to_expr_source_round_trips_edge_values() {
+        for value in [
+            Value::Number(-1.0),
+            Value::Number(-0.5),
+            Value::Number(-0.0),
+            Value::Number(-1e21),
+            Value::Number(f64::MIN),
+            Value::String("`".into()),
+            Value::String("&#96;&amp;`&".into()),
+            Value::String("`{(a)}`".into()),
+            Value::List(vec![Value::Number(-2.0), Value::String("a`b".into())]),
+        ] {
+            let source = value.to_expr_source().unwrap();
+
+            let (evaluated, round_tripped) = round_trip(&source);
+
+            assert_eq!(value, evaluated, "{source}");
+            assert_eq!(Some(value), round_tripped, "{source}");
+        }
+    }
+
+    #[test]
+    fn to_expr_source_negative_numbers() {
+        assert_eq!(
+            Some("(negate 1.5)".to_string()),
+            Value::Number(-1.5).to_expr_source()
+        );
+        assert_eq!(Some("0".to_string()), Value::Number(-0.0).to_expr_source());
+    }
+
+    #[test]
+    fn to_expr_source_non_finite_numbers() {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert_eq!(None, Value::Number(value).to_expr_source());
+            assert_eq!(
+                None,
+                Value::List(vec![Value::Number(value)]).to_expr_source()
+            );
+        }
+    }
+
+    #[test]
+    fn hash_matches_eq() {
+        let values = [
+            Value::Number(0.0),
+            Value::Number(-0.0),
+            Value::Fn(BuiltinFn::ID.into()),
+            Value::Fn(
+                BuiltinFn {
+                    namespace: FnNamespace::User,
+                    pure: false,
+                    ..BuiltinFn::ID
+                }
+                .into(),
+            ),
+            Value::Closure(
+                Closure::Partial {
+                    f: Value::Fn(BuiltinFn::CONCAT.into()),
+                    arg: Value::String("a".into()),
+                }
+                .into(),
+            ),
+            Value::Closure(
+                Closure::Partial {
+                    f: Value::Fn(BuiltinFn::CONCAT.into()),
+                    arg: Value::String("a".into()),
+                }
+                .into(),
+            ),
+            Value::Type(Type::String.into()),
+        ];
+
+        let unique: std::collections::HashSet<Value> = values.into_iter().collect();
+
+        assert_eq!(5, unique.len());
+    }
+
+    #[test]
+    fn nan_equals_itself() {
+        let nan = Value::Number(f64::NAN);
+        let other_nan = Value::Number(-f64::NAN);
+
+        assert_eq!(nan, nan);
+        assert_eq!(nan, other_nan);
+        assert_ne!(nan, Value::Number(0.0));
+
+        let unique: std::collections::HashSet<Value> = [nan, other_nan].into_iter().collect();
+
+        assert_eq!(1, unique.len());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let user_id = BuiltinFn {
+            namespace: FnNamespace::User,
+            ..BuiltinFn::ID
+        };
+
+        let values = vec![
+            Value::String("a".into()),
+            Value::Number(1.5),
+            Value::Bool(true),
+            Value::Fn(BuiltinFn::CONCAT.into()),
+            Value::Fn(user_id.into()),
+            Value::Closure(
+                Closure::Partial {
+                    f: Value::Fn(BuiltinFn::CONCAT.into()),
+                    arg: Value::String("b".into()),
+                }
+                .into(),
+            ),
+            Value::Type(Type::List(Type::String.into()).into()),
+            Value::List(vec![Value::String("c".into())]),
+        ];
+
+        for value in values {
+            let json = serde_json::to_value(&value).unwrap();
+
+            assert_eq!(value, serde_json::from_value::<Value>(json).unwrap());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_fn_is_tagged_by_name_and_namespace() {
+        assert_eq!(
+            serde_json::json!({
+                "type": "Fn",
+                "value": {"name": "concat", "namespace": "Builtin"},
+            }),
+            serde_json::to_value(Value::Fn(BuiltinFn::CONCAT.into())).unwrap()
+        );
+
+        assert_eq!(
+            serde_json::json!({"type": "String", "value": "a"}),
+            serde_json::to_value(Value::String("a".into())).unwrap()
+        );
+
+        let json = serde_json::json!({
+            "type": "Fn",
+            "value": {"name": "missing", "namespace": "User"},
+        });
+
+        assert_eq!(
+            "unknown builtin: missing",
+            serde_json::from_value::<Value>(json)
+                .unwrap_err()
+                .to_string()
+        );
+    }
 }
@@ -1,15 +1,28 @@
 //! The virtual machine and associated types
 
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
 use crate::{
+    ast::IdentifierKind,
+    builtins::{BuiltinFn, call_with_timeout},
     compiler::{
         CompileTimeEnv, ExprByteCode,
         lookup::{BUILTIN, PROMPT, SECRET, TYPE, VAR},
         opcode,
     },
-    errors::{ExprErrorS, ExprResult, RuntimeError},
+    coverage::Coverage,
+    errors::{CompileError, ExprError, ExprErrorS, ExprResult, RuntimeError},
+    explain::Explanation,
+    metrics::metrics,
     prelude::lookup::{CLIENT_CTX, USER_BUILTIN},
+    provenance::{Provenance, TracedValue},
+    providers::{AsyncSecretProvider, FetchedSecrets, SecretProvider},
+    snapshot::{PendingValue, VmSnapshot},
     types::Type,
-    value::Value,
+    value::{Closure, Value},
 };
 
 #[derive(Debug, Clone, Default)]
@@ -30,11 +43,209 @@ impl RuntimeEnv {
     }
 }
 
+/// Overwrites the secrets before clearing them
+///
+/// Wrap a runtime env in [`Zeroizing`](zeroize::Zeroizing) to wipe its
+/// secrets when it's dropped.
+///
+/// ```
+/// use reqlang_expr::prelude::*;
+/// use zeroize::Zeroizing;
+///
+/// let runtime_env = Zeroizing::new(RuntimeEnv {
+///     secrets: vec!["abc".to_string()],
+///     ..Default::default()
+/// });
+///
+/// assert_eq!(vec!["abc".to_string()], runtime_env.secrets);
+/// ```
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for RuntimeEnv {
+    fn zeroize(&mut self) {
+        self.secrets.zeroize();
+    }
+}
+
+/// Check a runtime env has a value for every name in the compile time env
+///
+/// Returns a [`CompileError::RuntimeEnvMismatch`] for each of the vars,
+/// prompts, secrets, and client context whose lengths differ.
+///
+/// ```
+/// use reqlang_expr::prelude::*;
+///
+/// let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+///
+/// assert!(validate_runtime_env(&env, &RuntimeEnv::default()).is_err());
+///
+/// let runtime_env = RuntimeEnv {
+///     vars: vec!["1".to_string()],
+///     ..Default::default()
+/// };
+///
+/// assert!(validate_runtime_env(&env, &runtime_env).is_ok());
+/// ```
+pub fn validate_runtime_env(env: &CompileTimeEnv, runtime_env: &RuntimeEnv) -> ExprResult<()> {
+    check_runtime_env(env, runtime_env, &[])
+}
+
+/// [`validate_runtime_env`], allowing the lookups in `can_be_missing` to
+/// have fewer values than names
+///
+/// Used when missing values are fetched from a [`SecretProvider`] or
+/// suspended on instead of read from the runtime env.
+fn check_runtime_env(
+    env: &CompileTimeEnv,
+    runtime_env: &RuntimeEnv,
+    can_be_missing: &[&str],
+) -> ExprResult<()> {
+    let lengths = [
+        ("var", env.vars().len(), runtime_env.vars.len()),
+        ("prompt", env.prompts().len(), runtime_env.prompts.len()),
+        ("secret", env.secrets().len(), runtime_env.secrets.len()),
+        (
+            "client context",
+            env.client_context().len(),
+            runtime_env.client_context.len(),
+        ),
+    ];
+
+    let errs: Vec<ExprErrorS> = lengths
+        .into_iter()
+        .filter(|(lookup, expected, actual)| {
+            expected != actual && !(actual < expected && can_be_missing.contains(lookup))
+        })
+        .map(|(lookup, expected, actual)| {
+            (
+                CompileError::RuntimeEnvMismatch {
+                    lookup: lookup.to_string(),
+                    expected,
+                    actual,
+                }
+                .into(),
+                0..0,
+            )
+        })
+        .collect();
+
+    if errs.is_empty() { Ok(()) } else { Err(errs) }
+}
+
+/// Limits and allocation settings for a [`Vm`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmConfig {
+    /// Number of values the stack is preallocated with
+    pub initial_stack_capacity: usize,
+    /// Maximum number of values on the stack before [`RuntimeError::StackOverflow`]
+    pub max_stack_depth: usize,
+    /// Maximum number of constants bytecode can reference before [`RuntimeError::TooManyConstants`]
+    pub max_constants: usize,
+    /// Maximum byte length of a string returned by a call before [`RuntimeError::OutputTooLarge`]
+    ///
+    /// Each call's result is checked so nested calls like `concat` can't
+    /// build strings past the limit.
+    pub max_output_size: usize,
+    /// Record the [`Coverage`] of each evaluation, read with [`Vm::coverage`]
+    pub coverage: bool,
+    /// Maximum wall-clock time of each builtin call before [`RuntimeError::BuiltinTimeout`]
+    ///
+    /// Builtins can't be interrupted so a call's result is discarded if it
+    /// returns late. The limit only stops a call early if the builtin calls
+    /// [`check_timeout`](crate::builtins::check_timeout) while it runs; a
+    /// builtin that never checks it and never returns still hangs the
+    /// evaluation.
+    pub builtin_timeout: Option<Duration>,
+    /// Cache the results of [`BuiltinFn::pure`] builtins by their args
+    ///
+    /// The cache is kept between evaluations so a builtin called by several
+    /// expressions only runs once. Clear it with [`Vm::clear_memoized`].
+    pub memoize: bool,
+}
+
+impl VmConfig {
+    /// Tighter limits for evaluating untrusted expressions
+    ///
+    /// Pair with [`CompileTimeEnv::sandboxed`].
+    pub fn sandboxed() -> Self {
+        Self {
+            max_stack_depth: 128,
+            max_constants: 64,
+            max_output_size: 64 * 1024,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        Self {
+            initial_stack_capacity: 16,
+            max_stack_depth: 1024,
+            max_constants: u8::MAX as usize + 1,
+            max_output_size: usize::MAX,
+            coverage: false,
+            builtin_timeout: None,
+            memoize: false,
+        }
+    }
+}
+
+/// Result of [`Vm::interpret_suspendable`] and [`Vm::resume`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Evaluation {
+    Complete(Value),
+    /// Waiting on a prompt or secret missing from the runtime env
+    Suspended(VmSnapshot),
+}
+
+/// Where a `try` continues if an op it guards fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ErrorHandler {
+    /// Offset of the `try`'s fallback ops
+    pub(crate) fallback_ip: usize,
+    /// Number of values on the stack when the `try` started
+    pub(crate) stack_len: usize,
+}
+
+/// A branch being evaluated while in [`Vm::explain`]
+#[derive(Debug)]
+enum ExplainedBranch {
+    /// A `cond` that went `then` or `else` and the explanation of its condition
+    Cond { then: bool, condition: Explanation },
+    /// The fallback ops of a `try` whose guarded ops failed
+    Fallback,
+}
+
 #[derive(Debug)]
 pub struct Vm {
-    bytecode: Option<Box<ExprByteCode>>,
     ip: usize,
     stack: Vec<Value>,
+    /// Values bound by `let`, indexed by slot
+    locals: Vec<Value>,
+    /// Handlers of the `try`s being evaluated, innermost last
+    handlers: Vec<ErrorHandler>,
+    /// Explanations of the values in `locals` while in [`Vm::explain`]
+    explained_locals: Vec<Explanation>,
+    /// Where each branch being evaluated ends and how it was taken while in
+    /// [`Vm::explain`]
+    explained_branches: Vec<(usize, ExplainedBranch)>,
+    /// Provenance of the values in `locals` while in [`Vm::interpret_traced`]
+    traced_locals: Vec<Provenance>,
+    /// Where each branch being evaluated ends and the provenance of what
+    /// decided it was taken while in [`Vm::interpret_traced`]
+    traced_branches: Vec<(usize, Provenance)>,
+    config: VmConfig,
+    coverage: Option<Coverage>,
+    /// Vars, prompts, secrets (including those from a [`SecretProvider`]),
+    /// and client context read during the current evaluation by lookup and
+    /// index, so reading one again shares its string instead of copying it
+    inputs: HashMap<(u8, usize), Value>,
+    /// Results of pure builtin calls if [`VmConfig::memoize`] is enabled
+    memoized: HashMap<(BuiltinFn<'static>, Vec<Value>), Value>,
+    /// Explanations of the values on the stack while in [`Vm::explain`]
+    explanations: Option<Vec<Explanation>>,
+    /// Provenance of the values on the stack while in [`Vm::interpret_traced`]
+    provenances: Option<Vec<Provenance>>,
 }
 
 impl Default for Vm {
@@ -45,62 +256,674 @@ impl Default for Vm {
 
 impl Vm {
     pub fn new() -> Self {
+        Self::with_config(VmConfig::default())
+    }
+
+    /// Create a VM with a preallocated stack that enforces the limits in [`VmConfig`]
+    pub fn with_config(config: VmConfig) -> Self {
         Self {
-            bytecode: None,
             ip: 0,
-            stack: vec![],
+            stack: Vec::with_capacity(config.initial_stack_capacity),
+            locals: vec![],
+            handlers: vec![],
+            explained_locals: vec![],
+            explained_branches: vec![],
+            traced_locals: vec![],
+            traced_branches: vec![],
+            config,
+            coverage: None,
+            inputs: HashMap::new(),
+            memoized: HashMap::new(),
+            explanations: None,
+            provenances: None,
+        }
+    }
+
+    pub fn config(&self) -> &VmConfig {
+        &self.config
+    }
+
+    /// Coverage of the last bytecode evaluated if [`VmConfig::coverage`] is enabled
+    ///
+    /// Coverage starts over with each evaluation and continues when a
+    /// suspended evaluation is resumed. Use [`Coverage::merge`] to combine
+    /// evaluations of the same bytecode.
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let source = "(cond (eq :env `prod`) `https://example.com` `http://localhost`)";
+    /// let env = CompileTimeEnv::new(vec!["env".to_string()], vec![], vec![], vec![]);
+    ///
+    /// let ast = parse(source).unwrap();
+    /// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+    ///
+    /// let mut vm = Vm::with_config(VmConfig {
+    ///     coverage: true,
+    ///     ..Default::default()
+    /// });
+    ///
+    /// let runtime_env = RuntimeEnv {
+    ///     vars: vec!["prod".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// vm.interpret(bytecode.into(), &env, &runtime_env).unwrap();
+    ///
+    /// let coverage = vm.coverage().unwrap();
+    /// let (_, branch) = coverage.branches().first_key_value().unwrap();
+    ///
+    /// assert!(branch.then_taken);
+    /// assert!(!branch.else_taken);
+    /// ```
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Forget the results of builtin calls cached by [`VmConfig::memoize`]
+    pub fn clear_memoized(&mut self) {
+        self.memoized.clear();
+    }
+
+    /// Overwrite every value the VM holds on to after an evaluation: the
+    /// stack, `let` bindings, secrets from a [`SecretProvider`], and memoized
+    /// builtin calls
+    ///
+    /// The VM can be reused afterwards.
+    #[cfg(feature = "zeroize")]
+    pub fn wipe(&mut self) {
+        use zeroize::Zeroize;
+
+        self.stack.zeroize();
+        self.locals.zeroize();
+        self.handlers.clear();
+        self.explained_locals.clear();
+        self.explained_branches.clear();
+        self.traced_locals.clear();
+        self.traced_branches.clear();
+
+        for (_, mut input) in self.inputs.drain() {
+            input.zeroize();
+        }
+
+        for ((_, mut args), mut result) in self.memoized.drain() {
+            args.zeroize();
+            result.zeroize();
         }
+
+        self.explanations = None;
+        self.provenances = None;
     }
 
+    /// Interpret bytecode
+    ///
+    /// The runtime env is checked with [`validate_runtime_env`] first so a
+    /// runtime env missing values errors instead of panicking. Every other
+    /// way of interpreting bytecode checks it too.
     pub fn interpret(
         &mut self,
         bytecode: Box<ExprByteCode>,
         env: &CompileTimeEnv,
         runtime_env: &RuntimeEnv,
     ) -> ExprResult<Value> {
-        self.bytecode = Some(bytecode);
+        self.run(&bytecode, env, runtime_env, None)
+    }
+
+    /// Interpret bytecode, tracing the [`Provenance`] of each value as it's
+    /// evaluated
+    ///
+    /// Only the inputs that were read and contributed to the resulting value
+    /// are in its provenance, so inputs in branches that weren't taken aren't.
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let source = "(concat `Bearer ` !token)";
+    /// let env = CompileTimeEnv::new(vec![], vec![], vec!["token".to_string()], vec![]);
+    ///
+    /// let ast = parse(source).unwrap();
+    /// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+    ///
+    /// let runtime_env = RuntimeEnv {
+    ///     secrets: vec!["abc123".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let traced = Vm::new()
+    ///     .interpret_traced(bytecode.into(), &env, &runtime_env)
+    ///     .unwrap();
+    ///
+    /// assert_eq!(&Value::String("Bearer abc123".into()), traced.value());
+    /// assert!(traced.provenance().is_secret());
+    /// ```
+    pub fn interpret_traced(
+        &mut self,
+        bytecode: Box<ExprByteCode>,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> ExprResult<TracedValue> {
+        self.provenances = Some(vec![]);
+
+        let result = self.interpret(bytecode, env, runtime_env);
+
+        let provenance = self
+            .provenances
+            .take()
+            .and_then(|mut provenances| provenances.pop());
+
+        let value = result?;
+
+        Ok(TracedValue::new(
+            value,
+            provenance.expect("should have traced the value"),
+        ))
+    }
+
+    /// Interpret bytecode, explaining how each value was evaluated
+    ///
+    /// Returns the value and an [`Explanation`] tree of each call's callee,
+    /// args, and result. Values a secret contributed to are redacted.
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let source = "(concat `Bearer ` !token)";
+    /// let env = CompileTimeEnv::new(vec![], vec![], vec!["token".to_string()], vec![]);
+    ///
+    /// let ast = parse(source).unwrap();
+    /// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+    ///
+    /// let runtime_env = RuntimeEnv {
+    ///     secrets: vec!["abc123".to_string()],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let (value, explanation) = Vm::new().explain(&bytecode, &env, &runtime_env).unwrap();
+    ///
+    /// assert_eq!(Value::String("Bearer abc123".into()), value);
+    /// assert_eq!(None, explanation.value());
+    /// assert!(!explanation.to_string().contains("abc123"));
+    /// ```
+    pub fn explain(
+        &mut self,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> ExprResult<(Value, Explanation)> {
+        self.explanations = Some(vec![]);
+
+        let result = self.run(bytecode, env, runtime_env, None);
+
+        let explanation = self
+            .explanations
+            .take()
+            .and_then(|mut explanations| explanations.pop());
+
+        let value = result?;
+
+        Ok((value, explanation.expect("should have explained the value")))
+    }
+
+    /// Interpret bytecode, getting secrets missing from the runtime env from a [`SecretProvider`]
+    ///
+    /// Secrets set in `runtime_env` are used as is. Only secrets the bytecode
+    /// references are requested from the provider, so `runtime_env` can have
+    /// fewer secrets than the compile time env.
+    pub fn interpret_with_secret_provider(
+        &mut self,
+        bytecode: Box<ExprByteCode>,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+        secret_provider: &dyn SecretProvider,
+    ) -> ExprResult<Value> {
+        self.run(&bytecode, env, runtime_env, Some(secret_provider))
+    }
+
+    /// Interpret bytecode, getting secrets missing from the runtime env from an [`AsyncSecretProvider`]
+    ///
+    /// Secrets the bytecode references are fetched before interpreting.
+    /// Secrets missing from the compile time env aren't fetched, the VM
+    /// returns [`RuntimeError::InvalidBytecode`] when it gets to them.
+    pub async fn interpret_with_async_secret_provider(
+        &mut self,
+        bytecode: Box<ExprByteCode>,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+        secret_provider: &impl AsyncSecretProvider,
+    ) -> ExprResult<Value> {
+        check_runtime_env(env, runtime_env, &["secret"])?;
+
+        let mut fetched = FetchedSecrets::default();
+
+        for idx in bytecode.referenced_indexes(SECRET) {
+            let idx = idx as usize;
+
+            if runtime_env.secrets.get(idx).is_some() {
+                continue;
+            }
+
+            let Some(name) = env.get_secret(idx) else {
+                continue;
+            };
+
+            let value = secret_provider.get_secret(name).await;
+
+            fetched.insert(name.clone(), value);
+        }
+
+        self.run(&bytecode, env, runtime_env, Some(&fetched))
+    }
+
+    /// Interpret bytecode, suspending instead of reading a prompt or secret
+    /// missing from the runtime env
+    ///
+    /// The returned [`VmSnapshot`] says which value is pending. Resume it with
+    /// [`Vm::resume`] once the runtime env has the value.
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let source = "(concat `Hello ` ?name)";
+    /// let env = CompileTimeEnv::new(vec![], vec!["name".to_string()], vec![], vec![]);
+    ///
+    /// let ast = parse(source).unwrap();
+    /// let bytecode = compile(&mut (ast, 0..source.len()), &env).unwrap();
+    ///
+    /// let mut runtime_env = RuntimeEnv::default();
+    ///
+    /// let Ok(Evaluation::Suspended(snapshot)) =
+    ///     Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+    /// else {
+    ///     panic!("should suspend waiting on ?name");
+    /// };
+    ///
+    /// assert_eq!("name", snapshot.pending().name());
+    ///
+    /// runtime_env.prompts.push("World".to_string());
+    ///
+    /// assert_eq!(
+    ///     Ok(Evaluation::Complete(Value::String("Hello World".into()))),
+    ///     Vm::new().resume(snapshot, &bytecode, &env, &runtime_env)
+    /// );
+    /// ```
+    pub fn interpret_suspendable(
+        &mut self,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> ExprResult<Evaluation> {
+        check_runtime_env(env, runtime_env, &["prompt", "secret"])?;
+
+        self.reset(bytecode);
+
+        self.execute(bytecode, env, runtime_env, None, true)
+    }
+
+    /// Continue interpreting bytecode from a [`VmSnapshot`]
+    ///
+    /// The bytecode must be the bytecode that was suspended. Suspends again
+    /// if the runtime env is still missing a prompt or secret.
+    pub fn resume(
+        &mut self,
+        snapshot: VmSnapshot,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> ExprResult<Evaluation> {
+        let VmSnapshot {
+            ip,
+            stack,
+            locals,
+            handlers,
+            pending,
+        } = snapshot;
+
+        check_runtime_env(env, runtime_env, &["prompt", "secret"])?;
+
+        let expected_op = [opcode::GET, pending.lookup(), pending.index() as u8];
+
+        if bytecode.codes().get(ip..ip + expected_op.len()) != Some(&expected_op) {
+            return Err(vec![(
+                RuntimeError::InvalidSnapshot {
+                    message: format!(
+                        "expected a GET of {} at {ip:04} in the bytecode",
+                        pending.name()
+                    ),
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        self.ip = ip;
+        self.stack = stack;
+        self.locals = locals;
+        self.handlers = handlers;
+        // The runtime env can change between suspending and resuming
+        self.clear_inputs();
+
+        self.execute(bytecode, env, runtime_env, None, true)
+    }
+
+    /// Interpret a batch of bytecode against a shared environment
+    ///
+    /// The VM (and its stack) is reused between expressions. Results are
+    /// returned in the same order as `bytecodes`.
+    pub fn eval_all(
+        &mut self,
+        bytecodes: &[ExprByteCode],
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> Vec<ExprResult<Value>> {
+        bytecodes
+            .iter()
+            .map(|bytecode| self.run(bytecode, env, runtime_env, None))
+            .collect()
+    }
+
+    /// Interpret the results of [`compile_many`](crate::compiler::compile_many)
+    ///
+    /// Bytecode that failed to compile keeps its errors. Results are returned
+    /// in the same order as `bytecodes`.
+    ///
+    /// ```
+    /// use reqlang_expr::prelude::*;
+    ///
+    /// let source = "(eq `a` `a`) (not true) (missing)";
+    /// let env = CompileTimeEnv::default();
+    ///
+    /// let mut exprs = parse_many(source).unwrap();
+    /// let bytecodes = compile_many(&mut exprs, &env);
+    ///
+    /// let results = Vm::new().eval_many(bytecodes, &env, &RuntimeEnv::default());
+    ///
+    /// assert_eq!(Ok(Value::Bool(true)), results[0]);
+    /// assert_eq!(Ok(Value::Bool(false)), results[1]);
+    /// assert!(results[2].is_err());
+    /// ```
+    pub fn eval_many(
+        &mut self,
+        bytecodes: Vec<ExprResult<ExprByteCode>>,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> Vec<ExprResult<Value>> {
+        bytecodes
+            .into_iter()
+            .map(|bytecode| self.run(&bytecode?, env, runtime_env, None))
+            .collect()
+    }
+
+    /// Interpret a batch of bytecode against a shared environment in parallel
+    ///
+    /// Each worker thread reuses its own VM. Results are returned in the same
+    /// order as `bytecodes`.
+    #[cfg(feature = "rayon")]
+    pub fn par_eval_all(
+        bytecodes: &[ExprByteCode],
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> Vec<ExprResult<Value>> {
+        use rayon::prelude::*;
+
+        bytecodes
+            .par_iter()
+            .map_init(Vm::new, |vm, bytecode| {
+                vm.run(bytecode, env, runtime_env, None)
+            })
+            .collect()
+    }
+
+    fn run(
+        &mut self,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+        secret_provider: Option<&dyn SecretProvider>,
+    ) -> ExprResult<Value> {
+        match secret_provider {
+            Some(_) => check_runtime_env(env, runtime_env, &["secret"])?,
+            None => validate_runtime_env(env, runtime_env)?,
+        }
+
+        self.reset(bytecode);
+
+        match self.execute(bytecode, env, runtime_env, secret_provider, false)? {
+            Evaluation::Complete(value) => Ok(value),
+            Evaluation::Suspended(_) => unreachable!("should only suspend when suspendable"),
+        }
+    }
+
+    /// Start evaluating bytecode from the beginning
+    fn reset(&mut self, bytecode: &ExprByteCode) {
         self.ip = 0;
+        self.stack.clear();
+        self.stack.reserve(bytecode.max_stack_depth());
+        self.locals.clear();
+        self.handlers.clear();
+        self.explained_locals.clear();
+        self.explained_branches.clear();
+        self.traced_locals.clear();
+        self.traced_branches.clear();
+
+        self.coverage = self.config.coverage.then(|| Coverage::new(bytecode));
+
+        self.clear_inputs();
+    }
+
+    /// Forget the inputs read so far, overwriting them with the `zeroize`
+    /// feature
+    fn clear_inputs(&mut self) {
+        #[cfg(feature = "zeroize")]
+        for (_, mut input) in self.inputs.drain() {
+            zeroize::Zeroize::zeroize(&mut input);
+        }
+
+        self.inputs.clear();
+    }
+
+    /// Reject bytecode that would overflow the stack before running it
+    fn check_stack_depth(&self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        if bytecode.max_stack_depth() > self.config.max_stack_depth {
+            return Err(vec![(
+                RuntimeError::StackOverflow {
+                    max: self.config.max_stack_depth,
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(())
+    }
+
+    fn check_constants(&self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        if bytecode.constants().len() > self.config.max_constants {
+            return Err(vec![(
+                RuntimeError::TooManyConstants {
+                    max: self.config.max_constants,
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(())
+    }
+
+    /// Interpret from the current instruction pointer and stack, reporting
+    /// the outcome to [`metrics`]
+    fn execute(
+        &mut self,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+        secret_provider: Option<&dyn SecretProvider>,
+        suspendable: bool,
+    ) -> ExprResult<Evaluation> {
+        let start = Instant::now();
+
+        let result = self
+            .check_constants(bytecode)
+            .and_then(|_| self.check_stack_depth(bytecode))
+            .and_then(|_| self.dispatch(bytecode, env, runtime_env, secret_provider, suspendable));
+
+        let metrics = metrics();
+
+        match &result {
+            Ok(Evaluation::Suspended(_)) => return result,
+            Ok(Evaluation::Complete(_)) => {}
+            Err(errs) => {
+                for (err, _) in errs {
+                    if let ExprError::RuntimeError(err) = err {
+                        metrics.runtime_error(err);
+                    }
+                }
+            }
+        }
+
+        metrics.evaluated(start.elapsed());
+
+        result
+    }
+
+    fn dispatch(
+        &mut self,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+        secret_provider: Option<&dyn SecretProvider>,
+        suspendable: bool,
+    ) -> ExprResult<Evaluation> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "interpret",
+            bytecode_hash = bytecode_hash(bytecode),
+            ip = self.ip
+        )
+        .entered();
 
         let mut errs: Vec<ExprErrorS> = vec![];
 
-        while let Some(op_code) = self
-            .bytecode
-            .as_ref()
-            .and_then(|bc| bc.codes().get(self.ip))
-        {
-            if let Err(e) = self.interpret_op(env, runtime_env, *op_code) {
+        while let Some(op_code) = bytecode.codes().get(self.ip) {
+            if suspendable && let Some(pending) = self.pending_value(bytecode, env, runtime_env) {
+                if !errs.is_empty() {
+                    break;
+                }
+
+                #[cfg(feature = "tracing")]
+                tracing::debug!(pending = pending.name(), ip = self.ip, "suspended");
+
+                return Ok(Evaluation::Suspended(VmSnapshot {
+                    ip: self.ip,
+                    stack: std::mem::take(&mut self.stack),
+                    locals: std::mem::take(&mut self.locals),
+                    handlers: std::mem::take(&mut self.handlers),
+                    pending,
+                }));
+            }
+
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record_op(self.ip);
+            }
+
+            let result = self.interpret_op(bytecode, env, runtime_env, secret_provider, *op_code);
+
+            self.explain_branches();
+            self.trace_branches();
+
+            if let Err(e) = result {
+                let fatal = is_fatal(&e);
+
+                if !fatal && let Some(handler) = self.handlers.pop() {
+                    self.catch(bytecode, handler);
+
+                    continue;
+                }
+
                 errs.extend(e);
+
+                if fatal {
+                    break;
+                }
             }
         }
 
         if !errs.is_empty() {
+            #[cfg(feature = "tracing")]
+            tracing::debug!(errors = errs.len(), "evaluation failed");
+
             return Err(errs);
         }
 
-        self.stack_pop()
+        self.stack_pop().map(Evaluation::Complete)
+    }
+
+    /// The prompt or secret the op at the instruction pointer gets, if the
+    /// runtime env doesn't have a value for it
+    fn pending_value(
+        &self,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+    ) -> Option<PendingValue> {
+        let codes = bytecode.codes();
+
+        if codes.get(self.ip) != Some(&opcode::GET) {
+            return None;
+        }
+
+        let lookup = *codes.get(self.ip + 1)?;
+        let index = *codes.get(self.ip + 2)? as usize;
+
+        match lookup {
+            PROMPT if runtime_env.prompts.get(index).is_none() => Some(PendingValue::Prompt {
+                index,
+                name: env.get_prompt(index)?.clone(),
+            }),
+            SECRET if runtime_env.secrets.get(index).is_none() => Some(PendingValue::Secret {
+                index,
+                name: env.get_secret(index)?.clone(),
+            }),
+            _ => None,
+        }
     }
 
     fn interpret_op(
         &mut self,
+        bytecode: &ExprByteCode,
         env: &CompileTimeEnv,
         runtime_env: &RuntimeEnv,
+        secret_provider: Option<&dyn SecretProvider>,
         op_code: u8,
     ) -> ExprResult<()> {
         match op_code {
-            opcode::CALL => self.op_call(),
-            opcode::CONSTANT => self.op_constant(),
-            opcode::GET => self.op_get(env, runtime_env),
-            opcode::TRUE => self.op_true(),
-            opcode::FALSE => self.op_false(),
-            _ => panic!("Invalid OP code: {op_code}"),
+            opcode::CALL => self.op_call(bytecode, false),
+            opcode::CONSTANT => self.op_constant(bytecode),
+            opcode::GET => self.op_get(bytecode, env, runtime_env, secret_provider),
+            opcode::TRUE => self.op_true(bytecode),
+            opcode::FALSE => self.op_false(bytecode),
+            opcode::CALL_BUILTIN => self.op_call_builtin(bytecode, env),
+            opcode::LIST => self.op_list(bytecode),
+            opcode::GET_LOCAL => self.op_get_local(bytecode),
+            opcode::SET_LOCAL => self.op_set_local(bytecode),
+            opcode::JUMP_IF_FALSE => self.op_jump_if_false(bytecode),
+            opcode::JUMP => self.op_jump(bytecode),
+            opcode::TRY => self.op_try(bytecode),
+            opcode::END_TRY => self.op_end_try(bytecode),
+            opcode::CALL_SPREAD => self.op_call(bytecode, true),
+            _ => Err(invalid_bytecode(format!("invalid op code {op_code}"))),
         }
     }
 
-    fn op_call(&mut self) -> ExprResult<()> {
-        // Consume current op: CALL
-        self.read_u8();
+    /// Call the callee under the args, spreading the items of the last arg
+    /// in to the args if `spread`
+    fn op_call(&mut self, bytecode: &ExprByteCode, spread: bool) -> ExprResult<()> {
+        let op_ip = self.ip;
+
+        // Consume current op: CALL or CALL_SPREAD
+        self.read_u8(bytecode)?;
 
-        let arg_count = self.read_u8() as usize;
+        let arg_count = self.read_u8(bytecode)? as usize;
 
         let mut args: Vec<Value> = vec![];
 
@@ -110,129 +933,629 @@ impl Vm {
 
         args.reverse();
 
+        if spread {
+            let list = args
+                .pop()
+                .ok_or_else(|| invalid_bytecode("CALL_SPREAD without a list".to_string()))?;
+
+            let items = list
+                .get_list()
+                .map_err(|errs| bytecode.span_call_errors(op_ip, errs))?;
+
+            args.extend(items.iter().cloned());
+        }
+
         let value = self.stack_pop()?;
 
-        let builtin = value.get_func()?.func;
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::trace_span!("call", callee = %callee_name(&value), args = arg_count).entered();
+
+        if let Value::Fn(builtin) = &value {
+            self.record_cond(op_ip, builtin, &args);
+        }
+
+        // The callee is traced before the args
+        let untaken = match &value {
+            Value::Fn(builtin) if !spread && self.provenances.is_some() => {
+                untaken_cond_arg(builtin, &args).map(|index| index + 1)
+            }
+            _ => None,
+        };
+
+        let name = match &value {
+            Value::Fn(builtin) => builtin.name,
+            Value::Closure(closure) => match **closure {
+                Closure::Compose { .. } => "compose",
+                Closure::Partial { .. } => "partial",
+            },
+            _ => "call",
+        };
+
+        let result = match &value {
+            Value::Fn(builtin) => self.call_builtin(builtin, args),
+            _ => call_with_timeout(name, self.config.builtin_timeout, || value.call(args)),
+        }
+        .map_err(|errs| bytecode.span_call_errors(op_ip, errs))?;
+
+        self.check_output_size(&result)?;
+        self.explain_call(arg_count, None, &result);
+        self.stack_push(result)?;
+        self.trace_union(arg_count + 1, untaken);
+
+        Ok(())
+    }
+
+    fn op_call_builtin(&mut self, bytecode: &ExprByteCode, env: &CompileTimeEnv) -> ExprResult<()> {
+        let op_ip = self.ip;
+
+        // Consume current op: CALL_BUILTIN
+        self.read_u8(bytecode)?;
+
+        let builtin_idx = self.read_u8(bytecode)? as usize;
+        let arg_count = self.read_u8(bytecode)? as usize;
+
+        let mut args: Vec<Value> = vec![];
+
+        for _ in 0..arg_count {
+            args.push(self.stack_pop()?);
+        }
+
+        args.reverse();
+
+        let builtin = env
+            .get_builtin(builtin_idx)
+            .ok_or_else(|| invalid_bytecode(format!("undefined builtin {builtin_idx}")))?;
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("call", callee = builtin.name, args = arg_count).entered();
 
-        let result = builtin(args);
+        self.record_cond(op_ip, builtin, &args);
 
-        self.stack_push(result?);
+        let untaken = self
+            .provenances
+            .is_some()
+            .then(|| untaken_cond_arg(builtin, &args))
+            .flatten();
+
+        let result = self
+            .call_builtin(builtin, args)
+            .map_err(|errs| bytecode.span_call_errors(op_ip, errs))?;
+
+        self.check_output_size(&result)?;
+        self.explain_call(arg_count, Some(builtin), &result);
+        self.stack_push(result)?;
+        self.trace_union(arg_count, untaken);
 
         Ok(())
     }
 
-    fn op_get(&mut self, env: &CompileTimeEnv, runtime_env: &RuntimeEnv) -> ExprResult<()> {
+    /// Call a builtin, reusing the result of an earlier call with the same
+    /// args if it's pure and [`VmConfig::memoize`] is enabled
+    fn call_builtin(
+        &mut self,
+        builtin: &BuiltinFn<'static>,
+        args: Vec<Value>,
+    ) -> ExprResult<Value> {
+        let timeout = self.config.builtin_timeout;
+
+        builtin.check_arity(&args)?;
+
+        if !(self.config.memoize && builtin.pure) {
+            return call_with_timeout(builtin.name, timeout, || (builtin.func)(args));
+        }
+
+        let key = (builtin.clone(), args);
+
+        if let Some(result) = self.memoized.get(&key) {
+            return Ok(result.clone());
+        }
+
+        let result = call_with_timeout(builtin.name, timeout, || (builtin.func)(key.1.clone()))?;
+
+        self.memoized.insert(key, result.clone());
+
+        Ok(result)
+    }
+
+    fn op_get(
+        &mut self,
+        bytecode: &ExprByteCode,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+        secret_provider: Option<&dyn SecretProvider>,
+    ) -> ExprResult<()> {
         // Consume current op: GET
-        self.read_u8();
+        self.read_u8(bytecode)?;
 
-        let get_lookup = self.read_u8();
-        let get_idx = self.read_u8() as usize;
+        let get_lookup = self.read_u8(bytecode)?;
+        let get_idx = self.read_u8(bytecode)? as usize;
 
         match get_lookup {
             BUILTIN => {
                 let value = env
                     .get_builtin(get_idx)
-                    .unwrap_or_else(|| panic!("undefined builtin: {get_idx}"));
-                self.stack_push(Value::Fn(value.clone().into()));
+                    .ok_or_else(|| invalid_bytecode(format!("undefined builtin {get_idx}")))?;
+                self.stack_push(Value::Fn(value.clone().into()))?;
             }
             USER_BUILTIN => {
                 let value = env
                     .get_user_builtin(get_idx)
-                    .unwrap_or_else(|| panic!("undefined user builtin: {get_idx}"));
-                self.stack_push(Value::Fn(value.clone().into()));
-            }
-            VAR => {
-                let value = env
-                    .get_var(get_idx)
-                    .and_then(|_| runtime_env.vars.get(get_idx))
-                    .unwrap_or_else(|| panic!("undefined variable: {get_idx}"));
-
-                self.stack_push(Value::String(value.clone()));
-            }
-            PROMPT => {
-                let value = env
-                    .get_prompt(get_idx)
-                    .and_then(|_| runtime_env.prompts.get(get_idx))
-                    .unwrap_or_else(|| panic!("undefined prompt: {get_idx}"));
-
-                self.stack_push(Value::String(value.clone()));
-            }
-            SECRET => {
-                let value = env
-                    .get_secret(get_idx)
-                    .and_then(|_| runtime_env.secrets.get(get_idx))
-                    .unwrap_or_else(|| panic!("undefined secret: {get_idx}"));
-
-                self.stack_push(Value::String(value.clone()));
+                    .ok_or_else(|| invalid_bytecode(format!("undefined user builtin {get_idx}")))?;
+                self.stack_push(Value::Fn(value.clone().into()))?;
             }
-            CLIENT_CTX => {
-                let value = env
-                    .get_client_context(get_idx)
-                    .and_then(|_| runtime_env.client_context.get(get_idx))
-                    .unwrap_or_else(|| panic!("undefined client context: {get_idx}"));
+            VAR | PROMPT | SECRET | CLIENT_CTX => {
+                let value = self.input(env, runtime_env, secret_provider, get_lookup, get_idx)?;
 
-                self.stack_push(value.clone());
+                self.stack_push(value)?;
             }
             TYPE => {
-                let ty = self
-                    .bytecode
-                    .as_ref()
-                    .unwrap()
+                let ty = bytecode
                     .types()
                     .get(get_idx)
-                    .unwrap_or_else(|| panic!("undefined type: {get_idx}"));
+                    .ok_or_else(|| invalid_bytecode(format!("undefined type {get_idx}")))?;
 
                 if ty.is_type() {
-                    self.stack_push(Value::Type(ty.clone().into()));
+                    self.stack_push(Value::Type(ty.clone().into()))?;
                 } else {
-                    self.stack_push(Value::Type(Type::Type(ty.clone().into()).into()));
+                    self.stack_push(Value::Type(Type::Type(ty.clone().into()).into()))?;
                 }
             }
-            _ => panic!("Invalid get lookup code: {get_lookup}"),
+            _ => {
+                return Err(invalid_bytecode(format!(
+                    "invalid lookup type {get_lookup}"
+                )));
+            }
         };
 
+        if self.explanations.is_some() {
+            let name = |names: Option<&String>, kind: IdentifierKind| {
+                let sigil = kind.sigil().expect("should have a sigil");
+
+                names.map(|name| format!("{sigil}{name}"))
+            };
+
+            let source = match get_lookup {
+                VAR => name(env.get_var(get_idx), IdentifierKind::Var),
+                PROMPT => name(env.get_prompt(get_idx), IdentifierKind::Prompt),
+                SECRET => name(env.get_secret(get_idx), IdentifierKind::Secret),
+                CLIENT_CTX => name(env.get_client_context(get_idx), IdentifierKind::Client),
+                _ => None,
+            };
+
+            self.explain_pushed(source, get_lookup == SECRET);
+        }
+
+        if self.provenances.is_some() {
+            self.trace_pushed(Provenance::of_input(env, get_lookup, get_idx));
+        }
+
         Ok(())
     }
 
-    fn op_constant(&mut self) -> ExprResult<()> {
+    fn op_constant(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
         // Consume current op: CONSTANT
-        self.read_u8();
+        self.read_u8(bytecode)?;
 
-        let get_idx = self.read_u8() as usize;
+        let get_idx = self.read_u8(bytecode)? as usize;
 
-        let s = self
-            .bytecode
-            .as_ref()
-            .expect("should have bytecode")
+        let s = bytecode
             .constants()
             .get(get_idx)
-            .unwrap_or_else(|| panic!("undefined constant: {get_idx}"));
+            .ok_or_else(|| invalid_bytecode(format!("undefined constant {get_idx}")))?;
 
-        self.stack_push(s.clone());
+        self.stack_push(s.clone())?;
+        self.explain_pushed(None, false);
+        self.trace_pushed(Provenance::default());
 
         Ok(())
     }
 
-    fn op_true(&mut self) -> ExprResult<()> {
+    fn op_true(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
         // Consume current op: TRUE
-        self.read_u8();
+        self.read_u8(bytecode)?;
 
-        self.stack_push(Value::Bool(true));
+        self.stack_push(Value::Bool(true))?;
+        self.explain_pushed(None, false);
+        self.trace_pushed(Provenance::default());
 
         Ok(())
     }
 
-    fn op_false(&mut self) -> ExprResult<()> {
+    fn op_false(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
         // Consume current op: FALSE
-        self.read_u8();
+        self.read_u8(bytecode)?;
 
-        self.stack_push(Value::Bool(false));
+        self.stack_push(Value::Bool(false))?;
+        self.explain_pushed(None, false);
+        self.trace_pushed(Provenance::default());
 
         Ok(())
     }
 
-    fn stack_push(&mut self, value: Value) {
-        self.stack.push(value);
+    fn op_list(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        // Consume current op: LIST
+        self.read_u8(bytecode)?;
+
+        let item_count = self.read_u8(bytecode)? as usize;
+
+        let mut items: Vec<Value> = vec![];
+
+        for _ in 0..item_count {
+            items.push(self.stack_pop()?);
+        }
+
+        items.reverse();
+
+        self.stack_push(Value::List(items))?;
+        self.explain_list(item_count);
+        self.trace_union(item_count, None);
+
+        Ok(())
+    }
+
+    fn op_get_local(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        // Consume current op: GET_LOCAL
+        self.read_u8(bytecode)?;
+
+        let slot = self.read_u8(bytecode)? as usize;
+
+        let value = self
+            .locals
+            .get(slot)
+            .ok_or_else(|| invalid_bytecode(format!("undefined local {slot}")))?
+            .clone();
+
+        self.stack_push(value)?;
+
+        if let Some(explanations) = &mut self.explanations
+            && let Some(explanation) = self.explained_locals.get(slot)
+        {
+            explanations.push(explanation.clone());
+        }
+
+        if self.provenances.is_some() {
+            self.trace_pushed(self.traced_locals.get(slot).cloned().unwrap_or_default());
+        }
+
+        Ok(())
+    }
+
+    fn op_set_local(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        // Consume current op: SET_LOCAL
+        self.read_u8(bytecode)?;
+
+        let slot = self.read_u8(bytecode)? as usize;
+
+        let value = self.stack_pop()?;
+
+        set_slot(&mut self.locals, slot, value)?;
+
+        if let Some(explanation) = self
+            .explanations
+            .as_mut()
+            .and_then(|explanations| explanations.pop())
+        {
+            set_slot(&mut self.explained_locals, slot, explanation)?;
+        }
+
+        if let Some(provenance) = self
+            .provenances
+            .as_mut()
+            .and_then(|provenances| provenances.pop())
+        {
+            set_slot(&mut self.traced_locals, slot, provenance)?;
+        }
+
+        Ok(())
+    }
+
+    fn op_jump_if_false(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        let op_ip = self.ip;
+
+        // Consume current op: JUMP_IF_FALSE
+        self.read_u8(bytecode)?;
+
+        let offset = self.read_u16(bytecode)?;
+
+        let condition = self.stack_pop()?.get_bool()?;
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_cond(op_ip, condition);
+        }
+
+        if let Some(explanation) = self
+            .explanations
+            .as_mut()
+            .and_then(|explanations| explanations.pop())
+            && let Some(end) = opcode::branch_end(bytecode.codes(), op_ip)
+        {
+            self.explained_branches.push((
+                end,
+                ExplainedBranch::Cond {
+                    then: condition,
+                    condition: explanation,
+                },
+            ));
+        }
+
+        if let Some(provenance) = self
+            .provenances
+            .as_mut()
+            .and_then(|provenances| provenances.pop())
+            && let Some(end) = opcode::branch_end(bytecode.codes(), op_ip)
+        {
+            self.traced_branches.push((end, provenance));
+        }
+
+        if !condition {
+            self.ip += offset;
+        }
+
+        Ok(())
+    }
+
+    fn op_jump(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        // Consume current op: JUMP
+        self.read_u8(bytecode)?;
+
+        let offset = self.read_u16(bytecode)?;
+
+        self.ip += offset;
+
+        Ok(())
+    }
+
+    fn op_try(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        // Consume current op: TRY
+        self.read_u8(bytecode)?;
+
+        let offset = self.read_u16(bytecode)?;
+
+        self.handlers.push(ErrorHandler {
+            fallback_ip: self.ip + offset,
+            stack_len: self.stack.len(),
+        });
+
+        Ok(())
+    }
+
+    fn op_end_try(&mut self, bytecode: &ExprByteCode) -> ExprResult<()> {
+        // Consume current op: END_TRY
+        self.read_u8(bytecode)?;
+
+        let offset = self.read_u16(bytecode)?;
+
+        self.handlers.pop();
+        self.ip += offset;
+
+        if let Some(explanations) = &mut self.explanations
+            && let Some(guarded) = explanations.pop()
+        {
+            explanations.push(Explanation::new_try(false, guarded));
+        }
+
+        Ok(())
+    }
+
+    /// Abandon the ops guarded by a `try` and continue with its fallback ops
+    fn catch(&mut self, bytecode: &ExprByteCode, handler: ErrorHandler) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(ip = self.ip, fallback_ip = handler.fallback_ip, "caught");
+
+        self.ip = handler.fallback_ip;
+        self.stack.truncate(handler.stack_len);
+
+        if let Some(explanations) = &mut self.explanations {
+            explanations.truncate(handler.stack_len);
+        }
+
+        // Branches in the guarded ops end before the fallback ops
+        self.explained_branches
+            .retain(|(end, _)| *end > handler.fallback_ip);
+        self.traced_branches
+            .retain(|(end, _)| *end > handler.fallback_ip);
+
+        // The fallback ops end where the END_TRY right before them jumps to
+        let Some(fallback_end) = handler
+            .fallback_ip
+            .checked_sub(2)
+            .and_then(|operands_ip| bytecode.codes().get(operands_ip..))
+            .and_then(opcode::jump_offset)
+            .map(|offset| handler.fallback_ip + offset)
+        else {
+            return;
+        };
+
+        if self.explanations.is_some() {
+            self.explained_branches
+                .push((fallback_end, ExplainedBranch::Fallback));
+        }
+
+        // The values the guarded ops had evaluated, including the args of the
+        // op that failed, decided the fallback ops were taken
+        if let Some(provenances) = &mut self.provenances {
+            let guarded = provenances.split_off(handler.stack_len.min(provenances.len()));
+
+            self.traced_branches
+                .push((fallback_end, guarded.into_iter().collect()));
+        }
+    }
+
+    fn stack_push(&mut self, value: Value) -> ExprResult<()> {
+        if self.stack.len() >= self.config.max_stack_depth {
+            return Err(vec![(
+                RuntimeError::StackOverflow {
+                    max: self.config.max_stack_depth,
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        self.stack.push(value);
+
+        Ok(())
+    }
+
+    fn check_output_size(&self, value: &Value) -> ExprResult<()> {
+        if let Value::String(string) = value
+            && string.len() > self.config.max_output_size
+        {
+            return Err(vec![(
+                RuntimeError::OutputTooLarge {
+                    size: string.len(),
+                    max: self.config.max_output_size,
+                }
+                .into(),
+                0..0,
+            )]);
+        }
+
+        Ok(())
+    }
+
+    /// Explain the value the last op pushed if explaining
+    ///
+    /// Values without a `source` are explained by their source form.
+    fn explain_pushed(&mut self, source: Option<String>, secret: bool) {
+        if let Some(explanations) = &mut self.explanations
+            && let Some(value) = self.stack.last()
+        {
+            let source = source
+                .unwrap_or_else(|| value.to_expr_source().unwrap_or_else(|| value.to_string()));
+
+            explanations.push(Explanation::new_value(source, value, secret));
+        }
+    }
+
+    /// Replace the explanations of a call's callee and args with one of the
+    /// call if explaining
+    ///
+    /// Fused calls don't push their callee so it's explained from `fused`.
+    fn explain_call(
+        &mut self,
+        arg_count: usize,
+        fused: Option<&BuiltinFn<'static>>,
+        result: &Value,
+    ) {
+        let Some(explanations) = &mut self.explanations else {
+            return;
+        };
+
+        let args = explanations.split_off(explanations.len().saturating_sub(arg_count));
+
+        let callee = match fused {
+            Some(builtin) => Explanation::new_value(
+                builtin.name.to_string(),
+                &Value::Fn(builtin.clone().into()),
+                false,
+            ),
+            None => explanations
+                .pop()
+                .expect("should have explained the callee"),
+        };
+
+        explanations.push(Explanation::new_call(callee, args, result));
+    }
+
+    /// Replace the explanations of a list's items with one of the list if
+    /// explaining
+    fn explain_list(&mut self, item_count: usize) {
+        if let Some(explanations) = &mut self.explanations
+            && let Some(value) = self.stack.last()
+        {
+            let items = explanations.split_off(explanations.len().saturating_sub(item_count));
+
+            explanations.push(Explanation::new_list(items, value));
+        }
+    }
+
+    /// Replace the explanation of the branch taken with one of the `cond`, or
+    /// of the fallback ops with one of the `try`, for each branch ending at
+    /// the instruction pointer if explaining
+    fn explain_branches(&mut self) {
+        let Some(explanations) = &mut self.explanations else {
+            return;
+        };
+
+        while self
+            .explained_branches
+            .last()
+            .is_some_and(|(end, _)| *end == self.ip)
+        {
+            let (_, branch) = self
+                .explained_branches
+                .pop()
+                .expect("should have a branch ending");
+
+            if let Some(taken) = explanations.pop() {
+                explanations.push(match branch {
+                    ExplainedBranch::Cond { then, condition } => {
+                        Explanation::new_branch(condition, then, taken)
+                    }
+                    ExplainedBranch::Fallback => Explanation::new_try(true, taken),
+                });
+            }
+        }
+    }
+
+    /// Trace the value the last op pushed if tracing
+    fn trace_pushed(&mut self, provenance: Provenance) {
+        if let Some(provenances) = &mut self.provenances {
+            provenances.push(provenance);
+        }
+    }
+
+    /// Replace the provenance of the values the last op popped with the union
+    /// of them, except the one at `untaken`, for the value it pushed if
+    /// tracing
+    fn trace_union(&mut self, count: usize, untaken: Option<usize>) {
+        if let Some(provenances) = &mut self.provenances {
+            let popped = provenances.split_off(provenances.len().saturating_sub(count));
+
+            provenances.push(
+                popped
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(index, _)| Some(*index) != untaken)
+                    .map(|(_, provenance)| provenance)
+                    .collect(),
+            );
+        }
+    }
+
+    /// Add the provenance of what decided each branch ending at the
+    /// instruction pointer was taken to the branch's value if tracing
+    fn trace_branches(&mut self) {
+        let Some(provenances) = &mut self.provenances else {
+            return;
+        };
+
+        while self
+            .traced_branches
+            .last()
+            .is_some_and(|(end, _)| *end == self.ip)
+        {
+            let (_, provenance) = self
+                .traced_branches
+                .pop()
+                .expect("should have a branch ending");
+
+            if let Some(taken) = provenances.last_mut() {
+                taken.extend(provenance);
+            }
+        }
+    }
+
+    /// Record which branch a call to `cond` takes if coverage is enabled
+    fn record_cond(&mut self, op_ip: usize, builtin: &BuiltinFn, args: &[Value]) {
+        if let Some(coverage) = &mut self.coverage
+            && builtin.name == BuiltinFn::COND.name
+            && let Some(Value::Bool(condition)) = args.first()
+        {
+            coverage.record_cond(op_ip, *condition);
+        }
     }
 
     fn stack_pop(&mut self) -> ExprResult<Value> {
@@ -243,24 +1566,186 @@ impl Vm {
         Err(vec![(RuntimeError::EmptyStack.into(), 0..0)])
     }
 
-    fn read_u8(&mut self) -> u8 {
-        let current_ip = self.ip as u8;
+    /// The value of a var, prompt, secret, or client context
+    ///
+    /// Each is read from the runtime env, or fetched from the secret provider,
+    /// once per evaluation. Reading it again clones the cached value, which
+    /// shares its string.
+    fn input(
+        &mut self,
+        env: &CompileTimeEnv,
+        runtime_env: &RuntimeEnv,
+        secret_provider: Option<&dyn SecretProvider>,
+        get_lookup: u8,
+        get_idx: usize,
+    ) -> ExprResult<Value> {
+        if let Some(value) = self.inputs.get(&(get_lookup, get_idx)) {
+            return Ok(value.clone());
+        }
+
+        let value = match get_lookup {
+            VAR => env
+                .get_var(get_idx)
+                .and_then(|_| runtime_env.vars.get(get_idx))
+                .map(|value| Value::from(value.as_str()))
+                .ok_or_else(|| invalid_bytecode(format!("undefined variable {get_idx}")))?,
+            PROMPT => env
+                .get_prompt(get_idx)
+                .and_then(|_| runtime_env.prompts.get(get_idx))
+                .map(|value| Value::from(value.as_str()))
+                .ok_or_else(|| invalid_bytecode(format!("undefined prompt {get_idx}")))?,
+            SECRET => {
+                let name = env
+                    .get_secret(get_idx)
+                    .ok_or_else(|| invalid_bytecode(format!("undefined secret {get_idx}")))?;
+
+                match (runtime_env.secrets.get(get_idx), secret_provider) {
+                    (Some(value), _) => Value::from(value.as_str()),
+                    (None, Some(secret_provider)) => {
+                        let value = secret_provider.get_secret(name).map_err(|err| {
+                            vec![(
+                                RuntimeError::SecretUnavailable {
+                                    name: name.clone(),
+                                    message: err.to_string(),
+                                }
+                                .into(),
+                                0..0,
+                            )]
+                        })?;
+
+                        let provided = Value::from(value.as_str());
+
+                        #[cfg(feature = "zeroize")]
+                        drop(zeroize::Zeroizing::new(value));
+
+                        provided
+                    }
+                    (None, None) => {
+                        return Err(invalid_bytecode(format!("undefined secret {get_idx}")));
+                    }
+                }
+            }
+            _ => env
+                .get_client_context(get_idx)
+                .and_then(|_| runtime_env.client_context.get(get_idx))
+                .cloned()
+                .ok_or_else(|| invalid_bytecode(format!("undefined client context {get_idx}")))?,
+        };
+
+        self.inputs.insert((get_lookup, get_idx), value.clone());
+
+        Ok(value)
+    }
+
+    /// Read a big endian `u16` operand
+    fn read_u16(&mut self, bytecode: &ExprByteCode) -> ExprResult<usize> {
+        Ok(u16::from_be_bytes([self.read_u8(bytecode)?, self.read_u8(bytecode)?]) as usize)
+    }
+
+    /// Read the byte at the instruction pointer, erroring if the bytecode
+    /// ends before it
+    fn read_u8(&mut self, bytecode: &ExprByteCode) -> ExprResult<u8> {
+        let current_ip = self.ip;
 
         self.ip += 1;
 
-        *self
-            .bytecode
-            .as_ref()
-            .expect("should have bytecode")
-            .codes()
-            .get(current_ip as usize)
-            .expect("should have op in bytecode at {}")
+        bytecode.codes().get(current_ip).copied().ok_or_else(|| {
+            invalid_bytecode(format!(
+                "bytecode ends at {current_ip} in the middle of an op"
+            ))
+        })
+    }
+}
+
+/// Index of the arg a call to `cond` doesn't return, if the call is to `cond`
+fn untaken_cond_arg(builtin: &BuiltinFn, args: &[Value]) -> Option<usize> {
+    if builtin.name != BuiltinFn::COND.name || args.len() != 3 {
+        return None;
+    }
+
+    match args.first() {
+        Some(Value::Bool(true)) => Some(2),
+        Some(Value::Bool(false)) => Some(1),
+        _ => None,
+    }
+}
+
+/// [`RuntimeError::InvalidBytecode`] for bytecode referencing something that
+/// doesn't exist
+pub(crate) fn invalid_bytecode(message: String) -> Vec<ExprErrorS> {
+    vec![(RuntimeError::InvalidBytecode { message }.into(), 0..0)]
+}
+
+/// If the errors end the whole evaluation instead of being caught by a `try`
+///
+/// Overflowing the stack is a limit on the whole evaluation and invalid
+/// bytecode can't be run any further.
+pub(crate) fn is_fatal(errs: &[ExprErrorS]) -> bool {
+    errs.iter().any(|(err, _)| {
+        matches!(
+            err,
+            ExprError::RuntimeError(
+                RuntimeError::StackOverflow { .. } | RuntimeError::InvalidBytecode { .. }
+            )
+        )
+    })
+}
+
+/// Set the value in a slot, replacing the value of a `let` that's gone out
+/// of scope
+///
+/// The compiler numbers slots by nesting so a slot is never set before the
+/// slots below it. Hand built or deserialized bytecode can, which is a
+/// [`RuntimeError::InvalidBytecode`].
+pub(crate) fn set_slot<T>(slots: &mut Vec<T>, slot: usize, value: T) -> ExprResult<()> {
+    let len = slots.len();
+
+    match slots.get_mut(slot) {
+        Some(existing) => *existing = value,
+        None if slot == len => slots.push(value),
+        None => {
+            return Err(invalid_bytecode(format!(
+                "local {slot} set before local {len}"
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// A hash of the op codes and constants in bytecode
+#[cfg(feature = "tracing")]
+fn bytecode_hash(bytecode: &ExprByteCode) -> u64 {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    bytecode.codes().hash(&mut hasher);
+
+    for constant in bytecode.constants() {
+        constant.to_string().hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+/// Name of a function value for `call` spans
+#[cfg(feature = "tracing")]
+fn callee_name(value: &Value) -> String {
+    match value {
+        Value::Fn(builtin) => builtin.name.to_string(),
+        value => value.to_string(),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{compiler::get_version_bytes, errors::ExprError, prelude::lookup};
+    use std::sync::Arc;
+
+    use crate::{
+        builtins::{BuiltinCategory, FnArg, FnNamespace},
+        compiler::get_version_bytes,
+        prelude::lookup,
+    };
 
     use super::*;
 
@@ -294,7 +1779,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Invalid OP code: 99")]
     fn test_invalid_opcode_99() {
         let mut vm = Vm::new();
 
@@ -305,12 +1789,79 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        // Attempt to interpret the bytecode, expecting a panic due to invalid opcode 99
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "invalid op code 99".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn test_truncated_ops() {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let runtime_env = RuntimeEnv {
+            vars: vec!["a_value".to_string()],
+            ..Default::default()
+        };
+
+        for (name, truncated, ends_at) in [
+            ("GET", &[opcode::GET, lookup::VAR][..], 2),
+            ("CALL", &[opcode::GET, lookup::BUILTIN, 0, opcode::CALL], 4),
+            ("CONSTANT", &[opcode::CONSTANT], 1),
+            ("JUMP", &[opcode::JUMP, 0], 2),
+            ("TRY", &[opcode::TRY, 0], 2),
+        ] {
+            let mut codes = get_version_bytes().to_vec();
+            codes.extend(truncated);
+
+            let bytecode = Box::new(ExprByteCode::new(codes, vec![Value::Bool(true)], vec![]));
+
+            assert_eq!(
+                Err(vec![(
+                    RuntimeError::InvalidBytecode {
+                        message: format!("bytecode ends at {ends_at} in the middle of an op")
+                    }
+                    .into(),
+                    0..0
+                )]),
+                Vm::new().interpret(bytecode.clone(), &env, &runtime_env),
+                "{name}"
+            );
+
+            assert!(
+                Vm::new()
+                    .interpret_suspendable(&bytecode, &env, &runtime_env)
+                    .is_err(),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_truncated_op_in_try_isnt_caught() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.extend([opcode::TRY, 0, 2, opcode::CONSTANT]);
+
+        let bytecode = Box::new(ExprByteCode::new(codes, vec![], vec![]));
+
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "bytecode ends at 4 in the middle of an op".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            Vm::new().interpret(bytecode, &CompileTimeEnv::default(), &RuntimeEnv::default())
+        );
     }
 
     #[test]
-    #[should_panic(expected = "Invalid get lookup code: 99")]
     fn test_invalid_look_99() {
         let mut vm = Vm::new();
 
@@ -323,12 +1874,19 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        // Attempt to interpret the bytecode, expecting a panic due to invalid opcode 99
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "invalid lookup type 99".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "undefined variable: 99")]
     fn undefined_variable() {
         let mut vm = Vm::new();
 
@@ -341,11 +1899,19 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined variable 99".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "undefined prompt: 99")]
     fn undefined_prompt() {
         let mut vm = Vm::new();
 
@@ -358,11 +1924,19 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined prompt 99".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "undefined secret: 99")]
     fn undefined_secret() {
         let mut vm = Vm::new();
 
@@ -375,47 +1949,98 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined secret 99".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "undefined builtin: 255")]
-    fn undefined_builtin() {
+    fn call_spread_without_a_list() {
         let mut vm = Vm::new();
 
         let mut codes = get_version_bytes().to_vec();
         codes.push(opcode::GET);
         codes.push(lookup::BUILTIN);
-        codes.push(255);
+        codes.push(0);
+        codes.push(opcode::CALL_SPREAD);
+        codes.push(0);
 
         let bytecode = Box::new(ExprByteCode::new(codes, vec![], vec![]));
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "CALL_SPREAD without a list".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "undefined user builtin: 255")]
-    fn undefined_user_builtin() {
+    fn undefined_builtin() {
         let mut vm = Vm::new();
 
         let mut codes = get_version_bytes().to_vec();
         codes.push(opcode::GET);
-        codes.push(lookup::USER_BUILTIN);
+        codes.push(lookup::BUILTIN);
         codes.push(255);
 
         let bytecode = Box::new(ExprByteCode::new(codes, vec![], vec![]));
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
-    }
-
-    #[test]
-    #[should_panic(expected = "undefined client context: 255")]
-    fn undefined_client_context() {
-        let mut vm = Vm::new();
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined builtin 255".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn undefined_user_builtin() {
+        let mut vm = Vm::new();
+
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::GET);
+        codes.push(lookup::USER_BUILTIN);
+        codes.push(255);
+
+        let bytecode = Box::new(ExprByteCode::new(codes, vec![], vec![]));
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined user builtin 255".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn undefined_client_context() {
+        let mut vm = Vm::new();
 
         let mut codes = get_version_bytes().to_vec();
         codes.push(opcode::GET);
@@ -426,11 +2051,19 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined client context 255".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "undefined type: 255")]
     fn undefined_type() {
         let mut vm = Vm::new();
 
@@ -443,11 +2076,19 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined type 255".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
     }
 
     #[test]
-    #[should_panic(expected = "undefined constant: 255")]
     fn undefined_constant() {
         let mut vm = Vm::new();
 
@@ -459,6 +2100,895 @@ mod tests {
         let env = CompileTimeEnv::default();
         let runtime_env = RuntimeEnv::default();
 
-        let _ = vm.interpret(bytecode, &env, &runtime_env);
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined constant 255".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn eval_all_returns_results_in_order() {
+        let mut vm = Vm::new();
+
+        let mut true_codes = get_version_bytes().to_vec();
+        true_codes.push(opcode::TRUE);
+
+        let mut empty_stack_codes = get_version_bytes().to_vec();
+        empty_stack_codes.push(opcode::GET);
+        empty_stack_codes.push(lookup::BUILTIN);
+        empty_stack_codes.push(0);
+        empty_stack_codes.push(opcode::CALL);
+        empty_stack_codes.push(1);
+
+        let mut constant_codes = get_version_bytes().to_vec();
+        constant_codes.push(opcode::CONSTANT);
+        constant_codes.push(0);
+
+        let bytecodes = vec![
+            ExprByteCode::new(true_codes, vec![], vec![]),
+            ExprByteCode::new(empty_stack_codes, vec![], vec![]),
+            ExprByteCode::new(
+                constant_codes,
+                vec![Value::String("constant".into())],
+                vec![],
+            ),
+        ];
+
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            vec![
+                Ok(Value::Bool(true)),
+                Err(vec![(
+                    ExprError::RuntimeError(RuntimeError::EmptyStack),
+                    0..0
+                )]),
+                Ok(Value::String("constant".into())),
+            ],
+            vm.eval_all(&bytecodes, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn eval_all_resets_stack_between_expressions() {
+        let mut vm = Vm::new();
+
+        // Leaves an extra value on the stack
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::TRUE);
+        codes.push(opcode::FALSE);
+
+        let mut empty_stack_codes = get_version_bytes().to_vec();
+        empty_stack_codes.push(opcode::GET);
+        empty_stack_codes.push(lookup::BUILTIN);
+        empty_stack_codes.push(0);
+        empty_stack_codes.push(opcode::CALL);
+        empty_stack_codes.push(1);
+
+        let bytecodes = vec![
+            ExprByteCode::new(codes, vec![], vec![]),
+            ExprByteCode::new(empty_stack_codes, vec![], vec![]),
+        ];
+
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            vec![
+                Ok(Value::Bool(false)),
+                Err(vec![(
+                    ExprError::RuntimeError(RuntimeError::EmptyStack),
+                    0..0
+                )]),
+            ],
+            vm.eval_all(&bytecodes, &env, &runtime_env)
+        );
+    }
+
+    fn runtime_env_mismatch() -> ExprResult<Value> {
+        Err(vec![(
+            CompileError::RuntimeEnvMismatch {
+                lookup: "var".to_string(),
+                expected: 1,
+                actual: 0,
+            }
+            .into(),
+            0..0,
+        )])
+    }
+
+    fn var_env() -> (CompileTimeEnv, ExprByteCode) {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let source = ":a";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        (env, bytecode)
+    }
+
+    #[test]
+    fn bytecode_compiled_with_another_env() {
+        let (_, bytecode) = var_env();
+
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined variable 0".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            Vm::new().interpret(
+                bytecode.into(),
+                &CompileTimeEnv::default(),
+                &RuntimeEnv::default()
+            )
+        );
+    }
+
+    #[test]
+    fn eval_all_validates_runtime_env() {
+        let (env, bytecode) = var_env();
+
+        assert_eq!(
+            vec![runtime_env_mismatch(), runtime_env_mismatch()],
+            Vm::new().eval_all(&[bytecode.clone(), bytecode], &env, &RuntimeEnv::default())
+        );
+    }
+
+    #[test]
+    fn eval_many_validates_runtime_env() {
+        let (env, bytecode) = var_env();
+
+        assert_eq!(
+            vec![runtime_env_mismatch()],
+            Vm::new().eval_many(vec![Ok(bytecode)], &env, &RuntimeEnv::default())
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_eval_all_validates_runtime_env() {
+        let (env, bytecode) = var_env();
+
+        assert_eq!(
+            vec![runtime_env_mismatch()],
+            Vm::par_eval_all(&[bytecode], &env, &RuntimeEnv::default())
+        );
+    }
+
+    #[test]
+    fn explain_validates_runtime_env() {
+        let (env, bytecode) = var_env();
+
+        assert_eq!(
+            runtime_env_mismatch(),
+            Vm::new()
+                .explain(&bytecode, &env, &RuntimeEnv::default())
+                .map(|(value, _)| value)
+        );
+    }
+
+    #[test]
+    fn suspendable_validates_runtime_env() {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec!["p".to_string()], vec![], vec![]);
+        let source = "(concat :a ?p)";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["a".to_string()],
+            ..Default::default()
+        };
+
+        // Prompts and secrets can be missing since they're suspended on
+        let Ok(Evaluation::Suspended(snapshot)) =
+            Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+        else {
+            panic!("should suspend waiting on ?p");
+        };
+
+        assert_eq!(
+            Err(runtime_env_mismatch().unwrap_err()),
+            Vm::new().interpret_suspendable(&bytecode, &env, &RuntimeEnv::default())
+        );
+        assert_eq!(
+            Err(runtime_env_mismatch().unwrap_err()),
+            Vm::new().resume(snapshot, &bytecode, &env, &RuntimeEnv::default())
+        );
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_eval_all_returns_results_in_order() {
+        let bytecodes: Vec<ExprByteCode> = (0..64)
+            .map(|i| {
+                let mut codes = get_version_bytes().to_vec();
+                codes.push(if i % 2 == 0 {
+                    opcode::TRUE
+                } else {
+                    opcode::FALSE
+                });
+
+                ExprByteCode::new(codes, vec![], vec![])
+            })
+            .collect();
+
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        let expected: Vec<ExprResult<Value>> =
+            (0..64).map(|i| Ok(Value::Bool(i % 2 == 0))).collect();
+
+        assert_eq!(expected, Vm::par_eval_all(&bytecodes, &env, &runtime_env));
+    }
+
+    #[test]
+    fn call_builtin() {
+        let mut vm = Vm::new();
+
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::CONSTANT);
+        codes.push(0);
+        codes.push(opcode::CALL_BUILTIN);
+        codes.push(0);
+        codes.push(1);
+
+        let bytecode = Box::new(ExprByteCode::new(
+            codes,
+            vec![Value::String("value".into())],
+            vec![],
+        ));
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            Ok(Value::String("value".into())),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn call_undefined_builtin() {
+        let mut vm = Vm::new();
+
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::CALL_BUILTIN);
+        codes.push(255);
+        codes.push(0);
+
+        let bytecode = Box::new(ExprByteCode::new(codes, vec![], vec![]));
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            Err(vec![(
+                RuntimeError::InvalidBytecode {
+                    message: "undefined builtin 255".to_string()
+                }
+                .into(),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn with_config_preallocates_stack() {
+        let vm = Vm::with_config(VmConfig {
+            initial_stack_capacity: 64,
+            ..Default::default()
+        });
+
+        assert!(vm.stack.capacity() >= 64);
+        assert_eq!(64, vm.config().initial_stack_capacity);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut vm = Vm::with_config(VmConfig {
+            max_stack_depth: 2,
+            ..Default::default()
+        });
+
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::TRUE);
+        codes.push(opcode::TRUE);
+        codes.push(opcode::TRUE);
+        codes.push(opcode::TRUE);
+
+        let bytecode = Box::new(ExprByteCode::new(codes, vec![], vec![]));
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::StackOverflow { max: 2 }),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn output_too_large() {
+        let mut vm = Vm::with_config(VmConfig {
+            max_output_size: 4,
+            ..Default::default()
+        });
+
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        let source = "(concat `ab` (concat `cd` `ef`))";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::OutputTooLarge { size: 6, max: 4 }),
+                0..0
+            )]),
+            vm.interpret(bytecode.into(), &env, &runtime_env)
+        );
+
+        let source = "(concat `ab` `cd`)";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        assert_eq!(
+            Ok(Value::String("abcd".into())),
+            vm.interpret(bytecode.into(), &env, &runtime_env)
+        );
+    }
+
+    fn interpret_with_timeout(builtin: BuiltinFn<'static>) -> ExprResult<Value> {
+        let mut vm = Vm::with_config(VmConfig {
+            builtin_timeout: Some(Duration::from_millis(5)),
+            ..Default::default()
+        });
+
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(builtin);
+
+        let source = "(slow)";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        vm.interpret(bytecode.into(), &env, &RuntimeEnv::default())
+    }
+
+    #[test]
+    fn builtin_timeout() {
+        let result = interpret_with_timeout(BuiltinFn {
+            name: "slow",
+            args: &[],
+            return_type: Type::String,
+            func: |_| {
+                std::thread::sleep(Duration::from_millis(20));
+
+                Ok(Value::String("b".into()))
+            },
+            namespace: FnNamespace::User,
+            pure: false,
+            category: BuiltinCategory::Value,
+            capability: None,
+        });
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::BuiltinTimeout {
+                    name: "slow".to_string()
+                }),
+                0..6
+            )]),
+            result
+        );
+    }
+
+    #[test]
+    fn builtin_timeout_checked_cooperatively() {
+        let result = interpret_with_timeout(BuiltinFn {
+            name: "slow",
+            args: &[],
+            return_type: Type::String,
+            func: |_| {
+                loop {
+                    crate::builtins::check_timeout()?;
+                }
+            },
+            namespace: FnNamespace::User,
+            pure: false,
+            category: BuiltinCategory::Value,
+            capability: None,
+        });
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::BuiltinTimeout {
+                    name: "slow".to_string()
+                }),
+                0..6
+            )]),
+            result
+        );
+        assert_eq!(Ok(()), crate::builtins::check_timeout());
+    }
+
+    #[test]
+    fn builtin_errors_are_reported_at_their_call() {
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(BuiltinFn {
+            name: "fail",
+            args: &[],
+            return_type: Type::String,
+            func: |_| {
+                Err(vec![(
+                    RuntimeError::InvalidArgument {
+                        name: "value".to_string(),
+                        message: "failed".to_string(),
+                    }
+                    .into(),
+                    0..0,
+                )])
+            },
+            namespace: FnNamespace::User,
+            pure: false,
+            category: BuiltinCategory::Value,
+            capability: None,
+        });
+
+        let source = "(concat `a` (fail))";
+        let ast = crate::parser::parse(source).unwrap();
+        let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+        let errs = Vm::new()
+            .interpret(bytecode.clone().into(), &env, &RuntimeEnv::default())
+            .unwrap_err();
+
+        assert_eq!(12..18, errs[0].1);
+
+        // Bytecode without call spans reports errors where the builtin did
+        let unspanned = ExprByteCode::from_bytes(&bytecode.to_bytes()).unwrap();
+        let errs = Vm::new()
+            .interpret(unspanned.into(), &env, &RuntimeEnv::default())
+            .unwrap_err();
+
+        assert_eq!(0..0, errs[0].1);
+    }
+
+    static HASH_CALLS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    fn count_hash_calls(memoize: bool, pure: bool) -> usize {
+        use std::sync::atomic::Ordering;
+
+        HASH_CALLS.store(0, Ordering::SeqCst);
+
+        let mut vm = Vm::with_config(VmConfig {
+            memoize,
+            ..Default::default()
+        });
+
+        let mut env = CompileTimeEnv::default();
+        env.add_user_builtin(BuiltinFn {
+            name: "hash",
+            args: &[FnArg {
+                name: "value",
+                ty: Type::String,
+                variadic: false,
+                optional: false,
+            }],
+            return_type: Type::String,
+            func: |args| {
+                HASH_CALLS.fetch_add(1, Ordering::SeqCst);
+
+                Ok(args[0].clone())
+            },
+            namespace: FnNamespace::User,
+            pure,
+            category: BuiltinCategory::Value,
+            capability: None,
+        });
+
+        for source in ["(hash `a`)", "(concat (hash `a`) (hash `b`))"] {
+            let ast = crate::parser::parse(source).unwrap();
+            let bytecode = crate::compiler::compile(&mut (ast, 0..source.len()), &env).unwrap();
+
+            vm.interpret(bytecode.into(), &env, &RuntimeEnv::default())
+                .unwrap();
+        }
+
+        HASH_CALLS.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn memoize_pure_builtins() {
+        assert_eq!(3, count_hash_calls(false, true));
+        assert_eq!(3, count_hash_calls(true, false));
+        assert_eq!(2, count_hash_calls(true, true));
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn wipe_and_zeroize_secrets() {
+        use zeroize::Zeroize;
+
+        let env = CompileTimeEnv::new(vec![], vec![], vec!["token".to_string()], vec![]);
+
+        let mut runtime_env = RuntimeEnv {
+            secrets: vec!["abc".to_string()],
+            ..Default::default()
+        };
+
+        let mut vm = Vm::with_config(VmConfig {
+            memoize: true,
+            ..Default::default()
+        });
+
+        let bytecode = compile_source("(uppercase !token)", &env);
+
+        assert_eq!(
+            Ok(Value::String("ABC".into())),
+            vm.interpret(bytecode.into(), &env, &runtime_env)
+        );
+        assert_eq!(1, vm.memoized.len());
+
+        vm.wipe();
+
+        assert!(vm.memoized.is_empty());
+        assert!(vm.stack.is_empty());
+
+        runtime_env.zeroize();
+
+        assert!(runtime_env.secrets.is_empty());
+
+        let mut value = Value::List(vec![Value::String("abc".into()), Value::Bool(true)]);
+        value.zeroize();
+
+        assert_eq!(Value::List(vec![]), value);
+    }
+
+    #[test]
+    fn too_many_constants() {
+        let mut vm = Vm::with_config(VmConfig {
+            max_constants: 1,
+            ..Default::default()
+        });
+
+        let mut codes = get_version_bytes().to_vec();
+        codes.push(opcode::CONSTANT);
+        codes.push(0);
+
+        let bytecode = Box::new(ExprByteCode::new(
+            codes,
+            vec![Value::String("a".into()), Value::String("b".into())],
+            vec![],
+        ));
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::TooManyConstants { max: 1 }),
+                0..0
+            )]),
+            vm.interpret(bytecode, &env, &runtime_env)
+        );
+    }
+
+    fn compile_source(source: &str, env: &CompileTimeEnv) -> ExprByteCode {
+        let ast = crate::parser::parse(source).unwrap();
+
+        crate::compiler::compile(&mut (ast, 0..source.len()), env).unwrap()
+    }
+
+    #[test]
+    fn suspend_and_resume() {
+        let env = CompileTimeEnv::new(
+            vec!["greeting".to_string()],
+            vec!["name".to_string()],
+            vec!["token".to_string()],
+            vec![],
+        );
+        let bytecode = compile_source("(concat :greeting ` ` ?name ` ` !token)", &env);
+
+        let mut runtime_env = RuntimeEnv {
+            vars: vec!["Hello".to_string()],
+            ..Default::default()
+        };
+
+        let Ok(Evaluation::Suspended(snapshot)) =
+            Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+        else {
+            panic!("should suspend on ?name");
+        };
+
+        assert_eq!(
+            &PendingValue::Prompt {
+                index: 0,
+                name: "name".to_string()
+            },
+            snapshot.pending()
+        );
+        assert_eq!(
+            &[
+                Value::Fn(crate::builtins::BuiltinFn::CONCAT.into()),
+                Value::String("Hello".into()),
+                Value::String(" ".into()),
+            ],
+            snapshot.stack()
+        );
+
+        runtime_env.prompts.push("World".to_string());
+
+        let Ok(Evaluation::Suspended(snapshot)) =
+            Vm::new().resume(snapshot, &bytecode, &env, &runtime_env)
+        else {
+            panic!("should suspend on !token");
+        };
+
+        assert_eq!(
+            &PendingValue::Secret {
+                index: 0,
+                name: "token".to_string()
+            },
+            snapshot.pending()
+        );
+
+        // Continue in another "process" from the serialized snapshot
+        let snapshot = VmSnapshot::from_bytes(&snapshot.to_bytes(), &env).unwrap();
+
+        runtime_env.secrets.push("abc".to_string());
+
+        assert_eq!(
+            Ok(Evaluation::Complete(Value::String(
+                "Hello World abc".into()
+            ))),
+            Vm::new().resume(snapshot, &bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn suspend_and_resume_in_let() {
+        let env = CompileTimeEnv::new(
+            vec!["greeting".to_string()],
+            vec!["name".to_string()],
+            vec![],
+            vec![],
+        );
+        let bytecode = compile_source("(let (x (uppercase :greeting)) (concat x ?name x))", &env);
+
+        let mut runtime_env = RuntimeEnv {
+            vars: vec!["Hello".to_string()],
+            ..Default::default()
+        };
+
+        let Ok(Evaluation::Suspended(snapshot)) =
+            Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+        else {
+            panic!("should suspend on ?name");
+        };
+
+        assert_eq!(&[Value::String("HELLO".into())], snapshot.locals());
+
+        let snapshot = VmSnapshot::from_bytes(&snapshot.to_bytes(), &env).unwrap();
+
+        runtime_env.prompts.push(" ".to_string());
+
+        assert_eq!(
+            Ok(Evaluation::Complete(Value::String("HELLO HELLO".into()))),
+            Vm::new().resume(snapshot, &bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn suspend_and_resume_in_try() {
+        let env = CompileTimeEnv::new(vec![], vec!["region".to_string()], vec![], vec![]);
+        let bytecode = compile_source(
+            "(concat `region=` (try (assert_eq ?region `us`) `eu`))",
+            &env,
+        );
+
+        let mut runtime_env = RuntimeEnv::default();
+
+        let Ok(Evaluation::Suspended(snapshot)) =
+            Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+        else {
+            panic!("should suspend on ?region");
+        };
+
+        let snapshot = VmSnapshot::from_bytes(&snapshot.to_bytes(), &env).unwrap();
+
+        runtime_env.prompts.push("ca".to_string());
+
+        assert_eq!(
+            Ok(Evaluation::Complete(Value::String("region=eu".into()))),
+            Vm::new().resume(snapshot, &bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn try_discards_values_of_failed_ops() {
+        let env = CompileTimeEnv::default();
+        let bytecode = compile_source(
+            "(concat `a` (try (concat `b` (assert false)) `c`) `d`)",
+            &env,
+        );
+
+        let mut vm = Vm::new();
+
+        assert_eq!(
+            Ok(Value::String("acd".into())),
+            vm.interpret(bytecode.into(), &env, &RuntimeEnv::default())
+        );
+        assert!(vm.handlers.is_empty());
+    }
+
+    #[test]
+    fn suspendable_skips_prompts_in_branches_not_taken() {
+        let env = CompileTimeEnv::new(
+            vec!["mode".to_string()],
+            vec!["password".to_string()],
+            vec![],
+            vec![],
+        );
+        let bytecode = compile_source("(cond (eq :mode `local`) `dev` ?password)", &env);
+
+        let runtime_env = RuntimeEnv {
+            vars: vec!["local".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Ok(Evaluation::Complete(Value::String("dev".into()))),
+            Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn suspendable_completes_without_missing_values() {
+        let env = CompileTimeEnv::new(vec![], vec!["name".to_string()], vec![], vec![]);
+        let bytecode = compile_source("(id ?name)", &env);
+
+        let runtime_env = RuntimeEnv {
+            prompts: vec!["World".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Ok(Evaluation::Complete(Value::String("World".into()))),
+            Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn resume_with_different_bytecode() {
+        let env = CompileTimeEnv::new(vec![], vec!["name".to_string()], vec![], vec![]);
+        let bytecode = compile_source("(id ?name)", &env);
+        let runtime_env = RuntimeEnv::default();
+
+        let Ok(Evaluation::Suspended(snapshot)) =
+            Vm::new().interpret_suspendable(&bytecode, &env, &runtime_env)
+        else {
+            panic!("should suspend on ?name");
+        };
+
+        assert_eq!(
+            Err(vec![(
+                ExprError::RuntimeError(RuntimeError::InvalidSnapshot {
+                    message: "expected a GET of name at 0003 in the bytecode".to_string()
+                }),
+                0..0
+            )]),
+            Vm::new().resume(snapshot, &compile_source("true", &env), &env, &runtime_env)
+        );
+    }
+
+    #[test]
+    fn local_set_out_of_order() {
+        let mut codes = get_version_bytes().to_vec();
+        codes.extend([opcode::TRUE, opcode::SET_LOCAL, 1, opcode::GET_LOCAL, 1]);
+
+        let bytecode = Box::new(ExprByteCode::new(codes, vec![], vec![]));
+        let env = CompileTimeEnv::default();
+        let runtime_env = RuntimeEnv::default();
+
+        let expected = Err(vec![(
+            RuntimeError::InvalidBytecode {
+                message: "local 1 set before local 0".to_string(),
+            }
+            .into(),
+            0..0,
+        )]);
+
+        assert_eq!(
+            expected,
+            Vm::new().interpret(bytecode.clone(), &env, &runtime_env)
+        );
+        assert_eq!(
+            expected,
+            Vm::new()
+                .explain(&bytecode, &env, &runtime_env)
+                .map(|(value, _)| value)
+        );
+    }
+
+    #[test]
+    fn repeated_gets_share_inputs() {
+        let env = CompileTimeEnv::new(
+            vec!["a".to_string()],
+            vec!["b".to_string()],
+            vec!["c".to_string()],
+            vec![],
+        );
+        let runtime_env = RuntimeEnv {
+            vars: vec!["x".repeat(1024)],
+            prompts: vec!["y".repeat(1024)],
+            secrets: vec!["z".repeat(1024)],
+            ..Default::default()
+        };
+
+        for source in ["[:a :a]", "[?b ?b]", "[!c (id !c)]"] {
+            let Ok(Value::List(items)) =
+                Vm::new().interpret(compile_source(source, &env).into(), &env, &runtime_env)
+            else {
+                panic!("should evaluate to a list: {source}");
+            };
+
+            let [Value::String(first), Value::String(second)] = items.as_slice() else {
+                panic!("should have two strings: {source}");
+            };
+
+            assert!(Arc::ptr_eq(first, second), "{source}");
+        }
+    }
+
+    #[test]
+    fn inputs_arent_shared_between_evaluations() {
+        let env = CompileTimeEnv::new(vec!["a".to_string()], vec![], vec![], vec![]);
+        let bytecode = compile_source(":a", &env);
+        let mut vm = Vm::new();
+
+        for value in ["first", "second"] {
+            let runtime_env = RuntimeEnv {
+                vars: vec![value.to_string()],
+                ..Default::default()
+            };
+
+            assert_eq!(
+                Ok(Value::String(value.into())),
+                vm.interpret(bytecode.clone().into(), &env, &runtime_env)
+            );
+        }
+    }
+
+    #[test]
+    fn resume_reads_inputs_from_the_new_runtime_env() {
+        let env = CompileTimeEnv::new(vec![], vec!["name".to_string()], vec![], vec![]);
+        let bytecode = compile_source("(concat ?name (id ?name))", &env);
+        let mut vm = Vm::new();
+
+        let Ok(Evaluation::Suspended(snapshot)) =
+            vm.interpret_suspendable(&bytecode, &env, &RuntimeEnv::default())
+        else {
+            panic!("should suspend on ?name");
+        };
+
+        let runtime_env = RuntimeEnv {
+            prompts: vec!["ab".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Ok(Evaluation::Complete(Value::String("abab".into()))),
+            vm.resume(snapshot, &bytecode, &env, &runtime_env)
+        );
     }
 }
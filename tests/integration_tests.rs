@@ -1,5 +1,19 @@
+use std::io::Read;
+
 use reqlang_expr::prelude::get_version_bytes;
 
+/// Reads a byte at a time so tokens are split across reads
+struct ByteAtATime<'a>(&'a [u8]);
+
+impl Read for ByteAtATime<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.0.take(1).read(buf)?;
+        self.0 = &self.0[read..];
+
+        Ok(read)
+    }
+}
+
 macro_rules! test {
     (
         $source:tt;
@@ -24,6 +38,14 @@ macro_rules! test {
                     ::pretty_assertions::assert_eq!($expected_tokens, tokens);
                 }
 
+                #[test]
+                fn [< $test_name:lower $(_ $test_name2:lower)* _streamed_tokens >]() {
+                    let reader = crate::ByteAtATime($source.as_bytes());
+                    let tokens: Vec<_> = ::reqlang_expr::lexer::ReaderLexer::new(reader).collect();
+
+                    ::pretty_assertions::assert_eq!($expected_tokens, tokens);
+                }
+
                 #[test]
                 fn [< $test_name:lower $(_ $test_name2:lower)* _ast >]() {
                     let ast = ::reqlang_expr::parser::parse(&$source);
@@ -62,7 +84,7 @@ macro_rules! test {
                     if let Ok(ast) = ast && let Ok(op_codes) = ::reqlang_expr::compiler::compile(&mut (ast, 0..$source.len()), &env) {
                         let expected_disassembly: String = $expected_disassembly.to_string();
                         let disassemble = ::reqlang_expr::disassembler::Disassembler::new(&op_codes, &env);
-                        let disassembly = disassemble.disassemble();
+                        let disassembly = disassemble.disassemble().expect("should disassemble compiled bytecode");
 
                         ::pretty_assertions::assert_eq!(expected_disassembly, disassembly);
                     }
@@ -171,7 +193,7 @@ mod valid {
     use reqlang_expr::{errors::ExprResult, value::Value};
 
     fn example_builtin(_args: Vec<Value>) -> ExprResult<Value> {
-        Ok(Value::String("".to_string()))
+        Ok(Value::String("".into()))
     }
 
     test! {
@@ -192,7 +214,11 @@ mod valid {
                 name: "foo",
                 args: &[],
                 return_type: Type::String,
-                func: crate::valid::example_builtin
+                func: crate::valid::example_builtin,
+                namespace: FnNamespace::User,
+                pure: false,
+                category: BuiltinCategory::Value,
+                capability: None,
             }
         ];
 
@@ -214,7 +240,11 @@ mod valid {
                 name: "foo",
                 args: &[],
                 return_type: Type::String,
-                func: crate::valid::example_builtin
+                func: crate::valid::example_builtin,
+                namespace: FnNamespace::User,
+                pure: false,
+                category: BuiltinCategory::Value,
+                capability: None,
             }.into()));
     }
 
@@ -237,7 +267,7 @@ mod valid {
             crate::make_test_bytecode(vec![
                 opcode::CONSTANT, 0
             ]),
-            vec![Value::String("test string".to_string())],
+            vec![Value::String("test string".into())],
             vec![]
         ));
 
@@ -247,7 +277,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("test string".to_string()));
+        interpets to: Ok(Value::String("test string".into()));
     }
 
     test! {
@@ -314,7 +344,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("noop".to_string()));
+        interpets to: Ok(Value::String("noop".into()));
     }
 
     test! {
@@ -346,12 +376,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Fn(BuiltinFn {
-            name: "noop",
-            args: &[],
-            return_type: Type::String,
-            func: crate::valid::example_builtin
-        }.into()));
+        interpets to: Ok(Value::Fn(BuiltinFn::NOOP.into()));
     }
 
     test! {
@@ -394,7 +419,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "noop".to_string()));
+            "noop".into()));
     }
 
     test! {
@@ -424,7 +449,7 @@ mod valid {
                 opcode::CALL, 1
             ]),
             vec![
-                Value::String("test value".to_string()),
+                Value::String("test value".into()),
             ],
             vec![]
         ));
@@ -436,7 +461,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "test value".to_string()));
+            "test value".into()));
     }
 
     test! {
@@ -477,7 +502,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "b_value".to_string()));
+            "b_value".into()));
     }
 
     test! {
@@ -527,7 +552,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "b_value".to_string()));
+            "b_value".into()));
     }
     test! {
         ":b";
@@ -560,7 +585,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "b_value".to_string()));
+            "b_value".into()));
     }
 
     test! {
@@ -594,7 +619,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "b_value".to_string()));
+            "b_value".into()));
     }
 
     test! {
@@ -635,7 +660,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "b_value".to_string()));
+            "b_value".into()));
     }
 
     test! {
@@ -669,7 +694,7 @@ mod valid {
         };
 
         interpets to: Ok(Value::String(
-            "b_value".to_string()));
+            "b_value".into()));
     }
 
     test! {
@@ -698,12 +723,12 @@ mod valid {
         disassembles to: "VERSION 0800\n----\n0000 GET CLIENT_CTX      1 == 'b'\n";
 
         runtime env: {
-            client_context: vec![Value::String("a_value".to_string()), Value::String("b_value".to_string())],
+            client_context: vec![Value::String("a_value".into()), Value::String("b_value".into())],
             ..Default::default()
         };
 
         interpets to: Ok(Value::String(
-            "b_value".to_string()));
+            "b_value".into()));
     }
 
     // test! {
@@ -1045,16 +1070,17 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 3,
                 opcode::TRUE,
+                opcode::JUMP_IF_FALSE, 0, 4,
                 opcode::FALSE,
-                opcode::CALL, 2
+                opcode::JUMP, 0, 1,
+                opcode::FALSE
             ]),
             vec![],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         3 == 'and'\n0003 TRUE\n0004 FALSE\n0005 CALL             (2 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 TRUE\n0001 JUMP_IF_FALSE       4 -> 0008\n0004 FALSE\n0005 JUMP                1 -> 0009\n0008 FALSE\n";
 
         runtime env: {
             ..Default::default()
@@ -1092,16 +1118,17 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 3,
                 opcode::TRUE,
+                opcode::JUMP_IF_FALSE, 0, 4,
                 opcode::TRUE,
-                opcode::CALL, 2
+                opcode::JUMP, 0, 1,
+                opcode::FALSE
             ]),
             vec![],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         3 == 'and'\n0003 TRUE\n0004 TRUE\n0005 CALL             (2 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 TRUE\n0001 JUMP_IF_FALSE       4 -> 0008\n0004 TRUE\n0005 JUMP                1 -> 0009\n0008 FALSE\n";
 
         runtime env: {
             ..Default::default()
@@ -1139,15 +1166,17 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 3,
-                opcode::FALSE, opcode::TRUE,
-                opcode::CALL, 2
+                opcode::FALSE,
+                opcode::JUMP_IF_FALSE, 0, 4,
+                opcode::TRUE,
+                opcode::JUMP, 0, 1,
+                opcode::FALSE
             ]),
             vec![],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         3 == 'and'\n0003 FALSE\n0004 TRUE\n0005 CALL             (2 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 FALSE\n0001 JUMP_IF_FALSE       4 -> 0008\n0004 TRUE\n0005 JUMP                1 -> 0009\n0008 FALSE\n";
 
         runtime env: {
             ..Default::default()
@@ -1185,15 +1214,17 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 4,
-                opcode::TRUE, opcode::FALSE,
-                opcode::CALL, 2
+                opcode::TRUE,
+                opcode::JUMP_IF_FALSE, 0, 4,
+                opcode::TRUE,
+                opcode::JUMP, 0, 1,
+                opcode::FALSE
             ]),
             vec![],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         4 == 'or'\n0003 TRUE\n0004 FALSE\n0005 CALL             (2 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 TRUE\n0001 JUMP_IF_FALSE       4 -> 0008\n0004 TRUE\n0005 JUMP                1 -> 0009\n0008 FALSE\n";
 
         runtime env: {
             ..Default::default()
@@ -1231,15 +1262,17 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 4,
-                opcode::TRUE, opcode::TRUE,
-                opcode::CALL, 2
+                opcode::TRUE,
+                opcode::JUMP_IF_FALSE, 0, 4,
+                opcode::TRUE,
+                opcode::JUMP, 0, 1,
+                opcode::TRUE
             ]),
             vec![],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         4 == 'or'\n0003 TRUE\n0004 TRUE\n0005 CALL             (2 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 TRUE\n0001 JUMP_IF_FALSE       4 -> 0008\n0004 TRUE\n0005 JUMP                1 -> 0009\n0008 TRUE\n";
 
         runtime env: {
             ..Default::default()
@@ -1277,16 +1310,17 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 4,
                 opcode::FALSE,
+                opcode::JUMP_IF_FALSE, 0, 4,
                 opcode::TRUE,
-                opcode::CALL, 2
+                opcode::JUMP, 0, 1,
+                opcode::TRUE
             ]),
             vec![],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         4 == 'or'\n0003 FALSE\n0004 TRUE\n0005 CALL             (2 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 FALSE\n0001 JUMP_IF_FALSE       4 -> 0008\n0004 TRUE\n0005 JUMP                1 -> 0009\n0008 TRUE\n";
 
         runtime env: {
             ..Default::default()
@@ -1326,26 +1360,26 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 5,
                 opcode::TRUE,
+                opcode::JUMP_IF_FALSE, 0, 5,
                 opcode::CONSTANT, 0,
-                opcode::CONSTANT, 1,
-                opcode::CALL, 3
+                opcode::JUMP, 0, 2,
+                opcode::CONSTANT, 1
             ]),
             vec![
-                Value::String("foo".to_string()),
-                Value::String("bar".to_string()),
+                Value::String("foo".into()),
+                Value::String("bar".into()),
             ],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         5 == 'cond'\n0003 TRUE\n0004 CONSTANT            0 == '`foo`'\n0006 CONSTANT            1 == '`bar`'\n0008 CALL             (3 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 TRUE\n0001 JUMP_IF_FALSE       5 -> 0009\n0004 CONSTANT            0 == '`foo`'\n0006 JUMP                2 -> 0011\n0009 CONSTANT            1 == '`bar`'\n";
 
         runtime env: {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foo".to_string()));
+        interpets to: Ok(Value::String("foo".into()));
     }
 
     test! {
@@ -1379,26 +1413,26 @@ mod valid {
 
         compiles to: Ok(ExprByteCode::new(
             crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 5,
                 opcode::FALSE,
+                opcode::JUMP_IF_FALSE, 0, 5,
                 opcode::CONSTANT, 0,
-                opcode::CONSTANT, 1,
-                opcode::CALL, 3
+                opcode::JUMP, 0, 2,
+                opcode::CONSTANT, 1
             ]),
             vec![
-                Value::String("foo".to_string()),
-                Value::String("bar".to_string()),
+                Value::String("foo".into()),
+                Value::String("bar".into()),
             ],
             vec![]
         ));
 
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         5 == 'cond'\n0003 FALSE\n0004 CONSTANT            0 == '`foo`'\n0006 CONSTANT            1 == '`bar`'\n0008 CALL             (3 args)\n";
+        disassembles to: "VERSION 0800\n----\n0000 FALSE\n0001 JUMP_IF_FALSE       5 -> 0009\n0004 CONSTANT            0 == '`foo`'\n0006 JUMP                2 -> 0011\n0009 CONSTANT            1 == '`bar`'\n";
 
         runtime env: {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("bar".to_string()));
+        interpets to: Ok(Value::String("bar".into()));
     }
 
     test! {
@@ -1446,7 +1480,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foo".to_string()));
+        interpets to: Ok(Value::String("foo".into()));
     }
 
     test! {
@@ -1462,7 +1496,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("true".to_string()));
+        interpets to: Ok(Value::String("true".into()));
     }
 
     test! {
@@ -1478,7 +1512,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("false".to_string()));
+        interpets to: Ok(Value::String("false".into()));
     }
 
     test! {
@@ -1494,7 +1528,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("id(value: Value) -> Value".to_string()));
+        interpets to: Ok(Value::String("id(value: Value) -> Value".into()));
     }
 
     test! {
@@ -1510,7 +1544,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("noop".to_string()));
+        interpets to: Ok(Value::String("noop".into()));
     }
 
     test! {
@@ -1526,7 +1560,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foobar".to_string()));
+        interpets to: Ok(Value::String("foobar".into()));
     }
 
     test! {
@@ -1542,7 +1576,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("abcdefghij".to_string()));
+        interpets to: Ok(Value::String("abcdefghij".into()));
     }
 
     test! {
@@ -1558,7 +1592,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foofalse".to_string()));
+        interpets to: Ok(Value::String("foofalse".into()));
     }
 
     test! {
@@ -1773,7 +1807,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foo".to_string()));
+        interpets to: Ok(Value::String("foo".into()));
     }
 
     test! {
@@ -1789,7 +1823,7 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foo ".to_string()));
+        interpets to: Ok(Value::String("foo ".into()));
     }
 
     test! {
@@ -1805,13 +1839,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String(" foo".to_string()));
+        interpets to: Ok(Value::String(" foo".into()));
     }
 
     test! {
-        "(lowercase `FOO`)";
+        "(trim `--foo--` `-`)";
 
-        scenario: lowercase;
+        scenario: trim chars;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -1821,13 +1855,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foo".to_string()));
+        interpets to: Ok(Value::String("foo".into()));
     }
 
     test! {
-        "(uppercase `foo`)";
+        "(trim_start `--foo--` `-`)";
 
-        scenario: uppercase;
+        scenario: trim start chars;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -1837,13 +1871,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("FOO".to_string()));
+        interpets to: Ok(Value::String("foo--".into()));
     }
 
     test! {
-        "(type `foo`)";
+        "(trim_end `-_foo_-` `_-`)";
 
-        scenario: type string;
+        scenario: trim end chars;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -1853,13 +1887,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(Type::String.into()).into()));
+        interpets to: Ok(Value::String("-_foo".into()));
     }
 
     test! {
-        "(type true)";
+        "(trim ` foo ` true)";
 
-        scenario: type bool true;
+        scenario: trim chars not a string;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -1869,13 +1903,20 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(Type::Bool.into()).into()));
+        interpets to: Err(vec![(
+            CompileError::TypeMismatch {
+                expected: Type::String,
+                actual: Type::Bool
+            }
+            .into(),
+            14..18
+        )]);
     }
 
     test! {
-        "(type false)";
+        "(trim `a` `b` `c`)";
 
-        scenario: type bool false;
+        scenario: trim with too many args;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -1885,13 +1926,21 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(Type::Bool.into()).into()));
+        interpets to: Err(vec![(
+            CompileError::WrongNumberOfArgs {
+                expected: 2,
+                actual: 3,
+                signature: "trim(value: String, chars?: String) -> String".to_string()
+            }
+            .into(),
+            14..17
+        )]);
     }
 
     test! {
-        "(type id)";
+        "(lowercase `FOO`)";
 
-        scenario: type builtin id;
+        scenario: lowercase;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -1901,21 +1950,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(
-            Value::Type(
-                Type::Type(Type::Fn {
-                    args: vec![Type::Value],
-                    variadic_arg: None,
-                    returns: Type::Value.into()
-                }.into()).into()
-            )
-        );
+        interpets to: Ok(Value::String("foo".into()));
     }
 
     test! {
-        "(type concat)";
+        "(uppercase `foo`)";
 
-        scenario: type builtin concat;
+        scenario: uppercase;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -1925,44 +1966,18 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(
-            Value::Type(
-                Type::Type(Type::Fn {
-                    args: vec![Type::Value, Type::Value],
-                    variadic_arg: Some(Type::Value.into()),
-                    returns: Type::String.into()
-                }.into()).into()
-            )
-        );
+        interpets to: Ok(Value::String("FOO".into()));
     }
 
     test! {
-        "@intest";
-
-        scenario: client in test value;
-
-        tokens should be: vec![
-            Ok((0, Token::identifier("@intest"), 7)),
-        ];
+        "(eq_ignore_case `Application/JSON` `application/json`)";
 
-        ast should be: Ok(
-            Expr::identifier_with_type("@intest", Type::Value)
-        );
+        scenario: eq ignore case matching;
 
         env: (vec![], vec![], vec![], vec![]);
 
         user builtins: [];
 
-        compiles to: Ok(ExprByteCode::new(
-            crate::make_test_bytecode(vec![
-                opcode::GET, lookup::CLIENT_CTX, 0
-            ]),
-            vec![],
-            vec![]
-        ));
-
-        disassembles to: "VERSION 0800\n----\n0000 GET CLIENT_CTX      0 == 'intest'\n";
-
         runtime env: {
             ..Default::default()
         };
@@ -1971,115 +1986,58 @@ mod valid {
     }
 
     test! {
-        "((id id) `foo`)";
+        "(eq_ignore_case :a `EXAMPLE.COM`)";
 
-        scenario: call expr with call expression as calle;
+        scenario: eq ignore case variable matching;
 
-        env: (vec![], vec![], vec![], vec![]);
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
 
         user builtins: [];
 
         runtime env: {
+            vars: vec!["example.com".to_string()],
             ..Default::default()
         };
 
-        interpets to: Ok(Value::String("foo".to_string()));
+        interpets to: Ok(Value::Bool(true));
     }
 
     test! {
-        "(eq true true)";
-
-        scenario: eq true true;
-
-        tokens should be: vec![
-            Ok((0, Token::LParan, 1)),
-            Ok((1, Token::identifier("eq"), 3)),
-            Ok((4, Token::True, 8)),
-            Ok((9, Token::True, 13)),
-            Ok((13, Token::RParan, 14))
-        ];
+        "(eq_ignore_case `foo` `bar`)";
 
-        ast should be: Ok(Expr::call(
-            (Expr::identifier("eq"), 1..3),
-            vec![
-                (Expr::bool(true), 4..8),
-                (Expr::bool(true), 9..13),
-            ]
-        ));
+        scenario: eq ignore case mismatching;
 
         env: (vec![], vec![], vec![], vec![]);
 
         user builtins: [];
 
-        compiles to: Ok(ExprByteCode::new(
-            crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 15,
-                opcode::TRUE,
-                opcode::TRUE,
-                opcode::CALL, 2
-            ]),
-            vec![],
-            vec![]
-        ));
-
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN        15 == 'eq'\n0003 TRUE\n0004 TRUE\n0005 CALL             (2 args)\n";
-
         runtime env: {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(true));
+        interpets to: Ok(Value::Bool(false));
     }
 
     test! {
-        "(eq false true)";
-
-        scenario: eq false true;
-
-        tokens should be: vec![
-            Ok((0, Token::LParan, 1)),
-            Ok((1, Token::identifier("eq"), 3)),
-            Ok((4, Token::False, 9)),
-            Ok((10, Token::True, 14)),
-            Ok((14, Token::RParan, 15))
-        ];
+        "(snake_case `userId`)";
 
-        ast should be: Ok(Expr::call(
-            (Expr::identifier("eq"), 1..3),
-            vec![
-                (Expr::bool(false), 4..9),
-                (Expr::bool(true), 10..14),
-            ]
-        ));
+        scenario: snake case;
 
         env: (vec![], vec![], vec![], vec![]);
 
         user builtins: [];
 
-        compiles to: Ok(ExprByteCode::new(
-            crate::make_test_bytecode(vec![
-                opcode::GET, lookup::BUILTIN, 15,
-                opcode::FALSE,
-                opcode::TRUE,
-                opcode::CALL, 2
-            ]),
-            vec![],
-            vec![]
-        ));
-
-        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN        15 == 'eq'\n0003 FALSE\n0004 TRUE\n0005 CALL             (2 args)\n";
-
         runtime env: {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(false));
+        interpets to: Ok(Value::String("user_id".into()));
     }
 
     test! {
-        "(eq `foo` `foo`)";
+        "(snake_case `Content-Type`)";
 
-        scenario: builtin eq same string;
+        scenario: snake case acronyms;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2089,13 +2047,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(true));
+        interpets to: Ok(Value::String("content_type".into()));
     }
 
     test! {
-        "(eq `foo` `bar`)";
+        "(camel_case `user_id`)";
 
-        scenario: builtin eq different strings;
+        scenario: camel case;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2105,13 +2063,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(false));
+        interpets to: Ok(Value::String("userId".into()));
     }
 
     test! {
-        "(eq true true)";
+        "(camel_case `X-Request-ID`)";
 
-        scenario: builtin eq same bool;
+        scenario: camel case acronyms;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2121,13 +2079,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(true));
+        interpets to: Ok(Value::String("xRequestId".into()));
     }
 
     test! {
-        "(eq true false)";
+        "(kebab_case `XMLHttpRequest`)";
 
-        scenario: builtin eq different bool;
+        scenario: kebab case acronyms;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2137,13 +2095,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(false));
+        interpets to: Ok(Value::String("xml-http-request".into()));
     }
 
     test! {
-        "(eq id id)";
+        "(title_case `user_id`)";
 
-        scenario: builtin eq same fn;
+        scenario: title case;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2153,13 +2111,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(true));
+        interpets to: Ok(Value::String("User Id".into()));
     }
 
     test! {
-        "(eq id concat)";
+        "(truncate `Hello World` 5)";
 
-        scenario: builtin eq different fns;
+        scenario: truncate;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2169,13 +2127,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Bool(false));
+        interpets to: Ok(Value::String("Hello".into()));
     }
 
     test! {
-        "String";
+        "(truncate `Hello World` 5 true)";
 
-        scenario: type literal String;
+        scenario: truncate with ellipsis;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2185,13 +2143,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(Type::String.into()).into()));
+        interpets to: Ok(Value::String("Hell…".into()));
     }
 
     test! {
-        "Bool";
+        "(truncate `Hello` 5 true)";
 
-        scenario: type literal Bool;
+        scenario: truncate shorter than length;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2201,29 +2159,30 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(Type::Bool.into()).into()));
+        interpets to: Ok(Value::String("Hello".into()));
     }
 
     test! {
-        "Type<String>";
+        "(truncate :a 3)";
 
-        scenario: type literal Type String;
+        scenario: truncate multibyte characters;
 
-        env: (vec![], vec![], vec![], vec![]);
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
 
         user builtins: [];
 
         runtime env: {
+            vars: vec!["hééllo".to_string()],
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(Type::String.into()).into()));
+        interpets to: Ok(Value::String("héé".into()));
     }
 
     test! {
-        "Fn() -> Value";
+        "(truncate `Hello` 1.5)";
 
-        scenario: type literal Type Fn no args;
+        scenario: truncate fractional length;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2233,18 +2192,20 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(
-            Type::Fn {
-                args: vec![],
-                variadic_arg: None,
-                returns: Type::Value.into()
-            }.into()).into()));
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "length".to_string(),
+                message: "expected a non negative whole number but received 1.5".to_string(),
+            }
+            .into(),
+            0..22
+        )]);
     }
 
     test! {
-        "Fn(Value) -> Value";
+        "(truncate `Hello` 2 true true)";
 
-        scenario: type literal Type Fn arg;
+        scenario: truncate with too many args;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2254,18 +2215,22 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(
-            Type::Fn {
-                args: vec![Type::Value],
-                variadic_arg: None,
-                returns: Type::Value.into()
-            }.into()).into()));
+        interpets to: Err(vec![(
+            CompileError::WrongNumberOfArgs {
+                expected: 3,
+                actual: 4,
+                signature: "truncate(value: String, length: Number, ellipsis?: Bool) -> String"
+                    .to_string()
+            }
+            .into(),
+            25..29
+        )]);
     }
 
     test! {
-        "Fn(Value, Value) -> Value";
+        "((id trim))";
 
-        scenario: type literal Type Fn args;
+        scenario: call to a function value with too few args;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2275,18 +2240,21 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(
-            Type::Fn {
-                args: vec![Type::Value, Type::Value],
-                variadic_arg: None,
-                returns: Type::Value.into()
-            }.into()).into()));
+        interpets to: Err(vec![(
+            RuntimeError::WrongNumberOfArgs {
+                name: "trim".to_string(),
+                expected: 1,
+                actual: 0,
+            }
+            .into(),
+            0..11
+        )]);
     }
 
     test! {
-        "Fn(...Value) -> Value";
+        "(count_matches `o` `Hello World`)";
 
-        scenario: type literal Type Fn vargs;
+        scenario: count matches;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2296,18 +2264,13 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(
-            Type::Fn {
-                args: vec![],
-                variadic_arg: Some(Type::Value.into()),
-                returns: Type::Value.into()
-            }.into()).into()));
+        interpets to: Ok(Value::Number(2.0));
     }
 
     test! {
-        "Fn(Value, ...Value) -> Value";
+        "(count_matches `aa` `aaaaa`)";
 
-        scenario: type literal Type Fn arg and vargs;
+        scenario: count matches non overlapping;
 
         env: (vec![], vec![], vec![], vec![]);
 
@@ -2317,33 +2280,2469 @@ mod valid {
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(
-            Type::Fn {
-                args: vec![Type::Value],
-                variadic_arg: Some(Type::Value.into()),
-                returns: Type::Value.into()
-            }.into()).into()));
+        interpets to: Ok(Value::Number(2.0));
     }
 
     test! {
-        "Fn(Value, Value, ...Value) -> Value";
+        "(count_matches `z` :a)";
 
-        scenario: type literal Type Fn args and vargs;
+        scenario: count matches none;
 
-        env: (vec![], vec![], vec![], vec![]);
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
 
         user builtins: [];
 
         runtime env: {
+            vars: vec!["abc".to_string()],
             ..Default::default()
         };
 
-        interpets to: Ok(Value::Type(Type::Type(
-            Type::Fn {
-                args: vec![Type::Value, Type::Value],
-                variadic_arg: Some(Type::Value.into()),
-                returns: Type::Value.into()
-            }.into()).into()));
+        interpets to: Ok(Value::Number(0.0));
+    }
+
+    test! {
+        "(count_matches `` `abc`)";
+
+        scenario: count matches empty needle;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(0.0));
+    }
+
+    test! {
+        "(line :a 0)";
+
+        scenario: line first;
+
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["first\nsecond\r\nthird".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("first".into()));
+    }
+
+    test! {
+        "(line :a 2)";
+
+        scenario: line last;
+
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["first\nsecond\r\nthird".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("third".into()));
+    }
+
+    test! {
+        "(line :a 3)";
+
+        scenario: line out of range;
+
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["first\nsecond\r\nthird".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "n".to_string(),
+                message: "line 3 is out of range for 3 lines".to_string(),
+            }
+            .into(),
+            0..11
+        )]);
+    }
+
+    test! {
+        "(html_escape `<a href=\"x\">Tom & Jerry's</a>`)";
+
+        scenario: html escape;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;".into()));
+    }
+
+    test! {
+        "(html_unescape `&lt;b&gt;Tom &amp; Jerry&#39;s &#x27;&apos; &unknown; & done&lt;/b&gt;`)";
+
+        scenario: html unescape;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("<b>Tom & Jerry's '' &unknown; & done</b>".into()));
+    }
+
+    test! {
+        "(html_unescape (html_escape :a))";
+
+        scenario: html escape round trip;
+
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["<x a=\"1\" b='2'>&amp;</x>".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("<x a=\"1\" b='2'>&amp;</x>".into()));
+    }
+
+    test! {
+        "(semver_gte `2.10.0` `2.3.0`)";
+
+        scenario: semver gte greater;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(semver_gte `v2.3.0` `2.3`)";
+
+        scenario: semver gte equal;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(semver_gte `2.3.0-rc.1` `2.3.0`)";
+
+        scenario: semver gte pre release;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(semver_gte :a `2.3.0`)";
+
+        scenario: semver gte invalid;
+
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["latest".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "a".to_string(),
+                message: "invalid semver version `latest`".to_string(),
+            }
+            .into(),
+            0..23
+        )]);
+    }
+
+    test! {
+        "(crc32 `Hello World`)";
+
+        scenario: crc32;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("4a17b156".into()));
+    }
+
+    test! {
+        "(crc32 ``)";
+
+        scenario: crc32 empty;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("00000000".into()));
+    }
+
+    test! {
+        "(adler32 `Wikipedia`)";
+
+        scenario: adler32;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("11e60398".into()));
+    }
+
+    test! {
+        "(adler32 ``)";
+
+        scenario: adler32 empty;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("00000001".into()));
+    }
+
+    test! {
+        "(abs 2.5)";
+
+        scenario: abs;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(2.5));
+    }
+
+    test! {
+        "(floor 2.7)";
+
+        scenario: floor;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(2.0));
+    }
+
+    test! {
+        "(ceil 2.2)";
+
+        scenario: ceil;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(3.0));
+    }
+
+    test! {
+        "(round 2.5)";
+
+        scenario: round half;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(3.0));
+    }
+
+    test! {
+        "(round 2.49)";
+
+        scenario: round down;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(2.0));
+    }
+
+    test! {
+        "(pow 2 10)";
+
+        scenario: pow;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(1024.0));
+    }
+
+    test! {
+        "(pow 4 0.5)";
+
+        scenario: pow fractional exponent;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(2.0));
+    }
+
+    test! {
+        "(pow 10 400)";
+
+        scenario: pow overflow;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "exponent".to_string(),
+                message: "10 to the power of 400 is not a finite number".to_string(),
+            }
+            .into(),
+            0..12
+        )]);
+    }
+
+    test! {
+        "(sqrt 16)";
+
+        scenario: sqrt;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(4.0));
+    }
+
+    test! {
+        "(sqrt (floor 2.25))";
+
+        scenario: sqrt nested;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(std::f64::consts::SQRT_2));
+    }
+
+    test! {
+        "(clamp 250 1 100)";
+
+        scenario: clamp above max;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(100.0));
+    }
+
+    test! {
+        "(clamp 0 1 100)";
+
+        scenario: clamp below min;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(1.0));
+    }
+
+    test! {
+        "(clamp 25 1 100)";
+
+        scenario: clamp within range;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(25.0));
+    }
+
+    test! {
+        "(clamp 5 10 1)";
+
+        scenario: clamp min greater than max;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "min".to_string(),
+                message: "min 10 is greater than max 1".to_string(),
+            }
+            .into(),
+            0..14
+        )]);
+    }
+
+    test! {
+        "((compose uppercase trim) ` a `)";
+
+        scenario: compose;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("A".into()));
+    }
+
+    test! {
+        "((partial concat `Bearer `) `abc`)";
+
+        scenario: partial;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("Bearer abc".into()));
+    }
+
+    test! {
+        "((compose (partial concat `a`) (partial concat `b`)) `c` `d`)";
+
+        scenario: compose partials;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("abcd".into()));
+    }
+
+    test! {
+        "[`a` :b 1]";
+
+        scenario: list literal;
+
+        env: (vec!["b".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["b".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::List(vec![
+            Value::String("a".into()),
+            Value::String("b".into()),
+            Value::Number(1.0),
+        ]));
+    }
+
+    test! {
+        "(type [`a` `b`])";
+
+        scenario: list type;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(Type::List(Type::String.into()).into()).into()));
+    }
+
+    test! {
+        "(len [[`a`] []])";
+
+        scenario: len;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(2.0));
+    }
+
+    test! {
+        "(get [`a` `b`] 1)";
+
+        scenario: get;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("b".into()));
+    }
+
+    test! {
+        "(get [`a` `b`] 2)";
+
+        scenario: get out of range;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "index".to_string(),
+                message: "index 2 is out of range for a list of 2 items".to_string(),
+            }
+            .into(),
+            0..17
+        )]);
+    }
+
+    test! {
+        "(len `a`)";
+
+        scenario: len of non list;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::TypeMismatch {
+                expected: Type::List(Type::Value.into()),
+                actual: Type::String,
+            }
+            .into(),
+            0..9
+        )]);
+    }
+
+    test! {
+        "(map_join [`a` true 1] to_str `, `)";
+
+        scenario: map_join;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("a, true, 1".into()));
+    }
+
+    test! {
+        "(map_join [`a` `b`] (compose uppercase trim) ``)";
+
+        scenario: map_join closure;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("AB".into()));
+    }
+
+    test! {
+        "(map uppercase [`a` `b`])";
+
+        scenario: map;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::List(vec![
+            Value::String("A".into()),
+            Value::String("B".into())
+        ]));
+    }
+
+    test! {
+        "(map (partial eq `a`) [`a` `b`])";
+
+        scenario: map closure;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::List(vec![Value::Bool(true), Value::Bool(false)]));
+    }
+
+    test! {
+        "(map uppercase [1 2])";
+
+        scenario: map fn not matching items;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            CompileError::TypeMismatch {
+                expected: Type::Fn {
+                    args: vec![Type::Number],
+                    variadic_arg: None,
+                    returns: Type::Value.into(),
+                },
+                actual: Type::Fn {
+                    args: vec![Type::String],
+                    variadic_arg: None,
+                    returns: Type::String.into(),
+                },
+            }
+            .into(),
+            5..14
+        )]);
+    }
+
+    test! {
+        "(filter is_empty [`a` `` `b`])";
+
+        scenario: filter;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::List(vec![
+            Value::String("".into())
+        ]));
+    }
+
+    test! {
+        "(filter trim [`a`])";
+
+        scenario: filter fn not returning bool;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            CompileError::TypeMismatch {
+                expected: Type::Fn {
+                    args: vec![Type::String],
+                    variadic_arg: None,
+                    returns: Type::Bool.into(),
+                },
+                actual: Type::Fn {
+                    args: vec![Type::String],
+                    variadic_arg: None,
+                    returns: Type::String.into(),
+                },
+            }
+            .into(),
+            8..12
+        )]);
+    }
+
+    test! {
+        "(filter id [`a`])";
+
+        scenario: filter fn returning non bool value;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::TypeMismatch {
+                expected: Type::Bool,
+                actual: Type::String,
+            }
+            .into(),
+            0..17
+        )]);
+    }
+
+    test! {
+        "(reduce concat `` [`a` `b`])";
+
+        scenario: reduce;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("ab".into()));
+    }
+
+    test! {
+        "(reduce not true [false])";
+
+        scenario: reduce fn with too few args;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            CompileError::TypeMismatch {
+                expected: Type::Fn {
+                    args: vec![Type::Bool, Type::Bool],
+                    variadic_arg: None,
+                    returns: Type::Value.into(),
+                },
+                actual: Type::Fn {
+                    args: vec![Type::Bool],
+                    variadic_arg: None,
+                    returns: Type::Bool.into(),
+                },
+            }
+            .into(),
+            8..11
+        )]);
+    }
+
+    test! {
+        "(concat ...[`a` `b`])";
+
+        scenario: call spread;
+
+        tokens should be: vec![
+            Ok((0, Token::LParan, 1)),
+            Ok((1, Token::identifier("concat"), 7)),
+            Ok((8, Token::ThreeDot, 11)),
+            Ok((11, Token::LBracket, 12)),
+            Ok((12, Token::String("a".to_string()), 15)),
+            Ok((16, Token::String("b".to_string()), 19)),
+            Ok((19, Token::RBracket, 20)),
+            Ok((20, Token::RParan, 21)),
+        ];
+
+        ast should be: Ok(Expr::call((Expr::identifier("concat"), 1..7), vec![
+            (
+                Expr::spread((
+                    Expr::list(vec![
+                        (Expr::string("a"), 12..15),
+                        (Expr::string("b"), 16..19),
+                    ]),
+                    11..20
+                )),
+                8..20
+            )
+        ]));
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        compiles to: Ok(ExprByteCode::new(
+            crate::make_test_bytecode(vec![
+                opcode::GET, lookup::BUILTIN, 7,
+                opcode::CONSTANT, 0,
+                opcode::CONSTANT, 1,
+                opcode::LIST, 2,
+                opcode::CALL_SPREAD, 1
+            ]),
+            vec![
+                Value::String("a".into()),
+                Value::String("b".into()),
+            ],
+            vec![]
+        ));
+
+        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN         7 == 'concat'\n0003 CONSTANT            0 == '`a`'\n0005 CONSTANT            1 == '`b`'\n0007 LIST             (2 items)\n0009 CALL_SPREAD      (1 args)\n";
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("ab".into()));
+    }
+
+    test! {
+        "(concat `a` ...:b)";
+
+        scenario: call spread var;
+
+        env: (vec!["b".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["b".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            CompileError::TypeMismatch {
+                expected: Type::List(Type::Value.into()),
+                actual: Type::String,
+            }
+            .into(),
+            15..17
+        )]);
+    }
+
+    test! {
+        "(uppercase ...(id [1]))";
+
+        scenario: call spread item of wrong type;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::TypeMismatch {
+                expected: Type::String,
+                actual: Type::Number,
+            }
+            .into(),
+            0..23
+        )]);
+    }
+
+    test! {
+        "(not ...[true true])";
+
+        scenario: call spread too many args;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::WrongNumberOfArgs {
+                name: "not".to_string(),
+                expected: 1,
+                actual: 2,
+            }
+            .into(),
+            0..20
+        )]);
+    }
+
+    test! {
+        "((partial concat `a`) ...[`b` `c`])";
+
+        scenario: call spread closure;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("abc".into()));
+    }
+
+    test! {
+        "[...[`a`]]";
+
+        scenario: spread in list;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(CompileError::InvalidSpread.into(), 1..9)]);
+    }
+
+    test! {
+        "(let (x (noop)) (concat x x))";
+
+        scenario: let;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("noopnoop".into()));
+    }
+
+    test! {
+        "(let (x :a) (let (y (concat x `b`)) (concat x y (let (x `c`) x))))";
+
+        scenario: nested let;
+
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["a".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("aabc".into()));
+    }
+
+    test! {
+        "(let (f concat) (f `a` `b`))";
+
+        scenario: let fn;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("ab".into()));
+    }
+
+    test! {
+        "(secure_eq !sig `abc123`)";
+
+        scenario: secure_eq equal;
+
+        env: (vec![], vec![], vec!["sig".to_string()], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            secrets: vec!["abc123".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(secure_eq !sig `abc124`)";
+
+        scenario: secure_eq not equal;
+
+        env: (vec![], vec![], vec!["sig".to_string()], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            secrets: vec!["abc123".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(secure_eq !sig `abc`)";
+
+        scenario: secure_eq different lengths;
+
+        env: (vec![], vec![], vec!["sig".to_string()], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            secrets: vec!["abc123".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(cond true `a` (parse_bool `maybe`))";
+
+        scenario: cond skips else branch;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("a".into()));
+    }
+
+    test! {
+        "(cond false (parse_bool `maybe`) `b`)";
+
+        scenario: cond skips then branch;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("b".into()));
+    }
+
+    test! {
+        "(and false (parse_bool `maybe`))";
+
+        scenario: and short circuits;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(or true (parse_bool `maybe`))";
+
+        scenario: or short circuits;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(and true (id `yes`))";
+
+        scenario: and checks second arg is bool;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::TypeMismatch {
+                expected: Type::Bool,
+                actual: Type::String,
+            }
+            .into(),
+            0..0
+        )]);
+    }
+
+    test! {
+        "(assert (contains `json` :content_type))";
+
+        scenario: assert passes;
+
+        env: (vec!["content_type".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["application/json".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(assert (is_empty :body) `expected an empty body`)";
+
+        scenario: assert fails with message;
+
+        env: (vec!["body".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["ok".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::AssertionFailed {
+                message: "expected an empty body".to_string(),
+                left: None,
+                right: None,
+            }
+            .into(),
+            0..50
+        )]);
+    }
+
+    test! {
+        "(assert false)";
+
+        scenario: assert fails with default message;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::AssertionFailed {
+                message: "condition is false".to_string(),
+                left: None,
+                right: None,
+            }
+            .into(),
+            0..14
+        )]);
+    }
+
+    test! {
+        "(assert_eq :status `200`)";
+
+        scenario: assert_eq passes;
+
+        env: (vec!["status".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["200".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("200".into()));
+    }
+
+    test! {
+        "(assert_eq :status `200` `expected a successful response`)";
+
+        scenario: assert_eq fails;
+
+        env: (vec!["status".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["404".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::AssertionFailed {
+                message: "expected a successful response".to_string(),
+                left: Some(Value::String("404".into())),
+                right: Some(Value::String("200".into())),
+            }
+            .into(),
+            0..58
+        )]);
+    }
+
+    test! {
+        "(try (parse_bool :flag) false)";
+
+        scenario: try returns expr;
+
+        env: (vec!["flag".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["true".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(try (parse_bool :flag) false)";
+
+        scenario: try returns fallback on error;
+
+        env: (vec!["flag".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["maybe".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(try (assert false `first`) (assert false `second`))";
+
+        scenario: try fails if fallback fails;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::AssertionFailed {
+                message: "second".to_string(),
+                left: None,
+                right: None,
+            }
+            .into(),
+            28..51
+        )]);
+    }
+
+    test! {
+        "(try (try (assert false) (assert_eq :a `b`)) (concat :a `!`))";
+
+        scenario: nested try;
+
+        env: (vec!["a".to_string()], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            vars: vec!["a".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("a!".into()));
+    }
+
+    test! {
+        "(assert (eq (try (assert false) `a`) `b`))";
+
+        scenario: try only catches errors in its expr;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::AssertionFailed {
+                message: "condition is false".to_string(),
+                left: None,
+                right: None,
+            }
+            .into(),
+            0..42
+        )]);
+    }
+
+    test! {
+        "(type (compose not is_empty))";
+
+        scenario: compose type;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(
+            Type::Type(
+                Type::Fn {
+                    args: vec![Type::String],
+                    variadic_arg: None,
+                    returns: Type::Bool.into()
+                }
+                .into()
+            )
+            .into()
+        ));
+    }
+
+    test! {
+        "(type (partial concat `a`))";
+
+        scenario: partial type;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(
+            Type::Type(
+                Type::Fn {
+                    args: vec![Type::Value],
+                    variadic_arg: Some(Type::Value.into()),
+                    returns: Type::String.into()
+                }
+                .into()
+            )
+            .into()
+        ));
+    }
+
+    test! {
+        "(compose contains trim)";
+
+        scenario: compose f with two args;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "f".to_string(),
+                message: "expected a function accepting one argument but received Fn(String, String) -> Bool".to_string(),
+            }
+            .into(),
+            0..23
+        )]);
+    }
+
+    test! {
+        "(compose trim true)";
+
+        scenario: compose non function;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::TypeMismatch {
+                expected: Type::Fn {
+                    args: vec![],
+                    variadic_arg: Some(Type::Value.into()),
+                    returns: Type::Value.into()
+                },
+                actual: Type::Bool
+            }
+            .into(),
+            0..19
+        )]);
+    }
+
+    test! {
+        "(partial trim true)";
+
+        scenario: partial arg type mismatch;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::TypeMismatch {
+                expected: Type::String,
+                actual: Type::Bool
+            }
+            .into(),
+            0..19
+        )]);
+    }
+
+    test! {
+        "(partial noop `a`)";
+
+        scenario: partial function without args;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "f".to_string(),
+                message: "expected a function accepting arguments but received Fn() -> String".to_string(),
+            }
+            .into(),
+            0..18
+        )]);
+    }
+
+    test! {
+        "(if (is_empty ``) `empty` `not empty`)";
+
+        scenario: if;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("empty".into()));
+    }
+
+    test! {
+        "(neq `a` `b`)";
+
+        scenario: neq different;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(neq 1 1)";
+
+        scenario: neq same;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(xor true true)";
+
+        scenario: xor true true;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(xor true false)";
+
+        scenario: xor true false;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(xor false true)";
+
+        scenario: xor false true;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(xor false false)";
+
+        scenario: xor false false;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(implies true true)";
+
+        scenario: implies true true;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(implies true false)";
+
+        scenario: implies true false;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(implies false true)";
+
+        scenario: implies false true;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(implies false false)";
+
+        scenario: implies false false;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(parse_bool `TRUE`)";
+
+        scenario: parse_bool true;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(parse_bool ` no `)";
+
+        scenario: parse_bool no;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(parse_bool `1`)";
+
+        scenario: parse_bool one;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(not (parse_bool ?confirm))";
+
+        scenario: parse_bool prompt;
+
+        env: (vec![], vec!["confirm".to_string()], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            prompts: vec!["Yes".to_string()],
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(parse_bool `maybe`)";
+
+        scenario: parse_bool invalid;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            RuntimeError::InvalidArgument {
+                name: "value".to_string(),
+                message: "expected true, false, yes, no, 1, or 0 but received `maybe`".to_string(),
+            }
+            .into(),
+            0..20
+        )]);
+    }
+
+    test! {
+        "(cmp `a` `b`)";
+
+        scenario: cmp less;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(-1.0));
+    }
+
+    test! {
+        "(cmp `a` `a`)";
+
+        scenario: cmp equal;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(0.0));
+    }
+
+    test! {
+        "(cmp `a` `B`)";
+
+        scenario: cmp by code point;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(1.0));
+    }
+
+    test! {
+        "(cmp (sort_key `a`) (sort_key `B`))";
+
+        scenario: sort_key ignores case;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(-1.0));
+    }
+
+    test! {
+        "(cmp (sort_key `a`) (sort_key `A`))";
+
+        scenario: sort_key breaks ties by case;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(1.0));
+    }
+
+    test! {
+        "(cmp (sort_key `Ab`) (sort_key `a`))";
+
+        scenario: sort_key prefix first;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Number(1.0));
+    }
+
+    test! {
+        "(type `foo`)";
+
+        scenario: type string;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(Type::String.into()).into()));
+    }
+
+    test! {
+        "(type true)";
+
+        scenario: type bool true;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(Type::Bool.into()).into()));
+    }
+
+    test! {
+        "(type false)";
+
+        scenario: type bool false;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(Type::Bool.into()).into()));
+    }
+
+    test! {
+        "(type id)";
+
+        scenario: type builtin id;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(
+            Value::Type(
+                Type::Type(Type::Fn {
+                    args: vec![Type::Value],
+                    variadic_arg: None,
+                    returns: Type::Value.into()
+                }.into()).into()
+            )
+        );
+    }
+
+    test! {
+        "(type concat)";
+
+        scenario: type builtin concat;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(
+            Value::Type(
+                Type::Type(Type::Fn {
+                    args: vec![Type::Value, Type::Value],
+                    variadic_arg: Some(Type::Value.into()),
+                    returns: Type::String.into()
+                }.into()).into()
+            )
+        );
+    }
+
+    test! {
+        "@intest";
+
+        scenario: client in test value;
+
+        tokens should be: vec![
+            Ok((0, Token::identifier("@intest"), 7)),
+        ];
+
+        ast should be: Ok(
+            Expr::identifier_with_type("@intest", Type::Value)
+        );
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        compiles to: Ok(ExprByteCode::new(
+            crate::make_test_bytecode(vec![
+                opcode::GET, lookup::CLIENT_CTX, 0
+            ]),
+            vec![],
+            vec![]
+        ));
+
+        disassembles to: "VERSION 0800\n----\n0000 GET CLIENT_CTX      0 == 'intest'\n";
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "((id id) `foo`)";
+
+        scenario: call expr with call expression as calle;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::String("foo".into()));
+    }
+
+    test! {
+        "(eq true true)";
+
+        scenario: eq true true;
+
+        tokens should be: vec![
+            Ok((0, Token::LParan, 1)),
+            Ok((1, Token::identifier("eq"), 3)),
+            Ok((4, Token::True, 8)),
+            Ok((9, Token::True, 13)),
+            Ok((13, Token::RParan, 14))
+        ];
+
+        ast should be: Ok(Expr::call(
+            (Expr::identifier("eq"), 1..3),
+            vec![
+                (Expr::bool(true), 4..8),
+                (Expr::bool(true), 9..13),
+            ]
+        ));
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        compiles to: Ok(ExprByteCode::new(
+            crate::make_test_bytecode(vec![
+                opcode::GET, lookup::BUILTIN, 15,
+                opcode::TRUE,
+                opcode::TRUE,
+                opcode::CALL, 2
+            ]),
+            vec![],
+            vec![]
+        ));
+
+        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN        15 == 'eq'\n0003 TRUE\n0004 TRUE\n0005 CALL             (2 args)\n";
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(eq false true)";
+
+        scenario: eq false true;
+
+        tokens should be: vec![
+            Ok((0, Token::LParan, 1)),
+            Ok((1, Token::identifier("eq"), 3)),
+            Ok((4, Token::False, 9)),
+            Ok((10, Token::True, 14)),
+            Ok((14, Token::RParan, 15))
+        ];
+
+        ast should be: Ok(Expr::call(
+            (Expr::identifier("eq"), 1..3),
+            vec![
+                (Expr::bool(false), 4..9),
+                (Expr::bool(true), 10..14),
+            ]
+        ));
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        compiles to: Ok(ExprByteCode::new(
+            crate::make_test_bytecode(vec![
+                opcode::GET, lookup::BUILTIN, 15,
+                opcode::FALSE,
+                opcode::TRUE,
+                opcode::CALL, 2
+            ]),
+            vec![],
+            vec![]
+        ));
+
+        disassembles to: "VERSION 0800\n----\n0000 GET BUILTIN        15 == 'eq'\n0003 FALSE\n0004 TRUE\n0005 CALL             (2 args)\n";
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(eq `foo` `foo`)";
+
+        scenario: builtin eq same string;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(eq `foo` `bar`)";
+
+        scenario: builtin eq different strings;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(eq true true)";
+
+        scenario: builtin eq same bool;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(eq true false)";
+
+        scenario: builtin eq different bool;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "(eq id id)";
+
+        scenario: builtin eq same fn;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(true));
+    }
+
+    test! {
+        "(eq id concat)";
+
+        scenario: builtin eq different fns;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Bool(false));
+    }
+
+    test! {
+        "String";
+
+        scenario: type literal String;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(Type::String.into()).into()));
+    }
+
+    test! {
+        "Bool";
+
+        scenario: type literal Bool;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(Type::Bool.into()).into()));
+    }
+
+    test! {
+        "Type<String>";
+
+        scenario: type literal Type String;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(Type::String.into()).into()));
+    }
+
+    test! {
+        "Fn() -> Value";
+
+        scenario: type literal Type Fn no args;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(
+            Type::Fn {
+                args: vec![],
+                variadic_arg: None,
+                returns: Type::Value.into()
+            }.into()).into()));
+    }
+
+    test! {
+        "Fn(Value) -> Value";
+
+        scenario: type literal Type Fn arg;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(
+            Type::Fn {
+                args: vec![Type::Value],
+                variadic_arg: None,
+                returns: Type::Value.into()
+            }.into()).into()));
+    }
+
+    test! {
+        "Fn(Value, Value) -> Value";
+
+        scenario: type literal Type Fn args;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(
+            Type::Fn {
+                args: vec![Type::Value, Type::Value],
+                variadic_arg: None,
+                returns: Type::Value.into()
+            }.into()).into()));
+    }
+
+    test! {
+        "Fn(...Value) -> Value";
+
+        scenario: type literal Type Fn vargs;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(
+            Type::Fn {
+                args: vec![],
+                variadic_arg: Some(Type::Value.into()),
+                returns: Type::Value.into()
+            }.into()).into()));
+    }
+
+    test! {
+        "Fn(Value, ...Value) -> Value";
+
+        scenario: type literal Type Fn arg and vargs;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(
+            Type::Fn {
+                args: vec![Type::Value],
+                variadic_arg: Some(Type::Value.into()),
+                returns: Type::Value.into()
+            }.into()).into()));
+    }
+
+    test! {
+        "Fn(Value, Value, ...Value) -> Value";
+
+        scenario: type literal Type Fn args and vargs;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Ok(Value::Type(Type::Type(
+            Type::Fn {
+                args: vec![Type::Value, Type::Value],
+                variadic_arg: Some(Type::Value.into()),
+                returns: Type::Value.into()
+            }.into()).into()));
     }
 
     // test! {
@@ -2437,7 +4836,7 @@ mod invalid {
         ast should be: Err(vec![(
             SyntaxError::UnrecognizedToken {
                 token: String::from(")"),
-                expected: vec![r#""(""#.to_string(), r#""Fn""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
+                expected: vec![r#""(""#.to_string(), r#""[""#.to_string(), r#""Fn""#.to_string(), r#""...""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "template_start".to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
             }.into(),
             1..2
         )]);
@@ -2449,7 +4848,7 @@ mod invalid {
         compiles to: Err(vec![(
             SyntaxError::UnrecognizedToken {
                 token: String::from(")"),
-                expected: vec![r#""(""#.to_string(), r#""Fn""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
+                expected: vec![r#""(""#.to_string(), r#""[""#.to_string(), r#""Fn""#.to_string(), r#""...""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "template_start".to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
             }.into(),
             1..2
         )]);
@@ -2463,7 +4862,7 @@ mod invalid {
         interpets to: Err(vec![(
             SyntaxError::UnrecognizedToken {
                 token: String::from(")"),
-                expected: vec![r#""(""#.to_string(), r#""Fn""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
+                expected: vec![r#""(""#.to_string(), r#""[""#.to_string(), r#""Fn""#.to_string(), r#""...""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "template_start".to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
             }.into(),
             1..2
         )]);
@@ -2726,7 +5125,7 @@ mod invalid {
 
         interpets to: Err(vec![(
             SyntaxError::UnrecognizedEOF {
-                expected: vec![r#""(""#.to_string(), r#"")""#.to_string(), r#""Fn""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
+                expected: vec![r#""(""#.to_string(), r#"")""#.to_string(), r#""[""#.to_string(), r#""Fn""#.to_string(), r#""...""#.to_string(), r#""true""#.to_string(), r#""false""#.to_string(), "template_start".to_string(), "string".to_string(), "number".to_string(), "identifier".to_string(), "ty".to_string()]
             }.into(),
             19..19
         )]);
@@ -2753,8 +5152,13 @@ mod invalid {
         user builtins: [];
 
         compiles to: Err(vec![(
-            CompileError::WrongNumberOfArgs { expected: 1, actual: 0 }.into(),
-            0..5
+            CompileError::WrongNumberOfArgs {
+                expected: 1,
+                actual: 0,
+                signature: "not(value: Bool) -> Bool".to_string()
+            }
+            .into(),
+            4..5
         )]);
 
         disassembles to: "";
@@ -2764,8 +5168,13 @@ mod invalid {
         };
 
         interpets to: Err(vec![(
-            CompileError::WrongNumberOfArgs { expected: 1, actual: 0 }.into(),
-            0..5
+            CompileError::WrongNumberOfArgs {
+                expected: 1,
+                actual: 0,
+                signature: "not(value: Bool) -> Bool".to_string()
+            }
+            .into(),
+            4..5
         )]);
     }
 
@@ -2795,8 +5204,13 @@ mod invalid {
         user builtins: [];
 
         compiles to: Err(vec![(
-            CompileError::WrongNumberOfArgs { expected: 1, actual: 2 }.into(),
-            0..16
+            CompileError::WrongNumberOfArgs {
+                expected: 1,
+                actual: 2,
+                signature: "not(value: Bool) -> Bool".to_string()
+            }
+            .into(),
+            10..15
         )]);
 
         disassembles to: "";
@@ -2806,8 +5220,13 @@ mod invalid {
         };
 
         interpets to: Err(vec![(
-            CompileError::WrongNumberOfArgs { expected: 1, actual: 2 }.into(),
-            0..16
+            CompileError::WrongNumberOfArgs {
+                expected: 1,
+                actual: 2,
+                signature: "not(value: Bool) -> Bool".to_string()
+            }
+            .into(),
+            10..15
         )]);
     }
 
@@ -2877,8 +5296,13 @@ mod invalid {
         user builtins: [];
 
         compiles to: Err(vec![(
-            CompileError::WrongNumberOfArgs { expected: 1, actual: 2 }.into(),
-            0..17
+            CompileError::WrongNumberOfArgs {
+                expected: 1,
+                actual: 2,
+                signature: "not(value: Bool) -> Bool".to_string()
+            }
+            .into(),
+            10..16
         )]);
 
         disassembles to: "";
@@ -2888,8 +5312,13 @@ mod invalid {
         };
 
         interpets to: Err(vec![(
-            CompileError::WrongNumberOfArgs { expected: 1, actual: 2 }.into(),
-            0..17
+            CompileError::WrongNumberOfArgs {
+                expected: 1,
+                actual: 2,
+                signature: "not(value: Bool) -> Bool".to_string()
+            }
+            .into(),
+            10..16
         )]);
     }
 
@@ -2919,7 +5348,15 @@ mod invalid {
         user builtins: [];
 
         compiles to: Err(vec![
-            (CompileError::WrongNumberOfArgs { expected: 1, actual: 2 }.into(), 0..17),
+            (
+                CompileError::WrongNumberOfArgs {
+                    expected: 1,
+                    actual: 2,
+                    signature: "not(value: Bool) -> Bool".to_string()
+                }
+                .into(),
+                12..16
+            ),
             (CompileError::TypeMismatch { expected: Type::Bool, actual: Type::String }.into(), 5..11)
         ]);
 
@@ -2930,8 +5367,70 @@ mod invalid {
         };
 
         interpets to: Err(vec![
-            (CompileError::WrongNumberOfArgs { expected: 1, actual: 2 }.into(), 0..17),
+            (
+                CompileError::WrongNumberOfArgs {
+                    expected: 1,
+                    actual: 2,
+                    signature: "not(value: Bool) -> Bool".to_string()
+                }
+                .into(),
+                12..16
+            ),
             (CompileError::TypeMismatch { expected: Type::Bool, actual: Type::String }.into(), 5..11)
         ]);
     }
+
+    test! {
+        "(let (x `a`) (not x))";
+
+        scenario: let type mismatch;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            CompileError::TypeMismatch { expected: Type::Bool, actual: Type::String }.into(),
+            18..19
+        )]);
+    }
+
+    test! {
+        "(let (concat `a`) concat)";
+
+        scenario: let shadowing builtin;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(
+            CompileError::AlreadyDefined("concat".to_string()).into(),
+            6..12
+        )]);
+    }
+
+    test! {
+        "(let x)";
+
+        scenario: let without binding;
+
+        env: (vec![], vec![], vec![], vec![]);
+
+        user builtins: [];
+
+        runtime env: {
+            ..Default::default()
+        };
+
+        interpets to: Err(vec![(CompileError::InvalidLet.into(), 0..7)]);
+    }
 }
@@ -0,0 +1,106 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use reqlang_expr::{
+    metrics::{Metrics, set_metrics},
+    prelude::*,
+};
+
+#[derive(Default)]
+struct Recorded {
+    lexed: AtomicUsize,
+    parsed: AtomicUsize,
+    compiled: AtomicUsize,
+    compile_failures: AtomicUsize,
+    cache_hits: AtomicUsize,
+    cache_misses: AtomicUsize,
+    runtime_errors: Mutex<Vec<&'static str>>,
+    evaluations: Mutex<Vec<Duration>>,
+}
+
+struct Recorder(Arc<Recorded>);
+
+impl Metrics for Recorder {
+    fn lexed(&self, _duration: Duration) {
+        self.0.lexed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn parsed(&self, _success: bool, _duration: Duration) {
+        self.0.parsed.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn compiled(&self, success: bool, _duration: Duration) {
+        self.0.compiled.fetch_add(1, Ordering::SeqCst);
+
+        if !success {
+            self.0.compile_failures.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn cache_hit(&self) {
+        self.0.cache_hits.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn cache_miss(&self) {
+        self.0.cache_misses.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn runtime_error(&self, error: &RuntimeError) {
+        self.0.runtime_errors.lock().unwrap().push(error.kind());
+    }
+
+    fn evaluated(&self, duration: Duration) {
+        self.0.evaluations.lock().unwrap().push(duration);
+    }
+}
+
+// Metrics are process wide so everything is checked in a single test
+#[test]
+fn reports_stages_cache_lookups_errors_and_evaluations() {
+    let recorded = Arc::new(Recorded::default());
+
+    assert!(set_metrics(Box::new(Recorder(recorded.clone()))).is_ok());
+    assert!(set_metrics(Box::new(Recorder(recorded.clone()))).is_err());
+
+    let env = CompileTimeEnv::default();
+    let mut cache = CompilationCache::new();
+
+    let bytecode = cache.get_or_compile("`a`", &env).unwrap().clone();
+    cache.get_or_compile("`a`", &env).unwrap();
+    assert!(cache.get_or_compile(":missing", &env).is_err());
+
+    assert_eq!(2, recorded.lexed.load(Ordering::SeqCst));
+    assert_eq!(2, recorded.parsed.load(Ordering::SeqCst));
+    assert_eq!(2, recorded.compiled.load(Ordering::SeqCst));
+    assert_eq!(1, recorded.compile_failures.load(Ordering::SeqCst));
+    assert_eq!(1, recorded.cache_hits.load(Ordering::SeqCst));
+    assert_eq!(2, recorded.cache_misses.load(Ordering::SeqCst));
+
+    let runtime_env = RuntimeEnv::default();
+
+    Vm::new()
+        .interpret(bytecode.clone().into(), &env, &runtime_env)
+        .unwrap();
+
+    let mut limited = Vm::with_config(VmConfig {
+        max_constants: 0,
+        ..Default::default()
+    });
+
+    assert!(
+        limited
+            .interpret(bytecode.into(), &env, &runtime_env)
+            .is_err()
+    );
+
+    assert_eq!(
+        vec!["too_many_constants"],
+        *recorded.runtime_errors.lock().unwrap()
+    );
+    assert_eq!(2, recorded.evaluations.lock().unwrap().len());
+}
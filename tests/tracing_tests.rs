@@ -0,0 +1,116 @@
+#![cfg(feature = "tracing")]
+
+use std::{
+    fmt::Debug,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use reqlang_expr::{cache::source_hash, prelude::*};
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+};
+
+/// A span and its fields as `name=value` strings
+#[derive(Debug, Clone, PartialEq)]
+struct RecordedSpan {
+    name: &'static str,
+    fields: Vec<String>,
+}
+
+impl RecordedSpan {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find_map(|field| field.strip_prefix(&format!("{name}=")))
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Vec<String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.push(format!("{}={value:?}", field.name()));
+    }
+}
+
+/// Records every span created while it's the default subscriber
+#[derive(Default, Clone)]
+struct Recorder {
+    next_id: Arc<AtomicU64>,
+    spans: Arc<Mutex<Vec<RecordedSpan>>>,
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = vec![];
+        span.record(&mut FieldVisitor(&mut fields));
+
+        self.spans.lock().unwrap().push(RecordedSpan {
+            name: span.metadata().name(),
+            fields,
+        });
+
+        Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+fn record(f: impl FnOnce()) -> Vec<RecordedSpan> {
+    let recorder = Recorder::default();
+
+    tracing::subscriber::with_default(recorder.clone(), f);
+
+    recorder.spans.lock().unwrap().clone()
+}
+
+#[test]
+fn spans_lexing_parsing_compiling_and_interpreting() {
+    let source = "(concat `a` (noop))";
+    let env = CompileTimeEnv::default();
+
+    let spans = record(|| {
+        let mut ast = (parse(source).unwrap(), 0..source.len());
+        let bytecode = compile(&mut ast, &env).unwrap();
+
+        Vm::new()
+            .interpret(bytecode.into(), &env, &RuntimeEnv::default())
+            .unwrap();
+    });
+
+    let names: Vec<&str> = spans.iter().map(|span| span.name).collect();
+
+    assert_eq!(
+        vec!["parse", "lex", "compile", "interpret", "call", "call"],
+        names
+    );
+
+    let expected_hash = source_hash(source).to_string();
+
+    assert_eq!(Some(expected_hash.as_str()), spans[0].field("source_hash"));
+    assert_eq!(Some(expected_hash.as_str()), spans[1].field("source_hash"));
+    assert!(spans[2].field("source_hash").is_some());
+    assert!(spans[3].field("bytecode_hash").is_some());
+
+    assert_eq!(Some("noop"), spans[4].field("callee"));
+    assert_eq!(Some("0"), spans[4].field("args"));
+    assert_eq!(Some("concat"), spans[5].field("callee"));
+    assert_eq!(Some("2"), spans[5].field("args"));
+}